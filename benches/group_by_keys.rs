@@ -0,0 +1,65 @@
+//! Group-by workloads, with and without `--intern-keys`.
+//!
+//! Keys built on the spot (`a[$1, $2]`, `a[$1 "-" $2]`) are looked up without allocating; only
+//! new keys get a buffer of their own. Run with `cargo bench --bench group_by_keys`; each line
+//! reports the fastest of several runs over the same generated input.
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use zawk::runtime::map_key::set_intern_keys;
+use zawk::Program;
+
+const RUNS: usize = 5;
+const LINES: usize = 200_000;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "field_key",
+        r#"{ n[$1]++ } END { for (k in n) s += n[k]; print length(n), s }"#,
+    ),
+    (
+        "composite_key",
+        r#"{ n[$1, $2] += $3 } END { for (k in n) s += n[k]; print length(n), s }"#,
+    ),
+    (
+        "concatenated_key",
+        r#"{ n[$1 "-" $2] += $3 } END { for (k in n) s += n[k]; print length(n), s }"#,
+    ),
+];
+
+// Few distinct keys, each seen many times: the case interning is meant for.
+fn input() -> String {
+    let mut s = String::new();
+    for i in 0..LINES {
+        writeln!(s, "host-{:02} region-{} {}", i % 40, i % 7, i % 100).unwrap();
+    }
+    s
+}
+
+fn fastest(prog: &Program, input: &str) -> (Duration, String) {
+    let mut best = Duration::MAX;
+    let mut out = String::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        out = prog.run_on_str(input).unwrap();
+        best = best.min(start.elapsed());
+    }
+    (best, out)
+}
+
+fn main() {
+    let input = input();
+    for (name, src) in PROGRAMS {
+        let prog = Program::compile(src).unwrap();
+        set_intern_keys(false);
+        let (plain, expected) = fastest(&prog, &input);
+        set_intern_keys(true);
+        let (interned, out) = fastest(&prog, &input);
+        set_intern_keys(false);
+        assert_eq!(out, expected, "{}", name);
+        println!(
+            "{:<20} {:>10.2?} {:>10.2?} with --intern-keys",
+            name, plain, interned
+        );
+    }
+}
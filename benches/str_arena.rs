@@ -0,0 +1,82 @@
+//! Allocation-heavy scripts, with and without `--str-arena`.
+//!
+//! Each script builds strings too long to be stored inline for every record. Run with
+//! `cargo bench --features str_arena --bench str_arena`; each line reports the fastest of several
+//! runs over the same generated input.
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use zawk::runtime::str_arena::set_enabled;
+use zawk::Program;
+
+const RUNS: usize = 5;
+const LINES: usize = 100_000;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "concat_substr",
+        r#"{ s = toupper($1) "/" substr($0, 3, 20); t = s "|" (NR * 1.5); n += length(t) + (t ~ /REGION/) }
+        END { print n }"#,
+    ),
+    (
+        "number_format",
+        r#"{ s = ($3 * 1.5) " units"; t = sprintf("%s:%d:%.3f", $1, NR, $3 / 7); n += length(s) + length(t) }
+        END { print n }"#,
+    ),
+    (
+        "field_rewrite",
+        r#"{ $2 = toupper($2); $1 = substr($1, 2); n += length($0) } END { print n }"#,
+    ),
+    (
+        "gsub",
+        r#"{ s = $0; gsub(/-/, "_", s); n += length(s) } END { print n }"#,
+    ),
+    // Strings stored in arrays are copied out of the arena.
+    (
+        "stored_values",
+        r#"{ u[$1 "/" $2] = tolower($1) "-" $3 } END { for (k in u) n += length(u[k]); print n }"#,
+    ),
+];
+
+fn input() -> String {
+    let mut s = String::new();
+    for i in 0..LINES {
+        writeln!(
+            s,
+            "host-{:02}-abcdefghij region-{}-klmnopqrstuv {}",
+            i % 40,
+            i % 7,
+            i
+        )
+        .unwrap();
+    }
+    s
+}
+
+fn fastest(prog: &Program, input: &str) -> (Duration, String) {
+    let mut best = Duration::MAX;
+    let mut out = String::new();
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        out = prog.run_on_str(input).unwrap();
+        best = best.min(start.elapsed());
+    }
+    (best, out)
+}
+
+fn main() {
+    let input = input();
+    for (name, src) in PROGRAMS {
+        let prog = Program::compile(src).unwrap();
+        set_enabled(false);
+        let (heap, expected) = fastest(&prog, &input);
+        set_enabled(true);
+        let (arena, out) = fastest(&prog, &input);
+        set_enabled(false);
+        assert_eq!(out, expected, "{}", name);
+        println!(
+            "{:<20} {:>10.2?} {:>10.2?} with --str-arena",
+            name, heap, arena
+        );
+    }
+}
@@ -11,7 +11,7 @@
 //!    extra wrapper to enforce those rather than passing them down to the user.
 use std::ptr;
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 pub struct Arena(bumpalo::Bump);
 pub type Vec<'a, T> = bumpalo::collections::Vec<'a, T>;
 
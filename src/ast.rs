@@ -20,8 +20,12 @@
 ///    we currently don't do), and we'd want to verify that performance didn't degrade when the
 ///    patterns are _not sparse_ in the input.
 use crate::arena::{self, Arena};
-use crate::builtins::Function;
+use crate::builtins::{Function, Variable};
+use crate::compile;
 use crate::common::{Either, FileSpec, Stage};
+use crate::runtime;
+
+use hashbrown::HashMap;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Unop {
@@ -39,6 +43,11 @@ static_map!(
     ["+", Unop::Pos]
 );
 
+/// The address of a node, which identifies it in `Prog::lines`.
+pub(crate) fn addr<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
 pub struct FunDec<'a, 'b, I> {
     pub name: I,
     pub args: Vec<I>,
@@ -62,6 +71,8 @@ pub struct Prog<'a, 'b, I> {
     pub output_sep: Option<&'b [u8]>,
     // ORS
     pub output_record_sep: Option<&'b [u8]>,
+    // -o template=...; see cfg::Template.
+    pub output_template: Option<&'b [u8]>,
     pub decs: arena::Vec<'a, FunDec<'a, 'b, I>>,
     pub begin: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
     pub prepare: arena::Vec<'a, &'a Stmt<'a, 'b, I>>,
@@ -69,7 +80,16 @@ pub struct Prog<'a, 'b, I> {
     pub pats: arena::Vec<'a, (Pattern<'a, 'b, I>, Option<&'a Stmt<'a, 'b, I>>)>,
     pub stage: Stage<()>,
     pub argv: Vec<&'b str>,
+    // The unescaped values of `var=value` operands in `argv`, by index.
+    pub argv_assignments: Vec<(usize, &'b [u8])>,
+    // The number of entries at the end of `argv` that are arguments for the script (after `--`).
+    pub script_args: usize,
     pub parse_header: bool,
+    // Compile for `zawk debug`, keeping each named global variable in a register of its own.
+    pub debug: bool,
+    // The line (counting from 0) that each statement and pattern starts on, keyed by the address
+    // of the node. Runtime errors report these lines.
+    pub lines: HashMap<usize, usize>,
 }
 
 fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
@@ -120,6 +140,11 @@ fn parse_header<'a, 'b, I: From<&'b str> + Clone>(
     )));
 }
 
+// Illegal frawk identifiers holding the ARGV index reached by `assign_operands`, and the entry at
+// that index.
+const ARGV_INDEX: &str = "--argi";
+const ARGV_ENTRY: &str = "--arg";
+
 impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
     pub(crate) fn from_stage(arena: &'a Arena, stage: Stage<()>) -> Self {
         Prog {
@@ -127,16 +152,130 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             prelude_vardecs: Vec::new(),
             output_sep: None,
             output_record_sep: None,
+            output_template: None,
             decs: arena.new_vec(),
             begin: arena.new_vec(),
             prepare: arena.new_vec(),
             end: arena.new_vec(),
             pats: arena.new_vec(),
             argv: Vec::new(),
+            argv_assignments: Vec::new(),
+            script_args: 0,
             parse_header: false,
+            debug: false,
+            lines: HashMap::new(),
             stage,
         }
     }
+    pub(crate) fn record_line<T>(&mut self, node: &'a T, line: usize) {
+        self.lines.entry(addr(node)).or_insert(line);
+    }
+    /// Command-line operands of the form `var=value` are assignments, made when the main input
+    /// reaches them in ARGV. The runtime skips these operands when it looks for the next input
+    /// file (see `FileRead::next_input`); the program makes the assignments itself. Once a file
+    /// starts we walk ARGV up to the entry naming that file, performing the assignments along the
+    /// way:
+    ///
+    ///  for (; ARGI < ARGC; ++ARGI) {
+    ///      ARG = ARGV[ARGI]
+    ///      if (ARGI >= 4 && ARGI < 6) {}   # arguments for the script
+    ///      else if (ARG == FILENAME) { ++ARGI; break; }
+    ///      else if (ARG == "x=a\\tb") x = "a\tb"
+    ///      ...
+    ///      else if (index(ARG, "=") == 2 && substr(ARG, 1, 1) == "x") x = substr(ARG, 3)
+    ///      ...
+    ///  }
+    ///
+    /// Operands given on the command line are matched exactly, so that their values are unescaped
+    /// like those of -v; the final checks catch operands a script adds to ARGV, whose values are
+    /// used as they are. Files that were skipped for having no records are passed over. Once the
+    /// input is exhausted the same loop runs without the FILENAME check (`until_file` is false)
+    /// to make any remaining assignments. Only the variables in `vars` can be assigned this way.
+    fn assign_operands(
+        &self,
+        arena: &'a Arena,
+        vars: &[&'b str],
+        until_file: bool,
+    ) -> &'a Stmt<'a, 'b, I> {
+        use {self::Binop::*, self::Expr::*, Stmt::*};
+        let ix = arena.alloc(Var(ARGV_INDEX.into()));
+        let entry = arena.alloc(Var(ARGV_ENTRY.into()));
+        let next = arena.alloc(Expr(arena.alloc(Inc {
+            is_inc: true,
+            is_post: false,
+            x: ix,
+        })));
+        // The cases are tried in order, as an if-else chain.
+        let mut cases = Vec::with_capacity(self.argv_assignments.len() + vars.len() + 2);
+        if self.script_args > 0 {
+            let end = self.argv.len() as i64;
+            let start = end - self.script_args as i64;
+            let skip = arena.alloc(And(
+                arena.alloc(Binop(GTE, ix, arena.alloc(ILit(start)))),
+                arena.alloc(Binop(LT, ix, arena.alloc(ILit(end)))),
+            ));
+            cases.push((skip, arena.alloc(Block(arena.new_vec()))));
+        }
+        if until_file {
+            cases.push((
+                arena.alloc(Binop(EQ, entry, arena.alloc(Var("FILENAME".into())))),
+                arena.alloc(Block(arena.new_vec_from_slice(&[next, arena.alloc(Break)]))),
+            ));
+        }
+        for (i, value) in self.argv_assignments.iter() {
+            let arg = self.argv[*i];
+            let var = match runtime::split_assignment(arg) {
+                Some((var, _)) if vars.contains(&var) => var,
+                _ => continue,
+            };
+            cases.push((
+                arena.alloc(Binop(EQ, entry, arena.alloc(StrLit(arg.as_bytes())))),
+                arena.alloc(Expr(arena.alloc(Assign(
+                    arena.alloc(Var(var.into())),
+                    arena.alloc(StrLit(value)),
+                )))),
+            ));
+        }
+        for var in vars {
+            let len = var.len() as i64;
+            let name = arena.alloc(Call(
+                Either::Right(Function::Substr),
+                arena.alloc_slice(&[entry, arena.alloc(ILit(1)), arena.alloc(ILit(len))]),
+            ));
+            let eq_sign = arena.alloc(Call(
+                Either::Right(Function::SubstrIndex),
+                arena.alloc_slice(&[entry, arena.alloc(StrLit(b"="))]),
+            ));
+            let value = arena.alloc(Call(
+                Either::Right(Function::Substr),
+                arena.alloc_slice(&[entry, arena.alloc(ILit(len + 2))]),
+            ));
+            cases.push((
+                arena.alloc(And(
+                    arena.alloc(Binop(EQ, eq_sign, arena.alloc(ILit(len + 1)))),
+                    arena.alloc(Binop(EQ, name, arena.alloc(StrLit(var.as_bytes())))),
+                )),
+                arena.alloc(Expr(arena.alloc(Assign(arena.alloc(Var((*var).into())), value)))),
+            ));
+        }
+        let chain = cases
+            .into_iter()
+            .rev()
+            .fold(None, |rest, (cond, stmt)| Some(arena.alloc(If(cond, stmt, rest))));
+        let mut body = arena.vec_with_capacity(2);
+        body.push(arena.alloc(Expr(arena.alloc(Assign(
+            entry,
+            arena.alloc(Index(arena.alloc(Var("ARGV".into())), ix)),
+        )))));
+        body.extend(chain);
+        arena.alloc(For(
+            None,
+            Some(arena.alloc(Binop(LT, ix, arena.alloc(Var("ARGC".into()))))),
+            Some(next),
+            arena.alloc(Block(body)),
+        ))
+    }
+
     pub(crate) fn desugar_stage(&self, arena: &'a Arena) -> Stage<&'a Stmt<'a, 'b, I>> {
         use {self::Binop::*, self::Expr::*, Stmt::*};
         let mut conds = 0;
@@ -153,6 +292,33 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             )))));
         }
 
+        // Set argc, argv. This comes before -H, whose header is read from the first file in ARGV.
+        if !self.argv.is_empty() {
+            begin.push(arena.alloc(Expr(arena.alloc(Assign(
+                arena.alloc(Var("ARGC".into())),
+                arena.alloc(ILit(self.argv.len() as i64)),
+            )))));
+            let argv = arena.alloc(Var("ARGV".into()));
+            for (ix, arg) in self.argv.iter().enumerate() {
+                let arg = arena.alloc(StrLit(arg.as_bytes()));
+                let ix = arena.alloc(ILit(ix as i64));
+                let arr_exp = arena.alloc(Index(argv, ix));
+                begin.push(arena.alloc(Expr(arena.alloc(Assign(arr_exp, arg)))));
+            }
+        }
+        let assigned = if matches!(self.stage, Stage::Main(_)) {
+            self.assigned_vars()
+        } else {
+            Vec::new()
+        };
+        if !assigned.is_empty() {
+            begin.push(arena.alloc(Expr(arena.alloc(Assign(
+                arena.alloc(Var(ARGV_INDEX.into())),
+                arena.alloc(ILit(1)),
+            )))));
+        }
+
+
         // for -H
         if self.parse_header {
             parse_header(arena, &mut begin);
@@ -184,21 +350,6 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             )));
         }
 
-        // Set argc, argv
-        if !self.argv.is_empty() {
-            begin.push(arena.alloc(Expr(arena.alloc(Assign(
-                arena.alloc(Var("ARGC".into())),
-                arena.alloc(ILit(self.argv.len() as i64)),
-            )))));
-            let argv = arena.alloc(Var("ARGV".into()));
-            for (ix, arg) in self.argv.iter().enumerate() {
-                let arg = arena.alloc(StrLit(arg.as_bytes()));
-                let ix = arena.alloc(ILit(ix as i64));
-                let arr_exp = arena.alloc(Index(argv, ix));
-                begin.push(arena.alloc(Expr(arena.alloc(Assign(arr_exp, arg)))));
-            }
-        }
-
         begin.extend(self.begin.iter().cloned());
 
         // Desugar patterns into if statements, with the usual desugaring for an empty action.
@@ -213,6 +364,13 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
             is_post: false,
             x: arena.alloc(Var("FNR".into())),
         }))));
+        if !assigned.is_empty() {
+            inner.push(arena.alloc(If(
+                arena.alloc(Binop(EQ, arena.alloc(Var("FNR".into())), arena.alloc(ILit(1)))),
+                self.assign_operands(arena, &assigned, /*until_file=*/ true),
+                None,
+            )));
+        }
         let init_len = inner.len();
         for (pat, body) in self.pats.iter() {
             let body = if let Some(body) = body {
@@ -289,11 +447,15 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
                 arena.alloc(Binop(GT, arena.alloc(ReadStdin), arena.alloc(ILit(0)))),
                 arena.alloc(Block(inner)),
             ));
-            main_loop = Some(if self.prepare.is_empty() {
+            main_loop = Some(if self.prepare.is_empty() && assigned.is_empty() {
                 main_portion
             } else {
-                let mut block = arena.vec_with_capacity(self.prepare.len() + 1);
+                let mut block = arena.vec_with_capacity(self.prepare.len() + 2);
                 block.push(main_portion);
+                if !assigned.is_empty() {
+                    // Assignments after the last file take effect before END.
+                    block.push(self.assign_operands(arena, &assigned, /*until_file=*/ false));
+                }
                 block.extend(self.prepare.iter().cloned());
                 arena.alloc(Stmt::Block(block))
             });
@@ -319,6 +481,89 @@ impl<'a, 'b, I: From<&'b str> + Clone> Prog<'a, 'b, I> {
     }
 }
 
+impl<'a, 'b, I: Clone> Prog<'a, 'b, I> {
+    /// The variables assigned by `var=value` operands in ARGV. Array-valued special variables are
+    /// left out; they cannot be assigned a string.
+    fn assigned_vars(&self) -> Vec<&'b str> {
+        let mut vars = Vec::new();
+        for (i, _) in self.argv_assignments.iter() {
+            if let Some((var, _)) = runtime::split_assignment(self.argv[*i]) {
+                let is_array = Variable::try_from(var).is_ok_and(|v| compile::Ty::from(v).is_array());
+                if !is_array && !vars.contains(&var) {
+                    vars.push(var);
+                }
+            }
+        }
+        vars
+    }
+    /// The number of input records the program reads before exiting, if that can be read off of
+    /// its first rule: `NR > n { exit }` (or `NR >= n`, `NR == n`) only ever looks at the first
+    /// few records, and we can stop reading input there instead of waiting for one more record.
+    ///
+    /// Programs with an END block are excluded, as `exit` skips the END block while running out
+    /// of input does not.
+    pub(crate) fn record_limit(&self) -> Option<u64>
+    where
+        Variable: TryFrom<I>,
+    {
+        use self::{Binop::*, Expr::*};
+        if !self.end.is_empty() || !self.prepare.is_empty() {
+            return None;
+        }
+        let (pat, body) = self.pats.first()?;
+        let is_exit = |stmt: &Stmt<'a, 'b, I>| {
+            matches!(stmt, Stmt::Expr(Call(Either::Right(Function::Exit), [ILit(0)])))
+        };
+        let exits = match body {
+            Some(Stmt::Block(stmts)) => stmts.len() == 1 && is_exit(stmts[0]),
+            Some(stmt) => is_exit(stmt),
+            None => false,
+        };
+        if !exits {
+            return None;
+        }
+        let is_nr = |v: &I| matches!(Variable::try_from(v.clone()), Ok(Variable::NR));
+        let (op, n) = match pat {
+            Pattern::Bool(Binop(op, Var(v), ILit(n))) if is_nr(v) => (*op, *n),
+            Pattern::Bool(Binop(op, ILit(n), Var(v))) if is_nr(v) => {
+                let flipped = match op {
+                    LT => GT,
+                    LTE => GTE,
+                    EQ => EQ,
+                    _ => return None,
+                };
+                (flipped, *n)
+            }
+            _ => return None,
+        };
+        match op {
+            GT if n >= 0 => Some(n as u64),
+            GTE | EQ if n >= 1 => Some(n as u64 - 1),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'b, I> Stmt<'a, 'b, I> {
+    /// Whether this statement contains a `next` or `nextfile` statement.
+    pub(crate) fn has_next(&self) -> bool {
+        use Stmt::*;
+        match self {
+            Next | NextFile => true,
+            Block(stmts) => stmts.iter().any(|s| s.has_next()),
+            If(_, t, f) => t.has_next() || f.is_some_and(|f| f.has_next()),
+            For(init, _, update, body) => {
+                init.is_some_and(|s| s.has_next())
+                    || update.is_some_and(|s| s.has_next())
+                    || body.has_next()
+            }
+            DoWhile(_, body) | While(_, _, body) | ForEach(_, _, body) => body.has_next(),
+            StartCond(_) | EndCond(_) | LastCond(_) | Expr(_) | Print(..) | Printf(..) | Break
+            | Continue | Return(_) => false,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Binop {
     Plus,
@@ -362,6 +607,8 @@ pub enum Expr<'a, 'b, I> {
     Unop(Unop, &'a Expr<'a, 'b, I>),
     Binop(Binop, &'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
     Call(Either<I, Function>, &'a [&'a Expr<'a, 'b, I>]),
+    // `@f(args)`: call the user-defined function named by the value of `f`.
+    IndirectCall(&'a Expr<'a, 'b, I>, &'a [&'a Expr<'a, 'b, I>]),
     Var(I),
     Index(&'a Expr<'a, 'b, I>, &'a Expr<'a, 'b, I>),
     Assign(
@@ -4,11 +4,13 @@ use crate::ast;
 use crate::common::Either;
 use crate::common::{NodeIx, Result};
 use crate::compile;
-use crate::runtime::{Int, IntMap, Str, StrMap};
+use crate::runtime::{self, Int, IntMap, Str, StrMap};
 use crate::types::{self, SmallVec};
+use serde::{Deserialize, Serialize};
 use smallvec::smallvec;
 
 use std::convert::TryFrom;
+use std::time::Duration;
 
 pub const VERSION: &'static str = "0.5.8";
 
@@ -19,6 +21,7 @@ pub enum Function {
     FloatFunc(FloatFunc),
     IntFunc(Bitwise),
     Close,
+    FFlush,
     ReadErr,
     ReadErrCmd,
     Nextline,
@@ -29,6 +32,9 @@ pub enum Function {
     NextFile,
     Setcol,
     Split,
+    // split(s, arr, pat, seps), which also stores the separators in seps.
+    SplitSeps,
+    PatSplit,
     Length,
     Uuid,
     Ulid,
@@ -45,6 +51,10 @@ pub enum Function {
     LogWarn,
     LogError,
     Systime,
+    SystimeMs,
+    SystimeUs,
+    Clock,
+    Sleep,
     Strftime,
     Mktime,
     Duration,
@@ -55,7 +65,9 @@ pub enum Function {
     Parse,
     RegexParse,
     Strtonum,
+    ParseNumber,
     FormatBytes,
+    FormatNum,
     ToBytes,
     StartsWith,
     EndsWith,
@@ -70,6 +82,13 @@ pub enum Function {
     PadRight,
     PadBoth,
     StrCmp,
+    NatCmp,
+    Levenshtein,
+    Similarity,
+    Diff,
+    Soundex,
+    Metaphone,
+    FuzzyMatch,
     Mask,
     Repeat,
     Words,
@@ -83,13 +102,27 @@ pub enum Function {
     Escape,
     Encode,
     Decode,
+    Iconv,
+    Compress,
+    Decompress,
+    Utf8Valid,
+    Utf8Fix,
+    Normalize(NormalForm),
     Digest,
+    FileDigest,
+    DigestInit,
+    DigestUpdate,
+    DigestFinal,
     Hmac,
     Jwt,
     Dejwt,
+    Cert,
     Encrypt,
     Decrypt,
     Url,
+    UaParse,
+    Logfmt,
+    ToLogfmt,
     Pairs,
     Record,
     Message,
@@ -98,6 +131,9 @@ pub enum Function {
     Path,
     DataUrl,
     DateTime,
+    DateParse,
+    DateAdd,
+    DateDiff,
     Shlex,
     Func,
     Tuple,
@@ -106,10 +142,29 @@ pub enum Function {
     Hex2Rgb,
     Rgb2Hex,
     FromJson,
+    FromToml,
+    ToToml,
+    TomlValue,
+    FromIni,
+    ToIni,
+    PropGet,
+    ToXml,
+    XmlUpdate,
+    HtmlTable,
+    FromMsgpack,
+    FromCbor,
+    FromProtobuf,
     ToJson,
     VarDump,
     ReadAll,
+    ReadLines,
     WriteAll,
+    AppendAll,
+    Glob,
+    Stat,
+    Exists,
+    Mkdirp,
+    RmFile,
     FromCsv,
     ToCsv,
     HttpGet,
@@ -127,11 +182,36 @@ pub enum Function {
     Publish,
     Min,
     Max,
+    DecAdd,
+    DecSub,
+    DecMul,
+    DecDiv,
+    DecCmp,
+    DecFmt,
+    BigAdd,
+    BigSub,
+    BigMul,
+    BigDiv,
+    BigMod,
+    BigPow,
+    BigCmp,
+    U64Add,
+    U64Mul,
+    U64Str,
     Seq,
     ArrayMax,
     ArrayMin,
     ArrayMean,
     ArraySum,
+    ArrayKeys,
+    ArrayValues,
+    ArrayInvert,
+    ArrayMerge,
+    ArraySlice,
+    ArrayCopy,
+    ArrayEquals,
+    ArraySortedKeys,
+    ArraySortByValue,
     Asort,
     BloomFilterInsert,
     BloomFilterContains,
@@ -142,6 +222,8 @@ pub enum Function {
     Delete,
     Clear,
     Match,
+    // match(s, re, arr), which also stores the capture groups in arr.
+    MatchArr,
     SubstrIndex,
     SubstrLastIndex,
     LastPart,
@@ -153,13 +235,24 @@ pub enum Function {
     JoinCols,
     JoinCSV,
     JoinTSV,
+    // select(spec, sep, esc): the columns named by spec, escaped per esc (0 none, 1 csv, 2 tsv).
+    Select,
     IntMapJoin,
     Uniq,
     TypeOfVariable,
     IsArray,
     IsInt,
     IsNum,
+    IsNan,
+    IsInf,
     IsFormat,
+    IpInCidr,
+    CidrContains,
+    Ip2Int,
+    Int2Ip,
+    IpVersion,
+    Geoip,
+    GeoipDb,
     Substr,
     CharAt,
     ToInt,
@@ -167,7 +260,15 @@ pub enum Function {
     Rand,
     Srand,
     ReseedRng,
+    RandInt,
+    Choice,
+    Shuffle,
+    Sample,
     System,
+    ExecOutput,
+    Spawn,
+    SetEnv,
+    UnsetEnv,
     // For header-parsing logic
     UpdateUsedFields,
     SetFI,
@@ -175,9 +276,31 @@ pub enum Function {
     ToLower,
     IncMap,
     Exit,
+    // A function loaded with --load; see runtime::ext.
+    Ext(u32),
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// The Unicode normalization forms, for `nfc`, `nfd`, `nfkc` and `nfkd`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NormalForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+impl NormalForm {
+    pub fn func_name(&self) -> &'static str {
+        match self {
+            NormalForm::Nfc => "nfc",
+            NormalForm::Nfd => "nfd",
+            NormalForm::Nfkc => "nfkc",
+            NormalForm::Nfkd => "nfkd",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Bitwise {
     Complement,
     And,
@@ -240,7 +363,7 @@ impl Bitwise {
 
 // TODO: move the llvm-level code back into the LLVM module.
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FloatFunc {
     Cos,
     Sin,
@@ -259,6 +382,10 @@ pub enum FloatFunc {
     Ceil,
     Floor,
     Round,
+    // round(x, digits)
+    RoundTo,
+    FloorTo,
+    CeilTo,
 }
 
 impl FloatFunc {
@@ -276,14 +403,17 @@ impl FloatFunc {
             Abs => op.abs(),
             Ceil => op.ceil(),
             Floor => op.floor(),
-            Round => op.round(),
-            Atan2 => panic!("float: mismatched arity!"),
+            Round => runtime::rounding::round(op),
+            Atan2 | RoundTo | FloorTo | CeilTo => panic!("float: mismatched arity!"),
         }
     }
     pub fn eval2(&self, x: f64, y: f64) -> f64 {
         use FloatFunc::*;
         match self {
             Atan2 => x.atan2(y),
+            RoundTo => runtime::rounding::round_to(x, y),
+            FloorTo => runtime::rounding::floor_to(x, y),
+            CeilTo => runtime::rounding::ceil_to(x, y),
             Sqrt | Cos | Sin | Atan | Log | Log2 | Log10 | Exp | Abs | Ceil | Floor | Round => {
                 panic!("float: mismatched arity!")
             }
@@ -305,7 +435,9 @@ impl FloatFunc {
             Abs => "abs",
             Ceil => "ceil",
             Floor => "floor",
-            Round => "round",
+            Round | RoundTo => "round",
+            FloorTo => "floor_to",
+            CeilTo => "ceil_to",
         }
     }
 
@@ -313,7 +445,7 @@ impl FloatFunc {
         use FloatFunc::*;
         match self {
             Sqrt | Cos | Sin | Atan | Log | Log2 | Log10 | Exp | Abs | Ceil | Floor | Round => 1,
-            Atan2 => 2,
+            Atan2 | RoundTo | FloorTo | CeilTo => 2,
         }
     }
     fn sig(&self) -> (SmallVec<compile::Ty>, compile::Ty) {
@@ -331,7 +463,9 @@ impl FloatFunc {
 static_map!(
     FUNCTIONS<&'static str, Function>,
     ["close", Function::Close],
+    ["fflush", Function::FFlush],
     ["split", Function::Split],
+    ["patsplit", Function::PatSplit],
     ["length", Function::Length],
     ["uuid", Function::Uuid],
     ["ulid", Function::Ulid],
@@ -348,6 +482,10 @@ static_map!(
     ["log_warn", Function::LogWarn],
     ["log_error", Function::LogError],
     ["systime", Function::Systime],
+    ["systime_ms", Function::SystimeMs],
+    ["systime_us", Function::SystimeUs],
+    ["clock", Function::Clock],
+    ["sleep", Function::Sleep],
     ["strftime", Function::Strftime],
     ["mktime", Function::Mktime],
     ["duration", Function::Duration],
@@ -356,15 +494,32 @@ static_map!(
     ["trim", Function::Trim],
     ["encode", Function::Encode],
     ["decode", Function::Decode],
+    ["iconv", Function::Iconv],
+    ["compress", Function::Compress],
+    ["decompress", Function::Decompress],
+    ["utf8valid", Function::Utf8Valid],
+    ["utf8fix", Function::Utf8Fix],
+    ["nfc", Function::Normalize(NormalForm::Nfc)],
+    ["nfd", Function::Normalize(NormalForm::Nfd)],
+    ["nfkc", Function::Normalize(NormalForm::Nfkc)],
+    ["nfkd", Function::Normalize(NormalForm::Nfkd)],
     ["digest", Function::Digest],
     ["hash", Function::Digest],
+    ["file_digest", Function::FileDigest],
+    ["digest_init", Function::DigestInit],
+    ["digest_update", Function::DigestUpdate],
+    ["digest_final", Function::DigestFinal],
     ["hmac", Function::Hmac],
     ["jwt", Function::Jwt],
     ["dejwt", Function::Dejwt],
+    ["cert", Function::Cert],
     ["encrypt", Function::Encrypt],
     ["decrypt", Function::Decrypt],
     ["data_url", Function::DataUrl],
     ["url", Function::Url],
+    ["ua_parse", Function::UaParse],
+    ["logfmt", Function::Logfmt],
+    ["to_logfmt", Function::ToLogfmt],
     ["pairs", Function::Pairs],
     ["record", Function::Record],
     ["message", Function::Message],
@@ -372,6 +527,9 @@ static_map!(
     ["semver", Function::SemVer],
     ["path", Function::Path],
     ["datetime", Function::DateTime],
+    ["dateparse", Function::DateParse],
+    ["dateadd", Function::DateAdd],
+    ["datediff", Function::DateDiff],
     ["shlex", Function::Shlex],
     ["tuple", Function::Tuple],
     ["variant", Function::Variant],
@@ -394,20 +552,64 @@ static_map!(
     ["publish", Function::Publish],
     ["from_json", Function::FromJson],
     ["to_json", Function::ToJson],
+    ["from_toml", Function::FromToml],
+    ["to_toml", Function::ToToml],
+    ["toml_value", Function::TomlValue],
+    ["from_ini", Function::FromIni],
+    ["to_ini", Function::ToIni],
+    ["prop_get", Function::PropGet],
+    ["to_xml", Function::ToXml],
+    ["xml_update", Function::XmlUpdate],
+    ["html_table", Function::HtmlTable],
+    ["from_msgpack", Function::FromMsgpack],
+    ["from_cbor", Function::FromCbor],
+    ["from_protobuf", Function::FromProtobuf],
     ["var_dump", Function::VarDump],
     ["read_all", Function::ReadAll],
+    ["read_lines", Function::ReadLines],
     ["write_all", Function::WriteAll],
+    ["append_all", Function::AppendAll],
+    ["glob", Function::Glob],
+    ["stat", Function::Stat],
+    ["exists", Function::Exists],
+    ["mkdirp", Function::Mkdirp],
+    ["rmfile", Function::RmFile],
     ["pprint", Function::VarDump],
     ["from_csv", Function::FromCsv],
     ["to_csv", Function::ToCsv],
     ["min", Function::Min],
     ["max", Function::Max],
+    ["dec_add", Function::DecAdd],
+    ["dec_sub", Function::DecSub],
+    ["dec_mul", Function::DecMul],
+    ["dec_div", Function::DecDiv],
+    ["dec_cmp", Function::DecCmp],
+    ["dec_fmt", Function::DecFmt],
+    ["big_add", Function::BigAdd],
+    ["big_sub", Function::BigSub],
+    ["big_mul", Function::BigMul],
+    ["big_div", Function::BigDiv],
+    ["big_mod", Function::BigMod],
+    ["big_pow", Function::BigPow],
+    ["big_cmp", Function::BigCmp],
+    ["u64_add", Function::U64Add],
+    ["u64_mul", Function::U64Mul],
+    ["u64_str", Function::U64Str],
     // array underscore functions
     ["_max", Function::ArrayMax],
     ["_min", Function::ArrayMin],
     ["_sum", Function::ArraySum],
     ["_mean", Function::ArrayMean],
     ["_join", Function::IntMapJoin],
+    ["_keys", Function::ArrayKeys],
+    ["_values", Function::ArrayValues],
+    ["_invert", Function::ArrayInvert],
+    ["_merge", Function::ArrayMerge],
+    ["_slice", Function::ArraySlice],
+    ["_copy", Function::ArrayCopy],
+    ["_equals", Function::ArrayEquals],
+    ["_sorted_keys", Function::ArraySortedKeys],
+    ["_sort_by_value", Function::ArraySortByValue],
     ["seq", Function::Seq],
     ["uniq", Function::Uniq],
     ["asort", Function::Asort],
@@ -420,7 +622,9 @@ static_map!(
     ["parse", Function::Parse],
     ["rparse", Function::RegexParse],
     ["strtonum", Function::Strtonum],
+    ["parse_number", Function::ParseNumber],
     ["format_bytes", Function::FormatBytes],
+    ["format_num", Function::FormatNum],
     ["to_bytes", Function::ToBytes],
     ["starts_with", Function::StartsWith],
     ["ends_with", Function::EndsWith],
@@ -435,6 +639,13 @@ static_map!(
     ["pad_start", Function::PadRight],
     ["pad", Function::PadBoth],
     ["strcmp", Function::StrCmp],
+    ["natcmp", Function::NatCmp],
+    ["levenshtein", Function::Levenshtein],
+    ["similarity", Function::Similarity],
+    ["diff", Function::Diff],
+    ["soundex", Function::Soundex],
+    ["metaphone", Function::Metaphone],
+    ["fuzzy_match", Function::FuzzyMatch],
     ["mask", Function::Mask],
     ["repeat", Function::Repeat],
     ["default_if_empty", Function::DefaultIfEmpty],
@@ -450,7 +661,16 @@ static_map!(
     ["isarray", Function::IsArray],
     ["isint", Function::IsInt],
     ["isnum", Function::IsNum],
+    ["is_nan", Function::IsNan],
+    ["is_inf", Function::IsInf],
     ["is", Function::IsFormat],
+    ["ip_in_cidr", Function::IpInCidr],
+    ["cidr_contains", Function::CidrContains],
+    ["ip2int", Function::Ip2Int],
+    ["int2ip", Function::Int2Ip],
+    ["ip_version", Function::IpVersion],
+    ["geoip", Function::Geoip],
+    ["geoip_db", Function::GeoipDb],
     ["match", Function::Match],
     ["sub", Function::Sub],
     ["gsub", Function::GSub],
@@ -465,6 +685,8 @@ static_map!(
     ["ceil", Function::FloatFunc(FloatFunc::Ceil)],
     ["floor", Function::FloatFunc(FloatFunc::Floor)],
     ["round", Function::FloatFunc(FloatFunc::Round)],
+    ["floor_to", Function::FloatFunc(FloatFunc::FloorTo)],
+    ["ceil_to", Function::FloatFunc(FloatFunc::CeilTo)],
     ["cos", Function::FloatFunc(FloatFunc::Cos)],
     ["sin", Function::FloatFunc(FloatFunc::Sin)],
     ["atan", Function::FloatFunc(FloatFunc::Atan)],
@@ -483,16 +705,25 @@ static_map!(
     ["join_fields", Function::JoinCols],
     ["join_csv", Function::JoinCSV],
     ["join_tsv", Function::JoinTSV],
+    ["select", Function::Select],
     ["escape_csv", Function::EscapeCSV],
     ["escape_tsv", Function::EscapeTSV],
     ["rand", Function::Rand],
     ["srand", Function::Srand],
+    ["randint", Function::RandInt],
+    ["choice", Function::Choice],
+    ["shuffle", Function::Shuffle],
+    ["sample", Function::Sample],
     ["index", Function::SubstrIndex],
     ["last_index", Function::SubstrLastIndex],
     ["last_part", Function::LastPart],
     ["toupper", Function::ToUpper],
     ["tolower", Function::ToLower],
     ["system", Function::System],
+    ["exec_output", Function::ExecOutput],
+    ["spawn", Function::Spawn],
+    ["setenv", Function::SetEnv],
+    ["unsetenv", Function::UnsetEnv],
     ["exit", Function::Exit]
 );
 
@@ -502,19 +733,55 @@ impl<'a> TryFrom<&'a str> for Function {
     fn try_from(value: &'a str) -> std::result::Result<Function, ()> {
         match FUNCTIONS.get(value) {
             Some(v) => Ok(*v),
-            None => Err(()),
+            None => runtime::ext::lookup(value).map(Function::Ext).ok_or(()),
         }
     }
 }
 
 pub(crate) trait IsSprintf {
     fn is_sprintf(&self) -> bool;
+    // tee is also var-arg, and is lowered to a sequence of print statements.
+    fn is_tee(&self) -> bool;
+    // Assignments to PARALLEL_MERGE with literal keys and values are interpreted at compile time.
+    fn is_parallel_merge(&self) -> bool;
+    // _map, _filter and _reduce take the name of a user-defined function and are lowered to loops.
+    fn array_fn(&self) -> Option<ArrayFn>;
+    // With -H, `FIELDS[name]` is shorthand for `$FI[name]`.
+    fn is_fields(&self) -> bool;
+    fn fi() -> Self;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ArrayFn {
+    Map,
+    Filter,
+    Reduce,
 }
 
 impl<'a> IsSprintf for &'a str {
     fn is_sprintf(&self) -> bool {
         *self == "sprintf"
     }
+    fn is_tee(&self) -> bool {
+        *self == "tee"
+    }
+    fn is_parallel_merge(&self) -> bool {
+        *self == "PARALLEL_MERGE"
+    }
+    fn is_fields(&self) -> bool {
+        *self == "FIELDS"
+    }
+    fn fi() -> Self {
+        "FI"
+    }
+    fn array_fn(&self) -> Option<ArrayFn> {
+        match *self {
+            "_map" => Some(ArrayFn::Map),
+            "_filter" => Some(ArrayFn::Filter),
+            "_reduce" => Some(ArrayFn::Reduce),
+            _ => None,
+        }
+    }
 }
 
 impl Function {
@@ -535,6 +802,60 @@ impl Function {
                 );
                 ctx.nw.add_dep(arg1, args[1], Constraint::Flows(()));
             }
+            Function::SplitSeps | Function::PatSplit => {
+                for arg in [args[1], args[3]] {
+                    let arr = ctx.constant(
+                        Map {
+                            key: BaseTy::Int,
+                            val: BaseTy::Str,
+                        }
+                            .abs(),
+                    );
+                    ctx.nw.add_dep(arr, arg, Constraint::Flows(()));
+                }
+            }
+            Function::MatchArr => {
+                let arg2 = ctx.constant(
+                    Map {
+                        key: BaseTy::Str,
+                        val: BaseTy::Str,
+                    }
+                        .abs(),
+                );
+                ctx.nw.add_dep(arg2, args[2], Constraint::Flows(()));
+            }
+            Function::ArrayKeys
+            | Function::ArrayValues
+            | Function::ArrayInvert
+            | Function::ArraySortedKeys
+            | Function::ArraySortByValue
+            | Function::Choice
+            | Function::Sample => {
+                let is_map = ctx.constant(Some(Map {
+                    key: None,
+                    val: None,
+                }));
+                ctx.nw.add_dep(is_map, args[0], Constraint::Flows(()));
+            }
+            Function::ToLogfmt | Function::ToToml | Function::ToIni | Function::ToXml => {
+                let str_keys = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Str),
+                    val: None,
+                }));
+                ctx.nw.add_dep(str_keys, args[0], Constraint::Flows(()));
+            }
+            Function::ArraySlice | Function::Shuffle => {
+                let int_keys = ctx.constant(Some(Map {
+                    key: Some(BaseTy::Int),
+                    val: None,
+                }));
+                ctx.nw.add_dep(int_keys, args[0], Constraint::Flows(()));
+            }
+            // Both arrays passed to _merge, _copy, _equals and asort have the same type.
+            Function::ArrayMerge | Function::ArrayCopy | Function::ArrayEquals | Function::Asort => {
+                ctx.nw.add_dep(args[0], args[1], Constraint::Flows(()));
+                ctx.nw.add_dep(args[1], args[0], Constraint::Flows(()));
+            }
             Function::Clear => {
                 let is_map = ctx.constant(Some(Map {
                     key: None,
@@ -628,7 +949,18 @@ impl Function {
             ),
             LastPart => (smallvec![Str, Str], Str),
             Min | Max => (smallvec![Str,Str,Str], Str),
-            StrCmp => (smallvec![Str,Str], Int),
+            DecAdd | DecSub | DecMul | DecDiv => (smallvec![Str, Str], Str),
+            DecCmp => (smallvec![Str, Str], Int),
+            DecFmt => (smallvec![Str, Int], Str),
+            BigAdd | BigSub | BigMul | BigDiv | BigMod | BigPow => (smallvec![Str, Str], Str),
+            BigCmp => (smallvec![Str, Str], Int),
+            U64Add | U64Mul => (smallvec![Str, Str], Str),
+            U64Str => (smallvec![Int], Str),
+            StrCmp | NatCmp | Levenshtein => (smallvec![Str,Str], Int),
+            Similarity => (smallvec![Str,Str], Float),
+            Diff => (smallvec![Str,Str], Str),
+            Soundex | Metaphone => (smallvec![Str], Str),
+            FuzzyMatch => (smallvec![Str, Str, Float], Int),
             DefaultIfEmpty => (smallvec![Str,Str], Str),
             AppendIfMissing | PrependIfMissing | RemoveIfEnd | RemoveIfBegin => (smallvec![Str,Str], Str),
             Quote | DoubleQuote => (smallvec![Str], Str),
@@ -672,8 +1004,27 @@ impl Function {
             }
             Srand => (smallvec![Int], Int),
             System | HexToInt => (smallvec![Str], Int),
+            // A command line for the shell, or the program and its arguments as an array.
+            ExecOutput | Spawn => match incoming[0] {
+                MapIntStr => (smallvec![MapIntStr], Str),
+                _ => (smallvec![Str], Str),
+            },
+            SetEnv => (smallvec![Str, Str], Int),
+            UnsetEnv => (smallvec![Str], Int),
             ReseedRng => (smallvec![], Int),
             Rand => (smallvec![], Float),
+            RandInt => (smallvec![Int, Int], Int),
+            Choice if incoming[0].is_array() => (smallvec![incoming[0]], incoming[0].val()?),
+            Sample if incoming[0].is_array() => {
+                (smallvec![incoming[0], Int], compile::Ty::map(Int, incoming[0].val()?)?)
+            }
+            Choice | Sample => {
+                return err!("invalid input spec for {}: {:?}", self, incoming);
+            }
+            Shuffle => match incoming[0] {
+                MapIntInt | MapIntFloat | MapIntStr => (smallvec![incoming[0]], Int),
+                _ => return err!("shuffle expects an integer-indexed array, got: {:?}", incoming),
+            },
             ToInt => {
                 let inc = incoming[0];
                 match inc {
@@ -699,20 +1050,26 @@ impl Function {
             Ulid => (smallvec![], Str),
             Whoami | Version | Os | OsFamily | Arch | Pwd | UserHome => (smallvec![], Str),
             LocalIp => (smallvec![], Str),
-            Systime => (smallvec![], Int),
-            Strftime => (smallvec![Str, Int], Str),
-            Mktime => (smallvec![Str, Int], Int),
+            Systime | SystimeMs | SystimeUs => (smallvec![], Int),
+            Clock => (smallvec![], Float),
+            Sleep => (smallvec![Float], Int),
+            Strftime => (smallvec![Str, Int, Str], Str),
+            Mktime => (smallvec![Str, Str], Int),
             Duration => (smallvec![Str], Int),
             MkBool => (smallvec![Str], Int),
             Fend => (smallvec![Str], Str),
-            Url | Path | SemVer => (smallvec![Str], MapStrStr),
+            Url | Path | SemVer | UaParse => (smallvec![Str], MapStrStr),
             Pairs => (smallvec![Str,Str,Str], MapStrStr),
+            Logfmt => (smallvec![Str], MapStrStr),
+            ToLogfmt => (smallvec![incoming[0], Str], Str),
             Parse => (smallvec![Str, Str], MapStrStr),
             RegexParse => (smallvec![Str, Str], MapIntStr),
             Record => (smallvec![Str], MapStrStr),
             Message => (smallvec![Str], MapStrStr),
             DataUrl => (smallvec![Str], MapStrStr),
             DateTime => (smallvec![Str], MapStrInt),
+            DateParse => (smallvec![Str, Str], Int),
+            DateAdd | DateDiff => (smallvec![Int, Int, Str], Int),
             Shlex => (smallvec![Str], MapIntStr),
             Tuple => (smallvec![Str], MapIntStr),
             Flags => (smallvec![Str], MapStrInt),
@@ -734,16 +1091,33 @@ impl Function {
             SqliteExecute | MysqlExecute => (smallvec![Str, Str], Int),
             Publish => (smallvec![Str, Str], Null),
             FromJson => (smallvec![Str], MapStrStr),
+            FromToml => (smallvec![Str], MapStrStr),
+            ToToml => (smallvec![incoming[0], Str], Str),
+            TomlValue => (smallvec![Str, Str], Str),
+            FromIni => (smallvec![Str], MapStrStr),
+            ToIni => (smallvec![incoming[0]], Str),
+            PropGet => (smallvec![Str, Str], Str),
+            ToXml => (smallvec![incoming[0], Str], Str),
+            XmlUpdate => (smallvec![Str, Str, Str], Str),
+            HtmlTable => (smallvec![Str, Int], MapIntStr),
+            FromMsgpack | FromCbor => (smallvec![Str], MapStrStr),
+            FromProtobuf => (smallvec![Str, Str, Str], MapStrStr),
             ToJson => (smallvec![incoming[0]], Str),
             VarDump => (smallvec![incoming[0]], Null),
             ReadAll => (smallvec![Str], Str),
-            WriteAll => (smallvec![Str, Str], Null),
+            ReadLines => (smallvec![Str], MapIntStr),
+            WriteAll | AppendAll => (smallvec![Str, Str], Null),
+            Glob => (smallvec![Str], MapIntStr),
+            Stat => (smallvec![Str], MapStrStr),
+            Exists | Mkdirp | RmFile => (smallvec![Str], Int),
             FromCsv => (smallvec![Str], MapIntStr),
             ToCsv => (smallvec![incoming[0]], Str),
             Trim => (smallvec![Str, Str], Str),
             Truncate => (smallvec![Str, Int, Str], Str),
             Strtonum => (smallvec![Str], Float),
+            ParseNumber => (smallvec![Str, Str], Float),
             FormatBytes => (smallvec![Int], Str),
+            FormatNum => (smallvec![Float, Str, Str], Str),
             ToBytes => (smallvec![Str], Int),
             StartsWith => (smallvec![Str, Str], Int),
             EndsWith => (smallvec![Str, Str], Int),
@@ -756,13 +1130,22 @@ impl Function {
             Escape => (smallvec![Str, Str], Str),
             Encode => (smallvec![Str, Str], Str),
             Decode => (smallvec![Str, Str], Str),
+            Iconv => (smallvec![Str, Str, Str], Str),
+            Compress | Decompress => (smallvec![Str, Str], Str),
+            Utf8Valid => (smallvec![Str], Int),
+            Utf8Fix | Normalize(_) => (smallvec![Str], Str),
             Digest => (smallvec![Str, Str], Str),
+            FileDigest => (smallvec![Str, Str], Str),
+            DigestInit => (smallvec![Str, Str], Int),
+            DigestUpdate => (smallvec![Int, Str], Int),
+            DigestFinal => (smallvec![Int], Str),
             Hmac => (smallvec![Str, Str, Str], Str),
             Jwt => (smallvec![Str, Str, MapStrStr], Str),
-            Dejwt => (smallvec![Str, Str], MapStrStr),
+            Dejwt => (smallvec![Str, Str, Str], MapStrStr),
+            Cert => (smallvec![Str], MapStrStr),
             Encrypt => (smallvec![Str, Str, Str], Str),
             Decrypt => (smallvec![Str, Str, Str], Str),
-            Asort => (smallvec![incoming[0],incoming[0]], Int),
+            Asort => (smallvec![incoming[0], incoming[0], Str], Int),
             BloomFilterInsert => (smallvec![Str, Str], Null),
             BloomFilterContains | BloomFilterContainsWithInsert => (smallvec![Str, Str], Int),
             Fake => (smallvec![Str, Str], Str),
@@ -770,8 +1153,37 @@ impl Function {
             IsArray => (smallvec![incoming[0]], Int),
             IsInt => (smallvec![incoming[0]], Int),
             IsNum => (smallvec![incoming[0]], Int),
+            IsNan | IsInf => (smallvec![Float], Int),
             IsFormat => (smallvec![Str, Str], Int),
+            IpInCidr | CidrContains => (smallvec![Str, Str], Int),
+            Ip2Int | IpVersion => (smallvec![Str], Int),
+            Int2Ip => (smallvec![Int], Str),
+            Geoip => (smallvec![Str], MapStrStr),
+            GeoipDb => (smallvec![Str], Int),
             IntMapJoin => (smallvec![incoming[0], Str], Str),
+            ArrayKeys | ArrayValues | ArrayInvert | ArrayMerge if !incoming[0].is_array() => {
+                return err!("invalid input spec for {}: {:?}", self, incoming);
+            }
+            ArrayKeys => (smallvec![incoming[0]], compile::Ty::map(Int, incoming[0].key()?)?),
+            ArraySortedKeys | ArraySortByValue if incoming[0].is_array() => {
+                (smallvec![incoming[0], Str], compile::Ty::map(Int, incoming[0].key()?)?)
+            }
+            ArraySortedKeys | ArraySortByValue => {
+                return err!("invalid input spec for {}: {:?}", self, incoming);
+            }
+            ArrayValues => (smallvec![incoming[0]], compile::Ty::map(Int, incoming[0].val()?)?),
+            ArrayInvert => (smallvec![incoming[0]], incoming[0].inverted()?),
+            ArrayMerge => (smallvec![incoming[0], incoming[0]], incoming[0]),
+            ArrayCopy | ArrayEquals if incoming[0].is_array() => {
+                (smallvec![incoming[0], incoming[0]], Int)
+            }
+            ArrayCopy | ArrayEquals => {
+                return err!("invalid input spec for {}: {:?}", self, incoming);
+            }
+            ArraySlice => match incoming[0] {
+                MapIntInt | MapIntFloat | MapIntStr => (smallvec![incoming[0], Int, Int], incoming[0]),
+                _ => return err!("_slice expects an integer-indexed array, got: {:?}", incoming),
+            },
             ArrayMax | ArrayMin | ArraySum | ArrayMean => {
                 if let MapIntInt = incoming[0] {
                     (smallvec![incoming[0]], Int)
@@ -782,13 +1194,16 @@ impl Function {
                 }
             }
             Close => (smallvec![Str], Str),
+            FFlush => (smallvec![Str], Int),
             Sub | GSub => (smallvec![Str, Str, Str], Int),
             GenSub => (smallvec![Str, Str, Str, Str], Str),
             ToUpper | ToLower | EscapeCSV | EscapeTSV => (smallvec![Str], Str),
             Substr => (smallvec![Str, Int, Int], Str),
             CharAt => (smallvec![Str, Int], Str),
             Match => (smallvec![Str, Str], Int),
+            MatchArr => (smallvec![Str, Str, MapStrStr], Int),
             Exit => (smallvec![Int], Null),
+            Ext(ix) => (smallvec![Str; runtime::ext::get(*ix).arity], Str),
             // Split's second input can be a map of either type
             Split => {
                 if let MapIntStr | MapStrStr = incoming[1] {
@@ -797,8 +1212,16 @@ impl Function {
                     return err!("invalid input spec for split: {:?}", incoming);
                 }
             }
+            // Both arrays of split with separators, and of patsplit, have the same type.
+            SplitSeps | PatSplit => match (incoming[1], incoming[3]) {
+                (MapIntStr, MapIntStr) | (MapStrStr, MapStrStr) => {
+                    (smallvec![Str, incoming[1], Str, incoming[3]], Int)
+                }
+                _ => return err!("invalid input spec for {}: {:?}", self, incoming),
+            },
             JoinCols => (smallvec![Int, Int, Str], Str),
             JoinCSV | JoinTSV => (smallvec![Int, Int], Str),
+            Select => (smallvec![Str, Str, Int], Str),
             SetFI => (smallvec![Int, Int], Int),
         })
     }
@@ -808,17 +1231,27 @@ impl Function {
         Some(match self {
             FloatFunc(ff) => ff.arity(),
             IntFunc(bw) => bw.arity(),
-            UpdateUsedFields | Rand | Ulid | LocalIp | Systime | ReseedRng | ReadErrStdin | NextlineStdin | NextFile
+            UpdateUsedFields | Rand | Ulid | LocalIp | Systime | SystimeMs | SystimeUs | Clock | ReseedRng | ReadErrStdin | NextlineStdin | NextFile
             | ReadLineStdinFused => 0,
             Whoami | Version | Os | OsFamily | Arch | Pwd | UserHome => 0,
-            Exit | ToUpper | ToLower | Clear | Srand | System | HexToInt | ToInt | EscapeCSV
-            | EscapeTSV | Close | Length | ReadErr | ReadErrCmd | Nextline | NextlineCmd
-            | Uuid | SnowFlake | Fend | Url | SemVer | Path | DataUrl | DateTime | Shlex | Tuple | Variant | Flags | ParseArray | Func | ToJson | FromJson | ToCsv | FromCsv | TypeOfVariable | IsArray | Unop(_) => 1,
-            SetFI | SubstrIndex | SubstrLastIndex | Match | Setcol | Binop(_) => 2,
+            Exit | ToUpper | ToLower | Clear | Srand | System | ExecOutput | Spawn | HexToInt | ToInt | EscapeCSV
+            | EscapeTSV | Close | FFlush | Length | ReadErr | ReadErrCmd | Nextline | NextlineCmd
+            | Uuid | SnowFlake | Fend | Url | UaParse | SemVer | Path | DataUrl | DateTime | Shlex | Tuple | Variant | Flags | ParseArray | Func | ToJson | FromJson | FromToml | ToCsv | FromCsv | TypeOfVariable | IsArray | Unop(_) => 1,
+            UnsetEnv => 1,
+            Sleep => 1,
+            SetFI | SubstrIndex | SubstrLastIndex | Match | Setcol | SetEnv | Binop(_) => 2,
             JoinCSV | JoinTSV | Delete | Contains => 2,
             DefaultIfEmpty => 2,
             AppendIfMissing | PrependIfMissing | RemoveIfEnd | RemoveIfBegin => 2,
             Pairs => 3,
+            Logfmt => 1,
+            ToToml | TomlValue => 2,
+            FromIni | ToIni => 1,
+            PropGet | ToXml | HtmlTable => 2,
+            XmlUpdate | FromProtobuf => 3,
+            FromMsgpack | FromCbor => 1,
+            ToLogfmt => 2,
+            SplitSeps | PatSplit => 4,
             LastPart => 2,
             Hex2Rgb => 1,
             Rgb2Hex => 3,
@@ -827,25 +1260,37 @@ impl Function {
             Quote | DoubleQuote => 1,
             VarDump => 1,
             FormatBytes | ToBytes => 1,
+            FormatNum => 3,
             StartsWith | EndsWith | TextContains => 2,
-            ReadAll => 1,
-            WriteAll => 2,
-            Dejwt => 2,
+            ReadAll | ReadLines => 1,
+            WriteAll | AppendAll => 2,
+            Glob | Stat | Exists | Mkdirp | RmFile => 1,
+            Dejwt => 3,
+            Cert => 1,
             BloomFilterInsert | BloomFilterContains | BloomFilterContainsWithInsert => 2,
             Fake => 2,
             Encrypt | Decrypt => 3,
-            Strftime | Mktime => 2,
+            Strftime => 3,
+            DateParse => 2,
+            DateAdd | DateDiff => 3,
+            Mktime => 2,
             Duration => 1,
-            StrCmp => 2,
+            StrCmp | NatCmp | Levenshtein | Similarity | Diff => 2,
+            Soundex | Metaphone => 1,
+            FuzzyMatch => 3,
             CharAt => 2,
             MkBool => 1,
             Trim => 2,
             Capitalize | UnCapitalize | Mask | Strtonum | CamelCase | KebabCase | SnakeCase | TitleCase | Words => 1,
             Repeat => 2,
             Min | Max => 3,
+            DecAdd | DecSub | DecMul | DecDiv | DecCmp | DecFmt | ParseNumber => 2,
+            BigAdd | BigSub | BigMul | BigDiv | BigMod | BigPow | BigCmp => 2,
+            U64Add | U64Mul => 2,
+            U64Str => 1,
             Seq => 3,
             Uniq => 2,
-            Asort => 2,
+            Asort => 3,
             HttpGet => 2,
             HttpPost => 3,
             S3Get => 2,
@@ -856,15 +1301,26 @@ impl Function {
             SqliteQuery | SqliteExecute | MysqlQuery | MysqlExecute => 2,
             PadLeft | PadRight | PadBoth => 3,
             Publish => 2,
-            IsInt | IsNum => 1,
+            IsInt | IsNum | IsNan | IsInf => 1,
             IsFormat => 2,
-            Encode | Decode | Digest | Escape => 2,
-            Hmac | Jwt => 3,
+            IpInCidr | CidrContains => 2,
+            Ip2Int | Int2Ip | IpVersion => 1,
+            Geoip | GeoipDb => 1,
+            Encode | Decode | Compress | Decompress | Digest | FileDigest | DigestInit | DigestUpdate | Escape => 2,
+            DigestFinal => 1,
+            Hmac | Jwt | Iconv => 3,
+            Utf8Valid | Utf8Fix | Normalize(_) => 1,
             LogDebug | LogInfo | LogWarn | LogError => 1,
             ArrayMax | ArrayMin | ArraySum | ArrayMean => 1,
             IntMapJoin => 2,
-            IncMap | JoinCols | Substr | Sub | GSub | Split | Truncate => 3,
+            ArrayKeys | ArrayValues | ArrayInvert => 1,
+            ArrayMerge | ArrayCopy | ArrayEquals | ArraySortedKeys | ArraySortByValue => 2,
+            ArraySlice => 3,
+            RandInt | Sample => 2,
+            Choice | Shuffle => 1,
+            IncMap | JoinCols | Select | Substr | Sub | GSub | Split | Truncate | MatchArr => 3,
             GenSub => 4,
+            Ext(ix) => runtime::ext::get(*ix).arity,
         })
     }
 
@@ -896,19 +1352,30 @@ impl Function {
                 Ok(step_arith(&args[0], &args[1]))
             }
             Min | Max => Ok(Scalar(BaseTy::Str).abs()),
-            Rand | Binop(Div) | Binop(Pow) => Ok(Scalar(BaseTy::Float).abs()),
+            DecAdd | DecSub | DecMul | DecDiv | DecFmt => Ok(Scalar(BaseTy::Str).abs()),
+            DecCmp => Ok(Scalar(BaseTy::Int).abs()),
+            BigAdd | BigSub | BigMul | BigDiv | BigMod | BigPow => Ok(Scalar(BaseTy::Str).abs()),
+            BigCmp => Ok(Scalar(BaseTy::Int).abs()),
+            U64Add | U64Mul | U64Str => Ok(Scalar(BaseTy::Str).abs()),
+            Rand | Clock | Binop(Div) | Binop(Pow) => Ok(Scalar(BaseTy::Float).abs()),
+            RandInt | Shuffle => Ok(Scalar(BaseTy::Int).abs()),
+            Choice => match &args[0] {
+                Some(Map { val: Some(val), .. }) => Ok(Scalar(*val).abs()),
+                _ => Ok(None),
+            },
             Setcol => Ok(Scalar(BaseTy::Null).abs()),
             Clear | SubstrIndex | SubstrLastIndex | Srand | ReseedRng | Unop(Not) | Binop(IsMatch) | Binop(LT)
             | Binop(GT) | Binop(LTE) | Binop(GTE) | Binop(EQ) | Length | Split | ReadErr
-            | ReadErrCmd | ReadErrStdin | Contains | Delete | Match | Sub | GSub | ToInt | Systime | Mktime | Duration
-            | System | HexToInt | Asort | MkBool | SnowFlake => Ok(Scalar(BaseTy::Int).abs()),
-            ToUpper | ToLower | JoinCSV | JoinTSV | Uuid | Ulid | LocalIp | Strftime | Fend | Trim | Truncate | JoinCols
+            | ReadErrCmd | ReadErrStdin | Contains | Delete | Match | MatchArr | SplitSeps | PatSplit | Sub | GSub | ToInt | Systime | SystimeMs | SystimeUs | Sleep | Mktime | DateParse | DateAdd | DateDiff | Duration
+            | System | SetEnv | UnsetEnv | FFlush | HexToInt | Asort | ArrayCopy | ArrayEquals | MkBool | SnowFlake => Ok(Scalar(BaseTy::Int).abs()),
+            ToUpper | ToLower | JoinCSV | JoinTSV | Uuid | Ulid | LocalIp | Strftime | Fend | Trim | Truncate | JoinCols | Select
             | EscapeCSV | EscapeTSV | Escape
             | Unop(Column) | Binop(Concat) | Nextline | NextlineCmd | NextlineStdin | GenSub | Substr | CharAt
-            | Encode | Decode | Digest | Hmac | Jwt | ToJson | ToCsv | TypeOfVariable | IntMapJoin => {
+            | Encode | Decode | Iconv | Compress | Decompress | Utf8Fix | Normalize(_) | Digest | FileDigest | DigestFinal | Hmac | Jwt | ToJson | ToLogfmt | ToToml | TomlValue | ToIni | PropGet | ToXml | XmlUpdate | ToCsv | TypeOfVariable | IntMapJoin => {
                 Ok(Scalar(BaseTy::Str).abs())
             }
             Encrypt | Decrypt => Ok(Scalar(BaseTy::Str).abs()),
+            Ext(_) => Ok(Scalar(BaseTy::Str).abs()),
             Fake => Ok(Scalar(BaseTy::Str).abs()),
             Whoami | Version | Os | OsFamily | Arch | Pwd | UserHome => {
                 Ok(Scalar(BaseTy::Str).abs())
@@ -916,7 +1383,7 @@ impl Function {
             LastPart => {
                 Ok(Scalar(BaseTy::Str).abs())
             }
-            FormatBytes => {
+            FormatBytes | FormatNum => {
                 Ok(Scalar(BaseTy::Str).abs())
             }
             ToBytes => {
@@ -929,13 +1396,15 @@ impl Function {
             BloomFilterContains | BloomFilterContainsWithInsert => {
                 Ok(Scalar(BaseTy::Int).abs())
             }
-            Strtonum => Ok(Scalar(BaseTy::Float).abs()),
+            Strtonum | ParseNumber => Ok(Scalar(BaseTy::Float).abs()),
             Capitalize | UnCapitalize | Mask | CamelCase | KebabCase | SnakeCase | TitleCase | Repeat => Ok(Scalar(BaseTy::Str).abs()),
             DefaultIfEmpty => Ok(Scalar(BaseTy::Str).abs()),
             AppendIfMissing | PrependIfMissing | RemoveIfEnd | RemoveIfBegin => Ok(Scalar(BaseTy::Str).abs()),
             Quote | DoubleQuote => Ok(Scalar(BaseTy::Str).abs()),
-            IsArray | IsNum | IsInt | IsFormat => Ok(Scalar(BaseTy::Int).abs()),
-            Url | SemVer | Path | DataUrl | Dejwt | Pairs | Record | Message => {
+            IsArray | IsNum | IsInt | IsNan | IsInf | IsFormat | Utf8Valid => Ok(Scalar(BaseTy::Int).abs()),
+            IpInCidr | CidrContains | Ip2Int | IpVersion | GeoipDb => Ok(Scalar(BaseTy::Int).abs()),
+            Int2Ip => Ok(Scalar(BaseTy::Str).abs()),
+            Url | UaParse | SemVer | Path | DataUrl | Dejwt | Cert | Geoip | Pairs | Logfmt | FromIni | Record | Message => {
                 Ok(Map {
                     key: BaseTy::Str,
                     val: BaseTy::Str,
@@ -965,7 +1434,7 @@ impl Function {
                     val: BaseTy::Str,
                 }.abs())
             }
-            Shlex | Func | Tuple | ParseArray => {
+            Shlex | Func | Tuple | ParseArray | HtmlTable => {
                 Ok(Map {
                     key: BaseTy::Int,
                     val: BaseTy::Str,
@@ -1012,7 +1481,7 @@ impl Function {
                 }.abs())
             }
             S3Get | S3Put => Ok(Scalar(BaseTy::Str).abs()),
-            FromJson => {
+            FromJson | FromToml | FromMsgpack | FromCbor | FromProtobuf => {
                 Ok(Map {
                     key: BaseTy::Str,
                     val: BaseTy::Str,
@@ -1031,6 +1500,24 @@ impl Function {
                 }.abs())
             }
             PadLeft | PadRight | PadBoth => Ok(Scalar(BaseTy::Str).abs()),
+            ArrayKeys | ArrayValues | ArrayInvert | ArraySortedKeys | ArraySortByValue | Sample => match &args[0] {
+                Some(Map { key, val }) => Ok(Some(match self {
+                    ArrayKeys | ArraySortedKeys | ArraySortByValue => Map {
+                        key: Some(BaseTy::Int),
+                        val: *key,
+                    },
+                    ArrayValues | Sample => Map {
+                        key: Some(BaseTy::Int),
+                        val: *val,
+                    },
+                    _ => Map {
+                        key: val.map(|v| if v == BaseTy::Float { BaseTy::Str } else { v }),
+                        val: *key,
+                    },
+                })),
+                _ => Ok(None),
+            },
+            ArrayMerge | ArraySlice => Ok(args[0]),
             ArrayMax | ArrayMin | ArraySum | ArrayMean => match &args[0] {
                 Some(Map {
                          key: Some(BaseTy::Int),
@@ -1046,12 +1533,36 @@ impl Function {
                 }
                 _ => { Ok(Scalar(BaseTy::Float).abs()) }
             },
-            StrCmp => Ok(Scalar(BaseTy::Int).abs()),
+            StrCmp | NatCmp | Levenshtein => Ok(Scalar(BaseTy::Int).abs()),
+            Similarity => Ok(Scalar(BaseTy::Float).abs()),
+            Diff | Soundex | Metaphone => Ok(Scalar(BaseTy::Str).abs()),
+            FuzzyMatch => Ok(Scalar(BaseTy::Int).abs()),
             IncMap => Ok(step_arith(&types::val_of(&args[0])?, &args[2])),
             Exit | SetFI | UpdateUsedFields | NextFile | ReadLineStdinFused | Close => Ok(None),
             KvGet => Ok(Scalar(BaseTy::Str).abs()),
             ReadAll => Ok(Scalar(BaseTy::Str).abs()),
-            WriteAll => Ok(None),
+            ReadLines => {
+                Ok(Map {
+                    key: BaseTy::Int,
+                    val: BaseTy::Str,
+                }.abs())
+            }
+            ExecOutput | Spawn => Ok(Scalar(BaseTy::Str).abs()),
+            WriteAll | AppendAll => Ok(None),
+            Glob => {
+                Ok(Map {
+                    key: BaseTy::Int,
+                    val: BaseTy::Str,
+                }.abs())
+            }
+            Stat => {
+                Ok(Map {
+                    key: BaseTy::Str,
+                    val: BaseTy::Str,
+                }.abs())
+            }
+            Exists | Mkdirp | RmFile => Ok(Scalar(BaseTy::Int).abs()),
+            DigestInit | DigestUpdate => Ok(Scalar(BaseTy::Int).abs()),
             KvPut | KvDelete | KvClear => Ok(None),
             VarDump => Ok(None),
             LogDebug | LogInfo | LogWarn | LogError => Ok(None),
@@ -1060,7 +1571,7 @@ impl Function {
     }
 }
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 // We may relax this in the future, but these names are all-caps here to match
 // their names in Awk.
 #[allow(clippy::upper_case_acronyms)]
@@ -1081,14 +1592,19 @@ pub(crate) enum Variable {
     FI = 13,
     ENVIRON = 14,
     PROCINFO = 15,
+    ERRNO = 16,
+    IGNORECASE = 17,
+    OCSVQUOTE = 18,
+    CONVFMT = 19,
+    OFMT = 20,
 }
 
 impl From<Variable> for compile::Ty {
     fn from(v: Variable) -> compile::Ty {
         use Variable::*;
         match v {
-            FS | OFS | ORS | RS | FILENAME => compile::Ty::Str,
-            PID | ARGC | NF | NR | FNR | RSTART | RLENGTH => compile::Ty::Int,
+            FS | OFS | ORS | RS | FILENAME | ERRNO | OCSVQUOTE | CONVFMT | OFMT => compile::Ty::Str,
+            PID | ARGC | NF | NR | FNR | RSTART | RLENGTH | IGNORECASE => compile::Ty::Int,
             ARGV => compile::Ty::MapIntStr,
             FI => compile::Ty::MapStrInt,
             ENVIRON => compile::Ty::MapStrStr,
@@ -1114,6 +1630,11 @@ pub(crate) struct Variables<'a> {
     pub fi: StrMap<'a, Int>,
     pub environ: StrMap<'a, Str<'a>>,
     pub procinfo: StrMap<'a, Str<'a>>,
+    pub errno: Str<'a>,
+    pub ignorecase: Int,
+    pub ocsvquote: Str<'a>,
+    pub convfmt: Str<'a>,
+    pub ofmt: Str<'a>,
 }
 
 impl<'a> Default for Variables<'a> {
@@ -1135,6 +1656,11 @@ impl<'a> Default for Variables<'a> {
             fi: Default::default(),
             environ: load_env_variables(),
             procinfo: load_procinfo_variables(),
+            errno: Default::default(),
+            ignorecase: 0,
+            ocsvquote: Default::default(),
+            convfmt: Default::default(),
+            ofmt: Default::default(),
         }
     }
 }
@@ -1151,7 +1677,7 @@ fn load_env_variables<'a>() -> StrMap<'a, Str<'a>> {
 fn load_procinfo_variables<'a>() -> StrMap<'a, Str<'a>> {
     let procinfo = StrMap::default();
     procinfo.insert("version".into(), VERSION.into());
-    procinfo.insert("strftime".into(), "%a %m %e %H:%M:%S %Z %Y".into());
+    procinfo.insert("strftime".into(), runtime::date_time::DEFAULT_FORMAT.into());
     procinfo.insert("pid".into(), std::process::id().to_string().into());
     procinfo.insert("platform".into(), "posix".into());
     unsafe {
@@ -1169,12 +1695,22 @@ fn load_procinfo_variables<'a>() -> StrMap<'a, Str<'a>> {
 fn load_procinfo_variables<'a>() -> StrMap<'a, Str<'a>> {
     let procinfo = StrMap::default();
     procinfo.insert("version".into(), VERSION.into());
-    procinfo.insert("strftime".into(), "%a %m %e %H:%M:%S %Z %Y".into());
+    procinfo.insert("strftime".into(), runtime::date_time::DEFAULT_FORMAT.into());
     procinfo.insert("pid".into(), std::process::id().to_string().into());
     procinfo.insert("platform".into(), "windows".into());
     procinfo
 }
 
+// WASI has no process ids or users.
+#[cfg(target_family = "wasm")]
+fn load_procinfo_variables<'a>() -> StrMap<'a, Str<'a>> {
+    let procinfo = StrMap::default();
+    procinfo.insert("version".into(), VERSION.into());
+    procinfo.insert("strftime".into(), runtime::date_time::DEFAULT_FORMAT.into());
+    procinfo.insert("platform".into(), "wasm".into());
+    procinfo
+}
+
 impl<'a> Variables<'a> {
     pub fn load_int(&self, var: Variable) -> Result<Int> {
         use Variable::*;
@@ -1186,7 +1722,11 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart,
             RLENGTH => self.rlength,
             PID => self.pid,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV | ENVIRON | PROCINFO => return err!("var {} not an int", var),
+            IGNORECASE => self.ignorecase,
+            FI | ORS | OFS | FS | RS | FILENAME | ERRNO | ARGV | ENVIRON | PROCINFO | OCSVQUOTE | CONVFMT
+            | OFMT => {
+                return err!("var {} not an int", var)
+            }
         })
     }
 
@@ -1200,12 +1740,19 @@ impl<'a> Variables<'a> {
             RSTART => self.rstart = i,
             RLENGTH => self.rlength = i,
             PID => self.pid = i,
-            FI | ORS | OFS | FS | RS | FILENAME | ARGV | ENVIRON | PROCINFO => return err!("var {} not an int", var),
+            IGNORECASE => {
+                self.ignorecase = i;
+                runtime::str_impl::set_ignore_case(i != 0);
+            }
+            FI | ORS | OFS | FS | RS | FILENAME | ERRNO | ARGV | ENVIRON | PROCINFO | OCSVQUOTE | CONVFMT
+            | OFMT => {
+                return err!("var {} not an int", var)
+            }
         }
         Ok(())
     }
 
-    pub fn load_str(&self, var: Variable) -> Result<Str<'a>> {
+    pub fn load_str(&mut self, var: Variable) -> Result<Str<'a>> {
         use Variable::*;
         Ok(match var {
             FS => self.fs.clone(),
@@ -1213,7 +1760,16 @@ impl<'a> Variables<'a> {
             ORS => self.ors.clone(),
             RS => self.rs.clone(),
             FILENAME => self.filename.clone(),
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO => {
+            OCSVQUOTE => self.ocsvquote.clone(),
+            CONVFMT => self.convfmt.clone(),
+            OFMT => self.ofmt.clone(),
+            ERRNO => {
+                if let Some(msg) = runtime::errors::take() {
+                    self.errno = Str::from(msg);
+                }
+                self.errno.clone()
+            }
+            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO | IGNORECASE => {
                 return err!("var {} not a string", var);
             }
         })
@@ -1221,24 +1777,49 @@ impl<'a> Variables<'a> {
 
     pub fn store_str(&mut self, var: Variable, s: Str<'a>) -> Result<()> {
         use Variable::*;
+        use runtime::printf::{set_number_format, NumberFormat};
         match var {
             FS => self.fs = s,
             OFS => self.ofs = s,
             ORS => self.ors = s,
             RS => self.rs = s,
             FILENAME => self.filename = s,
-            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO => {
+            OCSVQUOTE => {
+                use runtime::splitter::batch::{set_csv_quoting, CsvQuoting};
+                set_csv_quoting(CsvQuoting::parse(&s.to_string())?);
+                self.ocsvquote = s
+            }
+            CONVFMT => {
+                s.with_bytes(|bs| set_number_format(NumberFormat::Convert, bs))?;
+                self.convfmt = s
+            }
+            OFMT => {
+                s.with_bytes(|bs| set_number_format(NumberFormat::Output, bs))?;
+                self.ofmt = s
+            }
+            ERRNO => {
+                runtime::errors::clear();
+                self.errno = s
+            }
+            FI | PID | ARGC | ARGV | NF | NR | FNR | RSTART | RLENGTH | ENVIRON | PROCINFO | IGNORECASE => {
                 return err!("var {} not a string", var);
             }
         };
         Ok(())
     }
 
+    /// Set ERRNO to `msg`, replacing any error reported by a builtin before it.
+    pub(crate) fn set_errno(&mut self, msg: &'a str) {
+        runtime::errors::clear();
+        self.errno = Str::from(msg);
+    }
+
     pub fn load_intmap(&self, var: Variable) -> Result<IntMap<Str<'a>>> {
         use Variable::*;
         match var {
             ARGV => Ok(self.argv.clone()),
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH | ENVIRON | PROCINFO => {
+            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | RLENGTH | ENVIRON | PROCINFO | IGNORECASE | OCSVQUOTE
+            | CONVFMT | OFMT => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -1251,7 +1832,8 @@ impl<'a> Variables<'a> {
                 self.argv = m;
                 Ok(())
             }
-            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | RLENGTH | ENVIRON | PROCINFO => {
+            FI | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | RLENGTH | ENVIRON | PROCINFO | IGNORECASE | OCSVQUOTE
+            | CONVFMT | OFMT => {
                 err!("var {} is not an int-keyed map", var)
             }
         }
@@ -1261,8 +1843,8 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             FI => Ok(self.fi.clone()),
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | ENVIRON | PROCINFO
-            | RLENGTH => {
+            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | ENVIRON | PROCINFO
+            | RLENGTH | IGNORECASE | OCSVQUOTE | CONVFMT | OFMT => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -1275,8 +1857,8 @@ impl<'a> Variables<'a> {
                 self.fi = m;
                 Ok(())
             }
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | ENVIRON | PROCINFO
-            | RLENGTH => {
+            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | ENVIRON | PROCINFO
+            | RLENGTH | IGNORECASE | OCSVQUOTE | CONVFMT | OFMT => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -1286,9 +1868,14 @@ impl<'a> Variables<'a> {
         use Variable::*;
         match var {
             ENVIRON => Ok(self.environ.clone()),
-            PROCINFO => Ok(self.environ.clone()),
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | FI
-            | RLENGTH => {
+            PROCINFO => {
+                for (k, v) in runtime::mem_stats::entries() {
+                    self.procinfo.insert(k.into(), v.to_string().into());
+                }
+                Ok(self.procinfo.clone())
+            }
+            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | FI
+            | RLENGTH | IGNORECASE | OCSVQUOTE | CONVFMT | OFMT => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
@@ -1305,19 +1892,66 @@ impl<'a> Variables<'a> {
                 self.procinfo = m;
                 Ok(())
             }
-            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | RSTART | FI
-            | RLENGTH => {
+            ARGV | PID | ORS | OFS | ARGC | NF | NR | FNR | FS | RS | FILENAME | ERRNO | RSTART | FI
+            | RLENGTH | IGNORECASE | OCSVQUOTE | CONVFMT | OFMT => {
                 err!("var {} is not a string-keyed map", var)
             }
         }
     }
+
+    /// The timeout for reads from the file or command `input`, taken from
+    /// `PROCINFO[input, "READ_TIMEOUT"]` or else `PROCINFO["READ_TIMEOUT"]`, in milliseconds. As in
+    /// gawk, a missing or non-positive value means reads never time out.
+    pub(crate) fn read_timeout(&self, input: &Str<'a>) -> Option<Duration> {
+        // NB: this assumes the default SUBSEP.
+        let specific = Str::concat(input.clone(), Str::from("\x1cREAD_TIMEOUT"));
+        let ms = [specific, Str::from("READ_TIMEOUT")]
+            .into_iter()
+            .find(|k| self.procinfo.contains(k))
+            .map(|k| runtime::convert::<&Str, Int>(&self.procinfo.get(&k)))?;
+        if ms > 0 {
+            Some(Duration::from_millis(ms as u64))
+        } else {
+            None
+        }
+    }
+
+    /// Set `name` in ENVIRON and in the environment of the zawk process, which is what commands
+    /// started afterwards inherit. Returns 0, or -1 (changing nothing) if `name` or `value` cannot
+    /// be stored in the environment.
+    pub(crate) fn setenv(&mut self, name: &Str<'a>, value: &Str<'a>) -> Int {
+        let (k, v) = (name.to_string(), value.to_string());
+        if !valid_env_name(&k) || v.contains('\0') {
+            return -1;
+        }
+        std::env::set_var(k, v);
+        self.environ.insert(name.clone(), value.clone());
+        0
+    }
+
+    /// Remove `name` from ENVIRON and from the environment of the zawk process. Returns 0, or -1 if
+    /// `name` is not a valid variable name.
+    pub(crate) fn unsetenv(&mut self, name: &Str<'a>) -> Int {
+        let k = name.to_string();
+        if !valid_env_name(&k) {
+            return -1;
+        }
+        std::env::remove_var(k);
+        self.environ.delete(name);
+        0
+    }
+}
+
+// std::env::set_var panics on names that the platform cannot represent.
+fn valid_env_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0'])
 }
 
 impl Variable {
     pub(crate) fn ty(&self) -> types::TVar<types::BaseTy> {
         use Variable::*;
         match self {
-            PID | ARGC | NF | FNR | NR | RSTART | RLENGTH => {
+            PID | ARGC | NF | FNR | NR | RSTART | RLENGTH | IGNORECASE => {
                 types::TVar::Scalar(types::BaseTy::Int)
             }
             // NB: For full compliance, this may have to be Str -> Str
@@ -1354,7 +1988,9 @@ impl Variable {
                 key: types::BaseTy::Str,
                 val: types::BaseTy::Str,
             },
-            ORS | OFS | FS | RS | FILENAME => types::TVar::Scalar(types::BaseTy::Str),
+            ORS | OFS | FS | RS | FILENAME | ERRNO | OCSVQUOTE | CONVFMT | OFMT => {
+                types::TVar::Scalar(types::BaseTy::Str)
+            }
         }
     }
 }
@@ -1392,6 +2028,11 @@ impl TryFrom<usize> for Variable {
             13 => Ok(FI),
             14 => Ok(ENVIRON),
             15 => Ok(PROCINFO),
+            16 => Ok(ERRNO),
+            17 => Ok(IGNORECASE),
+            18 => Ok(OCSVQUOTE),
+            19 => Ok(CONVFMT),
+            20 => Ok(OFMT),
             _ => Err(()),
         }
     }
@@ -1414,5 +2055,10 @@ static_map!(
     ["PID", Variable::PID],
     ["FI", Variable::FI],
     ["ENVIRON", Variable::ENVIRON],
-    ["PROCINFO", Variable::PROCINFO]
+    ["PROCINFO", Variable::PROCINFO],
+    ["ERRNO", Variable::ERRNO],
+    ["IGNORECASE", Variable::IGNORECASE],
+    ["OCSVQUOTE", Variable::OCSVQUOTE],
+    ["CONVFMT", Variable::CONVFMT],
+    ["OFMT", Variable::OFMT]
 );
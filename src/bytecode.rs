@@ -2,17 +2,18 @@ use std::hash::{Hash, Hasher};
 use std::marker::PhantomData;
 use std::sync::Arc;
 
-use crate::builtins::{Bitwise, FloatFunc, Variable};
+use crate::builtins::{Bitwise, FloatFunc, NormalForm, Variable};
 use crate::common::{FileSpec, NumTy};
 use crate::compile::{self, Ty};
 use crate::interp::{index, index_mut, Storage};
-use crate::runtime::{self, Float, Int, Str, UniqueStr};
+use crate::runtime::{self, safe_math, Float, Int, Str, UniqueStr};
 
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub(crate) use crate::interp::Interp;
 
-#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct Label(pub usize);
 
 impl std::fmt::Debug for Label {
@@ -61,17 +62,32 @@ impl<T> PartialEq for Reg<T> {
 impl<T> Eq for Reg<T> {}
 // PhantomData gets in the way here.
 unsafe impl<T> Send for Reg<T> {}
+impl<T> Serialize for Reg<T> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        self.0.serialize(s)
+    }
+}
+impl<'de, T> Deserialize<'de> for Reg<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Reg<T>, D::Error> {
+        Ok(Reg(u32::deserialize(d)?, PhantomData))
+    }
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum Instr<'a> {
     // By default, instructions have destination first, and src(s) second.
-    StoreConstStr(Reg<Str<'a>>, UniqueStr<'a>),
+    StoreConstStr(
+        Reg<Str<'a>>,
+        #[serde(with = "crate::precompile::unique_str")] UniqueStr<'a>,
+    ),
     StoreConstInt(Reg<Int>, Int),
     StoreConstFloat(Reg<Float>, Float),
 
     // Conversions
     IntToStr(Reg<Str<'a>>, Reg<Int>),
     FloatToStr(Reg<Str<'a>>, Reg<Float>),
+    // Like FloatToStr, but with OFMT rather than CONVFMT: used for the arguments of print.
+    FloatToOutStr(Reg<Str<'a>>, Reg<Float>),
     StrToInt(Reg<Int>, Reg<Str<'a>>),
     HexStrToInt(Reg<Int>, Reg<Str<'a>>),
     FloatToInt(Reg<Int>, Reg<Float>),
@@ -104,20 +120,39 @@ pub(crate) enum Instr<'a> {
     Float2(FloatFunc, Reg<Float>, Reg<Float>, Reg<Float>),
     Int1(Bitwise, Reg<Int>, Reg<Int>),
     Int2(Bitwise, Reg<Int>, Reg<Int>, Reg<Int>),
+    // Arithmetic under --safe-math, which sets ERRNO on errors.
+    SafeInt(safe_math::Op, Reg<Int>, Reg<Int>, Reg<Int>),
+    SafeFloat(safe_math::Op, Reg<Float>, Reg<Float>, Reg<Float>),
     Rand(Reg<Float>),
     Srand(
         /* previous seed */ Reg<Int>,
         /* new seed */ Reg<Int>,
     ),
     ReseedRng(/* previous seed */ Reg<Int>),
+    RandInt(Reg<Int>, Reg<Int>, Reg<Int>),
 
     // String processing
     Concat(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     StartsWithConst(Reg<Int>, Reg<Str<'a>>, Arc<[u8]>),
     IsMatch(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
-    IsMatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    IsMatchConst(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        #[serde(with = "crate::precompile::regex")] Arc<Regex>,
+    ),
     Match(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
-    MatchConst(Reg<Int>, Reg<Str<'a>>, Arc<Regex>),
+    // match(s, pat, arr): stores the matched text and capture groups in arr.
+    MatchArr(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
+    MatchConst(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        #[serde(with = "crate::precompile::regex")] Arc<Regex>,
+    ),
     // index(s, t) returns index of substring t in s, 0 if it does not appear.
     SubstrIndex(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     SubstrLastIndex(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
@@ -134,6 +169,18 @@ pub(crate) enum Instr<'a> {
         /*for*/ Reg<Str<'a>>,
         /*in*/ Reg<Str<'a>>,
     ),
+    SubConst(
+        Reg<Int>,
+        #[serde(with = "crate::precompile::regex")] Arc<Regex>,
+        /*for*/ Reg<Str<'a>>,
+        /*in*/ Reg<Str<'a>>,
+    ),
+    GSubConst(
+        Reg<Int>,
+        #[serde(with = "crate::precompile::regex")] Arc<Regex>,
+        /*for*/ Reg<Str<'a>>,
+        /*in*/ Reg<Str<'a>>,
+    ),
     GenSubDynamic(
         Reg<Str<'a>>,
         /*pat*/ Reg<Str<'a>>,
@@ -183,6 +230,12 @@ pub(crate) enum Instr<'a> {
         Reg<Int>,     /* end col */
         Reg<Str<'a>>, /* sep */
     ),
+    SelectColumns(
+        Reg<Str<'a>>, /* dst */
+        Reg<Str<'a>>, /* spec */
+        Reg<Str<'a>>, /* sep */
+        Reg<Int>,     /* escaper: 0 none, 1 csv, 2 tsv */
+    ),
     ToUpperAscii(Reg<Str<'a>>, Reg<Str<'a>>),
     ToLowerAscii(Reg<Str<'a>>, Reg<Str<'a>>),
 
@@ -206,24 +259,60 @@ pub(crate) enum Instr<'a> {
     Arch(Reg<Str<'a>>),
     Pwd(Reg<Str<'a>>),
     UserHome(Reg<Str<'a>>),
-    Strftime(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>),
+    Strftime(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Str<'a>>),
     Encode(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Decode(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Iconv(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Compress(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Decompress(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Utf8Valid(Reg<Int>, Reg<Str<'a>>),
+    Utf8Fix(Reg<Str<'a>>, Reg<Str<'a>>),
+    Normalize(Reg<Str<'a>>, Reg<Str<'a>>, NormalForm),
     Digest(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    FileDigest(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DigestInit(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DigestUpdate(Reg<Int>, Reg<Int>, Reg<Str<'a>>),
+    DigestFinal(Reg<Str<'a>>, Reg<Int>),
     Hmac(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Jwt(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>),
-    Dejwt( Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Dejwt(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Cert(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     Encrypt(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Decrypt(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
-    Mktime(Reg<Int>, Reg<Str<'a>>, Reg<Int>),
+    Mktime(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     Duration(Reg<Int>, Reg<Str<'a>>),
     MkBool(Reg<Int>, Reg<Str<'a>>),
     Systime(Reg<Int>),
+    SystimeMs(Reg<Int>),
+    SystimeUs(Reg<Int>),
+    Clock(Reg<Float>),
+    Sleep(Reg<Int>, Reg<Float>),
     Fend(Reg<Str<'a>>, Reg<Str<'a>>),
     Min(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Max(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecAdd(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecSub(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecMul(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecDiv(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecCmp(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DecFmt(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>),
+    BigAdd(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigSub(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigMul(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigDiv(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigMod(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigPow(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    BigCmp(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    U64Add(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    U64Mul(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    U64Str(Reg<Str<'a>>, Reg<Int>),
     Seq(Reg<runtime::IntMap<Float>>, Reg<Float>, Reg<Float>, Reg<Float>),
     Url(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    UaParse(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    Logfmt(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    MapStrIntToLogfmt(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Int>>, Reg<Str<'a>>),
+    MapStrFloatToLogfmt(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Float>>, Reg<Str<'a>>),
+    MapStrStrToLogfmt(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     Pairs(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Record(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     Message(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
@@ -231,6 +320,9 @@ pub(crate) enum Instr<'a> {
     Path(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     DataUrl(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     DateTime(Reg<runtime::StrMap<'a, Int>>, Reg<Str<'a>>),
+    DateParse(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    DateAdd(Reg<Int>, Reg<Int>, Reg<Int>, Reg<Str<'a>>),
+    DateDiff(Reg<Int>, Reg<Int>, Reg<Int>, Reg<Str<'a>>),
     Shlex(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
     Tuple(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
     Flags(Reg<runtime::StrMap<'a, Int>>, Reg<Str<'a>>),
@@ -252,7 +344,15 @@ pub(crate) enum Instr<'a> {
     IsNumTrue(Reg<Int>),
     IsNumFalse(Reg<Int>),
     IsStrNum(Reg<Int>, Reg<Str<'a>>),
+    IsNan(Reg<Int>, Reg<Float>),
+    IsInf(Reg<Int>, Reg<Float>),
     IsFormat(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    CidrContains(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Ip2Int(Reg<Int>, Reg<Str<'a>>),
+    Int2Ip(Reg<Str<'a>>, Reg<Int>),
+    IpVersion(Reg<Int>, Reg<Str<'a>>),
+    Geoip(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    GeoipDb(Reg<Int>, Reg<Str<'a>>),
     HttpGet(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>),
     HttpPost(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
     S3Get(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
@@ -262,7 +362,14 @@ pub(crate) enum Instr<'a> {
     KvDelete(Reg<Str<'a>>, Reg<Str<'a>>),
     KvClear(Reg<Str<'a>>),
     ReadAll(Reg<Str<'a>>, Reg<Str<'a>>),
+    ReadLines(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
     WriteAll(Reg<Str<'a>>, Reg<Str<'a>>),
+    AppendAll(Reg<Str<'a>>, Reg<Str<'a>>),
+    Glob(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
+    Stat(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    Exists(Reg<Int>, Reg<Str<'a>>),
+    Mkdirp(Reg<Int>, Reg<Str<'a>>),
+    RmFile(Reg<Int>, Reg<Str<'a>>),
     LogDebug(Reg<Str<'a>>),
     LogInfo(Reg<Str<'a>>),
     LogWarn(Reg<Str<'a>>),
@@ -277,6 +384,24 @@ pub(crate) enum Instr<'a> {
     BloomFilterContainsWithInsert(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     Fake(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     FromJson(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    FromToml(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    MapStrIntToToml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Int>>, Reg<Str<'a>>),
+    MapStrFloatToToml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Float>>, Reg<Str<'a>>),
+    MapStrStrToToml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    TomlValue(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    FromIni(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    MapStrIntToIni(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Int>>),
+    MapStrFloatToIni(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Float>>),
+    MapStrStrToIni(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>),
+    PropGet(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    MapStrIntToXml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Int>>, Reg<Str<'a>>),
+    MapStrFloatToXml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Float>>, Reg<Str<'a>>),
+    MapStrStrToXml(Reg<Str<'a>>, Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    XmlUpdate(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    HtmlTable(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>, Reg<Int>),
+    FromMsgpack(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    FromCbor(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>),
+    FromProtobuf(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
     MapIntIntToJson(Reg<Str<'a>>, Reg<runtime::IntMap<Int>>),
     MapIntFloatToJson(Reg<Str<'a>>, Reg<runtime::IntMap<Float>>),
     MapIntStrToJson(Reg<Str<'a>>, Reg<runtime::IntMap<Str<'a>>>),
@@ -297,9 +422,9 @@ pub(crate) enum Instr<'a> {
     DumpInt(Reg<Int>),
     DumpFloat(Reg<Float>),
     DumpNull(),
-    MapIntIntAsort(Reg<Int>, Reg<runtime::IntMap<Int>>, Reg<runtime::IntMap<Int>>),
-    MapIntFloatAsort(Reg<Int>, Reg<runtime::IntMap<Float>>, Reg<runtime::IntMap<Float>>),
-    MapIntStrAsort(Reg<Int>, Reg<runtime::IntMap<Str<'a>>>, Reg<runtime::IntMap<Str<'a>>>),
+    MapIntIntAsort(Reg<Int>, Reg<runtime::IntMap<Int>>, Reg<runtime::IntMap<Int>>, Reg<Str<'a>>),
+    MapIntFloatAsort(Reg<Int>, Reg<runtime::IntMap<Float>>, Reg<runtime::IntMap<Float>>, Reg<Str<'a>>),
+    MapIntStrAsort(Reg<Int>, Reg<runtime::IntMap<Str<'a>>>, Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
     MapIntIntJoin(Reg<Str<'a>>, Reg<runtime::IntMap<Int>>, Reg<Str<'a>>),
     MapIntFloatJoin(Reg<Str<'a>>, Reg<runtime::IntMap<Float>>, Reg<Str<'a>>),
     MapIntStrJoin(Reg<Str<'a>>, Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>),
@@ -321,7 +446,9 @@ pub(crate) enum Instr<'a> {
     Parse(Reg<runtime::StrMap<'a, Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>),
     RegexParse(Reg<runtime::IntMap<Str<'a>>>, Reg<Str<'a>>, Reg<Str<'a>>),
     Strtonum(Reg<Float>, Reg<Str<'a>>),
+    ParseNumber(Reg<Float>, Reg<Str<'a>>, Reg<Str<'a>>),
     FormatBytes(Reg<Str<'a>>, Reg<Int>),
+    FormatNum(Reg<Str<'a>>, Reg<Float>, Reg<Str<'a>>, Reg<Str<'a>>),
     ToBytes(Reg<Int>, Reg<Str<'a>>),
     StartsWith(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
     EndsWith(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
@@ -336,6 +463,13 @@ pub(crate) enum Instr<'a> {
     PadRight(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Str<'a>>),
     PadBoth(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>, Reg<Str<'a>>),
     StrCmp(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    NatCmp(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Levenshtein(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Similarity(Reg<Float>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Diff(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
+    Soundex(Reg<Str<'a>>, Reg<Str<'a>>),
+    Metaphone(Reg<Str<'a>>, Reg<Str<'a>>),
+    FuzzyMatch(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>, Reg<Float>),
     Mask(Reg<Str<'a>>, Reg<Str<'a>>),
     Repeat(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Int>),
     DefaultIfEmpty(Reg<Str<'a>>, Reg<Str<'a>>, Reg<Str<'a>>),
@@ -364,6 +498,35 @@ pub(crate) enum Instr<'a> {
         Reg<runtime::StrMap<'a, Str<'a>>>,
         Reg<Str<'a>>,
     ),
+    // split(s, arr, pat, seps) and patsplit(s, arr, pat, seps)
+    SplitSepsInt(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+    ),
+    SplitSepsStr(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
+    PatSplitInt(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+        Reg<Str<'a>>,
+        Reg<runtime::IntMap<Str<'a>>>,
+    ),
+    PatSplitStr(
+        Reg<Int>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+        Reg<Str<'a>>,
+        Reg<runtime::StrMap<'a, Str<'a>>>,
+    ),
     Sprintf {
         dst: Reg<Str<'a>>,
         fmt: Reg<Str<'a>>,
@@ -379,8 +542,17 @@ pub(crate) enum Instr<'a> {
         args: Vec<Reg<Str<'a>>>,
     },
     Close(Reg<Str<'a>>),
+    FFlush(Reg<Int>, Reg<Str<'a>>),
     RunCmd(Reg<Int>, Reg<Str<'a>>),
+    ExecOutput(Reg<Str<'a>>, Reg<Str<'a>>),
+    ExecOutputArgv(Reg<Str<'a>>, Reg<runtime::IntMap<Str<'a>>>),
+    Spawn(Reg<Str<'a>>, Reg<Str<'a>>),
+    SpawnArgv(Reg<Str<'a>>, Reg<runtime::IntMap<Str<'a>>>),
+    SetEnv(Reg<Int>, Reg<Str<'a>>, Reg<Str<'a>>),
+    UnsetEnv(Reg<Int>, Reg<Str<'a>>),
     Exit(Reg<Int>),
+    // Call the function loaded with --load at the given index in runtime::ext.
+    CallExt(Reg<Str<'a>>, u32, Vec<Reg<Str<'a>>>),
 
     // Map operations
     Lookup {
@@ -434,6 +606,74 @@ pub(crate) enum Instr<'a> {
         dst: NumTy,
         map: NumTy,
     },
+    // _keys, _values, _invert, _merge and _slice, which store a new array in `dst`. Its type
+    // follows from `map_ty`; see `Instr::accum`.
+    Keys {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+    },
+    Values {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+    },
+    Invert {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+    },
+    Merge {
+        map_ty: Ty,
+        dst: NumTy,
+        left: NumTy,
+        right: NumTy,
+    },
+    Slice {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+        from: Reg<Int>,
+        to: Reg<Int>,
+    },
+    // choice, sample and shuffle, which draw from the same generator as rand().
+    Choice {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+    },
+    Sample {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+        k: Reg<Int>,
+    },
+    Shuffle {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+    },
+    // _sorted_keys, or _sort_by_value if `by_value` is set.
+    SortKeys {
+        map_ty: Ty,
+        dst: NumTy,
+        map: NumTy,
+        order: Reg<Str<'a>>,
+        by_value: bool,
+    },
+    // _copy(to, from) and _equals(left, right), with their Int results in `dst`.
+    CopyMap {
+        map_ty: Ty,
+        dst: NumTy,
+        to: NumTy,
+        from: NumTy,
+    },
+    EqualMaps {
+        map_ty: Ty,
+        dst: NumTy,
+        left: NumTy,
+        right: NumTy,
+    },
     IterHasNext {
         iter_ty: Ty,
         dst: NumTy,
@@ -579,7 +819,7 @@ impl<'a> Instr<'a> {
                 sr.accum(&mut f);
                 ir.accum(&mut f)
             }
-            FloatToStr(sr, fr) => {
+            FloatToStr(sr, fr) | FloatToOutStr(sr, fr) => {
                 sr.accum(&mut f);
                 fr.accum(&mut f);
             }
@@ -601,9 +841,14 @@ impl<'a> Instr<'a> {
             LocalIp(sr) => {
                 sr.accum(&mut f);
             }
-            Systime(sr) => {
+            Systime(sr) | SystimeMs(sr) | SystimeUs(sr) => {
                 sr.accum(&mut f);
             }
+            Clock(dst) => dst.accum(&mut f),
+            Sleep(dst, secs) => {
+                dst.accum(&mut f);
+                secs.accum(&mut f);
+            }
             Encode(res, format, text) => {
                 res.accum(&mut f);
                 format.accum(&mut f);
@@ -614,6 +859,25 @@ impl<'a> Instr<'a> {
                 format.accum(&mut f);
                 text.accum(&mut f);
             }
+            Iconv(res, text, from, to) => {
+                res.accum(&mut f);
+                text.accum(&mut f);
+                from.accum(&mut f);
+                to.accum(&mut f);
+            }
+            Compress(res, format, text) | Decompress(res, format, text) => {
+                res.accum(&mut f);
+                format.accum(&mut f);
+                text.accum(&mut f);
+            }
+            Utf8Valid(res, text) => {
+                res.accum(&mut f);
+                text.accum(&mut f);
+            }
+            Utf8Fix(res, text) | Normalize(res, text, _) => {
+                res.accum(&mut f);
+                text.accum(&mut f);
+            }
             Escape(res, format, text) => {
                 res.accum(&mut f);
                 format.accum(&mut f);
@@ -624,6 +888,25 @@ impl<'a> Instr<'a> {
                 algorithm.accum(&mut f);
                 text.accum(&mut f);
             }
+            FileDigest(res, algorithm, path) => {
+                res.accum(&mut f);
+                algorithm.accum(&mut f);
+                path.accum(&mut f);
+            }
+            DigestInit(res, algorithm, key) => {
+                res.accum(&mut f);
+                algorithm.accum(&mut f);
+                key.accum(&mut f);
+            }
+            DigestUpdate(res, handle, text) => {
+                res.accum(&mut f);
+                handle.accum(&mut f);
+                text.accum(&mut f);
+            }
+            DigestFinal(res, handle) => {
+                res.accum(&mut f);
+                handle.accum(&mut f);
+            }
             Hmac(res, algorithm, key, text) => {
                 res.accum(&mut f);
                 algorithm.accum(&mut f);
@@ -636,10 +919,15 @@ impl<'a> Instr<'a> {
                 key.accum(&mut f);
                 payload.accum(&mut f);
             }
-            Dejwt(res, key, token) => {
+            Dejwt(res, key, token, options) => {
                 res.accum(&mut f);
                 key.accum(&mut f);
                 token.accum(&mut f);
+                options.accum(&mut f);
+            }
+            Cert(res, source) => {
+                res.accum(&mut f);
+                source.accum(&mut f);
             }
             Encrypt(res, mode, plain_text, key) => {
                 res.accum(&mut f);
@@ -653,10 +941,11 @@ impl<'a> Instr<'a> {
                 encrypted_text.accum(&mut f);
                 key.accum(&mut f);
             }
-            Strftime(res, format, timestamp) => {
+            Strftime(res, format, timestamp, timezone) => {
                 res.accum(&mut f);
                 format.accum(&mut f);
                 timestamp.accum(&mut f);
+                timezone.accum(&mut f);
             }
             Mktime(res, date_time_text,timezone) => {
                 res.accum(&mut f);
@@ -675,10 +964,25 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
-            Url(dst, src) => {
+            Url(dst, src) | UaParse(dst, src) | Logfmt(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
+            MapStrIntToLogfmt(dst, arr, order) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                order.accum(&mut f);
+            }
+            MapStrFloatToLogfmt(dst, arr, order) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                order.accum(&mut f);
+            }
+            MapStrStrToLogfmt(dst, arr, order) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                order.accum(&mut f);
+            }
             Pairs(dst, src, pair_sep, kv_sep) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
@@ -709,6 +1013,17 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 timestamp.accum(&mut f);
             }
+            DateParse(dst, text, format) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+                format.accum(&mut f);
+            }
+            DateAdd(dst, a, b, unit) | DateDiff(dst, a, b, unit) => {
+                dst.accum(&mut f);
+                a.accum(&mut f);
+                b.accum(&mut f);
+                unit.accum(&mut f);
+            }
             Shlex(dst, text) => {
                 dst.accum(&mut f);
                 text.accum(&mut f);
@@ -786,10 +1101,26 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 path.accum(&mut f);
             }
-            WriteAll( path, content) => {
+            ReadLines(dst, path) => {
+                dst.accum(&mut f);
+                path.accum(&mut f);
+            }
+            WriteAll(path, content) | AppendAll(path, content) => {
                 path.accum(&mut f);
                 content.accum(&mut f);
             }
+            Glob(dst, pattern) => {
+                dst.accum(&mut f);
+                pattern.accum(&mut f);
+            }
+            Stat(dst, path) => {
+                dst.accum(&mut f);
+                path.accum(&mut f);
+            }
+            Exists(dst, path) | Mkdirp(dst, path) | RmFile(dst, path) => {
+                dst.accum(&mut f);
+                path.accum(&mut f);
+            }
             LogDebug( message) => {
                 message.accum(&mut f);
             }
@@ -845,10 +1176,87 @@ impl<'a> Instr<'a> {
                 data.accum(&mut f);
                 locale.accum(&mut f);
             }
-            FromJson(dst, src) => {
+            FromJson(dst, src) | FromToml(dst, src) => {
+                dst.accum(&mut f);
+                src.accum(&mut f);
+            }
+            MapStrIntToToml(dst, arr, like) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                like.accum(&mut f);
+            }
+            MapStrFloatToToml(dst, arr, like) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                like.accum(&mut f);
+            }
+            MapStrStrToToml(dst, arr, like) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                like.accum(&mut f);
+            }
+            TomlValue(dst, text, path) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+                path.accum(&mut f);
+            }
+            FromIni(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
+            MapStrIntToIni(dst, arr) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+            }
+            MapStrFloatToIni(dst, arr) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+            }
+            MapStrStrToIni(dst, arr) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+            }
+            PropGet(dst, text, key) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+                key.accum(&mut f);
+            }
+            MapStrIntToXml(dst, arr, root) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                root.accum(&mut f);
+            }
+            MapStrFloatToXml(dst, arr, root) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                root.accum(&mut f);
+            }
+            MapStrStrToXml(dst, arr, root) => {
+                dst.accum(&mut f);
+                arr.accum(&mut f);
+                root.accum(&mut f);
+            }
+            XmlUpdate(dst, text, xpath, value) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+                xpath.accum(&mut f);
+                value.accum(&mut f);
+            }
+            HtmlTable(dst, html, index) => {
+                dst.accum(&mut f);
+                html.accum(&mut f);
+                index.accum(&mut f);
+            }
+            FromMsgpack(dst, src) | FromCbor(dst, src) => {
+                dst.accum(&mut f);
+                src.accum(&mut f);
+            }
+            FromProtobuf(dst, src, descriptors, message) => {
+                dst.accum(&mut f);
+                src.accum(&mut f);
+                descriptors.accum(&mut f);
+                message.accum(&mut f);
+            }
             MapIntIntToJson(dst, arr) => {
                 dst.accum(&mut f);
                 arr.accum(&mut f);
@@ -917,20 +1325,23 @@ impl<'a> Instr<'a> {
             }
             DumpNull() => {
             }
-            MapIntIntAsort( dst, arr, target) => {
+            MapIntIntAsort( dst, arr, target, order) => {
                 dst.accum(&mut f);
                 arr.accum(&mut f);
                 target.accum(&mut f);
+                order.accum(&mut f);
             }
-            MapIntFloatAsort(dst, arr,target) => {
+            MapIntFloatAsort(dst, arr,target, order) => {
                 dst.accum(&mut f);
                 arr.accum(&mut f);
                 target.accum(&mut f);
+                order.accum(&mut f);
             }
-            MapIntStrAsort(dst, arr,target) => {
+            MapIntStrAsort(dst, arr,target, order) => {
                 dst.accum(&mut f);
                 arr.accum(&mut f);
                 target.accum(&mut f);
+                order.accum(&mut f);
             }
             MapIntIntJoin( dst, arr, target) => {
                 dst.accum(&mut f);
@@ -1007,6 +1418,45 @@ impl<'a> Instr<'a> {
                 second.accum(&mut f);
                 third.accum(&mut f);
             }
+            DecAdd(dst, x, y) | DecSub(dst, x, y) | DecMul(dst, x, y) | DecDiv(dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            DecCmp(dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            DecFmt(dst, x, scale) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                scale.accum(&mut f);
+            }
+            BigAdd(dst, x, y)
+            | BigSub(dst, x, y)
+            | BigMul(dst, x, y)
+            | BigDiv(dst, x, y)
+            | BigMod(dst, x, y)
+            | BigPow(dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            BigCmp(dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            U64Add(dst, x, y) | U64Mul(dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            U64Str(dst, x) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+            }
             Seq(dst, start, step,end) => {
                 dst.accum(&mut f);
                 start.accum(&mut f);
@@ -1043,10 +1493,21 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 text.accum(&mut f);
             }
+            ParseNumber(dst, text, locale) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+                locale.accum(&mut f);
+            }
             FormatBytes(dst, size ) => {
                 dst.accum(&mut f);
                 size.accum(&mut f);
             }
+            FormatNum(dst, x, pattern, locale) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                pattern.accum(&mut f);
+                locale.accum(&mut f);
+            }
             ToBytes(dst, text ) => {
                 dst.accum(&mut f);
                 text.accum(&mut f);
@@ -1108,11 +1569,31 @@ impl<'a> Instr<'a> {
                 len.accum(&mut f);
                 pad.accum(&mut f);
             }
-            StrCmp(dst, text1, text2 ) => {
+            StrCmp(dst, text1, text2 ) | NatCmp(dst, text1, text2) | Levenshtein(dst, text1, text2) => {
+                dst.accum(&mut f);
+                text1.accum(&mut f);
+                text2.accum(&mut f);
+            }
+            Similarity(dst, text1, text2) => {
+                dst.accum(&mut f);
+                text1.accum(&mut f);
+                text2.accum(&mut f);
+            }
+            Diff(dst, text1, text2) => {
                 dst.accum(&mut f);
                 text1.accum(&mut f);
                 text2.accum(&mut f);
             }
+            Soundex(dst, text) | Metaphone(dst, text) => {
+                dst.accum(&mut f);
+                text.accum(&mut f);
+            }
+            FuzzyMatch(dst, text1, text2, threshold) => {
+                dst.accum(&mut f);
+                text1.accum(&mut f);
+                text2.accum(&mut f);
+                threshold.accum(&mut f);
+            }
             Mask(dst, text ) => {
                 dst.accum(&mut f);
                 text.accum(&mut f);
@@ -1198,6 +1679,27 @@ impl<'a> Instr<'a> {
                 format.accum(&mut f);
                 text.accum(&mut f);
             }
+            CidrContains(dst, net, addr) => {
+                dst.accum(&mut f);
+                net.accum(&mut f);
+                addr.accum(&mut f);
+            }
+            Ip2Int(dst, ip) | IpVersion(dst, ip) => {
+                dst.accum(&mut f);
+                ip.accum(&mut f);
+            }
+            Int2Ip(dst, n) => {
+                dst.accum(&mut f);
+                n.accum(&mut f);
+            }
+            Geoip(dst, ip) => {
+                dst.accum(&mut f);
+                ip.accum(&mut f);
+            }
+            GeoipDb(dst, path) => {
+                dst.accum(&mut f);
+                path.accum(&mut f);
+            }
             StrToInt(ir, sr) | HexStrToInt(ir, sr) => {
                 ir.accum(&mut f);
                 sr.accum(&mut f);
@@ -1288,6 +1790,20 @@ impl<'a> Instr<'a> {
                 dst.accum(&mut f);
                 src.accum(&mut f);
             }
+            SafeInt(_, dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            SafeFloat(_, dst, x, y) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+                y.accum(&mut f);
+            }
+            IsNan(dst, x) | IsInf(dst, x) => {
+                dst.accum(&mut f);
+                x.accum(&mut f);
+            }
             Int2(_, dst, x, y) => {
                 dst.accum(&mut f);
                 x.accum(&mut f);
@@ -1299,6 +1815,11 @@ impl<'a> Instr<'a> {
                 seed.accum(&mut f)
             }
             ReseedRng(res) => res.accum(&mut f),
+            RandInt(res, lo, hi) => {
+                res.accum(&mut f);
+                lo.accum(&mut f);
+                hi.accum(&mut f)
+            }
             StartsWithConst(res, s, _) => {
                 res.accum(&mut f);
                 s.accum(&mut f);
@@ -1318,6 +1839,12 @@ impl<'a> Instr<'a> {
                 l.accum(&mut f);
                 r.accum(&mut f);
             }
+            MatchArr(res, l, r, arr) => {
+                res.accum(&mut f);
+                l.accum(&mut f);
+                r.accum(&mut f);
+                arr.accum(&mut f);
+            }
             MatchConst(res, src, _) | IsMatchConst(res, src, _) => {
                 res.accum(&mut f);
                 src.accum(&mut f);
@@ -1347,6 +1874,11 @@ impl<'a> Instr<'a> {
                 s.accum(&mut f);
                 in_s.accum(&mut f);
             }
+            GSubConst(res, _, s, in_s) | SubConst(res, _, s, in_s) => {
+                res.accum(&mut f);
+                s.accum(&mut f);
+                in_s.accum(&mut f);
+            }
             GenSubDynamic(res, pat, s, how, in_s) => {
                 res.accum(&mut f);
                 pat.accum(&mut f);
@@ -1463,6 +1995,12 @@ impl<'a> Instr<'a> {
                 end.accum(&mut f);
                 sep.accum(&mut f);
             }
+            SelectColumns(dst, spec, sep, esc) => {
+                dst.accum(&mut f);
+                spec.accum(&mut f);
+                sep.accum(&mut f);
+                esc.accum(&mut f);
+            }
             ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
                 dst.accum(&mut f);
                 src.accum(&mut f);
@@ -1479,6 +2017,22 @@ impl<'a> Instr<'a> {
                 arr.accum(&mut f);
                 pat.accum(&mut f);
             }
+            SplitSepsInt(flds, to_split, arr, pat, seps)
+            | PatSplitInt(flds, to_split, arr, pat, seps) => {
+                flds.accum(&mut f);
+                to_split.accum(&mut f);
+                arr.accum(&mut f);
+                pat.accum(&mut f);
+                seps.accum(&mut f);
+            }
+            SplitSepsStr(flds, to_split, arr, pat, seps)
+            | PatSplitStr(flds, to_split, arr, pat, seps) => {
+                flds.accum(&mut f);
+                to_split.accum(&mut f);
+                arr.accum(&mut f);
+                pat.accum(&mut f);
+                seps.accum(&mut f);
+            }
             Sprintf { dst, fmt, args } => {
                 dst.accum(&mut f);
                 fmt.accum(&mut f);
@@ -1504,11 +2058,38 @@ impl<'a> Instr<'a> {
                 }
             }
             Close(file) => file.accum(&mut f),
+            FFlush(dst, file) => {
+                dst.accum(&mut f);
+                file.accum(&mut f);
+            }
             RunCmd(dst, cmd) => {
                 dst.accum(&mut f);
                 cmd.accum(&mut f);
             }
+            ExecOutput(dst, cmd) | Spawn(dst, cmd) => {
+                dst.accum(&mut f);
+                cmd.accum(&mut f);
+            }
+            ExecOutputArgv(dst, argv) | SpawnArgv(dst, argv) => {
+                dst.accum(&mut f);
+                argv.accum(&mut f);
+            }
+            SetEnv(dst, name, value) => {
+                dst.accum(&mut f);
+                name.accum(&mut f);
+                value.accum(&mut f);
+            }
+            UnsetEnv(dst, name) => {
+                dst.accum(&mut f);
+                name.accum(&mut f);
+            }
             Exit(code) => code.accum(&mut f),
+            CallExt(dst, _, args) => {
+                dst.accum(&mut f);
+                for reg in args {
+                    reg.accum(&mut f)
+                }
+            }
             Lookup {
                 map_ty,
                 dst,
@@ -1545,6 +2126,80 @@ impl<'a> Instr<'a> {
                 f(*dst, map_ty.key_iter().unwrap());
                 f(*map, *map_ty);
             }
+            Keys { map_ty, dst, map } => {
+                f(*dst, Ty::map(Ty::Int, map_ty.key().unwrap()).unwrap());
+                f(*map, *map_ty);
+            }
+            Values { map_ty, dst, map } => {
+                f(*dst, Ty::map(Ty::Int, map_ty.val().unwrap()).unwrap());
+                f(*map, *map_ty);
+            }
+            SortKeys {
+                map_ty,
+                dst,
+                map,
+                order,
+                ..
+            } => {
+                f(*dst, Ty::map(Ty::Int, map_ty.key().unwrap()).unwrap());
+                f(*map, *map_ty);
+                order.accum(&mut f);
+            }
+            Invert { map_ty, dst, map } => {
+                f(*dst, map_ty.inverted().unwrap());
+                f(*map, *map_ty);
+            }
+            Merge {
+                map_ty,
+                dst,
+                left,
+                right,
+            } => {
+                f(*dst, *map_ty);
+                f(*left, *map_ty);
+                f(*right, *map_ty);
+            }
+            Slice {
+                map_ty,
+                dst,
+                map,
+                from,
+                to,
+            } => {
+                f(*dst, *map_ty);
+                f(*map, *map_ty);
+                from.accum(&mut f);
+                to.accum(&mut f);
+            }
+            Choice { map_ty, dst, map } => {
+                f(*dst, map_ty.val().unwrap());
+                f(*map, *map_ty);
+            }
+            Sample { map_ty, dst, map, k } => {
+                f(*dst, Ty::map(Ty::Int, map_ty.val().unwrap()).unwrap());
+                f(*map, *map_ty);
+                k.accum(&mut f);
+            }
+            Shuffle { map_ty, dst, map } => {
+                f(*dst, Ty::Int);
+                f(*map, *map_ty);
+            }
+            CopyMap {
+                map_ty,
+                dst,
+                to: left,
+                from: right,
+            }
+            | EqualMaps {
+                map_ty,
+                dst,
+                left,
+                right,
+            } => {
+                f(*dst, Ty::Int);
+                f(*left, *map_ty);
+                f(*right, *map_ty);
+            }
             Store {
                 map_ty,
                 map,
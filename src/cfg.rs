@@ -1,8 +1,10 @@
 use crate::arena;
 use crate::ast::{self, Expr, Stmt, Unop};
 use crate::builtins::{self, IsSprintf};
-use crate::common::{Either, FileSpec, Graph, NodeIx, NumTy, Result, Stage};
+use crate::column_stats;
+use crate::common::{Either, FileSpec, Graph, MergeStrategy, NodeIx, NumTy, Result, Stage};
 use crate::dom;
+use crate::trace;
 
 use hashbrown::{HashMap, HashSet};
 use petgraph::Direction;
@@ -14,7 +16,7 @@ use std::fmt;
 use std::hash::Hash;
 use std::io;
 use std::mem;
-use crate::runtime::{Int};
+use crate::runtime::{bignum, Int};
 
 pub(crate) type SmallVec<T> = smallvec::SmallVec<[T; 4]>;
 
@@ -142,6 +144,61 @@ pub enum Escaper {
     Identity,
 }
 
+// The template of `-o template=...`. Print statements render it with printf.
+struct Template<'b> {
+    // The template as a printf format: each placeholder becomes a %s, and a final %s takes ORS.
+    fmt: &'b [u8],
+    fields: Vec<TemplateField<'b>>,
+}
+
+enum TemplateField<'b> {
+    // {N}: the Nth value printed or, for a bare print, the Nth column. {0} is always $0.
+    Index(usize),
+    // {name}: the column with this name in the header read by -H.
+    Name(&'b [u8]),
+}
+
+impl<'b> Template<'b> {
+    // Placeholders are runs of letters, digits, '_', '.' and '-' in braces; other braces, like the
+    // ones in a JSON template, are kept as they are.
+    fn parse(arena: &'b arena::Arena, tmpl: &[u8], parse_header: bool) -> Result<Template<'b>> {
+        let is_name = |b: &u8| b.is_ascii_alphanumeric() || matches!(*b, b'_' | b'.' | b'-');
+        let mut fmt = Vec::with_capacity(tmpl.len() + 2);
+        let mut fields = Vec::new();
+        let mut i = 0;
+        while i < tmpl.len() {
+            let name_len = tmpl[i + 1..].iter().take_while(|b| is_name(b)).count();
+            let end = i + 1 + name_len;
+            if tmpl[i] == b'{' && name_len > 0 && tmpl.get(end) == Some(&b'}') {
+                let name = &tmpl[i + 1..end];
+                fields.push(match std::str::from_utf8(name).ok().and_then(|n| n.parse().ok()) {
+                    Some(n) => TemplateField::Index(n),
+                    None if parse_header => TemplateField::Name(arena.alloc_bytes(name)),
+                    None => {
+                        return err!(
+                            "template placeholder {{{}}} names a column, which needs -H",
+                            String::from_utf8_lossy(name)
+                        )
+                    }
+                });
+                fmt.extend_from_slice(b"%s");
+                i = end + 1;
+                continue;
+            }
+            if tmpl[i] == b'%' {
+                fmt.push(b'%');
+            }
+            fmt.push(tmpl[i]);
+            i += 1;
+        }
+        fmt.extend_from_slice(b"%s");
+        Ok(Template {
+            fmt: arena.alloc_bytes(&fmt),
+            fields,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) enum PrimVal<'a> {
     Var(Ident),
@@ -189,6 +246,8 @@ pub(crate) enum PrimStmt<'a> {
         /* args */ SmallVec<PrimVal<'a>>,
         /* output */ Option<(PrimVal<'a>, FileSpec)>,
     ),
+    // The following statements come from this line of the program (counting from 0).
+    Line(usize),
 }
 
 // only add constraints when doing an AsgnVar. Because these things are "shallow" it works.
@@ -266,6 +325,7 @@ impl<'a> PrimStmt<'a> {
                 }
             }
             IterDrop(v) | Return(v) => v.replace(update),
+            Line(_) => {}
         }
     }
 }
@@ -289,6 +349,19 @@ pub(crate) struct ProgramContext<'a, I> {
     pub fold_regex_constants: bool,
     // Thread through information regarding header columns used.
     pub parse_header: bool,
+    // The number of records after which the program is known to exit; see Prog::record_limit.
+    pub record_limit: Option<u64>,
+    // Whether the program calls fflush. Such programs usually talk to another process record by
+    // record, so input is read without buffering as well.
+    pub flushes: bool,
+    // Set for programs that only aggregate a single column; see the column_stats module.
+    pub column_stats: Option<column_stats::Plan>,
+    // Report division by zero and integer overflow in ERRNO; see runtime::safe_math.
+    pub safe_math: bool,
+    // The files the program was read from, for locating runtime errors.
+    pub(crate) sources: trace::SourceMap,
+    // Holds strings created while compiling the program, such as folded constants.
+    pub(crate) arena: &'a arena::Arena,
 }
 
 impl<'a, I> ProgramContext<'a, I> {
@@ -301,6 +374,20 @@ impl<'a, I> ProgramContext<'a, I> {
 }
 
 impl<'a> ProgramContext<'a, &'a str> {
+    /// The merge strategies requested via PARALLEL_MERGE, for variables that appear in the
+    /// program.
+    pub(crate) fn merge_strategies(&self) -> HashMap<Ident, MergeStrategy> {
+        let mut res = HashMap::new();
+        for (name, m) in self.shared.merges.iter() {
+            if let Some(id) = std::str::from_utf8(name)
+                .ok()
+                .and_then(|name| self.shared.hm.get(name))
+            {
+                res.insert(*id, *m);
+            }
+        }
+        res
+    }
     pub(crate) fn dbg_print(&self, w: &mut impl io::Write) -> io::Result<()> {
         for f in self.funcs.iter() {
             write!(w, "function {}={}(", f.name, f.ident)?;
@@ -347,6 +434,14 @@ impl<'a, I> ProgramContext<'a, I>
     pub(crate) fn local_globals_ref(&self) -> &HashSet<NumTy> {
         &self.shared.local_globals
     }
+    /// Whether the program was compiled for the debugger.
+    pub(crate) fn debug(&self) -> bool {
+        self.shared.debug
+    }
+    /// The named global variables of the program.
+    pub(crate) fn globals(&self) -> impl Iterator<Item = (&I, Ident)> + '_ {
+        self.shared.hm.iter().filter(|(_, id)| id.global).map(|(name, id)| (name, *id))
+    }
 
     // We want to optimize scripts that never override FS after the start of the program. We do
     // this by collecting any builtin variable assignments (as well as getline and UDF calls)
@@ -363,6 +458,15 @@ impl<'a, I> ProgramContext<'a, I>
             Stage::Par { begin, .. } => begin,
         }
     }
+    /// The number of assignments to the builtin variable `v` in the program, including those
+    /// introduced when desugaring the main loop.
+    pub fn builtin_assignments(&self, v: builtins::Variable) -> usize {
+        self.funcs
+            .iter()
+            .filter_map(|f| f.vars.get(&Some(v)))
+            .map(Vec::len)
+            .sum()
+    }
     pub fn analyze_sep_assignments(&self) -> SepAssign<'a> {
         let mut field_sep = None;
         let mut record_sep = None;
@@ -445,7 +549,14 @@ impl<'a, I> ProgramContext<'a, I>
             max: 1, // 0 reserved for assigning to "unused" var for side-effecting operations
             conds: Default::default(),
             esc,
+            merges: Default::default(),
+            pending_next: None,
+            debug: p.debug,
+            flushes: false,
         };
+        if p.decs.iter().any(|d| d.body.has_next()) {
+            shared.pending_next = Some(shared.fresh());
+        }
         let mut func_table: HashMap<FunctionName<I>, NumTy> = Default::default();
         let mut funcs: Vec<Function<'a, I>> = Default::default();
         for fundec in p.decs.iter() {
@@ -490,6 +601,18 @@ impl<'a, I> ProgramContext<'a, I>
             f.ret = ret;
             funcs.push(f);
         }
+        let named_funcs: Vec<(I, &[u8], usize)> = p
+            .decs
+            .iter()
+            .map(|d| {
+                let lit = arena.alloc_bytes(d.name.to_string().as_bytes());
+                (d.name.clone(), lit, d.args.len())
+            })
+            .collect();
+        let template = match p.output_template {
+            Some(tmpl) => Some(Template::parse(arena, tmpl, p.parse_header)?),
+            None => None,
+        };
         // Now that we have all the functions in place, it's time to fill them up and convert them
         // to SSA.
         macro_rules! fill {
@@ -501,7 +624,10 @@ impl<'a, I> ProgramContext<'a, I>
                         ctx: &mut shared,
                         f: &mut func,
                         func_table: &func_table,
+                        named_funcs: &named_funcs,
                         parse_header: p.parse_header,
+                        lines: &p.lines,
+                        template: template.as_ref(),
                     }
                     .fill(s)?;
                     func_table.insert($name, offset as NumTy);
@@ -521,7 +647,10 @@ impl<'a, I> ProgramContext<'a, I>
                 ctx: &mut shared,
                 f: funcs.get_mut(f as usize).unwrap(),
                 func_table: &func_table,
+                named_funcs: &named_funcs,
                 parse_header: p.parse_header,
+                lines: &p.lines,
+                template: template.as_ref(),
             }
                 .fill(fundec.body)?;
         }
@@ -556,14 +685,27 @@ impl<'a, I> ProgramContext<'a, I>
             }
         };
 
-        Ok(ProgramContext {
+        let flushes = shared.flushes;
+        let mut ctx = ProgramContext {
             shared,
             funcs,
             main_offset,
             allow_arbitrary_commands: false,
             fold_regex_constants: false,
+            safe_math: false,
+            sources: Default::default(),
             parse_header: p.parse_header,
-        })
+            record_limit: None,
+            flushes,
+            column_stats: column_stats::plan(p),
+            arena,
+        };
+        // Only the main loop's `NR++` may assign to NR, otherwise NR need not count the records
+        // read so far.
+        if ctx.builtin_assignments(builtins::Variable::NR) == 1 {
+            ctx.record_limit = p.record_limit();
+        }
+        Ok(ctx)
     }
 }
 
@@ -571,7 +713,13 @@ struct View<'a, 'b, I> {
     ctx: &'a mut GlobalContext<I>,
     f: &'a mut Function<'b, I>,
     func_table: &'a HashMap<FunctionName<I>, NumTy>,
+    // The name (also as a string literal) and number of parameters of every user-defined
+    // function, used to resolve indirect calls.
+    named_funcs: &'a [(I, &'b [u8], usize)],
     parse_header: bool,
+    // See ast::Prog::lines.
+    lines: &'a HashMap<usize, usize>,
+    template: Option<&'a Template<'b>>,
 }
 
 #[derive(Debug)]
@@ -592,6 +740,17 @@ struct GlobalContext<I> {
     max: NumTy,
     conds: HashMap<usize, Ident>,
     esc: Escaper,
+    // Merge strategies for parallel scripts, set via `PARALLEL_MERGE["var"] = "strategy"`. These
+    // are keyed by variable name, and resolved to identifiers once the whole program is lowered.
+    merges: Vec<(Vec<u8>, MergeStrategy)>,
+    // Set by `next` (to 1) or `nextfile` (to 2) inside a function, which then returns. Every call
+    // to a function checks it and moves on to the next record (or returns from the calling
+    // function in turn). Only allocated if some function uses `next` or `nextfile`.
+    pending_next: Option<Ident>,
+    // See `Prog::debug`.
+    debug: bool,
+    // Whether the program calls fflush; see ProgramContext::flushes.
+    flushes: bool,
 }
 
 impl<I> GlobalContext<I> {
@@ -691,6 +850,33 @@ fn record_ident(
     orig.entry(blk).or_insert(HashSet::default()).insert(id);
 }
 
+// Is `e` the ENVIRON variable? Writes to it are mirrored into the environment of the process.
+fn is_environ<I: Clone>(e: &Expr<I>) -> bool
+where
+    builtins::Variable: TryFrom<I>,
+{
+    matches!(e, Expr::Var(id) if matches!(builtins::Variable::try_from(id.clone()), Ok(builtins::Variable::ENVIRON)))
+}
+
+// The builtin for `op`. With -M, the arithmetic operators compute with arbitrary-precision numbers
+// (see `runtime::bignum`).
+fn arith(op: ast::Binop) -> builtins::Function {
+    use ast::Binop::*;
+    use builtins::Function::*;
+    if !bignum::enabled() {
+        return Binop(op);
+    }
+    match op {
+        Plus => BigAdd,
+        Minus => BigSub,
+        Mult => BigMul,
+        Div => BigDiv,
+        Mod => BigMod,
+        Pow => BigPow,
+        op => Binop(op),
+    }
+}
+
 impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debug> View<'a, 'b, I>
     where
         builtins::Variable: TryFrom<I>,
@@ -774,6 +960,9 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         mut current_open: NodeIx,
     ) -> Result<NodeIx> /*next open */ {
         use Stmt::*;
+        if let Some(line) = self.line_of(stmt) {
+            self.mark_line(current_open, line);
+        }
         Ok(match stmt {
             StartCond(cond) => {
                 self.set_cond(current_open, *cond, 1)?;
@@ -818,6 +1007,9 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 self.add_stmt(current_open, PrimStmt::Printf(fmt_v, arg_vs, out_v))?;
                 current_open
             }
+            Print(vs, out) if self.template.is_some() => {
+                self.print_template(vs, out, current_open)?
+            }
             Print(vs, out) => {
                 let ors = {
                     let ors = self.fresh_local();
@@ -868,9 +1060,11 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 };
                 let mut print_args = SmallVec::with_capacity(vs.len() * 2);
                 for (i, v) in vs.iter().enumerate() {
-                    let (next, mut to_print) = self.convert_val(*v, current_open)?;
-                    to_print = self.escape(to_print, current_open)?;
+                    let (next, to_print) = self.convert_val(*v, current_open)?;
+                    // Escape in `next`: converting `v` may have opened new blocks (e.g. for a
+                    // ternary), and its value is not available before them.
                     current_open = next;
+                    let to_print = self.escape(to_print, current_open)?;
                     print_args.push(to_print);
                     if i == vs.len() - 1 {
                         print_args.push(ors.clone());
@@ -998,11 +1192,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 } else {
                     (current_open, PrimExpr::Val(PrimVal::Var(Ident::unused())))
                 };
-                self.add_stmt(current_open, PrimStmt::AsgnVar(self.f.ret, e))?;
-                self.f
-                    .cfg
-                    .add_edge(current_open, self.f.exit, Transition::null());
-                self.seal(current_open);
+                self.do_return(current_open, e)?;
                 current_open
             }
         })
@@ -1029,6 +1219,24 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 &Unop(ast::Unop::Not, &Unop(ast::Unop::Not, $e))
             };
         }
+        // With -H, FIELDS[name] is $FI[name]: reading, assigning or incrementing it works on the
+        // column with that header.
+        if self.parse_header {
+            match expr {
+                Index(Var(arr), ix) if arr.is_fields() => {
+                    return self.header_column(ix, None, current_open);
+                }
+                Assign(Index(Var(arr), ix), to) if arr.is_fields() => {
+                    let (next, to_v) = self.convert_val(to, current_open)?;
+                    return self.header_column(ix, Some((None, to_v)), next);
+                }
+                AssignOp(Index(Var(arr), ix), op, to) if arr.is_fields() => {
+                    let (next, to_v) = self.convert_val(to, current_open)?;
+                    return self.header_column(ix, Some((Some(*op), to_v)), next);
+                }
+                _ => {}
+            }
+        }
         let res_expr = match expr {
             ILit(n) => PrimExpr::Val(PrimVal::ILit(*n)),
             FLit(n) => PrimExpr::Val(PrimVal::FLit(*n)),
@@ -1045,18 +1253,37 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             Unop(op, e) => {
                 let next_cond = in_cond && matches!(op, ast::Unop::Not);
                 let (next, v) = self.convert_val_inner(e, current_open, next_cond)?;
-                return Ok((
-                    next,
-                    PrimExpr::CallBuiltin(builtins::Function::Unop(*op), smallvec![v]),
-                ));
+                let zero = PrimVal::StrLit(b"0");
+                let res = match op {
+                    ast::Unop::Neg if bignum::enabled() => {
+                        PrimExpr::CallBuiltin(builtins::Function::BigSub, smallvec![zero, v])
+                    }
+                    ast::Unop::Pos if bignum::enabled() => {
+                        PrimExpr::CallBuiltin(builtins::Function::BigAdd, smallvec![v, zero])
+                    }
+                    _ => PrimExpr::CallBuiltin(builtins::Function::Unop(*op), smallvec![v]),
+                };
+                return Ok((next, res));
             }
             Binop(op, e1, e2) => {
                 let (next, v1) = self.convert_val(e1, current_open)?;
                 let (next, v2) = self.convert_val(e2, next)?;
-                return Ok((
-                    next,
-                    PrimExpr::CallBuiltin(builtins::Function::Binop(*op), smallvec![v1, v2]),
-                ));
+                use ast::Binop::*;
+                let res = match op {
+                    // With -M, comparisons are `big_cmp(v1, v2) op 0`.
+                    LT | GT | LTE | GTE | EQ if bignum::enabled() => {
+                        let cmp = self.to_val(
+                            PrimExpr::CallBuiltin(builtins::Function::BigCmp, smallvec![v1, v2]),
+                            next,
+                        )?;
+                        PrimExpr::CallBuiltin(
+                            builtins::Function::Binop(*op),
+                            smallvec![cmp, PrimVal::ILit(0)],
+                        )
+                    }
+                    op => PrimExpr::CallBuiltin(arith(*op), smallvec![v1, v2]),
+                };
+                return Ok((next, res));
             }
             ITE(cond, tcase, fcase) => {
                 let res_id = self.fresh_local();
@@ -1093,6 +1320,9 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     } else {
                         PrimExpr::LoadBuiltin(bi)
                     }
+                } else if self.parse_header && id.is_fields() {
+                    // On its own, FIELDS is FI, so `for (name in FIELDS)` visits the headers.
+                    PrimExpr::LoadBuiltin(builtins::Variable::FI)
                 } else {
                     let ident = self.get_identifier(id);
                     PrimExpr::Val(PrimVal::Var(ident))
@@ -1104,7 +1334,30 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 return Ok((next, PrimExpr::Index(arr_v, ix_v)));
             }
             Call(fname, args) => return self.call(current_open, fname, args),
+            IndirectCall(f, args) => {
+                let (mut open, f) = self.convert_val(f, current_open)?;
+                let mut prim_args = SmallVec::with_capacity(args.len());
+                for a in args.iter() {
+                    let (next, v) = self.convert_val(a, open)?;
+                    open = next;
+                    prim_args.push(v);
+                }
+                return self.call_by_name(f, prim_args, open);
+            }
             Assign(Index(arr, ix), to) => {
+                if let (Var(arr_name), StrLit(var), StrLit(strategy)) = (arr, ix, to) {
+                    if arr_name.is_parallel_merge() {
+                        match MergeStrategy::try_from(*strategy) {
+                            Ok(m) => self.ctx.merges.push((var.to_vec(), m)),
+                            Err(()) => {
+                                return err!(
+                                    "unknown PARALLEL_MERGE strategy \"{}\" (expected one of sum, min, max, concat, union)",
+                                    String::from_utf8_lossy(strategy)
+                                )
+                            }
+                        }
+                    }
+                }
                 return self.do_assign_index(
                     arr,
                     ix,
@@ -1114,7 +1367,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             }
 
             AssignOp(Index(arr, ix), op, to) => {
-                if let ast::Binop::Plus = op {
+                if let (ast::Binop::Plus, false, false) = (op, is_environ(arr), bignum::enabled()) {
                     // We don't need in_cond here, it would seem, because there aren't
                     // subexpressions which should be considered patterns.
                     return self.convert_expr(
@@ -1130,10 +1383,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                         let arr_cell_v = slf.to_val(PrimExpr::Index(arr_v, ix_v.clone()), next)?;
                         Ok((
                             next,
-                            PrimExpr::CallBuiltin(
-                                builtins::Function::Binop(*op),
-                                smallvec![arr_cell_v, to_v],
-                            ),
+                            PrimExpr::CallBuiltin(arith(*op), smallvec![arr_cell_v, to_v]),
                         ))
                     },
                     current_open,
@@ -1151,12 +1401,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 let (next, to_v) = self.convert_val(to, current_open)?;
                 return self.do_assign(
                     x,
-                    |v| {
-                        PrimExpr::CallBuiltin(
-                            builtins::Function::Binop(*op),
-                            smallvec![v.clone(), to_v],
-                        )
-                    },
+                    |v| PrimExpr::CallBuiltin(arith(*op), smallvec![v.clone(), to_v]),
                     next,
                 );
             }
@@ -1292,6 +1537,213 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         Ok((current_open, res_expr))
     }
 
+    // Call the user-defined function whose name is the string `f`, as in `@f(args)`:
+    //
+    // f == "g" ? g(args) : f == "h" ? h(args) : <error>
+    //
+    // for every function g, h, ... that takes at least `args.len()` parameters. Literal names are
+    // resolved at compile time.
+    fn call_by_name(
+        &mut self,
+        f: PrimVal<'b>,
+        args: SmallVec<PrimVal<'b>>,
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        use builtins::Function;
+        let named_funcs = self.named_funcs;
+        let cands: Vec<_> = named_funcs
+            .iter()
+            .filter(|(_, _, arity)| *arity >= args.len())
+            .map(|(name, lit, _)| (self.func_table[&FunctionName::Named(name.clone())], *lit))
+            .collect();
+        // See the UDF case in `call`.
+        self.f
+            .vars
+            .entry(None)
+            .or_insert_with(Vec::new)
+            .push((current_open.index(), None));
+        if let PrimVal::StrLit(name) = f {
+            return match cands.iter().find(|(_, lit)| *lit == name) {
+                Some((i, _)) => self.call_udf(*i, args, current_open),
+                None => err!(
+                    "\"{}\" is not the name of a function taking {} argument(s)",
+                    String::from_utf8_lossy(name),
+                    args.len()
+                ),
+            };
+        }
+        let res = self.fresh_local();
+        self.ctx.may_rename.push(res);
+        let next = self.f.cfg.add_node(Default::default());
+        let mut open = current_open;
+        for (i, lit) in cands {
+            let is_match = self.to_val(
+                PrimExpr::CallBuiltin(
+                    Function::Binop(ast::Binop::EQ),
+                    smallvec![f.clone(), PrimVal::StrLit(lit)],
+                ),
+                open,
+            )?;
+            let call = self.f.cfg.add_node(Default::default());
+            self.add_stmt(
+                call,
+                PrimStmt::AsgnVar(res, PrimExpr::CallUDF(i, args.clone())),
+            )?;
+            self.f.cfg.add_edge(open, call, Transition::new(is_match));
+            self.f.cfg.add_edge(call, next, Transition::null());
+            let other = self.f.cfg.add_node(Default::default());
+            self.f.cfg.add_edge(open, other, Transition::null());
+            open = other;
+        }
+        // Like gawk, treat calls to a function that does not exist as a fatal error.
+        let mut msg = PrimVal::StrLit(b"indirect call: \"");
+        for part in [
+            f,
+            PrimVal::StrLit(b"\" is not the name of a function taking "),
+            PrimVal::ILit(args.len() as i64),
+            PrimVal::StrLit(b" argument(s)"),
+        ] {
+            let concat = Function::Binop(ast::Binop::Concat);
+            msg = self.to_val(PrimExpr::CallBuiltin(concat, smallvec![msg, part]), open)?;
+        }
+        for (bi, arg) in [
+            (Function::LogError, msg),
+            (Function::Exit, PrimVal::ILit(2)),
+        ] {
+            let e = PrimExpr::CallBuiltin(bi, smallvec![arg]);
+            self.add_stmt(open, PrimStmt::AsgnVar(Ident::unused(), e))?;
+        }
+        self.add_stmt(
+            open,
+            PrimStmt::AsgnVar(res, PrimExpr::Val(PrimVal::StrLit(b""))),
+        )?;
+        self.f.cfg.add_edge(open, next, Transition::null());
+        let next = self.check_next(next)?;
+        Ok((next, PrimExpr::Val(PrimVal::Var(res))))
+    }
+
+    // _map(arr, "f" [, dest]) stores f(arr[k]) for every k in arr, and _filter(arr, "pred" [, dest])
+    // keeps the elements for which pred(arr[k]) is true. Both work in place unless given a
+    // destination array, and return the number of elements in the result.
+    // _reduce(arr, "f", init) folds arr with acc = f(acc, arr[k]), starting from init.
+    fn do_array_fn<'c>(
+        &mut self,
+        which: builtins::ArrayFn,
+        args: &'c [&'c Expr<'c, 'b, I>],
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        use builtins::{ArrayFn::*, Function};
+        let name = match which {
+            Map => "_map",
+            Filter => "_filter",
+            Reduce => "_reduce",
+        };
+        match (which, args.len()) {
+            (Map | Filter, 2 | 3) | (Reduce, 3) => {}
+            (Reduce, _) => {
+                return err!("_reduce takes 3 arguments: array, function name, initial value")
+            }
+            _ => {
+                return err!(
+                    "{} takes 2 or 3 arguments: array, function name[, destination array]",
+                    name
+                )
+            }
+        }
+        let mut open = current_open;
+        let mut vals = SmallVec::<PrimVal<'b>>::with_capacity(args.len());
+        for a in args.iter() {
+            let (next, v) = self.convert_val(a, open)?;
+            open = next;
+            vals.push(v);
+        }
+        let as_array = |v: &PrimVal<'b>| match v {
+            PrimVal::Var(id) => Ok(*id),
+            _ => err!("{} expects an array, got {:?}", name, v),
+        };
+        let arr = vals[0].clone();
+        let arr_id = as_array(&arr)?;
+        let f = vals[1].clone();
+        let (dest, acc) = match (which, vals.get(2)) {
+            (Reduce, Some(init)) => {
+                let acc = self.fresh_local();
+                self.ctx.may_rename.push(acc);
+                self.add_stmt(open, PrimStmt::AsgnVar(acc, PrimExpr::Val(init.clone())))?;
+                (arr_id, Some(acc))
+            }
+            (_, Some(dest)) => (as_array(dest)?, None),
+            (_, None) => (arr_id, None),
+        };
+        if dest != arr_id {
+            let clear = PrimExpr::CallBuiltin(Function::Clear, smallvec![PrimVal::Var(dest)]);
+            self.add_stmt(open, PrimStmt::AsgnVar(Ident::unused(), clear))?;
+        }
+
+        // The same loop as `for (k in arr)`; iteration is over a snapshot of the keys, so deleting
+        // elements as we go is fine.
+        let iter = self.to_val(PrimExpr::IterBegin(arr.clone()), open)?;
+        let cond_block = self.f.cfg.add_node(Default::default());
+        let has_next = self.to_val(PrimExpr::HasNext(iter.clone()), cond_block)?;
+        self.f.cfg.add_edge(open, cond_block, Transition::null());
+        let footer = self.f.cfg.add_node(Default::default());
+        self.add_stmt(footer, PrimStmt::IterDrop(iter.clone()))?;
+
+        let body_start = self.f.cfg.add_node(Default::default());
+        let k = self.fresh_local();
+        self.ctx.may_rename.push(k);
+        self.add_stmt(body_start, PrimStmt::AsgnVar(k, PrimExpr::Next(iter)))?;
+        let v = self.to_val(PrimExpr::Index(arr.clone(), PrimVal::Var(k)), body_start)?;
+        let call_args = match acc {
+            Some(acc) => smallvec![PrimVal::Var(acc), v.clone()],
+            None => smallvec![v.clone()],
+        };
+        let (mut body_end, res) = self.call_by_name(f, call_args, body_start)?;
+        match (which, acc) {
+            (Reduce, Some(acc)) => self.add_stmt(body_end, PrimStmt::AsgnVar(acc, res))?,
+            (Map, _) => self.add_stmt(body_end, PrimStmt::AsgnIndex(dest, PrimVal::Var(k), res))?,
+            _ => {
+                let keep = self.to_val(res, body_end)?;
+                let (kept, dropped) = (
+                    self.f.cfg.add_node(Default::default()),
+                    self.f.cfg.add_node(Default::default()),
+                );
+                if dest == arr_id {
+                    let delete = PrimExpr::CallBuiltin(
+                        Function::Delete,
+                        smallvec![arr.clone(), PrimVal::Var(k)],
+                    );
+                    self.add_stmt(dropped, PrimStmt::AsgnVar(Ident::unused(), delete))?;
+                } else {
+                    self.add_stmt(
+                        kept,
+                        PrimStmt::AsgnIndex(dest, PrimVal::Var(k), PrimExpr::Val(v)),
+                    )?;
+                }
+                let join = self.f.cfg.add_node(Default::default());
+                self.f.cfg.add_edge(body_end, kept, Transition::new(keep));
+                self.f.cfg.add_edge(body_end, dropped, Transition::null());
+                self.f.cfg.add_edge(kept, join, Transition::null());
+                self.f.cfg.add_edge(dropped, join, Transition::null());
+                body_end = join;
+            }
+        }
+        self.f
+            .cfg
+            .add_edge(cond_block, body_start, Transition::new(has_next));
+        self.f.cfg.add_edge(cond_block, footer, Transition::null());
+        self.f
+            .cfg
+            .add_edge(body_end, cond_block, Transition::null());
+
+        Ok((
+            footer,
+            match acc {
+                Some(acc) => PrimExpr::Val(PrimVal::Var(acc)),
+                None => PrimExpr::CallBuiltin(Function::Length, smallvec![PrimVal::Var(dest)]),
+            },
+        ))
+    }
+
     fn guarded_else(&mut self, from: NodeIx, to: NodeIx) {
         if self.f.cfg.node_weight(from).unwrap().sealed {
             return;
@@ -1318,6 +1770,39 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         }
         Ok((current_open, PrimExpr::Sprintf(fmt, res)))
     }
+    // tee(s, file1, file2, ...) prints s (followed by ORS) to standard output and to each of the
+    // files, as if by `print s; print s > file1; ...`. It evaluates to s.
+    fn do_tee<'c>(
+        &mut self,
+        args: &'c [&'c Expr<'c, 'b, I>],
+        mut current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        if args.is_empty() {
+            return err!("tee must have at least one argument");
+        }
+        let (next, s) = self.convert_val(args[0], current_open)?;
+        current_open = next;
+        let mut outs = Vec::with_capacity(args.len());
+        outs.push(None);
+        for a in &args[1..] {
+            let (next, f) = self.convert_val(a, current_open)?;
+            current_open = next;
+            outs.push(Some((f, FileSpec::Trunc)));
+        }
+        let ors = self.fresh_local();
+        self.add_stmt(
+            current_open,
+            PrimStmt::AsgnVar(ors, PrimExpr::LoadBuiltin(builtins::Variable::ORS)),
+        )?;
+        let to_print = self.escape(s.clone(), current_open)?;
+        for out in outs {
+            self.add_stmt(
+                current_open,
+                PrimStmt::PrintAll(smallvec![to_print.clone(), PrimVal::Var(ors)], out),
+            )?;
+        }
+        Ok((current_open, PrimExpr::Val(s)))
+    }
     fn do_assign<'c>(
         &mut self,
         v: &'c Expr<'c, 'b, I>,
@@ -1380,6 +1865,69 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             _ => err!("unsupported assignment LHS: {:?}", v),
         }
     }
+    // FIELDS[ix] under -H: read the column whose header is `ix` or, given `update`, assign `to`
+    // (combined with the current value by `op`, if there is one) to it. Unlike $FI[ix], a name
+    // that is not a header does not fall back to $0: it reads as "" and the assignment is
+    // dropped, leaving the record alone.
+    fn header_column<'c>(
+        &mut self,
+        ix: &'c Expr<'c, 'b, I>,
+        update: Option<(Option<ast::Binop>, PrimVal<'b>)>,
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        use builtins::Function;
+        let (open, ix_v) = self.convert_val(ix, current_open)?;
+        let fi = self.to_val(PrimExpr::LoadBuiltin(builtins::Variable::FI), open)?;
+        let known = self.to_val(
+            PrimExpr::CallBuiltin(Function::Contains, smallvec![fi.clone(), ix_v.clone()]),
+            open,
+        )?;
+        let res = self.fresh_local();
+        self.ctx.may_rename.push(res);
+
+        let tcase = self.f.cfg.add_node(Default::default());
+        let col = self.to_val(PrimExpr::Index(fi, ix_v), tcase)?;
+        let get_col = PrimExpr::CallBuiltin(Function::Unop(ast::Unop::Column), smallvec![col.clone()]);
+        let new = match &update {
+            None => get_col,
+            Some((None, to)) => PrimExpr::Val(to.clone()),
+            Some((Some(op), to)) => {
+                let cur = self.to_val(get_col, tcase)?;
+                PrimExpr::CallBuiltin(arith(*op), smallvec![cur, to.clone()])
+            }
+        };
+        if update.is_some() {
+            let new_v = self.to_val(new, tcase)?;
+            self.add_stmt(
+                tcase,
+                PrimStmt::AsgnVar(
+                    Ident::unused(),
+                    PrimExpr::CallBuiltin(Function::Setcol, smallvec![col, new_v.clone()]),
+                ),
+            )?;
+            self.add_stmt(tcase, PrimStmt::AsgnVar(res, PrimExpr::Val(new_v)))?;
+        } else {
+            self.add_stmt(tcase, PrimStmt::AsgnVar(res, new))?;
+        }
+
+        let fcase = self.f.cfg.add_node(Default::default());
+        let missing = match update {
+            None => PrimExpr::Val(PrimVal::StrLit(b"")),
+            Some((None, to)) => PrimExpr::Val(to),
+            Some((Some(op), to)) => {
+                PrimExpr::CallBuiltin(arith(op), smallvec![PrimVal::StrLit(b""), to])
+            }
+        };
+        self.add_stmt(fcase, PrimStmt::AsgnVar(res, missing))?;
+
+        let next = self.f.cfg.add_node(Default::default());
+        self.f.cfg.add_edge(open, tcase, Transition::new(known));
+        self.f.cfg.add_edge(open, fcase, Transition::null());
+        self.f.cfg.add_edge(tcase, next, Transition::null());
+        self.f.cfg.add_edge(fcase, next, Transition::null());
+        Ok((next, PrimExpr::Val(PrimVal::Var(res))))
+    }
+
     fn do_assign_index<'c>(
         &mut self,
         arr: &'c Expr<'c, 'b, I>,
@@ -1410,6 +1958,18 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             next,
             PrimStmt::AsgnIndex(arr_id, ix_v.clone(), to_e.clone()),
         )?;
+        if is_environ(arr) {
+            // ENVIRON[k] = v => ENVIRON[k] = v; setenv(k, ENVIRON[k]), so that commands we run
+            // later see the new value.
+            let to_v = self.to_val(PrimExpr::Index(arr_v.clone(), ix_v.clone()), next)?;
+            self.add_stmt(
+                next,
+                PrimStmt::AsgnVar(
+                    Ident::unused(),
+                    PrimExpr::CallBuiltin(builtins::Function::SetEnv, smallvec![ix_v.clone(), to_v]),
+                ),
+            )?;
+        }
         Ok((next, PrimExpr::Index(arr_v, ix_v)))
     }
 
@@ -1510,15 +2070,106 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 .add_edge(current_open, header, Transition::null());
             self.seal(current_open);
             Ok(())
+        } else if let (FunctionName::Named(_), Some(pending)) =
+            (&self.f.name, self.ctx.pending_next)
+        {
+            let code = if is_next_file { 2 } else { 1 };
+            self.add_stmt(
+                current_open,
+                PrimStmt::AsgnVar(pending, PrimExpr::Val(PrimVal::ILit(code))),
+            )?;
+            self.do_return(current_open, PrimExpr::Val(PrimVal::Var(Ident::unused())))?;
+            Ok(())
         } else {
             err!(
-                "Cannot use `{}` from outside of the toplevel loop! \
-                 Note that frawk does not support `next` or `nextfile` from inside functions.",
+                "Cannot use `{}` from outside of the toplevel loop!",
                 if is_next_file { "nextfile" } else { "next" }
             )
         }
     }
 
+    fn do_return(&mut self, current_open: NodeIx, e: PrimExpr<'b>) -> Result<()> {
+        self.add_stmt(current_open, PrimStmt::AsgnVar(self.f.ret, e))?;
+        self.f
+            .cfg
+            .add_edge(current_open, self.f.exit, Transition::null());
+        self.seal(current_open);
+        Ok(())
+    }
+
+    // Check whether the function that was just called ran `next` or `nextfile`, and if so jump
+    // to the top of the main loop, or return from this function if it is a UDF.
+    fn check_next(&mut self, current_open: NodeIx) -> Result<NodeIx> {
+        use builtins::Function;
+        let pending = match self.ctx.pending_next {
+            Some(pending) => pending,
+            None => return Ok(current_open),
+        };
+        let code = self.fresh_local();
+        self.add_stmt(
+            current_open,
+            PrimStmt::AsgnVar(code, PrimExpr::Val(PrimVal::Var(pending))),
+        )?;
+        let taken = self.f.cfg.add_node(Default::default());
+        let next = self.f.cfg.add_node(Default::default());
+        self.f
+            .cfg
+            .add_edge(current_open, taken, Transition::new(PrimVal::Var(code)));
+        self.f.cfg.add_edge(current_open, next, Transition::null());
+        if let FunctionName::Named(_) = self.f.name {
+            self.do_return(taken, PrimExpr::Val(PrimVal::Var(Ident::unused())))?;
+        } else if let Some(header) = self.f.toplevel_header {
+            self.add_stmt(
+                taken,
+                PrimStmt::AsgnVar(pending, PrimExpr::Val(PrimVal::ILit(0))),
+            )?;
+            let is_next_file = self.to_val(
+                PrimExpr::CallBuiltin(
+                    Function::Binop(ast::Binop::EQ),
+                    smallvec![PrimVal::Var(code), PrimVal::ILit(2)],
+                ),
+                taken,
+            )?;
+            let next_file = self.f.cfg.add_node(Default::default());
+            self.add_stmt(
+                next_file,
+                PrimStmt::AsgnVar(
+                    Ident::unused(),
+                    PrimExpr::CallBuiltin(Function::NextFile, smallvec![]),
+                ),
+            )?;
+            self.f
+                .cfg
+                .add_edge(taken, next_file, Transition::new(is_next_file));
+            self.f.cfg.add_edge(taken, header, Transition::null());
+            self.f.cfg.add_edge(next_file, header, Transition::null());
+        } else {
+            // Like gawk, calling `next` from BEGIN or END is a fatal error.
+            let msg = PrimVal::StrLit(b"`next` or `nextfile` called outside of the main loop");
+            for (bi, arg) in [(Function::LogError, msg), (Function::Exit, PrimVal::ILit(2))] {
+                let e = PrimExpr::CallBuiltin(bi, smallvec![arg]);
+                self.add_stmt(taken, PrimStmt::AsgnVar(Ident::unused(), e))?;
+            }
+            self.f.cfg.add_edge(taken, next, Transition::null());
+        }
+        Ok(next)
+    }
+
+    fn call_udf(
+        &mut self,
+        i: NumTy,
+        args: SmallVec<PrimVal<'b>>,
+        current_open: NodeIx,
+    ) -> Result<(NodeIx, PrimExpr<'b>)> {
+        let call = PrimExpr::CallUDF(i, args);
+        if self.ctx.pending_next.is_none() {
+            return Ok((current_open, call));
+        }
+        let res = self.to_val(call, current_open)?;
+        let next = self.check_next(current_open)?;
+        Ok((next, PrimExpr::Val(res)))
+    }
+
     fn make_loop<'c>(
         &mut self,
         body: &'c Stmt<'c, 'b, I>,
@@ -1566,6 +2217,10 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         }
         self.f.cfg.add_edge(b_end, h, Transition::null());
         self.f.loop_ctx.pop().unwrap();
+        if is_toplevel {
+            // Code after the main loop (i.e. END) cannot use `next`.
+            self.f.toplevel_header = None;
+        }
         Ok((h, b_start, b_end, f))
     }
 
@@ -1594,9 +2249,32 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                 // function that occurs in expression position.
                 return self.do_sprintf(args, current_open);
             }
+            Either::Left(fname)
+                if fname.is_tee()
+                    && !self
+                        .func_table
+                        .contains_key(&FunctionName::Named(fname.clone())) =>
+            {
+                return self.do_tee(args, current_open);
+            }
+            Either::Left(fname)
+                if fname.array_fn().is_some()
+                    && !self
+                        .func_table
+                        .contains_key(&FunctionName::Named(fname.clone())) =>
+            {
+                return self.do_array_fn(fname.array_fn().unwrap(), args, current_open);
+            }
             Either::Left(fname) => {
                 if let Ok(bi) = builtins::Function::try_from(fname.clone()) {
                     // Okay, there's a builtin in here.
+                    if let Some(feature) = crate::runtime::features::missing(&bi) {
+                        return err!(
+                            "{} is not available: zawk was built without the `{}` feature",
+                            fname,
+                            feature
+                        );
+                    }
                     Either::Right(bi)
                 } else {
                     // We'll keep this as a raw identifier. Below, we'll check if it's a UDF, or if
@@ -1615,9 +2293,17 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             open = next;
             prim_args.push(v);
         }
+        // delete ENVIRON[k] => unsetenv(k)
+        let bi = match bi {
+            Either::Right(builtins::Function::Delete) if args.first().is_some_and(|a| is_environ(a)) => {
+                prim_args.remove(0);
+                Either::Right(builtins::Function::UnsetEnv)
+            }
+            bi => bi,
+        };
         match bi {
             Either::Left(fname) => {
-                return if let Some(i) = self.func_table.get(&FunctionName::Named(fname.clone())) {
+                if let Some(&i) = self.func_table.get(&FunctionName::Named(fname.clone())) {
                     // For field separator optimizations, any UDF calls in the BEGIN block of main
                     // causes fallback to the generic regex-based splitter.
                     //
@@ -1627,10 +2313,10 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                         .entry(None)
                         .or_insert_with(Vec::new)
                         .push((current_open.index(), None));
-                    Ok((open, PrimExpr::CallUDF(*i, prim_args)))
+                    self.call_udf(i, prim_args, open)
                 } else {
                     err!("Call to unknown function \"{}\"", fname)
-                };
+                }
             }
             // Now to "fill in the extras."
             Either::Right(mut bi) => {
@@ -1645,6 +2331,28 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     prim_args.push(PrimVal::Var(fs));
                 }
 
+                // round(x, digits) => round_to(x, digits)
+                if bi == builtins::Function::FloatFunc(builtins::FloatFunc::Round)
+                    && args.len() == 2
+                {
+                    bi = builtins::Function::FloatFunc(builtins::FloatFunc::RoundTo);
+                }
+
+                // split(s, arr, re, seps) => split_seps(s, arr, re, seps)
+                if bi == builtins::Function::Split && args.len() == 4 {
+                    bi = builtins::Function::SplitSeps;
+                }
+                // patsplit(s, arr, re) => patsplit(s, arr, re, <unused array>)
+                if bi == builtins::Function::PatSplit && args.len() == 3 {
+                    let seps = self.fresh_local();
+                    prim_args.push(PrimVal::Var(seps));
+                }
+
+                // match(s, re, arr) => match_arr(s, re, arr)
+                if bi == builtins::Function::Match && args.len() == 3 {
+                    bi = builtins::Function::MatchArr;
+                }
+
                 // join_fields(start, end) => join_{c,t}sv (if in csv/tsv output mode)
                 // join_fields(start, end) => join_fields(start, end, OFS) (otherwise)
                 if bi == builtins::Function::JoinCols && args.len() == 2 {
@@ -1664,6 +2372,39 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                         }
                     }
                 }
+                // select(spec) => select(spec, ",", 1) (in csv output mode)
+                // select(spec) => select(spec, "\t", 2) (in tsv output mode)
+                // select(spec) => select(spec, OFS, 0) (otherwise)
+                // select(spec, sep) => select(spec, sep, 0)
+                if bi == builtins::Function::Select && args.len() == 1 {
+                    match self.ctx.esc {
+                        Escaper::CSV => {
+                            prim_args.push(PrimVal::StrLit(b","));
+                            prim_args.push(PrimVal::ILit(1));
+                        }
+                        Escaper::TSV => {
+                            prim_args.push(PrimVal::StrLit(b"\t"));
+                            prim_args.push(PrimVal::ILit(2));
+                        }
+                        Escaper::Identity => {
+                            let fs = self.fresh_local();
+                            self.add_stmt(
+                                current_open,
+                                PrimStmt::AsgnVar(
+                                    fs,
+                                    PrimExpr::LoadBuiltin(builtins::Variable::OFS),
+                                ),
+                            )?;
+                            prim_args.push(PrimVal::Var(fs));
+                            prim_args.push(PrimVal::ILit(0));
+                        }
+                    }
+                } else if bi == builtins::Function::Select && args.len() == 2 {
+                    prim_args.push(PrimVal::ILit(0));
+                }
+                if bi == builtins::Function::FFlush {
+                    self.ctx.flushes = true;
+                }
                 // Fill params if absent
                 let args_len = args.len();
                 match bi {
@@ -1705,6 +2446,26 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     builtins::Function::Pairs if args_len == 2 => {
                         prim_args.push(PrimVal::StrLit(b"="));
                     }
+                    // to_logfmt(arr) => to_logfmt(arr, ""), which sorts all keys
+                    builtins::Function::ToLogfmt if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // to_toml(arr) => to_toml(arr, ""), which guesses every value's type
+                    builtins::Function::ToToml if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // to_xml(arr) => to_xml(arr, "root")
+                    builtins::Function::ToXml if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b"root"));
+                    }
+                    // fuzzy_match(a, b) => fuzzy_match(a, b, 0.85)
+                    builtins::Function::FuzzyMatch if args_len == 2 => {
+                        prim_args.push(PrimVal::FLit(0.85));
+                    }
+                    // html_table(html) => html_table(html, 1)
+                    builtins::Function::HtmlTable if args_len == 1 => {
+                        prim_args.push(PrimVal::ILit(1));
+                    }
                     // pad (s, n) => pad(s, len, " ")
                     builtins::Function::PadLeft if args_len == 2 => {
                         prim_args.push(PrimVal::StrLit(b" "));
@@ -1723,10 +2484,18 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     builtins::Function::DateTime if args_len == 0 => {
                         prim_args.push(PrimVal::StrLit(b""));
                     }
+                    // dateparse(text) => dateparse(text, "");
+                    builtins::Function::DateParse if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
                     // uniq(arr) => uniq(arr, param);
                     builtins::Function::Uniq if args_len == 1 => {
                         prim_args.push(PrimVal::StrLit(b""));
                     }
+                    // fflush() => fflush(""), which flushes every output.
+                    builtins::Function::FFlush if args_len == 0 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
                     // uuid() => uuid("v4");
                     builtins::Function::Uuid if args_len == 0 => {
                         prim_args.push(PrimVal::StrLit(b"v4"));
@@ -1742,18 +2511,24 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                         // rightmost index.
                         prim_args.push(PrimVal::ILit(i64::max_value()));
                     }
-                    // strftime() => strftime("", -1);
+                    // strftime() => strftime("", -1, "");
                     builtins::Function::Strftime if args_len == 0 => {
                         prim_args.push(PrimVal::StrLit(b"")); // ISO 8601 / RFC 3339 date & time format
                         prim_args.push(PrimVal::ILit(-1 as Int));
+                        prim_args.push(PrimVal::StrLit(b""));
                     }
-                    // strftime(format, timestamp) => strftime(format, -1);
+                    // strftime(format) => strftime(format, -1, "");
                     builtins::Function::Strftime if args_len == 1 => {
                         prim_args.push(PrimVal::ILit(-1 as Int));
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // strftime(format, timestamp) => strftime(format, timestamp, "");
+                    builtins::Function::Strftime if args_len == 2 => {
+                        prim_args.push(PrimVal::StrLit(b""));
                     }
-                    // mktime(date_text, timezone) => mktime(date_text, -1);
+                    // mktime(date_text) => mktime(date_text, "");
                     builtins::Function::Mktime if args_len == 1 => {
-                        prim_args.push(PrimVal::ILit(-1 as Int));
+                        prim_args.push(PrimVal::StrLit(b""));
                     }
                     // trim(s) => trim(s, " ");
                     builtins::Function::Trim if args_len == 1 => {
@@ -1771,6 +2546,26 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     builtins::Function::Max if args_len == 2 => {
                         prim_args.push(PrimVal::StrLit(b""));
                     }
+                    // dec_fmt(x) => dec_fmt(x, -1), which strips trailing zeros.
+                    builtins::Function::DecFmt if args_len == 1 => {
+                        prim_args.push(PrimVal::ILit(-1));
+                    }
+                    // format_num(x, pattern) => format_num(x, pattern, ""), which writes `,` and `.`.
+                    builtins::Function::FormatNum if args_len == 2 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // dejwt(key, token) => dejwt(key, token, ""), which checks only the signature.
+                    builtins::Function::Dejwt if args_len == 2 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // digest_init(alg) => digest_init(alg, ""); the key is only used by HMACs.
+                    builtins::Function::DigestInit if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
+                    // parse_number(s) => parse_number(s, ""), which guesses the decimal mark.
+                    builtins::Function::ParseNumber if args_len == 1 => {
+                        prim_args.push(PrimVal::StrLit(b""));
+                    }
                     // seq(10) => seq(1,1 10);
                     builtins::Function::Seq if args_len == 1 => {
                         let max = prim_args.pop().unwrap();
@@ -1784,9 +2579,23 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                         prim_args.push(PrimVal::ILit(1));
                         prim_args.push(max);
                     }
-                    // asort(arr) => asort(arr,dst);
+                    // _sorted_keys(arr) => _sorted_keys(arr, "asc"), and the same for _sort_by_value
+                    builtins::Function::ArraySortedKeys | builtins::Function::ArraySortByValue
+                        if args_len == 1 =>
+                    {
+                        prim_args.push(PrimVal::StrLit(b"asc"));
+                    }
+                    // _slice(arr, from) => _slice(arr, from, <the last index>)
+                    builtins::Function::ArraySlice if args_len == 2 => {
+                        prim_args.push(PrimVal::ILit(Int::MAX));
+                    }
+                    // asort(arr) => asort(arr, arr, "asc"); asort(arr, dst) => asort(arr, dst, "asc")
                     builtins::Function::Asort if args_len == 1 => {
-                        prim_args.push(PrimVal::Var(Ident::unused()));
+                        prim_args.push(prim_args[0].clone());
+                        prim_args.push(PrimVal::StrLit(b"asc"));
+                    }
+                    builtins::Function::Asort if args_len == 2 => {
+                        prim_args.push(PrimVal::StrLit(b"asc"));
                     }
                     // http_get(url) => http_get(url,headers);
                     builtins::Function::HttpGet if args_len == 1 => {
@@ -1852,6 +2661,9 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
                     let to_set_var = PrimExpr::Val(PrimVal::Var(to_set));
                     let (next, _) = match assignee {
                         Expr::Unop(_, _) => self.do_assign(assignee, |_| to_set_var, open),
+                        Expr::Index(Expr::Var(arr), ix) if self.parse_header && arr.is_fields() => {
+                            self.header_column(ix, Some((None, PrimVal::Var(to_set))), open)
+                        }
                         Expr::Index(arr, ix) => self.do_assign_index(
                             arr,
                             ix,
@@ -1878,6 +2690,58 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         }
     }
 
+    // Print `vs` under `-o template=...`, as printf with the template's format.
+    fn print_template<'c>(
+        &mut self,
+        vs: &[&'c Expr<'c, 'b, I>],
+        out: &Option<(&'c Expr<'c, 'b, I>, FileSpec)>,
+        mut current_open: NodeIx,
+    ) -> Result<NodeIx> {
+        let tmpl = self.template.unwrap();
+        // Each value printed is evaluated once, whether the template uses it or not.
+        let mut vals = Vec::with_capacity(vs.len());
+        for v in vs.iter() {
+            let (next, v) = self.convert_val(v, current_open)?;
+            current_open = next;
+            vals.push(v);
+        }
+        let fi = Expr::Var(I::fi());
+        let mut args = SmallVec::with_capacity(tmpl.fields.len() + 1);
+        for field in tmpl.fields.iter() {
+            let key;
+            let col = match field {
+                TemplateField::Index(i) if *i > 0 && !vals.is_empty() => {
+                    args.push(vals.get(i - 1).cloned().unwrap_or(PrimVal::StrLit(b"")));
+                    continue;
+                }
+                TemplateField::Index(i) => Expr::ILit(*i as i64),
+                TemplateField::Name(name) => {
+                    key = Expr::StrLit(name);
+                    Expr::Index(&fi, &key)
+                }
+            };
+            let (next, v) = self.convert_val(&Expr::Unop(Unop::Column, &col), current_open)?;
+            current_open = next;
+            args.push(v);
+        }
+        let ors = self.fresh_local();
+        self.add_stmt(
+            current_open,
+            PrimStmt::AsgnVar(ors, PrimExpr::LoadBuiltin(builtins::Variable::ORS)),
+        )?;
+        args.push(PrimVal::Var(ors));
+        let out = if let Some((o, spec)) = out {
+            let (next, o) = self.convert_val(o, current_open)?;
+            current_open = next;
+            Some((o, *spec))
+        } else {
+            None
+        };
+        let fmt = PrimVal::StrLit(tmpl.fmt);
+        self.add_stmt(current_open, PrimStmt::Printf(fmt, args, out))?;
+        Ok(current_open)
+    }
+
     fn escape(&mut self, v: PrimVal<'b>, current_open: NodeIx) -> Result<PrimVal<'b>> {
         let builtin = match self.ctx.esc {
             Escaper::CSV => builtins::Function::EscapeCSV,
@@ -1917,7 +2781,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             let next = self.fresh();
             self.ctx.hm.insert(i.clone(), next);
             self.ctx.may_rename.push(next);
-            if self.f.name.is_main() {
+            if self.f.name.is_main() && !self.ctx.debug {
                 self.ctx.local_globals.insert(next.low);
             }
             next
@@ -1940,6 +2804,32 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
         Ok(())
     }
 
+    fn line_of<'c>(&self, stmt: &'c Stmt<'c, 'b, I>) -> Option<usize> {
+        use ast::addr;
+        match stmt {
+            Stmt::Block(_) => None,
+            // Patterns are desugared into `if` statements; the line is recorded for the pattern.
+            Stmt::If(cond, _, _) => self
+                .lines
+                .get(&addr(stmt))
+                .or_else(|| self.lines.get(&addr(*cond)))
+                .cloned(),
+            _ => self.lines.get(&addr(stmt)).cloned(),
+        }
+    }
+
+    fn mark_line(&mut self, at: NodeIx, line: usize) {
+        let bb = self.f.cfg.node_weight_mut(at).unwrap();
+        if bb.sealed {
+            // Unreachable code, as after a `return`.
+            return;
+        }
+        match bb.q.back_mut() {
+            Some(PrimStmt::Line(l)) => *l = line,
+            _ => bb.q.push_back(PrimStmt::Line(line)),
+        }
+    }
+
     fn seal(&mut self, at: NodeIx) {
         self.f.cfg.node_weight_mut(at).unwrap().sealed = true;
     }
@@ -2119,7 +3009,7 @@ impl<'a, 'b, I: Hash + Eq + Clone + Default + std::fmt::Display + std::fmt::Debu
             };
             self.ctx.max as usize
         ];
-        for id in self.ctx.hm.values() {
+        for id in self.ctx.hm.values().chain(self.ctx.pending_next.iter()) {
             if id.global && self.ctx.local_globals.get(&id.low).is_none() {
                 state[id.low as usize].global = true;
             }
@@ -0,0 +1,1993 @@
+//! The `zawk` command line.
+use clap::{Arg, Command};
+
+use crate::arena::Arena;
+use crate::cfg::{self, Escaper};
+#[cfg(any(feature = "jit", feature = "llvm_backend"))]
+use crate::codegen::{self, intrinsics::IntoRuntime};
+use crate::common::{CancelSignal, ExecutionStrategy, Stage};
+use crate::trace::SourceMap;
+use crate::{
+    ast, builtins, bytecode, column_stats, compile, include, lexer, lint, parallel_report, parsing,
+    precompile, pretty, query, schema, script_flags,
+};
+use crate::runtime::{self,
+    splitter::{
+        avro::AvroReader,
+        batch::{ByteReader, CSVReader, InputFormat},
+        ranges::{self, Boundary},
+        regex::RegexSplitter,
+    },
+    ChainedReader, LineReader, CHUNK_SIZE,
+};
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::iter::once;
+use std::mem;
+
+macro_rules! fail {
+    ($($t:tt)*) => {{
+        eprintln_ignore!($($t)*);
+        std::process::exit(1)
+    }}
+}
+
+#[derive(Clone)]
+struct PreludeScalars {
+    arbitrary_shell: bool,
+    safe_math: bool,
+    fold_regexes: bool,
+    parse_header: bool,
+    debug: bool,
+    escaper: Escaper,
+    stage: Stage<()>,
+}
+
+struct RawPrelude {
+    argv: Vec<String>,
+    // How many entries at the end of `argv` are arguments for the script (after `--`) rather
+    // than operands.
+    script_args: usize,
+    var_decs: Vec<String>,
+    field_sep: Option<String>,
+    output_sep: Option<&'static str>,
+    output_record_sep: Option<&'static str>,
+    output_template: Option<String>,
+    scalars: PreludeScalars,
+}
+
+struct Prelude<'a> {
+    var_decs: Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)>,
+    field_sep: Option<&'a [u8]>,
+    output_sep: Option<&'a [u8]>,
+    output_record_sep: Option<&'a [u8]>,
+    output_template: Option<&'a [u8]>,
+    argv: Vec<&'a str>,
+    argv_assignments: Vec<(usize, &'a [u8])>,
+    script_args: usize,
+    scalars: PreludeScalars,
+}
+
+// TODO: make file reading lazy
+fn open_file_read(f: &str) -> impl io::BufRead {
+    enum LazyReader<F, R> {
+        Uninit(F),
+        Init(R),
+    }
+
+    impl<R, F: FnMut() -> io::Result<R>> LazyReader<F, R> {
+        fn delegate<T>(&mut self, next: impl FnOnce(&mut R) -> io::Result<T>) -> io::Result<T> {
+            match self {
+                LazyReader::Uninit(f) => {
+                    *self = LazyReader::Init(f()?);
+                    self.delegate(next)
+                }
+                LazyReader::Init(r) => next(r),
+            }
+        }
+    }
+
+    // TODO: delegate other methods on read.
+    impl<R: io::Read, F: FnMut() -> io::Result<R>> io::Read for LazyReader<F, R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.delegate(|r| r.read(buf))
+        }
+    }
+
+    let filename = String::from(f);
+    BufReader::new(LazyReader::Uninit(move || -> io::Result<Box<dyn io::Read + Send>> {
+        let reader: Box<dyn io::Read + Send> = if filename.starts_with(runtime::encrypted::PREFIX) {
+            Box::new(runtime::encrypted::open_read(filename.as_str())?)
+        } else {
+            Box::new(File::open(filename.as_str())?)
+        };
+        Ok(runtime::encoding::decode_input(reader))
+    }))
+}
+
+/// Open a main-input file named in ARGV, where `-` is the standard input.
+fn open_argv_input(file: &str) -> Box<dyn io::Read + Send> {
+    if file == "-" {
+        Box::new(runtime::encoding::decode_input(Box::new(io::stdin())))
+    } else {
+        Box::new(open_file_read(file))
+    }
+}
+
+fn chained<LR: LineReader>(lr: LR) -> ChainedReader<LR> {
+    ChainedReader::new(std::iter::once(lr))
+}
+
+/// Read `input_files` in order, splitting records with a regex. With per-file parallelism the
+/// files instead form a queue that workers take whole files from.
+fn regex_splitters(
+    input_files: &[String],
+    chunk_size: usize,
+    check_utf8: bool,
+    exec_strategy: ExecutionStrategy,
+) -> ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>> {
+    let open = move |file: String| {
+        move || {
+            let reader: Box<dyn io::Read + Send> = Box::new(open_file_read(file.as_str()));
+            RegexSplitter::new(reader, chunk_size, file, check_utf8)
+        }
+    };
+    let files = input_files.iter().cloned();
+    match exec_strategy {
+        ExecutionStrategy::ShardPerFile => ChainedReader::sharded(files.map(open), check_utf8),
+        _ => ChainedReader::new(files.map(|file| open(file)())),
+    }
+}
+
+/// How `-p range` cuts input files up: for this many workers, with this chunk size, on this
+/// kind of record boundary.
+type RangeSplit = (usize, usize, Boundary);
+
+/// The main input files, for the parallel readers. With `-p range` a file is handed out as
+/// several ranges of records; files that cannot be split (encrypted or re-encoded input, pipes,
+/// files that fail to open) are read whole.
+fn input_handles(
+    input_files: Vec<String>,
+    split: Option<RangeSplit>,
+) -> impl Iterator<Item = (Box<dyn io::Read + Send>, String)> + Send + 'static {
+    let whole = |file: String| -> Vec<(Box<dyn io::Read + Send>, String)> {
+        vec![(Box::new(open_file_read(file.as_str())), file)]
+    };
+    input_files.into_iter().flat_map(move |file| {
+        let Some((workers, chunk_size, boundary)) = split else {
+            return whole(file);
+        };
+        if file.starts_with(runtime::encrypted::PREFIX) || runtime::encoding::decodes_input() {
+            return whole(file);
+        }
+        let opened = File::open(file.as_str())
+            .and_then(|mut f| {
+                if !f.metadata()?.is_file() {
+                    return Err(io::ErrorKind::Unsupported.into());
+                }
+                ranges::for_workers(&mut f, workers, chunk_size, boundary)
+            })
+            .and_then(|rs| {
+                rs.into_iter()
+                    .map(|r| ranges::open(file.as_str(), r))
+                    .collect::<io::Result<Vec<_>>>()
+            });
+        match opened {
+            Ok(readers) => readers
+                .into_iter()
+                .map(|r| (Box::new(r) as Box<dyn io::Read + Send>, file.clone()))
+                .collect(),
+            Err(_) => whole(file),
+        }
+    })
+}
+
+fn get_vars<'a, 'b>(
+    vars: impl Iterator<Item=&'b str>,
+    a: &'a Arena,
+    buf: &mut Vec<u8>,
+) -> Vec<(&'a str, &'a ast::Expr<'a, 'a, &'a str>)> {
+    let mut res = Vec::new();
+    let mut split_buf = Vec::new();
+    for var in vars {
+        buf.clear();
+        split_buf.clear();
+        split_buf.extend(var.splitn(2, '='));
+        if split_buf.len() != 2 {
+            fail!(
+                "received -v flag without an '=' sign: {} (split_buf={:?})",
+                var,
+                split_buf
+            );
+        }
+        // `name:type=value`, from -v:type (see `typed_vars`).
+        let (name, ty) = match split_buf[0].split_once(':') {
+            Some((name, ty)) => (name, Some(ty.trim())),
+            None => (split_buf[0], None),
+        };
+        let ident = a.alloc_str(name.trim());
+        if !lexer::is_ident(ident) {
+            fail!(
+                "invalid identifier for left-hand side of -v flag: {}",
+                ident
+            );
+        }
+        let str_lit = lexer::parse_string_literal(split_buf[1], a, buf);
+        let text = String::from_utf8_lossy(str_lit);
+        let exp = match ty {
+            None | Some("str") => ast::Expr::StrLit(str_lit),
+            Some("int") => match text.trim().parse::<i64>() {
+                Ok(i) => ast::Expr::ILit(i),
+                Err(_) => fail!("invalid value for -v:int {}: {:?}", ident, text),
+            },
+            Some("float") => match text.trim().parse::<f64>() {
+                Ok(f) => ast::Expr::FLit(f),
+                Err(_) => fail!("invalid value for -v:float {}: {:?}", ident, text),
+            },
+            Some(ty) => fail!(
+                "unknown type in -v:{} {} (expected int, float or str)",
+                ty,
+                ident
+            ),
+        };
+        res.push((ident, a.alloc(exp)))
+    }
+    res
+}
+
+// `-v:int x=5` gives a -v variable a type. clap would take `:int` as the value of -v, so this is
+// rewritten to `-v x:int=5` before parsing.
+fn typed_vars(args: Vec<String>) -> Vec<String> {
+    let mut res = Vec::with_capacity(args.len());
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            res.push(arg);
+            res.extend(iter);
+            break;
+        }
+        let ty = match arg.strip_prefix("-v:") {
+            Some(ty) => ty,
+            None => {
+                res.push(arg);
+                continue;
+            }
+        };
+        match iter.next() {
+            Some(dec) => {
+                res.push("-v".into());
+                res.push(match dec.split_once('=') {
+                    Some((name, val)) => format!("{}:{}={}", name, ty, val),
+                    None => dec,
+                });
+            }
+            None => res.push(arg),
+        }
+    }
+    res
+}
+
+fn get_prelude<'a>(a: &'a Arena, raw: &RawPrelude) -> Prelude<'a> {
+    let mut buf = Vec::new();
+    let output_sep = raw
+        .output_sep
+        .map(|s| lexer::parse_string_literal(s, a, &mut buf));
+    let output_record_sep = raw
+        .output_record_sep
+        .map(|s| lexer::parse_string_literal(s, a, &mut buf));
+    let field_sep = raw
+        .field_sep
+        .as_ref()
+        .map(|s| lexer::parse_string_literal(s.as_str(), a, &mut buf));
+    Prelude {
+        field_sep,
+        var_decs: get_vars(raw.var_decs.iter().map(|s| s.as_str()), a, &mut buf),
+        scalars: raw.scalars.clone(),
+        output_sep,
+        output_record_sep,
+        output_template: raw.output_template.as_ref().map(|s| a.alloc_bytes(s.as_bytes())),
+        argv: raw.argv.iter().map(|s| a.alloc_str(s.as_str())).collect(),
+        // Operands of the form `var=value` have their values unescaped, as with -v.
+        argv_assignments: raw
+            .argv
+            .iter()
+            .enumerate()
+            .take(raw.argv.len() - raw.script_args)
+            .skip(1)
+            .filter_map(|(i, arg)| {
+                let (_, value) = runtime::split_assignment(arg)?;
+                Some((i, lexer::parse_string_literal(value, a, &mut buf)))
+            })
+            .collect(),
+        script_args: raw.script_args,
+    }
+}
+
+fn parse_prog<'a>(
+    prog: &str,
+    a: &'a Arena,
+    prelude: &mut Prelude<'a>,
+) -> &'a ast::Prog<'a, 'a, &'a str> {
+    let prog = a.alloc_str(prog);
+    let lexer = lexer::Tokenizer::new(prog);
+    let mut buf = Vec::new();
+    let parser = parsing::syntax::ProgParser::new();
+    let mut prog = ast::Prog::from_stage(a, prelude.scalars.stage.clone());
+    prog.argv = mem::take(&mut prelude.argv);
+    prog.argv_assignments = mem::take(&mut prelude.argv_assignments);
+    prog.script_args = prelude.script_args;
+    match parser.parse(a, &mut buf, &mut prog, lexer) {
+        Ok(()) => {
+            prog.field_sep = prelude.field_sep;
+            prog.prelude_vardecs = mem::take(&mut prelude.var_decs);
+            prog.output_sep = prelude.output_sep;
+            prog.output_record_sep = prelude.output_record_sep;
+            prog.output_template = prelude.output_template;
+            prog.parse_header = prelude.scalars.parse_header;
+            prog.debug = prelude.scalars.debug;
+            a.alloc(prog)
+        }
+        Err(e) => {
+            fail!("{}", e);
+        }
+    }
+}
+
+fn get_context<'a>(
+    prog: &str,
+    a: &'a Arena,
+    mut prelude: Prelude<'a>,
+) -> cfg::ProgramContext<'a, &'a str> {
+    let stmt = parse_prog(prog, a, &mut prelude);
+    match cfg::ProgramContext::from_prog(a, stmt, prelude.scalars.escaper) {
+        Ok(mut ctx) => {
+            ctx.allow_arbitrary_commands = prelude.scalars.arbitrary_shell;
+            ctx.safe_math = prelude.scalars.safe_math;
+            ctx.fold_regex_constants = prelude.scalars.fold_regexes;
+            ctx
+        }
+        Err(e) => fail!("failed to create program context: {}", e),
+    }
+}
+
+// How the interpreter is followed as it runs the program.
+enum Monitor<'a> {
+    None,
+    // Run under the debugger; this is the text of the program.
+    Debug(&'a str),
+    // Count how often each statement runs: the text of the program, and the file to write the
+    // counts to.
+    Profile(&'a str, &'a str),
+}
+
+fn run_interp_with_context<'a>(
+    mut ctx: cfg::ProgramContext<'a, &'a str>,
+    stdin: impl LineReader,
+    ff: impl runtime::writers::FileFactory,
+    num_workers: usize,
+    monitor: Monitor,
+) {
+    let rc = {
+        let mut interp = match compile::bytecode(&mut ctx, stdin, ff, num_workers) {
+            Ok(ctx) => ctx,
+            Err(e) => fail!("bytecode compilation failure: {}", e),
+        };
+        match monitor {
+            Monitor::None => {}
+            Monitor::Debug(text) => interp.set_debugger(
+                text,
+                Box::new(BufReader::new(io::stdin())),
+                Box::new(io::stderr()),
+            ),
+            Monitor::Profile(text, out) => interp.set_profiler(text, out),
+        }
+        match interp.run() {
+            Err(e) => {
+                // Dropping the interpreter flushes what the program printed before the error.
+                mem::drop(interp);
+                fail!("fatal error during execution: {}", e)
+            }
+            Ok(0) => return,
+            Ok(n) => n,
+        }
+    };
+    std::process::exit(rc);
+}
+
+fn run_precompiled<'a>(
+    instrs: Vec<Vec<bytecode::Instr<'a>>>,
+    setup: &precompile::Setup,
+    argv: &[String],
+    stdin: impl LineReader,
+    ff: impl runtime::writers::FileFactory,
+) {
+    let rc = {
+        let mut interp = precompile::interp(instrs, setup, argv, stdin, ff);
+        match interp.run() {
+            Err(e) => {
+                // Dropping the interpreter flushes what the program printed before the error.
+                mem::drop(interp);
+                fail!("fatal error during execution: {}", e)
+            }
+            Ok(0) => return,
+            Ok(n) => n,
+        }
+    };
+    std::process::exit(rc);
+}
+
+#[cfg(feature = "jit")]
+fn run_cranelift_with_context<'a>(
+    mut ctx: cfg::ProgramContext<'a, &'a str>,
+    stdin: impl IntoRuntime,
+    ff: impl runtime::writers::FileFactory,
+    cfg: codegen::Config,
+    signal: CancelSignal,
+) {
+    if let Err(e) = compile::run_cranelift(&mut ctx, stdin, ff, cfg, signal) {
+        fail!("error compiling cranelift: {}", e)
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "llvm_backend")] {
+        fn run_llvm_with_context<'a>(
+            mut ctx: cfg::ProgramContext<'a, &'a str>,
+            stdin: impl IntoRuntime,
+            ff: impl runtime::writers::FileFactory,
+            cfg: codegen::Config,
+            signal: CancelSignal,
+        ) {
+            if let Err(e) = compile::run_llvm(&mut ctx, stdin, ff, cfg, signal) {
+                fail!("error compiling llvm: {}", e)
+            }
+        }
+
+        fn dump_llvm(prog: &str, cfg: codegen::Config, raw: &RawPrelude) -> String {
+            let a = Arena::default();
+            let mut ctx = get_context(prog, &a, get_prelude(&a, raw));
+            match compile::dump_llvm(&mut ctx, cfg) {
+                Ok(s) => s,
+                Err(e) => fail!("error compiling llvm: {}", e),
+            }
+        }
+
+    }
+}
+
+const DEFAULT_OPT_LEVEL: i32 = 3;
+
+fn output_format(
+    fmt: Option<&str>,
+    line_ending: Option<&str>,
+) -> (Escaper, Option<&'static str>, Option<&'static str>) {
+    let (escaper, output_sep, output_record_sep) = match fmt {
+        Some("csv") => (Escaper::CSV, Some(","), Some("\r\n")),
+        Some("tsv") => (Escaper::TSV, Some("\t"), Some("\n")),
+        // See output_template.
+        Some(s) if s.starts_with("template=") => (Escaper::Identity, None, None),
+        Some(s) => fail!(
+            "invalid output format {:?}; expected csv, tsv or template=TEMPLATE",
+            s
+        ),
+        None => (Escaper::Identity, None, None),
+    };
+    let output_record_sep = match line_ending {
+        Some("crlf") => Some("\r\n"),
+        Some("lf") => Some("\n"),
+        Some(s) => fail!("invalid line ending {:?}; expected crlf or lf", s),
+        None => output_record_sep,
+    };
+    (escaper, output_sep, output_record_sep)
+}
+
+// `-o template=T` renders each record printed from T; see cfg::Template.
+fn output_template(fmt: Option<&String>) -> Option<String> {
+    fmt?.strip_prefix("template=").map(String::from)
+}
+
+// `zawk compile`
+fn compile_to_file(matches: &clap::ArgMatches) {
+    let mut prog = include::Source::default();
+    let mut resolver = include::Resolver::new();
+    for pfile in matches.get_many::<String>("program-file").into_iter().flatten() {
+        match resolver.program_file(pfile) {
+            Ok(p) => prog.push(p),
+            Err(e) => fail!("{}", e),
+        }
+    }
+    let (escaper, output_sep, output_record_sep) =
+        output_format(
+            matches.get_one::<String>("output-format").map(|s| s.as_str()),
+            matches.get_one::<String>("line-ending").map(|s| s.as_str()),
+        );
+    let raw = RawPrelude {
+        // ARGV is set when the program is run.
+        argv: Vec::new(),
+        script_args: 0,
+        var_decs: matches.get_many::<String>("var").into_iter().flatten().cloned().collect(),
+        field_sep: matches.get_one::<String>("field-separator").cloned(),
+        output_sep,
+        output_record_sep,
+        output_template: output_template(matches.get_one::<String>("output-format")),
+        scalars: PreludeScalars {
+            escaper,
+            arbitrary_shell: matches.get_flag("arbitrary-shell"),
+            safe_math: matches.get_flag("safe-math"),
+            fold_regexes: true,
+            stage: ExecutionStrategy::Serial.stage(),
+            parse_header: matches.get_flag("parse-header"),
+            debug: false,
+        },
+    };
+    let a = Arena::default();
+    let mut ctx = get_context(prog.text.as_str(), &a, get_prelude(&a, &raw));
+    let bytes = match compile::precompile(&mut ctx).and_then(|p| p.to_bytes()) {
+        Ok(bytes) => bytes,
+        Err(e) => fail!("bytecode compilation failure: {}", e),
+    };
+    let out = matches.get_one::<String>("output").unwrap();
+    if let Err(e) = std::fs::write(out, bytes) {
+        fail!("failed to write {}: {}", out, e);
+    }
+}
+
+// `zawk fmt`
+fn format_programs(matches: &clap::ArgMatches) {
+    if let Some(text) = matches.get_one::<String>("source") {
+        match pretty::format(text) {
+            Ok(res) => print!("{}", res),
+            Err(e) => fail!("{}", e),
+        }
+        return;
+    }
+    let files: Vec<&String> = matches.get_many::<String>("files").into_iter().flatten().collect();
+    if files.is_empty() {
+        let mut text = String::new();
+        if let Err(e) = io::Read::read_to_string(&mut io::stdin(), &mut text) {
+            fail!("failed to read program from standard input: {}", e);
+        }
+        match pretty::format(&text) {
+            Ok(res) => print!("{}", res),
+            Err(e) => fail!("{}", e),
+        }
+        return;
+    }
+    let (write, check) = (matches.get_flag("write"), matches.get_flag("check"));
+    let mut unformatted = false;
+    for file in files {
+        let text = match std::fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => fail!("failed to read {}: {}", file, e),
+        };
+        let res = match pretty::format(&text) {
+            Ok(res) => res,
+            Err(e) => fail!("{}: {}", file, e),
+        };
+        if check {
+            if res != text {
+                println!("{}", file);
+                unformatted = true;
+            }
+        } else if write {
+            if res != text {
+                if let Err(e) = std::fs::write(file, res) {
+                    fail!("failed to write {}: {}", file, e);
+                }
+            }
+        } else {
+            print!("{}", res);
+        }
+    }
+    if unformatted {
+        std::process::exit(1);
+    }
+}
+
+// `zawk lint`
+fn lint_program(matches: &clap::ArgMatches) {
+    let mut resolver = include::Resolver::new();
+    let source = if let Some(pfiles) = matches.get_many::<String>("program-file") {
+        let mut prog = include::Source::default();
+        for pfile in pfiles {
+            match resolver.program_file(pfile) {
+                Ok(p) => prog.push(p),
+                Err(e) => fail!("{}", e),
+            }
+        }
+        prog
+    } else if let Some(p) = matches.get_one::<String>("program") {
+        match resolver.expand(p, "cmd. line") {
+            Ok(p) => p,
+            Err(e) => fail!("{}", e),
+        }
+    } else {
+        fail!("must specify program at command line, or in a file via -f");
+    };
+    let raw = RawPrelude {
+        argv: Vec::new(),
+        script_args: 0,
+        var_decs: matches.get_many::<String>("var").into_iter().flatten().cloned().collect(),
+        field_sep: None,
+        output_sep: None,
+        output_record_sep: None,
+        output_template: None,
+        scalars: PreludeScalars {
+            escaper: Escaper::Identity,
+            arbitrary_shell: false,
+            safe_math: false,
+            fold_regexes: true,
+            stage: ExecutionStrategy::Serial.stage(),
+            parse_header: false,
+            debug: false,
+        },
+    };
+    let a = Arena::default();
+    let mut prelude = get_prelude(&a, &raw);
+    let prog = parse_prog(source.text.as_str(), &a, &mut prelude);
+    let findings = lint::lint(prog);
+    // The type checker reports the errors that running the program would.
+    let checked = cfg::ProgramContext::from_prog(&a, prog, Escaper::Identity)
+        .and_then(|mut ctx| compile::context_compiles(&mut ctx));
+    let failed = checked.is_err() || !findings.is_empty();
+    if let Err(e) = checked {
+        println!("error: {}", e);
+    }
+    for f in findings {
+        match f.line {
+            Some(line) => println!("{}: warning: {}", source.map.describe(line), f.msg),
+            None => println!("warning: {}", f.msg),
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+fn print_schema(matches: &clap::ArgMatches) {
+    let files: Vec<String> = matches.get_many::<String>("input-files").into_iter().flatten().cloned().collect();
+    let ifmt = match matches.get_one::<String>("input-format").map(|s| s.as_str()) {
+        Some("tsv") => InputFormat::TSV,
+        Some(_) => InputFormat::CSV,
+        None => match files.first().and_then(|f| std::path::Path::new(f).extension()) {
+            Some(ext) if ext.eq_ignore_ascii_case("tsv") => InputFormat::TSV,
+            _ => InputFormat::CSV,
+        },
+    };
+    let sample = match matches.get_one::<String>("sample").map(|s| s.parse::<usize>()) {
+        Some(Ok(n)) => n,
+        Some(Err(e)) => fail!("value of 'sample' flag must be numeric: {}", e),
+        None => 10_000,
+    };
+    let header = !matches.get_flag("no-header");
+    let schema = if files.is_empty() {
+        let inp = CSVReader::new(
+            once((runtime::encoding::decode_input(Box::new(io::stdin())), String::from("-"))),
+            ifmt,
+            CHUNK_SIZE,
+            false,
+            ExecutionStrategy::Serial,
+            CancelSignal::default(),
+        );
+        schema::sniff(inp, header, sample)
+    } else {
+        let inp = CSVReader::new(
+            files.into_iter().map(|f| (open_file_read(f.as_str()), f)),
+            ifmt,
+            CHUNK_SIZE,
+            false,
+            ExecutionStrategy::Serial,
+            CancelSignal::default(),
+        );
+        schema::sniff(inp, header, sample)
+    };
+    let schema = schema.unwrap_or_else(|e| fail!("failed to read input: {}", e));
+    let stdout = io::stdout().lock();
+    let res = match matches.get_one::<String>("output-format").map(|s| s.as_str()) {
+        Some(fmt) => schema.write_delimited(stdout, fmt == "tsv"),
+        None => schema.write_table(stdout),
+    };
+    if let Err(e) = res {
+        fail!("failed to write report: {}", e);
+    }
+}
+
+#[cfg(feature = "jit")]
+fn dump_clif(prog: &str, cfg: codegen::Config, raw: &RawPrelude) -> String {
+    let a = Arena::default();
+    let mut ctx = get_context(prog, &a, get_prelude(&a, raw));
+    match compile::dump_clif(&mut ctx, cfg) {
+        Ok(s) => s,
+        Err(e) => fail!("error compiling cranelift: {}", e),
+    }
+}
+
+fn dump_bytecode(prog: &str, raw: &RawPrelude) -> String {
+    use std::io::Cursor;
+    let a = Arena::default();
+    let mut ctx = get_context(prog, &a, get_prelude(&a, raw));
+    let fake_inp: Box<dyn io::Read + Send> = Box::new(Cursor::new(vec![]));
+    let interp = match compile::bytecode(
+        &mut ctx,
+        chained(CSVReader::new(
+            once((fake_inp, String::from("unused"))),
+            InputFormat::CSV,
+            CHUNK_SIZE,
+            /*check_utf8=*/ false,
+            ExecutionStrategy::Serial,
+            Default::default(),
+        )),
+        runtime::writers::default_factory(),
+        /*num_workers=*/ 1,
+    ) {
+        Ok(ctx) => ctx,
+        Err(e) => fail!("bytecode compilation failure: {}", e),
+    };
+    let mut v = Vec::<u8>::new();
+    for (i, func) in interp.instrs().iter().enumerate() {
+        writeln!(&mut v, "function {} {{", i).unwrap();
+        for (j, inst) in func.iter().enumerate() {
+            writeln!(&mut v, "\t[{:2}] {:?}", j, inst).unwrap();
+        }
+        writeln!(&mut v, "}}\n").unwrap();
+    }
+    String::from_utf8(v).unwrap()
+}
+
+/// The `zawk` command line: parse the arguments of the process and run the program they name.
+pub fn run() {
+    //.env load support
+    dotenv::dotenv().ok();
+    let dump_cmd = Command::new("dump").about("Dump text to CSV")
+        .arg(Arg::new("prometheus")
+            .long("prometheus")
+            .num_args(0)
+            .help("Parse Prometheus metrics to CSV")
+        )
+        .arg(Arg::new("input-file")
+            .index(1)
+            .required(true)
+            .help("Text file or URL to parse")
+        );
+    let query_cmd = Command::new("query").about("Run a SQL-like query: select ... [from FILE] [where ...] [group by ...] [limit N]")
+        .arg(Arg::new("query")
+            .index(1)
+            .required(true)
+            .help("The query, e.g. 'select $1, sum($3) group by $1'")
+        )
+        .arg(Arg::new("input-files")
+            .index(2)
+            .num_args(1..)
+            .help("Input files; a .csv or .tsv extension selects the input and output format")
+        );
+    let schema_cmd = Command::new("schema").about("Report the inferred type, null rate, distinct count, range and example values of each column of a CSV or TSV file")
+        .arg(Arg::new("input-files")
+            .index(1)
+            .num_args(1..)
+            .help("Input files, read from standard input if none are given"))
+        .arg(Arg::new("input-format")
+            .long("input-format")
+            .short('i')
+            .value_name("csv|tsv")
+            .help("Format of the input; by default TSV for files ending in .tsv and CSV otherwise")
+            .value_parser(["csv", "tsv"]))
+        .arg(Arg::new("sample")
+            .long("sample")
+            .short('n')
+            .num_args(1)
+            .value_name("ROWS")
+            .help("Read at most ROWS rows after the header, or every row if 0 [default: 10000]"))
+        .arg(Arg::new("no-header")
+            .long("no-header")
+            .num_args(0)
+            .help("The first row is data; columns are named $1, $2, ..."))
+        .arg(Arg::new("output-format")
+            .long("output-format")
+            .value_name("csv|tsv")
+            .help("Print one row per column in this format instead of a table")
+            .value_parser(["csv", "tsv"]));
+    let lint_cmd = Command::new("lint").about("Check a program for likely mistakes without running it: unused variables and functions, variables that are never assigned, ignored assignments to NF, string comparisons of numbers and unreachable code")
+        .arg(Arg::new("program")
+            .index(1)
+            .help("The program to check, unless -f is given"))
+        .arg(Arg::new("program-file")
+            .short('f')
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("FILE")
+            .help("Check the program in FILE; may be given more than once"))
+        .arg(Arg::new("var")
+            .short('v')
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("var=val")
+            .help("Treat <var> as assigned, as when the program is run with -v"));
+    let fmt_cmd = Command::new("fmt").about("Print programs with consistent indentation and spacing: one statement per line, braces around every body and four spaces per level. Comments, blank lines between statements and @include directives are kept")
+        .arg(Arg::new("files")
+            .index(1)
+            .num_args(1..)
+            .help("The program files to format, read from standard input if none are given"))
+        .arg(Arg::new("source")
+            .long("source")
+            .short('e')
+            .num_args(1)
+            .value_name("PROGRAM")
+            .conflicts_with("files")
+            .help("Format PROGRAM, such as a one-liner, instead of a file"))
+        .arg(Arg::new("write")
+            .long("write")
+            .short('w')
+            .num_args(0)
+            .requires("files")
+            .help("Rewrite the files in place instead of printing them"))
+        .arg(Arg::new("check")
+            .long("check")
+            .num_args(0)
+            .requires("files")
+            .conflicts_with("write")
+            .help("Print the names of the files that are not formatted, and exit with status 1 if there are any"));
+    // `zawk debug` is rewritten to `zawk --debug` before the arguments are parsed; this is here
+    // for the help text.
+    let debug_cmd = Command::new("debug").about("Run a program under an interactive debugger, which reads commands such as `break`, `step`, `print` and `watch` from standard input")
+        .arg(Arg::new("args")
+            .num_args(0..)
+            .trailing_var_arg(true)
+            .allow_hyphen_values(true)
+            .value_name("PROGRAM-FILE [OPTIONS] [FILES]")
+            .help("The program file, followed by the options and input files to run it with"));
+    let compile_cmd = Command::new("compile").about("Compile a program to bytecode, which `zawk -f FILE` runs without parsing or type-checking it again")
+        .arg(Arg::new("program-file")
+            .index(1)
+            .num_args(1..)
+            .required(true)
+            .help("The program files to compile, found like those passed to -f"))
+        .arg(Arg::new("output")
+            .long("output")
+            .short('o')
+            .num_args(1)
+            .required(true)
+            .value_name("FILE")
+            .help("Write the compiled program to FILE, conventionally named with a .zbc extension"))
+        .arg(Arg::new("var")
+            .short('v')
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("var=val")
+            .help("Assign the value <val> to the variable <var> in the compiled program"))
+        .arg(Arg::new("field-separator")
+            .long("field-separator")
+            .short('F')
+            .num_args(1)
+            .value_name("FS")
+            .help("Field separator `FS` for the compiled program"))
+        .arg(Arg::new("parse-header")
+            .long("parse-header")
+            .short('H')
+            .num_args(0)
+            .help("Consume the first line of input and populate the `FI` variable with column names mapping to column indexes"))
+        .arg(Arg::new("output-format")
+            .long("output-format")
+            .value_name("csv|tsv|template=T")
+            .help("If set, records output via print are escaped according to the rules of the corresponding format. With template=TEMPLATE, each record printed is rendered from TEMPLATE, where {N} is the Nth value printed (the Nth column for a bare print) and {name} the column named name with -H"))
+        .arg(Arg::new("line-ending")
+            .long("line-ending")
+            .value_name("crlf|lf")
+            .help("End each record printed with CRLF or LF, setting ORS. `-o csv` ends records with CRLF otherwise")
+            .value_parser(["crlf", "lf"]))
+        .arg(Arg::new("arbitrary-shell")
+            .short('A')
+            .long("arbitrary-shell")
+            .num_args(0)
+            .help("Allow strings passed to the shell to contain user input"))
+        .arg(Arg::new("safe-math")
+            .long("safe-math")
+            .num_args(0)
+            .help("Report division by zero and integer overflow in ERRNO instead of stopping the program"));
+    #[allow(unused_mut)]
+        let mut app = Command::new("zawk")
+        .version(builtins::VERSION)
+        .author("Eli R, linux_china")
+        .about("zawk is an AWK language implementation by Rust with stdlib support")
+        .subcommand(dump_cmd)
+        .subcommand(query_cmd)
+        .subcommand(schema_cmd)
+        .subcommand(lint_cmd)
+        .subcommand(fmt_cmd)
+        .subcommand(debug_cmd)
+        .subcommand(compile_cmd)
+        .arg(Arg::new("program-file")
+            .long("program-file")
+            .short('f')
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .help("Read the program source from the file/url program-file, instead of from the command line. Multiple '-f' options may be used"))
+        .arg(Arg::new("exec")
+            .long("exec")
+            .short('E')
+            .num_args(1)
+            .value_name("program-file")
+            .help("Like -f, but the last option processed: all remaining arguments are passed to the program. Intended for #! scripts"))
+        .arg(Arg::new("opt-level")
+            .long("opt-level")
+            .short('O')
+            .num_args(1)
+            .allow_hyphen_values(true)
+            .help("The optimization level for the program. Positive levels determine the optimization level for LLVM. Level `-1` forces bytecode interpretation")
+            .value_parser(["-1", "0", "1", "2", "3"]))
+        .arg(Arg::new("out-file")
+            .long("out-file")
+            .num_args(1)
+            .value_name("FILE")
+            .help("Write to specified output file instead of standard output"))
+        .arg(Arg::new("tee")
+            .long("tee")
+            .num_args(1)
+            .value_name("FILE")
+            .conflicts_with("out-file")
+            .help("Copy standard output to the specified file as well"))
+        .arg(Arg::new("line-buffered")
+            .long("line-buffered")
+            .num_args(0)
+            .help("Flush standard output after every line, even when it is not a terminal, and handle each input record as soon as it is read. Useful when piping the output of a long-running zawk into another program"))
+        .arg(Arg::new("unbuffered")
+            .long("unbuffered")
+            .num_args(0)
+            .help("Process each record as soon as it is read and flush every write, rather than reading and writing in large batches. Useful in the middle of a streaming pipeline such as `tail -f log | zawk ... | ...`, at some cost in throughput"))
+        .arg(Arg::new("no-clobber")
+            .long("no-clobber")
+            .num_args(0)
+            .help("Fail rather than overwrite an existing file with `print > file` or --out-file. Appending with `>>` is still allowed"))
+        .arg(Arg::new("lock-output")
+            .long("lock-output")
+            .num_args(0)
+            .help("Lock output files while writing to them and always append, so that zawk processes writing to the same file at once do not interleave partial lines"))
+        .arg(Arg::new("atomic-writes")
+            .long("atomic-writes")
+            .num_args(0)
+            .conflicts_with("parallel-strategy")
+            .help("Write each output file to a temporary file that replaces it once the file is closed or the program succeeds, so that a failing program never leaves a partly-written file behind. Individual files can be written this way by naming them atomic://FILE"))
+        .arg(Arg::new("manifest")
+            .long("manifest")
+            .num_args(1)
+            .value_name("FILE")
+            .help("On exit, write a JSON manifest with the row count and sha256 of every output file to FILE"))
+        .arg(Arg::new("pipe-buffer")
+            .long("pipe-buffer")
+            .num_args(1)
+            .value_name("SIZE")
+            .help("Size of the output batches sent to commands in `print | \"cmd\"` (e.g. 4K, 1M; default 64K)"))
+        .arg(Arg::new("pipe-queue")
+            .long("pipe-queue")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(clap::value_parser!(usize))
+            .help("Number of batches that may be queued for a command before writes block (default 8)"))
+        .arg(Arg::new("pipe-timeout")
+            .long("pipe-timeout")
+            .num_args(1)
+            .value_name("DURATION")
+            .help("Fail with an error instead of blocking longer than DURATION (e.g. 30s, 5m) on a command that is not reading its input"))
+        .arg(Arg::new("pipe-stats")
+            .long("pipe-stats")
+            .num_args(0)
+            .help("Print per-command pipe statistics (bytes, batches, time spent blocked) to standard error on exit"))
+        .arg(Arg::new("round-mode")
+            .long("round-mode")
+            .num_args(1)
+            .value_parser(["exact", "half-up", "half-even"])
+            .help("How round() and printf precisions round ties: exact (the default, printf rounds the binary value as C does), half-up or half-even (banker's rounding)"))
+        .arg(Arg::new("bignum")
+            .long("bignum")
+            .short('M')
+            .num_args(0)
+            .help("Compute with arbitrary-precision numbers: integers of any size are exact, and division keeps 34 significant digits"))
+        .arg(Arg::new("use-lc-numeric")
+            .long("use-lc-numeric")
+            .short('N')
+            .num_args(0)
+            .help("Print floats in printf and sprintf with the decimal point of the locale (LC_ALL, LC_NUMERIC or LANG)"))
+        .arg(Arg::new("input-encoding")
+            .long("input-encoding")
+            .num_args(1)
+            .value_name("ENCODING")
+            .help("Convert the input, and files read with getline, from ENCODING (such as gbk, shift_jis or latin1) to UTF-8 before splitting it into records"))
+        .arg(Arg::new("unicode")
+            .long("unicode")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("chars")
+            .value_parser(["chars", "graphemes"])
+            .help("Make length, substr, index, match, char_at, toupper and tolower all work on characters, or on grapheme clusters with --unicode=graphemes (also set by UNICODE=1 in the environment)"))
+        .arg(Arg::new("load")
+            .long("load")
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("LIBRARY")
+            .help("Load builtin functions from a shared library exporting zawk_init; may be repeated"))
+        .arg(Arg::new("stats-mem")
+            .long("stats-mem")
+            .num_args(0)
+            .help("Print memory statistics (peak RSS, heap, string buffers and maps) to standard error on exit"))
+        .arg(Arg::new("intern-keys")
+            .long("intern-keys")
+            .num_args(0)
+            .help("Store each distinct string used as an array key once, shared by every array that holds it. Lowers memory use for large group-by aggregations with many repeated keys"))
+        .arg(Arg::new("str-arena")
+            .long("str-arena")
+            .num_args(0)
+            .help("Allocate short-lived strings, such as those built for each record by concatenation or string functions, from blocks that are reused once the strings are dropped. Speeds up string-heavy scripts. Needs a build with the str_arena feature"))
+        .arg(Arg::new("utf8")
+            .long("utf8")
+            .num_args(0)
+            .help("Validate all input as UTF-8, returning an error if it is invalid"))
+        .arg(Arg::new("dump-cfg")
+            .long("dump-cfg")
+            .num_args(0)
+            .help("Print untyped SSA form for input program"))
+        .arg(Arg::new("explain-parallel")
+            .long("explain-parallel")
+            .num_args(0)
+            .help("Explain how the input program would behave when run in parallel with -pr or -pf, without running it"))
+        .arg(Arg::new("dump-bytecode")
+            .long("dump-bytecode")
+            .num_args(0)
+            .help("Print bytecode for input program"))
+        .arg(Arg::new("emit")
+            .long("emit")
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("ast|ir|bytecode|clif|llvm")
+            .value_parser(["ast", "ir", "bytecode", "clif", "llvm"])
+            .help("Print what the compiler produced for the program instead of running it: the syntax tree, the untyped SSA form, interpreter bytecode, Cranelift IR or LLVM-IR. May be repeated"))
+        .arg(Arg::new("parse-header")
+            .long("parse-header")
+            .short('H')
+            .num_args(0)
+            .help("Consume the first line of input and populate the `FI` variable with column names mapping to column indexes"))
+        .arg(Arg::new("input-format")
+            .long("input-format")
+            .short('i')
+            .value_name("csv|tsv|avro|inplace")
+            .help("Input is split according to the rules of (csv|tsv). $0 contains the unescaped line. Assigning to a column rebuilds $0 with OFS. `-i avro` reads Avro object container files, one record per line. `-i inplace` is the same as --inplace, as in gawk")
+            .value_parser(["csv", "tsv", "avro", "inplace"]))
+        .arg(Arg::new("inplace")
+            .long("inplace")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("")
+            .value_name("SUFFIX")
+            .conflicts_with_all(["out-file", "tee", "parallel-strategy"])
+            .help("Edit the input files in place: standard output written while reading each file replaces its contents once the file has been read to the end. With SUFFIX, the original is kept as a backup in the file name with SUFFIX appended"))
+        .arg(Arg::new("var")
+            .short('v')
+            .num_args(1)
+            .action(clap::ArgAction::Append)
+            .value_name("var=val")
+            .help("Assign the value <val> to the variable <var>, before execution of the frawk program begins. Multiple '-v' options may be used. Write -v:int or -v:float to assign a number rather than a string"))
+        .arg(Arg::new("null")
+            .long("null")
+            .short('0')
+            .num_args(0)
+            .help("Records are separated by NUL bytes, as produced by `find -print0`. Sets both RS and ORS to \"\\0\""))
+        .arg(Arg::new("field-separator")
+            .long("field-separator")
+            .short('F')
+            .num_args(1)
+            .value_name("FS")
+            .help("Field separator `FS` for frawk program"))
+        .arg(Arg::new("backend")
+            .long("backend")
+            .short('B')
+            .help("The backend used to run the frawk program, ranging from fastest to compile and slowest to execute, and slowest to compile and fastest to execute. Cranelift is the default, or the interpreter when zawk is built without the `jit` feature")
+            .value_parser(["interp", "cranelift", "llvm"]))
+        .arg(Arg::new("output-format")
+            .long("output-format")
+            .short('o')
+            .value_name("csv|tsv|template=T")
+            .help("If set, records output via print are escaped according to the rules of the corresponding format. With template=TEMPLATE, each record printed is rendered from TEMPLATE, where {N} is the Nth value printed (the Nth column for a bare print) and {name} the column named name with -H"))
+        .arg(Arg::new("line-ending")
+            .long("line-ending")
+            .value_name("crlf|lf")
+            .help("End each record printed with CRLF or LF, setting ORS. `-o csv` ends records with CRLF otherwise")
+            .conflicts_with("null")
+            .value_parser(["crlf", "lf"]))
+        .arg(Arg::new("program")
+            .index(1)
+            .help("The frawk program to execute"))
+        .arg(Arg::new("input-files")
+            .index(2)
+            .num_args(1..)
+            .help("Input files to be read by frawk program"))
+        .arg(Arg::new("parallel-strategy")
+            .short('p')
+            .help("Attempt to execute the script in parallel. Strategy r[ecord] parallelizes within the current input file. Strategy f[ile] parallelizes between input files. Strategy range splits each input file into ranges of records that workers read independently, which suits single large files")
+            .value_parser(["r", "record", "f", "file", "range"]))
+        .arg(Arg::new("head")
+            .long("head")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(clap::value_parser!(u64))
+            .help("Stop reading input after the first N records (not counting the header with -H), then run the END block"))
+        .arg(Arg::new("select")
+            .long("select")
+            .num_args(1)
+            .value_name("COLUMNS")
+            .conflicts_with("program-file")
+            .help("Instead of running a program, print the given columns of each record, as with the `select` function: a comma-separated list of column numbers, header names (with -H) and ranges like `2-4` or `3-`. The first positional argument is then an input file"))
+        .arg(Arg::new("chunk-size")
+            .long("chunk-size")
+            .num_args(1)
+            .help("Buffer size when reading input. This is present primarily for debugging purposes; it's possible that tuning this will help performance, but it should not be necessary"))
+        .arg(Arg::new("arbitrary-shell")
+            .short('A')
+            .long("arbitrary-shell")
+            .num_args(0)
+            .help("By default, strings that are passed to the shell via pipes or the 'system' function are restricted from potentially containing user input. This flag bypasses that check, for the cases where such a use is known to be safe"))
+        .arg(Arg::new("safe-math")
+            .long("safe-math")
+            .num_args(0)
+            .help("Division by zero gives NaN and integer overflow wraps around, setting ERRNO, instead of stopping the program"))
+        .arg(Arg::new("debug")
+            .long("debug")
+            .num_args(0)
+            .hide(true)
+            .help("Run the program under the debugger; see `zawk debug`"))
+        .arg(Arg::new("profile")
+            .long("profile")
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("awkprof.out")
+            .value_name("FILE")
+            .help("Count how many times each statement of the program runs, and write the program with the counts to FILE (awkprof.out by default) when it exits. Profiled programs run on the bytecode interpreter"))
+        .arg(Arg::new("strict")
+            .long("strict")
+            .num_args(0)
+            .help("Stop the program when a builtin fails, such as char_at(s, 0) or a failed s3_get, instead of setting ERRNO and returning an empty value"))
+        .arg(Arg::new("ordered-arrays")
+            .long("ordered-arrays")
+            .num_args(0)
+            .help("Keep arrays in the order their keys were added, even after deleting elements. Deleting from such an array takes time proportional to its size"))
+        .arg(Arg::new("jobs")
+            .short('j')
+            .requires("parallel-strategy")
+            .num_args(1)
+            .help("Number or worker threads to launch when executing in parallel, requires '-p' flag to be set. When using record-level parallelism, this value is an upper bound on the number of worker threads that will be spawned; the number of active worker threads is chosen dynamically"));
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "llvm_backend")] {
+            app = app.arg(Arg::new("dump-llvm")
+             .long("dump-llvm")
+             .num_args(0)
+             .help("Print LLVM-IR for the input program"));
+        }
+    }
+    let mut args: Vec<String> = std::env::args().collect();
+    if args.get(1).is_some_and(|a| a == "debug") {
+        // zawk debug prog.awk ... => zawk --debug -f prog.awk ...
+        args[1] = "--debug".into();
+        if args.get(2).is_some_and(|a| !a.starts_with('-')) {
+            args.insert(2, "-f".into());
+        }
+    }
+    let (args, script_args) = match script_flags::expand(args) {
+        Ok(script_flags::Expanded::Args { args, script_args }) => (typed_vars(args), script_args),
+        Ok(script_flags::Expanded::Help(help)) => {
+            print!("{}", help);
+            return;
+        }
+        Err(e) => fail!("{}", e),
+    };
+    let matches = app.get_matches_from(args);
+    if matches.get_flag("stats-mem") {
+        runtime::mem_stats::enable();
+    }
+    runtime::map_key::set_intern_keys(matches.get_flag("intern-keys"));
+    if matches.get_flag("str-arena") {
+        if !cfg!(feature = "str_arena") {
+            fail!("--str-arena is not available: zawk was built without the `str_arena` feature");
+        }
+        runtime::str_arena::set_enabled(true);
+    }
+    if let Some(mode) = matches.get_one::<String>("round-mode") {
+        let mode = runtime::rounding::RoundMode::from_name(mode).expect("validated by clap");
+        runtime::rounding::set_mode(mode);
+    }
+    runtime::locale::set_use_lc_numeric(matches.get_flag("use-lc-numeric"));
+    if matches.get_flag("bignum") {
+        if !cfg!(feature = "bignum") {
+            fail!("-M is not available: zawk was built without the `bignum` feature");
+        }
+        runtime::bignum::enable();
+    }
+    if let Some(encoding) = matches.get_one::<String>("input-encoding") {
+        if let Err(e) = runtime::encoding::set_input_encoding(encoding) {
+            fail!("--input-encoding: {}", e);
+        }
+    }
+    let unicode = matches.get_one::<String>("unicode").cloned().or_else(|| std::env::var("UNICODE").ok());
+    if let Some(units) = unicode.as_deref().and_then(runtime::str_impl::TextUnits::from_name) {
+        runtime::str_impl::set_text_units(units);
+    }
+    let libs: Vec<String> = matches.get_many::<String>("load").into_iter().flatten().cloned().collect();
+    if !libs.is_empty() {
+        if let Err(e) = runtime::ext::load(&libs) {
+            fail!("{}", e);
+        }
+    }
+    runtime::errors::set_strict(matches.get_flag("strict"));
+    runtime::set_ordered_arrays(matches.get_flag("ordered-arrays"));
+    if let Some(matches) = matches.subcommand_matches("compile") {
+        compile_to_file(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("schema") {
+        print_schema(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("lint") {
+        lint_program(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("fmt") {
+        format_programs(matches);
+        return;
+    }
+    // dump sub command
+    if let Some(matches) = matches.subcommand_matches("dump") {
+        let input_file = matches.get_one::<String>("input-file").unwrap();
+        if matches.get_flag("prometheus") {
+            let text = runtime::csv::parse_prometheus(input_file);
+            println!("{}", text);
+        }
+        return;
+    }
+    let query = matches.subcommand_matches("query").map(|qm| {
+        let q = qm.get_one::<String>("query").unwrap();
+        let mut query = query::compile(q).unwrap_or_else(|e| fail!("invalid query: {}", e));
+        let files: Vec<String> = qm
+            .get_many::<String>("input-files")
+            .map(|x| x.map(String::from).collect())
+            .unwrap_or_default();
+        if !files.is_empty() && query.from.is_some() {
+            fail!("query has a `from` clause, but input files were also given");
+        }
+        let files = query.from.take().map(|f| vec![f]).unwrap_or(files);
+        (query, files)
+    });
+    // Queries over .csv/.tsv files read and write that format unless told otherwise.
+    let query_fmt = query.as_ref().and_then(|(_, files)| {
+        let ext = std::path::Path::new(files.first()?).extension()?;
+        if ext.eq_ignore_ascii_case("csv") {
+            Some("csv")
+        } else if ext.eq_ignore_ascii_case("tsv") {
+            Some("tsv")
+        } else {
+            None
+        }
+    });
+    let ifmt = match matches
+        .get_one::<String>("input-format")
+        .map(|s| s.as_str())
+        .or(query_fmt)
+    {
+        Some("csv") => Some(InputFormat::CSV),
+        Some("tsv") => Some(InputFormat::TSV),
+        Some("avro") | Some("inplace") | None => None,
+        Some(x) => fail!("invalid input format: {}", x),
+    };
+    let avro = matches.get_one::<String>("input-format").is_some_and(|f| f == "avro");
+    if (ifmt.is_some() || avro) && matches.get_one::<String>("field-separator").is_some() {
+        fail!("the argument '--field-separator' cannot be used with '--input-format'");
+    }
+    let exec_strategy = match matches.get_one::<String>("parallel-strategy").map(|s| s.as_str()) {
+        Some("r") | Some("record") => ExecutionStrategy::ShardPerRecord,
+        Some("f") | Some("file") | Some("range") => ExecutionStrategy::ShardPerFile,
+        None => ExecutionStrategy::Serial,
+        Some(x) => fail!(
+            "invalid execution strategy (clap arg parsing should handle this): {}",
+            x
+        ),
+    };
+    let split_ranges = matches
+        .get_one::<String>("parallel-strategy")
+        .is_some_and(|s| s == "range");
+
+    let inplace = match matches.get_one::<String>("inplace") {
+        Some(suffix) => Some(suffix.clone()),
+        None if matches.get_one::<String>("input-format").is_some_and(|f| f == "inplace") => {
+            Some(String::new())
+        }
+        None => None,
+    };
+    if avro && !matches!(exec_strategy, ExecutionStrategy::Serial) {
+        fail!("Avro files can only be read serially");
+    }
+    if let Some(suffix) = &inplace {
+        if !matches!(exec_strategy, ExecutionStrategy::Serial) {
+            fail!("files can only be edited in place when reading serially");
+        }
+        runtime::writers::set_inplace(Some(suffix.clone()).filter(|s| !s.is_empty()));
+    }
+
+    if matches.get_flag("line-buffered") {
+        runtime::writers::set_line_buffered();
+        runtime::splitter::set_unbuffered();
+    }
+    if matches.get_flag("unbuffered") {
+        runtime::writers::set_unbuffered();
+        runtime::splitter::set_unbuffered();
+    }
+    if matches.get_flag("atomic-writes") {
+        runtime::writers::set_atomic_writes();
+    }
+
+    // NB: do we want this to be a command-line param?
+    let chunk_size = if let Some(cs) = matches.get_one::<String>("chunk-size") {
+        match cs.parse::<usize>() {
+            Ok(u) => u,
+            Err(e) => fail!("value of 'chunk-size' flag must be numeric: {}", e),
+        }
+    } else {
+        CHUNK_SIZE
+    };
+    let num_workers = match matches.get_one::<String>("jobs") {
+        Some(s) => match s.parse::<usize>() {
+            Ok(u) => u,
+            Err(e) => fail!("value of 'jobs' flag must be numeric: {}", e),
+        },
+        None => exec_strategy.num_workers(),
+    };
+    let mut input_files: Vec<String> = match &query {
+        Some((_, files)) => files.clone(),
+        None => matches
+            .get_many::<String>("input-files")
+            .map(|x| x.map(String::from).collect())
+            .unwrap_or_default(),
+    };
+    let mut precompiled = None;
+    let (mut program_string, mut sources) = {
+        if let Some((query, _)) = &query {
+            (query.program.clone(), SourceMap::default())
+        } else if let Some(spec) = matches.get_one::<String>("select") {
+            // As with -f, the "program" is another input file. select already escapes the
+            // columns for -o, so it is written with printf rather than print.
+            if let Some(p) = matches.get_one::<String>("program") {
+                input_files.insert(0, p.into());
+            }
+            let prog = format!("{{ printf \"%s%s\", select({}), ORS }}", query::quote(spec));
+            (prog, SourceMap::default())
+        } else if let Some(pfiles) = matches.get_many::<String>("program-file") {
+            // We specified a file on the command line, so the "program" will be
+            // interpreted as another input file.
+            if let Some(p) = matches.get_one::<String>("program") {
+                input_files.insert(0, p.into());
+            }
+            let mut prog = include::Source::default();
+            let mut resolver = include::Resolver::new();
+            for pfile in pfiles {
+                let text = if precompile::is_precompiled(pfile) {
+                    let loaded = std::fs::read(pfile)
+                        .map_err(|e| format!("failed to read program from {}: {}", pfile, e))
+                        .and_then(|bytes| {
+                            precompile::Program::from_bytes(&bytes).map_err(|e| e.to_string())
+                        });
+                    match loaded {
+                        Ok(p) => precompiled = Some(p),
+                        Err(e) => fail!("{}: {}", pfile, e),
+                    }
+                    continue;
+                } else if pfile.starts_with("https://") || pfile.starts_with("http://") {
+                    #[cfg(not(feature = "net"))]
+                    fail!("failed to read program from {}: zawk was built without the `net` feature", pfile);
+                    #[cfg(feature = "net")]
+                    match reqwest::blocking::get(pfile).unwrap().text() {
+                        Ok(p) => resolver.expand(p.as_str(), pfile),
+                        Err(e) => fail!("failed to read program from {}: {}", pfile, e),
+                    }
+                } else {
+                    resolver.program_file(pfile)
+                };
+                match text {
+                    Ok(p) => prog.push(p),
+                    Err(e) => fail!("{}", e),
+                }
+            }
+            if precompiled.is_some() && !prog.text.trim().is_empty() {
+                fail!("a precompiled program cannot be combined with other program files");
+            }
+            (prog.text, prog.map)
+        } else if let Some(p) = matches.get_one::<String>("program") {
+            // Named as in gawk's error messages.
+            match include::Resolver::new().expand(p, "cmd. line") {
+                Ok(p) => (p.text, p.map),
+                Err(e) => fail!("{}", e),
+            }
+        } else {
+            fail!("must specify program at command line, or in a file via -f");
+        }
+    };
+    let debug = matches.get_flag("debug");
+    if debug {
+        if precompiled.is_some() {
+            fail!("zawk debug needs the source of the program, not a precompiled one");
+        }
+        if !matches!(exec_strategy, ExecutionStrategy::Serial) {
+            fail!("zawk debug does not support parallel execution");
+        }
+        if matches.get_one::<String>("backend").is_some_and(|b| b != "interp") {
+            fail!("zawk debug always runs programs on the bytecode interpreter");
+        }
+    }
+    let profile = matches.get_one::<String>("profile");
+    if profile.is_some() {
+        if debug {
+            fail!("--profile cannot be used with zawk debug");
+        }
+        if precompiled.is_some() {
+            fail!("--profile needs the source of the program, not a precompiled one");
+        }
+        if !matches!(exec_strategy, ExecutionStrategy::Serial) {
+            fail!("--profile does not support parallel execution");
+        }
+        if matches.get_one::<String>("backend").is_some_and(|b| b != "interp") {
+            fail!("--profile always runs programs on the bytecode interpreter");
+        }
+        // Run the program as `zawk fmt` prints it, with every statement on a line of its own, so
+        // that the counts in the profile are those of single statements. Programs that do not
+        // parse are left alone, to report the error against the original text.
+        if let Ok(text) = pretty::format(&program_string) {
+            program_string = text;
+            sources = SourceMap::default();
+        }
+    }
+    if precompiled.is_some() {
+        let given = |id: &str| {
+            matches.value_source(id) == Some(clap::parser::ValueSource::CommandLine)
+        };
+        // These are compiled into the program.
+        for (id, flag) in [
+            ("var", "-v"),
+            ("field-separator", "-F"),
+            ("parse-header", "-H"),
+            ("output-format", "-o"),
+            ("line-ending", "--line-ending"),
+            ("null", "-0"),
+            ("arbitrary-shell", "-A"),
+            ("safe-math", "--safe-math"),
+        ] {
+            if given(id) {
+                fail!("{} has no effect on a precompiled program; pass it to `zawk compile`", flag);
+            }
+        }
+        for (id, flag) in [
+            ("parallel-strategy", "-p"),
+            ("dump-cfg", "--dump-cfg"),
+            ("dump-bytecode", "--dump-bytecode"),
+            ("emit", "--emit"),
+            ("bignum", "-M"),
+            ("explain-parallel", "--explain-parallel"),
+        ] {
+            if given(id) {
+                fail!("{} is not supported for precompiled programs", flag);
+            }
+        }
+        if matches.get_one::<String>("backend").is_some_and(|b| b != "interp") {
+            fail!("precompiled programs always run on the bytecode interpreter");
+        }
+    }
+    let argv: Vec<String> = std::env::args()
+        .next()
+        .into_iter()
+        .chain(input_files.iter().cloned())
+        .chain(script_args)
+        .collect();
+    if argv.len() > input_files.len() + 1 {
+        runtime::script_args(input_files.len() as runtime::Int + 1, argv.len() as runtime::Int);
+    }
+    let (escaper, output_sep, mut output_record_sep) =
+        output_format(
+            matches.get_one::<String>("output-format").map(|s| s.as_str()).or(query_fmt),
+            matches.get_one::<String>("line-ending").map(|s| s.as_str()),
+        );
+    runtime::splitter::batch::set_output_format(match escaper {
+        Escaper::CSV => Some(InputFormat::CSV),
+        Escaper::TSV => Some(InputFormat::TSV),
+        Escaper::Identity => None,
+    });
+    let null_records = matches.get_flag("null");
+    if null_records {
+        output_record_sep = Some("\\0");
+    }
+    let arbitrary_shell = matches.get_flag("arbitrary-shell");
+    let parse_header = matches.get_flag("parse-header")
+        || query.as_ref().is_some_and(|(q, _)| q.uses_header)
+        || precompiled.as_ref().is_some_and(|p| p.setup.parse_header);
+
+    let opt_level: i32 = match matches.get_one::<String>("opt-level").map(|s| s.as_str()) {
+        Some("3") => 3,
+        Some("2") => 2,
+        Some("1") => 1,
+        Some("0") => 0,
+        Some("-1") => -1,
+        None => DEFAULT_OPT_LEVEL,
+        Some(x) => panic!("this case should be covered by clap argument validation: found unexpected opt-level value {}", x),
+    };
+    let raw = RawPrelude {
+        field_sep: matches.get_one::<String>("field-separator").map(String::from),
+        // RS goes first so that an explicit `-v RS=...` still takes precedence over -0.
+        var_decs: null_records
+            .then(|| String::from("RS=\\0"))
+            .into_iter()
+            .chain(matches.get_many::<String>("var").into_iter().flatten().cloned())
+            .collect(),
+        output_sep,
+        output_template: output_template(matches.get_one::<String>("output-format")),
+        scalars: PreludeScalars {
+            escaper,
+            arbitrary_shell,
+            safe_math: matches.get_flag("safe-math"),
+            fold_regexes: opt_level >= 3,
+            stage: exec_strategy.stage(),
+            parse_header,
+            debug,
+        },
+        output_record_sep,
+        script_args: argv.len() - input_files.len() - 1,
+        argv,
+    };
+    // The --dump-* flags are older spellings of --emit.
+    let mut emit: Vec<&str> = matches
+        .get_many::<String>("emit")
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+    for (id, kind) in [("dump-cfg", "ir"), ("dump-bytecode", "bytecode"), ("dump-llvm", "llvm")] {
+        if matches.try_get_one::<bool>(id).ok().flatten() == Some(&true) {
+            emit.push(kind);
+        }
+    }
+    #[cfg(feature = "jit")]
+    let codegen_config = codegen::Config {
+        opt_level: if opt_level < 0 { 3 } else { opt_level as usize },
+        num_workers,
+    };
+    for kind in emit.iter() {
+        let out = match *kind {
+            "ast" => {
+                let a = Arena::default();
+                let mut prelude = get_prelude(&a, &raw);
+                parse_prog(program_string.as_str(), &a, &mut prelude).to_string()
+            }
+            "ir" => {
+                let a = Arena::default();
+                let ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
+                let mut v = Vec::new();
+                let _ = ctx.dbg_print(&mut v);
+                String::from_utf8_lossy(&v).into_owned()
+            }
+            "bytecode" => dump_bytecode(program_string.as_str(), &raw),
+            "clif" => {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "jit")] {
+                        dump_clif(program_string.as_str(), codegen_config, &raw)
+                    } else {
+                        fail!("--emit clif requires zawk to be built with the jit feature")
+                    }
+                }
+            }
+            "llvm" => {
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "llvm_backend")] {
+                        dump_llvm(program_string.as_str(), codegen_config, &raw)
+                    } else {
+                        fail!("--emit llvm requires zawk to be built with the llvm_backend feature")
+                    }
+                }
+            }
+            _ => unreachable!("--emit values are checked by clap"),
+        };
+        let _ = write!(std::io::stdout(), "{}", out);
+    }
+    let opt_explain_parallel = matches.get_flag("explain-parallel");
+    let skip_output = !emit.is_empty() || opt_explain_parallel;
+    if opt_explain_parallel {
+        let a = Arena::default();
+        let mut prelude = get_prelude(&a, &raw);
+        let prog = parse_prog(program_string.as_str(), &a, &mut prelude);
+        let _ = write!(std::io::stdout(), "{}", parallel_report::explain(prog));
+    }
+    if skip_output {
+        return;
+    }
+    let check_utf8 = matches.get_flag("utf8");
+    let signal = CancelSignal::default();
+
+    // This horrid macro is here because all of the different ways of reading input are different
+    // types, making functions hard to write. Still, there must be something to be done to clean
+    // this up here.
+    macro_rules! with_inp {
+        ($analysis:expr, $inp:ident, $body:expr) => {{
+            if avro {
+                // With -H, only the first file gets a line of field names.
+                let header = std::sync::atomic::AtomicBool::new(parse_header);
+                let $inp = ChainedReader::from_argv(
+                    move |file| {
+                        let header = header.swap(false, std::sync::atomic::Ordering::Relaxed);
+                        AvroReader::new(open_argv_input(file), file, header, check_utf8)
+                    },
+                    check_utf8,
+                );
+                $body
+            } else if matches!(exec_strategy, ExecutionStrategy::Serial) {
+                // Files are opened as the program reaches them in ARGV, so that scripts can edit
+                // the list before (or while) it is read.
+                let signal = signal.clone();
+                match (ifmt, $analysis) {
+                    (Some(ifmt), _) => {
+                        let $inp = ChainedReader::from_argv(
+                            move |file| {
+                                CSVReader::new(
+                                    once((open_argv_input(file), String::from(file))),
+                                    ifmt,
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                )
+                            },
+                            check_utf8,
+                        );
+                        $body
+                    }
+                    (
+                        None,
+                        cfg::SepAssign::Potential {
+                            field_sep,
+                            record_sep,
+                        },
+                    ) if field_sep.unwrap_or(b" ") == b" "
+                        && record_sep.unwrap_or(b"\n").len() == 1 =>
+                    {
+                        let record_sep = record_sep.unwrap_or(b"\n")[0];
+                        let $inp = ChainedReader::from_argv(
+                            move |file| {
+                                ByteReader::new_whitespace(
+                                    once((open_argv_input(file), String::from(file))),
+                                    record_sep,
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                )
+                            },
+                            check_utf8,
+                        );
+                        $body
+                    }
+                    (
+                        None,
+                        cfg::SepAssign::Potential {
+                            field_sep: Some(field_sep),
+                            record_sep,
+                        },
+                    ) if field_sep.len() == 1 && record_sep.unwrap_or(b"\n").len() == 1 => {
+                        let field_sep = field_sep[0];
+                        let record_sep = record_sep.unwrap_or(b"\n")[0];
+                        let $inp = ChainedReader::from_argv(
+                            move |file| {
+                                ByteReader::new(
+                                    once((open_argv_input(file), String::from(file))),
+                                    field_sep,
+                                    record_sep,
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                )
+                            },
+                            check_utf8,
+                        );
+                        $body
+                    }
+                    (None, _) => {
+                        let $inp = ChainedReader::from_argv(
+                            move |file| {
+                                RegexSplitter::new(
+                                    open_argv_input(file),
+                                    chunk_size,
+                                    String::from(file),
+                                    check_utf8,
+                                )
+                            },
+                            check_utf8,
+                        );
+                        $body
+                    }
+                }
+            } else if input_files.len() == 0 {
+                let _reader = runtime::encoding::decode_input(Box::new(io::stdin()));
+                match (ifmt, $analysis) {
+                    (Some(ifmt), _) => {
+                        let $inp = CSVReader::new(
+                            once((_reader, String::from("-"))),
+                            ifmt,
+                            chunk_size,
+                            check_utf8,
+                            exec_strategy,
+                            signal.clone(),
+                        );
+                        $body
+                    }
+                    (
+                        None,
+                        cfg::SepAssign::Potential {
+                            field_sep,
+                            record_sep,
+                        },
+                    ) => {
+                        let field_sep = field_sep.unwrap_or(b" ");
+                        let record_sep = record_sep.unwrap_or(b"\n");
+                        if field_sep.len() == 1 && record_sep.len() == 1 {
+                            if field_sep == b" " {
+                                let $inp = ByteReader::new_whitespace(
+                                    once((_reader, String::from("-"))),
+                                    record_sep[0],
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                );
+                                $body
+                            } else {
+                                let $inp = ByteReader::new(
+                                    once((_reader, String::from("-"))),
+                                    field_sep[0],
+                                    record_sep[0],
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                );
+                                $body
+                            }
+                        } else {
+                            let $inp =
+                                chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
+                            $body
+                        }
+                    }
+                    (None, cfg::SepAssign::Unsure) => {
+                        let $inp =
+                            chained(RegexSplitter::new(_reader, chunk_size, "-", check_utf8));
+                        $body
+                    }
+                }
+            } else if let Some(ifmt) = ifmt {
+                let boundary = match ifmt {
+                    InputFormat::CSV => Boundary::Csv,
+                    InputFormat::TSV => Boundary::Byte(b'\n'),
+                };
+                let $inp = CSVReader::new(
+                    input_handles(
+                        input_files.clone(),
+                        split_ranges.then_some((num_workers, chunk_size, boundary)),
+                    ),
+                    ifmt,
+                    chunk_size,
+                    check_utf8,
+                    exec_strategy,
+                    signal.clone(),
+                );
+                $body
+            } else {
+                match $analysis {
+                    cfg::SepAssign::Potential {
+                        field_sep,
+                        record_sep,
+                    } => {
+                        let field_sep = field_sep.unwrap_or(b" ");
+                        let record_sep = record_sep.unwrap_or(b"\n");
+                        if field_sep.len() == 1 && record_sep.len() == 1 {
+                            let boundary = Boundary::Byte(record_sep[0]);
+                            let file_handles = input_handles(
+                                input_files.clone(),
+                                split_ranges.then_some((num_workers, chunk_size, boundary)),
+                            );
+                            if field_sep == b" " {
+                                let $inp = ByteReader::new_whitespace(
+                                    file_handles,
+                                    record_sep[0],
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                );
+                                $body
+                            } else {
+                                let $inp = ByteReader::new(
+                                    file_handles,
+                                    field_sep[0],
+                                    record_sep[0],
+                                    chunk_size,
+                                    check_utf8,
+                                    exec_strategy,
+                                    signal.clone(),
+                                );
+                                $body
+                            }
+                        } else {
+                            let $inp = regex_splitters(
+                                &input_files,
+                                chunk_size,
+                                check_utf8,
+                                exec_strategy,
+                            );
+                            $body
+                        }
+                    }
+                    cfg::SepAssign::Unsure => {
+                        let $inp = regex_splitters(
+                            &input_files,
+                            chunk_size,
+                            check_utf8,
+                            exec_strategy,
+                        );
+                        $body
+                    }
+                }
+            }
+        }};
+    }
+
+    let a = Arena::default();
+    let (instrs, setup) = match precompiled {
+        Some(p) => (Some(p.instrs), Some(p.setup)),
+        None => (None, None),
+    };
+    let (ctx, analysis_result, record_limit, flushes) = match &setup {
+        Some(setup) => (None, setup.sep_assign(), setup.record_limit, setup.flushes),
+        None => {
+            let mut ctx = get_context(program_string.as_str(), &a, get_prelude(&a, &raw));
+            ctx.sources = mem::take(&mut sources);
+            let analysis_result = ctx.analyze_sep_assignments();
+            let (record_limit, flushes) = (ctx.record_limit, ctx.flushes);
+            (Some(ctx), analysis_result, record_limit, flushes)
+        }
+    };
+    // Don't hold a record back until the next one arrives when the output is flushed as it goes.
+    if flushes {
+        runtime::splitter::set_unbuffered();
+    }
+    let head = matches
+        .get_one::<u64>("head")
+        .map(|n| n.saturating_add(parse_header as u64));
+    let record_limit = head.into_iter().chain(record_limit).min();
+    if let Some(n) = record_limit {
+        runtime::limit_records(n);
+    }
+    // Workers claim records in whatever order they get to them, so the first N records are only
+    // the ones read when a single thread reads them.
+    let num_workers = if record_limit.is_some() { 1 } else { num_workers };
+    let mut pipe_config = runtime::writers::PipeConfig::default();
+    if let Some(size) = matches.get_one::<String>("pipe-buffer") {
+        pipe_config.buffer = runtime::rotate::parse_size(size)
+            .filter(|n| *n > 0)
+            .unwrap_or_else(|| fail!("invalid --pipe-buffer size: {}", size))
+            as usize;
+    }
+    if let Some(n) = matches.get_one::<usize>("pipe-queue") {
+        pipe_config.queue = *n;
+    }
+    if let Some(dur) = matches.get_one::<String>("pipe-timeout") {
+        pipe_config.timeout = Some(
+            runtime::rotate::parse_interval(dur)
+                .unwrap_or_else(|| fail!("invalid --pipe-timeout duration: {}", dur)),
+        );
+    }
+    pipe_config.stats = matches.get_flag("pipe-stats");
+    runtime::writers::set_pipe_config(pipe_config);
+    runtime::writers::set_output_config(runtime::writers::OutputConfig {
+        no_clobber: matches.get_flag("no-clobber"),
+        lock: matches.get_flag("lock-output"),
+    });
+    let out_file = matches.get_one::<String>("out-file");
+    let tee_file = matches.get_one::<String>("tee");
+    if let Some(manifest) = matches.get_one::<String>("manifest") {
+        runtime::manifest::enable(manifest);
+        for f in out_file.iter().chain(tee_file.iter()) {
+            runtime::manifest::record(f);
+        }
+    }
+    macro_rules! with_io {
+        (|$inp:ident, $out:ident| $body:expr) => {
+            match (out_file, tee_file) {
+                (Some(oup), _) => {
+                    let $out = runtime::writers::factory_from_file(oup)
+                        .unwrap_or_else(|e| fail!("failed to open {}: {}", oup, e));
+                    with_inp!(analysis_result, $inp, $body);
+                }
+                (None, Some(tee)) => {
+                    let $out = runtime::writers::factory_with_tee(tee)
+                        .unwrap_or_else(|e| fail!("failed to open {}: {}", tee, e));
+                    with_inp!(analysis_result, $inp, $body);
+                }
+                (None, None) => {
+                    let $out = runtime::writers::default_factory();
+                    with_inp!(analysis_result, $inp, $body);
+                }
+            }
+        };
+    }
+    if let (Some(instrs), Some(setup)) = (instrs, &setup) {
+        with_io!(|inp, oup| run_precompiled(instrs, setup, &raw.argv, inp, oup));
+        return;
+    }
+    let ctx = ctx.expect("programs that are not precompiled have a context");
+    // Single-column aggregations skip compilation altogether. --head and --inplace are
+    // implemented by the main loop, so they need a compiled program.
+    if let (Some(plan), None, 1.., false, None, None) =
+        (&ctx.column_stats, head, opt_level, debug, profile, &inplace)
+    {
+        with_io!(|inp, oup| if let Err(e) = column_stats::run(plan, &input_files, inp, oup) {
+            fail!("fatal error during execution: {}", e)
+        });
+        return;
+    }
+    if debug {
+        let monitor = Monitor::Debug(program_string.as_str());
+        with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers, monitor));
+        return;
+    }
+    if let Some(out) = profile {
+        let monitor = Monitor::Profile(program_string.as_str(), out.as_str());
+        with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers, monitor));
+        return;
+    }
+    match matches.get_one::<String>("backend").map(|s| s.as_str()) {
+        Some("llvm") => {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "llvm_backend")] {
+                    with_io!(|inp, oup| run_llvm_with_context(
+                            ctx,
+                            inp,
+                            oup,
+                            codegen::Config {
+                                opt_level: opt_level as usize,
+                                num_workers,
+                            },
+                            signal,
+                    ));
+                } else {
+                    fail!("backend specified as LLVM, but compiled without LLVM support");
+                }
+            }
+        }
+        Some("interp") => {
+            with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers, Monitor::None))
+        }
+        None | Some("cranelift") => {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "jit")] {
+                    with_io!(|inp, oup| run_cranelift_with_context(
+                        ctx,
+                        inp,
+                        oup,
+                        codegen::Config {
+                            opt_level: opt_level as usize,
+                            num_workers,
+                        },
+                        signal,
+                    ));
+                } else {
+                    if matches.get_one::<String>("backend").is_some() {
+                        fail!("backend specified as cranelift, but compiled without cranelift support");
+                    }
+                    with_io!(|inp, oup| run_interp_with_context(ctx, inp, oup, num_workers, Monitor::None))
+                }
+            }
+        }
+        Some(b) => {
+            fail!("invalid backend: {:?}", b);
+        }
+    }
+}
@@ -143,6 +143,8 @@ pub(crate) struct Generator {
     cctx: codegen::Context,
     funcs: Vec<Option<Prelude>>,
     mains: Stage<FuncId>,
+    // The text of each function as it is defined, for `--emit clif`.
+    listing: Option<String>,
 }
 
 /// The state required for generating code for the function at `f`.
@@ -227,7 +229,17 @@ fn jit_builder() -> Result<JITBuilder> {
 }
 
 impl Generator {
-    pub(crate) fn init(typer: &mut Typer, _config: Config) -> Result<Generator> {
+    pub(crate) fn init(typer: &mut Typer, config: Config) -> Result<Generator> {
+        Generator::build(typer, config, None)
+    }
+
+    /// The Cranelift IR of the program, one function after another, before it is optimized.
+    pub(crate) fn dump(typer: &mut Typer, config: Config) -> Result<String> {
+        let global = Generator::build(typer, config, Some(String::new()))?;
+        Ok(global.listing.unwrap_or_default())
+    }
+
+    fn build(typer: &mut Typer, _config: Config, listing: Option<String>) -> Result<Generator> {
         let builder = jit_builder()?;
         let mut regstate = RegistrationState { builder };
         intrinsics::register_all(&mut regstate)?;
@@ -247,6 +259,7 @@ impl Generator {
             funcs: Default::default(),
             // placeholder
             mains: Stage::Main(FuncId::from_u32(0)),
+            listing,
         };
         global.define_functions(typer)?;
         let stage = match typer.stage() {
@@ -347,6 +360,12 @@ impl Generator {
     }
 
     fn define_cur_function(&mut self, id: FuncId) -> Result<()> {
+        if let Some(listing) = &mut self.listing {
+            use std::fmt::Write;
+            let decl = self.shared.module.declarations().get_function_decl(id);
+            let name = decl.name.as_deref().unwrap_or("<anonymous>");
+            let _ = writeln!(listing, "; {}\n{}", name, self.cctx.func.display());
+        }
         self.shared
             .module
             .define_function(id, &mut self.cctx)
@@ -762,6 +781,8 @@ impl<'a> View<'a> {
             }
             // Phis are handled in predecessor blocks
             Phi(..) => Ok(()),
+            // Line markers are only used by the bytecode interpreter.
+            Line(_) => Ok(()),
         }
     }
 
@@ -1048,6 +1069,9 @@ impl<'a> View<'a> {
             Ceil => self.call_external(external!(_frawk_ceil), args),
             Floor => self.call_external(external!(_frawk_floor), args),
             Round => self.call_external(external!(_frawk_round), args),
+            RoundTo => self.call_external(external!(_frawk_round_to), args),
+            FloorTo => self.call_external(external!(_frawk_floor_to), args),
+            CeilTo => self.call_external(external!(_frawk_ceil_to), args),
         }
     }
 
@@ -4,13 +4,14 @@
 //! try and hew closely to the steps in the `interp` module, with most functionality in the
 //! underlying runtime library.
 use super::{Backend, FunctionAttr, Sig};
-use crate::runtime::{self, printf::{printf, FormatArg}, splitter::{
+use crate::runtime::{self, printf::{printf, FormatArg, NumberFormat}, splitter::{
+    avro::AvroReader,
     batch::{ByteReader, CSVReader, WhitespaceOffsets},
     chunk::{ChunkProducer, OffsetChunk},
     regex::RegexSplitter,
-}, ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap, math_util, string_util, faker};
+}, ChainedReader, FileRead, Float, Int, IntMap, Line, LineReader, RegexCache, Str, StrMap, math_util, string_util, faker, ip};
 use crate::{
-    builtins::Variable,
+    builtins::{NormalForm, Variable},
     common::{CancelSignal, Cleanup, FileSpec, Notification, Result},
     compile::Ty,
     pushdown::FieldSet,
@@ -19,7 +20,7 @@ use crate::{
 use libc::{c_void};
 use paste::paste;
 use rand::{self, Rng};
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
 use smallvec;
 
 use std::convert::TryFrom;
@@ -95,6 +96,7 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         ref_map(map_ty);
         [ReadOnly] int_to_str(int_ty) -> str_ty;
         [ReadOnly] float_to_str(float_ty) -> str_ty;
+        [ReadOnly] float_to_out_str(float_ty) -> str_ty;
         [ReadOnly] str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] hex_str_to_int(str_ref_ty) -> int_ty;
         [ReadOnly] str_to_float(str_ref_ty) -> float_ty;
@@ -104,38 +106,58 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] match_pat(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] match_const_pat(str_ref_ty, rt_ty) -> int_ty;
         [ReadOnly] match_pat_loc(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        match_pat_arr(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> int_ty;
         [ReadOnly] match_const_pat_loc(rt_ty, str_ref_ty, rt_ty) -> int_ty;
         [ReadOnly] substr_index(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] substr_last_index(str_ref_ty, str_ref_ty) -> int_ty;
         subst_first(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
         subst_all(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        subst_first_const(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        subst_all_const(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         gen_subst(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         escape_csv(str_ref_ty) -> str_ty;
         escape_tsv(str_ref_ty) -> str_ty;
         substr(str_ref_ty, int_ty, int_ty) -> str_ty;
-        [ReadOnly] char_at(str_ref_ty, int_ty) -> str_ty;
+        char_at(rt_ty, str_ref_ty, int_ty) -> str_ty;
         [ReadOnly] last_part(str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] get_col(rt_ty, int_ty) -> str_ty;
         [ReadOnly] join_csv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_tsv(rt_ty, int_ty, int_ty) -> str_ty;
         [ReadOnly] join_cols(rt_ty, int_ty, int_ty, str_ref_ty) -> str_ty;
+        select_cols(rt_ty, str_ref_ty, str_ref_ty, int_ty) -> str_ty;
         [ReadOnly] to_upper_ascii(str_ref_ty) -> str_ty;
         [ReadOnly] to_lower_ascii(str_ref_ty) -> str_ty;
         set_col(rt_ty, int_ty, str_ref_ty);
         split_int(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
         split_str(rt_ty, str_ref_ty, map_ty, str_ref_ty) -> int_ty;
+        split_seps_int(rt_ty, str_ref_ty, map_ty, str_ref_ty, map_ty) -> int_ty;
+        split_seps_str(rt_ty, str_ref_ty, map_ty, str_ref_ty, map_ty) -> int_ty;
+        patsplit_int(rt_ty, str_ref_ty, map_ty, str_ref_ty, map_ty) -> int_ty;
+        patsplit_str(rt_ty, str_ref_ty, map_ty, str_ref_ty, map_ty) -> int_ty;
         rand_float(rt_ty) -> float_ty;
+        safe_int(rt_ty, int_ty, int_ty, int_ty) -> int_ty;
+        safe_float(rt_ty, int_ty, float_ty, float_ty) -> float_ty;
         seed_rng(rt_ty, int_ty) -> int_ty;
         reseed_rng(rt_ty) -> int_ty;
+        rand_int(rt_ty, int_ty, int_ty) -> int_ty;
 
         exit(rt_ty, int_ty);
         run_system(str_ref_ty) -> int_ty;
+        exec_output(str_ref_ty) -> str_ty;
+        exec_output_argv(map_ty) -> str_ty;
+        spawn(str_ref_ty) -> str_ty;
+        spawn_argv(map_ty) -> str_ty;
+        set_env(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        unset_env(rt_ty, str_ref_ty) -> int_ty;
+        ext_push_arg(str_ref_ty);
+        ext_call(rt_ty, int_ty) -> str_ty;
         print_all_stdout(rt_ty, pa_args_ty, int_ty);
         print_all_file(rt_ty, pa_args_ty, int_ty, str_ref_ty, int_ty);
         sprintf_impl(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty) -> str_ty;
         printf_impl_file(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty, str_ref_ty, int_ty);
         printf_impl_stdout(rt_ty, str_ref_ty, fmt_args_ty, fmt_tys_ty, int_ty);
         close_file(rt_ty, str_ref_ty);
+        fflush(rt_ty, str_ref_ty) -> int_ty;
         read_err(rt_ty, str_ref_ty, int_ty) -> int_ty;
         read_err_stdin(rt_ty) -> int_ty;
         next_line(rt_ty, str_ref_ty, int_ty) -> str_ty;
@@ -156,14 +178,21 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         user_home(rt_ty) -> str_ty;
         local_ip(rt_ty) -> str_ty;
         systime(rt_ty) -> int_ty;
-        [ReadOnly] mktime(str_ref_ty, int_ty) -> int_ty;
+        systime_ms(rt_ty) -> int_ty;
+        systime_us(rt_ty) -> int_ty;
+        clock(rt_ty) -> float_ty;
+        sleep(float_ty) -> int_ty;
+        [ReadOnly] mktime(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] duration(str_ref_ty) -> int_ty;
-        [ReadOnly] strftime(rt_ty, str_ref_ty, int_ty) -> str_ty;
+        [ReadOnly] strftime(rt_ty, str_ref_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] mkbool(str_ref_ty) -> int_ty;
         [ReadOnly] fend(str_ref_ty) -> str_ty;
         [ReadOnly] trim(str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] strtonum(str_ref_ty) -> float_ty;
+        [ReadOnly] parse_number(str_ref_ty, str_ref_ty) -> float_ty;
         format_bytes(int_ty) -> str_ty;
+        // Reads the --round-mode setting.
+        [ReadOnly] format_num(float_ty, str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] to_bytes(str_ref_ty) -> int_ty;
         [ReadOnly] starts_with(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] ends_with(str_ref_ty, str_ref_ty) -> int_ty;
@@ -189,16 +218,42 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] pad_right(str_ref_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] pad_both(str_ref_ty, int_ty, str_ref_ty) -> str_ty;
         [ReadOnly] strcmp(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] encode(str_ref_ty, str_ref_ty) -> str_ty;
-        [ReadOnly] decode(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] natcmp(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] levenshtein(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] similarity(str_ref_ty, str_ref_ty) -> float_ty;
+        [ReadOnly] diff(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] soundex(str_ref_ty) -> str_ty;
+        [ReadOnly] metaphone(str_ref_ty) -> str_ty;
+        [ReadOnly] fuzzy_match(str_ref_ty, str_ref_ty, float_ty) -> int_ty;
+        encode(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        decode(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        iconv(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        compress(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        decompress(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] utf8_valid(str_ref_ty) -> int_ty;
+        [ReadOnly] utf8_fix(str_ref_ty) -> str_ty;
+        [ReadOnly] nfc(str_ref_ty) -> str_ty;
+        [ReadOnly] nfd(str_ref_ty) -> str_ty;
+        [ReadOnly] nfkc(str_ref_ty) -> str_ty;
+        [ReadOnly] nfkd(str_ref_ty) -> str_ty;
         [ReadOnly] escape(str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] digest(str_ref_ty, str_ref_ty) -> str_ty;
+        file_digest(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        digest_init(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        digest_update(rt_ty, int_ty, str_ref_ty) -> int_ty;
+        digest_final(rt_ty, int_ty) -> str_ty;
         [ReadOnly] hmac(str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
-        [ReadOnly] jwt(str_ref_ty, str_ref_ty, map_ty) -> str_ty;
-        [ReadOnly] dejwt(str_ref_ty, str_ref_ty) -> map_ty;
+        jwt(rt_ty, str_ref_ty, str_ref_ty, map_ty) -> str_ty;
+        dejwt(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> map_ty;
+        cert(rt_ty, str_ref_ty) -> map_ty;
         [ReadOnly] encrypt(str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] decrypt(str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] url(str_ref_ty) -> map_ty;
+        [ReadOnly] ua_parse(str_ref_ty) -> map_ty;
+        [ReadOnly] logfmt(str_ref_ty) -> map_ty;
+        [ReadOnly] map_str_int_to_logfmt(map_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] map_str_float_to_logfmt(map_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] map_str_str_to_logfmt(map_ty, str_ref_ty) -> str_ty;
         [ReadOnly] parse(str_ref_ty,str_ref_ty) -> map_ty;
         [ReadOnly] rparse(str_ref_ty,str_ref_ty) -> map_ty;
         [ReadOnly] record(str_ref_ty) -> map_ty;
@@ -208,6 +263,9 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] path(str_ref_ty) -> map_ty;
         [ReadOnly] data_url(str_ref_ty) -> map_ty;
         [ReadOnly] datetime(str_ref_ty) -> map_ty;
+        dateparse(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        dateadd(rt_ty, int_ty, int_ty, str_ref_ty) -> int_ty;
+        datediff(rt_ty, int_ty, int_ty, str_ref_ty) -> int_ty;
         [ReadOnly] shlex(str_ref_ty) -> map_ty;
         [ReadOnly] tuple(str_ref_ty) -> map_ty;
         [ReadOnly] flags(str_ref_ty) -> map_ty;
@@ -222,24 +280,49 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] mysql_execute(str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] http_get(str_ref_ty, map_ty) -> map_ty;
         [ReadOnly] http_post(str_ref_ty, map_ty, str_ref_ty) -> map_ty;
-        [ReadOnly] s3_get(str_ref_ty, str_ref_ty) -> str_ty;
-        [ReadOnly] s3_put(str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        s3_get(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        s3_put(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] kv_get(str_ref_ty, str_ref_ty) -> str_ty;
         kv_put(str_ref_ty, str_ref_ty, str_ref_ty);
         kv_delete(str_ref_ty, str_ref_ty);
         kv_clear(str_ref_ty);
-        [ReadOnly] read_all(str_ref_ty) -> str_ty;
-        write_all(str_ref_ty, str_ref_ty);
+        read_all(rt_ty, str_ref_ty) -> str_ty;
+        read_lines(rt_ty, str_ref_ty) -> map_ty;
+        write_all(rt_ty, str_ref_ty, str_ref_ty);
+        append_all(rt_ty, str_ref_ty, str_ref_ty);
+        glob(rt_ty, str_ref_ty) -> map_ty;
+        stat(rt_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] exists(str_ref_ty) -> int_ty;
+        mkdirp(rt_ty, str_ref_ty) -> int_ty;
+        rmfile(rt_ty, str_ref_ty) -> int_ty;
         log_debug(rt_ty, str_ref_ty);
         log_info(rt_ty, str_ref_ty);
         log_warn(rt_ty, str_ref_ty);
         log_error(rt_ty, str_ref_ty);
         publish(str_ref_ty, str_ref_ty);
-        bf_insert(str_ref_ty, str_ref_ty);
-        [ReadOnly] bf_contains(str_ref_ty, str_ref_ty) -> int_ty;
-        [ReadOnly] bf_icontains(str_ref_ty, str_ref_ty) -> int_ty;
+        bf_insert(rt_ty, str_ref_ty, str_ref_ty);
+        [ReadOnly] bf_contains(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
+        bf_icontains(rt_ty, str_ref_ty, str_ref_ty) -> int_ty;
         [ReadOnly] fake(str_ref_ty, str_ref_ty) -> str_ty;
         [ReadOnly] from_json(str_ref_ty) -> map_ty;
+        from_toml(rt_ty, str_ref_ty) -> map_ty;
+        map_str_int_to_toml(rt_ty, map_ty, str_ref_ty) -> str_ty;
+        map_str_float_to_toml(rt_ty, map_ty, str_ref_ty) -> str_ty;
+        map_str_str_to_toml(rt_ty, map_ty, str_ref_ty) -> str_ty;
+        toml_value(rt_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] from_ini(str_ref_ty) -> map_ty;
+        [ReadOnly] map_str_int_to_ini(map_ty) -> str_ty;
+        [ReadOnly] map_str_float_to_ini(map_ty) -> str_ty;
+        [ReadOnly] map_str_str_to_ini(map_ty) -> str_ty;
+        [ReadOnly] prop_get(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] map_str_int_to_xml(map_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] map_str_float_to_xml(map_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] map_str_str_to_xml(map_ty, str_ref_ty) -> str_ty;
+        xml_update(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] html_table(str_ref_ty, int_ty) -> map_ty;
+        from_msgpack(rt_ty, str_ref_ty) -> map_ty;
+        from_cbor(rt_ty, str_ref_ty) -> map_ty;
+        from_protobuf(rt_ty, str_ref_ty, str_ref_ty, str_ref_ty) -> map_ty;
         [ReadOnly] map_int_int_to_json(map_ty) -> str_ty;
         [ReadOnly] map_int_float_to_json(map_ty) -> str_ty;
         [ReadOnly] map_int_str_to_json(map_ty) -> str_ty;
@@ -260,9 +343,9 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         dump_int(int_ty);
         dump_float(float_ty);
         dump_null();
-        map_int_int_asort(map_ty, map_ty) -> int_ty;
-        map_int_float_asort(map_ty, map_ty) -> int_ty;
-        map_int_str_asort(map_ty, map_ty) -> int_ty;
+        map_int_int_asort(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        map_int_float_asort(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
+        map_int_str_asort(rt_ty, map_ty, map_ty, str_ref_ty) -> int_ty;
         [ReadOnly] map_int_int_join(map_ty, str_ref_ty) -> str_ty;
         [ReadOnly] map_int_float_join(map_ty, str_ref_ty) -> str_ty;
         [ReadOnly] map_int_str_join(map_ty, str_ref_ty) -> str_ty;
@@ -280,6 +363,24 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] map_int_str_to_csv(map_ty) -> str_ty;
         [ReadOnly] min(str_ref_ty,str_ref_ty,str_ref_ty) -> str_ty;
         [ReadOnly] max(str_ref_ty,str_ref_ty,str_ref_ty) -> str_ty;
+        [ReadOnly] dec_add(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] dec_sub(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] dec_mul(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] dec_div(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] dec_cmp(str_ref_ty, str_ref_ty) -> int_ty;
+        // Reads the --round-mode setting.
+        [ReadOnly] dec_fmt(str_ref_ty, int_ty) -> str_ty;
+        [ReadOnly] big_add(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_sub(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_mul(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_div(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_mod(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_pow(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] big_cmp(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] big_is_false(str_ref_ty) -> int_ty;
+        [ReadOnly] u64_add(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] u64_mul(str_ref_ty, str_ref_ty) -> str_ty;
+        [ReadOnly] u64_str(int_ty) -> str_ty;
         [ReadOnly] seq(float_ty,float_ty,float_ty) -> map_ty;
         [ReadOnly] uniq(map_ty, str_ref_ty) -> map_ty;
         [ReadOnly] type_of_array() -> str_ty;
@@ -294,7 +395,15 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly] is_num_true() -> int_ty;
         [ReadOnly] is_num_false() -> int_ty;
         [ReadOnly] is_str_num(str_ref_ty) -> int_ty;
+        [ReadOnly] is_nan(float_ty) -> int_ty;
+        [ReadOnly] is_inf(float_ty) -> int_ty;
         [ReadOnly] is_format(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] cidr_contains(str_ref_ty, str_ref_ty) -> int_ty;
+        [ReadOnly] ip2int(str_ref_ty) -> int_ty;
+        [ReadOnly] int2ip(int_ty) -> str_ty;
+        [ReadOnly] ip_version(str_ref_ty) -> int_ty;
+        geoip(rt_ty, str_ref_ty) -> map_ty;
+        geoip_db(rt_ty, str_ref_ty) -> int_ty;
         // TODO: we are no longer relying on avoiding collisions with exisint library symbols
         // (everything in this module was one no_mangle); we should look into removing the _frawk
         // prefix.
@@ -313,8 +422,12 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         [ReadOnly, ArgmemOnly] _frawk_abs(float_ty) -> float_ty;
         [ReadOnly, ArgmemOnly] _frawk_ceil(float_ty) -> float_ty;
         [ReadOnly, ArgmemOnly] _frawk_floor(float_ty) -> float_ty;
-        [ReadOnly, ArgmemOnly] _frawk_round(float_ty) -> float_ty;
         [ReadOnly, ArgmemOnly] _frawk_atan2(float_ty, float_ty) -> float_ty;
+        // The rounding functions read the --round-mode setting.
+        [ReadOnly] _frawk_round(float_ty) -> float_ty;
+        [ReadOnly] _frawk_round_to(float_ty, float_ty) -> float_ty;
+        [ReadOnly] _frawk_floor_to(float_ty, float_ty) -> float_ty;
+        [ReadOnly] _frawk_ceil_to(float_ty, float_ty) -> float_ty;
 
         load_var_str(rt_ty, int_ty) -> str_ty;
         store_var_str(rt_ty, int_ty, str_ref_ty);
@@ -345,6 +458,18 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_intint(map_ty, int_ty);
         clear_intint(map_ty);
         drop_intint(map_ty);
+        [ReadOnly] keys_intint(map_ty) -> map_ty;
+        [ReadOnly] values_intint(map_ty) -> map_ty;
+        sorted_keys_intint(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_intint(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_intint(map_ty) -> map_ty;
+        [ReadOnly] merge_intint(map_ty, map_ty) -> map_ty;
+        copy_intint(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_intint(map_ty, map_ty) -> int_ty;
+        choice_intint(rt_ty, map_ty) -> int_ty;
+        sample_intint(rt_ty, map_ty, int_ty) -> map_ty;
+        shuffle_intint(rt_ty, map_ty) -> int_ty;
+        [ReadOnly] slice_intint(map_ty, int_ty, int_ty) -> map_ty;
         inc_int_intint(map_ty, int_ty, int_ty) -> int_ty;
         inc_float_intint(map_ty, int_ty, float_ty) -> int_ty;
 
@@ -357,6 +482,18 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_intfloat(map_ty, int_ty);
         clear_intfloat(map_ty);
         drop_intfloat(map_ty);
+        [ReadOnly] keys_intfloat(map_ty) -> map_ty;
+        [ReadOnly] values_intfloat(map_ty) -> map_ty;
+        sorted_keys_intfloat(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_intfloat(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_intfloat(map_ty) -> map_ty;
+        [ReadOnly] merge_intfloat(map_ty, map_ty) -> map_ty;
+        copy_intfloat(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_intfloat(map_ty, map_ty) -> int_ty;
+        choice_intfloat(rt_ty, map_ty) -> float_ty;
+        sample_intfloat(rt_ty, map_ty, int_ty) -> map_ty;
+        shuffle_intfloat(rt_ty, map_ty) -> int_ty;
+        [ReadOnly] slice_intfloat(map_ty, int_ty, int_ty) -> map_ty;
         inc_int_intfloat(map_ty, int_ty, int_ty) -> float_ty;
         inc_float_intfloat(map_ty, int_ty, float_ty) -> float_ty;
 
@@ -369,6 +506,18 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_intstr(map_ty, int_ty);
         clear_intstr(map_ty);
         drop_intstr(map_ty);
+        [ReadOnly] keys_intstr(map_ty) -> map_ty;
+        [ReadOnly] values_intstr(map_ty) -> map_ty;
+        sorted_keys_intstr(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_intstr(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_intstr(map_ty) -> map_ty;
+        [ReadOnly] merge_intstr(map_ty, map_ty) -> map_ty;
+        copy_intstr(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_intstr(map_ty, map_ty) -> int_ty;
+        choice_intstr(rt_ty, map_ty) -> str_ty;
+        sample_intstr(rt_ty, map_ty, int_ty) -> map_ty;
+        shuffle_intstr(rt_ty, map_ty) -> int_ty;
+        [ReadOnly] slice_intstr(map_ty, int_ty, int_ty) -> map_ty;
         inc_int_intstr(map_ty, int_ty, int_ty) -> str_ty;
         inc_float_intstr(map_ty, int_ty, float_ty) -> str_ty;
 
@@ -381,6 +530,16 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_strint(map_ty, str_ref_ty);
         clear_strint(map_ty);
         drop_strint(map_ty);
+        [ReadOnly] keys_strint(map_ty) -> map_ty;
+        [ReadOnly] values_strint(map_ty) -> map_ty;
+        sorted_keys_strint(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_strint(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_strint(map_ty) -> map_ty;
+        [ReadOnly] merge_strint(map_ty, map_ty) -> map_ty;
+        copy_strint(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_strint(map_ty, map_ty) -> int_ty;
+        choice_strint(rt_ty, map_ty) -> int_ty;
+        sample_strint(rt_ty, map_ty, int_ty) -> map_ty;
         inc_int_strint(map_ty, str_ref_ty, int_ty) -> int_ty;
         inc_float_strint(map_ty, str_ref_ty, float_ty) -> int_ty;
 
@@ -393,6 +552,16 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_strfloat(map_ty, str_ref_ty);
         clear_strfloat(map_ty);
         drop_strfloat(map_ty);
+        [ReadOnly] keys_strfloat(map_ty) -> map_ty;
+        [ReadOnly] values_strfloat(map_ty) -> map_ty;
+        sorted_keys_strfloat(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_strfloat(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_strfloat(map_ty) -> map_ty;
+        [ReadOnly] merge_strfloat(map_ty, map_ty) -> map_ty;
+        copy_strfloat(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_strfloat(map_ty, map_ty) -> int_ty;
+        choice_strfloat(rt_ty, map_ty) -> float_ty;
+        sample_strfloat(rt_ty, map_ty, int_ty) -> map_ty;
         inc_int_strfloat(map_ty, str_ref_ty, int_ty) -> float_ty;
         inc_float_strfloat(map_ty, str_ref_ty, float_ty) -> float_ty;
 
@@ -405,6 +574,16 @@ pub(crate) fn register_all(cg: &mut impl Backend) -> Result<()> {
         delete_strstr(map_ty, str_ref_ty);
         clear_strstr(map_ty);
         drop_strstr(map_ty);
+        [ReadOnly] keys_strstr(map_ty) -> map_ty;
+        [ReadOnly] values_strstr(map_ty) -> map_ty;
+        sorted_keys_strstr(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        sort_by_value_strstr(rt_ty, map_ty, str_ref_ty) -> map_ty;
+        [ReadOnly] invert_strstr(map_ty) -> map_ty;
+        [ReadOnly] merge_strstr(map_ty, map_ty) -> map_ty;
+        copy_strstr(map_ty, map_ty) -> int_ty;
+        [ReadOnly] equals_strstr(map_ty, map_ty) -> int_ty;
+        choice_strstr(rt_ty, map_ty) -> str_ty;
+        sample_strstr(rt_ty, map_ty, int_ty) -> map_ty;
         inc_int_strstr(map_ty, str_ref_ty, int_ty) -> str_ty;
         inc_float_strstr(map_ty, str_ref_ty, float_ty) -> str_ty;
 
@@ -460,12 +639,16 @@ macro_rules! try_abort {
 }
 
 // we use a "silent" abort for write errors to play nicely with unix tools like "head" which
-// deliberately close pipes prematurely.
+// deliberately close pipes prematurely. Errors that were reported as fatal (such as a command
+// pipe timing out) still stop the program with a failure.
 macro_rules! try_silent_abort {
     ($rt:expr, $e:expr) => {
         match $e {
             Ok(res) => res,
-            Err(_) => exit!($rt),
+            Err(_) => {
+                try_abort!($rt, runtime::errors::check());
+                exit!($rt)
+            }
         }
     };
 }
@@ -480,9 +663,14 @@ macro_rules! exit {
         let rt_raw = $runtime as *mut Runtime;
         let rt = &mut *rt_raw;
         let code = $code;
+        if code != 0 {
+            rt.core.write_files.discard_atomic_writes();
+        }
         if rt.concurrent {
             let pid = rt.core.vars.pid;
             rt.cancel_signal.cancel(code);
+            // Workers that have not started yet would otherwise hold up the drop below.
+            with_input!(&rt.input_data, |(_, read_files)| read_files.stop_waiting());
             std::ptr::drop_in_place(rt_raw);
             if pid == 1 {
                 // We are the main thread. Drop on `rt` should have waited for other threads to exit.
@@ -508,17 +696,27 @@ macro_rules! with_input {
             $crate::codegen::intrinsics::InputData::V2($p) => $body,
             $crate::codegen::intrinsics::InputData::V3($p) => $body,
             $crate::codegen::intrinsics::InputData::V4($p) => $body,
+            $crate::codegen::intrinsics::InputData::V5($p) => $body,
+            $crate::codegen::intrinsics::InputData::V6($p) => $body,
+            $crate::codegen::intrinsics::InputData::V7($p) => $body,
+            $crate::codegen::intrinsics::InputData::V8($p) => $body,
         }
     };
 }
 
 pub(crate) type InputTuple<LR> = (<LR as LineReader>::Line, FileRead<LR>);
+type WhitespaceChunks = Box<dyn ChunkProducer<Chunk=OffsetChunk<WhitespaceOffsets>>>;
 
 pub(crate) enum InputData {
     V1(InputTuple<CSVReader<Box<dyn ChunkProducer<Chunk=OffsetChunk>>>>),
-    V2(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk=OffsetChunk<WhitespaceOffsets>>>>>),
+    V2(InputTuple<ByteReader<WhitespaceChunks>>),
     V3(InputTuple<ByteReader<Box<dyn ChunkProducer<Chunk=OffsetChunk>>>>),
     V4(InputTuple<ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>>),
+    // The same readers, one per file named in ARGV.
+    V5(InputTuple<ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk=OffsetChunk>>>>>),
+    V6(InputTuple<ChainedReader<ByteReader<WhitespaceChunks>>>),
+    V7(InputTuple<ChainedReader<ByteReader<Box<dyn ChunkProducer<Chunk=OffsetChunk>>>>>),
+    V8(InputTuple<ChainedReader<AvroReader>>),
 }
 
 pub(crate) trait IntoRuntime {
@@ -569,6 +767,13 @@ impl_into_runtime!(
 );
 impl_into_runtime!(ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>, V3);
 impl_into_runtime!(ChainedReader<RegexSplitter<Box<dyn io::Read + Send>>>, V4);
+impl_into_runtime!(ChainedReader<CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>, V5);
+impl_into_runtime!(
+    ChainedReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>>,
+    V6
+);
+impl_into_runtime!(ChainedReader<ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>>>, V7);
+impl_into_runtime!(ChainedReader<AvroReader>, V8);
 
 pub(crate) struct Runtime<'a> {
     pub(crate) core: crate::interp::Core<'a>,
@@ -580,11 +785,12 @@ pub(crate) struct Runtime<'a> {
 }
 
 impl<'a> Runtime<'a> {
-    fn reset_file_vars(&mut self) {
+    fn reset_file_vars(&mut self) -> Result<()> {
         self.core.vars.fnr = 0;
         self.core.vars.filename = with_input!(&mut self.input_data, |(_, read_files)| {
             read_files.stdin_filename().upcast()
         });
+        self.core.write_files.start_input_file(&self.core.vars.filename)
     }
 }
 
@@ -603,11 +809,94 @@ pub(crate) unsafe extern "C" fn run_system(cmd: *mut U128) -> Int {
     s.with_bytes(runtime::run_command)
 }
 
+pub(crate) unsafe extern "C" fn exec_output(cmd: *mut U128) -> U128 {
+    let s: &Str = &*(cmd as *mut Str);
+    mem::transmute::<Str, U128>(Str::from(s.with_bytes(runtime::exec_output)))
+}
+
+pub(crate) unsafe extern "C" fn exec_output_argv(argv: *mut c_void) -> U128 {
+    let argv = mem::transmute::<*mut c_void, IntMap<Str>>(argv);
+    let res = runtime::exec_output_argv(&argv);
+    mem::forget(argv);
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn spawn(cmd: *mut U128) -> U128 {
+    let s: &Str = &*(cmd as *mut Str);
+    mem::transmute::<Str, U128>(Str::from(s.with_bytes(runtime::spawn)))
+}
+
+pub(crate) unsafe extern "C" fn spawn_argv(argv: *mut c_void) -> U128 {
+    let argv = mem::transmute::<*mut c_void, IntMap<Str>>(argv);
+    let res = runtime::spawn_argv(&argv);
+    mem::forget(argv);
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn set_env(
+    runtime: *mut c_void,
+    name: *mut U128,
+    value: *mut U128,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let name = &*(name as *mut Str);
+    let value = &*(value as *mut Str);
+    runtime.core.vars.setenv(name, value)
+}
+
+pub(crate) unsafe extern "C" fn unset_env(runtime: *mut c_void, name: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let name = &*(name as *mut Str);
+    runtime.core.vars.unsetenv(name)
+}
+
+thread_local! {
+    // The arguments of the next call to an extension function, added by `ext_push_arg`.
+    static EXT_ARGS: std::cell::RefCell<Vec<Vec<u8>>> = Default::default();
+}
+
+pub(crate) unsafe extern "C" fn ext_push_arg(arg: *mut U128) {
+    let arg = &*(arg as *mut Str);
+    EXT_ARGS.with(|args| args.borrow_mut().push(arg.with_bytes(|bs| bs.to_vec())));
+}
+
+pub(crate) unsafe extern "C" fn ext_call(runtime: *mut c_void, ext: Int) -> U128 {
+    let args = EXT_ARGS.with(|args| mem::take(&mut *args.borrow_mut()));
+    let args: Vec<&[u8]> = args.iter().map(|a| &a[..]).collect();
+    match runtime::ext::call(ext as u32, &args) {
+        Ok(res) => mem::transmute::<Str, U128>(res),
+        Err(e) => fail!(runtime, "{}", e),
+    }
+}
+
 pub(crate) unsafe extern "C" fn rand_float(runtime: *mut c_void) -> f64 {
     let runtime = &mut *(runtime as *mut Runtime);
     runtime.core.rng.gen_range(0.0..=1.0)
 }
 
+pub(crate) unsafe extern "C" fn safe_int(runtime: *mut c_void, op: Int, x: Int, y: Int) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let (res, err) = runtime::safe_math::int(runtime::safe_math::Op::from_int(op), x, y);
+    if let Some(msg) = err {
+        runtime.core.vars.set_errno(msg);
+    }
+    res
+}
+
+pub(crate) unsafe extern "C" fn safe_float(
+    runtime: *mut c_void,
+    op: Int,
+    x: Float,
+    y: Float,
+) -> Float {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let (res, err) = runtime::safe_math::float(runtime::safe_math::Op::from_int(op), x, y);
+    if let Some(msg) = err {
+        runtime.core.vars.set_errno(msg);
+    }
+    res
+}
+
 pub(crate) unsafe extern "C" fn seed_rng(runtime: *mut c_void, seed: Int) -> Int {
     let runtime = &mut *(runtime as *mut Runtime);
     runtime.core.reseed(seed as u64) as Int
@@ -618,6 +907,11 @@ pub(crate) unsafe extern "C" fn reseed_rng(runtime: *mut c_void) -> Int {
     runtime.core.reseed_random() as Int
 }
 
+pub(crate) unsafe extern "C" fn rand_int(runtime: *mut c_void, lo: Int, hi: Int) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    math_util::rand_int(&mut runtime.core.rng, lo, hi)
+}
+
 pub(crate) unsafe extern "C" fn read_err(
     runtime: *mut c_void,
     file: *mut c_void,
@@ -644,6 +938,12 @@ pub(crate) unsafe extern "C" fn read_err_stdin(runtime: *mut c_void) -> Int {
         .read_err_stdin())
 }
 
+/// Stop with the error that `errors::report_deferred` held back under --strict, if there is one,
+/// now that the program has finished.
+pub(crate) unsafe fn check_deferred_error(runtime: &mut Runtime) {
+    try_abort!(runtime, runtime::errors::check());
+}
+
 pub(crate) unsafe extern "C" fn next_line_stdin_fused(runtime: *mut c_void) {
     let runtime = &mut *(runtime as *mut Runtime);
     let changed = try_abort!(
@@ -652,12 +952,12 @@ pub(crate) unsafe extern "C" fn next_line_stdin_fused(runtime: *mut c_void) {
             runtime
                 .core
                 .regexes
-                .get_line_stdin_reuse(&runtime.core.vars.rs, read_files, line)
+                .get_line_stdin_reuse(&runtime.core.vars, read_files, line)
         }),
         "unexpected error when reading line from stdin:"
     );
     if changed {
-        runtime.reset_file_vars();
+        try_abort!(runtime, runtime.reset_file_vars());
     }
 }
 
@@ -669,6 +969,7 @@ pub(crate) unsafe extern "C" fn next_file(runtime: *mut c_void) {
             read_files.next_file()
         })
     );
+    try_abort!(runtime, runtime.reset_file_vars());
 }
 
 pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
@@ -679,12 +980,12 @@ pub(crate) unsafe extern "C" fn next_line_stdin(runtime: *mut c_void) -> U128 {
             runtime
                 .core
                 .regexes
-                .get_line_stdin(&runtime.core.vars.rs, read_files)
+                .get_line_stdin(&runtime.core.vars, read_files)
         }),
         "unexpected error when reading line from stdin:"
     );
     if changed {
-        runtime.reset_file_vars();
+        try_abort!(runtime, runtime.reset_file_vars());
     }
     mem::transmute::<Str, U128>(res)
 }
@@ -697,10 +998,14 @@ pub(crate) unsafe extern "C" fn next_line(
     let runtime = &mut *(runtime as *mut Runtime);
     let file = &*(file as *mut Str);
     let res = with_input!(&mut runtime.input_data, |(_, read_files)| {
-        runtime
-            .core
-            .regexes
-            .get_line(file, &runtime.core.vars.rs, read_files, is_file != 0)
+        let timeout = runtime.core.vars.read_timeout(file);
+        runtime.core.regexes.get_line(
+            file,
+            &runtime.core.vars.rs,
+            read_files,
+            is_file != 0,
+            timeout,
+        )
     });
     match res {
         Ok(res) => mem::transmute::<Str, U128>(res),
@@ -767,6 +1072,37 @@ pub(crate) unsafe extern "C" fn split_int(
     res
 }
 
+macro_rules! split_seps_impl {
+    ($name:ident, $map:ident, $patsplit:expr, $key:expr) => {
+        pub(crate) unsafe extern "C" fn $name(
+            runtime: *mut c_void,
+            to_split: *mut c_void,
+            into_arr: *mut c_void,
+            pat: *mut c_void,
+            seps: *mut c_void,
+        ) -> Int {
+            let runtime = &mut *(runtime as *mut Runtime);
+            let into_arr = mem::transmute::<*mut c_void, $map<Str>>(into_arr);
+            let seps = mem::transmute::<*mut c_void, $map<Str>>(seps);
+            let to_split = &*(to_split as *mut Str);
+            let pat = &*(pat as *mut Str);
+            let res = runtime
+                .core
+                .split_seps($patsplit, to_split, pat, &into_arr, &seps, $key);
+            mem::forget((into_arr, seps));
+            match res {
+                Ok(n) => n,
+                Err(e) => fail!(runtime, "failed to split string: {}", e),
+            }
+        }
+    };
+}
+
+split_seps_impl!(split_seps_int, IntMap, false, |i| i);
+split_seps_impl!(split_seps_str, StrMap, false, runtime::convert::<Int, Str>);
+split_seps_impl!(patsplit_int, IntMap, true, |i| i);
+split_seps_impl!(patsplit_str, StrMap, true, runtime::convert::<Int, Str>);
+
 pub(crate) unsafe extern "C" fn get_col(runtime: *mut c_void, col: Int) -> U128 {
     let runtime = &mut *(runtime as *mut Runtime);
     let col_str = with_input!(&mut runtime.input_data, |(line, _)| {
@@ -855,19 +1191,131 @@ pub(crate) unsafe extern "C" fn systime() -> Int {
     seconds as Int
 }
 
-pub(crate) unsafe extern "C" fn encode(format: *mut U128, text: *mut U128) -> U128 {
+pub(crate) unsafe extern "C" fn systime_ms(_runtime: *mut c_void) -> Int {
+    runtime::date_time::systime_ms()
+}
+
+pub(crate) unsafe extern "C" fn systime_us(_runtime: *mut c_void) -> Int {
+    runtime::date_time::systime_us()
+}
+
+pub(crate) unsafe extern "C" fn clock(_runtime: *mut c_void) -> Float {
+    runtime::date_time::clock()
+}
+
+pub(crate) unsafe extern "C" fn sleep(secs: Float) -> Int {
+    runtime::date_time::sleep(secs)
+}
+
+pub(crate) unsafe extern "C" fn encode(runtime: *mut c_void, format: *mut U128, text: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let format = &*(format as *mut Str);
     let text = &*(text as *mut Str);
-    let date_time_text = runtime::encoding::encode(format.as_str(), text.as_str());
-    let res = Str::from(date_time_text);
+    let res = match text.with_bytes(|bs| runtime::encoding::encode(format.as_str(), bs)) {
+        Ok(encoded) => Str::from(encoded),
+        Err(msg) => {
+            try_abort!(runtime, runtime::errors::report(format!("encode: {}", msg)));
+            Str::default()
+        }
+    };
     mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn decode(format: *mut U128, text: *mut U128) -> U128 {
+pub(crate) unsafe extern "C" fn decode(runtime: *mut c_void, format: *mut U128, text: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let format = &*(format as *mut Str);
     let text = &*(text as *mut Str);
-    let date_time_text = runtime::encoding::decode(format.as_str(), text.as_str());
-    let res = Str::from(date_time_text);
+    let res = match runtime::encoding::decode(format.as_str(), text.as_str()) {
+        Ok(bytes) => Str::from(&bytes[..]).unmoor(),
+        Err(msg) => {
+            try_abort!(runtime, runtime::errors::report(format!("decode: {}", msg)));
+            Str::default()
+        }
+    };
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn utf8_valid(text: *mut U128) -> Int {
+    let text = &*(text as *mut Str);
+    text.with_bytes(runtime::utf8::is_utf8) as Int
+}
+
+pub(crate) unsafe extern "C" fn utf8_fix(text: *mut U128) -> U128 {
+    let text = &*(text as *mut Str);
+    let res = match text.with_bytes(runtime::utf8::repair) {
+        Some(fixed) => Str::from(fixed),
+        None => text.clone(),
+    };
+    mem::transmute::<Str, U128>(res)
+}
+
+unsafe fn normalize(text: *mut U128, form: NormalForm) -> U128 {
+    let text = &*(text as *mut Str);
+    let res = text.with_bytes(|bs| runtime::utf8::normalize(bs, form));
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn nfc(text: *mut U128) -> U128 {
+    normalize(text, NormalForm::Nfc)
+}
+
+pub(crate) unsafe extern "C" fn nfd(text: *mut U128) -> U128 {
+    normalize(text, NormalForm::Nfd)
+}
+
+pub(crate) unsafe extern "C" fn nfkc(text: *mut U128) -> U128 {
+    normalize(text, NormalForm::Nfkc)
+}
+
+pub(crate) unsafe extern "C" fn nfkd(text: *mut U128) -> U128 {
+    normalize(text, NormalForm::Nfkd)
+}
+
+pub(crate) unsafe extern "C" fn compress(runtime: *mut c_void, format: *mut U128, text: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let format = &*(format as *mut Str);
+    let text = &*(text as *mut Str);
+    let res = match text.with_bytes(|bs| runtime::encoding::compress(format.as_str(), bs)) {
+        Ok(bytes) => Str::from(&bytes[..]).unmoor(),
+        Err(msg) => {
+            try_abort!(runtime, runtime::errors::report(format!("compress: {}", msg)));
+            Str::default()
+        }
+    };
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn decompress(runtime: *mut c_void, format: *mut U128, text: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let format = &*(format as *mut Str);
+    let text = &*(text as *mut Str);
+    let res = match text.with_bytes(|bs| runtime::encoding::decompress(format.as_str(), bs)) {
+        Ok(bytes) => Str::from(&bytes[..]).unmoor(),
+        Err(msg) => {
+            try_abort!(runtime, runtime::errors::report(format!("decompress: {}", msg)));
+            Str::default()
+        }
+    };
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn iconv(
+    runtime: *mut c_void,
+    text: *mut U128,
+    from: *mut U128,
+    to: *mut U128,
+) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(text as *mut Str);
+    let from = &*(from as *mut Str);
+    let to = &*(to as *mut Str);
+    let res = match text.with_bytes(|bs| runtime::encoding::iconv(bs, from.as_str(), to.as_str())) {
+        Ok(bytes) => Str::from(&bytes[..]).unmoor(),
+        Err(msg) => {
+            try_abort!(runtime, runtime::errors::report(format!("iconv: {}", msg)));
+            Str::default()
+        }
+    };
     mem::transmute::<Str, U128>(res)
 }
 
@@ -886,6 +1334,57 @@ pub(crate) unsafe extern "C" fn digest(algorithm: *mut U128, text: *mut U128) ->
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn file_digest(runtime: *mut c_void, algorithm: *mut U128, path: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let algorithm = &*(algorithm as *mut Str);
+    let path = &*(path as *mut Str);
+    let res = match runtime::crypto::file_digest(algorithm.as_str(), path.as_str()) {
+        Ok(res) => res,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("file_digest: {}", e)));
+            String::new()
+        }
+    };
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn digest_init(runtime: *mut c_void, algorithm: *mut U128, key: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let algorithm = &*(algorithm as *mut Str);
+    let key = &*(key as *mut Str);
+    match runtime.core.digests.init(algorithm.as_str(), key.as_str()) {
+        Ok(handle) => handle,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("digest_init: {}", e)));
+            0
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn digest_update(runtime: *mut c_void, handle: Int, text: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(text as *mut Str);
+    match text.with_bytes(|bs| runtime.core.digests.update(handle, bs)) {
+        Ok(()) => 1,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("digest_update: {}", e)));
+            0
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn digest_final(runtime: *mut c_void, handle: Int) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let res = match runtime.core.digests.finish(handle) {
+        Ok(res) => res,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("digest_final: {}", e)));
+            String::new()
+        }
+    };
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
 pub(crate) unsafe extern "C" fn hmac(algorithm: *mut U128, key: *mut U128, text: *mut U128) -> U128 {
     let algorithm = &*(algorithm as *mut Str);
     let key = &*(key as *mut Str);
@@ -895,23 +1394,54 @@ pub(crate) unsafe extern "C" fn hmac(algorithm: *mut U128, key: *mut U128, text:
     mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn jwt(algorithm: *mut U128, key: *mut U128, payload: *mut c_void) -> U128 {
+pub(crate) unsafe extern "C" fn jwt(runtime: *mut c_void, algorithm: *mut U128, key: *mut U128, payload: *mut c_void) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let algorithm = &*(algorithm as *mut Str);
     let key = &*(key as *mut Str);
     let payload = mem::transmute::<*mut c_void, StrMap<Str>>(payload);
-    let date_time_text = runtime::crypto::jwt(algorithm.as_str(), key.as_str(), &payload);
+    let token = runtime::crypto::jwt(algorithm.as_str(), key.as_str(), &payload);
     mem::forget(payload);
-    let res = Str::from(date_time_text);
-    mem::transmute::<Str, U128>(res)
+    let token = match token {
+        Ok(token) => token,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("jwt: {}", e)));
+            String::new()
+        }
+    };
+    mem::transmute::<Str, U128>(Str::from(token))
 }
 
-pub(crate) unsafe extern "C" fn dejwt(key: *mut U128, token: *mut U128) -> *mut c_void {
+pub(crate) unsafe extern "C" fn dejwt(runtime: *mut c_void, key: *mut U128, token: *mut U128, options: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
     let key = &*(key as *mut Str);
     let token = &*(token as *mut Str);
-    let jwt = runtime::crypto::dejwt(key.as_str(), token.as_str());
+    let options = &*(options as *mut Str);
+    let jwt: StrMap<Str> = match runtime::crypto::dejwt(key.as_str(), token.as_str(), options.as_str()) {
+        Ok(claims) => {
+            runtime.core.vars.set_errno("");
+            claims
+        }
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("dejwt: {}", e)));
+            Default::default()
+        }
+    };
     mem::transmute::<StrMap<Str>, *mut c_void>(jwt)
 }
 
+pub(crate) unsafe extern "C" fn cert(runtime: *mut c_void, source: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let source = &*(source as *mut Str);
+    let info: StrMap<Str> = match runtime::crypto::cert(source.as_str()) {
+        Ok(info) => info,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("cert: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(info)
+}
+
 pub(crate) unsafe extern "C" fn encrypt(mode: *mut U128, plain_text: *mut U128, key: *mut U128) -> U128 {
     let mode = &*(mode as *mut Str);
     let plain_text = &*(plain_text as *mut Str);
@@ -930,8 +1460,14 @@ pub(crate) unsafe extern "C" fn decrypt(mode: *mut U128, encrypted_text: *mut U1
     mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn strftime(rt: *mut c_void, format: *mut U128, timestamp: Int) -> U128 {
+pub(crate) unsafe extern "C" fn strftime(
+    rt: *mut c_void,
+    format: *mut U128,
+    timestamp: Int,
+    timezone: *mut U128,
+) -> U128 {
     let format = &*(format as *mut Str);
+    let timezone = &*(timezone as *mut Str);
     let mut date_time_format = format.to_string();
     if format.is_empty() {
         let rt = &mut *(rt as *mut Runtime);
@@ -942,14 +1478,10 @@ pub(crate) unsafe extern "C" fn strftime(rt: *mut c_void, format: *mut U128, tim
         }
     }
     if date_time_format.is_empty() {
-        date_time_format = "%a %m %e %H:%M:%S %Z %Y".to_owned();
+        date_time_format = runtime::date_time::DEFAULT_FORMAT.to_owned();
     }
-    let timestamp = if timestamp < 0 {
-        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64
-    } else {
-        timestamp as i64
-    };
-    let date_time_text = runtime::date_time::strftime(&date_time_format, timestamp);
+    let date_time_text =
+        runtime::date_time::strftime(&date_time_format, timestamp, timezone.as_str());
     let res = Str::from(date_time_text);
     mem::transmute::<Str, U128>(res)
 }
@@ -966,6 +1498,12 @@ pub(crate) unsafe extern "C" fn strtonum(text: *mut U128) -> Float {
     math_util::strtonum(text.as_str())
 }
 
+pub(crate) unsafe extern "C" fn parse_number(text: *mut U128, locale: *mut U128) -> Float {
+    let text = &*(text as *mut Str);
+    let locale = &*(locale as *mut Str);
+    runtime::locale::parse_number(text.as_str(), locale.as_str())
+}
+
 pub(crate) unsafe extern "C" fn capitalize(text: *mut U128) -> U128 {
     let text = &*(text as *mut Str);
     let res = text.capitalize();
@@ -977,6 +1515,13 @@ pub(crate) unsafe extern "C" fn format_bytes(size: Int) -> U128 {
     mem::transmute::<Str, U128>(Str::from(res))
 }
 
+pub(crate) unsafe extern "C" fn format_num(x: Float, pattern: *mut U128, locale: *mut U128) -> U128 {
+    let pattern = &*(pattern as *mut Str);
+    let locale = &*(locale as *mut Str);
+    let res = runtime::locale::format_num(x, pattern.as_str(), locale.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
 pub(crate) unsafe extern "C" fn to_bytes(text: *mut U128) -> Int {
     let text = &*(text as *mut Str);
     math_util::to_bytes(text.as_str())
@@ -1072,6 +1617,49 @@ pub(crate) unsafe extern "C" fn strcmp(text1: *mut U128, text2: *mut U128) -> In
     runtime::string_util::strcmp(text1.as_str(), text2.as_str())
 }
 
+pub(crate) unsafe extern "C" fn natcmp(text1: *mut U128, text2: *mut U128) -> Int {
+    let text1 = &*(text1 as *mut Str);
+    let text2 = &*(text2 as *mut Str);
+    runtime::string_util::natcmp(text1.as_str(), text2.as_str())
+}
+
+pub(crate) unsafe extern "C" fn levenshtein(text1: *mut U128, text2: *mut U128) -> Int {
+    let text1 = &*(text1 as *mut Str);
+    let text2 = &*(text2 as *mut Str);
+    runtime::string_util::levenshtein(text1.as_str(), text2.as_str())
+}
+
+pub(crate) unsafe extern "C" fn similarity(text1: *mut U128, text2: *mut U128) -> Float {
+    let text1 = &*(text1 as *mut Str);
+    let text2 = &*(text2 as *mut Str);
+    runtime::string_util::similarity(text1.as_str(), text2.as_str())
+}
+
+pub(crate) unsafe extern "C" fn diff(text1: *mut U128, text2: *mut U128) -> U128 {
+    let text1 = &*(text1 as *mut Str);
+    let text2 = &*(text2 as *mut Str);
+    let res = runtime::string_util::diff(text1.as_str(), text2.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn soundex(text: *mut U128) -> U128 {
+    let text = &*(text as *mut Str);
+    let res = runtime::string_util::soundex(text.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn metaphone(text: *mut U128) -> U128 {
+    let text = &*(text as *mut Str);
+    let res = runtime::string_util::metaphone(text.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn fuzzy_match(text1: *mut U128, text2: *mut U128, threshold: Float) -> Int {
+    let text1 = &*(text1 as *mut Str);
+    let text2 = &*(text2 as *mut Str);
+    runtime::string_util::fuzzy_match(text1.as_str(), text2.as_str(), threshold)
+}
+
 
 pub(crate) unsafe extern "C" fn mask(text: *mut U128) -> U128 {
     let text = &*(text as *mut Str);
@@ -1184,16 +1772,99 @@ pub(crate) unsafe extern "C" fn kv_clear(namespace: *mut U128) {
     runtime::kv::kv_clear(namespace.as_str());
 }
 
-pub(crate) unsafe extern "C" fn read_all(path: *mut U128) -> U128 {
+pub(crate) unsafe extern "C" fn read_all(runtime: *mut c_void, path: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let path = &*(path as *mut Str);
-    let value = runtime::string_util::read_all(path.as_str());
-    mem::transmute::<Str, U128>(Str::from(value))
+    let res = match runtime::string_util::read_all(path.as_str()) {
+        Ok(bytes) => Str::from(&bytes[..]).unmoor(),
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("read_all: {}", e)));
+            Str::default()
+        }
+    };
+    mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn write_all(path: *mut U128, content: *mut U128) {
+pub(crate) unsafe extern "C" fn read_lines(runtime: *mut c_void, path: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    let res: IntMap<Str> = match runtime::string_util::read_lines(path.as_str()) {
+        Ok(lines) => lines,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("read_lines: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<IntMap<Str>, *mut c_void>(res)
+}
+
+pub(crate) unsafe extern "C" fn write_all(runtime: *mut c_void, path: *mut U128, content: *mut U128) {
+    let runtime = &mut *(runtime as *mut Runtime);
     let path = &*(path as *mut Str);
     let content = &*(content as *mut Str);
-    runtime::string_util::write_all(path.as_str(), content.as_str());
+    try_abort!(runtime, runtime::string_util::write_str(path, content, false));
+}
+
+pub(crate) unsafe extern "C" fn append_all(runtime: *mut c_void, path: *mut U128, content: *mut U128) {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    let content = &*(content as *mut Str);
+    try_abort!(runtime, runtime::string_util::write_str(path, content, true));
+}
+
+pub(crate) unsafe extern "C" fn glob(runtime: *mut c_void, pattern: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let pattern = &*(pattern as *mut Str);
+    let res: IntMap<Str> = match runtime::os_util::glob(pattern.as_str()) {
+        Ok(paths) => paths,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("glob: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<IntMap<Str>, *mut c_void>(res)
+}
+
+pub(crate) unsafe extern "C" fn stat(runtime: *mut c_void, path: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    let res: StrMap<Str> = match runtime::os_util::stat(path.as_str()) {
+        Ok(info) => info,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("stat: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(res)
+}
+
+pub(crate) unsafe extern "C" fn exists(path: *mut U128) -> Int {
+    let path = &*(path as *mut Str);
+    runtime::os_util::exists(path.as_str()) as Int
+}
+
+pub(crate) unsafe extern "C" fn mkdirp(runtime: *mut c_void, path: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    match runtime::os_util::mkdirp(path.as_str()) {
+        Ok(()) => 1,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("mkdirp: {}", e)));
+            0
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn rmfile(runtime: *mut c_void, path: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    match runtime::os_util::rmfile(path.as_str()) {
+        Ok(()) => 1,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("rmfile: {}", e)));
+            0
+        }
+    }
 }
 
 pub(crate) unsafe extern "C" fn log_debug(runtime: *mut c_void, message: *mut U128) {
@@ -1230,22 +1901,25 @@ pub(crate) unsafe extern "C" fn publish(namespace: *mut U128, body: *mut U128) {
     runtime::network::publish(namespace.as_str(), body.as_str());
 }
 
-pub(crate) unsafe extern "C" fn bf_insert(item: *mut U128, group: *mut U128) {
+pub(crate) unsafe extern "C" fn bf_insert(runtime: *mut c_void, item: *mut U128, group: *mut U128) {
+    let runtime = &mut *(runtime as *mut Runtime);
     let item = &*(item as *mut Str);
     let group = &*(group as *mut Str);
-    runtime::encoding::bf_insert(item.as_str(), group.as_str());
+    item.with_bytes(|bs| runtime.core.sketches.bf_insert(bs, group.as_str()));
 }
 
-pub(crate) unsafe extern "C" fn bf_contains(item: *mut U128, group: *mut U128) -> Int {
+pub(crate) unsafe extern "C" fn bf_contains(runtime: *mut c_void, item: *mut U128, group: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
     let item = &*(item as *mut Str);
     let group = &*(group as *mut Str);
-    runtime::encoding::bf_contains(item.as_str(), group.as_str())
+    item.with_bytes(|bs| runtime.core.sketches.bf_contains(bs, group.as_str()))
 }
 
-pub(crate) unsafe extern "C" fn bf_icontains(item: *mut U128, group: *mut U128) -> Int {
+pub(crate) unsafe extern "C" fn bf_icontains(runtime: *mut c_void, item: *mut U128, group: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
     let item = &*(item as *mut Str);
     let group = &*(group as *mut Str);
-    runtime::encoding::bf_icontains(item.as_str(), group.as_str())
+    item.with_bytes(|bs| runtime.core.sketches.bf_icontains(bs, group.as_str()))
 }
 
 pub(crate) unsafe extern "C" fn fake(data: *mut U128, locale: *mut U128) -> U128 {
@@ -1256,9 +1930,10 @@ pub(crate) unsafe extern "C" fn fake(data: *mut U128, locale: *mut U128) -> U128
 }
 
 
-pub(crate) unsafe extern "C" fn mktime(date_time_text: *mut U128, timezone: Int) -> Int {
+pub(crate) unsafe extern "C" fn mktime(date_time_text: *mut U128, timezone: *mut U128) -> Int {
     let dt_text = &*(date_time_text as *mut Str);
-    runtime::date_time::mktime(dt_text.as_str(), timezone) as Int
+    let timezone = &*(timezone as *mut Str);
+    runtime::date_time::mktime(dt_text.as_str(), timezone.as_str()) as Int
 }
 
 pub(crate) unsafe extern "C" fn duration(expr: *mut U128) -> Int {
@@ -1282,6 +1957,118 @@ pub(crate) unsafe extern "C" fn max(first: *mut U128, second: *mut U128, third:
     mem::transmute::<Str, U128>(Str::from(max_item))
 }
 
+pub(crate) unsafe extern "C" fn dec_add(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::decimal::add(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn dec_sub(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::decimal::sub(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn dec_mul(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::decimal::mul(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn dec_div(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::decimal::div(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn dec_cmp(x: *mut U128, y: *mut U128) -> Int {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    runtime::decimal::cmp(x.as_str(), y.as_str())
+}
+
+pub(crate) unsafe extern "C" fn dec_fmt(x: *mut U128, scale: Int) -> U128 {
+    let x = &*(x as *mut Str);
+    let res = runtime::decimal::fmt(x.as_str(), scale);
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_add(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::add(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_sub(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::sub(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_mul(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::mul(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_div(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::div(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_mod(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::rem(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_pow(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::bignum::pow(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn big_cmp(x: *mut U128, y: *mut U128) -> Int {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    runtime::bignum::cmp(x.as_str(), y.as_str())
+}
+
+pub(crate) unsafe extern "C" fn big_is_false(s: *mut U128) -> Int {
+    let s = &*(s as *mut Str);
+    runtime::bignum::is_false(s.as_str()) as Int
+}
+
+pub(crate) unsafe extern "C" fn u64_add(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::math_util::u64_add(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn u64_mul(x: *mut U128, y: *mut U128) -> U128 {
+    let x = &*(x as *mut Str);
+    let y = &*(y as *mut Str);
+    let res = runtime::math_util::u64_mul(x.as_str(), y.as_str());
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
+pub(crate) unsafe extern "C" fn u64_str(x: Int) -> U128 {
+    let res = runtime::math_util::u64_str(x);
+    mem::transmute::<Str, U128>(Str::from(res))
+}
+
 pub(crate) unsafe extern "C" fn seq(start: Float, step: Float, end: Float) -> *mut c_void {
     let arr = math_util::seq(start, step, end);
     mem::transmute::<IntMap<Float>, *mut c_void>(arr)
@@ -1335,13 +2122,39 @@ pub(crate) unsafe extern "C" fn join_cols(
     mem::transmute::<Str, U128>(res)
 }
 
+pub(crate) unsafe extern "C" fn select_cols(
+    runtime: *mut c_void,
+    spec: *mut U128,
+    sep: *mut U128,
+    esc: Int,
+) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let res = try_abort!(
+        runtime,
+        with_input!(&mut runtime.input_data, |(line, _)| {
+            runtime::splitter::select_cols(
+                line,
+                &*(spec as *mut Str),
+                &*(sep as *mut Str),
+                &runtime.core.vars.fi,
+                &runtime.core.vars.fs,
+                &runtime.core.vars.ofs,
+                &mut runtime.core.regexes,
+                esc,
+            )
+        }),
+        "select:"
+    );
+    mem::transmute::<Str, U128>(res)
+}
+
 pub(crate) unsafe extern "C" fn to_upper_ascii(s: *mut U128) -> U128 {
-    let res = (*(s as *mut Str as *const Str)).to_upper_ascii();
+    let res = (*(s as *mut Str as *const Str)).to_upper();
     mem::transmute::<Str, U128>(res)
 }
 
 pub(crate) unsafe extern "C" fn to_lower_ascii(s: *mut U128) -> U128 {
-    let res = (*(s as *mut Str as *const Str)).to_lower_ascii();
+    let res = (*(s as *mut Str as *const Str)).to_lower();
     mem::transmute::<Str, U128>(res)
 }
 
@@ -1360,6 +2173,40 @@ pub(crate) unsafe extern "C" fn url(s: *mut U128) -> *mut c_void {
     mem::transmute::<StrMap<Str>, *mut c_void>(url_obj)
 }
 
+pub(crate) unsafe extern "C" fn ua_parse(s: *mut U128) -> *mut c_void {
+    let s = &*(s as *mut Str);
+    mem::transmute::<StrMap<Str>, *mut c_void>(string_util::ua_parse(s.as_str()))
+}
+
+pub(crate) unsafe extern "C" fn logfmt(s: *mut U128) -> *mut c_void {
+    let s = &*(s as *mut Str);
+    mem::transmute::<StrMap<Str>, *mut c_void>(string_util::logfmt(s.as_str()))
+}
+
+pub(crate) unsafe extern "C" fn map_str_int_to_logfmt(arr: *mut c_void, order: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Int>>(arr);
+    let order = &*(order as *mut Str);
+    let text = string_util::to_logfmt(&obj, order.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_float_to_logfmt(arr: *mut c_void, order: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Float>>(arr);
+    let order = &*(order as *mut Str);
+    let text = string_util::to_logfmt(&obj, order.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_str_to_logfmt(arr: *mut c_void, order: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let order = &*(order as *mut Str);
+    let text = string_util::to_logfmt(&obj, order.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
 pub(crate) unsafe extern "C" fn record(src: *mut U128) -> *mut c_void {
     let src = &*(src as *mut Str);
     let arr_obj = runtime::string_util::record(src.as_str());
@@ -1404,6 +2251,52 @@ pub(crate) unsafe extern "C" fn datetime(timestamp: *mut U128) -> *mut c_void {
     mem::transmute::<StrMap<Int>, *mut c_void>(result)
 }
 
+pub(crate) unsafe extern "C" fn dateparse(
+    runtime: *mut c_void,
+    text: *mut U128,
+    format: *mut U128,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(text as *mut Str);
+    let format = &*(format as *mut Str);
+    match runtime::date_time::dateparse(text.as_str(), format.as_str()) {
+        Ok(epoch) => epoch,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(e));
+            -1
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn dateadd(
+    runtime: *mut c_void,
+    epoch: Int,
+    n: Int,
+    unit: *mut U128,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let unit = &*(unit as *mut Str);
+    match runtime::date_time::dateadd(epoch, n, unit.as_str()) {
+        Ok(epoch) => epoch,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(e));
+            -1
+        }
+    }
+}
+
+pub(crate) unsafe extern "C" fn datediff(runtime: *mut c_void, a: Int, b: Int, unit: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let unit = &*(unit as *mut Str);
+    match runtime::date_time::datediff(a, b, unit.as_str()) {
+        Ok(n) => n,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(e));
+            -1
+        }
+    }
+}
+
 pub(crate) unsafe extern "C" fn type_of_array() -> U128 {
     mem::transmute::<Str, U128>(Str::from("array"))
 }
@@ -1462,12 +2355,65 @@ pub(crate) unsafe extern "C" fn is_str_num(text: *mut U128) -> Int {
     }
 }
 
+pub(crate) unsafe extern "C" fn is_nan(x: Float) -> Int {
+    x.is_nan() as Int
+}
+
+pub(crate) unsafe extern "C" fn is_inf(x: Float) -> Int {
+    x.is_infinite() as Int
+}
+
 pub(crate) unsafe extern "C" fn is_format(format: *mut U128, text: *mut U128) -> Int {
     let format = &*(format as *mut Str);
     let text = &*(text as *mut Str);
     string_util::is_format(format.as_str(), text.as_str())
 }
 
+pub(crate) unsafe extern "C" fn cidr_contains(net: *mut U128, addr: *mut U128) -> Int {
+    let net = &*(net as *mut Str);
+    let addr = &*(addr as *mut Str);
+    ip::cidr_contains(net.as_str(), addr.as_str())
+}
+
+pub(crate) unsafe extern "C" fn ip2int(ip: *mut U128) -> Int {
+    let ip = &*(ip as *mut Str);
+    ip::ip_to_int(ip.as_str())
+}
+
+pub(crate) unsafe extern "C" fn int2ip(n: Int) -> U128 {
+    mem::transmute::<Str, U128>(Str::from(ip::int_to_ip(n)))
+}
+
+pub(crate) unsafe extern "C" fn ip_version(ip: *mut U128) -> Int {
+    let ip = &*(ip as *mut Str);
+    ip::ip_version(ip.as_str())
+}
+
+pub(crate) unsafe extern "C" fn geoip(runtime: *mut c_void, ip: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let ip = &*(ip as *mut Str);
+    let info: StrMap<Str> = match runtime::geoip::geoip(ip.as_str()) {
+        Ok(info) => info,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("geoip: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(info)
+}
+
+pub(crate) unsafe extern "C" fn geoip_db(runtime: *mut c_void, path: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let path = &*(path as *mut Str);
+    match runtime::geoip::geoip_db(path.as_str()) {
+        Ok(()) => 1,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("geoip_db: {}", e)));
+            0
+        }
+    }
+}
+
 
 pub(crate) unsafe extern "C" fn shlex(text: *mut U128) -> *mut c_void {
     let text = &*(text as *mut Str);
@@ -1549,6 +2495,185 @@ pub(crate) unsafe extern "C" fn from_json(src: *mut U128) -> *mut c_void {
     mem::transmute::<StrMap<Str>, *mut c_void>(json_obj)
 }
 
+pub(crate) unsafe extern "C" fn from_toml(runtime: *mut c_void, src: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(src as *mut Str);
+    let map: StrMap<Str> = match runtime::toml::from_toml(text.as_str()) {
+        Ok(map) => map,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("from_toml: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(map)
+}
+
+unsafe fn map_to_toml<V: runtime::toml::TomlScalar>(runtime: *mut c_void, arr: *mut c_void, like: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let obj = mem::transmute::<*mut c_void, StrMap<V>>(arr);
+    let like = &*(like as *mut Str);
+    let res = runtime::toml::to_toml(&obj, like.as_str());
+    mem::forget(obj);
+    let text = match res {
+        Ok(text) => text,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("to_toml: {}", e)));
+            String::new()
+        }
+    };
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_int_to_toml(runtime: *mut c_void, arr: *mut c_void, like: *mut U128) -> U128 {
+    map_to_toml::<Int>(runtime, arr, like)
+}
+
+pub(crate) unsafe extern "C" fn map_str_float_to_toml(runtime: *mut c_void, arr: *mut c_void, like: *mut U128) -> U128 {
+    map_to_toml::<Float>(runtime, arr, like)
+}
+
+pub(crate) unsafe extern "C" fn map_str_str_to_toml(runtime: *mut c_void, arr: *mut c_void, like: *mut U128) -> U128 {
+    map_to_toml::<Str>(runtime, arr, like)
+}
+
+pub(crate) unsafe extern "C" fn toml_value(runtime: *mut c_void, text: *mut U128, path: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(text as *mut Str);
+    let path = &*(path as *mut Str);
+    let value = match runtime::toml::toml_value(text.as_str(), path.as_str()) {
+        Ok(value) => value,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("toml_value: {}", e)));
+            String::new()
+        }
+    };
+    mem::transmute::<Str, U128>(Str::from(value))
+}
+
+pub(crate) unsafe extern "C" fn from_ini(text: *mut U128) -> *mut c_void {
+    let text = &*(text as *mut Str);
+    mem::transmute::<StrMap<Str>, *mut c_void>(runtime::config_util::from_ini(text.as_str()))
+}
+
+pub(crate) unsafe extern "C" fn map_str_int_to_ini(arr: *mut c_void) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Int>>(arr);
+    let text = runtime::config_util::to_ini(&obj);
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_float_to_ini(arr: *mut c_void) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Float>>(arr);
+    let text = runtime::config_util::to_ini(&obj);
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_str_to_ini(arr: *mut c_void) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let text = runtime::config_util::to_ini(&obj);
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn prop_get(text: *mut U128, key: *mut U128) -> U128 {
+    let text = &*(text as *mut Str);
+    let key = &*(key as *mut Str);
+    let value = runtime::config_util::prop_get(text.as_str(), key.as_str());
+    mem::transmute::<Str, U128>(Str::from(value))
+}
+
+pub(crate) unsafe extern "C" fn map_str_int_to_xml(arr: *mut c_void, root: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Int>>(arr);
+    let root = &*(root as *mut Str);
+    let text = runtime::xml::to_xml(&obj, root.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_float_to_xml(arr: *mut c_void, root: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Float>>(arr);
+    let root = &*(root as *mut Str);
+    let text = runtime::xml::to_xml(&obj, root.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn map_str_str_to_xml(arr: *mut c_void, root: *mut U128) -> U128 {
+    let obj = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let root = &*(root as *mut Str);
+    let text = runtime::xml::to_xml(&obj, root.as_str());
+    mem::forget(obj);
+    mem::transmute::<Str, U128>(Str::from(text))
+}
+
+pub(crate) unsafe extern "C" fn xml_update(runtime: *mut c_void, text: *mut U128, xpath: *mut U128, value: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let text = &*(text as *mut Str);
+    let xpath = &*(xpath as *mut Str);
+    let value = &*(value as *mut Str);
+    let res = match runtime::xml::xml_update(text.as_str(), xpath.as_str(), value.as_str()) {
+        Ok(updated) => Str::from(updated),
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("xml_update: {}", e)));
+            text.clone()
+        }
+    };
+    mem::transmute::<Str, U128>(res)
+}
+
+pub(crate) unsafe extern "C" fn html_table(html: *mut U128, index: Int) -> *mut c_void {
+    let html = &*(html as *mut Str);
+    mem::transmute::<IntMap<Str>, *mut c_void>(runtime::html::html_table(html.as_str(), index))
+}
+
+pub(crate) unsafe extern "C" fn from_msgpack(runtime: *mut c_void, src: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let src = &*(src as *mut Str);
+    let map: StrMap<Str> = match src.with_bytes(runtime::binfmt::from_msgpack) {
+        Ok(map) => map,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("from_msgpack: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(map)
+}
+
+pub(crate) unsafe extern "C" fn from_cbor(runtime: *mut c_void, src: *mut U128) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let src = &*(src as *mut Str);
+    let map: StrMap<Str> = match src.with_bytes(runtime::binfmt::from_cbor) {
+        Ok(map) => map,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("from_cbor: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(map)
+}
+
+pub(crate) unsafe extern "C" fn from_protobuf(
+    runtime: *mut c_void,
+    src: *mut U128,
+    descriptors: *mut U128,
+    message: *mut U128,
+) -> *mut c_void {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let src = &*(src as *mut Str);
+    let descriptors = &*(descriptors as *mut Str);
+    let message = &*(message as *mut Str);
+    let res = src.with_bytes(|bytes| runtime::binfmt::from_protobuf(bytes, descriptors.as_str(), message.as_str()));
+    let map: StrMap<Str> = match res {
+        Ok(map) => map,
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("from_protobuf: {}", e)));
+            Default::default()
+        }
+    };
+    mem::transmute::<StrMap<Str>, *mut c_void>(map)
+}
+
 pub(crate) unsafe extern "C" fn map_int_int_to_json(arr: *mut c_void) -> U128 {
     let obj = mem::transmute::<*mut c_void, IntMap<Int>>(arr);
     let json_text = runtime::json::map_int_int_to_json(&obj);
@@ -1668,34 +2793,36 @@ pub(crate) unsafe extern "C" fn dump_null() {
     eprintln!("Null")
 }
 
-pub(crate) unsafe extern "C" fn map_int_int_asort(arr: *mut c_void, target: *mut c_void) -> Int {
-    let obj = mem::transmute::<*mut c_void, IntMap<Int>>(arr);
-    let target_obj = mem::transmute::<*mut c_void, IntMap<Int>>(target);
-    math_util::map_int_int_asort(&obj, &target_obj);
-    let result = obj.len() as Int;
+unsafe fn asort_impl<V: runtime::MapValue + math_util::Sortable>(
+    runtime: *mut c_void,
+    arr: *mut c_void,
+    target: *mut c_void,
+    order: *mut c_void,
+) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let order = &*(order as *mut Str);
+    let order = try_abort!(
+        runtime,
+        order.with_bytes(|bs| math_util::SortOrder::parse(&String::from_utf8_lossy(bs)))
+    );
+    let obj = mem::transmute::<*mut c_void, IntMap<V>>(arr);
+    let target_obj = mem::transmute::<*mut c_void, IntMap<V>>(target);
+    let result = math_util::asort(&obj, &target_obj, order);
     mem::forget(obj);
     mem::forget(target_obj);
     result
 }
 
-pub(crate) unsafe extern "C" fn map_int_float_asort(arr: *mut c_void, target: *mut c_void) -> Int {
-    let obj = mem::transmute::<*mut c_void, IntMap<Float>>(arr);
-    let target_obj = mem::transmute::<*mut c_void, IntMap<Float>>(target);
-    math_util::map_int_float_asort(&obj, &target_obj);
-    let result = obj.len() as Int;
-    mem::forget(obj);
-    mem::forget(target_obj);
-    result
+pub(crate) unsafe extern "C" fn map_int_int_asort(runtime: *mut c_void, arr: *mut c_void, target: *mut c_void, order: *mut c_void) -> Int {
+    asort_impl::<Int>(runtime, arr, target, order)
 }
 
-pub(crate) unsafe extern "C" fn map_int_str_asort(arr: *mut c_void, target: *mut c_void) -> Int {
-    let obj = mem::transmute::<*mut c_void, IntMap<Str>>(arr);
-    let target_obj = mem::transmute::<*mut c_void, IntMap<Str>>(target);
-    math_util::map_int_str_asort(&obj, &target_obj);
-    let result = obj.len() as Int;
-    mem::forget(obj);
-    mem::forget(target_obj);
-    result
+pub(crate) unsafe extern "C" fn map_int_float_asort(runtime: *mut c_void, arr: *mut c_void, target: *mut c_void, order: *mut c_void) -> Int {
+    asort_impl::<Float>(runtime, arr, target, order)
+}
+
+pub(crate) unsafe extern "C" fn map_int_str_asort(runtime: *mut c_void, arr: *mut c_void, target: *mut c_void, order: *mut c_void) -> Int {
+    asort_impl::<Str>(runtime, arr, target, order)
 }
 
 pub(crate) unsafe extern "C" fn map_int_int_join(arr: *mut c_void, sep: *mut U128) -> U128 {
@@ -1825,20 +2952,32 @@ pub(crate) unsafe extern "C" fn http_post(url: *mut U128, headers: *mut c_void,
     mem::transmute::<StrMap<Str>, *mut c_void>(resp)
 }
 
-pub(crate) unsafe extern "C" fn s3_get(bucket: *mut U128, object_name: *mut U128) -> U128 {
+pub(crate) unsafe extern "C" fn s3_get(runtime: *mut c_void, bucket: *mut U128, object_name: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let bucket = &*(bucket as *mut Str);
     let object_name = &*(object_name as *mut Str);
-    let body = runtime::s3::get_object(bucket.as_str(), object_name.as_str()).unwrap();
-    let res = Str::from(body);
+    let res = match runtime::s3::get_object(bucket.as_str(), object_name.as_str()) {
+        Ok(body) => Str::from(body),
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("s3_get: {}", e)));
+            Str::default()
+        }
+    };
     mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn s3_put(bucket: *mut U128, object_name: *mut U128, body: *mut U128) -> U128 {
+pub(crate) unsafe extern "C" fn s3_put(runtime: *mut c_void, bucket: *mut U128, object_name: *mut U128, body: *mut U128) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let bucket = &*(bucket as *mut Str);
     let object_name = &*(object_name as *mut Str);
     let body = &*(body as *mut Str);
-    let etag = runtime::s3::put_object(bucket.as_str(), object_name.as_str(), body.as_str()).unwrap().etag;
-    let res = Str::from(etag);
+    let res = match runtime::s3::put_object(bucket.as_str(), object_name.as_str(), body.as_str()) {
+        Ok(resp) => Str::from(resp.etag),
+        Err(e) => {
+            try_abort!(runtime, runtime::errors::report(format!("s3_put: {}", e)));
+            Str::default()
+        }
+    };
     mem::transmute::<Str, U128>(res)
 }
 
@@ -1869,7 +3008,7 @@ pub(crate) unsafe extern "C" fn starts_with_const(
     let other = slice::from_raw_parts(base, len as usize);
     let s1 = &*(s1 as *const Str);
     let s1_bytes = &*s1.get_bytes();
-    ((s1_bytes.len() >= other.len()) && &s1_bytes[..other.len()] == other) as Int
+    runtime::str_impl::starts_with(s1_bytes, other) as Int
 }
 
 pub(crate) unsafe extern "C" fn concat(s1: *mut c_void, s2: *mut c_void) -> U128 {
@@ -1917,6 +3056,25 @@ pub(crate) unsafe extern "C" fn match_pat_loc(
     res as Int
 }
 
+pub(crate) unsafe extern "C" fn match_pat_arr(
+    runtime: *mut c_void,
+    s: *mut c_void,
+    pat: *mut c_void,
+    arr: *mut c_void,
+) -> Int {
+    let runtime = runtime as *mut Runtime;
+    let s = &*(s as *mut Str);
+    let pat = &*(pat as *mut Str);
+    let arr = mem::transmute::<*mut c_void, StrMap<Str>>(arr);
+    let res = try_abort!(
+        runtime,
+        (*runtime).core.match_regex_arr(s, pat, &arr),
+        "match_pat_arr:"
+    );
+    mem::forget(arr);
+    res as Int
+}
+
 pub(crate) unsafe extern "C" fn match_const_pat_loc(
     runtime: *mut c_void,
     s: *mut c_void,
@@ -1986,6 +3144,32 @@ pub(crate) unsafe extern "C" fn subst_all(
     nsubs
 }
 
+pub(crate) unsafe extern "C" fn subst_first_const(
+    pat: *mut c_void,
+    s: *mut U128,
+    in_s: *mut U128,
+) -> Int {
+    let pat = &*(pat as *const Regex);
+    let s = &*(s as *mut Str);
+    let in_s = &mut *(in_s as *mut Str);
+    let (subbed, new) = in_s.subst_first(pat, s);
+    *in_s = subbed;
+    new as Int
+}
+
+pub(crate) unsafe extern "C" fn subst_all_const(
+    pat: *mut c_void,
+    s: *mut U128,
+    in_s: *mut U128,
+) -> Int {
+    let pat = &*(pat as *const Regex);
+    let s = &*(s as *mut Str);
+    let in_s = &mut *(in_s as *mut Str);
+    let (subbed, nsubs) = in_s.subst_all(pat, s);
+    *in_s = subbed;
+    nsubs
+}
+
 pub(crate) unsafe extern "C" fn gen_subst(
     runtime: *mut c_void,
     pat: *mut U128,
@@ -2022,10 +3206,15 @@ pub(crate) unsafe extern "C" fn substr(base: *mut U128, l: Int, r: Int) -> U128
     mem::transmute::<Str, U128>(res)
 }
 
-pub(crate) unsafe extern "C" fn char_at(text: *mut U128, index: Int) -> U128 {
+pub(crate) unsafe extern "C" fn char_at(runtime: *mut c_void, text: *mut U128, index: Int) -> U128 {
+    let runtime = &mut *(runtime as *mut Runtime);
     let text = &*(text as *mut Str);
-    let index = (index - 1) as usize;
-    let res = text.char_at(index);
+    let res = if index <= 0 {
+        try_abort!(runtime, runtime::errors::report(runtime::str_impl::char_at_error(index)));
+        Str::default()
+    } else {
+        text.char_at((index - 1) as usize)
+    };
     mem::transmute::<Str, U128>(res)
 }
 
@@ -2074,6 +3263,10 @@ pub(crate) unsafe extern "C" fn float_to_str(f: Float) -> U128 {
     mem::transmute::<Str, U128>(runtime::convert::<Float, Str>(f))
 }
 
+pub(crate) unsafe extern "C" fn float_to_out_str(f: Float) -> U128 {
+    mem::transmute::<Str, U128>(Str::from_float(f, NumberFormat::Output))
+}
+
 pub(crate) unsafe extern "C" fn str_to_int(s: *mut c_void) -> Int {
     let s = &*(s as *mut Str);
     math_util::strtoint(s.as_str())
@@ -2204,8 +3397,7 @@ macro_rules! str_compare_inner {
         pub(crate) unsafe extern "C" fn $name(s1: *mut c_void, s2: *mut c_void) -> Int {
             let s1 = &*(s1 as *mut Str);
             let s2 = &*(s2 as *mut Str);
-            let res = s1.with_bytes(|bs1| s2.with_bytes(|bs2| bs1 $op bs2)) as Int;
-            res
+            (s1.compare(s2) $op std::cmp::Ordering::Equal) as Int
         }
     }
 }
@@ -2265,6 +3457,7 @@ pub(crate) unsafe extern "C" fn print_all_stdout(rt: *mut c_void, args: *mut usi
     let args_wrapped: &[&Str] =
         slice::from_raw_parts(args as *const usize as *const &Str, num_args as usize);
     let rt = rt as *mut Runtime;
+    try_abort!(rt, runtime::errors::check());
     try_silent_abort!(rt, (*rt).core.write_files.write_all(args_wrapped, None))
 }
 
@@ -2278,6 +3471,7 @@ pub(crate) unsafe extern "C" fn print_all_file(
     let args_wrapped: &[&Str] =
         slice::from_raw_parts(args as *const usize as *const &Str, num_args as usize);
     let rt = rt as *mut Runtime;
+    try_abort!(rt, runtime::errors::check());
     let output_wrapped = Some((
         &*(output as *mut Str),
         try_abort!(rt, FileSpec::try_from(append)),
@@ -2301,6 +3495,7 @@ pub(crate) unsafe extern "C" fn printf_impl_file(
     output: *mut U128,
     append: Int,
 ) {
+    try_abort!(rt, runtime::errors::check());
     let output_wrapped = Some((
         &*(output as *mut Str),
         try_abort!(rt, FileSpec::try_from(append)),
@@ -2341,6 +3536,7 @@ pub(crate) unsafe extern "C" fn printf_impl_stdout(
     tys: *mut u32,
     num_args: Int,
 ) {
+    try_abort!(rt, runtime::errors::check());
     let format_args = wrap_args(&mut *(rt as *mut _), args, tys, num_args);
     let res = (*(rt as *mut Runtime)).core.write_files.printf(
         None,
@@ -2352,6 +3548,12 @@ pub(crate) unsafe extern "C" fn printf_impl_stdout(
     }
 }
 
+pub(crate) unsafe extern "C" fn fflush(runtime: *mut c_void, file: *mut U128) -> Int {
+    let runtime = &mut *(runtime as *mut Runtime);
+    let file = &*(file as *mut Str);
+    runtime.core.write_files.fflush(file)
+}
+
 pub(crate) unsafe extern "C" fn close_file(rt: *mut c_void, file: *mut U128) {
     let rt = &mut *(rt as *mut Runtime);
     let file = &*(file as *mut Str);
@@ -2396,7 +3598,19 @@ pub(crate) unsafe extern "C" fn _frawk_floor(f: Float) -> Float {
 }
 
 pub(crate) unsafe extern "C" fn _frawk_round(f: Float) -> Float {
-    f.round()
+    runtime::rounding::round(f)
+}
+
+pub(crate) unsafe extern "C" fn _frawk_round_to(x: Float, digits: Float) -> Float {
+    runtime::rounding::round_to(x, digits)
+}
+
+pub(crate) unsafe extern "C" fn _frawk_floor_to(x: Float, step: Float) -> Float {
+    runtime::rounding::floor_to(x, step)
+}
+
+pub(crate) unsafe extern "C" fn _frawk_ceil_to(x: Float, step: Float) -> Float {
+    runtime::rounding::ceil_to(x, step)
 }
 
 pub(crate) unsafe extern "C" fn _frawk_atan(f: Float) -> Float {
@@ -2570,6 +3784,83 @@ macro_rules! map_impl {
                 drop_map_generic::<$k, $v>(map)
             }
 
+            pub(crate) unsafe extern "C" fn [<keys_ $ty>](map: *mut c_void) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                let res = runtime::math_util::map_keys(&map);
+                mem::forget(map);
+                mem::transmute::<runtime::IntMap<$k>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<values_ $ty>](map: *mut c_void) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                let res = runtime::math_util::map_values(&map);
+                mem::forget(map);
+                mem::transmute::<runtime::IntMap<$v>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<sorted_keys_ $ty>](runtime: *mut c_void, map: *mut c_void, order: *mut c_void) -> *mut c_void {
+                sort_keys::<$k, $v>(runtime, map, order, false)
+            }
+
+            pub(crate) unsafe extern "C" fn [<sort_by_value_ $ty>](runtime: *mut c_void, map: *mut c_void, order: *mut c_void) -> *mut c_void {
+                sort_keys::<$k, $v>(runtime, map, order, true)
+            }
+
+            pub(crate) unsafe extern "C" fn [<invert_ $ty>](map: *mut c_void) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                let res: runtime::SharedMap<inv_key!($v), $k> = runtime::math_util::map_invert(&map);
+                mem::forget(map);
+                mem::transmute::<runtime::SharedMap<inv_key!($v), $k>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<merge_ $ty>](left: *mut c_void, right: *mut c_void) -> *mut c_void {
+                debug_assert!(!left.is_null() && !right.is_null());
+                let left = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(left);
+                let right = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(right);
+                let res = runtime::math_util::map_merge(&left, &right);
+                mem::forget((left, right));
+                mem::transmute::<runtime::SharedMap<$k, $v>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<copy_ $ty>](dst: *mut c_void, src: *mut c_void) -> Int {
+                debug_assert!(!dst.is_null() && !src.is_null());
+                let dst = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(dst);
+                let src = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(src);
+                let res = runtime::math_util::map_copy(&dst, &src);
+                mem::forget((dst, src));
+                res
+            }
+
+            pub(crate) unsafe extern "C" fn [<equals_ $ty>](left: *mut c_void, right: *mut c_void) -> Int {
+                debug_assert!(!left.is_null() && !right.is_null());
+                let left = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(left);
+                let right = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(right);
+                let res = runtime::math_util::map_equals(&left, &right) as Int;
+                mem::forget((left, right));
+                res
+            }
+
+            pub(crate) unsafe extern "C" fn [<choice_ $ty>](runtime: *mut c_void, map: *mut c_void) -> out_ty!($v) {
+                debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                let res = runtime::math_util::map_choice(&mut runtime.core.rng, &map);
+                mem::forget(map);
+                convert_out!($v, res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<sample_ $ty>](runtime: *mut c_void, map: *mut c_void, k: Int) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
+                let res = runtime::math_util::map_sample(&mut runtime.core.rng, &map, k);
+                mem::forget(map);
+                mem::transmute::<runtime::IntMap<$v>, *mut c_void>(res)
+            }
+
             pub(crate) unsafe extern "C" fn [<inc_int_ $ty>](map: *mut c_void, k: in_ty!($k), by: Int) -> out_ty!($v) {
                 debug_assert!(!map.is_null());
                 let map = mem::transmute::<*mut c_void, runtime::SharedMap<$k, $v>>(map);
@@ -2591,6 +3882,40 @@ macro_rules! map_impl {
     };
 }
 
+unsafe fn sort_keys<K, V>(
+    runtime: *mut c_void,
+    map: *mut c_void,
+    order: *mut c_void,
+    by_value: bool,
+) -> *mut c_void
+where
+    K: runtime::math_util::SortKey + runtime::math_util::Sortable,
+    V: runtime::math_util::Sortable + Clone,
+{
+    use runtime::math_util::{sort_by_value, sorted_keys, SortOrder};
+    let runtime = &mut *(runtime as *mut Runtime);
+    let order = &*(order as *mut Str);
+    let order = try_abort!(
+        runtime,
+        order.with_bytes(|bs| SortOrder::parse(&String::from_utf8_lossy(bs)))
+    );
+    let map = mem::transmute::<*mut c_void, runtime::SharedMap<K, V>>(map);
+    let res = if by_value {
+        sort_by_value(&map, order)
+    } else {
+        sorted_keys(&map, order)
+    };
+    mem::forget(map);
+    mem::transmute::<runtime::IntMap<K>, *mut c_void>(res)
+}
+
+// The key type of `_invert` of a map with values of the given type.
+macro_rules! inv_key {
+    (Int) => { Int };
+    (Float) => { Str };
+    (Str) => { Str };
+}
+
 map_impl!(intint, Int, Int);
 map_impl!(intfloat, Int, Float);
 map_impl!(intstr, Int, Str);
@@ -2598,6 +3923,33 @@ map_impl!(strint, Str, Int);
 map_impl!(strfloat, Str, Float);
 map_impl!(strstr, Str, Str);
 
+macro_rules! slice_impl {
+    ($ty:ident, $v:tt) => {
+        paste! {
+            pub(crate) unsafe extern "C" fn [<slice_ $ty>](map: *mut c_void, from: Int, to: Int) -> *mut c_void {
+                debug_assert!(!map.is_null());
+                let map = mem::transmute::<*mut c_void, runtime::IntMap<$v>>(map);
+                let res = runtime::math_util::map_slice(&map, from, to);
+                mem::forget(map);
+                mem::transmute::<runtime::IntMap<$v>, *mut c_void>(res)
+            }
+
+            pub(crate) unsafe extern "C" fn [<shuffle_ $ty>](runtime: *mut c_void, map: *mut c_void) -> Int {
+                debug_assert!(!map.is_null());
+                let runtime = &mut *(runtime as *mut Runtime);
+                let map = mem::transmute::<*mut c_void, runtime::IntMap<$v>>(map);
+                let res = runtime::math_util::map_shuffle(&mut runtime.core.rng, &map);
+                mem::forget(map);
+                res
+            }
+        }
+    };
+}
+
+slice_impl!(intint, Int);
+slice_impl!(intfloat, Float);
+slice_impl!(intstr, Str);
+
 macro_rules! slot_impl {
     ($name:ident, $ty:tt) => {
         paste! {
@@ -306,7 +306,10 @@ impl<'a> CodeGenerator for View<'a> {
                 Abs => Either::Right(builtin_functions::Function::Abs),
                 Ceil => Either::Right(builtin_functions::Function::Ceil),
                 Floor => Either::Right(builtin_functions::Function::Floor),
-                Round => Either::Right(builtin_functions::Function::Round)
+                Round => Either::Left(codegen::intrinsics::_frawk_round as _),
+                RoundTo => Either::Left(codegen::intrinsics::_frawk_round_to as _),
+                FloorTo => Either::Left(codegen::intrinsics::_frawk_floor_to as _),
+                CeilTo => Either::Left(codegen::intrinsics::_frawk_ceil_to as _),
             }
         }
         unsafe {
@@ -1144,7 +1147,7 @@ impl<'a, 'b> Generator<'a, 'b> {
                         match hl {
                             Ret(_, _) => exits.push((i, j)),
                             Phi(_, ty, _) if ty != &Ty::Null => phis.push((i, j)),
-                            Phi(_, _, _) | DropIter(_, _) | Call { .. } => {}
+                            Phi(_, _, _) | DropIter(_, _) | Call { .. } | Line(_) => {}
                         }
                     }
                 }
@@ -1530,6 +1533,8 @@ impl<'a> View<'a> {
             }
             // Returns are handled elsewhere
             Ret(_reg, _ty) => {}
+            // Only the bytecode interpreter locates runtime errors.
+            Line(_) => {}
             DropIter(reg, ty) => {
                 let drop_fn = match ty {
                     Ty::IterInt => intrinsic!(drop_iter_int),
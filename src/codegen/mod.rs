@@ -2,16 +2,18 @@
 //! programs based on the output of the `compile` module.
 //!
 //! The module root contains code that is shared by the cranelift and LLVM backends.
+#![cfg_attr(not(any(feature = "jit", feature = "llvm_backend")), allow(dead_code))]
 use crate::{
     builtins,
     bytecode::{self, Accum},
     common::{CancelSignal, Cleanup, FileSpec, NumTy, Result, Stage},
     compile,
+    interp::MergeSpec,
     pushdown::FieldSet,
     runtime::{self, UniqueStr},
 };
 
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
 
 use std::marker::PhantomData;
 use std::mem;
@@ -20,6 +22,8 @@ use std::sync::Arc;
 /// Options used to configure a code-generating backend.
 #[derive(Copy, Clone)]
 pub struct Config {
+    // Only the LLVM backend has optimization levels to choose from.
+    #[cfg_attr(not(feature = "llvm_backend"), allow(dead_code))]
     pub opt_level: usize,
     pub num_workers: usize,
 }
@@ -32,6 +36,7 @@ macro_rules! external {
 
 #[macro_use]
 pub(crate) mod intrinsics;
+#[cfg(feature = "jit")]
 pub(crate) mod clif;
 #[cfg(feature = "llvm_backend")]
 pub(crate) mod llvm;
@@ -54,6 +59,23 @@ macro_rules! intrinsic {
     };
 }
 
+// The intrinsic named `<prefix>_<map type>` for the given map type, such as `keys_intstr`.
+macro_rules! map_intrinsic {
+    ($prefix:ident, $map_ty:expr) => {
+        paste::paste! {
+            match $map_ty {
+                compile::Ty::MapIntInt => intrinsic!([<$prefix _intint>]),
+                compile::Ty::MapIntFloat => intrinsic!([<$prefix _intfloat>]),
+                compile::Ty::MapIntStr => intrinsic!([<$prefix _intstr>]),
+                compile::Ty::MapStrInt => intrinsic!([<$prefix _strint>]),
+                compile::Ty::MapStrFloat => intrinsic!([<$prefix _strfloat>]),
+                compile::Ty::MapStrStr => intrinsic!([<$prefix _strstr>]),
+                ty => return err!("non-map type: {:?}", ty),
+            }
+        }
+    };
+}
+
 #[derive(Copy, Clone)]
 pub(crate) enum Cmp {
     Eq,
@@ -144,6 +166,7 @@ pub(crate) unsafe fn run_main<R, FF, J>(
     ff: FF,
     used_fields: &FieldSet,
     named_columns: Option<Vec<&[u8]>>,
+    merge_spec: MergeSpec,
     num_workers: usize,
     cancel_signal: CancelSignal,
 ) -> Result<()>
@@ -153,10 +176,12 @@ where
     J: Jit,
 {
     let mut rt = stdin.into_runtime(ff, used_fields, named_columns, cancel_signal.clone());
+    rt.core.merge = merge_spec;
     let main = jit.main_functions()?;
     match main {
         Stage::Main(m) => {
             m.invoke(&mut rt);
+            intrinsics::check_deferred_error(&mut rt);
             Ok(())
         }
         Stage::Par {
@@ -174,6 +199,7 @@ where
                     for main in begin.into_iter().chain(main_loop).chain(end) {
                         main.invoke(&mut rt);
                     }
+                    intrinsics::check_deferred_error(&mut rt);
                     return Ok(());
                 }
                 #[cfg(not(debug_assertions))]
@@ -252,6 +278,7 @@ where
                                 read_files.inputs = old_read_files;
                                 end.invoke(&mut rt);
                             }
+                            intrinsics::check_deferred_error(&mut rt);
                         });
                     });
                     if scope_res.is_err() {
@@ -529,6 +556,17 @@ pub(crate) trait CodeGenerator: Backend {
         Ok(())
     }
 
+    /// Stores the result of `func`, an intrinsic for an array function such as `_keys` or `_copy`,
+    /// called on `args` in `dst`.
+    fn array_fn(&mut self, func: Op, args: &[Ref], dst: Ref) -> Result<()> {
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args {
+            vals.push(self.get_val(*arg)?);
+        }
+        let resv = self.call_intrinsic(func, &mut vals[..])?;
+        self.bind_val(dst, resv)
+    }
+
     /// Stores `val` into `map` at key `key`.
     ///
     /// Assumes that the types of the input registers match up.
@@ -586,6 +624,15 @@ pub(crate) trait CodeGenerator: Backend {
         self.bind_val(dst.reflect(), res)
     }
 
+    fn asort(&mut self, op: Op, dst: &impl Accum, arr: &impl Accum, target: &impl Accum, order: &impl Accum) -> Result<()> {
+        let rt = self.runtime_val();
+        let arrv = self.get_val(arr.reflect())?;
+        let targetv = self.get_val(target.reflect())?;
+        let orderv = self.get_val(order.reflect())?;
+        let res = self.call_intrinsic(op, &mut [rt, arrv, targetv, orderv])?;
+        self.bind_val(dst.reflect(), res)
+    }
+
     /// Wraps `call_intrinsic` for [`Op`]s that have one argument and return a value.
     fn unop(&mut self, op: Op, dst: &impl Accum, x: &impl Accum) -> Result<()> {
         let xv = self.get_val(x.reflect())?;
@@ -610,6 +657,7 @@ pub(crate) trait CodeGenerator: Backend {
             }
             IntToStr(sr, ir) => self.unop(intrinsic!(int_to_str), sr, ir),
             FloatToStr(sr, fr) => self.unop(intrinsic!(float_to_str), sr, fr),
+            FloatToOutStr(sr, fr) => self.unop(intrinsic!(float_to_out_str), sr, fr),
             StrToInt(ir, sr) => self.unop(intrinsic!(str_to_int), ir, sr),
             HexStrToInt(ir, sr) => self.unop(intrinsic!(hex_str_to_int), ir, sr),
             StrToFloat(fr, sr) => self.unop(intrinsic!(str_to_float), fr, sr),
@@ -639,6 +687,9 @@ pub(crate) trait CodeGenerator: Backend {
                 )?;
                 self.bind_val(res.reflect(), cmp)
             }
+            NotStr(res, sr) if runtime::bignum::enabled() => {
+                self.unop(intrinsic!(big_is_false), res, sr)
+            }
             NotStr(res, sr) => {
                 let sv = self.get_val(sr.reflect())?;
                 let lenv = self.call_intrinsic(intrinsic!(str_len), &mut [sv])?;
@@ -658,6 +709,22 @@ pub(crate) trait CodeGenerator: Backend {
             Float2(ff, dst, l, r) => self.binop(Op::Math(*ff), dst, l, r),
             Int1(bw, dst, src) => self.unop(Op::Bitwise(*bw), dst, src),
             Int2(bw, dst, l, r) => self.binop(Op::Bitwise(*bw), dst, l, r),
+            SafeInt(op, dst, l, r) => {
+                let rt = self.runtime_val();
+                let op = self.const_int(*op as i64);
+                let l = self.get_val(l.reflect())?;
+                let r = self.get_val(r.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(safe_int), &mut [rt, op, l, r])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            SafeFloat(op, dst, l, r) => {
+                let rt = self.runtime_val();
+                let op = self.const_int(*op as i64);
+                let l = self.get_val(l.reflect())?;
+                let r = self.get_val(r.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(safe_float), &mut [rt, op, l, r])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Rand(dst) => {
                 let rt = self.runtime_val();
                 let res = self.call_intrinsic(intrinsic!(rand_float), &mut [rt])?;
@@ -674,6 +741,13 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(reseed_rng), &mut [rt])?;
                 self.bind_val(dst.reflect(), res)
             }
+            RandInt(dst, lo, hi) => {
+                let rt = self.runtime_val();
+                let lov = self.get_val(lo.reflect())?;
+                let hiv = self.get_val(hi.reflect())?;
+                let res = self.call_intrinsic(intrinsic!(rand_int), &mut [rt, lov, hiv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             Concat(dst, l, r) => self.binop(intrinsic!(concat), dst, l, r),
             StartsWithConst(dst, s, bs) => {
                 let s = self.get_val(s.reflect())?;
@@ -682,6 +756,15 @@ pub(crate) trait CodeGenerator: Backend {
                 let res = self.call_intrinsic(intrinsic!(starts_with_const), &mut [s, ptr, len])?;
                 self.bind_val(dst.reflect(), res)
             }
+            MatchArr(dst, l, r, arr) => {
+                let lv = self.get_val(l.reflect())?;
+                let rv = self.get_val(r.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let rt = self.runtime_val();
+                let res =
+                    self.call_intrinsic(intrinsic!(match_pat_arr), &mut [rt, lv, rv, arrv])?;
+                self.bind_val(dst.reflect(), res)
+            }
             Match(dst, l, r) => {
                 let lv = self.get_val(l.reflect())?;
                 let rv = self.get_val(r.reflect())?;
@@ -731,6 +814,22 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(subst_all), &mut [rt, patv, sv, in_sv])?;
                 self.bind_val(res.reflect(), resv)
             }
+            SubConst(res, pat, s, in_s) => {
+                let patv = self.const_re(pat.clone());
+                let sv = self.get_val(s.reflect())?;
+                let in_sv = self.get_val(in_s.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(subst_first_const), &mut [patv, sv, in_sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
+            GSubConst(res, pat, s, in_s) => {
+                let patv = self.const_re(pat.clone());
+                let sv = self.get_val(s.reflect())?;
+                let in_sv = self.get_val(in_s.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(subst_all_const), &mut [patv, sv, in_sv])?;
+                self.bind_val(res.reflect(), resv)
+            }
             GenSubDynamic(res, pat, s, how, in_s) => {
                 let rt = self.runtime_val();
                 let patv = self.get_val(pat.reflect())?;
@@ -751,10 +850,10 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(res.reflect(), resv)
             }
             CharAt(res, text,index) => {
+                let rt = self.runtime_val();
                 let text = self.get_val(text.reflect())?;
                 let index = self.get_val(index.reflect())?;
-                //panic!("invalid index for chat_at: {}, should start with 1", index)
-                let resv = self.call_intrinsic(intrinsic!(char_at), &mut [text, index])?;
+                let resv = self.call_intrinsic(intrinsic!(char_at), &mut [rt, text, index])?;
                 self.bind_val(res.reflect(), resv)
             }
             LastPart(res, s,sep) => {
@@ -865,18 +964,69 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(systime), &mut [rt])?;
                 self.bind_val(dst.reflect(),resv)
             }
+            SystimeMs(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(systime_ms), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            SystimeUs(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(systime_us), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Clock(dst) => {
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(clock), &mut [rt])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Sleep(dst, secs) => self.unop(intrinsic!(sleep), dst, secs),
             Encode(dst,format, text) => {
+                let rt = self.runtime_val();
                 let format = self.get_val(format.reflect())?;
                 let text = self.get_val(text.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(encode), &mut [format, text])?;
+                let resv = self.call_intrinsic(intrinsic!(encode), &mut [rt, format, text])?;
                 self.bind_val(dst.reflect(),resv)
             }
             Decode(dst,format, text) => {
+                let rt = self.runtime_val();
                 let format = self.get_val(format.reflect())?;
                 let text = self.get_val(text.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(decode), &mut [format, text])?;
+                let resv = self.call_intrinsic(intrinsic!(decode), &mut [rt, format, text])?;
                 self.bind_val(dst.reflect(),resv)
             }
+            Utf8Valid(dst, text) => self.unop(intrinsic!(utf8_valid), dst, text),
+            Utf8Fix(dst, text) => self.unop(intrinsic!(utf8_fix), dst, text),
+            Normalize(dst, text, form) => {
+                let f = match form {
+                    builtins::NormalForm::Nfc => intrinsic!(nfc),
+                    builtins::NormalForm::Nfd => intrinsic!(nfd),
+                    builtins::NormalForm::Nfkc => intrinsic!(nfkc),
+                    builtins::NormalForm::Nfkd => intrinsic!(nfkd),
+                };
+                self.unop(f, dst, text)
+            }
+            Compress(dst, format, text) => {
+                let rt = self.runtime_val();
+                let format = self.get_val(format.reflect())?;
+                let text = self.get_val(text.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(compress), &mut [rt, format, text])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Decompress(dst, format, text) => {
+                let rt = self.runtime_val();
+                let format = self.get_val(format.reflect())?;
+                let text = self.get_val(text.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(decompress), &mut [rt, format, text])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Iconv(dst, text, from, to) => {
+                let rt = self.runtime_val();
+                let text = self.get_val(text.reflect())?;
+                let from = self.get_val(from.reflect())?;
+                let to = self.get_val(to.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(iconv), &mut [rt, text, from, to])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Escape(dst,format, text) => {
                 let format = self.get_val(format.reflect())?;
                 let text = self.get_val(text.reflect())?;
@@ -889,6 +1039,33 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(digest), &mut [algorithm, text])?;
                 self.bind_val(dst.reflect(),resv)
             }
+            FileDigest(dst, algorithm, path) => {
+                let rt = self.runtime_val();
+                let algorithm = self.get_val(algorithm.reflect())?;
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(file_digest), &mut [rt, algorithm, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DigestInit(dst, algorithm, key) => {
+                let rt = self.runtime_val();
+                let algorithm = self.get_val(algorithm.reflect())?;
+                let key = self.get_val(key.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(digest_init), &mut [rt, algorithm, key])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DigestUpdate(dst, handle, text) => {
+                let rt = self.runtime_val();
+                let handle = self.get_val(handle.reflect())?;
+                let text = self.get_val(text.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(digest_update), &mut [rt, handle, text])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DigestFinal(dst, handle) => {
+                let rt = self.runtime_val();
+                let handle = self.get_val(handle.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(digest_final), &mut [rt, handle])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Hmac(dst,algorithm,key, text) => {
                 let algorithm = self.get_val(algorithm.reflect())?;
                 let key = self.get_val(key.reflect())?;
@@ -897,17 +1074,26 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(dst.reflect(),resv)
             }
             Jwt(dst,algorithm,key, payload) => {
+                let rt = self.runtime_val();
                 let algorithm = self.get_val(algorithm.reflect())?;
                 let key = self.get_val(key.reflect())?;
                 let payload = self.get_val(payload.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(jwt), &mut [algorithm, key, payload])?;
+                let resv = self.call_intrinsic(intrinsic!(jwt), &mut [rt, algorithm, key, payload])?;
                 self.bind_val(dst.reflect(),resv)
             }
-            Dejwt(dst,key, token) => {
+            Dejwt(dst, key, token, options) => {
+                let rt = self.runtime_val();
                 let key = self.get_val(key.reflect())?;
                 let token = self.get_val(token.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(dejwt), &mut [key, token])?;
-                self.bind_val(dst.reflect(),resv)
+                let options = self.get_val(options.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dejwt), &mut [rt, key, token, options])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Cert(dst, source) => {
+                let rt = self.runtime_val();
+                let source = self.get_val(source.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(cert), &mut [rt, source])?;
+                self.bind_val(dst.reflect(), resv)
             }
             Encrypt(dst,mode,plain_text,key) => {
                 let mode = self.get_val(mode.reflect())?;
@@ -923,11 +1109,12 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(decrypt), &mut [mode,encrypted_text, key])?;
                 self.bind_val(dst.reflect(),resv)
             }
-            Strftime(dst,format, timestamp) => {
+            Strftime(dst,format, timestamp, timezone) => {
                 let format = self.get_val(format.reflect())?;
                 let timestamp = self.get_val(timestamp.reflect())?;
+                let timezone = self.get_val(timezone.reflect())?;
                 let rt = self.runtime_val();
-                let resv = self.call_intrinsic(intrinsic!(strftime), &mut [rt, format, timestamp])?;
+                let resv = self.call_intrinsic(intrinsic!(strftime), &mut [rt, format, timestamp, timezone])?;
                 self.bind_val(dst.reflect(),resv)
             }
             Mktime(dst,date_time_text,timezone) => {
@@ -940,6 +1127,26 @@ pub(crate) trait CodeGenerator: Backend {
             MkBool(dst,text) => self.unop(intrinsic!(mkbool), dst, text),
             Fend(dst,src) => self.unop(intrinsic!(fend), dst, src),
             Url(dst,src) => self.unop(intrinsic!(url), dst, src),
+            UaParse(dst, src) => self.unop(intrinsic!(ua_parse), dst, src),
+            Logfmt(dst, src) => self.unop(intrinsic!(logfmt), dst, src),
+            MapStrIntToLogfmt(dst, arr, order) => {
+                let arr = self.get_val(arr.reflect())?;
+                let order = self.get_val(order.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_int_to_logfmt), &mut [arr, order])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrFloatToLogfmt(dst, arr, order) => {
+                let arr = self.get_val(arr.reflect())?;
+                let order = self.get_val(order.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_float_to_logfmt), &mut [arr, order])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrStrToLogfmt(dst, arr, order) => {
+                let arr = self.get_val(arr.reflect())?;
+                let order = self.get_val(order.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_str_to_logfmt), &mut [arr, order])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Record(dst,src) => self.unop(intrinsic!(record), dst, src),
             Message(dst,src) => self.unop(intrinsic!(message), dst, src),
             Pairs(dst,src, pair_sep,kv_sep) => {
@@ -953,6 +1160,26 @@ pub(crate) trait CodeGenerator: Backend {
             Path(dst,src) => self.unop(intrinsic!(path), dst, src),
             DataUrl(dst,src) => self.unop(intrinsic!(data_url), dst, src),
             DateTime(dst,timestamp) => self.unop(intrinsic!(datetime), dst, timestamp),
+            DateParse(dst, text, format) => {
+                let rt = self.runtime_val();
+                let textv = self.get_val(text.reflect())?;
+                let formatv = self.get_val(format.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dateparse), &mut [rt, textv, formatv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DateAdd(dst, a, b, unit) | DateDiff(dst, a, b, unit) => {
+                let rt = self.runtime_val();
+                let av = self.get_val(a.reflect())?;
+                let bv = self.get_val(b.reflect())?;
+                let unitv = self.get_val(unit.reflect())?;
+                let f = if let DateAdd(..) = inst {
+                    intrinsic!(dateadd)
+                } else {
+                    intrinsic!(datediff)
+                };
+                let resv = self.call_intrinsic(f, &mut [rt, av, bv, unitv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             TypeOfArray(dst) => {
                 let resv = self.call_intrinsic(intrinsic!(type_of_array), &mut [])?;
                 self.bind_val(dst.reflect(), resv)
@@ -1003,12 +1230,35 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(is_str_num), &mut [text])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            IsNan(dst, x) => self.unop(intrinsic!(is_nan), dst, x),
+            IsInf(dst, x) => self.unop(intrinsic!(is_inf), dst, x),
             IsFormat(dst,format, text) => {
                 let format = self.get_val(format.reflect())?;
                 let text = self.get_val(text.reflect())?;
                 let resv = self.call_intrinsic(intrinsic!(is_format), &mut [format, text])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            CidrContains(dst, net, addr) => {
+                let net = self.get_val(net.reflect())?;
+                let addr = self.get_val(addr.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(cidr_contains), &mut [net, addr])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Ip2Int(dst, ip) => self.unop(intrinsic!(ip2int), dst, ip),
+            Int2Ip(dst, n) => self.unop(intrinsic!(int2ip), dst, n),
+            IpVersion(dst, ip) => self.unop(intrinsic!(ip_version), dst, ip),
+            Geoip(dst, ip) => {
+                let rt = self.runtime_val();
+                let ip = self.get_val(ip.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(geoip), &mut [rt, ip])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            GeoipDb(dst, path) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(geoip_db), &mut [rt, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Shlex(dst,text) => self.unop(intrinsic!(shlex), dst, text),
             Tuple(dst,text) => self.unop(intrinsic!(tuple), dst, text),
             Flags(dst,text) => self.unop(intrinsic!(flags), dst, text),
@@ -1048,6 +1298,102 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(dst.reflect(), resv)
             }
             FromJson(dst,src) => self.unop(intrinsic!(from_json), dst, src),
+            FromToml(dst, src) => {
+                let rt = self.runtime_val();
+                let src = self.get_val(src.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(from_toml), &mut [rt, src])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrIntToToml(dst, arr, like) => {
+                let rt = self.runtime_val();
+                let arr = self.get_val(arr.reflect())?;
+                let like = self.get_val(like.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_int_to_toml), &mut [rt, arr, like])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrFloatToToml(dst, arr, like) => {
+                let rt = self.runtime_val();
+                let arr = self.get_val(arr.reflect())?;
+                let like = self.get_val(like.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_float_to_toml), &mut [rt, arr, like])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrStrToToml(dst, arr, like) => {
+                let rt = self.runtime_val();
+                let arr = self.get_val(arr.reflect())?;
+                let like = self.get_val(like.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_str_to_toml), &mut [rt, arr, like])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            TomlValue(dst, text, path) => {
+                let rt = self.runtime_val();
+                let text = self.get_val(text.reflect())?;
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(toml_value), &mut [rt, text, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            FromIni(dst, src) => self.unop(intrinsic!(from_ini), dst, src),
+            MapStrIntToIni(dst, arr) => self.unop(intrinsic!(map_str_int_to_ini), dst, arr),
+            MapStrFloatToIni(dst, arr) => self.unop(intrinsic!(map_str_float_to_ini), dst, arr),
+            MapStrStrToIni(dst, arr) => self.unop(intrinsic!(map_str_str_to_ini), dst, arr),
+            PropGet(dst, text, key) => {
+                let text = self.get_val(text.reflect())?;
+                let key = self.get_val(key.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(prop_get), &mut [text, key])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrIntToXml(dst, arr, root) => {
+                let arr = self.get_val(arr.reflect())?;
+                let root = self.get_val(root.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_int_to_xml), &mut [arr, root])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrFloatToXml(dst, arr, root) => {
+                let arr = self.get_val(arr.reflect())?;
+                let root = self.get_val(root.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_float_to_xml), &mut [arr, root])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            MapStrStrToXml(dst, arr, root) => {
+                let arr = self.get_val(arr.reflect())?;
+                let root = self.get_val(root.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(map_str_str_to_xml), &mut [arr, root])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            XmlUpdate(dst, text, xpath, value) => {
+                let rt = self.runtime_val();
+                let text = self.get_val(text.reflect())?;
+                let xpath = self.get_val(xpath.reflect())?;
+                let value = self.get_val(value.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(xml_update), &mut [rt, text, xpath, value])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            HtmlTable(dst, html, index) => {
+                let html = self.get_val(html.reflect())?;
+                let index = self.get_val(index.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(html_table), &mut [html, index])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            FromMsgpack(dst, src) => {
+                let rt = self.runtime_val();
+                let src = self.get_val(src.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(from_msgpack), &mut [rt, src])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            FromCbor(dst, src) => {
+                let rt = self.runtime_val();
+                let src = self.get_val(src.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(from_cbor), &mut [rt, src])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            FromProtobuf(dst, src, descriptors, message) => {
+                let rt = self.runtime_val();
+                let src = self.get_val(src.reflect())?;
+                let descriptors = self.get_val(descriptors.reflect())?;
+                let message = self.get_val(message.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(from_protobuf), &mut [rt, src, descriptors, message])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             MapIntIntToJson(dst,arr) => self.unop(intrinsic!(map_int_int_to_json), dst, arr),
             MapIntFloatToJson(dst,arr) => self.unop(intrinsic!(map_int_float_to_json), dst, arr),
             MapIntStrToJson(dst,arr) => self.unop(intrinsic!(map_int_str_to_json), dst, arr),
@@ -1110,24 +1456,15 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(dump_null), &mut [])?;
                 Ok(())
             }
-            MapIntIntAsort(dst, arr,target) => {
-                let arr = self.get_val(arr.reflect())?;
-                let target = self.get_val(target.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(map_int_int_asort), &mut [arr, target])?;
-                self.bind_val(dst.reflect(),resv)
-            },
-            MapIntFloatAsort(dst, arr,target) => {
-                let arr = self.get_val(arr.reflect())?;
-                let target = self.get_val(target.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(map_int_float_asort), &mut [arr, target])?;
-                self.bind_val(dst.reflect(),resv)
-            },
-            MapIntStrAsort(dst, arr,target) => {
-                let arr = self.get_val(arr.reflect())?;
-                let target = self.get_val(target.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(map_int_str_asort), &mut [arr, target])?;
-                self.bind_val(dst.reflect(),resv)
-            },
+            MapIntIntAsort(dst, arr, target, order) => {
+                self.asort(intrinsic!(map_int_int_asort), dst, arr, target, order)
+            }
+            MapIntFloatAsort(dst, arr, target, order) => {
+                self.asort(intrinsic!(map_int_float_asort), dst, arr, target, order)
+            }
+            MapIntStrAsort(dst, arr, target, order) => {
+                self.asort(intrinsic!(map_int_str_asort), dst, arr, target, order)
+            }
             MapIntIntJoin(dst, arr,sep) => {
                 let arr = self.get_val(arr.reflect())?;
                 let sep = self.get_val(sep.reflect())?;
@@ -1172,16 +1509,18 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(dst.reflect(),resv)
             },
             S3Get(dst,bucket, object_name) => {
+                let rt = self.runtime_val();
                 let bucket = self.get_val(bucket.reflect())?;
                 let object_name = self.get_val(object_name.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(s3_get), &mut [bucket, object_name])?;
+                let resv = self.call_intrinsic(intrinsic!(s3_get), &mut [rt, bucket, object_name])?;
                 self.bind_val(dst.reflect(),resv)
             }
             S3Put(dst,bucket, object_name, body) => {
+                let rt = self.runtime_val();
                 let bucket = self.get_val(bucket.reflect())?;
                 let object_name = self.get_val(object_name.reflect())?;
                 let body = self.get_val(body.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(s3_put), &mut [bucket, object_name, body])?;
+                let resv = self.call_intrinsic(intrinsic!(s3_put), &mut [rt, bucket, object_name, body])?;
                 self.bind_val(dst.reflect(),resv)
             }
             Trim(dst,src, pat) => {
@@ -1191,7 +1530,15 @@ pub(crate) trait CodeGenerator: Backend {
                 self.bind_val(dst.reflect(),resv)
             }
             Strtonum(dst,text) => self.unop(intrinsic!(strtonum), dst, text),
+            ParseNumber(dst, text, locale) => self.binop(intrinsic!(parse_number), dst, text, locale),
             FormatBytes(dst,size) => self.unop(intrinsic!(format_bytes), dst, size),
+            FormatNum(dst, x, pattern, locale) => {
+                let x = self.get_val(x.reflect())?;
+                let pattern = self.get_val(pattern.reflect())?;
+                let locale = self.get_val(locale.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(format_num), &mut [x, pattern, locale])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             ToBytes(dst,text) => self.unop(intrinsic!(to_bytes), dst, text),
             StartsWith(dst,text, prefix) => {
                 let text = self.get_val(text.reflect())?;
@@ -1244,6 +1591,19 @@ pub(crate) trait CodeGenerator: Backend {
                 let resv = self.call_intrinsic(intrinsic!(strcmp), &mut [text1, text2])?;
                 self.bind_val(dst.reflect(),resv)
             }
+            NatCmp(dst, text1, text2) => self.binop(intrinsic!(natcmp), dst, text1, text2),
+            Levenshtein(dst, text1, text2) => self.binop(intrinsic!(levenshtein), dst, text1, text2),
+            Similarity(dst, text1, text2) => self.binop(intrinsic!(similarity), dst, text1, text2),
+            Diff(dst, text1, text2) => self.binop(intrinsic!(diff), dst, text1, text2),
+            Soundex(dst, text) => self.unop(intrinsic!(soundex), dst, text),
+            Metaphone(dst, text) => self.unop(intrinsic!(metaphone), dst, text),
+            FuzzyMatch(dst, text1, text2, threshold) => {
+                let text1 = self.get_val(text1.reflect())?;
+                let text2 = self.get_val(text2.reflect())?;
+                let threshold = self.get_val(threshold.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(fuzzy_match), &mut [text1, text2, threshold])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Mask(dst,text) => self.unop(intrinsic!(mask), dst, text),
             Repeat(dst,text,n) => {
                 let text = self.get_val(text.reflect())?;
@@ -1336,16 +1696,60 @@ pub(crate) trait CodeGenerator: Backend {
                 Ok(())
             }
             ReadAll(dst,path) => {
+                let rt = self.runtime_val();
                 let path = self.get_val(path.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(read_all), &mut [path])?;
+                let resv = self.call_intrinsic(intrinsic!(read_all), &mut [rt, path])?;
                 self.bind_val(dst.reflect(),resv)
             }
+            ReadLines(dst, path) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(read_lines), &mut [rt, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             WriteAll(path, content) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let content = self.get_val(content.reflect())?;
+                self.call_void(external!(write_all), &mut [rt, path, content])?;
+                Ok(())
+            }
+            AppendAll(path, content) => {
+                let rt = self.runtime_val();
                 let path = self.get_val(path.reflect())?;
                 let content = self.get_val(content.reflect())?;
-                self.call_void(external!(write_all), &mut [path, content])?;
+                self.call_void(external!(append_all), &mut [rt, path, content])?;
                 Ok(())
             }
+            Glob(dst, pattern) => {
+                let rt = self.runtime_val();
+                let pattern = self.get_val(pattern.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(glob), &mut [rt, pattern])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Stat(dst, path) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(stat), &mut [rt, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Exists(dst, path) => {
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(exists), &mut [path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            Mkdirp(dst, path) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(mkdirp), &mut [rt, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            RmFile(dst, path) => {
+                let rt = self.runtime_val();
+                let path = self.get_val(path.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(rmfile), &mut [rt, path])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             LogDebug(message) => {
                 let message = self.get_val(message.reflect())?;
                 let rt = self.runtime_val();
@@ -1379,19 +1783,22 @@ pub(crate) trait CodeGenerator: Backend {
             BloomFilterInsert(item, group) => {
                 let item = self.get_val(item.reflect())?;
                 let group = self.get_val(group.reflect())?;
-                self.call_void(external!(bf_insert), &mut [item, group])?;
+                let rt = self.runtime_val();
+                self.call_void(external!(bf_insert), &mut [rt, item, group])?;
                 Ok(())
             }
             BloomFilterContains(dst, item, group) => {
                 let item = self.get_val(item.reflect())?;
                 let group = self.get_val(group.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(bf_contains), &mut [item, group])?;
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(bf_contains), &mut [rt, item, group])?;
                 self.bind_val(dst.reflect(),resv)
             }
             BloomFilterContainsWithInsert(dst, item, group) => {
                 let item = self.get_val(item.reflect())?;
                 let group = self.get_val(group.reflect())?;
-                let resv = self.call_intrinsic(intrinsic!(bf_icontains), &mut [item, group])?;
+                let rt = self.runtime_val();
+                let resv = self.call_intrinsic(intrinsic!(bf_icontains), &mut [rt, item, group])?;
                 self.bind_val(dst.reflect(),resv)
             }
             Fake(dst, data, locale) => {
@@ -1414,6 +1821,101 @@ pub(crate) trait CodeGenerator: Backend {
                let resv = self.call_intrinsic(intrinsic!(max), &mut [first, second, third])?;
                self.bind_val(dst.reflect(),resv)
             }
+            DecAdd(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_add), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DecSub(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_sub), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DecMul(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_mul), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DecDiv(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_div), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DecCmp(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_cmp), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            DecFmt(dst, x, scale) => {
+                let x = self.get_val(x.reflect())?;
+                let scale = self.get_val(scale.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(dec_fmt), &mut [x, scale])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigAdd(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_add), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigSub(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_sub), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigMul(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_mul), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigDiv(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_div), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigMod(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_mod), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigPow(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_pow), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            BigCmp(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(big_cmp), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            U64Add(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(u64_add), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            U64Mul(dst, x, y) => {
+                let x = self.get_val(x.reflect())?;
+                let y = self.get_val(y.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(u64_mul), &mut [x, y])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            U64Str(dst, x) => {
+                let x = self.get_val(x.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(u64_str), &mut [x])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             Seq(dst,start, step,end) => {
                 let start = self.get_val(start.reflect())?;
                 let step = self.get_val(step.reflect())?;
@@ -1436,6 +1938,15 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(join_cols), &mut [rt, startv, endv, sepv])?;
                 self.bind_val(dst.reflect(), resv)
             }
+            SelectColumns(dst, spec, sep, esc) => {
+                let rt = self.runtime_val();
+                let specv = self.get_val(spec.reflect())?;
+                let sepv = self.get_val(sep.reflect())?;
+                let escv = self.get_val(esc.reflect())?;
+                let resv =
+                    self.call_intrinsic(intrinsic!(select_cols), &mut [rt, specv, sepv, escv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             SplitInt(flds, to_split, arr, pat) => {
                 let rt = self.runtime_val();
                 let tsv = self.get_val(to_split.reflect())?;
@@ -1454,6 +1965,36 @@ pub(crate) trait CodeGenerator: Backend {
                     self.call_intrinsic(intrinsic!(split_str), &mut [rt, tsv, arrv, patv])?;
                 self.bind_val(flds.reflect(), fldsv)
             }
+            SplitSepsInt(flds, to_split, arr, pat, seps)
+            | PatSplitInt(flds, to_split, arr, pat, seps) => {
+                let rt = self.runtime_val();
+                let tsv = self.get_val(to_split.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let sepsv = self.get_val(seps.reflect())?;
+                let intrinsic = if let SplitSepsInt(..) = inst {
+                    intrinsic!(split_seps_int)
+                } else {
+                    intrinsic!(patsplit_int)
+                };
+                let fldsv = self.call_intrinsic(intrinsic, &mut [rt, tsv, arrv, patv, sepsv])?;
+                self.bind_val(flds.reflect(), fldsv)
+            }
+            SplitSepsStr(flds, to_split, arr, pat, seps)
+            | PatSplitStr(flds, to_split, arr, pat, seps) => {
+                let rt = self.runtime_val();
+                let tsv = self.get_val(to_split.reflect())?;
+                let arrv = self.get_val(arr.reflect())?;
+                let patv = self.get_val(pat.reflect())?;
+                let sepsv = self.get_val(seps.reflect())?;
+                let intrinsic = if let SplitSepsStr(..) = inst {
+                    intrinsic!(split_seps_str)
+                } else {
+                    intrinsic!(patsplit_str)
+                };
+                let fldsv = self.call_intrinsic(intrinsic, &mut [rt, tsv, arrv, patv, sepsv])?;
+                self.bind_val(flds.reflect(), fldsv)
+            }
             Printf { output, fmt, args } => self.printf(output, fmt, &args[..]),
             Sprintf { dst, fmt, args } => self.sprintf(dst, fmt, &args[..]),
             PrintAll { output, args } => self.print_all(output, &args[..]),
@@ -1463,13 +2004,48 @@ pub(crate) trait CodeGenerator: Backend {
                 self.call_void(external!(close_file), &mut [rt, filev])?;
                 Ok(())
             }
+            FFlush(dst, file) => {
+                let rt = self.runtime_val();
+                let filev = self.get_val(file.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(fflush), &mut [rt, filev])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             RunCmd(dst, cmd) => self.unop(intrinsic!(run_system), dst, cmd),
+            ExecOutput(dst, cmd) => self.unop(intrinsic!(exec_output), dst, cmd),
+            ExecOutputArgv(dst, argv) => self.unop(intrinsic!(exec_output_argv), dst, argv),
+            Spawn(dst, cmd) => self.unop(intrinsic!(spawn), dst, cmd),
+            SetEnv(dst, name, value) => {
+                let rt = self.runtime_val();
+                let namev = self.get_val(name.reflect())?;
+                let valuev = self.get_val(value.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(set_env), &mut [rt, namev, valuev])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            UnsetEnv(dst, name) => {
+                let rt = self.runtime_val();
+                let namev = self.get_val(name.reflect())?;
+                let resv = self.call_intrinsic(intrinsic!(unset_env), &mut [rt, namev])?;
+                self.bind_val(dst.reflect(), resv)
+            }
+            SpawnArgv(dst, argv) => self.unop(intrinsic!(spawn_argv), dst, argv),
             Exit(code) => {
                 let rt = self.runtime_val();
                 let codev = self.get_val(code.reflect())?;
                 self.call_void(external!(exit), &mut [rt, codev])?;
                 Ok(())
             }
+            CallExt(dst, ext, args) => {
+                // Arguments are passed one at a time, so that neither backend needs a var-arg
+                // calling convention.
+                for arg in args {
+                    let argv = self.get_val(arg.reflect())?;
+                    self.call_void(external!(ext_push_arg), &mut [argv])?;
+                }
+                let rt = self.runtime_val();
+                let extv = self.const_int(*ext as i64);
+                let resv = self.call_intrinsic(intrinsic!(ext_call), &mut [rt, extv])?;
+                self.bind_val(dst.reflect(), resv)
+            }
             ReadErr(dst, file, is_file) => {
                 let rt = self.runtime_val();
                 let filev = self.get_val(file.reflect())?;
@@ -1543,6 +2119,113 @@ pub(crate) trait CodeGenerator: Backend {
             Delete { map_ty, map, key } => self.delete_map((*map, *map_ty), (*key, map_ty.key()?)),
             Clear { map_ty, map } => self.clear_map((*map, *map_ty)),
             Len { map_ty, map, dst } => self.len_map((*map, *map_ty), (*dst, compile::Ty::Int)),
+            Keys { map_ty, dst, map } => {
+                let dst_ty = compile::Ty::map(compile::Ty::Int, map_ty.key()?)?;
+                let func = map_intrinsic!(keys, *map_ty);
+                self.array_fn(func, &[(*map, *map_ty)], (*dst, dst_ty))
+            }
+            Values { map_ty, dst, map } => {
+                let dst_ty = compile::Ty::map(compile::Ty::Int, map_ty.val()?)?;
+                let func = map_intrinsic!(values, *map_ty);
+                self.array_fn(func, &[(*map, *map_ty)], (*dst, dst_ty))
+            }
+            SortKeys {
+                map_ty,
+                dst,
+                map,
+                order,
+                by_value,
+            } => {
+                let dst_ty = compile::Ty::map(compile::Ty::Int, map_ty.key()?)?;
+                let func = if *by_value {
+                    map_intrinsic!(sort_by_value, *map_ty)
+                } else {
+                    map_intrinsic!(sorted_keys, *map_ty)
+                };
+                let rt = self.runtime_val();
+                let mapv = self.get_val((*map, *map_ty))?;
+                let orderv = self.get_val(order.reflect())?;
+                let resv = self.call_intrinsic(func, &mut [rt, mapv, orderv])?;
+                self.bind_val((*dst, dst_ty), resv)
+            }
+            Choice { map_ty, dst, map } => {
+                let func = map_intrinsic!(choice, *map_ty);
+                let rt = self.runtime_val();
+                let mapv = self.get_val((*map, *map_ty))?;
+                let resv = self.call_intrinsic(func, &mut [rt, mapv])?;
+                self.bind_val((*dst, map_ty.val()?), resv)
+            }
+            Sample { map_ty, dst, map, k } => {
+                let dst_ty = compile::Ty::map(compile::Ty::Int, map_ty.val()?)?;
+                let func = map_intrinsic!(sample, *map_ty);
+                let rt = self.runtime_val();
+                let mapv = self.get_val((*map, *map_ty))?;
+                let kv = self.get_val(k.reflect())?;
+                let resv = self.call_intrinsic(func, &mut [rt, mapv, kv])?;
+                self.bind_val((*dst, dst_ty), resv)
+            }
+            Shuffle { map_ty, dst, map } => {
+                let func = match map_ty {
+                    compile::Ty::MapIntInt => intrinsic!(shuffle_intint),
+                    compile::Ty::MapIntFloat => intrinsic!(shuffle_intfloat),
+                    compile::Ty::MapIntStr => intrinsic!(shuffle_intstr),
+                    ty => return err!("shuffle of a string-indexed array: {:?}", ty),
+                };
+                let rt = self.runtime_val();
+                let mapv = self.get_val((*map, *map_ty))?;
+                let resv = self.call_intrinsic(func, &mut [rt, mapv])?;
+                self.bind_val((*dst, compile::Ty::Int), resv)
+            }
+            Invert { map_ty, dst, map } => {
+                let func = map_intrinsic!(invert, *map_ty);
+                self.array_fn(func, &[(*map, *map_ty)], (*dst, map_ty.inverted()?))
+            }
+            Merge {
+                map_ty,
+                dst,
+                left,
+                right,
+            } => {
+                let func = map_intrinsic!(merge, *map_ty);
+                let args = [(*left, *map_ty), (*right, *map_ty)];
+                self.array_fn(func, &args, (*dst, *map_ty))
+            }
+            CopyMap {
+                map_ty,
+                dst,
+                to,
+                from,
+            } => {
+                let func = map_intrinsic!(copy, *map_ty);
+                let args = [(*to, *map_ty), (*from, *map_ty)];
+                self.array_fn(func, &args, (*dst, compile::Ty::Int))
+            }
+            EqualMaps {
+                map_ty,
+                dst,
+                left,
+                right,
+            } => {
+                let func = map_intrinsic!(equals, *map_ty);
+                let args = [(*left, *map_ty), (*right, *map_ty)];
+                self.array_fn(func, &args, (*dst, compile::Ty::Int))
+            }
+            Slice {
+                map_ty,
+                dst,
+                map,
+                from,
+                to,
+            } => {
+                let func = match map_ty {
+                    compile::Ty::MapIntInt => intrinsic!(slice_intint),
+                    compile::Ty::MapIntFloat => intrinsic!(slice_intfloat),
+                    compile::Ty::MapIntStr => intrinsic!(slice_intstr),
+                    ty => return err!("_slice of a string-indexed array: {:?}", ty),
+                };
+                let args = [(*map, *map_ty), from.reflect(), to.reflect()];
+                self.array_fn(func, &args, (*dst, *map_ty))
+            }
             Store {
                 map_ty,
                 map,
@@ -0,0 +1,358 @@
+//! Column statistics pushdown.
+//!
+//! A common use of AWK is to compute a quick statistic over one column of a file:
+//!
+//! ```text
+//! $3 + 0 > max { max = $3 + 0 } END { print max }
+//! NR == 1 || $2 < lo { lo = $2 + 0 } { n++ } END { print lo, n }
+//! ```
+//!
+//! Programs like these fold every record into a handful of accumulators, and the only thing they
+//! need from a record is the value of a single column. `plan` recognizes them from their syntax
+//! tree, and `run` evaluates them directly against the input reader: the reader only splits out
+//! the aggregated column (see the `pushdown` module for how that works in the batch readers), the
+//! column is parsed as a number in place, and records are never materialized as AWK values.
+//!
+//! The recognized programs are deliberately narrow, so that `run` can reproduce exactly what the
+//! compiled program would have printed:
+//!
+//! * No BEGIN blocks, functions, or `-v` assignments, and no changes to OFS or ORS.
+//! * Main rules are either `COND { v = $k + 0 }` for an extremum, or an unconditional block of
+//!   `if (COND) v = $k + 0` and counting statements (`n++`, `n += 1`, `n = n + 1`).
+//! * COND compares `$k` (or `$k + 0`) to `v` with `<`, `<=`, `>` or `>=`, optionally preceded by
+//!   `NR == 1 ||` to start from the first value rather than from zero. `$k * 1` may be used in
+//!   place of `$k + 0`.
+//! * Every extremum uses the same column, and every accumulator is updated by one statement.
+//! * The END block only prints accumulators, `NR`, and string literals to standard output.
+use crate::ast::{Binop, Expr, Pattern, Prog, Stmt, Unop};
+use crate::builtins::Variable;
+use crate::common::{Result, Stage};
+use crate::pushdown::FieldSet;
+use crate::runtime::writers::FileFactory;
+use crate::runtime::{self, math_util, FileWrite, Float, Int, Line, LineReader, RegexCache, Str};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Agg {
+    /// The number of records read.
+    Count,
+    /// Replace the accumulator with the column's value whenever `value op acc` holds, or on the
+    /// first record when `first` is set.
+    Extremum { op: Binop, first: bool, value: Value },
+}
+
+/// How the column's value is turned into a number before it is stored.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Value {
+    PlusZero,
+    TimesOne,
+}
+
+impl Value {
+    fn apply(self, x: Float) -> Float {
+        match self {
+            Value::PlusZero => x + 0.0,
+            Value::TimesOne => x * 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Output {
+    Acc(usize),
+    NR,
+    Lit(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Plan {
+    /// The aggregated column, if any extrema are computed.
+    pub col: Option<usize>,
+    pub aggs: Vec<Agg>,
+    /// The arguments of each print statement in the END block.
+    pub prints: Vec<Vec<Output>>,
+    pub field_sep: Option<Vec<u8>>,
+}
+
+struct Builder<I> {
+    col: Option<usize>,
+    accs: Vec<I>,
+    aggs: Vec<Agg>,
+}
+
+impl<I: Clone + PartialEq> Builder<I>
+where
+    Variable: TryFrom<I>,
+{
+    fn add(&mut self, var: &I, agg: Agg, col: Option<usize>) -> Option<()> {
+        if Variable::try_from(var.clone()).is_ok() || self.accs.contains(var) {
+            return None;
+        }
+        if let Some(col) = col {
+            if *self.col.get_or_insert(col) != col {
+                return None;
+            }
+        }
+        self.accs.push(var.clone());
+        self.aggs.push(agg);
+        Some(())
+    }
+
+    // `if (cond) v = $k + 0`, or the same written as a pattern and an action.
+    fn extremum<'a, 'b>(&mut self, cond: &Expr<'a, 'b, I>, stmt: &Stmt<'a, 'b, I>) -> Option<()> {
+        let (var, value, col) = match single(stmt)? {
+            Stmt::Expr(Expr::Assign(Expr::Var(v), rhs)) => {
+                let (value, col) = numeric_col(rhs)?;
+                (v, value, col)
+            }
+            _ => return None,
+        };
+        let (first, cmp) = match cond {
+            Expr::Or(Expr::Binop(Binop::EQ, Expr::Var(nr), Expr::ILit(1)), cmp) if is_nr(nr) => {
+                (true, *cmp)
+            }
+            cmp => (false, cmp),
+        };
+        let op = match cmp {
+            Expr::Binop(op, lhs, Expr::Var(v)) if v == var && compared_col(lhs)? == col => *op,
+            Expr::Binop(op, Expr::Var(v), rhs) if v == var && compared_col(rhs)? == col => {
+                match op {
+                    Binop::LT => Binop::GT,
+                    Binop::LTE => Binop::GTE,
+                    Binop::GT => Binop::LT,
+                    Binop::GTE => Binop::LTE,
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        if !matches!(op, Binop::LT | Binop::LTE | Binop::GT | Binop::GTE) {
+            return None;
+        }
+        self.add(var, Agg::Extremum { op, first, value }, Some(col))
+    }
+
+    fn stmt<'a, 'b>(&mut self, stmt: &Stmt<'a, 'b, I>) -> Option<()> {
+        use Expr::{Assign, AssignOp, ILit, Inc, Var};
+        match stmt {
+            Stmt::Block(stmts) => stmts.iter().try_for_each(|s| self.stmt(s)),
+            Stmt::If(cond, then, None) => self.extremum(cond, then),
+            Stmt::Expr(Inc {
+                is_inc: true,
+                x: Var(v),
+                ..
+            })
+            | Stmt::Expr(AssignOp(Var(v), Binop::Plus, ILit(1))) => self.add(v, Agg::Count, None),
+            Stmt::Expr(Assign(Var(v), Expr::Binop(Binop::Plus, Var(w), ILit(1)))) if v == w => {
+                self.add(v, Agg::Count, None)
+            }
+            _ => None,
+        }
+    }
+
+    fn prints<'a, 'b>(&self, stmt: &Stmt<'a, 'b, I>, out: &mut Vec<Vec<Output>>) -> Option<()> {
+        match stmt {
+            Stmt::Block(stmts) => stmts.iter().try_for_each(|s| self.prints(s, out)),
+            Stmt::Print(args, None) if !args.is_empty() => {
+                out.push(args.iter().map(|e| self.output(e)).collect::<Option<_>>()?);
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    fn output<'a, 'b>(&self, e: &Expr<'a, 'b, I>) -> Option<Output> {
+        match e {
+            Expr::StrLit(s) => Some(Output::Lit(s.to_vec())),
+            Expr::Var(v) if is_nr(v) => Some(Output::NR),
+            Expr::Var(v) => self.accs.iter().position(|a| a == v).map(Output::Acc),
+            _ => None,
+        }
+    }
+}
+
+fn is_nr<I: Clone>(v: &I) -> bool
+where
+    Variable: TryFrom<I>,
+{
+    matches!(Variable::try_from(v.clone()), Ok(Variable::NR))
+}
+
+// Unwrap `{ stmt }`.
+fn single<'c, 'a, 'b, I>(stmt: &'c Stmt<'a, 'b, I>) -> Option<&'c Stmt<'a, 'b, I>> {
+    match stmt {
+        Stmt::Block(stmts) if stmts.len() == 1 => single(stmts[0]),
+        Stmt::Block(_) => None,
+        stmt => Some(stmt),
+    }
+}
+
+// `$k`, for a constant k > 0.
+fn col<I>(e: &Expr<I>) -> Option<usize> {
+    match e {
+        Expr::Unop(Unop::Column, Expr::ILit(k)) if *k > 0 => Some(*k as usize),
+        _ => None,
+    }
+}
+
+// `$k + 0` or `$k * 1`: the numeric value of column k.
+fn numeric_col<I>(e: &Expr<I>) -> Option<(Value, usize)> {
+    match e {
+        Expr::Binop(Binop::Plus, c, Expr::ILit(0)) | Expr::Binop(Binop::Plus, Expr::ILit(0), c) => {
+            Some((Value::PlusZero, col(c)?))
+        }
+        Expr::Binop(Binop::Mult, c, Expr::ILit(1)) | Expr::Binop(Binop::Mult, Expr::ILit(1), c) => {
+            Some((Value::TimesOne, col(c)?))
+        }
+        _ => None,
+    }
+}
+
+// The column compared against a numeric accumulator. The comparison is numeric either way, and
+// adding zero does not change its outcome.
+fn compared_col<I>(e: &Expr<I>) -> Option<usize> {
+    col(e).or_else(|| numeric_col(e).map(|(_, k)| k))
+}
+
+/// Recognize programs that only compute extrema and counts over a single column.
+pub(crate) fn plan<'a, 'b, I: Clone + PartialEq>(prog: &Prog<'a, 'b, I>) -> Option<Plan>
+where
+    Variable: TryFrom<I>,
+{
+    if !matches!(prog.stage, Stage::Main(_))
+        || !prog.decs.is_empty()
+        || !prog.begin.is_empty()
+        || !prog.prepare.is_empty()
+        || !prog.prelude_vardecs.is_empty()
+        || prog.output_sep.is_some()
+        || prog.output_record_sep.is_some()
+        || prog.output_template.is_some()
+        || prog.parse_header
+        || prog.end.is_empty()
+    {
+        return None;
+    }
+    let mut b = Builder {
+        col: None,
+        accs: Vec::new(),
+        aggs: Vec::new(),
+    };
+    for (pat, body) in prog.pats.iter() {
+        match (pat, body) {
+            (Pattern::Null, Some(body)) => b.stmt(body)?,
+            (Pattern::Bool(cond), Some(body)) => b.extremum(cond, body)?,
+            _ => return None,
+        }
+    }
+    let mut prints = Vec::new();
+    for stmt in prog.end.iter() {
+        b.prints(stmt, &mut prints)?;
+    }
+    Some(Plan {
+        col: b.col,
+        aggs: b.aggs,
+        prints,
+        field_sep: prog.field_sep.map(<[u8]>::to_vec),
+    })
+}
+
+#[derive(Copy, Clone)]
+enum Acc {
+    Int(Int),
+    Float(Float),
+}
+
+/// Evaluate `plan` over the records of `inp`, printing the results with `ff`. `input_files` are
+/// the file operands, which readers that take their files from ARGV are given up front.
+pub(crate) fn run(
+    plan: &Plan,
+    input_files: &[String],
+    mut inp: impl LineReader,
+    ff: impl FileFactory,
+) -> Result<()> {
+    if inp.reads_argv() {
+        let mut files = input_files
+            .iter()
+            .filter(|f| !f.is_empty() && runtime::split_assignment(f).is_none())
+            .peekable();
+        if files.peek().is_none() {
+            inp.push_input("-");
+        }
+        for file in files {
+            inp.push_input(file);
+        }
+    }
+    let fs: Str = match &plan.field_sep {
+        Some(sep) => Str::from(&sep[..]).unmoor(),
+        None => Str::from(" "),
+    };
+    let ofs = Str::from(" ");
+    let mut rc = RegexCache::default();
+    inp.set_used_fields(&plan.col.map_or_else(FieldSet::empty, FieldSet::singleton));
+
+    let mut accs: Vec<Acc> = plan
+        .aggs
+        .iter()
+        .map(|agg| match agg {
+            Agg::Count => Acc::Int(0),
+            Agg::Extremum { .. } => Acc::Float(0.0),
+        })
+        .collect();
+    let mut nr: Int = 0;
+    let mut line = Default::default();
+    loop {
+        inp.read_line_reuse(&fs, &mut rc, &mut line)?;
+        if inp.read_state() <= 0 {
+            break;
+        }
+        nr += 1;
+        let x = match plan.col {
+            Some(col) => math_util::strtonum(line.get_col(col as Int, &fs, &ofs, &mut rc)?.as_str()),
+            None => 0.0,
+        };
+        for (agg, acc) in plan.aggs.iter().zip(accs.iter_mut()) {
+            match (agg, acc) {
+                (Agg::Count, Acc::Int(n)) => *n += 1,
+                (Agg::Extremum { op, first, value }, Acc::Float(m)) => {
+                    let replace = (*first && nr == 1)
+                        || match op {
+                            Binop::LT => x < *m,
+                            Binop::LTE => x <= *m,
+                            Binop::GT => x > *m,
+                            Binop::GTE => x >= *m,
+                            _ => false,
+                        };
+                    if replace {
+                        *m = value.apply(x);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut out = FileWrite::new(ff);
+    let ors = Str::from("\n");
+    for print in plan.prints.iter() {
+        let strs: Vec<Str> = print
+            .iter()
+            .map(|o| match o {
+                Output::Acc(i) => match accs[*i] {
+                    Acc::Int(n) => runtime::convert::<Int, Str>(n),
+                    Acc::Float(f) => runtime::convert::<Float, Str>(f),
+                },
+                Output::NR => runtime::convert::<Int, Str>(nr),
+                Output::Lit(s) => Str::from(&s[..]).unmoor(),
+            })
+            .collect();
+        let mut args = Vec::with_capacity(strs.len() * 2);
+        for (i, s) in strs.iter().enumerate() {
+            if i > 0 {
+                args.push(&ofs);
+            }
+            args.push(s);
+        }
+        args.push(&ors);
+        out.write_all(&args[..], None)?;
+    }
+    out.shutdown()
+}
@@ -1,5 +1,6 @@
 //! This file contains common type definitions and utilities used in other parts of the project.
 use hashbrown::HashSet;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::fmt;
 use std::hash::Hash;
@@ -47,7 +48,7 @@ impl ExecutionStrategy {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Stage<T> {
     Main(T),
     Par {
@@ -294,6 +295,18 @@ impl Notification {
         self.notified.store(true, Ordering::Release);
         self.cv.notify_all();
     }
+    /// Wait for a notification for at most `timeout`, returning whether one was received.
+    pub fn wait_timeout(&self, timeout: std::time::Duration) -> bool {
+        if self.has_been_notified() {
+            return true;
+        }
+        let guard = self.mu.lock().unwrap();
+        let (_guard, _res) = self
+            .cv
+            .wait_timeout_while(guard, timeout, |_| !self.has_been_notified())
+            .unwrap();
+        self.has_been_notified()
+    }
     pub fn wait(&self) {
         loop {
             // Fast path: check if the notification has already happened.
@@ -388,7 +401,7 @@ impl<Arg> Drop for Cleanup<Arg> {
     }
 }
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
 #[repr(i64)]
 pub enum FileSpec {
     Trunc = 0,
@@ -421,6 +434,33 @@ impl std::convert::TryFrom<i64> for FileSpec {
     }
 }
 
+/// How the values a variable takes in each worker of a parallel script are combined before the
+/// END block runs. Set from a script with `PARALLEL_MERGE["var"] = "min"`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MergeStrategy {
+    #[default]
+    Sum,
+    Min,
+    Max,
+    Concat,
+    Union,
+}
+
+impl std::convert::TryFrom<&[u8]> for MergeStrategy {
+    type Error = ();
+    fn try_from(s: &[u8]) -> std::result::Result<MergeStrategy, ()> {
+        match s {
+            b"sum" => Ok(MergeStrategy::Sum),
+            b"min" => Ok(MergeStrategy::Min),
+            b"max" => Ok(MergeStrategy::Max),
+            b"concat" => Ok(MergeStrategy::Concat),
+            b"union" => Ok(MergeStrategy::Union),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg_attr(not(any(feature = "jit", feature = "llvm_backend")), allow(dead_code))]
 pub(crate) fn traverse<T>(o: Option<Result<T>>) -> Result<Option<T>> {
     match o {
         Some(e) => Ok(Some(e?)),
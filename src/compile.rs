@@ -1,21 +1,29 @@
 use crate::builtins;
 use crate::bytecode;
 use crate::cfg::{self, is_unused, Function, Ident, PrimExpr, PrimStmt, PrimVal, ProgramContext};
+#[cfg(any(feature = "jit", feature = "llvm_backend"))]
 use crate::codegen;
 #[cfg(feature = "llvm_backend")]
 use crate::codegen::llvm;
+#[cfg(any(feature = "jit", feature = "llvm_backend"))]
+use crate::common::CancelSignal;
 use crate::common::{
-    CancelSignal, CompileError, Either, Graph, NodeIx, NumTy, Result, Stage, WorkList,
+    CompileError, Either, Graph, MergeStrategy, NodeIx, NumTy, Result, Stage, WorkList,
 };
+use crate::const_fold;
 use crate::cross_stage;
 use crate::input_taint::TaintedStringAnalysis;
+use crate::interp::MergeSpec;
+use crate::precompile;
 use crate::pushdown::{FieldSet, UsedFieldAnalysis};
 use crate::runtime::{self, Str};
 use crate::string_constants::{self, StringConstantAnalysis};
+use crate::trace;
 use crate::types;
 
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
+use serde::{Deserialize, Serialize};
 use smallvec::smallvec;
 
 use std::collections::VecDeque;
@@ -26,7 +34,7 @@ use crate::compile::Ty::{MapIntFloat, MapIntInt, MapIntStr, MapStrFloat, MapStrI
 pub(crate) const UNUSED: u32 = u32::max_value();
 pub(crate) const NULL_REG: u32 = UNUSED - 1;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Default)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Hash, Default, Serialize, Deserialize)]
 pub(crate) enum Ty {
     Int = 0,
     Float = 1,
@@ -114,6 +122,29 @@ impl Ty {
         }
     }
 
+    /// The map type with the given key and value types.
+    pub(crate) fn map(key: Ty, val: Ty) -> Result<Ty> {
+        use Ty::*;
+        match (key, val) {
+            (Int, Int) => Ok(MapIntInt),
+            (Int, Float) => Ok(MapIntFloat),
+            (Int, Str) => Ok(MapIntStr),
+            (Str, Int) => Ok(MapStrInt),
+            (Str, Float) => Ok(MapStrFloat),
+            (Str, Str) => Ok(MapStrStr),
+            _ => err!("no map type with keys of type {:?} and values of type {:?}", key, val),
+        }
+    }
+
+    /// The type of `_invert` of a map of this type: values become keys, with floats as strings.
+    pub(crate) fn inverted(self) -> Result<Ty> {
+        let key = match self.val()? {
+            Ty::Float => Ty::Str,
+            val => val,
+        };
+        Ty::map(key, self.key()?)
+    }
+
     pub(crate) fn val(self) -> Result<Ty> {
         use Ty::*;
         match self {
@@ -161,7 +192,43 @@ pub(crate) fn bytecode<'a, LR: runtime::LineReader>(
     Typer::init_from_ctx(ctx)?.to_interp(reader, ff, num_workers)
 }
 
-#[cfg(test)]
+/// Compile the program for the bytecode interpreter, to be saved by `zawk compile`.
+pub(crate) fn precompile<'a>(
+    ctx: &mut cfg::ProgramContext<'a, &'a str>,
+) -> Result<precompile::Program<'a>> {
+    let (field_sep, record_sep, sep_unsure) = match ctx.analyze_sep_assignments() {
+        cfg::SepAssign::Potential {
+            field_sep,
+            record_sep,
+        } => (field_sep.map(<[u8]>::to_vec), record_sep.map(<[u8]>::to_vec), false),
+        cfg::SepAssign::Unsure => (None, None, true),
+    };
+    let (record_limit, flushes, parse_header) = (ctx.record_limit, ctx.flushes, ctx.parse_header);
+    let mut typer = Typer::init_from_ctx(ctx)?;
+    let instrs = typer.to_bytecode()?;
+    let mut regs = [0; NUM_TYPES];
+    for (i, count) in regs.iter_mut().enumerate() {
+        *count = typer.regs.stats.0[i].len() as NumTy;
+    }
+    let setup = precompile::Setup {
+        stage: typer.stage(),
+        regs,
+        used_fields: typer.used_fields.clone(),
+        named_columns: typer
+            .named_columns
+            .take()
+            .map(|cols| cols.into_iter().map(<[u8]>::to_vec).collect()),
+        merge_spec: mem::take(&mut typer.merge_spec),
+        field_sep,
+        record_sep,
+        sep_unsure,
+        record_limit,
+        flushes,
+        parse_header,
+    };
+    Ok(precompile::Program { instrs, setup })
+}
+
 pub(crate) fn context_compiles<'a>(ctx: &mut cfg::ProgramContext<'a, &'a str>) -> Result<()> {
     Typer::init_from_ctx(ctx)?;
     Ok(())
@@ -210,6 +277,7 @@ pub(crate) fn run_llvm<'a>(
     let mut typer = Typer::init_from_ctx(ctx)?;
     let used_fields = typer.used_fields.clone();
     let named_cols = typer.named_columns.take();
+    let merge_spec = mem::take(&mut typer.merge_spec);
     unsafe {
         let gen = Generator::init(&mut typer, cfg)?;
         codegen::run_main(
@@ -218,12 +286,23 @@ pub(crate) fn run_llvm<'a>(
             ff,
             &used_fields,
             named_cols,
+            merge_spec,
             cfg.num_workers,
             cancel_signal,
         )
     }
 }
 
+#[cfg(feature = "jit")]
+pub(crate) fn dump_clif<'a>(
+    ctx: &mut cfg::ProgramContext<'a, &'a str>,
+    cfg: codegen::Config,
+) -> Result<String> {
+    let mut typer = Typer::init_from_ctx(ctx)?;
+    codegen::clif::Generator::dump(&mut typer, cfg)
+}
+
+#[cfg(feature = "jit")]
 pub(crate) fn run_cranelift<'a>(
     ctx: &mut cfg::ProgramContext<'a, &'a str>,
     reader: impl codegen::intrinsics::IntoRuntime,
@@ -235,6 +314,7 @@ pub(crate) fn run_cranelift<'a>(
     let mut typer = Typer::init_from_ctx(ctx)?;
     let used_fields = typer.used_fields.clone();
     let named_cols = typer.named_columns.take();
+    let merge_spec = mem::take(&mut typer.merge_spec);
     unsafe {
         let gen = Generator::init(&mut typer, cfg)?;
         codegen::run_main(
@@ -243,6 +323,7 @@ pub(crate) fn run_cranelift<'a>(
             ff,
             &used_fields,
             named_cols,
+            merge_spec,
             cfg.num_workers,
             cancel_signal,
         )
@@ -265,6 +346,8 @@ pub(crate) enum HighLevel {
     Ret(NumTy, Ty),
     Phi(NumTy, Ty, SmallVec<(NodeIx /*pred*/, NumTy /*register*/)>),
     DropIter(NumTy, Ty),
+    // Marks where the code for a line of the program starts, for locating runtime errors.
+    Line(usize),
 }
 
 #[derive(Default)]
@@ -353,6 +436,9 @@ pub(crate) struct Typer<'a> {
     used_fields: FieldSet,
     // The fields referenced by name via the FI builtin variable
     named_columns: Option<Vec<&'a [u8]>>,
+    // PARALLEL_MERGE strategies for global variables, and the resulting per-slot merge table.
+    merge_strategies: HashMap<Ident, MergeStrategy>,
+    merge_spec: MergeSpec,
     // For rejecting suspcicious programs with commands.
     taint_analysis: Option<TaintedStringAnalysis>,
     // For analysis passes that introspect into the set of constant string values that will
@@ -360,6 +446,9 @@ pub(crate) struct Typer<'a> {
     string_constants: Option<StringConstantAnalysis<'a>>,
     // Not used for bytecode generation.
     callgraph: Graph<HashSet<(NumTy, Ty)>, ()>,
+    // The name of each frame's function, and the lines of its bytecode (filled in by
+    // `to_bytecode`).
+    debug_info: trace::DebugInfo,
 
     // The global variables referenced (transitively) by each function. This is used both for
     // cross-stage state propagation for parallel execution, as well as for implementing global
@@ -445,6 +534,7 @@ struct View<'a, 'b> {
     local_globals: &'b HashSet<NumTy>,
     arity: &'b HashMap<NumTy, NumTy>,
     func_info: &'b Vec<FuncInfo>,
+    safe_math: bool,
     // The current basic block being filled; It'll be swapped into `frame.cfg` as we translate a
     // given function cfg.
     stream: &'b mut Node<'a>,
@@ -515,6 +605,7 @@ fn accum(inst: &Instr, mut f: impl FnMut(NumTy, Ty)) {
             }
         }
         Right(Ret(reg, ty)) | Right(Phi(reg, ty, _)) | Right(DropIter(reg, ty)) => f(*reg, *ty),
+        Right(Line(_)) => {}
     }
 }
 
@@ -532,7 +623,7 @@ impl<'a> Typer<'a> {
     ) -> Result<bytecode::Interp<'a, LR>> {
         let instrs = self.to_bytecode()?;
         let cols = self.named_columns.take();
-        Ok(bytecode::Interp::new(
+        let mut interp = bytecode::Interp::new(
             instrs,
             self.stage(),
             num_workers,
@@ -541,7 +632,10 @@ impl<'a> Typer<'a> {
             ff,
             &self.used_fields,
             cols,
-        ))
+        );
+        interp.set_merge_spec(mem::take(&mut self.merge_spec));
+        interp.set_debug_info(mem::take(&mut self.debug_info));
+        Ok(interp)
     }
 
     // At initialization time, we generate Either<LL, HL>, this function lowers the HL into LL.
@@ -661,6 +755,9 @@ impl<'a> Typer<'a> {
                         Either::Right(Phi(_, _, _)) => {}
                         // we do not explicitly drop iterators in the bytecode interpreter.
                         Either::Right(DropIter(_, _)) => {}
+                        Either::Right(Line(line)) => {
+                            self.debug_info.funcs[i].lines.push((instrs.len(), *line))
+                        }
                     }
                 }
 
@@ -729,6 +826,7 @@ impl<'a> Typer<'a> {
             ));
         }
         let types::TypeInfo { var_tys, func_tys } = types::get_types(pc)?;
+        gen.merge_strategies = pc.merge_strategies();
         let local_globals = pc.local_globals();
         macro_rules! init_entry {
             ($v:expr, $func_id:expr, $args:expr) => {
@@ -741,6 +839,13 @@ impl<'a> Typer<'a> {
                     f.cur_ident = res;
                     gen.frames.push(f);
                     gen.callgraph.add_node(Default::default());
+                    gen.debug_info.funcs.push(trace::FuncLines {
+                        name: match &pc.funcs[$func_id as usize].name {
+                            cfg::FunctionName::Named(name) => Some(name.to_string()),
+                            _ => None,
+                        },
+                        lines: Vec::new(),
+                    });
                     gen.func_info.push(FuncInfo {
                         ret_ty,
                         arg_tys: $args.clone(),
@@ -788,6 +893,16 @@ impl<'a> Typer<'a> {
             .main_stage()
             .map_ref(|o| gen.id_map[&(*o as NumTy, Default::default())] as usize);
         gen.local_globals = local_globals;
+        gen.debug_info.sources = pc.sources.clone();
+        if pc.debug() {
+            gen.debug_info.globals = pc
+                .globals()
+                .filter_map(|(name, id)| {
+                    let (reg, ty) = gen.regs.globals.get(&id)?;
+                    Some((name.to_string(), *reg, *ty))
+                })
+                .collect();
+        }
         for frame in gen.frames.iter_mut() {
             let src_func = frame.src_function as usize;
             let mut stream = Default::default();
@@ -799,12 +914,23 @@ impl<'a> Typer<'a> {
                 arity: &gen.arity,
                 local_globals: &gen.local_globals,
                 func_info: &gen.func_info,
+                safe_math: pc.safe_math,
                 stream: &mut stream,
             }
                 .process_function(&pc.funcs[src_func])?;
         }
+        // Fold constants first, so the analyses below see the folded values.
+        for frame in gen.frames.iter_mut() {
+            for bb in frame.cfg.node_weights_mut() {
+                const_fold::fold_block(&mut bb.insts, pc.arena);
+            }
+        }
         // TODO: mark used frames first and then exclude them from the analyses?
         gen.run_analyses()?;
+        if pc.debug() {
+            // The debugger can print any field.
+            gen.used_fields = FieldSet::all();
+        }
         gen.mark_used_frames();
         gen.add_slots()?;
         Ok(gen)
@@ -824,7 +950,9 @@ impl<'a> Typer<'a> {
                     if let Some(sca) = &mut self.string_constants {
                         if sca.cfg().query_regex {
                             if let Either::Left(LL::IsMatch(_, _, pat))
-                            | Either::Left(LL::Match(_, _, pat)) = stmt
+                            | Either::Left(LL::Match(_, _, pat))
+                            | Either::Left(LL::Sub(_, pat, _, _))
+                            | Either::Left(LL::GSub(_, pat, _, _)) = stmt
                             {
                                 refs.push((fix, bbix, stmtix, *pat));
                             }
@@ -880,6 +1008,12 @@ impl<'a> Typer<'a> {
                         Either::Left(LL::Match(dst, s, _)) => {
                             Either::Left(LL::MatchConst(*dst, *s, re))
                         }
+                        Either::Left(LL::Sub(dst, _, s, in_s)) => {
+                            Either::Left(LL::SubConst(*dst, re, *s, *in_s))
+                        }
+                        Either::Left(LL::GSub(dst, _, s, in_s)) => {
+                            Either::Left(LL::GSubConst(*dst, re, *s, *in_s))
+                        }
                         _ => {
                             return err!(
                                 "unexpected instruction during regex constant folding: {:?}",
@@ -935,6 +1069,13 @@ impl<'a> Typer<'a> {
         if let Some(off) = end {
             self.frames[off].load_slots(slots.loop_stores.iter().cloned(), &mut ctr)?;
         }
+        for (id, m) in self.merge_strategies.iter() {
+            if let Some(reg) = self.regs.globals.get(id) {
+                if let Some(slot) = ctr.slots.get(reg) {
+                    self.merge_spec.set(reg.1, *slot, *m);
+                }
+            }
+        }
 
         Ok(())
     }
@@ -1054,6 +1195,14 @@ impl<'a, 'b> View<'a, 'b> {
                                 self.convert(dst, Ty::Int, reg, ty)?;
                                 reg = dst;
                             }
+                            Ty::Str if runtime::bignum::enabled() => {
+                                // With -M, strings holding zero are false, as `!` has it.
+                                let not = self.regs.stats.reg_of_ty(Ty::Int);
+                                let dst = self.regs.stats.reg_of_ty(Ty::Int);
+                                self.pushl(LL::NotStr(not.into(), reg.into()));
+                                self.pushl(LL::Not(dst.into(), not.into()));
+                                reg = dst;
+                            }
                             Ty::Str => {
                                 let dst = self.regs.stats.reg_of_ty(Ty::Int);
                                 self.pushl(LL::LenStr(dst.into(), reg.into()));
@@ -1382,6 +1531,29 @@ impl<'a, 'b> View<'a, 'b> {
                 LL::NegInt(res_reg.into(), conv_regs[0].into())
             }),
             Unop(Pos) => self.mov(res_reg, conv_regs[0], conv_tys[0])?,
+            // Under --safe-math, float addition and the like cannot fail, so they are left alone.
+            Binop(op @ (Plus | Minus | Mult | Div | Mod))
+                if self.safe_math && (conv_tys[0] == Ty::Int || matches!(op, Div | Mod)) =>
+            {
+                use runtime::safe_math::Op;
+                let op = match op {
+                    Plus => Op::Add,
+                    Minus => Op::Sub,
+                    Mult => Op::Mul,
+                    Div => Op::Div,
+                    _ => Op::Mod,
+                };
+                // Errors set ERRNO, so the instruction stays even if the result is unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let (x, y) = (conv_regs[0], conv_regs[1]);
+                self.pushl(if conv_tys[0] == Ty::Int {
+                    LL::SafeInt(op, res_reg.into(), x.into(), y.into())
+                } else {
+                    LL::SafeFloat(op, res_reg.into(), x.into(), y.into())
+                });
+            }
             Binop(Plus) => gen_op!(Plus, [Float, AddFloat], [Int, AddInt]),
             Binop(Minus) => gen_op!(Minus, [Float, MinusFloat], [Int, MinusInt]),
             Binop(Mult) => gen_op!(Minus, [Float, MulFloat], [Int, MulInt]),
@@ -1414,6 +1586,18 @@ impl<'a, 'b> View<'a, 'b> {
                 }
             }
             Match => gen_op!(Match, [Str, Match]),
+            MatchArr => {
+                // Always emitted: it fills in the array, and RSTART and RLENGTH.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::MatchArr(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                    conv_regs[2].into(),
+                ))
+            }
             SubstrIndex => gen_op!(SubstrIndex, [Str, SubstrIndex]),
             SubstrLastIndex => gen_op!(SubstrLastIndex, [Str, SubstrLastIndex]),
             Contains => {
@@ -1444,7 +1628,50 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::RunCmd(res_reg.into(), conv_regs[0].into()))
             }
+            ExecOutput | Spawn => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let dst = res_reg.into();
+                self.pushl(match (bf, conv_tys[0]) {
+                    (ExecOutput, Ty::Str) => LL::ExecOutput(dst, conv_regs[0].into()),
+                    (ExecOutput, Ty::MapIntStr) => LL::ExecOutputArgv(dst, conv_regs[0].into()),
+                    (Spawn, Ty::Str) => LL::Spawn(dst, conv_regs[0].into()),
+                    (Spawn, Ty::MapIntStr) => LL::SpawnArgv(dst, conv_regs[0].into()),
+                    _ => return err!("invalid input type for {}: {:?}", bf, &conv_tys[..]),
+                })
+            }
+            SetEnv => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::SetEnv(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
+            }
+            FFlush => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FFlush(res_reg.into(), conv_regs[0].into()))
+            }
+            UnsetEnv => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::UnsetEnv(res_reg.into(), conv_regs[0].into()))
+            }
             Exit => self.pushl(LL::Exit(conv_regs[0].into())),
+            Ext(ix) => {
+                // Extensions may have side effects, so they are called even if unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let args = conv_regs.iter().map(|r| (*r).into()).collect();
+                self.pushl(LL::CallExt(res_reg.into(), *ix, args))
+            }
             ReadErr => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ReadErr(
@@ -1588,6 +1815,43 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::ReseedRng(res_reg.into()))
             }
+            RandInt => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::RandInt(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+            }
+            Choice => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Choice {
+                    map_ty: conv_tys[0],
+                    dst: res_reg,
+                    map: conv_regs[0],
+                })
+            }
+            Sample => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Sample {
+                    map_ty: conv_tys[0],
+                    dst: res_reg,
+                    map: conv_regs[0],
+                    k: conv_regs[1].into(),
+                })
+            }
+            Shuffle => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Shuffle {
+                    map_ty: conv_tys[0],
+                    dst: res_reg,
+                    map: conv_regs[0],
+                })
+            }
             Split => {
                 if res_reg == UNUSED {
                     res_reg = self.regs.stats.reg_of_ty(res_ty);
@@ -1610,6 +1874,28 @@ impl<'a, 'b> View<'a, 'b> {
                     return err!("invalid input types to split: {:?}", &conv_tys[..]);
                 })
             }
+            SplitSeps | PatSplit => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let (flds, s, pat) = (res_reg.into(), conv_regs[0].into(), conv_regs[2].into());
+                let (arr, seps) = (conv_regs[1], conv_regs[3]);
+                self.pushl(match (*bf, conv_tys[1]) {
+                    (SplitSeps, Ty::MapIntStr) => {
+                        LL::SplitSepsInt(flds, s, arr.into(), pat, seps.into())
+                    }
+                    (SplitSeps, Ty::MapStrStr) => {
+                        LL::SplitSepsStr(flds, s, arr.into(), pat, seps.into())
+                    }
+                    (PatSplit, Ty::MapIntStr) => {
+                        LL::PatSplitInt(flds, s, arr.into(), pat, seps.into())
+                    }
+                    (PatSplit, Ty::MapStrStr) => {
+                        LL::PatSplitStr(flds, s, arr.into(), pat, seps.into())
+                    }
+                    _ => return err!("invalid input types to {}: {:?}", bf, &conv_tys[..]),
+                })
+            }
             Length => {
                 if res_reg != UNUSED {
                     self.pushl(match conv_tys[0] {
@@ -1793,28 +2079,27 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
-            Digest => {
+            Compress => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::Digest(
+                    self.pushl(LL::Compress(
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
                     ))
                 }
             }
-            Hmac => {
+            Decompress => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::Hmac(
+                    self.pushl(LL::Decompress(
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
-                        conv_regs[2].into(),
                     ))
                 }
             }
-            Jwt => {
+            Iconv => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::Jwt(
+                    self.pushl(LL::Iconv(
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
@@ -1822,15 +2107,90 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
-            Dejwt => {
+            Utf8Valid => {
                 if res_reg != UNUSED {
-                    self.pushl(LL::Dejwt(
+                    self.pushl(LL::Utf8Valid(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Utf8Fix => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Utf8Fix(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Normalize(form) => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Normalize(res_reg.into(), conv_regs[0].into(), *form))
+                }
+            }
+            Digest => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Digest(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            FileDigest => {
+                // Emitted even if the result is unused, as a missing file sets ERRNO.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FileDigest(
+                    res_reg.into(),
+                    conv_regs[0].into(),
+                    conv_regs[1].into(),
+                ))
+            }
+            DigestInit | DigestUpdate | DigestFinal => {
+                // These change the digest contexts held by the runtime.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(match bf {
+                    DigestInit => LL::DigestInit(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()),
+                    DigestUpdate => LL::DigestUpdate(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()),
+                    _ => LL::DigestFinal(res_reg.into(), conv_regs[0].into()),
+                })
+            }
+            Hmac => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Hmac(
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
+                        conv_regs[2].into(),
                     ))
                 }
             }
+            Jwt | Dejwt => {
+                // Emitted even if the result is unused, as a bad key or token sets ERRNO.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(if let Jwt = bf {
+                    LL::Jwt(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    )
+                } else {
+                    LL::Dejwt(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    )
+                })
+            }
+            Cert => {
+                // Emitted even if the result is unused, as a bad certificate sets ERRNO.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Cert(res_reg.into(), conv_regs[0].into()))
+            }
             Encrypt => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Encrypt(
@@ -1857,6 +2217,7 @@ impl<'a, 'b> View<'a, 'b> {
                         res_reg.into(),
                         conv_regs[0].into(),
                         conv_regs[1].into(),
+                        conv_regs[2].into(),
                     ))
                 }
             }
@@ -1914,6 +2275,15 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            ParseNumber => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::ParseNumber(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
             FormatBytes => {
                 if res_reg != UNUSED {
                     self.pushl(LL::FormatBytes(
@@ -1922,6 +2292,16 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            FormatNum => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::FormatNum(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
             ToBytes => {
                 if res_reg != UNUSED {
                     self.pushl(LL::ToBytes(
@@ -2044,6 +2424,62 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            NatCmp => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::NatCmp(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Levenshtein => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Levenshtein(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Similarity => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Similarity(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Diff => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Diff(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            Soundex => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Soundex(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Metaphone => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Metaphone(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            FuzzyMatch => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::FuzzyMatch(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
             Mask => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Mask(
@@ -2161,6 +2597,21 @@ impl<'a, 'b> View<'a, 'b> {
                 }
                 self.pushl(LL::Systime(res_reg.into()))
             }
+            SystimeMs | SystimeUs | Clock => {
+                if res_reg != UNUSED {
+                    self.pushl(match bf {
+                        SystimeMs => LL::SystimeMs(res_reg.into()),
+                        SystimeUs => LL::SystimeUs(res_reg.into()),
+                        _ => LL::Clock(res_reg.into()),
+                    })
+                }
+            }
+            Sleep => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Sleep(res_reg.into(), conv_regs[0].into()))
+            }
             Fend => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Fend(res_reg.into(), conv_regs[0].into()))
@@ -2171,6 +2622,38 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::Url(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            UaParse => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::UaParse(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Logfmt => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Logfmt(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            ToLogfmt => {
+                if res_reg != UNUSED {
+                    match conv_tys[0] {
+                        Ty::MapStrInt => {
+                            self.pushl(LL::MapStrIntToLogfmt(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                        }
+                        Ty::MapStrFloat => {
+                            self.pushl(LL::MapStrFloatToLogfmt(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                        }
+                        Ty::MapStrStr => {
+                            self.pushl(LL::MapStrStrToLogfmt(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                        }
+                        _ => {
+                            return err!(
+                                "to_logfmt only support StrMap called with malformed types: {:?} => {:?}",
+                                &conv_tys[..],
+                                dst_ty
+                            );
+                        }
+                    }
+                }
+            }
             Pairs => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Pairs(res_reg.into(), conv_regs[0].into(),
@@ -2207,6 +2690,30 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::DateTime(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            DateParse => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::DateParse(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                    ))
+                }
+            }
+            DateAdd | DateDiff => {
+                if res_reg != UNUSED {
+                    let (dst, a, b, unit) = (
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    );
+                    self.pushl(if let DateAdd = bf {
+                        LL::DateAdd(dst, a, b, unit)
+                    } else {
+                        LL::DateDiff(dst, a, b, unit)
+                    })
+                }
+            }
             Shlex => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Shlex(res_reg.into(), conv_regs[0].into()))
@@ -2257,15 +2764,16 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::HttpPost(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
                 }
             }
-            S3Get => {
-                if res_reg != UNUSED {
-                    self.pushl(LL::S3Get(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
-                }
-            }
-            S3Put => {
-                if res_reg != UNUSED {
-                    self.pushl(LL::S3Put(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+            S3Get | S3Put => {
+                // Requests have effects, and failures set ERRNO, even if the result is unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(Ty::Str);
                 }
+                self.pushl(if let S3Get = bf {
+                    LL::S3Get(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())
+                } else {
+                    LL::S3Put(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into())
+                })
             }
             KvGet => {
                 if res_reg != UNUSED {
@@ -2281,14 +2789,41 @@ impl<'a, 'b> View<'a, 'b> {
             KvClear => {
                 self.pushl(LL::KvClear(conv_regs[0].into()))
             }
-            ReadAll => {
-                if res_reg != UNUSED {
-                    self.pushl(LL::ReadAll(res_reg.into(), conv_regs[0].into()))
+            ReadAll | ReadLines => {
+                // Failures set ERRNO, so the read happens even if the result is unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
                 }
+                self.pushl(if let ReadAll = bf {
+                    LL::ReadAll(res_reg.into(), conv_regs[0].into())
+                } else {
+                    LL::ReadLines(res_reg.into(), conv_regs[0].into())
+                })
             }
             WriteAll => {
                 self.pushl(LL::WriteAll(conv_regs[0].into(), conv_regs[1].into()))
             }
+            AppendAll => {
+                self.pushl(LL::AppendAll(conv_regs[0].into(), conv_regs[1].into()))
+            }
+            Exists => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Exists(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Glob | Stat | Mkdirp | RmFile => {
+                // These have effects, or set ERRNO on failure, even if the result is unused.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                let path = conv_regs[0].into();
+                self.pushl(match bf {
+                    Glob => LL::Glob(res_reg.into(), path),
+                    Stat => LL::Stat(res_reg.into(), path),
+                    Mkdirp => LL::Mkdirp(res_reg.into(), path),
+                    _ => LL::RmFile(res_reg.into(), path),
+                })
+            }
             LogDebug => {
                 self.pushl(LL::LogDebug(conv_regs[0].into()))
             }
@@ -2321,6 +2856,107 @@ impl<'a, 'b> View<'a, 'b> {
                     self.pushl(LL::FromJson(res_reg.into(), conv_regs[0].into()))
                 }
             }
+            // The TOML builtins are emitted even if their result is unused, as errors set ERRNO.
+            FromToml => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FromToml(res_reg.into(), conv_regs[0].into()))
+            }
+            ToToml => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                match conv_tys[0] {
+                    Ty::MapStrInt => self.pushl(LL::MapStrIntToToml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                    Ty::MapStrFloat => self.pushl(LL::MapStrFloatToToml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                    Ty::MapStrStr => self.pushl(LL::MapStrStrToToml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                    _ => {
+                        return err!(
+                            "to_toml only support StrMap called with malformed types: {:?} => {:?}",
+                            &conv_tys[..],
+                            dst_ty
+                        );
+                    }
+                }
+            }
+            TomlValue => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::TomlValue(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+            }
+            FromIni => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::FromIni(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            ToIni => {
+                if res_reg != UNUSED {
+                    match conv_tys[0] {
+                        Ty::MapStrInt => self.pushl(LL::MapStrIntToIni(res_reg.into(), conv_regs[0].into())),
+                        Ty::MapStrFloat => self.pushl(LL::MapStrFloatToIni(res_reg.into(), conv_regs[0].into())),
+                        Ty::MapStrStr => self.pushl(LL::MapStrStrToIni(res_reg.into(), conv_regs[0].into())),
+                        _ => {
+                            return err!(
+                                "to_ini only support StrMap called with malformed types: {:?} => {:?}",
+                                &conv_tys[..],
+                                dst_ty
+                            );
+                        }
+                    }
+                }
+            }
+            PropGet => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::PropGet(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                }
+            }
+            ToXml => {
+                if res_reg != UNUSED {
+                    match conv_tys[0] {
+                        Ty::MapStrInt => self.pushl(LL::MapStrIntToXml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                        Ty::MapStrFloat => self.pushl(LL::MapStrFloatToXml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                        Ty::MapStrStr => self.pushl(LL::MapStrStrToXml(res_reg.into(), conv_regs[0].into(), conv_regs[1].into())),
+                        _ => {
+                            return err!(
+                                "to_xml only support StrMap called with malformed types: {:?} => {:?}",
+                                &conv_tys[..],
+                                dst_ty
+                            );
+                        }
+                    }
+                }
+            }
+            XmlUpdate => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::XmlUpdate(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+            }
+            HtmlTable => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::HtmlTable(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                }
+            }
+            FromMsgpack => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FromMsgpack(res_reg.into(), conv_regs[0].into()))
+            }
+            FromCbor => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FromCbor(res_reg.into(), conv_regs[0].into()))
+            }
+            FromProtobuf => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::FromProtobuf(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+            }
             ToJson => {
                 if res_reg != UNUSED {
                     match conv_tys[0] {
@@ -2435,24 +3071,25 @@ impl<'a, 'b> View<'a, 'b> {
                 }
             }
             Asort => {
-                if res_reg != UNUSED {
-                    match conv_tys[0] {
-                        Ty::MapIntInt => {
-                            self.pushl(LL::MapIntIntAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
-                        }
-                        Ty::MapIntFloat => {
-                            self.pushl(LL::MapIntFloatAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
-                        }
-                        Ty::MapIntStr => {
-                            self.pushl(LL::MapIntStrAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
-                        }
-                        _ => {
-                            return err!(
-                                "asort only support IntMap called with malformed types: {:?} => {:?}",
-                                &conv_tys[..],
-                                dst_ty
-                             );
-                        }
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                match conv_tys[0] {
+                    Ty::MapIntInt => {
+                        self.pushl(LL::MapIntIntAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+                    }
+                    Ty::MapIntFloat => {
+                        self.pushl(LL::MapIntFloatAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+                    }
+                    Ty::MapIntStr => {
+                        self.pushl(LL::MapIntStrAsort(res_reg.into(), conv_regs[0].into(), conv_regs[1].into(), conv_regs[2].into()))
+                    }
+                    _ => {
+                        return err!(
+                            "asort only support IntMap called with malformed types: {:?} => {:?}",
+                            &conv_tys[..],
+                            dst_ty
+                         );
                     }
                 }
             }
@@ -2560,11 +3197,60 @@ impl<'a, 'b> View<'a, 'b> {
                     };
                 }
             }
+            IsNan => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::IsNan(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            IsInf => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::IsInf(res_reg.into(), conv_regs[0].into()))
+                }
+            }
             IsFormat => {
                 if res_reg != UNUSED {
                     self.pushl(LL::IsFormat(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
                 }
             }
+            CidrContains => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::CidrContains(res_reg.into(), conv_regs[0].into(), conv_regs[1].into()))
+                }
+            }
+            // ip_in_cidr(ip, cidr) is cidr_contains with the arguments swapped.
+            IpInCidr => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::CidrContains(res_reg.into(), conv_regs[1].into(), conv_regs[0].into()))
+                }
+            }
+            Ip2Int => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Ip2Int(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Int2Ip => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Int2Ip(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            IpVersion => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::IpVersion(res_reg.into(), conv_regs[0].into()))
+                }
+            }
+            Geoip => {
+                // Emitted even if the result is unused, as a failed lookup sets ERRNO.
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::Geoip(res_reg.into(), conv_regs[0].into()))
+            }
+            GeoipDb => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::GeoipDb(res_reg.into(), conv_regs[0].into()))
+            }
             Uniq => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Uniq(
@@ -2650,6 +3336,69 @@ impl<'a, 'b> View<'a, 'b> {
                     }
                 }
             }
+            ArrayKeys | ArrayValues | ArrayInvert => {
+                if res_reg != UNUSED {
+                    let (map_ty, dst, map) = (conv_tys[0], res_reg, conv_regs[0]);
+                    self.pushl(match bf {
+                        ArrayKeys => LL::Keys { map_ty, dst, map },
+                        ArrayValues => LL::Values { map_ty, dst, map },
+                        _ => LL::Invert { map_ty, dst, map },
+                    })
+                }
+            }
+            ArraySortedKeys | ArraySortByValue => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::SortKeys {
+                        map_ty: conv_tys[0],
+                        dst: res_reg,
+                        map: conv_regs[0],
+                        order: conv_regs[1].into(),
+                        by_value: *bf == ArraySortByValue,
+                    })
+                }
+            }
+            ArrayMerge => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Merge {
+                        map_ty: conv_tys[0],
+                        dst: res_reg,
+                        left: conv_regs[0],
+                        right: conv_regs[1],
+                    })
+                }
+            }
+            ArrayCopy => {
+                if res_reg == UNUSED {
+                    res_reg = self.regs.stats.reg_of_ty(res_ty);
+                }
+                self.pushl(LL::CopyMap {
+                    map_ty: conv_tys[0],
+                    dst: res_reg,
+                    to: conv_regs[0],
+                    from: conv_regs[1],
+                })
+            }
+            ArrayEquals => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::EqualMaps {
+                        map_ty: conv_tys[0],
+                        dst: res_reg,
+                        left: conv_regs[0],
+                        right: conv_regs[1],
+                    })
+                }
+            }
+            ArraySlice => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::Slice {
+                        map_ty: conv_tys[0],
+                        dst: res_reg,
+                        map: conv_regs[0],
+                        from: conv_regs[1].into(),
+                        to: conv_regs[2].into(),
+                    })
+                }
+            }
             IntMapJoin => {
                 if res_reg != UNUSED {
                     match conv_tys[0] {
@@ -2692,6 +3441,47 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            DecAdd | DecSub | DecMul | DecDiv | DecCmp | DecFmt => {
+                if res_reg != UNUSED {
+                    let (dst, x, y) = (res_reg.into(), conv_regs[0].into(), conv_regs[1].into());
+                    self.pushl(match bf {
+                        DecAdd => LL::DecAdd(dst, x, y),
+                        DecSub => LL::DecSub(dst, x, y),
+                        DecMul => LL::DecMul(dst, x, y),
+                        DecDiv => LL::DecDiv(dst, x, y),
+                        DecCmp => LL::DecCmp(res_reg.into(), x, y),
+                        _ => LL::DecFmt(dst, x, conv_regs[1].into()),
+                    })
+                }
+            }
+            BigAdd | BigSub | BigMul | BigDiv | BigMod | BigPow | BigCmp => {
+                if res_reg != UNUSED {
+                    let (dst, x, y) = (res_reg.into(), conv_regs[0].into(), conv_regs[1].into());
+                    self.pushl(match bf {
+                        BigAdd => LL::BigAdd(dst, x, y),
+                        BigSub => LL::BigSub(dst, x, y),
+                        BigMul => LL::BigMul(dst, x, y),
+                        BigDiv => LL::BigDiv(dst, x, y),
+                        BigMod => LL::BigMod(dst, x, y),
+                        BigPow => LL::BigPow(dst, x, y),
+                        _ => LL::BigCmp(res_reg.into(), x, y),
+                    })
+                }
+            }
+            U64Add | U64Mul => {
+                if res_reg != UNUSED {
+                    let (dst, x, y) = (res_reg.into(), conv_regs[0].into(), conv_regs[1].into());
+                    self.pushl(match bf {
+                        U64Add => LL::U64Add(dst, x, y),
+                        _ => LL::U64Mul(dst, x, y),
+                    })
+                }
+            }
+            U64Str => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::U64Str(res_reg.into(), conv_regs[0].into()))
+                }
+            }
             Seq => {
                 if res_reg != UNUSED {
                     self.pushl(LL::Seq(
@@ -2721,6 +3511,16 @@ impl<'a, 'b> View<'a, 'b> {
                     ))
                 }
             }
+            Select => {
+                if res_reg != UNUSED {
+                    self.pushl(LL::SelectColumns(
+                        res_reg.into(),
+                        conv_regs[0].into(),
+                        conv_regs[1].into(),
+                        conv_regs[2].into(),
+                    ))
+                }
+            }
         };
         self.convert(dst_reg, dst_ty, res_reg, res_ty)
     }
@@ -2971,6 +3771,13 @@ impl<'a, 'b> View<'a, 'b> {
                 let mut arg_regs = Vec::with_capacity(args.len());
                 for a in args {
                     let (a_reg, a_ty) = self.get_reg(a)?;
+                    if a_ty == Ty::Float {
+                        // print formats numbers with OFMT rather than CONVFMT.
+                        let s_reg = self.regs.stats.reg_of_ty(Ty::Str);
+                        self.pushl(LL::FloatToOutStr(s_reg.into(), a_reg.into()));
+                        arg_regs.push(s_reg.into());
+                        continue;
+                    }
                     arg_regs.push(self.ensure_ty(a_reg, a_ty, Ty::Str)?.into());
                 }
                 let out_reg = if let Some((out, append)) = out {
@@ -3013,6 +3820,7 @@ impl<'a, 'b> View<'a, 'b> {
                 let (reg, ty) = self.get_reg(v)?;
                 self.pushr(HighLevel::DropIter(reg, ty))
             }
+            PrimStmt::Line(line) => self.pushr(HighLevel::Line(*line)),
         };
         Ok(())
     }
@@ -3024,8 +3832,9 @@ impl<'a, 'b> View<'a, 'b> {
 /// simple "startswith" calls. This sort of trick is still only used in a few places.
 fn extract_anchored_literal(text: &str) -> Option<Arc<[u8]>> {
     use regex_syntax::ast::{parse, AssertionKind, Ast};
-    // We should only call extract_anchored_literal
-    let re_ast = parse::Parser::new().parse(text).unwrap();
+    // We should only call extract_anchored_literal on patterns that compiled, but those can still
+    // use lookaround or backreferences, which regex_syntax does not parse.
+    let re_ast = parse::Parser::new().parse(text).ok()?;
     if let Ast::Concat(concat) = &re_ast {
         let asts = &concat.asts;
         if asts.len() >= 2 {
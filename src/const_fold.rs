@@ -0,0 +1,119 @@
+//! Constant folding over the typed bytecode.
+//!
+//! Concatenations of string literals and arithmetic on numeric literals are lowered to
+//! instructions that recompute their result every time they run, usually once per record. This
+//! pass evaluates them once at compile time, replacing each with a store of the constant it
+//! produces. Folded strings are also visible to the string constant analysis, so a pattern built
+//! out of literals, as in `gsub(/a/ "b", x)`, is compiled once rather than looked up in the regex
+//! cache on every call.
+//!
+//! Folding works one basic block at a time. A register holds a known constant from the instruction
+//! that stores it until the next instruction in the block that writes to it. Function calls may
+//! write to any global register, so they forget everything known so far.
+use std::collections::VecDeque;
+
+use hashbrown::HashMap;
+
+use crate::arena::Arena;
+use crate::bytecode::{Instr, Reg};
+use crate::common::Either;
+use crate::compile::{HighLevel, LL};
+use crate::dataflow::{boilerplate, Key};
+use crate::runtime::{self, Float, Int, Str};
+
+// Concatenations longer than this are left for runtime.
+const MAX_STR_LEN: usize = 4 << 10;
+
+#[derive(Clone)]
+enum Const {
+    Int(Int),
+    Float(Float),
+    Str(Vec<u8>),
+}
+
+/// Fold the constant instructions in a basic block.
+pub(crate) fn fold_block<'a>(insts: &mut VecDeque<Either<LL<'a>, HighLevel>>, arena: &'a Arena) {
+    let mut known = HashMap::<Key, Const>::new();
+    for inst in insts.iter_mut() {
+        let ll = match inst {
+            Either::Left(ll) => ll,
+            Either::Right(HighLevel::Line(_)) => continue,
+            Either::Right(_) => {
+                known.clear();
+                continue;
+            }
+        };
+        if let Some(new) = fold(ll, &known, arena) {
+            *ll = new;
+        }
+        let stored = stored(ll, &known);
+        boilerplate::visit_ll(ll, |dst, _| {
+            known.remove(&dst);
+        });
+        if let Some((dst, c)) = stored {
+            known.insert(dst, c);
+        }
+    }
+}
+
+// The constant that `inst` stores, and where.
+fn stored(inst: &LL, known: &HashMap<Key, Const>) -> Option<(Key, Const)> {
+    use Instr::*;
+    match inst {
+        StoreConstInt(dst, i) => Some((dst.into(), Const::Int(*i))),
+        StoreConstFloat(dst, f) => Some((dst.into(), Const::Float(*f))),
+        StoreConstStr(dst, s) => Some((
+            dst.into(),
+            Const::Str(s.clone_str().with_bytes(|bs| bs.to_vec())),
+        )),
+        Mov(ty, dst, src) => Some((
+            Key::Reg(*dst, *ty),
+            known.get(&Key::Reg(*src, *ty))?.clone(),
+        )),
+        _ => None,
+    }
+}
+
+// A store of the constant `inst` computes, if all of its operands are known.
+fn fold<'a>(inst: &LL<'a>, known: &HashMap<Key, Const>, arena: &'a Arena) -> Option<LL<'a>> {
+    use Instr::*;
+    let int = |r: &Reg<Int>| match known.get(&Key::from(r)) {
+        Some(Const::Int(i)) => Some(*i),
+        _ => None,
+    };
+    let float = |r: &Reg<Float>| match known.get(&Key::from(r)) {
+        Some(Const::Float(f)) => Some(*f),
+        _ => None,
+    };
+    let string = |r: &Reg<Str<'a>>| match known.get(&Key::from(r)) {
+        Some(Const::Str(s)) => Some(s),
+        _ => None,
+    };
+    let store_str = |dst: &Reg<Str<'a>>, bs: &[u8]| {
+        StoreConstStr(*dst, Str::from(arena.alloc_bytes(bs)).into())
+    };
+    Some(match inst {
+        Concat(dst, l, r) => {
+            let (l, r) = (string(l)?, string(r)?);
+            if l.len() + r.len() > MAX_STR_LEN {
+                return None;
+            }
+            store_str(dst, &[&l[..], &r[..]].concat())
+        }
+        IntToStr(dst, i) => {
+            let s: Str = runtime::convert(int(i)?);
+            s.with_bytes(|bs| store_str(dst, bs))
+        }
+        IntToFloat(dst, i) => StoreConstFloat(*dst, runtime::convert(int(i)?)),
+        // Arithmetic that overflows is left for runtime.
+        AddInt(dst, l, r) => StoreConstInt(*dst, int(l)?.checked_add(int(r)?)?),
+        MinusInt(dst, l, r) => StoreConstInt(*dst, int(l)?.checked_sub(int(r)?)?),
+        MulInt(dst, l, r) => StoreConstInt(*dst, int(l)?.checked_mul(int(r)?)?),
+        NegInt(dst, i) => StoreConstInt(*dst, int(i)?.checked_neg()?),
+        AddFloat(dst, l, r) => StoreConstFloat(*dst, float(l)? + float(r)?),
+        MinusFloat(dst, l, r) => StoreConstFloat(*dst, float(l)? - float(r)?),
+        MulFloat(dst, l, r) => StoreConstFloat(*dst, float(l)? * float(r)?),
+        NegFloat(dst, f) => StoreConstFloat(*dst, -float(f)?),
+        _ => return None,
+    })
+}
@@ -181,7 +181,7 @@ pub(crate) mod boilerplate {
                     f(Key::Reg(*reg, *ty), Some(Key::Reg(*pred_reg, *ty)));
                 }
             }
-            DropIter(..) => {}
+            DropIter(..) | Line(_) => {}
         }
     }
 
@@ -204,7 +204,9 @@ pub(crate) mod boilerplate {
             Pwd(dst) => f(dst.into(), None),
             UserHome(dst) => f(dst.into(), None),
             LocalIp(dst) => f(dst.into(), None),
-            Systime(dst) => f(dst.into(), None),
+            Systime(dst) | SystimeMs(dst) | SystimeUs(dst) => f(dst.into(), None),
+            Clock(dst) => f(dst.into(), None),
+            Sleep(dst, _) => f(dst.into(), None),
             Encode(dst, format, text) => {
                 f(dst.into(), Some(format.into()));
                 f(dst.into(), Some(text.into()));
@@ -213,10 +215,34 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(format.into()));
                 f(dst.into(), Some(text.into()));
             }
+            Iconv(dst, text, from, to) => {
+                f(dst.into(), Some(text.into()));
+                f(dst.into(), Some(from.into()));
+                f(dst.into(), Some(to.into()));
+            }
+            Compress(dst, format, text) | Decompress(dst, format, text) => {
+                f(dst.into(), Some(format.into()));
+                f(dst.into(), Some(text.into()));
+            }
+            Utf8Valid(dst, text) => f(dst.into(), Some(text.into())),
+            Utf8Fix(dst, text) | Normalize(dst, text, _) => f(dst.into(), Some(text.into())),
             Digest(dst, algorithm, text) => {
                 f(dst.into(), Some(algorithm.into()));
                 f(dst.into(), Some(text.into()));
             }
+            FileDigest(dst, algorithm, path) => {
+                f(dst.into(), Some(algorithm.into()));
+                f(dst.into(), Some(path.into()));
+            }
+            DigestInit(dst, algorithm, key) => {
+                f(dst.into(), Some(algorithm.into()));
+                f(dst.into(), Some(key.into()));
+            }
+            DigestUpdate(dst, handle, text) => {
+                f(dst.into(), Some(handle.into()));
+                f(dst.into(), Some(text.into()));
+            }
+            DigestFinal(dst, handle) => f(dst.into(), Some(handle.into())),
             Escape(dst, format, text) => {
                 f(dst.into(), Some(format.into()));
                 f(dst.into(), Some(text.into()));
@@ -231,10 +257,12 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(key.into()));
                 f(dst.into(), Some(payload.into()));
             }
-            Dejwt(dst, key, token) => {
+            Dejwt(dst, key, token, options) => {
                 f(dst.into(), Some(key.into()));
                 f(dst.into(), Some(token.into()));
+                f(dst.into(), Some(options.into()));
             }
+            Cert(dst, source) => f(dst.into(), Some(source.into())),
             Encrypt(dst, mode,plain_text, key) => {
                 f(dst.into(), Some(mode.into()));
                 f(dst.into(), Some(plain_text.into()));
@@ -245,9 +273,10 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(encrypted_text.into()));
                 f(dst.into(), Some(key.into()));
             }
-            Strftime(dst, format, timestamp) => {
+            Strftime(dst, format, timestamp, timezone) => {
                 f(dst.into(), Some(format.into()));
                 f(dst.into(), Some(timestamp.into()));
+                f(dst.into(), Some(timezone.into()));
             }
             Mktime(dst, date_time_text, timezone) => {
                 f(dst.into(), Some(date_time_text.into()));
@@ -258,7 +287,19 @@ pub(crate) mod boilerplate {
             }
             MkBool(dst, text) => f(dst.into(), Some(text.into())),
             Fend(dst, src) => f(dst.into(), Some(src.into())),
-            Url(dst, src) => f(dst.into(), Some(src.into())),
+            Url(dst, src) | UaParse(dst, src) | Logfmt(dst, src) => f(dst.into(), Some(src.into())),
+            MapStrIntToLogfmt(dst, arr, order) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(order.into()));
+            }
+            MapStrFloatToLogfmt(dst, arr, order) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(order.into()));
+            }
+            MapStrStrToLogfmt(dst, arr, order) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(order.into()));
+            }
             Pairs(dst, src, pair_sep, kv_sep) => {
                 f(dst.into(), Some(src.into()));
                 f(dst.into(), Some(pair_sep.into()));
@@ -274,6 +315,9 @@ pub(crate) mod boilerplate {
             Path(dst, src) => f(dst.into(), Some(src.into())),
             DataUrl(dst, src) => f(dst.into(), Some(src.into())),
             DateTime(dst, timestamp) => f(dst.into(), Some(timestamp.into())),
+            DateParse(dst, _, _) | DateAdd(dst, _, _, _) | DateDiff(dst, _, _, _) => {
+                f(dst.into(), None)
+            }
             Shlex(dst, text) => f(dst.into(), Some(text.into())),
             Tuple(dst, text) => f(dst.into(), Some(text.into())),
             Flags(dst, text) => f(dst.into(), Some(text.into())),
@@ -286,7 +330,58 @@ pub(crate) mod boilerplate {
             }
             Variant(dst, text) => f(dst.into(), Some(text.into())),
             Func(dst, text) => f(dst.into(), Some(text.into())),
-            FromJson(dst, src) => f(dst.into(), Some(src.into())),
+            FromJson(dst, src) | FromToml(dst, src) => f(dst.into(), Some(src.into())),
+            MapStrIntToToml(dst, arr, like) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(like.into()));
+            }
+            MapStrFloatToToml(dst, arr, like) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(like.into()));
+            }
+            MapStrStrToToml(dst, arr, like) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(like.into()));
+            }
+            TomlValue(dst, text, path) => {
+                f(dst.into(), Some(text.into()));
+                f(dst.into(), Some(path.into()));
+            }
+            FromIni(dst, src) => f(dst.into(), Some(src.into())),
+            MapStrIntToIni(dst, arr) => f(dst.into(), Some(arr.into())),
+            MapStrFloatToIni(dst, arr) => f(dst.into(), Some(arr.into())),
+            MapStrStrToIni(dst, arr) => f(dst.into(), Some(arr.into())),
+            PropGet(dst, text, key) => {
+                f(dst.into(), Some(text.into()));
+                f(dst.into(), Some(key.into()));
+            }
+            MapStrIntToXml(dst, arr, root) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(root.into()));
+            }
+            MapStrFloatToXml(dst, arr, root) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(root.into()));
+            }
+            MapStrStrToXml(dst, arr, root) => {
+                f(dst.into(), Some(arr.into()));
+                f(dst.into(), Some(root.into()));
+            }
+            XmlUpdate(dst, text, xpath, value) => {
+                f(dst.into(), Some(text.into()));
+                f(dst.into(), Some(xpath.into()));
+                f(dst.into(), Some(value.into()));
+            }
+            HtmlTable(dst, html, index) => {
+                f(dst.into(), Some(html.into()));
+                f(dst.into(), Some(index.into()));
+            }
+            FromMsgpack(dst, src) | FromCbor(dst, src) => f(dst.into(), Some(src.into())),
+            FromProtobuf(dst, src, descriptors, message) => {
+                f(dst.into(), Some(src.into()));
+                f(dst.into(), Some(descriptors.into()));
+                f(dst.into(), Some(message.into()));
+            }
             MapIntIntToJson(dst, arr) => f(dst.into(), Some(arr.into())),
             MapIntFloatToJson(dst, arr) => f(dst.into(), Some(arr.into())),
             MapIntStrToJson(dst, arr) => f(dst.into(), Some(arr.into())),
@@ -307,17 +402,20 @@ pub(crate) mod boilerplate {
             DumpInt(_num) => {},
             DumpFloat(_num) => {},
             DumpNull() => {}
-            MapIntIntAsort(dst, arr, target) => {
+            MapIntIntAsort(dst, arr, target, order) => {
                 f(dst.into(), Some(arr.into()));
                 f(dst.into(), Some(target.into()));
+                f(dst.into(), Some(order.into()));
             }
-            MapIntFloatAsort(dst, arr, target) => {
+            MapIntFloatAsort(dst, arr, target, order) => {
                 f(dst.into(), Some(arr.into()));
                 f(dst.into(), Some(target.into()));
+                f(dst.into(), Some(order.into()));
             },
-            MapIntStrAsort(dst, arr, target) => {
+            MapIntStrAsort(dst, arr, target, order) => {
                 f(dst.into(), Some(arr.into()));
                 f(dst.into(), Some(target.into()));
+                f(dst.into(), Some(order.into()));
             },
             MapIntIntJoin(dst, arr, sep) => {
                 f(dst.into(), Some(arr.into()));
@@ -379,6 +477,10 @@ pub(crate) mod boilerplate {
             Strtonum(dst, text) => {
                 f(dst.into(), Some(text.into()));
             }
+            ParseNumber(dst, text, locale) => {
+                f(dst.into(), Some(text.into()));
+                f(dst.into(), Some(locale.into()));
+            }
             StartsWith(dst, text, prefix) => {
                 f(dst.into(), Some(text.into()));
                 f(dst.into(), Some(prefix.into()));
@@ -394,6 +496,11 @@ pub(crate) mod boilerplate {
             FormatBytes(dst, size) => {
                 f(dst.into(), Some(size.into()));
             }
+            FormatNum(dst, x, pattern, locale) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(pattern.into()));
+                f(dst.into(), Some(locale.into()));
+            }
             ToBytes(dst, text) => {
                 f(dst.into(), Some(text.into()));
             }
@@ -430,9 +537,25 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(len.into()));
                 f(dst.into(), Some(pad.into()));
             }
-            StrCmp(dst, text1, text2) => {
+            StrCmp(dst, text1, text2) | NatCmp(dst, text1, text2) | Levenshtein(dst, text1, text2) => {
+                f(dst.into(), Some(text1.into()));
+                f(dst.into(), Some(text2.into()));
+            }
+            Similarity(dst, text1, text2) => {
+                f(dst.into(), Some(text1.into()));
+                f(dst.into(), Some(text2.into()));
+            }
+            Diff(dst, text1, text2) => {
+                f(dst.into(), Some(text1.into()));
+                f(dst.into(), Some(text2.into()));
+            }
+            Soundex(dst, text) | Metaphone(dst, text) => {
+                f(dst.into(), Some(text.into()));
+            }
+            FuzzyMatch(dst, text1, text2, threshold) => {
                 f(dst.into(), Some(text1.into()));
                 f(dst.into(), Some(text2.into()));
+                f(dst.into(), Some(threshold.into()));
             }
             Mask(dst, text) => {
                 f(dst.into(), Some(text.into()));
@@ -501,7 +624,19 @@ pub(crate) mod boilerplate {
             ReadAll(dst, path) => {
                 f(dst.into(), Some(path.into()));
             }
-            WriteAll( _path, _content) => {
+            ReadLines(dst, path) => {
+                f(dst.into(), Some(path.into()));
+            }
+            WriteAll(_path, _content) | AppendAll(_path, _content) => {
+            }
+            Glob(dst, pattern) => {
+                f(dst.into(), Some(pattern.into()));
+            }
+            Stat(dst, path) => {
+                f(dst.into(), Some(path.into()));
+            }
+            Exists(dst, path) | Mkdirp(dst, path) | RmFile(dst, path) => {
+                f(dst.into(), Some(path.into()));
             }
             LogDebug( _message) => {
             }
@@ -553,6 +688,36 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(second.into()));
                 f(dst.into(), Some(third.into()));
             }
+            DecAdd(dst, x, y) | DecSub(dst, x, y) | DecMul(dst, x, y) | DecDiv(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            DecCmp(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            DecFmt(dst, x, scale) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(scale.into()));
+            }
+            BigAdd(dst, x, y)
+            | BigSub(dst, x, y)
+            | BigMul(dst, x, y)
+            | BigDiv(dst, x, y)
+            | BigMod(dst, x, y)
+            | BigPow(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            BigCmp(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            U64Add(dst, x, y) | U64Mul(dst, x, y) => {
+                f(dst.into(), Some(x.into()));
+                f(dst.into(), Some(y.into()));
+            }
+            U64Str(dst, x) => f(dst.into(), Some(x.into())),
             Seq(dst, start, step, end) => {
                 f(dst.into(), Some(start.into()));
                 f(dst.into(), Some(step.into()));
@@ -584,12 +749,21 @@ pub(crate) mod boilerplate {
             IsNumFalse(_dst) => {
             }
             IsStrNum(dst, text)  => f(dst.into(), Some(text.into())),
+            IsNan(dst, x) | IsInf(dst, x) => f(dst.into(), Some(x.into())),
             IsFormat(dst, format, text) => {
                 f(dst.into(), Some(format.into()));
                 f(dst.into(), Some(text.into()));
             }
+            CidrContains(dst, net, addr) => {
+                f(dst.into(), Some(net.into()));
+                f(dst.into(), Some(addr.into()));
+            }
+            Ip2Int(dst, ip) | IpVersion(dst, ip) => f(dst.into(), Some(ip.into())),
+            Int2Ip(dst, n) => f(dst.into(), Some(n.into())),
+            Geoip(dst, ip) => f(dst.into(), Some(ip.into())),
+            GeoipDb(dst, path) => f(dst.into(), Some(path.into())),
             IntToFloat(dst, src) => f(dst.into(), Some(src.into())),
-            FloatToStr(dst, src) => f(dst.into(), Some(src.into())),
+            FloatToStr(dst, src) | FloatToOutStr(dst, src) => f(dst.into(), Some(src.into())),
             FloatToInt(dst, src) => f(dst.into(), Some(src.into())),
             StrToFloat(dst, src) => f(dst.into(), Some(src.into())),
             LenStr(dst, src) | StrToInt(dst, src) | HexStrToInt(dst, src) => f(dst.into(), Some(src.into())),
@@ -606,6 +780,7 @@ pub(crate) mod boilerplate {
             | MulInt(dst, x, y)
             | MinusInt(dst, x, y)
             | ModInt(dst, x, y)
+            | SafeInt(_, dst, x, y)
             | Int2(_, dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -616,6 +791,7 @@ pub(crate) mod boilerplate {
             | ModFloat(dst, x, y)
             | Div(dst, x, y)
             | Pow(dst, x, y)
+            | SafeFloat(_, dst, x, y)
             | Float2(_, dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -629,6 +805,11 @@ pub(crate) mod boilerplate {
                 f(Key::Rng, Some(new.into()));
             }
             ReseedRng(new) => f(Key::Rng, Some(new.into())),
+            RandInt(dst, lo, hi) => {
+                f(dst.into(), Some(Key::Rng));
+                f(dst.into(), Some(lo.into()));
+                f(dst.into(), Some(hi.into()));
+            }
             Concat(dst, x, y) => {
                 f(dst.into(), Some(x.into()));
                 f(dst.into(), Some(y.into()));
@@ -650,6 +831,10 @@ pub(crate) mod boilerplate {
                 f(dstin.into(), Some(x.into()));
                 f(dstin.into(), Some(y.into()));
             }
+            GSubConst(dst, _, y, dstin) | SubConst(dst, _, y, dstin) => {
+                f(dst.into(), Some(y.into()));
+                f(dstin.into(), Some(y.into()));
+            }
             GenSubDynamic(dst, pat, s, how, in_s) => {
                 f(dst.into(), Some(pat.into()));
                 f(dst.into(), Some(s.into()));
@@ -704,6 +889,11 @@ pub(crate) mod boilerplate {
                 f(dst.into(), Some(y.into()));
                 f(dst.into(), Some(z.into()));
             }
+            SelectColumns(dst, spec, sep, esc) => {
+                f(dst.into(), Some(spec.into()));
+                f(dst.into(), Some(sep.into()));
+                f(dst.into(), Some(esc.into()));
+            }
             ToUpperAscii(dst, src) | ToLowerAscii(dst, src) => {
                 f(dst.into(), Some(src.into()));
             }
@@ -719,6 +909,32 @@ pub(crate) mod boilerplate {
                 f(Key::MapVal(dst2_reg, dst2_ty), Some(src1.into()));
                 f(Key::MapVal(dst2_reg, dst2_ty), Some(src2.into()));
             }
+            SplitSepsInt(dst1, src1, dst2, src2, dst3)
+            | PatSplitInt(dst1, src1, dst2, src2, dst3) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                for dst in [dst2, dst3] {
+                    let (dst_reg, dst_ty) = dst.reflect();
+                    debug_assert!(dst_ty.is_array());
+                    f(Key::MapVal(dst_reg, dst_ty), Some(src1.into()));
+                    f(Key::MapVal(dst_reg, dst_ty), Some(src2.into()));
+                }
+            }
+            SplitSepsStr(dst1, src1, dst2, src2, dst3)
+            | PatSplitStr(dst1, src1, dst2, src2, dst3) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                for dst in [dst2, dst3] {
+                    f(dst.into(), Some(src1.into()));
+                    f(dst.into(), Some(src2.into()));
+                }
+            }
+            MatchArr(dst1, src1, src2, dst2) => {
+                f(dst1.into(), Some(src1.into()));
+                f(dst1.into(), Some(src2.into()));
+                f(dst2.into(), Some(src1.into()));
+                f(dst2.into(), Some(src2.into()));
+            }
             SplitStr(dst1, src1, dst2, src2) => {
                 f(dst1.into(), Some(src1.into()));
                 f(dst1.into(), Some(src2.into()));
@@ -732,6 +948,18 @@ pub(crate) mod boilerplate {
                 }
             }
             RunCmd(dst, _) => f(dst.into(), None),
+            ExecOutput(dst, _) | ExecOutputArgv(dst, _) | Spawn(dst, _) | SpawnArgv(dst, _) => {
+                f(dst.into(), None)
+            }
+            SetEnv(dst, _, _) | UnsetEnv(dst, _) | FFlush(dst, _) => f(dst.into(), None),
+            CallExt(dst, _, args) => {
+                if args.is_empty() {
+                    f(dst.into(), None)
+                }
+                for arg in args {
+                    f(dst.into(), Some(arg.into()))
+                }
+            }
             Lookup {
                 map_ty,
                 dst,
@@ -764,6 +992,51 @@ pub(crate) mod boilerplate {
             IterBegin { map_ty, dst, map } => {
                 f(Key::Reg(*dst, map_ty.key_iter().unwrap()), Some(Key::MapKey(*map, *map_ty)))
             }
+            Keys { map_ty, dst, map } => {
+                let dst_ty = Ty::map(Ty::Int, map_ty.key().unwrap()).unwrap();
+                f(Key::MapKey(*dst, dst_ty), None);
+                f(Key::MapVal(*dst, dst_ty), Some(Key::MapKey(*map, *map_ty)));
+            }
+            SortKeys { map_ty, dst, map, .. } => {
+                let dst_ty = Ty::map(Ty::Int, map_ty.key().unwrap()).unwrap();
+                f(Key::MapKey(*dst, dst_ty), None);
+                f(Key::MapVal(*dst, dst_ty), Some(Key::MapKey(*map, *map_ty)));
+            }
+            Values { map_ty, dst, map } => {
+                let dst_ty = Ty::map(Ty::Int, map_ty.val().unwrap()).unwrap();
+                f(Key::MapKey(*dst, dst_ty), None);
+                f(Key::MapVal(*dst, dst_ty), Some(Key::MapVal(*map, *map_ty)));
+            }
+            Invert { map_ty, dst, map } => {
+                let dst_ty = map_ty.inverted().unwrap();
+                f(Key::MapKey(*dst, dst_ty), Some(Key::MapVal(*map, *map_ty)));
+                f(Key::MapVal(*dst, dst_ty), Some(Key::MapKey(*map, *map_ty)));
+            }
+            Merge { map_ty, dst, left, right } => {
+                for src in [left, right] {
+                    f(Key::MapKey(*dst, *map_ty), Some(Key::MapKey(*src, *map_ty)));
+                    f(Key::MapVal(*dst, *map_ty), Some(Key::MapVal(*src, *map_ty)));
+                }
+            }
+            Slice { map_ty, dst, map, .. } => {
+                f(Key::MapKey(*dst, *map_ty), None);
+                f(Key::MapVal(*dst, *map_ty), Some(Key::MapVal(*map, *map_ty)));
+            }
+            Choice { map_ty, dst, map } => {
+                f(Key::Reg(*dst, map_ty.val().unwrap()), Some(Key::MapVal(*map, *map_ty)))
+            }
+            Sample { map_ty, dst, map, .. } => {
+                let dst_ty = Ty::map(Ty::Int, map_ty.val().unwrap()).unwrap();
+                f(Key::MapKey(*dst, dst_ty), None);
+                f(Key::MapVal(*dst, dst_ty), Some(Key::MapVal(*map, *map_ty)));
+            }
+            Shuffle { dst, .. } => f(Key::Reg(*dst, Ty::Int), None),
+            CopyMap { map_ty, dst, to, from } => {
+                f(Key::Reg(*dst, Ty::Int), None);
+                f(Key::MapKey(*to, *map_ty), Some(Key::MapKey(*from, *map_ty)));
+                f(Key::MapVal(*to, *map_ty), Some(Key::MapVal(*from, *map_ty)));
+            }
+            EqualMaps { dst, .. } => f(Key::Reg(*dst, Ty::Int), None),
             IterGetNext { iter_ty, dst, iter } => {
                 f(Key::Reg(*dst, iter_ty.iter().unwrap()), Some(Key::Reg(*iter, *iter_ty)));
             }
@@ -0,0 +1,433 @@
+//! An interactive debugger for the bytecode interpreter, run by `zawk debug`.
+//!
+//! Before each instruction, the interpreter hands the debugger the position it is about to
+//! execute. Positions that start a line of the program (see `trace::FuncLines`) are where the
+//! debugger can stop: on a breakpoint, after a `step` or `next`, or when a watched variable has
+//! changed. When it stops, it reads commands until one of them resumes the program.
+//!
+//! The debugger reads commands from `input` and writes to `out`, which are the standard input and
+//! standard error when run from the command line; the program's own output is left alone.
+use crate::trace::DebugInfo;
+
+use hashbrown::HashMap;
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+/// The state of the running program that the debugger can look at.
+pub(crate) trait Inspect {
+    /// The value of a global variable, builtin variable or field (`$0`, `$1`, ...), formatted for
+    /// display, if there is one by that name.
+    fn value(&mut self, name: &str) -> Option<String>;
+    /// The function and return address of each active call, outermost first.
+    fn callers(&self) -> Vec<(usize, usize)>;
+    /// Write out what the program has printed so far, so that it comes before the debugger's
+    /// output.
+    fn flush(&mut self);
+}
+
+/// Something that follows the program as it runs on the interpreter.
+pub(crate) trait Monitor {
+    /// Called before instruction `instr` of function `func` runs, with `depth` calls active.
+    /// Returns true if the program should stop.
+    fn at(&mut self, ctx: &mut dyn Inspect, func: usize, instr: usize, depth: usize) -> bool;
+}
+
+enum Mode {
+    Run,
+    // Stop at the next line.
+    Step,
+    // Stop at the next line, unless it is in a function called from the current one.
+    Next(usize),
+}
+
+enum Point {
+    Break(usize),
+    // The variable, and its value when it was last checked.
+    Watch(String, Option<String>),
+}
+
+const HELP: &str = "\
+break [FILE:]LINE   stop before LINE runs (b)
+watch VAR           stop after the value of VAR changes
+delete [N]          remove breakpoint or watchpoint N, or all of them (d)
+info                list breakpoints and watchpoints (i)
+step                run to the next line (s)
+next                run to the next line, stepping over function calls (n)
+continue            run to the next breakpoint (c)
+print VAR...        show variables, arrays, builtin variables and fields like $1 (p)
+backtrace           show the active function calls (bt)
+list                show the program around the current line (l)
+quit                stop the program (q)
+An empty line repeats the previous command.
+";
+
+pub(crate) struct Debugger {
+    info: Arc<DebugInfo>,
+    // The lines of the program text.
+    text: Vec<String>,
+    // The line that starts at each (function, instruction) position.
+    starts: HashMap<(usize, usize), usize>,
+    // Every line that starts somewhere.
+    lines: BTreeSet<usize>,
+    points: Vec<(usize, Point)>,
+    next_id: usize,
+    mode: Mode,
+    // The line most recently started, the call depth it started at, and where.
+    cur: Option<(usize, usize, (usize, usize))>,
+    last_command: String,
+    // Set once the input has run out, after which the program runs to completion.
+    detached: bool,
+    quit: bool,
+    input: Box<dyn BufRead>,
+    out: Box<dyn Write>,
+}
+
+impl Debugger {
+    pub(crate) fn new(
+        info: Arc<DebugInfo>,
+        text: &str,
+        input: Box<dyn BufRead>,
+        out: Box<dyn Write>,
+    ) -> Debugger {
+        let mut starts = HashMap::new();
+        let mut lines = BTreeSet::new();
+        for (func, f) in info.funcs.iter().enumerate() {
+            for (instr, line) in f.lines.iter() {
+                starts.insert((func, *instr), *line);
+                lines.insert(*line);
+            }
+        }
+        Debugger {
+            info,
+            text: text.lines().map(String::from).collect(),
+            starts,
+            lines,
+            points: Vec::new(),
+            next_id: 1,
+            // Stop before the first line, to set breakpoints.
+            mode: Mode::Step,
+            cur: None,
+            last_command: String::new(),
+            detached: false,
+            quit: false,
+            input,
+            out,
+        }
+    }
+
+    fn show(&mut self, func: usize, instr: usize, line: usize) {
+        let at = self.info.describe(func, instr).unwrap_or_default();
+        let text = self.text.get(line).map(String::as_str).unwrap_or("");
+        let _ = writeln!(self.out, "{}\t{}", at, text);
+    }
+
+    // Read commands until one resumes the program.
+    fn commands(&mut self, ctx: &mut dyn Inspect, func: usize, instr: usize, depth: usize) {
+        loop {
+            let _ = write!(self.out, "(zawk) ");
+            let _ = self.out.flush();
+            let mut command = String::new();
+            match self.input.read_line(&mut command) {
+                Ok(0) | Err(_) => {
+                    self.detached = true;
+                    return;
+                }
+                Ok(_) => {}
+            }
+            let mut command = command.trim().to_string();
+            if command.is_empty() {
+                command = self.last_command.clone();
+            } else {
+                self.last_command = command.clone();
+            }
+            let mut words = command.split_whitespace();
+            let (cmd, args): (_, Vec<_>) = match words.next() {
+                Some(cmd) => (cmd, words.collect()),
+                None => continue,
+            };
+            match cmd {
+                "s" | "step" => {
+                    self.mode = Mode::Step;
+                    return;
+                }
+                "n" | "next" => {
+                    self.mode = Mode::Next(depth);
+                    return;
+                }
+                "c" | "continue" => {
+                    self.mode = Mode::Run;
+                    return;
+                }
+                "q" | "quit" => {
+                    self.quit = true;
+                    return;
+                }
+                "b" | "break" => match args.as_slice() {
+                    [at] => self.add_break(at),
+                    _ => self.say("usage: break [FILE:]LINE"),
+                },
+                "watch" => match args.as_slice() {
+                    [name] => {
+                        let value = ctx.value(name);
+                        let id = self.new_id();
+                        let _ = writeln!(
+                            self.out,
+                            "Watchpoint {}: {} = {}",
+                            id,
+                            name,
+                            value.as_deref().unwrap_or("(none)")
+                        );
+                        self.points
+                            .push((id, Point::Watch(name.to_string(), value)));
+                    }
+                    _ => self.say("usage: watch VAR"),
+                },
+                "d" | "delete" => match args.as_slice() {
+                    [] => self.points.clear(),
+                    [id] => match id.parse::<usize>() {
+                        Ok(id) if self.points.iter().any(|(i, _)| *i == id) => {
+                            self.points.retain(|(i, _)| *i != id)
+                        }
+                        _ => self.say(&format!("no breakpoint or watchpoint {}", id)),
+                    },
+                    _ => self.say("usage: delete [N]"),
+                },
+                "i" | "info" => self.info_points(),
+                "p" | "print" => {
+                    for name in args {
+                        match ctx.value(name) {
+                            Some(value) => {
+                                let _ = writeln!(self.out, "{} = {}", name, value);
+                            }
+                            None => self.say(&format!("no variable {}", name)),
+                        }
+                    }
+                }
+                "bt" | "backtrace" | "where" => {
+                    let mut frames = vec![(func, instr)];
+                    frames.extend(
+                        ctx.callers()
+                            .into_iter()
+                            .rev()
+                            .map(|(f, ret)| (f, ret.saturating_sub(1))),
+                    );
+                    for (i, (f, instr)) in frames.into_iter().enumerate() {
+                        let at = self.info.describe(f, instr).unwrap_or_default();
+                        let _ = writeln!(self.out, "#{} {}", i, at);
+                    }
+                }
+                "l" | "list" => {
+                    if let Some((line, _, _)) = self.cur {
+                        let start = line.saturating_sub(5);
+                        let end = (line + 6).min(self.text.len());
+                        for l in start..end {
+                            let (_, n) = self.info.sources.locate(l);
+                            let mark = if l == line { "=>" } else { "  " };
+                            let _ = writeln!(self.out, "{}{:>4}\t{}", mark, n, self.text[l]);
+                        }
+                    }
+                }
+                "h" | "help" => {
+                    let _ = write!(self.out, "{}", HELP);
+                }
+                _ => self.say(&format!("unknown command {:?}; try help", cmd)),
+            }
+        }
+    }
+
+    fn say(&mut self, msg: &str) {
+        let _ = writeln!(self.out, "{}", msg);
+    }
+
+    fn new_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn add_break(&mut self, at: &str) {
+        let (file, line) = match at.rsplit_once(':') {
+            Some((file, line)) => (Some(file), line),
+            None => (None, at),
+        };
+        // Breakpoints on lines that start no statement apply to the next one that does.
+        let found = line
+            .parse::<usize>()
+            .ok()
+            .and_then(|line| self.info.sources.find(file, line))
+            .and_then(|line| self.lines.range(line..).next().copied());
+        match found {
+            Some(line) => {
+                let id = self.new_id();
+                let at = self.info.sources.describe(line);
+                let _ = writeln!(self.out, "Breakpoint {} at {}", id, at);
+                self.points.push((id, Point::Break(line)));
+            }
+            None => self.say(&format!("no code at {}", at)),
+        }
+    }
+
+    fn info_points(&mut self) {
+        if self.points.is_empty() {
+            return self.say("no breakpoints or watchpoints");
+        }
+        for (id, point) in self.points.iter() {
+            let _ = match point {
+                Point::Break(line) => writeln!(
+                    self.out,
+                    "{}\tbreakpoint at {}",
+                    id,
+                    self.info.sources.describe(*line)
+                ),
+                Point::Watch(name, _) => writeln!(self.out, "{}\twatchpoint on {}", id, name),
+            };
+        }
+    }
+}
+
+impl Monitor for Debugger {
+    fn at(
+        &mut self,
+        ctx: &mut dyn Inspect,
+        func: usize,
+        instr: usize,
+        depth: usize,
+    ) -> bool {
+        if self.quit {
+            return true;
+        }
+        if self.detached {
+            return false;
+        }
+        let line = match self.starts.get(&(func, instr)) {
+            Some(line) => *line,
+            None => return false,
+        };
+        // A line can hold several statements. Moving on to the next one is not a new line, but
+        // going back to the start of the line, as a loop does, is.
+        let same_line = matches!(self.cur, Some((l, d, (f, i))) if (l, d, f) == (line, depth, func) && i < instr);
+        self.cur = Some((line, depth, (func, instr)));
+        if same_line {
+            return false;
+        }
+        let mut reasons = Vec::new();
+        for (id, point) in self.points.iter_mut() {
+            match point {
+                Point::Break(at) if *at == line => reasons.push(format!("Breakpoint {}", id)),
+                Point::Break(_) => {}
+                Point::Watch(name, last) => {
+                    let value = ctx.value(name);
+                    if value != *last {
+                        reasons.push(format!(
+                            "Watchpoint {}: {}\n  old value: {}\n  new value: {}",
+                            id,
+                            name,
+                            last.as_deref().unwrap_or("(none)"),
+                            value.as_deref().unwrap_or("(none)")
+                        ));
+                        *last = value;
+                    }
+                }
+            }
+        }
+        let stop = !reasons.is_empty()
+            || match self.mode {
+                Mode::Run => false,
+                Mode::Step => true,
+                Mode::Next(d) => depth <= d,
+            };
+        if !stop {
+            return false;
+        }
+        ctx.flush();
+        for reason in reasons {
+            let _ = writeln!(self.out, "{}", reason);
+        }
+        self.show(func, instr, line);
+        self.commands(ctx, func, instr, depth);
+        self.quit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trace::{FuncLines, SourceMap};
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Vars(i64);
+
+    impl Inspect for Vars {
+        fn value(&mut self, name: &str) -> Option<String> {
+            (name == "n").then(|| self.0.to_string())
+        }
+        fn callers(&self) -> Vec<(usize, usize)> {
+            vec![(0, 3)]
+        }
+        fn flush(&mut self) {}
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let info = DebugInfo {
+            funcs: vec![
+                FuncLines {
+                    name: None,
+                    lines: vec![(0, 0), (2, 1), (5, 3)],
+                },
+                FuncLines {
+                    name: Some("f".into()),
+                    lines: vec![(0, 3)],
+                },
+            ],
+            sources: SourceMap::new("t.awk"),
+            globals: Vec::new(),
+        };
+        let text = "BEGIN {\n  n = 1\n\n  n++ }";
+        let commands = "b 3\nwatch n\nc\nc\nbt\np n x\nq\n";
+        let out = Shared::default();
+        let mut d = Debugger::new(
+            Arc::new(info),
+            text,
+            Box::new(Cursor::new(commands)),
+            Box::new(out.clone()),
+        );
+        let mut vars = Vars(0);
+        assert!(!d.at(&mut vars, 0, 0, 0));
+        assert!(!d.at(&mut vars, 0, 1, 0));
+        vars.0 = 1;
+        assert!(!d.at(&mut vars, 0, 2, 0));
+        assert!(d.at(&mut vars, 1, 0, 1));
+        assert_eq!(
+            String::from_utf8(out.0.borrow().clone()).unwrap(),
+            "t.awk:1\tBEGIN {
+(zawk) Breakpoint 1 at t.awk:4
+(zawk) Watchpoint 2: n = 0
+(zawk) Watchpoint 2: n
+  old value: 0
+  new value: 1
+t.awk:2\t  n = 1
+(zawk) Breakpoint 1
+t.awk:4 in function f()\t  n++ }
+(zawk) #0 t.awk:4 in function f()
+#1 t.awk:2
+(zawk) n = 1
+no variable x
+(zawk) "
+        );
+    }
+}
@@ -1,8 +1,8 @@
 //! Noisey `Display` impls.
-use crate::ast::{Binop, Unop};
+use crate::ast::{Binop, Expr, Pattern, Prog, Stmt, Unop};
 use crate::builtins::{Function, Variable};
 use crate::cfg::{BasicBlock, Ident, PrimExpr, PrimStmt, PrimVal, Transition};
-use crate::common::FileSpec;
+use crate::common::{Either, FileSpec};
 use crate::lexer;
 use std::fmt::{self, Display, Formatter};
 use std::string::String;
@@ -83,6 +83,7 @@ impl<'a> Display for PrimStmt<'a> {
                 Ok(())
             }
             IterDrop(v) => write!(f, "drop_iter {}", v),
+            Line(l) => write!(f, "line {}", l + 1),
         }
     }
 }
@@ -161,7 +162,8 @@ impl Display for Function {
             ReadLineStdinFused => write!(f, "stdin-fused"),
             NextFile => write!(f, "nextfile"),
             Setcol => write!(f, "$="),
-            Split => write!(f, "split"),
+            Split | SplitSeps => write!(f, "split"),
+            PatSplit => write!(f, "patsplit"),
             Length => write!(f, "length"),
             Uuid => write!(f, "uuid"),
             Ulid => write!(f, "ulid"),
@@ -175,6 +177,10 @@ impl Display for Function {
             Pwd => write!(f, "pwd"),
             UserHome => write!(f, "user_home"),
             Systime => write!(f, "systime"),
+            SystimeMs => write!(f, "systime_ms"),
+            SystimeUs => write!(f, "systime_us"),
+            Clock => write!(f, "clock"),
+            Sleep => write!(f, "sleep"),
             Strftime => write!(f, "strftime"),
             Mktime => write!(f, "mktime"),
             Duration => write!(f, "duration"),
@@ -185,7 +191,9 @@ impl Display for Function {
             Parse => write!(f, "parse"),
             RegexParse => write!(f, "rparse"),
             Strtonum => write!(f, "strtonum"),
+            ParseNumber => write!(f, "parse_number"),
             FormatBytes => write!(f, "format_bytes"),
+            FormatNum => write!(f, "format_num"),
             ToBytes => write!(f, "to_bytes"),
             StartsWith => write!(f, "starts_with"),
             EndsWith => write!(f, "ends_with"),
@@ -200,6 +208,13 @@ impl Display for Function {
             PadRight => write!(f, "pad_right"),
             PadBoth => write!(f, "pad_both"),
             StrCmp => write!(f, "strcmp"),
+            NatCmp => write!(f, "natcmp"),
+            Levenshtein => write!(f, "levenshtein"),
+            Similarity => write!(f, "similarity"),
+            Diff => write!(f, "diff"),
+            Soundex => write!(f, "soundex"),
+            Metaphone => write!(f, "metaphone"),
+            FuzzyMatch => write!(f, "fuzzy_match"),
             Mask => write!(f, "mask"),
             Repeat => write!(f, "repeat"),
             DefaultIfEmpty => write!(f, "default_if_empty"),
@@ -213,13 +228,27 @@ impl Display for Function {
             Escape => write!(f, "escape"),
             Encode => write!(f, "encode"),
             Decode => write!(f, "decode"),
+            Iconv => write!(f, "iconv"),
+            Compress => write!(f, "compress"),
+            Decompress => write!(f, "decompress"),
+            Utf8Valid => write!(f, "utf8valid"),
+            Utf8Fix => write!(f, "utf8fix"),
+            Normalize(form) => write!(f, "{}", form.func_name()),
             Digest => write!(f, "digest"),
+            FileDigest => write!(f, "file_digest"),
+            DigestInit => write!(f, "digest_init"),
+            DigestUpdate => write!(f, "digest_update"),
+            DigestFinal => write!(f, "digest_final"),
             Hmac => write!(f, "hmac"),
             Jwt => write!(f, "jwt"),
             Dejwt => write!(f, "dejwt"),
+            Cert => write!(f, "cert"),
             Encrypt => write!(f, "encrypt"),
             Decrypt => write!(f, "decrypt"),
             Url => write!(f, "url"),
+            UaParse => write!(f, "ua_parse"),
+            Logfmt => write!(f, "logfmt"),
+            ToLogfmt => write!(f, "to_logfmt"),
             Pairs => write!(f, "pairs"),
             Record => write!(f, "record"),
             Message => write!(f, "message"),
@@ -227,6 +256,9 @@ impl Display for Function {
             Path => write!(f, "path"),
             DataUrl => write!(f, "data_url"),
             DateTime => write!(f, "datetime"),
+            DateParse => write!(f, "dateparse"),
+            DateAdd => write!(f, "dateadd"),
+            DateDiff => write!(f, "datediff"),
             Shlex => write!(f, "shlex"),
             Tuple => write!(f, "tuple"),
             Flags => write!(f, "flags"),
@@ -253,21 +285,69 @@ impl Display for Function {
             MysqlExecute => write!(f, "mysql_execute"),
             Publish => write!(f, "publish"),
             FromJson => write!(f, "from_json"),
+            FromToml => write!(f, "from_toml"),
+            ToToml => write!(f, "to_toml"),
+            TomlValue => write!(f, "toml_value"),
+            FromIni => write!(f, "from_ini"),
+            ToIni => write!(f, "to_ini"),
+            PropGet => write!(f, "prop_get"),
+            ToXml => write!(f, "to_xml"),
+            XmlUpdate => write!(f, "xml_update"),
+            HtmlTable => write!(f, "html_table"),
+            FromMsgpack => write!(f, "from_msgpack"),
+            FromCbor => write!(f, "from_cbor"),
+            FromProtobuf => write!(f, "from_protobuf"),
             ToJson => write!(f, "to_json"),
             VarDump => write!(f, "var_dump"),
             ReadAll => write!(f, "read_all"),
+            ReadLines => write!(f, "read_lines"),
             WriteAll => write!(f, "write_all"),
+            AppendAll => write!(f, "append_all"),
+            Glob => write!(f, "glob"),
+            Stat => write!(f, "stat"),
+            Exists => write!(f, "exists"),
+            Mkdirp => write!(f, "mkdirp"),
+            RmFile => write!(f, "rmfile"),
             FromCsv => write!(f, "from_csv"),
             ToCsv => write!(f, "to_csv"),
             Min => write!(f, "min"),
             Max => write!(f, "max"),
+            DecAdd => write!(f, "dec_add"),
+            DecSub => write!(f, "dec_sub"),
+            DecMul => write!(f, "dec_mul"),
+            DecDiv => write!(f, "dec_div"),
+            DecCmp => write!(f, "dec_cmp"),
+            DecFmt => write!(f, "dec_fmt"),
+            BigAdd => write!(f, "big_add"),
+            BigSub => write!(f, "big_sub"),
+            BigMul => write!(f, "big_mul"),
+            BigDiv => write!(f, "big_div"),
+            BigMod => write!(f, "big_mod"),
+            BigPow => write!(f, "big_pow"),
+            BigCmp => write!(f, "big_cmp"),
+            U64Add => write!(f, "u64_add"),
+            U64Mul => write!(f, "u64_mul"),
+            U64Str => write!(f, "u64_str"),
             ArrayMax => write!(f, "_max"),
             ArrayMin => write!(f, "_min"),
             ArraySum => write!(f, "_sum"),
             ArrayMean => write!(f, "_mean"),
             Seq => write!(f, "seq"),
             IntMapJoin => write!(f, "_join"),
+            ArrayKeys => write!(f, "_keys"),
+            ArrayValues => write!(f, "_values"),
+            ArrayInvert => write!(f, "_invert"),
+            ArrayMerge => write!(f, "_merge"),
+            ArraySlice => write!(f, "_slice"),
+            ArrayCopy => write!(f, "_copy"),
+            ArrayEquals => write!(f, "_equals"),
+            ArraySortedKeys => write!(f, "_sorted_keys"),
+            ArraySortByValue => write!(f, "_sort_by_value"),
             Asort => write!(f, "asort"),
+            RandInt => write!(f, "randint"),
+            Choice => write!(f, "choice"),
+            Shuffle => write!(f, "shuffle"),
+            Sample => write!(f, "sample"),
             BloomFilterInsert => write!(f, "bf_insert"),
             BloomFilterContains => write!(f, "bf_contains"),
             BloomFilterContainsWithInsert => write!(f, "bf_icontains"),
@@ -276,13 +356,23 @@ impl Display for Function {
             IsArray => write!(f, "isarray"),
             IsInt => write!(f, "isint"),
             IsNum => write!(f, "isnum"),
+            IsNan => write!(f, "is_nan"),
+            IsInf => write!(f, "is_inf"),
             IsFormat => write!(f, "is"),
+            IpInCidr => write!(f, "ip_in_cidr"),
+            CidrContains => write!(f, "cidr_contains"),
+            Ip2Int => write!(f, "ip2int"),
+            Int2Ip => write!(f, "int2ip"),
+            IpVersion => write!(f, "ip_version"),
+            Geoip => write!(f, "geoip"),
+            GeoipDb => write!(f, "geoip_db"),
             Uniq => write!(f, "uniq"),
             Contains => write!(f, "contains"),
             Delete => write!(f, "delete"),
             Clear => write!(f, "clear"),
             Close => write!(f, "close"),
-            Match => write!(f, "match"),
+            FFlush => write!(f, "fflush"),
+            Match | MatchArr => write!(f, "match"),
             SubstrIndex => write!(f, "index"),
             SubstrLastIndex => write!(f, "last_index"),
             LastPart => write!(f, "last_part"),
@@ -294,6 +384,7 @@ impl Display for Function {
             JoinCSV => write!(f, "join_csv"),
             JoinTSV => write!(f, "join_tsv"),
             JoinCols => write!(f, "join_fields"),
+            Select => write!(f, "select"),
             Substr => write!(f, "substr"),
             CharAt => write!(f, "char_at"),
             ToInt => write!(f, "int"),
@@ -302,12 +393,17 @@ impl Display for Function {
             Srand => write!(f, "srand"),
             ReseedRng => write!(f, "srand_reseed"),
             System => write!(f, "system"),
+            ExecOutput => write!(f, "exec_output"),
+            Spawn => write!(f, "spawn"),
+            SetEnv => write!(f, "setenv"),
+            UnsetEnv => write!(f, "unsetenv"),
             UpdateUsedFields => write!(f, "update_used_fields"),
             SetFI => write!(f, "set-FI"),
             ToLower => write!(f, "tolower"),
             ToUpper => write!(f, "toupper"),
             IncMap => write!(f, "inc_map"),
             Exit => write!(f, "exit"),
+            Ext(ix) => write!(f, "{}", crate::runtime::ext::get(*ix).name),
         }
     }
 }
@@ -335,6 +431,11 @@ impl Display for Variable {
                 FI => "FI",
                 ENVIRON => "ENVIRON",
                 PROCINFO => "PROCINFO",
+                ERRNO => "ERRNO",
+                IGNORECASE => "IGNORECASE",
+                OCSVQUOTE => "OCSVQUOTE",
+                CONVFMT => "CONVFMT",
+                OFMT => "OFMT",
             }
         )
     }
@@ -474,10 +575,237 @@ impl<'a> Display for lexer::Tok<'a> {
             StrLit(s) => return write!(fmt, "{:?}", s),
             PatLit(s) => return write!(fmt, "/{}/", s),
             CallStart(s) => return write!(fmt, "{}(", s),
+            IndirectCallStart(s) => return write!(fmt, "@{}(", s),
+            NamedColumn(s) => return write!(fmt, "@{:?}", s),
             FunDec(s) => return write!(fmt, "function {}", s),
 
-            ILit(s) | HexLit(s) | FLit(s) => return write!(fmt, "{}", s),
+            ILit(s) | HexLit(s) | BinLit(s) | OctLit(s) | FLit(s) => return write!(fmt, "{}", s),
         };
         write!(fmt, "{}", rep)
     }
 }
+
+// The syntax tree, one node per line with its children indented below it, for `--emit ast`.
+impl<'a, 'b, I: Display> Display for Prog<'a, 'b, I> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let mut t = Tree { f, depth: 0 };
+        for (name, e) in &self.prelude_vardecs {
+            t.node(format_args!("Assign -v {}", name), |t| t.expr(e))?;
+        }
+        for (name, stmts) in [("BEGIN", &self.begin), ("PREPARE", &self.prepare)] {
+            for s in stmts.iter() {
+                t.node(format_args!("{}", name), |t| t.stmt(s))?;
+            }
+        }
+        for dec in self.decs.iter() {
+            let args = dec.args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+            t.node(
+                format_args!("Function {}({})", dec.name, args.join(", ")),
+                |t| t.stmt(dec.body),
+            )?;
+        }
+        for (pat, body) in self.pats.iter() {
+            t.node(format_args!("Rule"), |t| {
+                match pat {
+                    Pattern::Null => t.leaf(format_args!("Always"))?,
+                    Pattern::Bool(e) => t.expr(e)?,
+                    Pattern::Comma(l, r) => t.node(format_args!("Range"), |t| {
+                        t.expr(l)?;
+                        t.expr(r)
+                    })?,
+                }
+                match body {
+                    Some(s) => t.stmt(s),
+                    None => t.leaf(format_args!("Print $0")),
+                }
+            })?;
+        }
+        for s in self.end.iter() {
+            t.node(format_args!("END"), |t| t.stmt(s))?;
+        }
+        Ok(())
+    }
+}
+
+struct Tree<'f, 'g> {
+    f: &'f mut Formatter<'g>,
+    depth: usize,
+}
+
+impl<'f, 'g> Tree<'f, 'g> {
+    fn leaf(&mut self, label: fmt::Arguments) -> fmt::Result {
+        writeln!(self.f, "{:width$}{}", "", label, width = self.depth * 2)
+    }
+
+    fn node(
+        &mut self,
+        label: fmt::Arguments,
+        children: impl FnOnce(&mut Self) -> fmt::Result,
+    ) -> fmt::Result {
+        self.leaf(label)?;
+        self.depth += 1;
+        let res = children(self);
+        self.depth -= 1;
+        res
+    }
+
+    fn opt_expr<I: Display>(&mut self, e: Option<&Expr<I>>) -> fmt::Result {
+        match e {
+            Some(e) => self.expr(e),
+            None => self.leaf(format_args!("None")),
+        }
+    }
+
+    fn opt_stmt<I: Display>(&mut self, s: Option<&Stmt<I>>) -> fmt::Result {
+        match s {
+            Some(s) => self.stmt(s),
+            None => self.leaf(format_args!("None")),
+        }
+    }
+
+    fn exprs<I: Display>(&mut self, es: &[&Expr<I>]) -> fmt::Result {
+        es.iter().try_for_each(|e| self.expr(e))
+    }
+
+    fn redirect<I: Display>(&mut self, out: &Option<(&Expr<I>, FileSpec)>) -> fmt::Result {
+        match out {
+            Some((e, spec)) => {
+                let op = match spec {
+                    FileSpec::Trunc => ">",
+                    FileSpec::Append => ">>",
+                    FileSpec::Cmd => "|",
+                };
+                self.node(format_args!("Redirect {}", op), |t| t.expr(e))
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn expr<I: Display>(&mut self, e: &Expr<I>) -> fmt::Result {
+        use Expr::*;
+        match e {
+            ILit(n) => self.leaf(format_args!("ILit {}", n)),
+            FLit(n) => self.leaf(format_args!("FLit {:?}", n)),
+            StrLit(s) => self.leaf(format_args!("StrLit {:?}", String::from_utf8_lossy(s))),
+            PatLit(s) => self.leaf(format_args!("PatLit /{}/", String::from_utf8_lossy(s))),
+            Unop(op, x) => self.node(format_args!("Unop {}", op), |t| t.expr(x)),
+            Binop(op, l, r) => self.node(format_args!("Binop {}", op), |t| {
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            Call(Either::Left(name), args) => {
+                self.node(format_args!("Call {}", name), |t| t.exprs(args))
+            }
+            Call(Either::Right(func), args) => {
+                self.node(format_args!("Call {}", func), |t| t.exprs(args))
+            }
+            IndirectCall(func, args) => self.node(format_args!("IndirectCall"), |t| {
+                t.expr(func)?;
+                t.exprs(args)
+            }),
+            Var(name) => self.leaf(format_args!("Var {}", name)),
+            Index(m, k) => self.node(format_args!("Index"), |t| {
+                t.expr(m)?;
+                t.expr(k)
+            }),
+            Assign(l, r) => self.node(format_args!("Assign"), |t| {
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            AssignOp(l, op, r) => self.node(format_args!("AssignOp {}=", op), |t| {
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            And(l, r) => self.node(format_args!("And"), |t| {
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            Or(l, r) => self.node(format_args!("Or"), |t| {
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            ITE(c, l, r) => self.node(format_args!("ITE"), |t| {
+                t.expr(c)?;
+                t.expr(l)?;
+                t.expr(r)
+            }),
+            Inc { is_inc, is_post, x } => {
+                let op = if *is_inc { "++" } else { "--" };
+                let label = if *is_post { "Post" } else { "Pre" };
+                self.node(format_args!("{}{}", label, op), |t| t.expr(x))
+            }
+            Getline { into, from, is_file } => {
+                let kind = match (from, is_file) {
+                    (None, _) => "",
+                    (Some(_), true) => " <",
+                    (Some(_), false) => " |",
+                };
+                self.node(format_args!("Getline{}", kind), |t| {
+                    t.opt_expr(*into)?;
+                    match from {
+                        Some(from) => t.expr(from),
+                        None => Ok(()),
+                    }
+                })
+            }
+            ReadStdin => self.leaf(format_args!("ReadStdin")),
+            Cond(n) => self.leaf(format_args!("Cond {}", n)),
+        }
+    }
+
+    fn stmt<I: Display>(&mut self, s: &Stmt<I>) -> fmt::Result {
+        use Stmt::*;
+        match s {
+            StartCond(n) => self.leaf(format_args!("StartCond {}", n)),
+            EndCond(n) => self.leaf(format_args!("EndCond {}", n)),
+            LastCond(n) => self.leaf(format_args!("LastCond {}", n)),
+            Expr(e) => self.expr(e),
+            Block(stmts) => self.node(format_args!("Block"), |t| {
+                stmts.iter().try_for_each(|s| t.stmt(s))
+            }),
+            Print(args, out) => self.node(format_args!("Print"), |t| {
+                t.exprs(args)?;
+                t.redirect(out)
+            }),
+            Printf(fmt, args, out) => self.node(format_args!("Printf"), |t| {
+                t.expr(fmt)?;
+                t.exprs(args)?;
+                t.redirect(out)
+            }),
+            If(c, then, els) => self.node(format_args!("If"), |t| {
+                t.expr(c)?;
+                t.stmt(then)?;
+                t.opt_stmt(*els)
+            }),
+            For(init, cond, update, body) => self.node(format_args!("For"), |t| {
+                t.opt_stmt(*init)?;
+                t.opt_expr(*cond)?;
+                t.opt_stmt(*update)?;
+                t.stmt(body)
+            }),
+            DoWhile(c, body) => self.node(format_args!("DoWhile"), |t| {
+                t.stmt(body)?;
+                t.expr(c)
+            }),
+            While(toplevel, c, body) => {
+                let label = if *toplevel { "While (main loop)" } else { "While" };
+                self.node(format_args!("{}", label), |t| {
+                    t.expr(c)?;
+                    t.stmt(body)
+                })
+            }
+            ForEach(var, m, body) => self.node(format_args!("ForEach {}", var), |t| {
+                t.expr(m)?;
+                t.stmt(body)
+            }),
+            Break => self.leaf(format_args!("Break")),
+            Continue => self.leaf(format_args!("Continue")),
+            Next => self.leaf(format_args!("Next")),
+            NextFile => self.leaf(format_args!("NextFile")),
+            Return(e) => match e {
+                Some(e) => self.node(format_args!("Return"), |t| t.expr(e)),
+                None => self.leaf(format_args!("Return")),
+            },
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Running AWK programs from Rust.
+//!
+//! ```
+//! let prog = zawk::Program::compile("{ total += $2 } END { print total }")?;
+//! assert_eq!(prog.run_on_str("a 1\nb 2\n")?, "3\n");
+//! # Ok::<(), zawk::embed::Error>(())
+//! ```
+//!
+//! A [`Program`] is parsed and type-checked once, when it is compiled, and can then be run any
+//! number of times. Programs run on the bytecode interpreter: the JIT is faster on large inputs,
+//! but it exits the process on fatal errors, which a library must not do.
+//!
+//! Output files, pipes and `system` work as they do in the `zawk` binary; only standard input and
+//! standard output are replaced by the reader and writer passed to [`Program::run_on_reader`].
+use crate::arena::Arena;
+use crate::bytecode::Instr;
+use crate::cfg::{self, Escaper};
+use crate::common::ExecutionStrategy;
+use crate::compile;
+use crate::precompile::{self, Setup};
+use crate::runtime::{
+    splitter::{batch::ByteReader, regex::RegexSplitter},
+    writers, ChainedReader, CHUNK_SIZE,
+};
+use crate::{ast, lexer, parsing};
+
+use std::io;
+use std::iter::once;
+use std::sync::{Arc, Mutex};
+
+pub use crate::common::CompileError as Error;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// How to compile and run a program; the fields mirror the command-line flags of the same
+/// purpose.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    /// The field separator (`-F`). Unlike `-F`, no escape sequences are interpreted.
+    pub field_sep: Option<String>,
+    /// Variables assigned before the program starts (`-v name=value`), as strings.
+    pub vars: Vec<(String, String)>,
+    /// Read the first line of input as a header naming the columns (`-H`).
+    pub parse_header: bool,
+    /// Allow `system` and command pipes whose commands are not string literals (`-A`).
+    pub arbitrary_shell: bool,
+    /// Report division by zero and integer overflow in `ERRNO` (`--safe-math`).
+    pub safe_math: bool,
+    /// `ARGV[1]`, `ARGV[2]`, ... when the program runs. `ARGV[0]` is `zawk`.
+    pub args: Vec<String>,
+}
+
+/// A compiled AWK program.
+pub struct Program {
+    instrs: Vec<Vec<Instr<'static>>>,
+    setup: Setup,
+    argv: Vec<String>,
+}
+
+impl Program {
+    /// Compile `src` with the default [`Options`].
+    pub fn compile(src: &str) -> Result<Program> {
+        Program::compile_with(src, &Options::default())
+    }
+
+    pub fn compile_with(src: &str, opts: &Options) -> Result<Program> {
+        let a = Arena::default();
+        let prog = parse(src, &a, opts)?;
+        let mut ctx = cfg::ProgramContext::from_prog(&a, prog, Escaper::Identity)?;
+        ctx.allow_arbitrary_commands = opts.arbitrary_shell;
+        ctx.safe_math = opts.safe_math;
+        let compiled = compile::precompile(&mut ctx)?;
+        // The instructions borrow from the arena the program was parsed into. Going through the
+        // precompiled format gives them their own copies of all strings.
+        let precompile::Program { instrs, setup } =
+            precompile::Program::from_bytes(&compiled.to_bytes()?)?;
+        let argv = once("zawk".to_string())
+            .chain(opts.args.iter().cloned())
+            .collect();
+        Ok(Program {
+            instrs,
+            setup,
+            argv,
+        })
+    }
+
+    /// Run the program with `input` as standard input and `output` as standard output, returning
+    /// the exit status: 0 unless the program calls `exit` with another value.
+    pub fn run_on_reader(
+        &self,
+        input: impl io::Read + Send + 'static,
+        output: impl io::Write + Send + 'static,
+    ) -> Result<i32> {
+        let input: Box<dyn io::Read + Send> = Box::new(input);
+        let ff = writers::factory_with_stdout(output);
+        let name = String::from("-");
+        let strategy = ExecutionStrategy::Serial;
+        let (instrs, setup, argv) = (self.instrs.clone(), &self.setup, &self.argv[..]);
+        // Mirrors the choice of splitter in the `zawk` binary.
+        match setup.sep_assign() {
+            cfg::SepAssign::Potential {
+                field_sep,
+                record_sep,
+            } if field_sep.map_or(1, <[u8]>::len) == 1
+                && record_sep.map_or(1, <[u8]>::len) == 1 =>
+            {
+                let record_sep = record_sep.map_or(b'\n', |s| s[0]);
+                let signal = Default::default();
+                match field_sep {
+                    None | Some(b" ") => {
+                        let reader = ByteReader::new_whitespace(
+                            once((input, name)),
+                            record_sep,
+                            CHUNK_SIZE,
+                            /*check_utf8=*/ false,
+                            strategy,
+                            signal,
+                        );
+                        precompile::interp(instrs, setup, argv, reader, ff).run()
+                    }
+                    Some(field_sep) => {
+                        let reader = ByteReader::new(
+                            once((input, name)),
+                            field_sep[0],
+                            record_sep,
+                            CHUNK_SIZE,
+                            /*check_utf8=*/ false,
+                            strategy,
+                            signal,
+                        );
+                        precompile::interp(instrs, setup, argv, reader, ff).run()
+                    }
+                }
+            }
+            _ => {
+                let splitter =
+                    RegexSplitter::new(input, CHUNK_SIZE, name, /*check_utf8=*/ false);
+                let reader = ChainedReader::new(once(splitter));
+                precompile::interp(instrs, setup, argv, reader, ff).run()
+            }
+        }
+    }
+
+    /// Run the program on `input`, returning what it prints to standard output.
+    pub fn run_on_str(&self, input: &str) -> Result<String> {
+        #[derive(Clone, Default)]
+        struct Buf(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Buf {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let out = Buf::default();
+        self.run_on_reader(io::Cursor::new(input.to_string()), out.clone())?;
+        let bytes = std::mem::take(&mut *out.0.lock().unwrap());
+        match String::from_utf8(bytes) {
+            Ok(s) => Ok(s),
+            Err(e) => err!("program produced invalid unicode: {}", e),
+        }
+    }
+}
+
+fn parse<'a>(src: &str, a: &'a Arena, opts: &Options) -> Result<&'a ast::Prog<'a, 'a, &'a str>> {
+    let mut buf = Vec::new();
+    let mut prog = ast::Prog::from_stage(a, ExecutionStrategy::Serial.stage());
+    let parser = parsing::syntax::ProgParser::new();
+    if let Err(e) = parser.parse(
+        a,
+        &mut buf,
+        &mut prog,
+        lexer::Tokenizer::new(a.alloc_str(src)),
+    ) {
+        return err!("failed to parse program: {}", e);
+    }
+    for (name, val) in &opts.vars {
+        if !lexer::is_ident(name) {
+            return err!("invalid variable name: {:?}", name);
+        }
+        let val = a.alloc(ast::Expr::StrLit(a.alloc_bytes(val.as_bytes())));
+        prog.prelude_vardecs.push((a.alloc_str(name), val));
+    }
+    prog.field_sep = opts.field_sep.as_ref().map(|s| a.alloc_bytes(s.as_bytes()));
+    prog.parse_header = opts.parse_header;
+    Ok(a.alloc(prog))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_programs() {
+        let prog = Program::compile("{ total += $2 } END { print total, NR }").unwrap();
+        assert_eq!(prog.run_on_str("a 1\nb 2\n").unwrap(), "3 2\n");
+        assert_eq!(prog.run_on_str("c 5\n").unwrap(), "5 1\n");
+
+        let opts = Options {
+            field_sep: Some(",".into()),
+            vars: vec![("greeting".into(), "hi".into())],
+            args: vec!["x".into()],
+            ..Default::default()
+        };
+        let prog =
+            Program::compile_with("{ print greeting, $2, ARGV[1] } END { exit 3 }", &opts).unwrap();
+        let out = Arc::new(Mutex::new(Vec::new()));
+        struct Shared(Arc<Mutex<Vec<u8>>>);
+        impl io::Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let rc = prog
+            .run_on_reader(&b"1,2\n"[..], Shared(out.clone()))
+            .unwrap();
+        assert_eq!(rc, 3);
+        assert_eq!(&out.lock().unwrap()[..], b"hi 2 x\n");
+
+        assert!(Program::compile("BEGIN { print ( }").is_err());
+        let opts = Options {
+            vars: vec![("not a name".into(), "".into())],
+            ..Default::default()
+        };
+        assert!(Program::compile_with("BEGIN {}", &opts).is_err());
+
+        let opts = Options {
+            safe_math: true,
+            ..Default::default()
+        };
+        let prog = Program::compile_with(
+            "{ print $1 / $2, $1 % $2, ERRNO; ERRNO = \"\" } END { print 4611686018427387904 * 4, ERRNO }",
+            &opts,
+        )
+        .unwrap();
+        assert_eq!(
+            prog.run_on_str("6 3\n1 0\n").unwrap(),
+            "2 0 \nNaN NaN division by zero\n0 integer overflow\n"
+        );
+    }
+}
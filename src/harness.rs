@@ -9,9 +9,11 @@ use crate::{
     ast,
     cfg::{self, Escaper},
     codegen::{self, intrinsics::IntoRuntime},
+    column_stats,
     common::{ExecutionStrategy, Result},
     compile, lexer,
     parsing::syntax,
+    precompile,
     pushdown::FieldSet,
     runtime::{
         self,
@@ -62,8 +64,8 @@ macro_rules! with_reader {
                 let field_sep = field_sep.unwrap_or(b" ");
                 let record_sep = record_sep.unwrap_or(b"\n");
                 if field_sep.len() == 1 && record_sep.len() == 1 {
-                    if field_sep == b" " && record_sep == b"\n" {
-                        let $id = simulate_stdin_whitespace($inp);
+                    if field_sep == b" " {
+                        let $id = simulate_stdin_whitespace(record_sep[0], $inp);
                         $body
                     } else {
                         let $id = simulate_stdin_singlechar(field_sep[0], record_sep[0], $inp);
@@ -94,9 +96,13 @@ fn simulate_stdin_singlechar(
     )
 }
 
-fn simulate_stdin_whitespace(inp: impl Into<String>) -> impl IntoRuntime + runtime::LineReader {
+fn simulate_stdin_whitespace(
+    record_sep: u8,
+    inp: impl Into<String>,
+) -> impl IntoRuntime + runtime::LineReader {
     ByteReader::new_whitespace(
         split_stdin(inp.into()),
+        record_sep,
         runtime::CHUNK_SIZE,
         /*check_utf8=*/ true,
         ExecutionStrategy::Serial,
@@ -234,6 +240,7 @@ cfg_if! {
     }
 }
 
+#[cfg(feature = "jit")]
 pub(crate) fn run_cranelift(
     prog: &str,
     stdin: impl Into<String>,
@@ -311,6 +318,53 @@ pub(crate) fn used_fields(prog: &str) -> Result<FieldSet> {
     compile::used_fields(&mut ctx)
 }
 
+pub(crate) fn record_limit(prog: &str) -> Result<Option<u64>> {
+    let a = Arena::default();
+    let esc = Escaper::Identity;
+    let stmt = parse_program(prog, &a, esc, ExecutionStrategy::Serial)?;
+    let ctx = cfg::ProgramContext::from_prog(&a, stmt, esc)?;
+    Ok(ctx.record_limit)
+}
+
+/// Run `prog` with the column_stats fast path, or return None if it does not apply.
+pub(crate) fn run_column_stats(prog: &str, stdin: impl Into<String>) -> Result<Option<String>> {
+    let a = Arena::default();
+    let esc = Escaper::Identity;
+    let stmt = parse_program(prog, &a, esc, ExecutionStrategy::Serial)?;
+    let ctx = cfg::ProgramContext::from_prog(&a, stmt, esc)?;
+    let plan = match &ctx.column_stats {
+        Some(plan) => plan,
+        None => return Ok(None),
+    };
+    let fake_fs = FakeFs::default();
+    with_reader!(ctx.analyze_sep_assignments(), stdin, |reader| {
+        column_stats::run(plan, &[], reader, fake_fs.clone())?;
+    });
+    match String::from_utf8(fake_fs.stdout.read_data()) {
+        Ok(s) => Ok(Some(s)),
+        Err(e) => err!("program produced invalid unicode: {}", e),
+    }
+}
+
+/// Run `prog` after a round trip through the precompiled format written by `zawk compile`.
+pub(crate) fn run_precompiled(prog: &str, stdin: impl Into<String>) -> Result<String> {
+    let a = Arena::default();
+    let esc = Escaper::Identity;
+    let stmt = parse_program(prog, &a, esc, ExecutionStrategy::Serial)?;
+    let mut ctx = cfg::ProgramContext::from_prog(&a, stmt, esc)?;
+    let bytes = compile::precompile(&mut ctx)?.to_bytes()?;
+    let precompile::Program { instrs, setup } = precompile::Program::from_bytes(&bytes)?;
+    let fake_fs = FakeFs::default();
+    let argv = ["zawk".to_string()];
+    with_reader!(setup.sep_assign(), stdin, |reader| {
+        precompile::interp(instrs, &setup, &argv, reader, fake_fs.clone()).run()?;
+    });
+    match String::from_utf8(fake_fs.stdout.read_data()) {
+        Ok(s) => Ok(s),
+        Err(e) => err!("program produced invalid unicode: {}", e),
+    }
+}
+
 pub(crate) fn parse_program<'a>(
     prog: &str,
     a: &'a Arena,
@@ -553,6 +607,7 @@ mod tests {
                     }
                 }
 
+                #[cfg(feature = "jit")]
                 #[test]
                 fn cranelift() {
                     match run_cranelift($e, $inp, $esc, $csv, ExecutionStrategy::Serial) {
@@ -608,6 +663,106 @@ mod tests {
         assert_eq!(FieldSet::all(), used_fields(p2).unwrap());
     }
 
+    #[test]
+    fn used_fields_nf() {
+        let p1 = r#"/x/ { print }"#;
+        assert_eq!(FieldSet::singleton(0), used_fields(p1).unwrap());
+        assert!(!used_fields(p1).unwrap().splits_records());
+
+        let p2 = r#"{ print $2, NF }"#;
+        let mut s2 = FieldSet::singleton(2);
+        s2.union(&FieldSet::nf());
+        assert_eq!(s2, used_fields(p2).unwrap());
+
+        let p3 = r#"{ print join_fields(1, 2) }"#;
+        assert!(used_fields(p3).unwrap().counts_fields());
+    }
+
+    #[test]
+    fn record_limits() {
+        assert_eq!(record_limit("NR > 10 { exit } { print }").unwrap(), Some(10));
+        assert_eq!(record_limit("NR>=10{exit}").unwrap(), Some(9));
+        assert_eq!(record_limit("3 < NR { exit }\n{ print $1 }").unwrap(), Some(3));
+        assert_eq!(record_limit("NR == 1 { exit }").unwrap(), Some(0));
+        // exit has to come before anything else runs, and must not skip an END block.
+        assert_eq!(record_limit("{ print } NR > 10 { exit }").unwrap(), None);
+        assert_eq!(record_limit("NR > 10 { exit } END { print NR }").unwrap(), None);
+        assert_eq!(record_limit("NR > 10 { print; exit }").unwrap(), None);
+        assert_eq!(record_limit("NR > 10 { exit 1 }").unwrap(), None);
+        assert_eq!(record_limit("FNR > 10 { exit }").unwrap(), None);
+        // NR has to count records.
+        assert_eq!(record_limit("NR > 10 { exit } /x/ { NR = 0 }").unwrap(), None);
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn column_stats_matches_compiled() {
+        let progs = [
+            "NR == 1 || $2 > m { m = $2 + 0 } END { print m }",
+            "NR == 1 || $2 < m { m = $2 + 0 } { n++ } END { print m, n }",
+            "$2 + 0 >= m { m = $2 * 1 } END { print \"max:\", m, NR }",
+            "{ if (m < $2) { m = 0 + $2 }; n += 1; c = c + 1 } END { print m; print n, c }",
+            "END { print NR }",
+            "{ n++ } END { print n }",
+        ];
+        let inputs = [
+            "a 9\nb 10\nc -3\n",
+            "x 1.5e3\ny -0\nz abc\n\nw 2 3\n",
+            "",
+            "  lead 0x10\n tab\t-7.25\n",
+        ];
+        for prog in progs.iter() {
+            for input in inputs.iter() {
+                let fast = run_column_stats(prog, *input)
+                    .unwrap()
+                    .unwrap_or_else(|| panic!("no plan for {}", prog));
+                let expected =
+                    run_cranelift(prog, *input, Escaper::Identity, None, ExecutionStrategy::Serial)
+                        .unwrap();
+                assert_eq!(fast, expected, "prog={} input={:?}", prog, input);
+            }
+        }
+        for prog in [
+            // String comparisons, or values that are not the compared column.
+            "$2 > m { m = $2 } END { print m }",
+            "$2 > m { m = $3 + 0 } END { print m }",
+            "$2 > m { m = $2 + 1 } END { print m }",
+            "NR == 2 || $2 > m { m = $2 + 0 } END { print m }",
+            // More than one column, statement, or output.
+            "$2 > m { m = $2 + 0 } $3 < l { l = $3 + 0 } END { print m, l }",
+            "$2 > m { m = $2 + 0 } { m++ } END { print m }",
+            "$2 > m { m = $2 + 0 } END { print m > \"out\" }",
+            "$2 > m { m = $2 + 0 } END { print m + 1 }",
+            "$2 > m { m = $2 + 0 } END { print x }",
+            // Anything else that could observe or change the program's state.
+            "BEGIN { OFS = \"-\" } $2 > m { m = $2 + 0 } END { print m, NR }",
+            "$2 > NF { NF = $2 + 0 } END { print NF }",
+            "{ n++ }",
+            "$2 > m { m = $2 + 0; print } END { print m }",
+        ] {
+            assert_eq!(run_column_stats(prog, "").unwrap(), None, "prog={}", prog);
+        }
+    }
+
+    #[cfg(feature = "jit")]
+    #[test]
+    fn precompiled_matches_compiled() {
+        let progs = [
+            r#"BEGIN { FS = "," } $2 ~ /^[0-9]+$/ { s[$1] += $2 } END { for (k in s) n++; print n, s["a"] }"#,
+            r#"{ x = x toupper($1) ";" } END { print x, 1e999, -1e999, 0.1 + 0.2, length(x) }"#,
+            r#"function f(a, b) { return a * b } NR > 1 { print f($2, NR), substr($0, 2) }"#,
+        ];
+        let input = "a,1\nb,x\na,41\n";
+        for prog in progs.iter() {
+            let expected =
+                run_cranelift(prog, input, Escaper::Identity, None, ExecutionStrategy::Serial)
+                    .unwrap();
+            assert_eq!(run_precompiled(prog, input).unwrap(), expected, "prog={}", prog);
+        }
+        let argv = run_precompiled("BEGIN { print ARGC, ARGV[0] }", "").unwrap();
+        assert_eq!(argv, "1 zawk\n");
+    }
+
     #[test]
     fn used_fields_with_joins() {
         let p1 = r#"{ print $0; x=1; if (z) { x=3 } else { x=4 }; print join_fields(x, 8); }"#;
@@ -618,6 +773,8 @@ mod tests {
         s1.set(6);
         s1.set(7);
         s1.set(8);
+        // join_fields stops at NF.
+        s1.union(&FieldSet::nf());
         assert_eq!(s1, used_fields(p1).unwrap());
     }
 
@@ -689,6 +846,32 @@ mod tests {
         "1 2\n24\n"
     );
 
+    test_program_parallel!(
+        parallel_merge_strategies,
+        ShardPerFile,
+        r#"BEGIN { PARALLEL_MERGE["lo"]="min"; PARALLEL_MERGE["hi"]="max"; PARALLEL_MERGE["last"]="union"; lo=100; }
+        { if ($1+0 < lo) lo = $1+0; if ($2+0 > hi) hi = $2+0; last[$1 % 3] = $2; tot += $1; }
+        END {
+            print lo, hi, tot, length(last);
+        }"#,
+        r#"5,6<<<FILE BREAK>>>3,4<<<FILE BREAK>>>1,2<<<FILE BREAK>>>7,8
+9,10"#,
+        "1 10 25 3\n"
+    );
+
+    test_program_parallel!(
+        parallel_bloom_filter,
+        ShardPerFile,
+        r#"BEGIN { bf_insert("begin"); }
+        { bf_insert($1); }
+        END {
+            print bf_contains("begin"), bf_contains("3"), bf_contains("9"), bf_contains("4");
+        }"#,
+        r#"1,2<<<FILE BREAK>>>3,4<<<FILE BREAK>>>5,6<<<FILE BREAK>>>7,8
+9,10"#,
+        "1 1 1 0\n"
+    );
+
     test_program!(
         map_default_args,
         r#"
@@ -728,6 +911,22 @@ mod tests {
         "1 1 1 1\n"
     );
 
+    test_program!(
+        rand_helpers,
+        r#"BEGIN {
+        for (run = 1; run <= 2; run++) {
+            srand(7); r = ""; for (i = 0; i < 20; i++) r = r randint(1, 6);
+            split("a b c d e", d); shuffle(d); s = sample(d, 3);
+            draw[run] = r " " choice(d) " " d[1] d[2] d[3] d[4] d[5] " " s[1] s[2] s[3];
+        }
+        print (draw[1] == draw[2]), split(draw[1], parts, " ");
+        split("a b c d e", a); n = shuffle(a); for (i = 1; i <= n; i++) seen[a[i]]++; print n, length(seen);
+        s = sample(a, 9); print length(s), (s[1] != s[2]), randint(4, 4), (randint(9, 8) >= 8);
+        m["k"] = "v"; split("", e); print choice(m), "[" choice(e) "]", length(sample(e, 2));
+        }"#,
+        "1 4\n5 5\n5 1 4 1\nv [] 0\n"
+    );
+
     test_program!(
         basic_csv_render,
         r#"BEGIN { print "hi", "there"; print "comma,\"in field","and a\ttab"; }"#,
@@ -737,6 +936,32 @@ r#"hi,there
         @out_fmt Escaper::CSV
     );
 
+    test_program!(
+        csv_render_ternary,
+        r#"BEGIN { for (i = 0; i < 3; i++) print i, (i ? i * 10 : "none"); }"#,
+        "0,none\n1,10\n2,20\n",
+        @out_fmt Escaper::CSV
+    );
+
+    test_program!(
+        csv_quoting_policy,
+        r#"BEGIN { print "a", "b,c", "say \"hi\"";
+        OCSVQUOTE = "all"; print "a", 1;
+        OCSVQUOTE = "quote=' escape=backslash"; print "it's", "x,y", "a\\b"; }"#,
+        "a,\"b,c\",\"say \"\"hi\"\"\"\n\"a\",\"1\"\n'it\\'s','x,y',a\\\\b\n",
+        @out_fmt Escaper::CSV
+    );
+
+    test_program!(
+        number_formats,
+        r#"BEGIN { x = 1/4; print x, x "", 2.0 "", 1e3 / 8;
+        CONVFMT = "%.2f"; print x, x "", 7.0 "";
+        OFMT = "%.1f"; print x, x "", 7.0, -0.5;
+        printf "%s %d\n", 2/3, 2/3;
+        CONVFMT = ""; OFMT = ""; print x, x ""; }"#,
+        "0.25 0.25 2 125\n0.25 0.25 7\n0.2 0.25 7 -0.5\n0.67 0\n0.25 0.25\n"
+    );
+
     test_program!(
         basic_tsv_render,
         r#"BEGIN { print "hi", "there"; print "comma,\"in field","and a\ttab"; }"#,
@@ -795,6 +1020,28 @@ is the second file
 it has one more line"#
     );
 
+    test_program!(
+        next_in_function,
+        r#"function skip(n) { if (n % 2) { next }; return n }
+        function nested(n) { return "[" skip(n) "]" }
+        { print nested(NR) }"#,
+        "[2]\n[4]\n",
+        @input "1\n2\n3\n4\n5"
+    );
+    test_program!(
+        next_file_in_function,
+        r#"function skip() { nextfile }
+        FNR == 2 { skip() }
+        { print "["FILENAME,NR,FNR"]", $0;}"#,
+          r#"[fake_stdin_0 1 1] this is
+[fake_stdin_1 3 1] And this
+"#,
+          @input r#"this is
+the first file<<<FILE BREAK>>>And this
+is the second file
+it has one more line"#
+    );
+
     test_program_csv!(
         csv_no_escaping,
         r#"function max(x, y) { return x<y?y:x; }
@@ -818,6 +1065,152 @@ it has one more line"#
           "1,2\t,3\"4\n",
           @input r#"help,"1,2\t,3""4",5"#
     );
+    test_program_csv!(
+        csv_whole_records,
+        r#"/a/ { print } END { print NR }"#,
+        "a,\"x\ny\",b\n\"q,a\",z\n3\n",
+        @input "a,\"x\ny\",b\nc,d\n\"q,a\",z"
+    );
+    test_program_csv!(
+        csv_count_fields,
+        r#"{ print $1, NF }"#,
+        "a 3\nc 2\nq,a 2\n",
+        @input "a,\"x\ny\",b\nc,d\n\"q,a\",z"
+    );
+    test_program_tsv!(
+        tsv_whole_records,
+        r#"/b/ { print } { n += NF } END { print n }"#,
+        "a\tb\n\tb\tc\n5\n",
+        @input "a\tb\n\tb\tc\nd"
+    );
+    test_program!(
+        whole_records,
+        r#"/b/ { print } END { print NR }"#,
+        "a  b\n b c \n4\n",
+        @input "a  b\n\n b c \nd"
+    );
+    test_program!(
+        whole_records_fs,
+        r#"BEGIN { FS = "," } /b/ { print } { n += NF; print $1 } END { print n }"#,
+        "a,,b\na\n\nb,c\nb\nd\n6\n",
+        @input "a,,b\n\nb,c\nd"
+    );
+    test_program_csv!(
+        csv_assign_fields,
+        r#"BEGIN { OFS = ";" } { $2 = $2 * 2; $5 = "x"; print $0, NF; $0 = "raw,\"p,q\""; print $0, $1, $2, NF; }"#,
+        "a;2;c;;x;5\nraw,\"p,q\";raw;p,q;2\n",
+        @input "a,1,c"
+    );
+    test_program_csv!(
+        csv_parse_number,
+        r#"{ total += parse_number($2, "de_DE"); print parse_number($3), is_nan(parse_number($1)) }
+        END { print total }"#,
+        "-1200 1\n0.5 1\n1234.5\n",
+        @input "ab,\"1.234,50 €\",\"(1,200.00)\"\nc,\"0,0\",$.50"
+    );
+    test_program!(
+        format_num_patterns,
+        r##"BEGIN { print format_num(1234567.891, "#,##0.00"), format_num(-1234.5, "$#,##0.00");
+        print format_num(1234.5, "#,##0.00 €", "de_DE"), format_num(0.256, "0.0%"), format_num(7, "000") }"##,
+        "1,234,567.89 -$1,234.50\n1.234,50 € 25.6% 007\n"
+    );
+    test_program!(
+        regex_lookaround_backrefs,
+        r#"BEGIN { s = "price $42"; p = "(?<=\\$)[0-9]+"; print match(s, p), RSTART, RLENGTH;
+        x = "bookkeeper"; n = gsub(/(\w)\1/, "<&>", x); print n, x, gensub(/(\w)\1/, "[\\1]", "g", "aabbc");
+        y = "a1b2"; sub(/[a-z](?=2)/, "X", y); k = split("a--b-c", parts, /-(?!-)/);
+        print y, k, parts[1], parts[2], ("foo" ~ /f(?!x)/), ("aa" ~ "(.)\\1") }"#,
+        "8 8 2\n3 b<oo><kk><ee>per [a][b]c\na1X2 3 a- b 1 1\n"
+    );
+    test_program!(
+        constant_folding,
+        r#"BEGIN { x = "ab" "cd" "-" 12; y = 3 * 4 + 1 - (-2); z = 1.5 * 2 - 0.25;
+        s = "banana"; n = gsub(/a/ "n", "_", s); t = "cdcd"; m = sub("c" "d", "[&]", t);
+        print x, y, z, n, s, m, t, ("xaby" ~ ("a" "b"));
+        IGNORECASE = 1; u = "ABab"; print gsub(/a/ "b", "x", u), u }"#,
+        "abcd-12 15 2.75 2 b__a 1 [cd]cd 1\n2 xx\n"
+    );
+    test_program!(
+        match_array,
+        r#"BEGIN { n = match("foo=bar; x=42", /([a-z]+)=([0-9]+)/, m);
+        print n, RSTART, RLENGTH, m[0], m[1], m[2], m[1, "start"], m[2, "length"], length(m);
+        match("abc", "(x)?b", m); print m[0], m[0, "start"], (1 in m), length(m);
+        print match("zzz", "q", m), length(m) }"#,
+        "10 10 4 x=42 x 42 10 2 9\nb 2 0 3\n0 0\n"
+    );
+    test_program!(
+        split_seps_and_patsplit,
+        r#"BEGIN { n = split("  a b  ", a, " ", s); print n, "[" s[0] "]", a[1], "[" s[1] "]", a[2], "[" s[2] "]";
+        n = split("a,b,", a, ",", s); print n, a[1], a[2], "[" a[3] "]", s[1], s[2], (3 in s);
+        n = patsplit("x1b22c", a, /[0-9]+/, s); print n, a[1], a[2], s[0], s[1], s[2];
+        print patsplit("ab12", b, "[0-9]"), b[1], b[2], length(b) }"#,
+        "2 [  ] a [ ] b [  ]\n3 a b [] , , 0\n2 1 22 x b c\n2 1 2 2\n"
+    );
+    test_program!(
+        ignorecase_variable,
+        r#"BEGIN { s = "Hello World"; p = "WORLD"; print (s ~ p), index(s, "world"), ("abc" == "ABC");
+        IGNORECASE = 1; print (s ~ p), (s ~ /^hello/), match(s, p), index(s, "world"), ("abc" == "ABC"), ("a" < "B");
+        t = s; gsub(/o/, "0", t); n = split("1X2x3", parts, "x"); print t, n;
+        IGNORECASE = 0; print (s ~ /^hello/), ("abc" == "ABC") }"#,
+        "0 0 0\n1 1 7 7 1 1\nHell0 W0rld 3\n0 0\n"
+    );
+    test_program!(
+        utf8_cleaning,
+        r#"BEGIN { bad = iconv("café", "utf-8", "latin1"); f = utf8fix(bad);
+        print utf8valid(bad), f, utf8valid(f), utf8fix("café");
+        d = nfd("café"); print length(d), length(nfc(d)), (nfc(d) == "café"), nfkc("ﬁ①"); }"#,
+        "0 caf\u{fffd} 1 café\n5 4 1 fi1\n"
+    );
+    test_program!(
+        iconv_round_trip,
+        r#"BEGIN { gbk = iconv("中文 café", "utf-8", "gbk"); print (gbk != "中文 café"), iconv(gbk, "gbk", "utf-8");
+        x = iconv("a", "no-such-encoding", "utf-8"); print "[" x "]", ERRNO }"#,
+        "1 中文 café\n[] iconv: unknown encoding 'no-such-encoding'\n"
+    );
+
+    test_program!(
+        encode_formats,
+        r#"BEGIN { print encode("hex-upper", "zawk"), decode("hex-upper", "7a61776b"), decode("base58", encode("base58", "id"))
+        x = encode("base36", "a"); print "[" x "]", (ERRNO ~ /^encode: unknown format 'base36', expected one of base32,/)
+        print "[" decode("base64", "!") "]", ERRNO }"#,
+        "7A61776B zawk id\n[] 1\n[] decode: invalid base64 text\n"
+    );
+
+    test_program!(
+        compression,
+        r#"{ for (f = 1; f <= 5; f++) { z = compress($f, $0 $0 $0); printf "%s:%d ", $f, (decompress($f, z) == $0 $0 $0) }
+        print ""
+        x = decompress("zstd", "plain"); print "[" x "]", (ERRNO ~ /^decompress: invalid zstd data: /)
+        x = compress("rar", "a"); print "[" x "]", ERRNO }"#,
+        "gzip:1 zlib:1 deflate:1 zstd:1 lz4:1 \n[] 1\n[] compress: unknown format 'rar', expected one of gzip, zlib, deflate, zstd, lz4\n",
+        @input "gzip zlib deflate zstd lz4\n"
+    );
+    test_program!(
+        fuzzy_compare,
+        r#"BEGIN { print levenshtein("kitten", "sitting"), similarity("abcd", "abxy"), similarity("same", "same"), "[" diff("x", "x") "]"
+        printf "%s", diff("a\nb\nc\n", "a\nB\nc\n") }"#,
+        "3 0.5 1 []\n--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n"
+    );
+    test_program!(
+        phonetic_match,
+        r#"{ print soundex($1), metaphone($1), fuzzy_match($1, $2), fuzzy_match($1, $2, 0.99) }"#,
+        "R163 RBRT 1 0\nS530 SM0 1 0\nK365 K0RN 0 0\n",
+        @input "Robert Roberta\nSmith smyth\nKatherine Smith\n"
+    );
+    test_program!(
+        unsigned_helpers,
+        r#"BEGIN { h = "14695981039346656037"; h = u64_mul(h, "1099511628211");
+        print h, u64_add("18446744073709551615", 2), u64_str(-1);
+        printf "%u %x\n", "18446744073709551615", h }"#,
+        "12638153115695167455 1 18446744073709551615\n18446744073709551615 af63bd4c8601b7df\n"
+    );
+    test_program!(
+        big_arithmetic,
+        r#"BEGIN { print big_add("9007199254740993", 0), big_mul("123456789012345678901", "10");
+        print big_sub("0.3", "0.1"), big_div(1, 8), big_mod(-7, 3), big_pow(2, 70);
+        print big_cmp("18446744073709551617", "18446744073709551616"), big_cmp("abc", "9") }"#,
+        "9007199254740993 1234567890123456789010\n0.2 0.125 -1 1180591620717411303424\n1 1\n"
+    );
     test_program_tsv!(
         tsv_escaping,
         r#"{ print $1,$2,$3; }"#,
@@ -846,6 +1239,15 @@ it has one more line"#
         @input "0\n1\n2\n3"
     );
 
+    test_program!(
+        getline_from_string,
+        r#"BEGIN { s = "a b\nc d\n\ne"; src = "@str:" s;
+        while ((getline line < src) > 0) { n++; last = line; }
+        r = (getline x < src); print n, last, r;
+        close(src); getline < src; print $2, NF; }"#,
+        "4 e 0\nb 2\n"
+    );
+
     test_program!(single_stmt, r#"BEGIN {print "hello"}"#, "hello\n");
     test_program!(
         to_lower_upper,
@@ -912,6 +1314,21 @@ print w,z;
         @input "1 2 3 4 5 6"
     );
 
+    test_program!(
+        select_columns,
+        r#"BEGIN{OFS="!";} { print select("3,1,5-"); print select("2-1", "~"); print select("4-9"); }"#,
+        "3!1!5!6\n2~1\n4!5!6\n",
+        @input "1 2 3 4 5 6"
+    );
+
+    test_program!(
+        select_csv,
+        r#"{ printf "%s\n", select("2,1"); }"#,
+        "\"x, y\",a\n",
+        @input "a,\"x, y\"",
+        @types [], @out_fmt Escaper::CSV, @csv Some(InputFormat::CSV)
+    );
+
     test_program!(
         map_ops_simple,
         r#"BEGIN {
@@ -1021,6 +1438,25 @@ depth 0: k=1 v=99
         "2\n2\n5\n2.718281828459045\n"
     );
 
+    test_program!(
+        decimal_rounding,
+        r#"BEGIN {
+        print round(2.675, 2), round(-1.5), round(1234.5, -2);
+        print floor_to(7.3, 0.5), ceil_to(7.3, 0.1), ceil_to(0.71, 0.05);
+        }"#,
+        "2.68 -2 1200\n7 7.3 0.75\n"
+    );
+
+    test_program!(
+        decimal_arithmetic,
+        r#"BEGIN {
+        split("0.10 0.20 0.30", xs);
+        for (i = 1; i <= 3; i++) { total = dec_add(total, xs[i]); f += xs[i] }
+        print total, dec_cmp(total, f), dec_fmt(dec_mul(total, "1.075"), 2), dec_div(total, 4);
+        }"#,
+        "0.60 -1 0.65 0.15\n"
+    );
+
     test_program!(
         explicit_split_fs,
         r#" BEGIN {
@@ -1311,6 +1747,18 @@ this as well"#
         "1 0 1 0\n"
     );
 
+    test_program!(
+        tee_prints_and_returns,
+        r#"BEGIN { OFS="-"; x = tee("hi"); print x, "there"; }"#,
+        "hi\nhi-there\n"
+    );
+
+    test_program!(
+        tee_udf_shadows_builtin,
+        r#"function tee(x) { return x "!"; } BEGIN { print tee("hi"); }"#,
+        "hi!\n"
+    );
+
     test_program!(
         add_zero_prints_float,
         r#"{ print $2 + 1; }"#,
@@ -1470,6 +1918,465 @@ function do_something(v) {
         @input "hello\n"
     );
 
+    test_program!(
+        environ_reaches_commands,
+        r#"BEGIN {
+    ENVIRON["ZAWK_TEST_ENV_A"] = "a"; ENVIRON["ZAWK_TEST_ENV_A"] = ENVIRON["ZAWK_TEST_ENV_A"] "1"
+    r = setenv("ZAWK_TEST_ENV_B", "b")
+    "echo $ZAWK_TEST_ENV_A-$ZAWK_TEST_ENV_B" | getline x; print r, x, ENVIRON["ZAWK_TEST_ENV_B"]
+    delete ENVIRON["ZAWK_TEST_ENV_A"]; unsetenv("ZAWK_TEST_ENV_B")
+    "echo ${ZAWK_TEST_ENV_A-none}-${ZAWK_TEST_ENV_B-none}" | getline y; print y
+    print ("ZAWK_TEST_ENV_B" in ENVIRON), setenv("A=B", "c"), unsetenv("")
+}"#,
+        "0 a1-b b\nnone-none\n0 -1 -1\n"
+    );
+
+    test_program!(
+        fflush_outputs,
+        r#"BEGIN { print "a"; r = fflush(); print r, fflush(""), fflush("not-open") }"#,
+        "a\n0 0 -1\n"
+    );
+
+    test_program!(
+        indirect_calls,
+        r#"function add(a, b) { return a + b }
+function cat(a, b) { return a "-" b }
+function shout(s, unused) { return toupper(s) }
+BEGIN {
+    n = split("add 2 3,cat x y", calls, ",")
+    for (i = 1; i <= n; i++) { split(calls[i], c, " "); f = c[1]; print @f(c[2], c[3]) }
+    g = "shout"; print @g(@f(1, 2))
+}"#,
+        "5\nx-y\n1-2\n"
+    );
+
+    test_program!(
+        array_fns,
+        r#"function sq(x) { return x * x }
+function even(x) { return x % 2 == 0 }
+function add(acc, x) { return acc + x }
+BEGIN {
+    split("1 2 3 4", a, " ")
+    print _filter(a, "even", evens), length(a), evens[2] evens[4]
+    f = "sq"; print _map(a, f), a[3]
+    print _reduce(a, "add", 0)
+    print _filter(a, "even"), length(a)
+}"#,
+        "2 4 24\n4 9\n30\n2 2\n"
+    );
+    test_program!(
+        array_builders,
+        r#"BEGIN {
+    a["x"] = 3; a["y"] = 1.5; a["z"] = 3
+    k = _keys(a); v = _values(a); print length(k), k[1], k[3], v[1], v[2]
+    inv = _invert(a); print length(inv), inv[3], inv[1.5]
+    split("a b c d e", parts, " "); s = _slice(parts, 2, 4); t = _slice(parts, 4)
+    print length(s), s[1], s[3], length(t), t[2], length(parts)
+    b["w"] = 9; b["x"] = 4; m = _merge(a, b); print length(m), m["x"], m["w"], m["y"], a["x"]
+}"#,
+        "3 x z 3 1.5\n2 z y\n3 b d 2 e 5\n4 4 9 1.5 3\n"
+    );
+    test_program!(
+        array_copy_equals,
+        r#"function fill(arr) { arr["k"] = "v" }
+BEGIN {
+    a["x"] = 1; a["y"] = "s"
+    n = _copy(b, a); b["x"] = 2; print n, a["x"], b["x"], _equals(a, b)
+    _copy(b, a); print _equals(a, b), length(b)
+    fill(b); print _equals(a, b), ("k" in a)
+    print _copy(a, a), _equals(a, a)
+}"#,
+        "2 1 2 0\n1 2\n0 0\n2 1\n"
+    );
+    test_program!(
+        array_sorted_keys,
+        r#"BEGIN {
+    a["b"] = 10; a["a"] = 9; a["c"] = "x"; a["10"] = 1
+    k = _sorted_keys(a); print k[1], k[2], k[3], k[4]
+    v = _sort_by_value(a); print v[1], v[2], v[3], v[4]
+    v = _sort_by_value(a, "num_desc"); print v[1], v[4], length(a), a["b"]
+    n[3] = 1; n[20] = 2; n[100] = 3
+    k = _sorted_keys(n, "desc"); print k[1], k[3]
+    k = _sorted_keys(n, "str_asc"); print k[1], k[3]
+}"#,
+        "10 a b c\n10 b a c\nb c 4 10\n100 3\n100 3\n"
+    );
+    test_program!(
+        natural_sort,
+        r#"BEGIN {
+    print natcmp("file10", "file2"), strcmp("file10", "file2"), natcmp("a2", "a2")
+    split("file10 file2 file1", f); n = asort(f, g, "natural"); print n, g[1], g[2], g[3], f[1]
+    split("1.10.0 1.2.0 1.9.0-beta 1.9.0", v); asort(v, v, "version_desc"); print v[1], v[2], v[3], v[4]
+    split("3 1 2.5", x); asort(x); print x[1], x[2], x[3]
+    k = _sorted_keys(f, "natural_desc"); print k[1]
+}"#,
+        "1 -1 0\n3 file1 file2 file10 file10\n1.10.0 1.9.0 1.9.0-beta 1.2.0\n1 2.5 3\n3\n"
+    );
+    test_program!(
+        array_insertion_order,
+        r#"BEGIN {
+    split("z y x w v u t s r q p", a); print _join(a, ",")
+    for (k in a) ks = ks k " "; print ks
+    m["zz"] = 1; m["aa"] = 2; m["mm"] = 3; m["zz"]++
+    for (k in m) kvs = kvs k "=" m[k] " "; print kvs
+}"#,
+        "z,y,x,w,v,u,t,s,r,q,p\n1 2 3 4 5 6 7 8 9 10 11 \nzz=2 aa=2 mm=3 \n"
+    );
+
+    test_program!(
+        nan_and_inf,
+        r#"BEGIN {
+    inf = 2 ^ 2000; nan = log(-1)
+    print is_nan(nan), is_nan(inf), is_nan("x"), is_inf(inf), is_inf(-inf), is_inf(1e308)
+}"#,
+        "1 0 0 1 1 0\n"
+    );
+
+    test_program!(
+        high_resolution_timers,
+        r#"BEGIN {
+    start = clock(); ms = systime_ms(); us = systime_us()
+    r = sleep(0.05); elapsed = clock() - start
+    print r, (elapsed >= 0.05), (elapsed < 5), sleep(-1), sleep("x")
+    print (int(ms / 1000) <= systime()), (us >= ms * 1000)
+}"#,
+        "0 1 1 -1 0\n1 1\n"
+    );
+
+    test_program!(
+        builtin_errors_set_errno,
+        r#"BEGIN {
+    x = char_at("abc", 0); print "[" x "]", ERRNO
+    ERRNO = ""; print char_at("abc", 3), "[" ERRNO "]"
+    n = length(capitalize("ab\xffcd")); print n, ERRNO
+}"#,
+        "[] char_at: index 0 is out of range, indexes start at 1\nc []\n2 string is not valid UTF-8: invalid utf-8 sequence of 1 bytes from index 2\n"
+    );
+
+    test_program!(
+        file_errors_set_errno,
+        r#"BEGIN {
+    x = read_all("/nonexistent/zawk"); print "[" x "]", (ERRNO ~ /^read_all: /)
+    read_lines("/nonexistent/zawk"); print (ERRNO ~ /^read_lines: /)
+    append_all("/nonexistent/zawk", "x"); print (ERRNO ~ /^append_all: /)
+}"#,
+        "[] 1\n1\n1\n"
+    );
+
+    test_program!(
+        file_metadata,
+        r#"BEGIN {
+    print exists("/"), exists("/nonexistent/zawk")
+    s = stat("/"); print s["type"]
+    s = stat("/nonexistent/zawk"); print length(s), (ERRNO ~ /^stat: /)
+    print rmfile("/nonexistent/zawk"), (ERRNO ~ /^rmfile: /)
+    print length(glob("/nonexistent/zawk/*"))
+}"#,
+        "1 0\ndir\n0 1\n0 1\n0\n"
+    );
+
+    test_program!(
+        file_digest_errors,
+        r#"BEGIN {
+    x = file_digest("md5", "/nonexistent/zawk"); print "[" x "]", (ERRNO ~ /^file_digest: /)
+    print file_digest("md5", "/dev/null")
+}"#,
+        "[] 1\nd41d8cd98f00b204e9800998ecf8427e\n"
+    );
+
+    test_program!(
+        digest_handles,
+        r#"BEGIN { h = digest_init("md5"); m = digest_init("HmacSHA256", "key") }
+{ digest_update(h, $0 "\n"); digest_update(m, $0); }
+END {
+    print digest_final(h), (digest_final(m) == hmac("HmacSHA256", "key", "ab"))
+    print digest_update(h, "x"), (ERRNO ~ /^digest_update: unknown digest handle/)
+    print digest_init("nope"), (ERRNO ~ /^digest_init: /)
+}"#,
+        "dd8c6a395b5dd36c56d23275028f526c 1\n0 1\n0 1\n",
+        @input "a\nb\n"
+    );
+
+    test_program!(
+        jwt_checks,
+        r#"BEGIN {
+    claims["sub"] = "42"; claims["aud"] = "api"; claims["exp"] = "4102444800"
+    t = jwt("HS256", "secret", claims)
+    c = dejwt("secret", t, "exp,aud=api"); print c["sub"], c["header.alg"]
+    c = dejwt("secret", t, "aud=web"); print length(c), (ERRNO ~ /^dejwt: token is not intended/)
+    c = dejwt("other", t); print length(c), (ERRNO ~ /^dejwt: /)
+    print "[" jwt("XX256", "secret", claims) "]", (ERRNO ~ /^jwt: unsupported algorithm/)
+}"#,
+        "42 HS256\n0 1\n0 1\n[] 1\n"
+    );
+
+    test_program!(
+        cert_errors,
+        r#"BEGIN {
+    c = cert("/nonexistent/zawk.pem"); print length(c), (ERRNO ~ /^cert: /)
+    c = cert("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n"); print length(c), (ERRNO ~ /^cert: invalid certificate/)
+}"#,
+        "0 1\n0 1\n"
+    );
+
+    test_program!(
+        ip_cidr,
+        r#"{ if (ip_in_cidr($1, "10.0.0.0/8")) n++; v[ip_version($1)]++ }
+END { print n, v[4], v[6], v[0], int2ip(ip2int("172.16.0.9") + 1), cidr_contains("fd00::/8", "fd12::/16") }"#,
+        "2 3 1 1 172.16.0.10 1\n",
+        @input "10.0.0.1\n10.255.1.1\n11.0.0.1\n::1\nbogus\n"
+    );
+
+    test_program!(
+        logfmt_round_trip,
+        r#"{ m = logfmt($0); print m["msg"] "|" m["path"] "|" length(m); m["n"] = NR; print to_logfmt(m, "level,msg"); }
+END { c["b"] = 2; c["a"]++; print to_logfmt(c); }"#,
+        "disk \"sda\" full|/a=b|4\nlevel=warn msg=\"disk \\\"sda\\\" full\" debug= n=1 path=\"/a=b\"\na=1 b=2\n",
+        @input "level=warn msg=\"disk \\\"sda\\\" full\" path=/a=b debug\n"
+    );
+
+    test_program!(
+        toml_round_trip,
+        r#"BEGIN {
+    doc = "[package]\nname = \"demo\"\nversion = \"1\"\n\n[[bin]]\nname = \"cli\"\n"
+    t = from_toml(doc); print t["package.name"], toml_value(doc, "bin.1.name"), toml_value(doc, "package.nope") "|"
+    t["package.version"] = "2"; t["package.publish"] = "false"
+    printf "%s", to_toml(t, doc)
+    print toml_value("a = ", "a") "|", (ERRNO ~ /^toml_value: invalid TOML at line 1, column 5/)
+}"#,
+        "demo cli |\n[[bin]]\nname = \"cli\"\n\n[package]\nname = \"demo\"\npublish = false\nversion = \"2\"\n| 1\n"
+    );
+
+    test_program!(
+        ini_properties,
+        r#"BEGIN {
+    t = from_ini("; app\nname = demo\n[server]\nhost = localhost\nport: 8080\n")
+    print t["name"], t["server.host"], t["server.port"], length(t)
+    t["server.port"] = 8081; t["server.tls.enabled"] = "true"
+    printf "%s", to_ini(t)
+    props = "! db\ndb.url = jdbc:h2:mem\ngreeting Hello \\\n    World\nname=caf\\u00e9\n"
+    print prop_get(props, "db.url") "|" prop_get(props, "greeting") "|" prop_get(props, "name") "|" prop_get(props, "nope") "|"
+}"#,
+        "demo localhost 8080 3\nname = demo\n\n[server]\nhost = localhost\nport = 8081\n\n[server.tls]\nenabled = true\njdbc:h2:mem|Hello World|café||\n"
+    );
+
+    test_program!(
+        xml_output_update,
+        r#"BEGIN {
+    c["server.@port"] = 80; c["server.host"] = "a<b"; c["name"] = "demo"
+    printf "%s", to_xml(c, "config")
+    doc = "<a><b id=\"1\">x</b><b id=\"2\">y</b></a>"
+    print xml_update(doc, "//b[@id='2']", "z"); print xml_update(doc, "//b/@id", "9")
+    print xml_update("<a>", "/a", "1") "|", (ERRNO ~ /^xml_update: invalid XML at line 1, column 4/)
+}"#,
+        "<config>\n  <name>demo</name>\n  <server port=\"80\">\n    <host>a&lt;b</host>\n  </server>\n</config>\n<a><b id=\"1\">x</b><b id=\"2\">z</b></a>\n<a><b id=\"9\">x</b><b id=\"9\">y</b></a>\n<a>| 1\n"
+    );
+
+    test_program!(
+        html_tables,
+        r#"BEGIN { RS = "\x01" }
+{
+    rows = html_table($0); for (i = 1; i <= length(rows); i++) print rows[i]
+    rows = html_table($0, 2); print rows[1], length(html_table($0, 3))
+}"#,
+        "Name,City\nAlice,\"Portland, OR\"\nTotal,\nsecond 0\n",
+        @input "<table><tr><th>Name</th><th>City</th></tr><tr><td>Alice</td><td>Portland, OR</td></tr><tr><td colspan=2>Total</td></tr></table><table><tr><td>second</td></tr></table>"
+    );
+
+    test_program!(
+        binary_records,
+        r#"BEGIN {
+    m = from_msgpack("\x82\xa2id\x07\xa2ok\xc3"); print m["id"], m["ok"]
+    c = from_cbor("\xa2\x64name\x61x\x61n\x22"); print c["name"], c["n"]
+    print length(from_cbor("\xa1")), ERRNO
+    print length(from_protobuf("", "/nonexistent.desc", "a.B")), (ERRNO ~ /^from_protobuf: \/nonexistent.desc: /)
+}"#,
+        "7 1\nx -3\n0 from_cbor: unexpected end of CBOR data\n0 1\n"
+    );
+
+    test_program!(
+        user_agents,
+        r#"{ u = ua_parse($0); print u["browser"], u["os"], u["device"], length(ua_parse("-")) }"#,
+        "Chrome Android smartphone 0\nGooglebot  crawler 0\n",
+        @input "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Mobile Safari/537.36\nMozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)\n"
+    );
+
+    test_program!(
+        geoip_errors,
+        r#"BEGIN {
+    print geoip_db("/nonexistent/zawk.mmdb"), (ERRNO ~ /^geoip_db: \/nonexistent\/zawk.mmdb/)
+    g = geoip("not-an-ip"); print length(g), (ERRNO ~ /^geoip: /)
+}"#,
+        "0 1\n0 1\n"
+    );
+
+    #[test]
+    fn runtime_errors_are_located() {
+        let a = Arena::default();
+        let prog = "function ratio(a, b) {\n  return a % b\n}\nBEGIN {\n  x = 1\n  print ratio(x, x - 1)\n}";
+        let e = match run_program(&a, prog, "", Escaper::Identity, None, ExecutionStrategy::Serial) {
+            Ok(_) => panic!("expected division by zero to fail"),
+            Err(e) => e.to_string(),
+        };
+        assert!(e.starts_with("error at line 2 in function ratio(): "), "{}", e);
+        assert!(e.contains("division by zero in %"), "{}", e);
+        assert!(e.ends_with("\n    called from line 6"), "{}", e);
+    }
+
+    #[test]
+    fn debugger_watches_globals() {
+        let a = Arena::default();
+        let text = "BEGIN {\n  n = 0\n  for (i = 1; i <= 3; i++)\n    n += i\n  s[\"total\"] = n\n  print n\n}";
+        let mut prog = ast::Prog::from_stage(&a, ExecutionStrategy::Serial.stage());
+        let lexer = lexer::Tokenizer::new(a.alloc_str(text));
+        syntax::ProgParser::new()
+            .parse(&a, &mut Vec::new(), &mut prog, lexer)
+            .unwrap();
+        prog.debug = true;
+        let mut ctx = cfg::ProgramContext::from_prog(&a, a.alloc(prog), Escaper::Identity).unwrap();
+        let fake_fs = FakeFs::default();
+        let mut interp =
+            compile::bytecode(&mut ctx, simulate_stdin_regex(""), fake_fs.clone(), 1).unwrap();
+        let out = tempfile::NamedTempFile::new().unwrap();
+        let commands = "watch n\nc\nc\np n i\nb 6\nd 1\nc\np s\nc\n";
+        interp.set_debugger(
+            text,
+            Box::new(io::Cursor::new(commands)),
+            Box::new(out.reopen().unwrap()),
+        );
+        interp.run().unwrap();
+        std::mem::drop(interp);
+        assert_eq!(String::from_utf8(fake_fs.stdout.read_data()).unwrap(), "6\n");
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "line 2\t  n = 0
+(zawk) Watchpoint 1: n = 0
+(zawk) Watchpoint 1: n
+  old value: 0
+  new value: 1
+line 4\t    n += i
+(zawk) Watchpoint 1: n
+  old value: 1
+  new value: 3
+line 4\t    n += i
+(zawk) n = 3
+i = 3
+(zawk) Breakpoint 2 at line 6
+(zawk) (zawk) Breakpoint 2
+line 6\t  print n
+(zawk) s = {\"total\": 6}
+(zawk) "
+        );
+    }
+
+    #[test]
+    fn profiler_counts_statements() {
+        let a = Arena::default();
+        let text = "function add(a, b) {\n  return a + b\n}\n{\n  total = add(total, $1)\n}\nEND {\n  for (i = 0; i < 2; i++) {\n    n++\n  }\n  print total, n\n}";
+        let mut prog = ast::Prog::from_stage(&a, ExecutionStrategy::Serial.stage());
+        let lexer = lexer::Tokenizer::new(a.alloc_str(text));
+        syntax::ProgParser::new()
+            .parse(&a, &mut Vec::new(), &mut prog, lexer)
+            .unwrap();
+        let mut ctx = cfg::ProgramContext::from_prog(&a, a.alloc(prog), Escaper::Identity).unwrap();
+        let fake_fs = FakeFs::default();
+        let mut interp =
+            compile::bytecode(&mut ctx, simulate_stdin_regex("1\n2\n3\n"), fake_fs.clone(), 1)
+                .unwrap();
+        let out = tempfile::NamedTempFile::new().unwrap();
+        interp.set_profiler(text, out.path().to_str().unwrap());
+        interp.run().unwrap();
+        std::mem::drop(interp);
+        assert_eq!(String::from_utf8(fake_fs.stdout.read_data()).unwrap(), "6 2\n");
+        assert_eq!(
+            std::fs::read_to_string(out.path()).unwrap(),
+            "# zawk profile: the number of times each statement ran
+         function add(a, b) {
+       3   return a + b
+         }
+         {
+       3   total = add(total, $1)
+         }
+         END {
+       1   for (i = 0; i < 2; i++) {
+       2     n++
+           }
+       1   print total, n
+         }
+
+# function calls
+       3 add()
+"
+        );
+    }
+
+    // RS = "\0" is what -0 sets. With the default FS, newlines inside a record separate fields,
+    // for split() as well as for $1..$NF; any other FS leaves them in the fields.
+    test_program!(
+        nul_records_default_fs,
+        r#"BEGIN { RS = "\0" } { n = split($0, p); print NF ":" $1 "-" $NF ":" n }"#,
+        "3:a-c:3\n2:d-e:2\n",
+        @input "a b\nc\0d  e\0"
+    );
+
+    test_program!(
+        nul_records_custom_fs,
+        r#"BEGIN { RS = "\0"; FS = "," } { print NF ":" $2 }"#,
+        "3:b\nc\n2:f\n",
+        @input "a,b\nc,d\0e,f\0"
+    );
+
+    #[test]
+    fn emit_ast() {
+        let a = Arena::default();
+        let prog = parse_program(
+            r#"function inc(x) { return x + 1 }
+/^#/ { next }
+{ for (k in a) delete a[k]; if (NF > 2) print inc($2) > "out"; else n++; }
+END { printf "%d\n", n }"#,
+            &a,
+            Escaper::Identity,
+            ExecutionStrategy::Serial,
+        )
+        .unwrap();
+        assert_eq!(
+            prog.to_string(),
+            r#"Function inc(x)
+  Return
+    Binop +
+      Var x
+      ILit 1
+Rule
+  PatLit /^#/
+  Next
+Rule
+  Always
+  Block
+    ForEach k
+      Var a
+      Call delete
+        Var a
+        Var k
+    If
+      Binop >
+        Var NF
+        ILit 2
+      Print
+        Call inc
+          Unop $
+            ILit 2
+        Redirect >
+          StrLit "out"
+      Post++
+        Var n
+END
+  Printf
+    StrLit "%d\n"
+    Var n
+"#
+        );
+    }
+
     // TODO test more operators, consider more edge cases around functions
 }
 
@@ -1564,6 +2471,21 @@ mod bench {
         sum_integer_str_hist_10k,
         r#"END { for (i=0; i<10000; i++) {SUMS[i ""]++; SUM += i;}; print SUM }"#
     );
+    bench_program!(
+        group_by_field,
+        r#"{ N[$1]++ } END { for (k in N) S += N[k]; print S }"#,
+        @input crate::test_string_constants::VIRGIL
+    );
+    bench_program!(
+        group_by_composite_key,
+        r#"{ N[$1, $2]++ } END { for (k in N) S += N[k]; print S }"#,
+        @input crate::test_string_constants::VIRGIL
+    );
+    bench_program!(
+        group_by_concatenated_key,
+        r#"{ N[$1 "-" $NF] += NF } END { for (k in N) S += N[k]; print S }"#,
+        @input crate::test_string_constants::VIRGIL
+    );
     bench_program!(
         recursive_fib_15,
         r#"
@@ -0,0 +1,224 @@
+//! `@include "file"` directives and the AWKPATH search path.
+//!
+//! As in gawk, a directive must be on a line of its own, outside of any rule or function. It is
+//! replaced by the contents of the named file before the program is parsed. Files are looked up
+//! in the current directory and then, if the name has no `/`, in each directory of the
+//! colon-separated `AWKPATH` environment variable, first as given and then with a `.awk` suffix.
+//! The files passed with `-f` are found the same way.
+//!
+//! A file is only included once, so two libraries can both include a third. A file that ends up
+//! including itself is an error.
+use crate::common::Result;
+use crate::trace::SourceMap;
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+const DIRECTIVE: &str = "@include";
+
+/// The directories in `AWKPATH`.
+pub(crate) fn search_path() -> Vec<PathBuf> {
+    match std::env::var_os("AWKPATH") {
+        Some(p) => std::env::split_paths(&p).filter(|d| !d.as_os_str().is_empty()).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Find the program file `name` in `path`.
+pub(crate) fn find(name: &str, path: &[PathBuf]) -> Option<PathBuf> {
+    // Not `is_file`: programs are also read from pipes like /dev/stdin.
+    let readable = |p: &PathBuf| p.exists() && !p.is_dir();
+    let candidates = [name.to_string(), format!("{}.awk", name)];
+    if let Some(file) = candidates.iter().map(PathBuf::from).find(readable) {
+        return Some(file);
+    }
+    if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        return None;
+    }
+    path.iter()
+        .flat_map(|dir| candidates.iter().map(move |c| dir.join(c)))
+        .find(readable)
+}
+
+// The file named by an `@include` line, if `line` is one.
+fn directive(line: &str) -> Option<Result<&str>> {
+    let rest = line.trim_start().strip_prefix(DIRECTIVE)?;
+    if rest.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+        return None;
+    }
+    let malformed = || err!("malformed @include directive: {}", line.trim());
+    let rest = match rest.trim_start().strip_prefix('"') {
+        Some(rest) => rest,
+        None => return Some(malformed()),
+    };
+    let (name, rest) = match rest.split_once('"') {
+        Some(split) => split,
+        None => return Some(malformed()),
+    };
+    let rest = rest.trim_start();
+    let rest = rest.strip_prefix(';').unwrap_or(rest).trim_start();
+    if name.is_empty() || !(rest.is_empty() || rest.starts_with('#')) {
+        return Some(malformed());
+    }
+    Some(Ok(name))
+}
+
+/// Whether `line` is a well-formed `@include` directive.
+pub(crate) fn is_directive(line: &str) -> bool {
+    matches!(directive(line), Some(Ok(_)))
+}
+
+/// The text of a program, along with the files its lines came from.
+#[derive(Default, Debug)]
+pub(crate) struct Source {
+    pub(crate) text: String,
+    pub(crate) map: SourceMap,
+    // The number of newlines in `text`.
+    lines: usize,
+}
+
+impl Source {
+    pub(crate) fn new(file: &str, text: String) -> Source {
+        Source {
+            lines: text.matches('\n').count(),
+            map: SourceMap::new(file),
+            text,
+        }
+    }
+
+    /// Append `other`, followed by a newline.
+    pub(crate) fn push(&mut self, other: Source) {
+        self.map.append(self.lines, other.map);
+        self.text.push_str(&other.text);
+        self.text.push('\n');
+        self.lines += other.lines + 1;
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Resolver {
+    path: Vec<PathBuf>,
+    // Every file read so far.
+    seen: HashSet<PathBuf>,
+    // The files currently being expanded, outermost first.
+    stack: Vec<PathBuf>,
+}
+
+impl Resolver {
+    pub(crate) fn new() -> Resolver {
+        Resolver {
+            path: search_path(),
+            ..Default::default()
+        }
+    }
+
+    /// Read the program file `name` (from `-f`), and expand its `@include` directives.
+    pub(crate) fn program_file(&mut self, name: &str) -> Result<Source> {
+        let file = match find(name, &self.path) {
+            Some(file) => file,
+            None => return err!("failed to read program from {}: file not found", name),
+        };
+        self.read(file).map(Option::unwrap_or_default)
+    }
+
+    /// Replace the `@include` directives in `text`, read from `origin`, with the files they name.
+    pub(crate) fn expand(&mut self, text: &str, origin: &str) -> Result<Source> {
+        if !text.contains(DIRECTIVE) {
+            return Ok(Source::new(origin, text.to_string()));
+        }
+        let mut res = Source::new(origin, String::with_capacity(text.len()));
+        for (i, line) in text.lines().enumerate() {
+            match directive(line) {
+                Some(name) => {
+                    let name = name?;
+                    let file = match find(name, &self.path) {
+                        Some(file) => file,
+                        None => return err!("@include: cannot find {:?} in AWKPATH", name),
+                    };
+                    if let Some(included) = self.read(file)? {
+                        res.push(included);
+                        res.map.add(res.lines, origin, i + 1);
+                        continue;
+                    }
+                }
+                None => res.text.push_str(line),
+            }
+            res.text.push('\n');
+            res.lines += 1;
+        }
+        Ok(res)
+    }
+
+    // The expanded contents of `file`, or None if it has been read already.
+    fn read(&mut self, file: PathBuf) -> Result<Option<Source>> {
+        let key = file.canonicalize().unwrap_or_else(|_| file.clone());
+        if let Some(start) = self.stack.iter().position(|f| *f == key) {
+            let cycle: Vec<_> = self.stack[start..]
+                .iter()
+                .chain(std::iter::once(&key))
+                .map(|f| f.display().to_string())
+                .collect();
+            return err!("@include cycle: {}", cycle.join(" -> "));
+        }
+        if !self.seen.insert(key.clone()) {
+            return Ok(None);
+        }
+        let text = match std::fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) => return err!("failed to read program from {}: {}", file.display(), e),
+        };
+        self.stack.push(key);
+        let res = self.expand(&text, &file.display().to_string());
+        self.stack.pop();
+        res.map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn write(dir: &Path, name: &str, text: &str) {
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+
+    #[test]
+    fn directives() {
+        assert_eq!(directive("@include \"lib.awk\"").unwrap().unwrap(), "lib.awk");
+        assert_eq!(directive("  @include \"a b\"; # note").unwrap().unwrap(), "a b");
+        assert!(directive("@include lib.awk").unwrap().is_err());
+        assert!(directive("@include \"lib.awk\" { print }").unwrap().is_err());
+        assert!(directive("@includes \"lib.awk\"").is_none());
+        assert!(directive("print \"@include\"").is_none());
+    }
+
+    #[test]
+    fn includes() {
+        let dir = tempfile::tempdir().unwrap();
+        let lib = dir.path().join("lib");
+        std::fs::create_dir(&lib).unwrap();
+        write(&lib, "util.awk", "@include \"base\"\nfunction twice(x) { return 2 * x }\n");
+        write(&lib, "base.awk", "function one() { return 1 }\n");
+        write(&lib, "a.awk", "@include \"b.awk\"\n");
+        write(&lib, "b.awk", "@include \"a.awk\"\n");
+        let mut r = Resolver {
+            path: vec![dir.path().join("missing"), lib.clone()],
+            ..Default::default()
+        };
+        let prog = r
+            .expand("@include \"util\"\n@include \"base.awk\"\nBEGIN { print twice(one()) }", "main")
+            .unwrap();
+        assert_eq!(
+            prog.text,
+            "function one() { return 1 }\n\nfunction twice(x) { return 2 * x }\n\n\nBEGIN { print twice(one()) }\n"
+        );
+        let at = |line| prog.map.describe(line);
+        assert_eq!(at(0), format!("{}:1", lib.join("base.awk").display()));
+        assert_eq!(at(2), format!("{}:2", lib.join("util.awk").display()));
+        assert_eq!(at(5), "main:3");
+        let err = r.program_file("a").unwrap_err().to_string();
+        assert!(err.contains("@include cycle") && err.contains("a.awk -> "), "{}", err);
+        assert!(r.expand("@include \"nope\"", "main").is_err());
+    }
+}
@@ -131,6 +131,13 @@ impl TaintedStringAnalysis {
                 self.dfa.add_query(cmd);
                 self.dfa.add_src(dst, Taint::Tainted);
             }
+            ExecOutput(dst, cmd) | Spawn(dst, cmd) => {
+                self.dfa.add_query(cmd);
+                self.dfa.add_src(dst, Taint::Tainted);
+            }
+            // Argument vectors never reach a shell, so user input in them cannot be reinterpreted
+            // as a different command.
+            ExecOutputArgv(dst, _) | SpawnArgv(dst, _) => self.dfa.add_src(dst, Taint::Tainted),
             _ => dataflow::boilerplate::visit_ll(inst, |dst, src| {
                 if let Some(src) = src {
                     self.dfa.add_dep(dst, src, ())
@@ -190,6 +197,9 @@ mod tests {
             BEGIN {  system(x($2, "dog")); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[i]=$i; system(m[3]); }"#,
             r#"BEGIN { for (i=1; i<10; i++) m[$i]=i; for (i in m) system(i); }"#,
+            r#"BEGIN { print exec_output("echo " $1); }"#,
+            r#"BEGIN { h = spawn("grep " $2); print "x" > h; }"#,
+            r#"BEGIN { x = exec_output("hostname"); system("ping " x); }"#,
         ];
 
         for p in progs.iter() {
@@ -207,6 +217,9 @@ mod tests {
             BEGIN { while(x("echo ", "hi") | getline) print; }"#,
             r#"function x(a, b) { return a b; }
             BEGIN {  system(x($2, "dog") ? "echo hello" : "echo goodbye"); }"#,
+            r#"BEGIN { print exec_output("date +%s"); }"#,
+            r#"BEGIN { split("grep -F", argv); argv[3] = $1; print exec_output(argv); }"#,
+            r#"BEGIN { split("sort -n", argv); h = spawn(argv); print $1 > h; }"#,
         ];
         for p in progs.iter() {
             assert_analysis_accept(p);
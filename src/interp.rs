@@ -1,17 +1,22 @@
 use crate::builtins::Variable;
 use crate::bytecode::{Get, Instr, Label, Reg};
-use crate::common::{NumTy, Result, Stage};
+use crate::common::{MergeStrategy, NumTy, Result, Stage};
 use crate::compile::{self, Ty};
+use crate::debug;
+use crate::profile;
 use crate::pushdown::FieldSet;
-use crate::runtime::{self, Float, Int, Line, LineReader, Str, UniqueStr};
+use crate::runtime::{self, Float, Int, Line, LineReader, Map, Str, UniqueStr};
+use crate::trace;
 
 use crossbeam::scope;
 use crossbeam_channel::bounded;
-use hashbrown::HashMap;
 use rand::{self, rngs::StdRng, Rng, SeedableRng};
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
+use crate::runtime::printf::NumberFormat;
+use serde::{Deserialize, Serialize};
 
 use std::mem;
+use std::sync::Arc;
 use std::time::SystemTime;
 use crate::builtins;
 
@@ -32,6 +37,10 @@ pub(crate) struct Core<'a> {
     pub rng: StdRng,
     pub current_seed: u64,
     pub slots: Slots,
+    pub sketches: runtime::sketch::Sketches,
+    pub digests: runtime::crypto::Digests,
+    // How to combine each slot when merging results from parallel workers.
+    pub merge: MergeSpec,
 }
 
 impl<'a> Drop for Core<'a> {
@@ -52,29 +61,88 @@ pub(crate) struct Slots {
     pub int: Vec<Int>,
     pub float: Vec<Float>,
     pub strs: Vec<UniqueStr<'static>>,
-    pub intint: Vec<HashMap<Int, Int>>,
-    pub intfloat: Vec<HashMap<Int, Float>>,
-    pub intstr: Vec<HashMap<Int, UniqueStr<'static>>>,
-    pub strint: Vec<HashMap<UniqueStr<'static>, Int>>,
-    pub strfloat: Vec<HashMap<UniqueStr<'static>, Float>>,
-    pub strstr: Vec<HashMap<UniqueStr<'static>, UniqueStr<'static>>>,
+    pub intint: Vec<Map<Int, Int>>,
+    pub intfloat: Vec<Map<Int, Float>>,
+    pub intstr: Vec<Map<Int, UniqueStr<'static>>>,
+    pub strint: Vec<Map<UniqueStr<'static>, Int>>,
+    pub strfloat: Vec<Map<UniqueStr<'static>, Float>>,
+    pub strstr: Vec<Map<UniqueStr<'static>, UniqueStr<'static>>>,
+}
+
+/// The merge strategy for each slot, indexed in the same way as [`Slots`]. Slots without an
+/// entry use [`MergeStrategy::Sum`].
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct MergeSpec {
+    pub int: Vec<MergeStrategy>,
+    pub float: Vec<MergeStrategy>,
+    pub strs: Vec<MergeStrategy>,
+    pub intint: Vec<MergeStrategy>,
+    pub intfloat: Vec<MergeStrategy>,
+    pub intstr: Vec<MergeStrategy>,
+    pub strint: Vec<MergeStrategy>,
+    pub strfloat: Vec<MergeStrategy>,
+    pub strstr: Vec<MergeStrategy>,
+}
+
+impl MergeSpec {
+    pub(crate) fn set(&mut self, ty: Ty, slot: usize, m: MergeStrategy) {
+        let v = match ty {
+            Ty::Int => &mut self.int,
+            Ty::Float => &mut self.float,
+            Ty::Str => &mut self.strs,
+            Ty::MapIntInt => &mut self.intint,
+            Ty::MapIntFloat => &mut self.intfloat,
+            Ty::MapIntStr => &mut self.intstr,
+            Ty::MapStrInt => &mut self.strint,
+            Ty::MapStrFloat => &mut self.strfloat,
+            Ty::MapStrStr => &mut self.strstr,
+            Ty::Null | Ty::IterInt | Ty::IterStr => return,
+        };
+        set_slot(v, slot, m)
+    }
 }
 
 /// A Simple helper trait for implement aggregations for slot values and variables.
-trait Agg {
+trait Agg: Sized {
     fn agg(self, other: Self) -> Self;
+    /// Aggregate according to a user-provided strategy; `agg` implements `Sum`.
+    fn agg_with(self, other: Self, m: MergeStrategy) -> Self {
+        match m {
+            MergeStrategy::Sum => self.agg(other),
+            _ => self.agg_other(other, m),
+        }
+    }
+    fn agg_other(self, other: Self, m: MergeStrategy) -> Self;
 }
 
 impl Agg for Int {
     fn agg(self, other: Int) -> Int {
         self + other
     }
+    fn agg_other(self, other: Int, m: MergeStrategy) -> Int {
+        match m {
+            MergeStrategy::Min => self.min(other),
+            MergeStrategy::Max => self.max(other),
+            MergeStrategy::Union if other != 0 => other,
+            MergeStrategy::Union => self,
+            MergeStrategy::Sum | MergeStrategy::Concat => self + other,
+        }
+    }
 }
 
 impl Agg for Float {
     fn agg(self, other: Float) -> Float {
         self + other
     }
+    fn agg_other(self, other: Float, m: MergeStrategy) -> Float {
+        match m {
+            MergeStrategy::Min => self.min(other),
+            MergeStrategy::Max => self.max(other),
+            MergeStrategy::Union if other != 0.0 => other,
+            MergeStrategy::Union => self,
+            MergeStrategy::Sum | MergeStrategy::Concat => self + other,
+        }
+    }
 }
 
 impl<'a> Agg for UniqueStr<'a> {
@@ -86,10 +154,32 @@ impl<'a> Agg for UniqueStr<'a> {
             other
         }
     }
+    fn agg_other(self, other: UniqueStr<'a>, m: MergeStrategy) -> UniqueStr<'a> {
+        // Empty strings are treated as "unset" for every strategy.
+        if other.is_empty() {
+            return self;
+        }
+        if self.is_empty() {
+            return other;
+        }
+        match m {
+            MergeStrategy::Concat => Str::concat(self.into_str(), other.into_str()).into(),
+            MergeStrategy::Min | MergeStrategy::Max => {
+                let (l, r) = (self.clone_str(), other.clone_str());
+                let other_less = l.with_bytes(|l| r.with_bytes(|r| r < l));
+                if other_less == (m == MergeStrategy::Min) {
+                    other
+                } else {
+                    self
+                }
+            }
+            MergeStrategy::Sum | MergeStrategy::Union => self.agg(other),
+        }
+    }
 }
 
-impl<K: std::hash::Hash + Eq, V: Agg + Default> Agg for HashMap<K, V> {
-    fn agg(mut self, other: HashMap<K, V>) -> HashMap<K, V> {
+impl<K: std::hash::Hash + Eq, V: Agg + Default> Agg for Map<K, V> {
+    fn agg(mut self, other: Map<K, V>) -> Map<K, V> {
         for (k, v) in other {
             let entry = self.entry(k).or_default();
             let v2 = mem::take(entry);
@@ -97,12 +187,34 @@ impl<K: std::hash::Hash + Eq, V: Agg + Default> Agg for HashMap<K, V> {
         }
         self
     }
+    fn agg_other(mut self, other: Map<K, V>, m: MergeStrategy) -> Map<K, V> {
+        use indexmap::map::Entry;
+        for (k, v) in other {
+            match self.entry(k) {
+                // Keys present in only one worker are kept as-is, rather than being combined with
+                // a default value.
+                Entry::Vacant(e) => {
+                    e.insert(v);
+                }
+                Entry::Occupied(mut e) if m == MergeStrategy::Union => {
+                    e.insert(v);
+                }
+                Entry::Occupied(mut e) => {
+                    let v2 = mem::take(e.get_mut());
+                    *e.get_mut() = v2.agg_other(v, m);
+                }
+            }
+        }
+        self
+    }
 }
 
 /// StageResult is a Send subset of Core that can be extracted for inter-stage aggregation in a
 /// parallel script.
 pub(crate) struct StageResult {
     slots: Slots,
+    // Sketches (e.g. bloom filters) are merged by replaying the worker's insertions.
+    sketches: runtime::sketch::Sketches,
     // TODO: put more variables in here? Most builtin variables are just going to be propagated
     // from the initial thread.
     nr: Int,
@@ -110,26 +222,27 @@ pub(crate) struct StageResult {
 }
 
 impl Slots {
-    fn combine(&mut self, mut other: Slots) {
+    fn combine(&mut self, mut other: Slots, merge: &MergeSpec) {
         macro_rules! for_each_slot_pair {
-            ($s1:ident, $s2:ident, $body:expr) => {
+            ($s1:ident, $s2:ident, $m:ident, $body:expr) => {
                 for_each_slot_pair!(
-                    $s1, $s2, $body, int, float, strs, intint, intfloat, intstr, strint, strfloat,
-                    strstr
+                    $s1, $s2, $m, $body, int, float, strs, intint, intfloat, intstr, strint,
+                    strfloat, strstr
                 );
             };
-            ($s1:ident, $s2:ident, $body:expr, $($fld:tt),*) => {$({
+            ($s1:ident, $s2:ident, $m:ident, $body:expr, $($fld:tt),*) => {$({
                 let $s1 = &mut self.$fld;
                 let $s2 = &mut other.$fld;
+                let $m = &merge.$fld;
                 $body
             });*};
         }
 
-        for_each_slot_pair!(a, b, {
+        for_each_slot_pair!(a, b, m, {
             a.resize_with(std::cmp::max(a.len(), b.len()), Default::default);
-            for (a_elt, b_elt_v) in a.iter_mut().zip(b.drain(..)) {
+            for (i, (a_elt, b_elt_v)) in a.iter_mut().zip(b.drain(..)).enumerate() {
                 let a_elt_v = mem::take(a_elt);
-                *a_elt = a_elt_v.agg(b_elt_v);
+                *a_elt = a_elt_v.agg_with(b_elt_v, m.get(i).cloned().unwrap_or_default());
             }
         });
     }
@@ -144,15 +257,10 @@ pub fn set_slot<T: Default>(vec: &mut Vec<T>, slot: usize, v: T) {
     vec.push(v)
 }
 
-pub fn combine_slot<T: Default>(vec: &mut Vec<T>, slot: usize, f: impl FnOnce(T) -> T) {
-    if slot < vec.len() {
-        let res = f(std::mem::take(&mut vec[slot]));
-        vec[slot] = res;
-        return;
-    }
-    vec.resize_with(slot, Default::default);
-    let res = f(Default::default());
-    vec.push(res)
+// Slots that were never stored to, because the main thread exited before the end of the main
+// loop and no worker ran, read as empty.
+fn take_slot<T: Default>(vec: &mut [T], slot: usize) -> T {
+    vec.get_mut(slot).map(mem::take).unwrap_or_default()
 }
 
 impl<'a> Core<'a> {
@@ -169,7 +277,14 @@ impl<'a> Core<'a> {
         let fi = self.vars.fi.shuttle();
         let environ = self.vars.environ.shuttle();
         let procinfo = self.vars.procinfo.shuttle();
+        let ignorecase = self.vars.ignorecase;
+        let strict = runtime::errors::is_strict();
+        let ordered_arrays = runtime::ordered_arrays();
+        let ocsvquote: UniqueStr<'a> = self.vars.ocsvquote.clone().into();
+        let convfmt: UniqueStr<'a> = self.vars.convfmt.clone().into();
+        let ofmt: UniqueStr<'a> = self.vars.ofmt.clone().into();
         let slots = self.slots.clone();
+        let sketches = self.sketches.for_worker();
         move || {
             let vars = Variables {
                 fs: fs.into_str(),
@@ -188,7 +303,27 @@ impl<'a> Core<'a> {
                 fi: fi.into(),
                 environ: environ.into(),
                 procinfo: procinfo.into(),
+                errno: Default::default(),
+                ignorecase,
+                ocsvquote: ocsvquote.into_str(),
+                convfmt: convfmt.into_str(),
+                ofmt: ofmt.into_str(),
             };
+            // IGNORECASE, OCSVQUOTE, CONVFMT and OFMT are also kept in thread-locals, for code that
+            // has no access to the variables (see str_impl, splitter::batch and printf), as are
+            // --strict and --ordered-arrays. Thread-locals start out unset on a new thread, and
+            // each worker has its own copy of the variables, so they are set again here.
+            runtime::str_impl::set_ignore_case(ignorecase != 0);
+            runtime::errors::set_strict(strict);
+            runtime::set_ordered_arrays(ordered_arrays);
+            let quoting = runtime::splitter::batch::CsvQuoting::parse(&vars.ocsvquote.to_string());
+            runtime::splitter::batch::set_csv_quoting(quoting.unwrap_or_default());
+            {
+                use runtime::printf::{set_number_format, NumberFormat};
+                // Both were checked when they were assigned.
+                let _ = vars.convfmt.with_bytes(|bs| set_number_format(NumberFormat::Convert, bs));
+                let _ = vars.ofmt.with_bytes(|bs| set_number_format(NumberFormat::Output, bs));
+            }
             Core {
                 vars,
                 regexes: Default::default(),
@@ -196,6 +331,9 @@ impl<'a> Core<'a> {
                 rng: rand::rngs::StdRng::seed_from_u64(seed),
                 current_seed: seed,
                 slots,
+                sketches,
+                digests: Default::default(),
+                merge: Default::default(),
             }
         }
     }
@@ -208,19 +346,24 @@ impl<'a> Core<'a> {
             rng: rand::rngs::StdRng::seed_from_u64(seed),
             current_seed: seed,
             slots: Default::default(),
+            sketches: Default::default(),
+            digests: Default::default(),
+            merge: Default::default(),
         }
     }
 
     pub fn extract_result(&mut self, rc: i32) -> StageResult {
         StageResult {
             slots: mem::take(&mut self.slots),
+            sketches: mem::take(&mut self.sketches),
             nr: self.vars.nr,
             rc,
         }
     }
 
-    pub fn combine(&mut self, StageResult { slots, nr, rc: _ }: StageResult) {
-        self.slots.combine(slots);
+    pub fn combine(&mut self, StageResult { slots, sketches, nr, rc: _ }: StageResult) {
+        self.slots.combine(slots, &self.merge);
+        self.sketches.merge(sketches);
         self.vars.nr = self.vars.nr.agg(nr);
     }
 
@@ -239,6 +382,37 @@ impl<'a> Core<'a> {
         self.regexes.regex_match_loc(&mut self.vars, pat, s)
     }
 
+    pub fn match_regex_arr(
+        &mut self,
+        s: &Str<'a>,
+        pat: &Str<'a>,
+        arr: &runtime::StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        self.regexes.regex_match_arr(&mut self.vars, pat, s, arr)
+    }
+
+    /// `split(s, arr, pat, seps)`, or `patsplit(s, arr, pat, seps)` if `patsplit` is set. Both
+    /// arrays are emptied first, and `key` turns the index of an element into its key.
+    pub fn split_seps<K: runtime::MapKey>(
+        &mut self,
+        patsplit: bool,
+        s: &Str<'a>,
+        pat: &Str<'a>,
+        arr: &runtime::SharedMap<K, Str<'a>>,
+        seps: &runtime::SharedMap<K, Str<'a>>,
+        key: impl Fn(Int) -> K,
+    ) -> Result<Int> {
+        arr.clear();
+        seps.clear();
+        let field = |i, fld| arr.insert(key(i), fld);
+        let sep = |i, sep| seps.insert(key(i), sep);
+        if patsplit {
+            self.regexes.pat_split(pat, s, field, sep)
+        } else {
+            self.regexes.split_seps(pat, s, field, sep)
+        }
+    }
+
     pub fn match_const_regex(&mut self, s: &Str<'a>, pat: &Regex) -> Result<Int> {
         runtime::RegexCache::regex_const_match_loc(&mut self.vars, pat, s)
     }
@@ -248,40 +422,40 @@ impl<'a> Core<'a> {
     }
 
     pub fn load_int(&mut self, slot: usize) -> Int {
-        self.slots.int[slot]
+        self.slots.int.get(slot).copied().unwrap_or_default()
     }
     pub fn load_float(&mut self, slot: usize) -> Float {
-        self.slots.float[slot]
+        self.slots.float.get(slot).copied().unwrap_or_default()
     }
     pub fn load_str(&mut self, slot: usize) -> Str<'a> {
-        mem::take(&mut self.slots.strs[slot]).into_str().upcast()
+        take_slot(&mut self.slots.strs, slot).into_str().upcast()
     }
     pub fn load_intint(&mut self, slot: usize) -> runtime::IntMap<Int> {
-        mem::take(&mut self.slots.intint[slot]).into()
+        take_slot(&mut self.slots.intint, slot).into()
     }
     pub fn load_intfloat(&mut self, slot: usize) -> runtime::IntMap<Float> {
-        mem::take(&mut self.slots.intfloat[slot]).into()
+        take_slot(&mut self.slots.intfloat, slot).into()
     }
     pub fn load_intstr(&mut self, slot: usize) -> runtime::IntMap<Str<'a>> {
-        mem::take(&mut self.slots.intstr[slot])
+        take_slot(&mut self.slots.intstr, slot)
             .into_iter()
             .map(|(k, v)| (k, v.into_str().upcast()))
             .collect()
     }
     pub fn load_strint(&mut self, slot: usize) -> runtime::StrMap<'a, Int> {
-        mem::take(&mut self.slots.strint[slot])
+        take_slot(&mut self.slots.strint, slot)
             .into_iter()
             .map(|(k, v)| (k.into_str().upcast(), v))
             .collect()
     }
     pub fn load_strfloat(&mut self, slot: usize) -> runtime::StrMap<'a, Float> {
-        mem::take(&mut self.slots.strfloat[slot])
+        take_slot(&mut self.slots.strfloat, slot)
             .into_iter()
             .map(|(k, v)| (k.into_str().upcast(), v))
             .collect()
     }
     pub fn load_strstr(&mut self, slot: usize) -> runtime::StrMap<'a, Str<'a>> {
-        mem::take(&mut self.slots.strstr[slot])
+        take_slot(&mut self.slots.strstr, slot)
             .into_iter()
             .map(|(k, v)| (k.into_str().upcast(), v.into_str().upcast()))
             .collect()
@@ -419,6 +593,10 @@ pub(crate) struct Interp<'a, LR: LineReader = ClassicReader> {
     num_workers: usize,
     instrs: Vec<Vec<Instr<'a>>>,
     stack: Vec<(usize /*function*/, Label /*instr*/)>,
+    // For reporting where runtime errors happen.
+    debug_info: Arc<trace::DebugInfo>,
+    // Set when running under the debugger or the profiler.
+    monitor: Option<Box<dyn debug::Monitor>>,
 
     line: LR::Line,
     read_files: runtime::FileRead<LR>,
@@ -469,6 +647,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             num_workers,
             instrs,
             stack: Default::default(),
+            debug_info: Default::default(),
+            monitor: None,
             floats: default_of(regs(Float)),
             ints: default_of(regs(Int)),
             strs: default_of(regs(Str)),
@@ -504,9 +684,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         })
     }
 
-    fn reset_file_vars(&mut self) {
+    fn reset_file_vars(&mut self) -> Result<()> {
         self.core.vars.fnr = 0;
         self.core.vars.filename = self.read_files.stdin_filename().upcast();
+        self.core.write_files.start_input_file(&self.core.vars.filename)
     }
 
     pub(crate) fn run_parallel(&mut self) -> Result<i32> {
@@ -569,6 +750,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                 let sender = sender.clone();
                 let core_shuttle = self.core.shuttle(i as Int + 2);
                 let instrs = self.instrs.clone();
+                let debug_info = self.debug_info.clone();
                 s.spawn(move |_| {
                     if let Some(read_files) = handle() {
                         let mut interp = Interp {
@@ -576,6 +758,8 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             num_workers: 1,
                             instrs,
                             stack: Default::default(),
+                            debug_info,
+                            monitor: None,
                             core: core_shuttle(),
                             line: Default::default(),
                             read_files,
@@ -605,7 +789,9 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
             }
             mem::drop(sender);
             self.core.vars.pid = 1;
-            let mut rc = self.run_at(main_loop)?;
+            let res = self.run_at(main_loop);
+            self.read_files.stop_waiting();
+            let mut rc = res?;
             self.core.vars.pid = 0;
             while let Ok(res) = receiver.recv() {
                 let res = res?;
@@ -629,6 +815,59 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         }
     }
 
+    pub(crate) fn set_merge_spec(&mut self, merge: MergeSpec) {
+        self.core.merge = merge;
+    }
+
+    pub(crate) fn set_debug_info(&mut self, info: trace::DebugInfo) {
+        self.debug_info = Arc::new(info);
+    }
+
+    /// Run the program under the debugger, which reads commands from `input` and writes to
+    /// `out`. `text` is the text of the program.
+    pub(crate) fn set_debugger(
+        &mut self,
+        text: &str,
+        input: Box<dyn std::io::BufRead>,
+        out: Box<dyn std::io::Write>,
+    ) {
+        let d = debug::Debugger::new(self.debug_info.clone(), text, input, out);
+        self.monitor = Some(Box::new(d));
+    }
+
+    /// Count how often each statement runs, and write the counts alongside `text`, the text of
+    /// the program, to `out` once the interpreter is dropped.
+    pub(crate) fn set_profiler(&mut self, text: &str, out: &str) {
+        let p = profile::Profiler::new(self.debug_info.clone(), text, out);
+        self.monitor = Some(Box::new(p));
+    }
+
+    // Let the monitor know that instruction `instr` of function `func` is next. Returns true if
+    // the program should stop.
+    fn monitor_stop(&mut self, func: usize, instr: usize) -> bool {
+        let mut m = match self.monitor.take() {
+            Some(m) => m,
+            None => return false,
+        };
+        let depth = self.stack.len();
+        let stop = m.at(self, func, instr, depth);
+        self.monitor = Some(m);
+        if stop {
+            self.stack.clear();
+        }
+        stop
+    }
+
+    /// Set ARGV and ARGC, for programs compiled without them.
+    pub(crate) fn set_argv(&mut self, args: impl Iterator<Item = Str<'a>>) {
+        let mut argc = 0;
+        for (i, arg) in args.enumerate() {
+            self.core.vars.argv.insert(i as Int, arg);
+            argc += 1;
+        }
+        self.core.vars.argc = argc;
+    }
+
     pub(crate) fn run_serial(&mut self) -> Result<i32> {
         let offs: smallvec::SmallVec<[usize; 3]> = self.main_func.iter().cloned().collect();
         for off in offs.into_iter() {
@@ -641,15 +880,31 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
     }
 
     pub(crate) fn run(&mut self) -> Result<i32> {
-        match self.main_func {
+        let res = match self.main_func {
             Stage::Main(_) => self.run_serial(),
             Stage::Par { .. } => self.run_parallel(),
         }
+        .and_then(|rc| runtime::errors::check().map(|()| rc));
+        if !matches!(res, Ok(0)) {
+            self.core.write_files.discard_atomic_writes();
+        }
+        res
+    }
+
+    pub(crate) fn run_at(&mut self, cur_fn: usize) -> Result<i32> {
+        let mut pos = (cur_fn, 0);
+        self.exec(&mut pos).map_err(|e| {
+            let callers = mem::take(&mut self.stack);
+            self.debug_info
+                .locate(e, pos, callers.into_iter().map(|(func, Label(ret))| (func, ret)))
+        })
     }
 
+    // Run the function `pos.0`, keeping `pos` at the instruction being executed.
     #[allow(clippy::never_loop)]
-    pub(crate) fn run_at(&mut self, mut cur_fn: usize) -> Result<i32> {
+    fn exec(&mut self, pos: &mut (usize, usize)) -> Result<i32> {
         use Instr::*;
+        let mut cur_fn = pos.0;
         let mut scratch: Vec<runtime::FormatArg> = Vec::new();
         // We are only accessing one vector at a time here, but it's hard to convince the borrow
         // checker of this fact, so we access the vectors through raw pointers.
@@ -657,6 +912,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         let mut cur = 0;
 
         'outer: loop {
+            *pos = (cur_fn, cur);
+            if self.monitor.is_some() && self.monitor_stop(cur_fn, cur) {
+                return Ok(0);
+            }
             // This somewhat ersatz structure is to allow 'cur' to be reassigned
             // in most but not all branches in the big match below.
             cur = loop {
@@ -685,6 +944,11 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let sr = *sr;
                         *self.get_mut(sr) = s;
                     }
+                    FloatToOutStr(sr, fr) => {
+                        let s = Str::from_float(*self.get(*fr), NumberFormat::Output);
+                        let sr = *sr;
+                        *self.get_mut(sr) = s;
+                    }
                     Uuid(dst, version) => {
                         let version = index(&self.strs, version);
                         let res = Str::from(runtime::math_util::uuid(version.as_str()));
@@ -738,17 +1002,88 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let ir = *dst;
                         *self.get_mut(ir) = result as Int;
                     }
+                    SystimeMs(dst) => *index_mut(&mut self.ints, dst) = runtime::date_time::systime_ms(),
+                    SystimeUs(dst) => *index_mut(&mut self.ints, dst) = runtime::date_time::systime_us(),
+                    Clock(dst) => *index_mut(&mut self.floats, dst) = runtime::date_time::clock(),
+                    Sleep(dst, secs) => {
+                        let secs = *index(&self.floats, secs);
+                        *index_mut(&mut self.ints, dst) = runtime::date_time::sleep(secs);
+                    }
                     Encode(dst, format, text) => {
                         let format = index(&self.strs, format);
                         let text = index(&self.strs, text);
-                        let dt_text = runtime::encoding::encode(format.as_str(), text.as_str());
-                        *index_mut(&mut self.strs, dst) = dt_text.into();
+                        *index_mut(&mut self.strs, dst) = match text.with_bytes(|bs| runtime::encoding::encode(format.as_str(), bs)) {
+                            Ok(encoded) => encoded.into(),
+                            Err(msg) => {
+                                runtime::errors::report(format!("encode: {}", msg))?;
+                                Str::default()
+                            }
+                        };
                     }
                     Decode(dst, format, text) => {
                         let format = index(&self.strs, format);
                         let text = index(&self.strs, text);
-                        let dt_text = runtime::encoding::decode(format.as_str(), text.as_str());
-                        *index_mut(&mut self.strs, dst) = dt_text.into();
+                        *index_mut(&mut self.strs, dst) = match runtime::encoding::decode(format.as_str(), text.as_str()) {
+                            Ok(bytes) => Str::from(&bytes[..]).unmoor().upcast(),
+                            Err(msg) => {
+                                runtime::errors::report(format!("decode: {}", msg))?;
+                                Str::default()
+                            }
+                        };
+                    }
+                    Utf8Valid(dst, text) => {
+                        let valid = index(&self.strs, text).with_bytes(runtime::utf8::is_utf8);
+                        *index_mut(&mut self.ints, dst) = valid as Int;
+                    }
+                    Utf8Fix(dst, text) => {
+                        let text = index(&self.strs, text);
+                        *index_mut(&mut self.strs, dst) = match text.with_bytes(runtime::utf8::repair) {
+                            Some(fixed) => Str::from(fixed),
+                            None => text.clone(),
+                        };
+                    }
+                    Normalize(dst, text, form) => {
+                        let res = index(&self.strs, text)
+                            .with_bytes(|bs| runtime::utf8::normalize(bs, *form));
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    Compress(dst, format, text) => {
+                        let format = index(&self.strs, format);
+                        let res = index(&self.strs, text)
+                            .with_bytes(|bs| runtime::encoding::compress(format.as_str(), bs));
+                        *index_mut(&mut self.strs, dst) = match res {
+                            Ok(bytes) => Str::from(&bytes[..]).unmoor().upcast(),
+                            Err(msg) => {
+                                runtime::errors::report(format!("compress: {}", msg))?;
+                                Str::default()
+                            }
+                        };
+                    }
+                    Decompress(dst, format, text) => {
+                        let format = index(&self.strs, format);
+                        let res = index(&self.strs, text)
+                            .with_bytes(|bs| runtime::encoding::decompress(format.as_str(), bs));
+                        *index_mut(&mut self.strs, dst) = match res {
+                            Ok(bytes) => Str::from(&bytes[..]).unmoor().upcast(),
+                            Err(msg) => {
+                                runtime::errors::report(format!("decompress: {}", msg))?;
+                                Str::default()
+                            }
+                        };
+                    }
+                    Iconv(dst, text, from, to) => {
+                        let from = index(&self.strs, from);
+                        let to = index(&self.strs, to);
+                        let res = index(&self.strs, text).with_bytes(|bs| {
+                            runtime::encoding::iconv(bs, from.as_str(), to.as_str())
+                        });
+                        *index_mut(&mut self.strs, dst) = match res {
+                            Ok(bytes) => Str::from(&bytes[..]).unmoor().upcast(),
+                            Err(msg) => {
+                                runtime::errors::report(format!("iconv: {}", msg))?;
+                                Str::default()
+                            }
+                        };
                     }
                     Digest(dst, algorithm, text) => {
                         let algorithm = index(&self.strs, algorithm);
@@ -756,6 +1091,50 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dt_text = runtime::crypto::digest(algorithm.as_str(), text.as_str());
                         *index_mut(&mut self.strs, dst) = dt_text.into();
                     }
+                    FileDigest(dst, algorithm, path) => {
+                        let algorithm = index(&self.strs, algorithm);
+                        let path = index(&self.strs, path);
+                        let res = match runtime::crypto::file_digest(algorithm.as_str(), path.as_str()) {
+                            Ok(res) => res,
+                            Err(e) => {
+                                runtime::errors::report(format!("file_digest: {}", e))?;
+                                String::new()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = res.into();
+                    }
+                    DigestInit(dst, algorithm, key) => {
+                        let algorithm = index(&self.strs, algorithm);
+                        let key = index(&self.strs, key);
+                        let handle = match self.core.digests.init(algorithm.as_str(), key.as_str()) {
+                            Ok(handle) => handle,
+                            Err(e) => {
+                                runtime::errors::report(format!("digest_init: {}", e))?;
+                                0
+                            }
+                        };
+                        *index_mut(&mut self.ints, dst) = handle;
+                    }
+                    DigestUpdate(dst, handle, text) => {
+                        let handle = *index(&self.ints, handle);
+                        let text = index(&self.strs, text);
+                        let res = text.with_bytes(|bs| self.core.digests.update(handle, bs));
+                        if let Err(e) = &res {
+                            runtime::errors::report(format!("digest_update: {}", e))?;
+                        }
+                        *index_mut(&mut self.ints, dst) = res.is_ok() as Int;
+                    }
+                    DigestFinal(dst, handle) => {
+                        let handle = *index(&self.ints, handle);
+                        let res = match self.core.digests.finish(handle) {
+                            Ok(res) => res,
+                            Err(e) => {
+                                runtime::errors::report(format!("digest_final: {}", e))?;
+                                String::new()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = res.into();
+                    }
                     Escape(dst, format, text) => {
                         let format = index(&self.strs, format);
                         let text = index(&self.strs, text);
@@ -773,16 +1152,43 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let algorithm = index(&self.strs, algorithm);
                         let key = index(&self.strs, key);
                         let payload = self.get(*payload);
-                        let token = runtime::crypto::jwt(algorithm.as_str(), key.as_str(), payload);
+                        let token = match runtime::crypto::jwt(algorithm.as_str(), key.as_str(), payload) {
+                            Ok(token) => token,
+                            Err(e) => {
+                                runtime::errors::report(format!("jwt: {}", e))?;
+                                String::new()
+                            }
+                        };
                         *index_mut(&mut self.strs, dst) = token.into();
                     }
-                    Dejwt(dst, key, token) => {
+                    Dejwt(dst, key, token, options) => {
                         let key = index(&self.strs, key);
                         let token = index(&self.strs, token);
-                        let res = runtime::crypto::dejwt(key.as_str(), token.as_str());
+                        let options = index(&self.strs, options);
+                        let res = match runtime::crypto::dejwt(key.as_str(), token.as_str(), options.as_str()) {
+                            Ok(claims) => {
+                                self.core.vars.set_errno("");
+                                claims
+                            }
+                            Err(e) => {
+                                runtime::errors::report(format!("dejwt: {}", e))?;
+                                Default::default()
+                            }
+                        };
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
+                    Cert(dst, source) => {
+                        let source = index(&self.strs, source);
+                        let res = match runtime::crypto::cert(source.as_str()) {
+                            Ok(info) => info,
+                            Err(e) => {
+                                runtime::errors::report(format!("cert: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
                     Encrypt(dst, mode, plain_text, key) => {
                         let mode = index(&self.strs, mode);
                         let plain_text = index(&self.strs, plain_text);
@@ -797,16 +1203,23 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let plain_text = runtime::crypto::decrypt(mode.as_str(), encrypted_text.as_str(), key.as_str());
                         *index_mut(&mut self.strs, dst) = plain_text.into();
                     }
-                    Strftime(dst, format, timestamp) => {
-                        let format = index(&self.strs, format);
+                    Strftime(dst, format, timestamp, timezone) => {
+                        let mut format = index(&self.strs, format).to_string();
+                        if format.is_empty() {
+                            format = self.core.vars.procinfo.get(&Str::from("strftime")).to_string();
+                        }
+                        if format.is_empty() {
+                            format = runtime::date_time::DEFAULT_FORMAT.to_string();
+                        }
                         let tt: i64 = *self.get(*timestamp);
-                        let dt_text = runtime::date_time::strftime(format.as_str(), tt);
+                        let timezone = index(&self.strs, timezone);
+                        let dt_text = runtime::date_time::strftime(&format, tt, timezone.as_str());
                         *index_mut(&mut self.strs, dst) = dt_text.into();
                     }
                     Mktime(dst, date_time_text, timezone) => {
                         let dt_text = index(&self.strs, date_time_text);
-                        let dt_timezone: i64 = *self.get(*timezone);
-                        let result = runtime::date_time::mktime(dt_text.as_str(), dt_timezone);
+                        let dt_timezone = index(&self.strs, timezone);
+                        let result = runtime::date_time::mktime(dt_text.as_str(), dt_timezone.as_str());
                         let ir = *dst;
                         *self.get_mut(ir) = result as Int;
                     }
@@ -831,6 +1244,34 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
+                    UaParse(dst, src) => {
+                        let res = runtime::string_util::ua_parse(index(&self.strs, src).as_str());
+                        let dst = *dst;
+                        *self.get_mut(dst) = res;
+                    }
+                    Logfmt(dst, src) => {
+                        let res = runtime::string_util::logfmt(index(&self.strs, src).as_str());
+                        let dst = *dst;
+                        *self.get_mut(dst) = res;
+                    }
+                    MapStrIntToLogfmt(dst, arr, order) => {
+                        let arr = self.get(*arr);
+                        let order = index(&self.strs, order);
+                        let res = Str::from(runtime::string_util::to_logfmt(arr, order.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrFloatToLogfmt(dst, arr, order) => {
+                        let arr = self.get(*arr);
+                        let order = index(&self.strs, order);
+                        let res = Str::from(runtime::string_util::to_logfmt(arr, order.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrStrToLogfmt(dst, arr, order) => {
+                        let arr = self.get(*arr);
+                        let order = index(&self.strs, order);
+                        let res = Str::from(runtime::string_util::to_logfmt(arr, order.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
                     Pairs(dst, src, pair_sep, kv_sep) => {
                         let src = index(&self.strs, src);
                         let pair_sep = index(&self.strs, pair_sep);
@@ -889,6 +1330,42 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = result;
                     }
+                    DateParse(dst, text, format) => {
+                        let text = index(&self.strs, text);
+                        let format = index(&self.strs, format);
+                        let res = runtime::date_time::dateparse(text.as_str(), format.as_str());
+                        *index_mut(&mut self.ints, dst) = match res {
+                            Ok(epoch) => epoch,
+                            Err(e) => {
+                                runtime::errors::report(e)?;
+                                -1
+                            }
+                        };
+                    }
+                    DateAdd(dst, epoch, n, unit) => {
+                        let (epoch, n) = (*index(&self.ints, epoch), *index(&self.ints, n));
+                        let unit = index(&self.strs, unit);
+                        let res = runtime::date_time::dateadd(epoch, n, unit.as_str());
+                        *index_mut(&mut self.ints, dst) = match res {
+                            Ok(epoch) => epoch,
+                            Err(e) => {
+                                runtime::errors::report(e)?;
+                                -1
+                            }
+                        };
+                    }
+                    DateDiff(dst, a, b, unit) => {
+                        let (a, b) = (*index(&self.ints, a), *index(&self.ints, b));
+                        let unit = index(&self.strs, unit);
+                        let res = runtime::date_time::datediff(a, b, unit.as_str());
+                        *index_mut(&mut self.ints, dst) = match res {
+                            Ok(n) => n,
+                            Err(e) => {
+                                runtime::errors::report(e)?;
+                                -1
+                            }
+                        };
+                    }
                     Shlex(dst, text) => {
                         let text = index(&self.strs, text);
                         let res = runtime::math_util::shlex(text.as_str());
@@ -957,15 +1434,27 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     S3Get(dst, bucket, object_name) => {
                         let bucket = index(&self.strs, bucket);
                         let object_name = index(&self.strs, object_name);
-                        let body = runtime::s3::get_object(bucket.as_str(), object_name.as_str()).unwrap();
-                        *index_mut(&mut self.strs, dst) = Str::from(body);
+                        let body = match runtime::s3::get_object(bucket.as_str(), object_name.as_str()) {
+                            Ok(body) => Str::from(body),
+                            Err(e) => {
+                                runtime::errors::report(format!("s3_get: {}", e))?;
+                                Str::default()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = body;
                     }
                     S3Put(dst, bucket, object_name, body) => {
                         let bucket = index(&self.strs, bucket);
                         let object_name = index(&self.strs, object_name);
                         let body = index(&self.strs, body);
-                        let etag = runtime::s3::put_object(bucket.as_str(), object_name.as_str(), body.as_str()).unwrap().etag;
-                        *index_mut(&mut self.strs, dst) = Str::from(etag);
+                        let etag = match runtime::s3::put_object(bucket.as_str(), object_name.as_str(), body.as_str()) {
+                            Ok(resp) => Str::from(resp.etag),
+                            Err(e) => {
+                                runtime::errors::report(format!("s3_put: {}", e))?;
+                                Str::default()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = etag;
                     }
                     FromJson(dst, src) => {
                         let src = index(&self.strs, src);
@@ -973,6 +1462,140 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
+                    FromToml(dst, src) => {
+                        let src = index(&self.strs, src);
+                        let res = match runtime::toml::from_toml(src.as_str()) {
+                            Ok(map) => map,
+                            Err(e) => {
+                                runtime::errors::report(format!("from_toml: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
+                    MapStrIntToToml(dst, arr, like) => {
+                        let like = index(&self.strs, like);
+                        let res = runtime::toml::to_toml(self.get(*arr), like.as_str());
+                        *index_mut(&mut self.strs, dst) = report_to_toml(res)?;
+                    }
+                    MapStrFloatToToml(dst, arr, like) => {
+                        let like = index(&self.strs, like);
+                        let res = runtime::toml::to_toml(self.get(*arr), like.as_str());
+                        *index_mut(&mut self.strs, dst) = report_to_toml(res)?;
+                    }
+                    MapStrStrToToml(dst, arr, like) => {
+                        let like = index(&self.strs, like);
+                        let res = runtime::toml::to_toml(self.get(*arr), like.as_str());
+                        *index_mut(&mut self.strs, dst) = report_to_toml(res)?;
+                    }
+                    TomlValue(dst, text, path) => {
+                        let text = index(&self.strs, text);
+                        let path = index(&self.strs, path);
+                        let res = match runtime::toml::toml_value(text.as_str(), path.as_str()) {
+                            Ok(value) => value,
+                            Err(e) => {
+                                runtime::errors::report(format!("toml_value: {}", e))?;
+                                String::new()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    FromIni(dst, src) => {
+                        let res = runtime::config_util::from_ini(index(&self.strs, src).as_str());
+                        let dst = *dst;
+                        *self.get_mut(dst) = res;
+                    }
+                    MapStrIntToIni(dst, arr) => {
+                        let res = Str::from(runtime::config_util::to_ini(self.get(*arr)));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrFloatToIni(dst, arr) => {
+                        let res = Str::from(runtime::config_util::to_ini(self.get(*arr)));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrStrToIni(dst, arr) => {
+                        let res = Str::from(runtime::config_util::to_ini(self.get(*arr)));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    PropGet(dst, text, key) => {
+                        let text = index(&self.strs, text);
+                        let key = index(&self.strs, key);
+                        let res = Str::from(runtime::config_util::prop_get(text.as_str(), key.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrIntToXml(dst, arr, root) => {
+                        let root = index(&self.strs, root);
+                        let res = Str::from(runtime::xml::to_xml(self.get(*arr), root.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrFloatToXml(dst, arr, root) => {
+                        let root = index(&self.strs, root);
+                        let res = Str::from(runtime::xml::to_xml(self.get(*arr), root.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    MapStrStrToXml(dst, arr, root) => {
+                        let root = index(&self.strs, root);
+                        let res = Str::from(runtime::xml::to_xml(self.get(*arr), root.as_str()));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    XmlUpdate(dst, text, xpath, value) => {
+                        let text = index(&self.strs, text);
+                        let xpath = index(&self.strs, xpath);
+                        let value = index(&self.strs, value);
+                        let res = match runtime::xml::xml_update(text.as_str(), xpath.as_str(), value.as_str()) {
+                            Ok(updated) => Str::from(updated),
+                            Err(e) => {
+                                runtime::errors::report(format!("xml_update: {}", e))?;
+                                text.clone()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    HtmlTable(dst, html, idx) => {
+                        let html = index(&self.strs, html);
+                        let idx = *index(&self.ints, idx);
+                        let res = runtime::html::html_table(html.as_str(), idx);
+                        let dst = *dst;
+                        *self.get_mut(dst) = res;
+                    }
+                    FromMsgpack(dst, src) => {
+                        let src = index(&self.strs, src);
+                        let res = match src.with_bytes(runtime::binfmt::from_msgpack) {
+                            Ok(map) => map,
+                            Err(e) => {
+                                runtime::errors::report(format!("from_msgpack: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
+                    FromCbor(dst, src) => {
+                        let src = index(&self.strs, src);
+                        let res = match src.with_bytes(runtime::binfmt::from_cbor) {
+                            Ok(map) => map,
+                            Err(e) => {
+                                runtime::errors::report(format!("from_cbor: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
+                    FromProtobuf(dst, src, descriptors, message) => {
+                        let src = index(&self.strs, src);
+                        let descriptors = index(&self.strs, descriptors);
+                        let message = index(&self.strs, message);
+                        let res = src.with_bytes(|bytes| {
+                            runtime::binfmt::from_protobuf(bytes, descriptors.as_str(), message.as_str())
+                        });
+                        let res = match res {
+                            Ok(map) => map,
+                            Err(e) => {
+                                runtime::errors::report(format!("from_protobuf: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
                     MapIntIntToJson(dst, arr) => {
                         let arr = self.get(*arr);
                         let dst = *dst;
@@ -1061,26 +1684,20 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     DumpNull() => {
                         eprintln!("Null");
                     }
-                    MapIntIntAsort(dst, arr, target) => {
-                        let arr = self.get(*arr);
-                        let target = self.get(*target);
-                        runtime::math_util::map_int_int_asort(arr, target);
-                        let dst = *dst;
-                        *self.get_mut(dst) = arr.len() as Int;
+                    MapIntIntAsort(dst, arr, target, order) => {
+                        let order = self.sort_order(order)?;
+                        let res = runtime::math_util::asort(self.get(*arr), self.get(*target), order);
+                        *self.get_mut(*dst) = res;
                     }
-                    MapIntFloatAsort(dst, arr, target) => {
-                        let arr = self.get(*arr);
-                        let target = self.get(*target);
-                        runtime::math_util::map_int_float_asort(arr, target);
-                        let dst = *dst;
-                        *self.get_mut(dst) = arr.len() as Int;
+                    MapIntFloatAsort(dst, arr, target, order) => {
+                        let order = self.sort_order(order)?;
+                        let res = runtime::math_util::asort(self.get(*arr), self.get(*target), order);
+                        *self.get_mut(*dst) = res;
                     }
-                    MapIntStrAsort(dst, arr, target) => {
-                        let arr = self.get(*arr);
-                        let target = self.get(*target);
-                        runtime::math_util::map_int_str_asort(arr, target);
-                        let dst = *dst;
-                        *self.get_mut(dst) = arr.len() as Int;
+                    MapIntStrAsort(dst, arr, target, order) => {
+                        let order = self.sort_order(order)?;
+                        let res = runtime::math_util::asort(self.get(*arr), self.get(*target), order);
+                        *self.get_mut(*dst) = res;
                     }
                     MapIntIntJoin(dst, arr, sep) => {
                         let arr = self.get(*arr);
@@ -1192,13 +1809,77 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     }
                     ReadAll(dst, path) => {
                         let path = index(&self.strs, path);
-                        let value = runtime::string_util::read_all(path.as_str());
-                        *index_mut(&mut self.strs, dst) = Str::from(value);
+                        let value = match runtime::string_util::read_all(path.as_str()) {
+                            Ok(bytes) => Str::from(&bytes[..]).unmoor().upcast(),
+                            Err(e) => {
+                                runtime::errors::report(format!("read_all: {}", e))?;
+                                Str::default()
+                            }
+                        };
+                        *index_mut(&mut self.strs, dst) = value;
+                    }
+                    ReadLines(dst, path) => {
+                        let path = index(&self.strs, path);
+                        let lines = match runtime::string_util::read_lines(path.as_str()) {
+                            Ok(lines) => lines,
+                            Err(e) => {
+                                runtime::errors::report(format!("read_lines: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = lines;
                     }
                     WriteAll(path, content) => {
                         let path = index(&self.strs, path);
                         let content = index(&self.strs, content);
-                        runtime::string_util::write_all(path.as_str(), content.as_str());
+                        runtime::string_util::write_str(path, content, false)?;
+                    }
+                    AppendAll(path, content) => {
+                        let path = index(&self.strs, path);
+                        let content = index(&self.strs, content);
+                        runtime::string_util::write_str(path, content, true)?;
+                    }
+                    Glob(dst, pattern) => {
+                        let pattern = index(&self.strs, pattern);
+                        let paths = match runtime::os_util::glob(pattern.as_str()) {
+                            Ok(paths) => paths,
+                            Err(e) => {
+                                runtime::errors::report(format!("glob: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = paths;
+                    }
+                    Stat(dst, path) => {
+                        let path = index(&self.strs, path);
+                        let info = match runtime::os_util::stat(path.as_str()) {
+                            Ok(info) => info,
+                            Err(e) => {
+                                runtime::errors::report(format!("stat: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = info;
+                    }
+                    Exists(dst, path) => {
+                        let path = index(&self.strs, path);
+                        *index_mut(&mut self.ints, dst) = runtime::os_util::exists(path.as_str()) as Int;
+                    }
+                    Mkdirp(dst, path) => {
+                        let path = index(&self.strs, path);
+                        let res = runtime::os_util::mkdirp(path.as_str());
+                        if let Err(e) = &res {
+                            runtime::errors::report(format!("mkdirp: {}", e))?;
+                        }
+                        *index_mut(&mut self.ints, dst) = res.is_ok() as Int;
+                    }
+                    RmFile(dst, path) => {
+                        let path = index(&self.strs, path);
+                        let res = runtime::os_util::rmfile(path.as_str());
+                        if let Err(e) = &res {
+                            runtime::errors::report(format!("rmfile: {}", e))?;
+                        }
+                        *index_mut(&mut self.ints, dst) = res.is_ok() as Int;
                     }
                     LogDebug(message) => {
                         let file_name = &self.core.vars.filename;
@@ -1256,19 +1937,19 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     BloomFilterInsert(item, group) => {
                         let item = index(&self.strs, item);
                         let group = index(&self.strs, group);
-                        runtime::encoding::bf_insert(item.as_str(), group.as_str());
+                        item.with_bytes(|bs| self.core.sketches.bf_insert(bs, group.as_str()));
                     }
                     BloomFilterContains(dst, item, group) => {
                         let item = index(&self.strs, item);
                         let group = index(&self.strs, group);
-                        let res = runtime::encoding::bf_icontains(item.as_str(), group.as_str());
+                        let res = item.with_bytes(|bs| self.core.sketches.bf_contains(bs, group.as_str()));
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
                     BloomFilterContainsWithInsert(dst, item, group) => {
                         let item = index(&self.strs, item);
                         let group = index(&self.strs, group);
-                        let res = runtime::encoding::bf_contains(item.as_str(), group.as_str());
+                        let res = item.with_bytes(|bs| self.core.sketches.bf_icontains(bs, group.as_str()));
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
@@ -1292,6 +1973,97 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = runtime::math_util::max(num1.as_str(), num2.as_str(), num3.as_str());
                         *index_mut(&mut self.strs, dst) = Str::from(res);
                     }
+                    DecAdd(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::decimal::add(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    DecSub(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::decimal::sub(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    DecMul(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::decimal::mul(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    DecDiv(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::decimal::div(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    DecCmp(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        *index_mut(&mut self.ints, dst) = runtime::decimal::cmp(x.as_str(), y.as_str());
+                    }
+                    DecFmt(dst, x, scale) => {
+                        let scale: Int = *self.get(*scale);
+                        let res = runtime::decimal::fmt(index(&self.strs, x).as_str(), scale);
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigAdd(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::add(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigSub(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::sub(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigMul(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::mul(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigDiv(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::div(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigMod(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::rem(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigPow(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::bignum::pow(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    BigCmp(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        *index_mut(&mut self.ints, dst) = runtime::bignum::cmp(x.as_str(), y.as_str());
+                    }
+                    U64Add(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::math_util::u64_add(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    U64Mul(dst, x, y) => {
+                        let x = index(&self.strs, x);
+                        let y = index(&self.strs, y);
+                        let res = runtime::math_util::u64_mul(x.as_str(), y.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    U64Str(dst, x) => {
+                        let res = runtime::math_util::u64_str(*index(&self.ints, x));
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
                     Seq(dst, start, step, end) => {
                         let start: Float = *self.get(*start);
                         let step: Float = *self.get(*step);
@@ -1326,11 +2098,24 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = num;
                     }
+                    ParseNumber(dst, text, locale) => {
+                        let text = index(&self.strs, text);
+                        let locale = index(&self.strs, locale);
+                        let num = runtime::locale::parse_number(text.as_str(), locale.as_str());
+                        *index_mut(&mut self.floats, dst) = num;
+                    }
                     FormatBytes(dst, size) => {
                         let size = *self.get(*size);
                         let text = runtime::math_util::format_bytes(size);
                         *index_mut(&mut self.strs, dst) = Str::from(text);
                     }
+                    FormatNum(dst, x, pattern, locale) => {
+                        let x = *self.get(*x);
+                        let pattern = index(&self.strs, pattern);
+                        let locale = index(&self.strs, locale);
+                        let text = runtime::locale::format_num(x, pattern.as_str(), locale.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(text);
+                    }
                     ToBytes(dst, text) => {
                         let text = index(&self.strs, text);
                         let size = runtime::math_util::to_bytes(text.as_str());
@@ -1425,6 +2210,45 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = res;
                     }
+                    NatCmp(dst, text1, text2) => {
+                        let text1 = index(&self.strs, text1);
+                        let text2 = index(&self.strs, text2);
+                        let res = runtime::string_util::natcmp(text1.as_str(), text2.as_str());
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    Levenshtein(dst, text1, text2) => {
+                        let text1 = index(&self.strs, text1);
+                        let text2 = index(&self.strs, text2);
+                        let res = runtime::string_util::levenshtein(text1.as_str(), text2.as_str());
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    Similarity(dst, text1, text2) => {
+                        let text1 = index(&self.strs, text1);
+                        let text2 = index(&self.strs, text2);
+                        let res = runtime::string_util::similarity(text1.as_str(), text2.as_str());
+                        *index_mut(&mut self.floats, dst) = res;
+                    }
+                    Diff(dst, text1, text2) => {
+                        let text1 = index(&self.strs, text1);
+                        let text2 = index(&self.strs, text2);
+                        let res = runtime::string_util::diff(text1.as_str(), text2.as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    Soundex(dst, text) => {
+                        let res = runtime::string_util::soundex(index(&self.strs, text).as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    Metaphone(dst, text) => {
+                        let res = runtime::string_util::metaphone(index(&self.strs, text).as_str());
+                        *index_mut(&mut self.strs, dst) = Str::from(res);
+                    }
+                    FuzzyMatch(dst, text1, text2, threshold) => {
+                        let threshold: Float = *self.get(*threshold);
+                        let text1 = index(&self.strs, text1);
+                        let text2 = index(&self.strs, text2);
+                        let res = runtime::string_util::fuzzy_match(text1.as_str(), text2.as_str(), threshold);
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
                     Mask(dst, text) => {
                         let dt_text = index(&self.strs, text).mask();
                         *index_mut(&mut self.strs, dst) = dt_text;
@@ -1526,12 +2350,57 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             *self.get_mut(dst) = 0;
                         }
                     }
+                    IsNan(dst, x) => {
+                        let res = index(&self.floats, x).is_nan() as Int;
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    IsInf(dst, x) => {
+                        let res = index(&self.floats, x).is_infinite() as Int;
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
                     IsFormat(dst, format, text) => {
                         let format = index(&self.strs, format);
                         let text = index(&self.strs, text);
                         let dst = *dst;
                         *self.get_mut(dst) = runtime::string_util::is_format(format.as_str(), text.as_str());
                     }
+                    CidrContains(dst, net, addr) => {
+                        let net = index(&self.strs, net);
+                        let addr = index(&self.strs, addr);
+                        let dst = *dst;
+                        *self.get_mut(dst) = runtime::ip::cidr_contains(net.as_str(), addr.as_str());
+                    }
+                    Ip2Int(dst, ip) => {
+                        let res = runtime::ip::ip_to_int(index(&self.strs, ip).as_str());
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    Int2Ip(dst, n) => {
+                        let res = Str::from(runtime::ip::int_to_ip(*index(&self.ints, n)));
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
+                    IpVersion(dst, ip) => {
+                        let res = runtime::ip::ip_version(index(&self.strs, ip).as_str());
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    Geoip(dst, ip) => {
+                        let ip = index(&self.strs, ip);
+                        let res = match runtime::geoip::geoip(ip.as_str()) {
+                            Ok(info) => info,
+                            Err(e) => {
+                                runtime::errors::report(format!("geoip: {}", e))?;
+                                Default::default()
+                            }
+                        };
+                        *self.get_mut(*dst) = res;
+                    }
+                    GeoipDb(dst, path) => {
+                        let path = index(&self.strs, path);
+                        let res = runtime::geoip::geoip_db(path.as_str());
+                        if let Err(e) = &res {
+                            runtime::errors::report(format!("geoip_db: {}", e))?;
+                        }
+                        *index_mut(&mut self.ints, dst) = res.is_ok() as Int;
+                    }
                     StrToInt(ir, sr) => {
                         let sr = index(&self.strs, sr);
                         let num = runtime::math_util::strtoint(sr.as_str());
@@ -1599,7 +2468,10 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = *self.get(*l);
                         let r = *self.get(*r);
-                        *self.get_mut(res) = l % r;
+                        if r == 0 {
+                            return err!("division by zero in %");
+                        }
+                        *self.get_mut(res) = l.wrapping_rem(r);
                     }
                     ModFloat(res, l, r) => {
                         let res = *res;
@@ -1627,8 +2499,12 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     NotStr(res, sr) => {
                         let res = *res;
                         let sr = *sr;
-                        let is_empty = self.get(sr).with_bytes(|bs| bs.is_empty());
-                        *self.get_mut(res) = is_empty as Int;
+                        let is_false = if runtime::bignum::enabled() {
+                            runtime::bignum::is_false(self.get(sr).as_str())
+                        } else {
+                            self.get(sr).with_bytes(|bs| bs.is_empty())
+                        };
+                        *self.get_mut(res) = is_false as Int;
                     }
                     NegInt(res, ir) => {
                         let res = *res;
@@ -1662,6 +2538,24 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let dst = *dst;
                         *self.get_mut(dst) = bw.eval2(ix, iy);
                     }
+                    SafeInt(op, dst, x, y) => {
+                        let ix = *index(&self.ints, x);
+                        let iy = *index(&self.ints, y);
+                        let (res, err) = runtime::safe_math::int(*op, ix, iy);
+                        if let Some(msg) = err {
+                            self.core.vars.set_errno(msg);
+                        }
+                        *index_mut(&mut self.ints, dst) = res;
+                    }
+                    SafeFloat(op, dst, x, y) => {
+                        let fx = *index(&self.floats, x);
+                        let fy = *index(&self.floats, y);
+                        let (res, err) = runtime::safe_math::float(*op, fx, fy);
+                        if let Some(msg) = err {
+                            self.core.vars.set_errno(msg);
+                        }
+                        *index_mut(&mut self.floats, dst) = res;
+                    }
                     Rand(dst) => {
                         let res: f64 = self.core.rng.gen_range(0.0..=1.0);
                         *index_mut(&mut self.floats, dst) = res;
@@ -1673,10 +2567,14 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     ReseedRng(res) => {
                         *index_mut(&mut self.ints, res) = self.core.reseed_random() as Int;
                     }
+                    RandInt(res, lo, hi) => {
+                        let (lo, hi) = (*index(&self.ints, lo), *index(&self.ints, hi));
+                        *index_mut(&mut self.ints, res) = runtime::math_util::rand_int(&mut self.core.rng, lo, hi);
+                    }
                     StartsWithConst(res, s, bs) => {
                         let s_bytes = unsafe { &*index(&self.strs, s).get_bytes() };
                         *index_mut(&mut self.ints, res) =
-                            (bs.len() <= s_bytes.len() && s_bytes[..bs.len()] == **bs) as Int;
+                            runtime::str_impl::starts_with(s_bytes, bs) as Int;
                     }
                     Concat(res, l, r) => {
                         let res = *res;
@@ -1689,6 +2587,13 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             .core
                             .match_regex(index(&self.strs, l), index(&self.strs, r))?;
                     }
+                    MatchArr(res, l, r, arr) => {
+                        *index_mut(&mut self.ints, res) = self.core.match_regex_arr(
+                            index(&self.strs, l),
+                            index(&self.strs, r),
+                            index(&self.maps_str_str, arr),
+                        )?;
+                    }
                     IsMatch(res, l, r) => {
                         *index_mut(&mut self.ints, res) = self
                             .core
@@ -1748,6 +2653,22 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         *index_mut(&mut self.strs, in_s) = subbed;
                         *index_mut(&mut self.ints, res) = subs_made;
                     }
+                    SubConst(res, pat, s, in_s) => {
+                        let (subbed, new) = {
+                            let s = index(&self.strs, s);
+                            index(&self.strs, in_s).subst_first(pat, s)
+                        };
+                        *index_mut(&mut self.strs, in_s) = subbed;
+                        *index_mut(&mut self.ints, res) = new as Int;
+                    }
+                    GSubConst(res, pat, s, in_s) => {
+                        let (subbed, subs_made) = {
+                            let s = index(&self.strs, s);
+                            index(&self.strs, in_s).subst_all(pat, s)
+                        };
+                        *index_mut(&mut self.strs, in_s) = subbed;
+                        *index_mut(&mut self.ints, res) = subs_made;
+                    }
                     GenSubDynamic(res, pat, s, how, in_s) => {
                         let subbed = {
                             let pat = index(&self.strs, pat);
@@ -1781,13 +2702,13 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     }
                     CharAt(dst, text, index) => {
                         let index = *self.get(*index);
-                        if index <= 0 {
-                            panic!("invalid index for chat_at: {}, should start with 1", index)
+                        *index_mut(&mut self.strs, dst) = if index <= 0 {
+                            runtime::errors::report(runtime::str_impl::char_at_error(index))?;
+                            Str::default()
                         } else {
                             let text = self.get(*text);
-                            let index = (index - 1) as usize;
-                            *index_mut(&mut self.strs, dst) = text.char_at(index);
-                        }
+                            text.char_at((index - 1) as usize)
+                        };
                     }
                     LastPart(res, s, sep) => {
                         let s = self.get(*s);
@@ -1811,7 +2732,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = self.get(*l);
                         let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l < r)) as Int;
+                        *self.get_mut(res) = l.compare(r).is_lt() as Int;
                     }
                     GTFloat(res, l, r) => {
                         let res = *res;
@@ -1829,7 +2750,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = self.get(*l);
                         let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l > r)) as Int;
+                        *self.get_mut(res) = l.compare(r).is_gt() as Int;
                     }
                     LTEFloat(res, l, r) => {
                         let res = *res;
@@ -1847,7 +2768,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = self.get(*l);
                         let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l <= r)) as Int;
+                        *self.get_mut(res) = l.compare(r).is_le() as Int;
                     }
                     GTEFloat(res, l, r) => {
                         let res = *res;
@@ -1865,7 +2786,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = self.get(*l);
                         let r = self.get(*r);
-                        *self.get_mut(res) = l.with_bytes(|l| r.with_bytes(|r| l >= r)) as Int;
+                        *self.get_mut(res) = l.compare(r).is_ge() as Int;
                     }
                     EQFloat(res, l, r) => {
                         let res = *res;
@@ -1883,7 +2804,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let res = *res;
                         let l = self.get(*l);
                         let r = self.get(*r);
-                        *self.get_mut(res) = (l == r) as Int;
+                        *self.get_mut(res) = l.compare(r).is_eq() as Int;
                     }
                     SetColumn(dst, src) => {
                         let col = *self.get(*dst);
@@ -1931,12 +2852,25 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             self.line.join_cols(start, end, sep, nf, |s| s)?
                         };
                     }
+                    SelectColumns(dst, spec, sep, esc) => {
+                        let res = runtime::splitter::select_cols(
+                            &mut self.line,
+                            index(&self.strs, spec),
+                            index(&self.strs, sep),
+                            &self.core.vars.fi,
+                            &self.core.vars.fs,
+                            &self.core.vars.ofs,
+                            &mut self.core.regexes,
+                            *index(&self.ints, esc),
+                        )?;
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
                     ToUpperAscii(dst, src) => {
-                        let res = index(&self.strs, src).to_upper_ascii();
+                        let res = index(&self.strs, src).to_upper();
                         *index_mut(&mut self.strs, dst) = res;
                     }
                     ToLowerAscii(dst, src) => {
-                        let res = index(&self.strs, src).to_lower_ascii();
+                        let res = index(&self.strs, src).to_lower();
                         *index_mut(&mut self.strs, dst) = res;
                     }
                     SplitInt(flds, to_split, arr, pat) => {
@@ -1959,6 +2893,32 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let flds = *flds;
                         *self.get_mut(flds) = res;
                     }
+                    instr @ (SplitSepsInt(flds, to_split, arr, pat, seps)
+                    | PatSplitInt(flds, to_split, arr, pat, seps)) => {
+                        let patsplit = matches!(instr, PatSplitInt(..));
+                        let res = self.core.split_seps(
+                            patsplit,
+                            index(&self.strs, to_split),
+                            index(&self.strs, pat),
+                            index(&self.maps_int_str, arr),
+                            index(&self.maps_int_str, seps),
+                            |i| i,
+                        )?;
+                        *index_mut(&mut self.ints, flds) = res;
+                    }
+                    instr @ (SplitSepsStr(flds, to_split, arr, pat, seps)
+                    | PatSplitStr(flds, to_split, arr, pat, seps)) => {
+                        let patsplit = matches!(instr, PatSplitStr(..));
+                        let res = self.core.split_seps(
+                            patsplit,
+                            index(&self.strs, to_split),
+                            index(&self.strs, pat),
+                            index(&self.maps_str_str, arr),
+                            index(&self.maps_str_str, seps),
+                            runtime::convert::<Int, Str>,
+                        )?;
+                        *index_mut(&mut self.ints, flds) = res;
+                    }
                     Sprintf { dst, fmt, args } => {
                         debug_assert_eq!(scratch.len(), 0);
                         for a in args.iter() {
@@ -1975,6 +2935,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         *self.get_mut(dst) = res;
                     }
                     PrintAll { output, args } => {
+                        runtime::errors::check()?;
                         let mut scratch_strs =
                             smallvec::SmallVec::<[&Str; 4]>::with_capacity(args.len());
                         for a in args {
@@ -1993,6 +2954,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         }
                     }
                     Printf { output, fmt, args } => {
+                        runtime::errors::check()?;
                         debug_assert_eq!(scratch.len(), 0);
                         for a in args.iter() {
                             scratch.push(self.format_arg(*a)?);
@@ -2022,11 +2984,48 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         self.core.write_files.close(file)?;
                         self.read_files.close(file);
                     }
+                    FFlush(dst, file) => {
+                        let file = index(&self.strs, file);
+                        *index_mut(&mut self.ints, dst) = self.core.write_files.fflush(file);
+                    }
                     RunCmd(dst, cmd) => {
                         *index_mut(&mut self.ints, dst) =
                             index(&self.strs, cmd).with_bytes(runtime::run_command);
                     }
+                    ExecOutput(dst, cmd) => {
+                        let out = index(&self.strs, cmd).with_bytes(runtime::exec_output);
+                        *index_mut(&mut self.strs, dst) = Str::from(out);
+                    }
+                    ExecOutputArgv(dst, argv) => {
+                        let out = runtime::exec_output_argv(self.get(*argv));
+                        *index_mut(&mut self.strs, dst) = Str::from(out);
+                    }
+                    Spawn(dst, cmd) => {
+                        let handle = index(&self.strs, cmd).with_bytes(runtime::spawn);
+                        *index_mut(&mut self.strs, dst) = Str::from(handle);
+                    }
+                    SpawnArgv(dst, argv) => {
+                        let handle = runtime::spawn_argv(self.get(*argv));
+                        *index_mut(&mut self.strs, dst) = Str::from(handle);
+                    }
+                    SetEnv(dst, name, value) => {
+                        let name = index(&self.strs, name);
+                        let value = index(&self.strs, value);
+                        *index_mut(&mut self.ints, dst) = self.core.vars.setenv(name, value);
+                    }
+                    UnsetEnv(dst, name) => {
+                        let name = index(&self.strs, name);
+                        *index_mut(&mut self.ints, dst) = self.core.vars.unsetenv(name);
+                    }
                     Exit(code) => return Ok(*index(&self.ints, code) as i32),
+                    CallExt(dst, ext, args) => {
+                        let res = {
+                            let args: smallvec::SmallVec<[&Str; 4]> =
+                                args.iter().map(|a| index(&self.strs, a)).collect();
+                            runtime::ext::call_strs(*ext, &args)?
+                        };
+                        *index_mut(&mut self.strs, dst) = res;
+                    }
                     Lookup {
                         map_ty,
                         dst,
@@ -2042,6 +3041,124 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                     Delete { map_ty, map, key } => self.delete(*map_ty, *map, *key),
                     Clear { map_ty, map } => self.clear(*map_ty, *map),
                     Len { map_ty, map, dst } => self.len(*map_ty, *map, *dst),
+                    Keys { map_ty, dst, map } => {
+                        let map = *map;
+                        map_regs!(*map_ty, map, {
+                            let res = runtime::math_util::map_keys(self.get(map));
+                            *self.get_mut(Reg::from(*dst)) = res;
+                        })
+                    }
+                    Values { map_ty, dst, map } => {
+                        let map = *map;
+                        map_regs!(*map_ty, map, {
+                            let res = runtime::math_util::map_values(self.get(map));
+                            *self.get_mut(Reg::from(*dst)) = res;
+                        })
+                    }
+                    SortKeys {
+                        map_ty,
+                        dst,
+                        map,
+                        order,
+                        by_value,
+                    } => {
+                        use runtime::math_util::{sort_by_value, sorted_keys};
+                        let order = self.sort_order(order)?;
+                        let map = *map;
+                        map_regs!(*map_ty, map, {
+                            let res = if *by_value {
+                                sort_by_value(self.get(map), order)
+                            } else {
+                                sorted_keys(self.get(map), order)
+                            };
+                            *self.get_mut(Reg::from(*dst)) = res;
+                        })
+                    }
+                    Invert { map_ty, dst, map } => self.invert(*map_ty, *map, *dst),
+                    Merge {
+                        map_ty,
+                        dst,
+                        left,
+                        right,
+                    } => {
+                        let left = *left;
+                        map_regs!(*map_ty, left, {
+                            let right = Reg::from(*right);
+                            let res = runtime::math_util::map_merge(self.get(left), self.get(right));
+                            *self.get_mut(Reg::from(*dst)) = res;
+                        })
+                    }
+                    CopyMap {
+                        map_ty,
+                        dst,
+                        to,
+                        from,
+                    } => {
+                        let to = *to;
+                        let res = map_regs!(*map_ty, to, {
+                            let from = Reg::from(*from);
+                            runtime::math_util::map_copy(self.get(to), self.get(from))
+                        });
+                        *index_mut(&mut self.ints, &Reg::from(*dst)) = res;
+                    }
+                    EqualMaps {
+                        map_ty,
+                        dst,
+                        left,
+                        right,
+                    } => {
+                        let left = *left;
+                        let res = map_regs!(*map_ty, left, {
+                            let right = Reg::from(*right);
+                            runtime::math_util::map_equals(self.get(left), self.get(right))
+                        });
+                        *index_mut(&mut self.ints, &Reg::from(*dst)) = res as Int;
+                    }
+                    Choice { map_ty, dst, map } => {
+                        let (map, dst, _k) = (*map, *dst, 0u32);
+                        map_regs!(*map_ty, map, _k, dst, {
+                            let map = self.get(map).clone();
+                            let res = runtime::math_util::map_choice(&mut self.core.rng, &map);
+                            *self.get_mut(dst) = res;
+                        })
+                    }
+                    Sample { map_ty, dst, map, k } => {
+                        let k = *self.get(*k);
+                        match map_ty {
+                            Ty::MapIntInt => self.sample_into::<Int, Int>(*map, *dst, k),
+                            Ty::MapIntFloat => self.sample_into::<Int, Float>(*map, *dst, k),
+                            Ty::MapIntStr => self.sample_into::<Int, Str<'a>>(*map, *dst, k),
+                            Ty::MapStrInt => self.sample_into::<Str<'a>, Int>(*map, *dst, k),
+                            Ty::MapStrFloat => self.sample_into::<Str<'a>, Float>(*map, *dst, k),
+                            Ty::MapStrStr => self.sample_into::<Str<'a>, Str<'a>>(*map, *dst, k),
+                            _ => return err!("sample of a non-map type: {:?}", map_ty),
+                        }
+                    }
+                    Shuffle { map_ty, dst, map } => {
+                        let map = *map;
+                        let res = match map_ty {
+                            Ty::MapIntInt => self.shuffle::<Int>(map),
+                            Ty::MapIntFloat => self.shuffle::<Float>(map),
+                            Ty::MapIntStr => self.shuffle::<Str<'a>>(map),
+                            _ => return err!("shuffle of a string-indexed array: {:?}", map_ty),
+                        };
+                        *index_mut(&mut self.ints, &Reg::from(*dst)) = res;
+                    }
+                    Slice {
+                        map_ty,
+                        dst,
+                        map,
+                        from,
+                        to,
+                    } => {
+                        let (from, to) = (*self.get(*from), *self.get(*to));
+                        match map_ty {
+                            Ty::MapIntInt => self.slice_into::<Int>(*map, *dst, from, to),
+                            Ty::MapIntFloat => self.slice_into::<Float>(*map, *dst, from, to),
+                            Ty::MapIntStr => self.slice_into::<Str<'a>>(*map, *dst, from, to),
+                            _ => return err!("_slice of a string-indexed array: {:?}", map_ty),
+                        }
+                    }
                     Store {
                         map_ty,
                         map,
@@ -2147,6 +3264,7 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                             &self.core.vars.rs,
                             &mut self.read_files,
                             *is_file,
+                            self.core.vars.read_timeout(file),
                         ) {
                             Ok(l) => *self.get_mut(dst) = l,
                             Err(_) => *self.get_mut(dst) = "".into(),
@@ -2162,25 +3280,25 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
                         let (changed, res) = self
                             .core
                             .regexes
-                            .get_line_stdin(&self.core.vars.rs, &mut self.read_files)?;
+                            .get_line_stdin(&self.core.vars, &mut self.read_files)?;
                         if changed {
-                            self.reset_file_vars();
+                            self.reset_file_vars()?;
                         }
                         *self.get_mut(dst) = res;
                     }
                     NextLineStdinFused() => {
                         let changed = self.core.regexes.get_line_stdin_reuse(
-                            &self.core.vars.rs,
+                            &self.core.vars,
                             &mut self.read_files,
                             &mut self.line,
                         )?;
                         if changed {
-                            self.reset_file_vars()
+                            self.reset_file_vars()?;
                         }
                     }
                     NextFile() => {
                         self.read_files.next_file()?;
-                        self.reset_file_vars();
+                        self.reset_file_vars()?;
                     }
                     UpdateUsedFields() => {
                         let fi = &self.core.vars.fi;
@@ -2329,6 +3447,52 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         let len = map_regs!(map_ty, map, self.get(map).len() as Int);
         *index_mut(&mut self.ints, &dst.into()) = len;
     }
+    fn sort_order(&self, order: &Reg<Str<'a>>) -> Result<runtime::math_util::SortOrder> {
+        index(&self.strs, order).with_bytes(|bs| runtime::math_util::SortOrder::parse(&String::from_utf8_lossy(bs)))
+    }
+    fn invert(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) {
+        match map_ty {
+            Ty::MapIntInt => self.invert_into::<Int, Int, Int>(map, dst),
+            Ty::MapIntFloat => self.invert_into::<Int, Float, Str<'a>>(map, dst),
+            Ty::MapIntStr => self.invert_into::<Int, Str<'a>, Str<'a>>(map, dst),
+            Ty::MapStrInt => self.invert_into::<Str<'a>, Int, Int>(map, dst),
+            Ty::MapStrFloat => self.invert_into::<Str<'a>, Float, Str<'a>>(map, dst),
+            Ty::MapStrStr => self.invert_into::<Str<'a>, Str<'a>, Str<'a>>(map, dst),
+            _ => unreachable!("_invert of a non-map: {:?}", map_ty),
+        }
+    }
+    fn invert_into<K, V, I>(&mut self, map: NumTy, dst: NumTy)
+    where
+        Self: Get<runtime::SharedMap<K, V>> + Get<runtime::SharedMap<I, K>>,
+        K: runtime::math_util::SortKey,
+        V: Clone + runtime::math_util::IntoKey<I>,
+        I: runtime::MapKey,
+    {
+        let res = runtime::math_util::map_invert(self.get(Reg::<runtime::SharedMap<K, V>>::from(map)));
+        *self.get_mut(Reg::<runtime::SharedMap<I, K>>::from(dst)) = res;
+    }
+    fn slice_into<V: runtime::MapValue>(&mut self, map: NumTy, dst: NumTy, from: Int, to: Int)
+    where
+        Self: Get<runtime::IntMap<V>>,
+    {
+        let res = runtime::math_util::map_slice(self.get(Reg::<runtime::IntMap<V>>::from(map)), from, to);
+        *self.get_mut(Reg::<runtime::IntMap<V>>::from(dst)) = res;
+    }
+    fn sample_into<K: runtime::MapKey, V: runtime::MapValue>(&mut self, map: NumTy, dst: NumTy, k: Int)
+    where
+        Self: Get<runtime::SharedMap<K, V>> + Get<runtime::IntMap<V>>,
+    {
+        let map = self.get(Reg::<runtime::SharedMap<K, V>>::from(map)).clone();
+        let res = runtime::math_util::map_sample(&mut self.core.rng, &map, k);
+        *self.get_mut(Reg::<runtime::IntMap<V>>::from(dst)) = res;
+    }
+    fn shuffle<V: runtime::MapValue>(&mut self, map: NumTy) -> Int
+    where
+        Self: Get<runtime::IntMap<V>>,
+    {
+        let map = self.get(Reg::<runtime::IntMap<V>>::from(map)).clone();
+        runtime::math_util::map_shuffle(&mut self.core.rng, &map)
+    }
     fn iter_begin(&mut self, map_ty: Ty, map: NumTy, dst: NumTy) {
         let _k = 0u32;
         let _v = 0u32;
@@ -2493,6 +3657,91 @@ pub(crate) fn pop<T: Clone>(s: &mut Storage<T>) -> T {
 
 // Used in benchmarking code.
 
+// Show the elements of an array in order of their keys, as `{key: value, ...}`.
+fn show_map<K: std::hash::Hash + Eq, V>(
+    m: &runtime::SharedMap<K, V>,
+    key: impl Fn(&K) -> (Int, String),
+    value: impl Fn(&V) -> String,
+) -> String {
+    let mut elts: Vec<_> = m.iter(|i| i.map(|(k, v)| (key(k), value(v))).collect());
+    elts.sort();
+    let elts: Vec<_> = elts.into_iter().map(|((_, k), v)| format!("{}: {}", k, v)).collect();
+    format!("{{{}}}", elts.join(", "))
+}
+
+fn show_str(s: &Str) -> String {
+    format!("{:?}", s.to_string())
+}
+
+fn show_float(f: &Float) -> String {
+    runtime::convert::<Float, Str>(*f).to_string()
+}
+
+fn report_to_toml<'a>(res: std::result::Result<String, String>) -> Result<Str<'a>> {
+    match res {
+        Ok(text) => Ok(Str::from(text)),
+        Err(e) => {
+            runtime::errors::report(format!("to_toml: {}", e))?;
+            Ok(Str::default())
+        }
+    }
+}
+
+impl<'a, LR: LineReader> debug::Inspect for Interp<'a, LR> {
+    fn value(&mut self, name: &str) -> Option<String> {
+        use Variable::*;
+        if let Some(col) = name.strip_prefix('$') {
+            let col = col.parse::<Int>().ok()?;
+            let vars = &self.core.vars;
+            let s = self
+                .line
+                .get_col(col, &vars.fs, &vars.ofs, &mut self.core.regexes)
+                .ok()?;
+            return Some(show_str(&s));
+        }
+        if let Ok(var) = Variable::try_from(name) {
+            return match var {
+                NF => {
+                    let nf = self.line.nf(&self.core.vars.fs, &mut self.core.regexes).ok()?;
+                    Some(nf.to_string())
+                }
+                ARGC | NR | FNR | RSTART | RLENGTH | PID | IGNORECASE => {
+                    self.core.vars.load_int(var).ok().map(|i| i.to_string())
+                }
+                FS | OFS | ORS | RS | FILENAME | OCSVQUOTE | CONVFMT | OFMT => {
+                    self.core.vars.load_str(var).ok().map(|s| show_str(&s))
+                }
+                // Loading ERRNO clears it, and the arrays are not worth showing.
+                ERRNO | FI | ARGV | ENVIRON | PROCINFO => None,
+            };
+        }
+        let (_, reg, ty) = self.debug_info.globals.iter().find(|(n, _, _)| n == name)?;
+        let reg = *reg;
+        let int_key = |k: &Int| (*k, k.to_string());
+        let str_key = |k: &Str| (0, show_str(k));
+        Some(match ty {
+            Ty::Int => index(&self.ints, &reg.into()).to_string(),
+            Ty::Float => show_float(index(&self.floats, &reg.into())),
+            Ty::Str => show_str(index(&self.strs, &reg.into())),
+            Ty::MapIntInt => show_map(index(&self.maps_int_int, &reg.into()), int_key, Int::to_string),
+            Ty::MapIntFloat => show_map(index(&self.maps_int_float, &reg.into()), int_key, show_float),
+            Ty::MapIntStr => show_map(index(&self.maps_int_str, &reg.into()), int_key, show_str),
+            Ty::MapStrInt => show_map(index(&self.maps_str_int, &reg.into()), str_key, Int::to_string),
+            Ty::MapStrFloat => show_map(index(&self.maps_str_float, &reg.into()), str_key, show_float),
+            Ty::MapStrStr => show_map(index(&self.maps_str_str, &reg.into()), str_key, show_str),
+            Ty::IterInt | Ty::IterStr | Ty::Null => return None,
+        })
+    }
+
+    fn callers(&self) -> Vec<(usize, usize)> {
+        self.stack.iter().map(|(func, Label(ret))| (*func, *ret)).collect()
+    }
+
+    fn flush(&mut self) {
+        let _ = self.core.write_files.flush_stdout();
+    }
+}
+
 #[cfg(test)]
 impl<T: Default> Storage<T> {
     #[cfg(feature = "unstable")]
@@ -2525,3 +3774,32 @@ impl<'a, LR: LineReader> Interp<'a, LR> {
         self.iters_str.reset();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combine_with_merge_spec() {
+        let mut spec = MergeSpec::default();
+        spec.set(Ty::Int, 1, MergeStrategy::Min);
+        spec.set(Ty::Float, 0, MergeStrategy::Max);
+        spec.set(Ty::Str, 0, MergeStrategy::Concat);
+        spec.set(Ty::MapIntInt, 0, MergeStrategy::Union);
+        let slots = |a: Int, b: Int, f: Float, s: &str, m: &[(Int, Int)]| Slots {
+            int: vec![a, b],
+            float: vec![f],
+            strs: vec![Str::from(s.to_string()).into()],
+            intint: vec![m.iter().cloned().collect()],
+            ..Default::default()
+        };
+        let mut main = slots(1, 5, 1.5, "a", &[(1, 1), (2, 2)]);
+        main.combine(slots(2, 3, 0.5, "b", &[(2, 20), (3, 30)]), &spec);
+        assert_eq!(main.int, vec![3, 3]);
+        assert_eq!(main.float, vec![1.5]);
+        assert_eq!(main.strs[0].clone_str().as_str(), "ab");
+        let mut m: Vec<_> = main.intint[0].iter().map(|(k, v)| (*k, *v)).collect();
+        m.sort();
+        assert_eq!(m, vec![(1, 1), (2, 20), (3, 30)]);
+    }
+}
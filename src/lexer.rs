@@ -97,10 +97,16 @@ pub enum Tok<'a> {
     StrLit(&'a str),
     PatLit(&'a str),
     CallStart(&'a str),
+    // `@name(`, an indirect call through the variable `name`.
+    IndirectCallStart(&'a str),
+    // `@"name"`, the column with header `name`.
+    NamedColumn(&'a str),
     FunDec(&'a str),
 
     ILit(&'a str),
     HexLit(&'a str),
+    BinLit(&'a str),
+    OctLit(&'a str),
     FLit(&'a str),
 }
 
@@ -418,6 +424,8 @@ impl<'a> Tokenizer<'a> {
     fn num(&self) -> Option<(Tok<'a>, usize)> {
         lazy_static! {
             static ref HEX_PATTERN: Regex = Regex::new(r"^[+-]?0[xX][0-9A-Fa-f]+").unwrap();
+            static ref BIN_PATTERN: Regex = Regex::new(r"^[+-]?0[bB][01]+").unwrap();
+            static ref OCT_PATTERN: Regex = Regex::new(r"^[+-]?0[oO][0-7]+").unwrap();
             static ref INT_PATTERN: Regex = Regex::new(r"^[+-]?\d+").unwrap();
             // Adapted from https://www.regular-expressions.info/floatingpoint.html
             static ref FLOAT_PATTERN: Regex = Regex::new(r"^[-+]?(\d*\.\d+([eE][-+]?\d+)?|\d+[eE][-+]?\d+)").unwrap();
@@ -426,6 +434,12 @@ impl<'a> Tokenizer<'a> {
         if let Some(i) = HEX_PATTERN.captures(text).and_then(|c| c.get(0)) {
             let is = i.as_str();
             return Some((Tok::HexLit(is), is.len()));
+        } else if let Some(i) = BIN_PATTERN.captures(text).and_then(|c| c.get(0)) {
+            let is = i.as_str();
+            Some((Tok::BinLit(is), is.len()))
+        } else if let Some(i) = OCT_PATTERN.captures(text).and_then(|c| c.get(0)) {
+            let is = i.as_str();
+            Some((Tok::OctLit(is), is.len()))
         } else if let Some(f) = FLOAT_PATTERN.captures(text).and_then(|c| c.get(0)) {
             let fs = f.as_str();
             Some((Tok::FLit(fs), fs.len()))
@@ -553,8 +567,12 @@ impl<'a> Tokenizer<'a> {
             &self.prev_tok,
             Some(Tok::Ident(_))
                 | Some(Tok::StrLit(_))
+                | Some(Tok::NamedColumn(_))
                 | Some(Tok::PatLit(_))
                 | Some(Tok::ILit(_))
+                | Some(Tok::HexLit(_))
+                | Some(Tok::BinLit(_))
+                | Some(Tok::OctLit(_))
                 | Some(Tok::FLit(_))
                 | Some(Tok::RParen)
                 | Some(Tok::RBrack)
@@ -638,6 +656,25 @@ impl<'a> Iterator for Tokenizer<'a> {
                     self.cur = new_start;
                     self.spanned(ix, new_start, Tok::StrLit(s))
                 }
+                '@' if self.text[ix + 1..].starts_with('"') => {
+                    self.cur += 2;
+                    let (s, new_start) = try_tok!(self.string_lit());
+                    self.cur = new_start;
+                    self.spanned(ix, new_start, Tok::NamedColumn(s))
+                }
+                '@' if self.text[ix + 1..].starts_with(is_id_start) => {
+                    self.cur += 1;
+                    let (s, new_start) = self.ident(ix + 1);
+                    if self.text.as_bytes().get(new_start) != Some(&b'(') {
+                        // Valid `@include` lines are expanded before the program is parsed.
+                        return Some(Err(Error {
+                            location: self.index_to_loc(ix),
+                            desc: "expected an indirect call like @f(...), or an @include \"file\" line outside of any rule or function",
+                        }));
+                    }
+                    self.cur = new_start + 1;
+                    self.spanned(ix, self.cur, Tok::IndirectCallStart(s))
+                }
                 '/' if self.potential_re() => {
                     self.cur += 1;
                     let (re, new_start) = try_tok!(self.regex_lit());
@@ -652,6 +689,22 @@ impl<'a> Iterator for Tokenizer<'a> {
                         self.cur += len;
                         self.spanned(ix, self.cur, tok)
                     } else if let Some((tok, len)) = self.num() {
+                        if let Tok::HexLit(is) | Tok::BinLit(is) | Tok::OctLit(is) = tok {
+                            // Values take up to 64 bits (see `runtime::radixtoi`); longer ones
+                            // are most likely a typo, and would otherwise quietly become 0.
+                            let radix = match tok {
+                                Tok::BinLit(_) => 2,
+                                Tok::OctLit(_) => 8,
+                                _ => 16,
+                            };
+                            let digits = is.trim_start_matches(['+', '-']);
+                            if u64::from_str_radix(&digits[2..], radix).is_err() {
+                                return Some(Err(Error {
+                                    location: self.index_to_loc(ix),
+                                    desc: "integer literal does not fit in 64 bits",
+                                }));
+                            }
+                        }
                         self.cur += len;
                         self.spanned(ix, self.cur, tok)
                     } else if is_id_start(c) {
@@ -827,4 +880,49 @@ and the third"#;
             b"are you there ?\\xh"
         );
     }
+
+    #[test]
+    fn radix_literals() {
+        use Tok::*;
+        assert_eq!(
+            lex_str("m = 0o755 / 0x1F / 0b1010").into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![
+                Ident("m"),
+                Assign,
+                OctLit("0o755"),
+                Div,
+                HexLit("0x1F"),
+                Div,
+                BinLit("0b1010"),
+                Newline,
+            ],
+        );
+        let max = format!("0b{}", "1".repeat(64));
+        assert_eq!(lex_str(&max)[0].1, BinLit(&max));
+        for text in [
+            format!("0b1{}", "0".repeat(64)),
+            "-0x10000000000000000".into(),
+            "0o2000000000000000000000".into(),
+        ] {
+            let err = Tokenizer::new(&text).find_map(Result::err).unwrap();
+            assert_eq!(err.desc, "integer literal does not fit in 64 bits");
+        }
+    }
+
+    #[test]
+    fn named_columns() {
+        use Tok::*;
+        assert_eq!(
+            lex_str(r#"@"unit price" / 2"#).into_iter().map(|x| x.1).collect::<Vec<_>>(),
+            vec![NamedColumn("unit price"), Div, ILit("2"), Newline],
+        );
+    }
+
+    #[test]
+    fn misplaced_include() {
+        let err = Tokenizer::new("BEGIN { @include \"lib\" }")
+            .find_map(|t| t.err())
+            .expect("@include inside a rule should not lex");
+        assert_eq!((err.location.line, err.location.col), (0, 8));
+    }
 }
@@ -0,0 +1,59 @@
+//! zawk, an AWK implementation with a standard library of extra functions.
+//!
+//! Most users want the `zawk` binary. Rust programs can also embed the interpreter through
+//! [`Program`]; see the [`embed`] module.
+#![recursion_limit = "1024"]
+#![cfg_attr(feature = "unstable", feature(core_intrinsics))]
+#![cfg_attr(feature = "unstable", feature(test))]
+#![cfg_attr(feature = "unstable", feature(write_all_vectored))]
+
+#[macro_use]
+mod common;
+
+mod arena;
+mod ast;
+mod builtins;
+mod bytecode;
+mod cfg;
+// `cli` and `runtime` are public for the binary and the benchmarks only; they are not part of
+// the library's interface.
+#[doc(hidden)]
+pub mod cli;
+#[macro_use]
+mod codegen;
+mod column_stats;
+mod compile;
+mod const_fold;
+mod cross_stage;
+mod dataflow;
+mod debug;
+mod display;
+mod dom;
+pub mod embed;
+#[cfg(test)]
+mod harness;
+mod include;
+mod input_taint;
+mod interp;
+mod lexer;
+mod lint;
+mod parallel_report;
+#[allow(unused_parens)] // Warnings appear in generated code
+#[allow(clippy::all)]
+mod parsing;
+mod precompile;
+mod pretty;
+mod profile;
+mod pushdown;
+mod query;
+#[doc(hidden)]
+pub mod runtime;
+mod schema;
+mod script_flags;
+mod string_constants;
+#[cfg(test)]
+mod test_string_constants;
+mod trace;
+mod types;
+
+pub use embed::{Error, Options, Program};
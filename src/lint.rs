@@ -0,0 +1,539 @@
+//! Static checks for `zawk lint`.
+//!
+//! These look for mistakes that parse and type-check but are probably not what the author meant:
+//!
+//! * global variables that are assigned but never read, or read but never assigned;
+//! * functions that are never called, and function parameters that are never used;
+//! * assignments to `NF`, which zawk ignores;
+//! * comparisons of a number with a string constant, which AWK performs as string comparisons;
+//! * printf and sprintf calls whose constant format uses more or fewer arguments than are passed,
+//!   or mixes numbered (`%1$s`) and unnumbered directives;
+//! * statements that follow a `return`, `next`, `exit`, `break` or `continue`.
+//!
+//! The checks run on the AST, before the program is lowered, and report the line that each
+//! statement starts on (see `Prog::lines`).
+use crate::ast::{self, Binop, Expr, Prog, Stmt, Unop};
+use crate::builtins::{Function, IsSprintf, Variable};
+
+use crate::common::Either;
+use crate::runtime::printf;
+
+use hashbrown::{HashMap, HashSet};
+
+/// A problem found by `lint`, at a line of the program text (counting from 0), if known.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Finding {
+    pub(crate) line: Option<usize>,
+    pub(crate) msg: String,
+}
+
+/// Check `prog`, returning the findings in the order of the lines they were found on.
+pub(crate) fn lint<'a>(prog: &'a Prog<'a, 'a, &'a str>) -> Vec<Finding> {
+    let mut l = Linter {
+        lines: &prog.lines,
+        line: None,
+        funcs: prog.decs.iter().map(|d| d.name).collect(),
+        params: &[],
+        used_params: HashSet::new(),
+        reads: HashMap::new(),
+        writes: HashMap::new(),
+        called: HashSet::new(),
+        strings: HashSet::new(),
+        findings: Vec::new(),
+    };
+    // Variables assigned with -v.
+    for (v, _) in prog.prelude_vardecs.iter() {
+        l.writes.entry(*v).or_insert(None);
+    }
+    for dec in prog.decs.iter() {
+        l.params = &dec.args[..];
+        l.used_params.clear();
+        l.stmt(dec.body);
+        let line = l.lines.get(&ast::addr(dec.body)).copied();
+        let unused: Vec<_> = dec
+            .args
+            .iter()
+            .filter(|p| !l.used_params.contains(*p))
+            .collect();
+        for p in unused {
+            l.warn(
+                line,
+                format!("parameter {} of function {}() is never used", p, dec.name),
+            );
+        }
+    }
+    l.params = &[];
+    for s in prog.begin.iter().chain(prog.prepare.iter()) {
+        l.stmt(s);
+    }
+    for (pat, body) in prog.pats.iter() {
+        match pat {
+            ast::Pattern::Null => {}
+            ast::Pattern::Bool(e) => {
+                l.line = l.lines.get(&ast::addr(*e)).copied();
+                l.expr(e);
+            }
+            ast::Pattern::Comma(start, end) => {
+                l.line = l.lines.get(&ast::addr(*start)).copied();
+                l.expr(start);
+                l.expr(end);
+            }
+        }
+        if let Some(body) = body {
+            l.stmt(body);
+        }
+    }
+    for s in prog.end.iter() {
+        l.stmt(s);
+    }
+
+    for dec in prog.decs.iter() {
+        // Functions can also be called by name, with `@f()` or `_map(a, "f")`.
+        if !l.called.contains(dec.name) && !l.strings.contains(dec.name.as_bytes()) {
+            let line = l.lines.get(&ast::addr(dec.body)).copied();
+            l.warn(line, format!("function {}() is never called", dec.name));
+        }
+    }
+    let mut unread: Vec<_> = l
+        .writes
+        .iter()
+        .filter(|(v, _)| !l.reads.contains_key(*v))
+        .map(|(v, line)| (*line, format!("variable {} is assigned but never used", v)))
+        .collect();
+    unread.extend(
+        l.reads
+            .iter()
+            .filter(|(v, _)| !l.writes.contains_key(*v))
+            .map(|(v, line)| (*line, format!("variable {} is used but never assigned", v))),
+    );
+    unread.sort();
+    for (line, msg) in unread {
+        l.warn(line, msg);
+    }
+    l.findings.sort_by_key(|f| f.line);
+    l.findings
+}
+
+struct Linter<'a> {
+    lines: &'a HashMap<usize, usize>,
+    // The line of the statement being checked.
+    line: Option<usize>,
+    // The user-defined functions.
+    funcs: HashSet<&'a str>,
+    // The parameters of the function being checked, which are its local variables.
+    params: &'a [&'a str],
+    used_params: HashSet<&'a str>,
+    // The first line that each global variable is read or assigned on.
+    reads: HashMap<&'a str, Option<usize>>,
+    writes: HashMap<&'a str, Option<usize>>,
+    called: HashSet<&'a str>,
+    strings: HashSet<&'a [u8]>,
+    findings: Vec<Finding>,
+}
+
+// Whether control never reaches the statement after `s`.
+fn terminates<'a>(s: &Stmt<'a, 'a, &'a str>) -> bool {
+    match s {
+        Stmt::Return(_) | Stmt::Next | Stmt::NextFile | Stmt::Break | Stmt::Continue => true,
+        Stmt::Expr(Expr::Call(Either::Right(Function::Exit), _)) => true,
+        Stmt::Block(ss) => ss.iter().any(|s| terminates(s)),
+        Stmt::If(_, t, Some(e)) => terminates(t) && terminates(e),
+        _ => false,
+    }
+}
+
+// Whether `e` is always a number, so that comparing it with a string constant compares strings.
+fn is_numeric<'a>(e: &Expr<'a, 'a, &'a str>) -> bool {
+    use Binop::*;
+    match e {
+        Expr::ILit(_) | Expr::FLit(_) | Expr::Inc { .. } => true,
+        Expr::Binop(Plus | Minus | Mult | Div | Mod | Pow, _, _) => true,
+        Expr::Unop(Unop::Neg | Unop::Pos, _) => true,
+        Expr::Var(v) => matches!(*v, "NF" | "NR" | "FNR" | "RSTART" | "RLENGTH"),
+        _ => false,
+    }
+}
+
+impl<'a> Linter<'a> {
+    fn warn(&mut self, line: Option<usize>, msg: String) {
+        self.findings.push(Finding { line, msg });
+    }
+
+    fn is_global(&mut self, v: &'a str) -> bool {
+        if self.params.contains(&v) {
+            self.used_params.insert(v);
+            return false;
+        }
+        // SUBSEP is set when the program starts, but it is not an ordinary builtin variable.
+        !(Variable::try_from(v).is_ok() || v == "SUBSEP" || v.is_parallel_merge())
+    }
+
+    fn read(&mut self, v: &'a str) {
+        if self.is_global(v) {
+            self.reads.entry(v).or_insert(self.line);
+        }
+    }
+
+    fn write(&mut self, v: &'a str) {
+        if self.is_global(v) {
+            self.writes.entry(v).or_insert(self.line);
+        }
+    }
+
+    fn block(&mut self, ss: &'a [&'a Stmt<'a, 'a, &'a str>]) {
+        let mut done = false;
+        for s in ss {
+            if done {
+                let line = self.lines.get(&ast::addr(*s)).copied().or(self.line);
+                self.warn(line, "unreachable code".into());
+            }
+            self.stmt(s);
+            done = terminates(s);
+        }
+    }
+
+    fn stmt(&mut self, s: &'a Stmt<'a, 'a, &'a str>) {
+        let outer = self.line;
+        if let Some(line) = self.lines.get(&ast::addr(s)) {
+            self.line = Some(*line);
+        }
+        match s {
+            Stmt::StartCond(_) | Stmt::EndCond(_) | Stmt::LastCond(_) => {}
+            Stmt::Break | Stmt::Continue | Stmt::Next | Stmt::NextFile => {}
+            Stmt::Expr(e) => self.expr(e),
+            Stmt::Block(ss) => self.block(ss),
+            Stmt::Print(args, out) => {
+                for a in args.iter() {
+                    self.expr(a);
+                }
+                if let Some((o, _)) = out {
+                    self.expr(o);
+                }
+            }
+            Stmt::Printf(fmt, args, out) => {
+                self.format("printf", fmt, args.len());
+                self.expr(fmt);
+                for a in args.iter() {
+                    self.expr(a);
+                }
+                if let Some((o, _)) = out {
+                    self.expr(o);
+                }
+            }
+            Stmt::If(cond, t, e) => {
+                self.expr(cond);
+                self.stmt(t);
+                if let Some(e) = e {
+                    self.stmt(e);
+                }
+            }
+            Stmt::For(init, cond, update, body) => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                }
+                if let Some(update) = update {
+                    self.stmt(update);
+                }
+                self.stmt(body);
+            }
+            Stmt::DoWhile(cond, body) | Stmt::While(_, cond, body) => {
+                self.expr(cond);
+                self.stmt(body);
+            }
+            Stmt::ForEach(v, arr, body) => {
+                self.write(v);
+                self.expr(arr);
+                self.stmt(body);
+            }
+            Stmt::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+        }
+        self.line = outer;
+    }
+
+    fn compare(&mut self, l: &Expr<'a, 'a, &'a str>, r: &Expr<'a, 'a, &'a str>) {
+        let s = match (l, r) {
+            (Expr::StrLit(s), e) | (e, Expr::StrLit(s)) if is_numeric(e) => s,
+            _ => return,
+        };
+        let text = String::from_utf8_lossy(s);
+        let mut msg = format!(
+            "comparison of a number with the string {:?} is done as a string comparison",
+            text
+        );
+        if text.trim().parse::<f64>().is_ok() {
+            msg.push_str("; remove the quotes to compare numbers");
+        }
+        self.warn(self.line, msg);
+    }
+
+    // Check that a constant format passed to printf or sprintf is given the arguments it uses.
+    fn format(&mut self, f: &str, fmt: &Expr<'a, 'a, &'a str>, given: usize) {
+        let spec = match fmt {
+            Expr::StrLit(s) => s,
+            _ => return,
+        };
+        let args = printf::format_args(spec);
+        if args.sequential > 0 && args.positional > 0 {
+            self.warn(
+                self.line,
+                format!("{} format mixes numbered (%n$) and unnumbered directives", f),
+            );
+        }
+        let expected = args.expected();
+        if expected != given {
+            let plural = |n| if n == 1 { "" } else { "s" };
+            self.warn(
+                self.line,
+                format!(
+                    "{} format uses {} argument{}, but {} {} given",
+                    f,
+                    expected,
+                    plural(expected),
+                    given,
+                    if given == 1 { "is" } else { "are" }
+                ),
+            );
+        }
+    }
+
+    // Check `e`, which is assigned to. If `update` is set, its old value is read as well.
+    fn lvalue(&mut self, e: &'a Expr<'a, 'a, &'a str>, update: bool) {
+        match e {
+            Expr::Var(v) => {
+                if *v == "NF" {
+                    self.warn(
+                        self.line,
+                        "assigning to NF has no effect: zawk does not rebuild $0, and NF is recomputed".into(),
+                    );
+                }
+                if update {
+                    self.read(v);
+                }
+                self.write(v);
+            }
+            Expr::Index(Expr::Var(arr), ix) => {
+                self.expr(ix);
+                if update {
+                    self.read(arr);
+                }
+                self.write(arr);
+            }
+            e => self.expr(e),
+        }
+    }
+
+    // An array argument that a builtin fills in or deletes from.
+    fn array_arg(&mut self, e: &'a Expr<'a, 'a, &'a str>) {
+        match e {
+            Expr::Var(v) => self.write(v),
+            e => self.expr(e),
+        }
+    }
+
+    fn builtin(&mut self, f: Function, args: &'a [&'a Expr<'a, 'a, &'a str>]) {
+        for (i, a) in args.iter().enumerate() {
+            match (f, i) {
+                (Function::Split | Function::PatSplit, 1 | 3)
+                | (Function::Match, 2)
+                | (Function::Delete | Function::Clear | Function::ArrayCopy, 0) => self.array_arg(a),
+                (Function::Sub | Function::GSub, 2) => self.lvalue(a, true),
+                _ => self.expr(a),
+            }
+        }
+    }
+
+    fn call(&mut self, f: &'a str, args: &'a [&'a Expr<'a, 'a, &'a str>]) {
+        let udf = self.funcs.contains(f);
+        match Function::try_from(f) {
+            // As in `cfg`, tee and the array functions can be redefined.
+            Ok(bi) if !(udf && (f.is_tee() || f.array_fn().is_some())) => {
+                return self.builtin(bi, args)
+            }
+            _ => {}
+        }
+        if udf {
+            self.called.insert(f);
+        } else if f.is_sprintf() {
+            if let Some((fmt, rest)) = args.split_first() {
+                self.format(f, fmt, rest.len());
+            }
+        }
+        for a in args.iter() {
+            match a {
+                // Arrays are passed by reference, so the function may assign to them.
+                Expr::Var(v) if udf => {
+                    self.read(v);
+                    self.write(v);
+                }
+                a => self.expr(a),
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &'a Expr<'a, 'a, &'a str>) {
+        use Expr::*;
+        match e {
+            ILit(_) | FLit(_) | PatLit(_) | ReadStdin | Cond(_) => {}
+            StrLit(s) => {
+                self.strings.insert(s);
+            }
+            Unop(_, x) => self.expr(x),
+            Binop(op, l, r) => {
+                if matches!(
+                    op,
+                    ast::Binop::LT
+                        | ast::Binop::GT
+                        | ast::Binop::LTE
+                        | ast::Binop::GTE
+                        | ast::Binop::EQ
+                ) {
+                    self.compare(l, r);
+                }
+                self.expr(l);
+                self.expr(r);
+            }
+            Call(Either::Left(f), args) => self.call(f, args),
+            Call(Either::Right(bi), args) => self.builtin(*bi, args),
+            IndirectCall(f, args) => {
+                self.expr(f);
+                for a in args.iter() {
+                    self.expr(a);
+                }
+            }
+            Var(v) => self.read(v),
+            Index(arr, ix) => {
+                self.expr(arr);
+                self.expr(ix);
+            }
+            Assign(l, r) => {
+                self.expr(r);
+                self.lvalue(l, false);
+            }
+            AssignOp(l, _, r) => {
+                self.expr(r);
+                self.lvalue(l, true);
+            }
+            And(l, r) | Or(l, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            ITE(c, t, f) => {
+                self.expr(c);
+                self.expr(t);
+                self.expr(f);
+            }
+            Inc { x, .. } => self.lvalue(x, true),
+            Getline { into, from, .. } => {
+                if let Some(from) = from {
+                    self.expr(from);
+                }
+                if let Some(into) = into {
+                    self.lvalue(into, false);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::Arena;
+    use crate::common::ExecutionStrategy;
+    use crate::harness::parse_program;
+    use crate::cfg::Escaper;
+
+    fn findings(prog: &str) -> Vec<(Option<usize>, String)> {
+        let a = Arena::default();
+        let prog = parse_program(prog, &a, Escaper::Identity, ExecutionStrategy::Serial).unwrap();
+        lint(prog).into_iter().map(|f| (f.line, f.msg)).collect()
+    }
+
+    #[test]
+    fn clean_program() {
+        let prog = r#"function add(a, b) { return a + b }
+{ total = add(total, $1); n[$2]++; split($3, parts, ":", seps); match($4, /(x+)/, m) }
+{ patsplit($5, nums, /[0-9]+/) }
+END { for (k in n) print k, n[k], parts[1], seps[1], m[1], nums[1]; print total }"#;
+        assert_eq!(findings(prog), vec![]);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        let prog = r#"function unused(x, y) { return x }
+function apply(s) { return toupper(s) }
+BEGIN { tmp = 1; f = "apply"; print @f("a") }
+{ print count }"#;
+        assert_eq!(
+            findings(prog),
+            vec![
+                (
+                    Some(0),
+                    "parameter y of function unused() is never used".into()
+                ),
+                (Some(0), "function unused() is never called".into()),
+                (Some(2), "variable tmp is assigned but never used".into()),
+                (Some(3), "variable count is used but never assigned".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn suspicious_statements() {
+        let prog = r#"NR > "10" { NF = 2 }
+{ if ($1 == "x") { next; print } }
+function f() { return 1; x = 2 }
+BEGIN { f(); if (1 + 1 == "two") exit; }"#;
+        assert_eq!(
+            findings(prog),
+            vec![
+                (
+                    Some(0),
+                    "comparison of a number with the string \"10\" is done as a string comparison; remove the quotes to compare numbers".into()
+                ),
+                (
+                    Some(0),
+                    "assigning to NF has no effect: zawk does not rebuild $0, and NF is recomputed".into()
+                ),
+                (Some(1), "unreachable code".into()),
+                (Some(2), "unreachable code".into()),
+                (Some(2), "variable x is assigned but never used".into()),
+                (
+                    Some(3),
+                    "comparison of a number with the string \"two\" is done as a string comparison".into()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn printf_formats() {
+        let prog = r#"BEGIN { printf "%s %d\n", "a", 1, 2 }
+BEGIN { x = sprintf("%2$s %1$s %%", "a"); printf("%s %1$s\n", x) }
+BEGIN { printf "%d%%\n", 50; print sprintf("%c", 65) }"#;
+        assert_eq!(
+            findings(prog),
+            vec![
+                (
+                    Some(0),
+                    "printf format uses 2 arguments, but 3 are given".into()
+                ),
+                (
+                    Some(1),
+                    "sprintf format uses 2 arguments, but 1 is given".into()
+                ),
+                (
+                    Some(1),
+                    "printf format mixes numbered (%n$) and unnumbered directives".into()
+                ),
+            ]
+        );
+    }
+}
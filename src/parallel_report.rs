@@ -0,0 +1,787 @@
+//! `--explain-parallel`: what changes when a script runs with `-pr` or `-pf`.
+//!
+//! In parallel mode each worker runs the main rules over its own share of the input. Variables
+//! that BEGIN shares with the main rules are copied into every worker, and variables that the
+//! main rules share with END are merged before END runs (see `info/parallelism.md`). A script
+//! that relies on seeing every record in order still runs, but can print something else than it
+//! does serially; `explain` points out the constructs that cause this, rule by rule, along with
+//! how each variable will be merged.
+//!
+//! This is a syntactic check. The main thing it looks for is a variable that a rule reads before
+//! anything in the current record has assigned it, which means that the value comes from an
+//! earlier record. Reads that only feed a new value of the same variable, like
+//! `max = $1 > max ? $1 : max` or `$1 > max { max = $1 }`, are per-worker accumulators instead,
+//! and are reported as part of how that variable is merged.
+use crate::ast::{Binop, Expr, FunDec, Pattern, Prog, Stmt};
+use crate::builtins::{Function, Variable};
+use crate::common::Either;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Site {
+    Begin,
+    Rule(usize),
+    Prepare,
+    End,
+}
+
+impl fmt::Display for Site {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Site::Begin => write!(f, "BEGIN"),
+            Site::Rule(i) => write!(f, "rule {}", i),
+            Site::Prepare => write!(f, "PREPARE"),
+            Site::End => write!(f, "END"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Write_ {
+    // `x = ...`, `getline x`, `split(s, x)` and the like.
+    Assign,
+    // `x++`, `x += ...`, `x -= ...`: merging these by adding up each worker's value is correct.
+    Add,
+    // `x = x $1` and the like, which build up a string.
+    Append,
+}
+
+#[derive(Default)]
+struct Facts {
+    // Every global variable referenced.
+    refs: BTreeSet<String>,
+    writes: BTreeMap<String, BTreeSet<Site>>,
+    // Variables assigned, rather than only added or appended to, somewhere in the main loop.
+    assigned: BTreeSet<String>,
+    // Variables appended to somewhere in the main loop.
+    appended: BTreeSet<String>,
+    // Reads of a value left over from an earlier record.
+    stale: BTreeMap<String, BTreeSet<Site>>,
+    arrays: BTreeSet<String>,
+    issues: BTreeSet<(Site, String)>,
+    merges: BTreeMap<String, String>,
+    prints: bool,
+}
+
+struct Walker<'a, 'b, I> {
+    funcs: &'a [FunDec<'a, 'b, I>],
+    site: Site,
+    // Parameters of the function being walked.
+    locals: Vec<&'a str>,
+    call_stack: Vec<&'a str>,
+    // Variables assigned earlier in the current record, on every path through the main loop.
+    defined: BTreeSet<String>,
+    // How many conditionals (or loop bodies, or function calls) we are nested in.
+    conditional: usize,
+    // The variables whose new value is being computed, so reads of them are not stale.
+    targets: Vec<String>,
+    facts: Facts,
+}
+
+impl<'a, 'b, I: AsRef<str>> Walker<'a, 'b, I> {
+    fn main_loop(&self) -> bool {
+        matches!(self.site, Site::Rule(_))
+    }
+
+    fn issue(&mut self, msg: impl Into<String>) {
+        if self.main_loop() {
+            self.facts.issues.insert((self.site, msg.into()));
+        }
+    }
+
+    // The name of `v` if it is a global, user-defined variable.
+    fn global(&self, v: &'a I) -> Option<String> {
+        let s = v.as_ref();
+        if self.locals.contains(&s) || s == "PARALLEL_MERGE" || Variable::try_from(s).is_ok() {
+            None
+        } else {
+            Some(s.to_string())
+        }
+    }
+
+    fn conditionally(&mut self, f: impl FnOnce(&mut Self)) {
+        self.conditional += 1;
+        f(self);
+        self.conditional -= 1;
+    }
+
+    fn with_targets(&mut self, targets: Vec<String>, f: impl FnOnce(&mut Self)) {
+        let prev = std::mem::replace(&mut self.targets, targets);
+        f(self);
+        self.targets = prev;
+    }
+
+    fn read(&mut self, v: &'a I) {
+        match Variable::try_from(v.as_ref()) {
+            Ok(Variable::NR) => {
+                self.issue("reads NR, which only counts the records the current worker has read")
+            }
+            Ok(Variable::FNR) => self.issue(
+                "reads FNR, which (with -pr) only counts the records the current worker has read",
+            ),
+            _ => {}
+        }
+        let name = match self.global(v) {
+            Some(name) => name,
+            None => return,
+        };
+        if self.main_loop() && !self.defined.contains(&name) && !self.targets.contains(&name) {
+            self.facts
+                .stale
+                .entry(name.clone())
+                .or_default()
+                .insert(self.site);
+        }
+        self.facts.refs.insert(name);
+    }
+
+    fn write(&mut self, v: &'a I, how: Write_, defines: bool) {
+        let name = match self.global(v) {
+            Some(name) => name,
+            None => return,
+        };
+        if self.main_loop() || self.site == Site::Prepare {
+            self.facts
+                .writes
+                .entry(name.clone())
+                .or_default()
+                .insert(self.site);
+            match how {
+                Write_::Assign => self.facts.assigned.insert(name.clone()),
+                Write_::Append => self.facts.appended.insert(name.clone()),
+                Write_::Add => false,
+            };
+        }
+        if defines && self.conditional == 0 {
+            self.defined.insert(name.clone());
+        }
+        self.facts.refs.insert(name);
+    }
+
+    fn array(&mut self, v: &'a I) {
+        if let Some(name) = self.global(v) {
+            self.facts.arrays.insert(name);
+        }
+    }
+
+    // The variable updated by assigning to `lhs`, if any.
+    fn target(&self, lhs: &'a Expr<'a, 'b, I>) -> Option<String> {
+        match lhs {
+            Expr::Var(v) | Expr::Index(Expr::Var(v), _) => self.global(v),
+            _ => None,
+        }
+    }
+
+    // Write to `lhs`, walking its subexpressions (such as array indexes) first.
+    fn store(&mut self, lhs: &'a Expr<'a, 'b, I>, how: Write_) {
+        match lhs {
+            Expr::Var(v) => self.write(v, how, true),
+            Expr::Index(arr, ix) => {
+                self.expr(ix);
+                match arr {
+                    Expr::Var(v) => {
+                        self.array(v);
+                        self.write(v, how, false);
+                    }
+                    arr => self.expr(arr),
+                }
+            }
+            lhs => self.expr(lhs),
+        }
+    }
+
+    // `lhs op= rhs` or `lhs++`. When `used` is false the statement only updates the variable, and
+    // reading its old value is not a stale read.
+    fn update(
+        &mut self,
+        lhs: &'a Expr<'a, 'b, I>,
+        op: Option<Binop>,
+        rhs: Option<&'a Expr<'a, 'b, I>>,
+        used: bool,
+    ) {
+        let target = self.target(lhs);
+        let how = match op {
+            None | Some(Binop::Plus) | Some(Binop::Minus) => Write_::Add,
+            Some(_) => Write_::Assign,
+        };
+        let targets = if used {
+            Vec::new()
+        } else {
+            target.into_iter().collect()
+        };
+        self.with_targets(targets, |w| {
+            if let Some(rhs) = rhs {
+                w.expr(rhs);
+            }
+            // Read the old value.
+            match lhs {
+                Expr::Var(v) => w.read(v),
+                Expr::Index(Expr::Var(v), _) => w.read(v),
+                _ => {}
+            }
+            w.store(lhs, how);
+        });
+    }
+
+    fn assign(&mut self, lhs: &'a Expr<'a, 'b, I>, rhs: &'a Expr<'a, 'b, I>) {
+        // PARALLEL_MERGE["x"] = "max"
+        if let (Expr::Index(Expr::Var(m), Expr::StrLit(var)), Expr::StrLit(strategy)) = (lhs, rhs) {
+            if m.as_ref() == "PARALLEL_MERGE" {
+                self.facts.merges.insert(
+                    String::from_utf8_lossy(var).into_owned(),
+                    String::from_utf8_lossy(strategy).into_owned(),
+                );
+                return;
+            }
+        }
+        let target = self.target(lhs);
+        // `x = x a b ...` appends to x.
+        let mut first = rhs;
+        while let Expr::Binop(Binop::Concat, l, _) = first {
+            first = l;
+        }
+        let how = match (lhs, first) {
+            (Expr::Var(l), Expr::Var(r)) if l.as_ref() == r.as_ref() => Write_::Append,
+            _ => Write_::Assign,
+        };
+        self.with_targets(target.into_iter().collect(), |w| w.expr(rhs));
+        self.store(lhs, how);
+    }
+
+    fn call_udf(&mut self, name: &str) {
+        let funcs = self.funcs;
+        let f = match funcs.iter().find(|f| f.name.as_ref() == name) {
+            Some(f) => f,
+            None => return,
+        };
+        let name = f.name.as_ref();
+        if self.call_stack.contains(&name) {
+            return;
+        }
+        self.call_stack.push(name);
+        let locals = std::mem::replace(
+            &mut self.locals,
+            f.args.iter().map(|a| a.as_ref()).collect(),
+        );
+        self.with_targets(Vec::new(), |w| w.conditionally(|w| w.stmt(f.body)));
+        self.locals = locals;
+        self.call_stack.pop();
+    }
+
+    fn builtin(&mut self, bi: Function, args: &'a [&'a Expr<'a, 'b, I>]) {
+        match (bi, args) {
+            (Function::Split | Function::PatSplit, [s, Expr::Var(arr), pat, Expr::Var(seps)]) => {
+                self.expr(s);
+                self.expr(pat);
+                for arr in [arr, seps] {
+                    self.array(arr);
+                    self.write(arr, Write_::Assign, true);
+                }
+            }
+            (Function::Split | Function::PatSplit, [s, Expr::Var(arr), rest @ ..]) => {
+                self.expr(s);
+                for a in rest {
+                    self.expr(a);
+                }
+                self.array(arr);
+                self.write(arr, Write_::Assign, true);
+            }
+            (Function::Match, [s, re, Expr::Var(arr)]) => {
+                self.expr(s);
+                self.expr(re);
+                self.array(arr);
+                self.write(arr, Write_::Assign, true);
+            }
+            (Function::ArrayCopy, [Expr::Var(dst), src]) => {
+                self.expr(src);
+                self.array(dst);
+                self.write(dst, Write_::Assign, true);
+            }
+            (Function::Clear, [Expr::Var(arr)]) => {
+                self.array(arr);
+                self.write(arr, Write_::Assign, true);
+            }
+            (Function::Delete, [Expr::Var(arr), key]) => {
+                self.expr(key);
+                self.array(arr);
+                self.write(arr, Write_::Assign, false);
+            }
+            (Function::Sub | Function::GSub, [re, to, target]) => {
+                self.expr(re);
+                self.expr(to);
+                self.update(target, Some(Binop::Concat), None, true);
+            }
+            (Function::Contains, [Expr::Var(arr), key]) => {
+                self.expr(key);
+                self.array(arr);
+                self.read(arr);
+            }
+            _ => {
+                if bi == Function::Exit {
+                    self.issue(
+                        "calls exit, which stops only this worker; other workers may already have processed later records",
+                    );
+                }
+                if bi == Function::DigestUpdate {
+                    self.issue(
+                        "calls digest_update, but digest handles are not shared with workers; END cannot see what workers hashed",
+                    );
+                }
+                for a in args {
+                    self.expr(a);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &'a Expr<'a, 'b, I>) {
+        use Expr::*;
+        match e {
+            ILit(_) | FLit(_) | StrLit(_) | PatLit(_) | ReadStdin | Cond(_) => {}
+            Unop(_, x) => self.expr(x),
+            Binop(_, l, r) => {
+                self.expr(l);
+                self.expr(r);
+            }
+            Call(Either::Left(f), args) => {
+                // The parser leaves builtins called by name unresolved, like user functions.
+                let name = f.as_ref();
+                if !self.funcs.iter().any(|d| d.name.as_ref() == name) {
+                    if let Ok(bi) = Function::try_from(name) {
+                        return self.builtin(bi, args);
+                    }
+                }
+                for a in args.iter() {
+                    self.expr(a);
+                }
+                self.call_udf(name);
+            }
+            Call(Either::Right(bi), args) => self.builtin(*bi, args),
+            IndirectCall(f, args) => {
+                self.expr(f);
+                for a in args.iter() {
+                    self.expr(a);
+                }
+                let funcs = self.funcs;
+                for dec in funcs.iter().filter(|dec| dec.args.len() >= args.len()) {
+                    self.call_udf(dec.name.as_ref());
+                }
+            }
+            Var(v) => self.read(v),
+            Index(arr, ix) => {
+                self.expr(ix);
+                match arr {
+                    Var(v) => {
+                        self.array(v);
+                        self.read(v);
+                    }
+                    arr => self.expr(arr),
+                }
+            }
+            Assign(lhs, rhs) => self.assign(lhs, rhs),
+            AssignOp(lhs, op, rhs) => self.update(lhs, Some(*op), Some(rhs), true),
+            And(l, r) | Or(l, r) => {
+                self.expr(l);
+                self.conditionally(|w| w.expr(r));
+            }
+            ITE(c, t, f) => {
+                self.expr(c);
+                self.conditionally(|w| {
+                    w.expr(t);
+                    w.expr(f);
+                });
+            }
+            Inc { x, .. } => self.update(x, None, None, true),
+            Getline { into, from, .. } => {
+                match from {
+                    Some(from) => {
+                        self.expr(from);
+                        self.issue("uses getline from a file or command, which every worker runs separately");
+                    }
+                    None => self.issue(
+                        "uses getline to read the next record, which may have gone to another worker",
+                    ),
+                }
+                if let Some(into) = into {
+                    self.store(into, Write_::Assign);
+                }
+            }
+        }
+    }
+
+    fn output(
+        &mut self,
+        args: &'a [&'a Expr<'a, 'b, I>],
+        out: &'a Option<(&'a Expr<'a, 'b, I>, crate::common::FileSpec)>,
+    ) {
+        for a in args.iter() {
+            self.expr(a);
+        }
+        if let Some((out, _)) = out {
+            self.expr(out);
+        }
+        if self.main_loop() {
+            self.facts.prints = true;
+        }
+    }
+
+    fn stmt(&mut self, s: &'a Stmt<'a, 'b, I>) {
+        use Stmt::*;
+        match s {
+            StartCond(_) | EndCond(_) | LastCond(_) | Break | Continue | Next | NextFile => {}
+            // A statement like `n++` or `sum += $1` does not otherwise use the old value of the
+            // variable it updates.
+            Expr(crate::ast::Expr::Inc { x, .. }) => self.update(x, None, None, false),
+            Expr(crate::ast::Expr::AssignOp(lhs, op, rhs)) => {
+                self.update(lhs, Some(*op), Some(rhs), false)
+            }
+            Expr(e) => self.expr(e),
+            Block(stmts) => {
+                for s in stmts.iter() {
+                    self.stmt(s);
+                }
+            }
+            Print(args, out) => self.output(args, out),
+            Printf(fmt, args, out) => {
+                self.expr(fmt);
+                self.output(args, out);
+            }
+            If(c, t, f) => {
+                self.expr(c);
+                self.conditionally(|w| {
+                    w.stmt(t);
+                    if let Some(f) = f {
+                        w.stmt(f);
+                    }
+                });
+            }
+            For(init, cond, update, body) => {
+                if let Some(init) = init {
+                    self.stmt(init);
+                }
+                if let Some(cond) = cond {
+                    self.expr(cond);
+                }
+                self.conditionally(|w| {
+                    w.stmt(body);
+                    if let Some(update) = update {
+                        w.stmt(update);
+                    }
+                });
+            }
+            DoWhile(cond, body) => {
+                self.stmt(body);
+                self.expr(cond);
+            }
+            While(_, cond, body) => {
+                self.expr(cond);
+                self.conditionally(|w| w.stmt(body));
+            }
+            ForEach(v, arr, body) => {
+                self.expr(arr);
+                if let crate::ast::Expr::Var(arr) = arr {
+                    self.array(arr);
+                }
+                self.conditionally(|w| {
+                    w.write(v, Write_::Assign, false);
+                    w.stmt(body);
+                });
+            }
+            Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    // The variables assigned by an action that does nothing but assign variables, as in
+    // `$1 > max { max = $1 }`.
+    fn pure_targets(&self, s: &'a Stmt<'a, 'b, I>, res: &mut Vec<String>) -> bool {
+        match s {
+            Stmt::Block(stmts) => stmts.iter().all(|s| self.pure_targets(s, res)),
+            Stmt::Expr(Expr::Assign(lhs, _))
+            | Stmt::Expr(Expr::AssignOp(lhs, _, _))
+            | Stmt::Expr(Expr::Inc { x: lhs, .. }) => {
+                res.extend(self.target(lhs));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn rule(&mut self, i: usize, pat: &'a Pattern<'a, 'b, I>, body: Option<&'a Stmt<'a, 'b, I>>) {
+        self.site = Site::Rule(i);
+        let mut targets = Vec::new();
+        if !body.is_some_and(|b| self.pure_targets(b, &mut targets)) {
+            targets.clear();
+        }
+        match pat {
+            Pattern::Null => {}
+            Pattern::Bool(p) => self.with_targets(targets, |w| w.expr(p)),
+            Pattern::Comma(l, r) => {
+                self.issue("uses a range pattern; each worker tracks ranges on its own, so a range that spans two workers' records is not matched as a whole");
+                self.expr(l);
+                self.conditionally(|w| w.expr(r));
+            }
+        }
+        let run = |w: &mut Self| match body {
+            Some(body) => w.stmt(body),
+            None => w.facts.prints = true,
+        };
+        if let Pattern::Null = pat {
+            run(self)
+        } else {
+            self.conditionally(run)
+        }
+    }
+}
+
+/// Describe how `prog` behaves when it runs in parallel.
+pub(crate) fn explain<'a, 'b, I: AsRef<str>>(prog: &'a Prog<'a, 'b, I>) -> String {
+    let walker = |site| Walker {
+        funcs: &prog.decs,
+        site,
+        locals: Vec::new(),
+        call_stack: Vec::new(),
+        defined: Default::default(),
+        conditional: 0,
+        targets: Vec::new(),
+        facts: Default::default(),
+    };
+    let mut begin = walker(Site::Begin);
+    for (v, _) in prog.prelude_vardecs.iter() {
+        begin.write(v, Write_::Assign, true);
+    }
+    for s in prog.begin.iter() {
+        begin.stmt(s);
+    }
+    let mut main = walker(Site::Rule(0));
+    for (i, (pat, body)) in prog.pats.iter().enumerate() {
+        main.rule(i + 1, pat, *body);
+    }
+    let mut prepare = walker(Site::Prepare);
+    for s in prog.prepare.iter() {
+        prepare.stmt(s);
+    }
+    let mut end = walker(Site::End);
+    for s in prog.end.iter() {
+        end.stmt(s);
+    }
+
+    let mut out = String::new();
+    if prog.pats.is_empty() {
+        let _ = writeln!(
+            out,
+            "The script has no main rules, so there is nothing to run in parallel."
+        );
+        return out;
+    }
+    let (begin, mut main, prepare, end) = (begin.facts, main.facts, prepare.facts, end.facts);
+    let merges: BTreeMap<_, _> = begin.merges.iter().chain(main.merges.iter()).collect();
+
+    for (v, sites) in main.stale.iter() {
+        if !main.writes.contains_key(v) {
+            continue;
+        }
+        for site in sites {
+            main.issues.insert((
+                *site,
+                format!("reads `{}` before setting it for the current record, so it sees a value from an earlier record; each worker only sees part of the input", v),
+            ));
+        }
+    }
+
+    let copied: Vec<_> = begin
+        .refs
+        .iter()
+        .filter(|v| main.refs.contains(*v) || prepare.refs.contains(*v))
+        .collect();
+    let mut merged = Vec::new();
+    let written = main
+        .writes
+        .keys()
+        .chain(prepare.writes.keys())
+        .collect::<BTreeSet<_>>();
+    for v in written.into_iter().filter(|v| end.refs.contains(*v)) {
+        let array = main.arrays.contains(v) || prepare.arrays.contains(v) || end.arrays.contains(v);
+        let strategy = merges.get(v).map(|s| s.as_str());
+        let mut line = format!(
+            "  {}: {}{}",
+            v,
+            strategy.unwrap_or("sum"),
+            if array { ", per key" } else { "" }
+        );
+        if prepare.writes.contains_key(v) {
+            line.push_str(" (each worker sets it in PREPARE)");
+        } else if strategy.is_none() {
+            line.push_str(" (numbers are added, and for strings one worker's value is kept)");
+            if main.assigned.contains(v) || main.appended.contains(v) {
+                let site = *main.writes[v].iter().next().unwrap();
+                // Suggest the strategy that fits how the variable is built up.
+                let suggestion = if !main.assigned.contains(v) {
+                    "\"concat\""
+                } else if array {
+                    "\"union\""
+                } else {
+                    "\"min\" or \"max\""
+                };
+                main.issues.insert((
+                    site,
+                    format!("assigns `{}`, which END reads after adding up the last value from every worker; if that is not intended, set PARALLEL_MERGE[\"{}\"] to {} in BEGIN", v, v, suggestion),
+                ));
+            }
+        }
+        merged.push(line);
+    }
+
+    let _ = writeln!(out, "Parallel execution report (-pr / -pf)");
+    if main.issues.is_empty() {
+        let _ = writeln!(
+            out,
+            "\nNo problems found: the script should give the same results in parallel."
+        );
+    } else {
+        let _ = writeln!(
+            out,
+            "\n{} construct(s) can make the result differ from a serial run:",
+            main.issues.len()
+        );
+        for (site, msg) in main.issues.iter() {
+            let _ = writeln!(out, "  {}: {}", site, msg);
+        }
+    }
+    if main.prints {
+        let _ = writeln!(
+            out,
+            "\nOutput from the main rules is written by several workers, so lines can come out in a different order."
+        );
+    }
+    if !copied.is_empty() {
+        let names: Vec<_> = copied.iter().map(|s| s.as_str()).collect();
+        let _ = writeln!(
+            out,
+            "\nCopied from BEGIN into every worker: {}",
+            names.join(", ")
+        );
+    }
+    if !merged.is_empty() {
+        let _ = writeln!(out, "\nMerged across workers before END:");
+        for line in merged {
+            let _ = writeln!(out, "{}", line);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arena::Arena;
+    use crate::cfg::Escaper;
+    use crate::common::ExecutionStrategy;
+    use crate::harness::parse_program;
+
+    fn report(prog: &str) -> String {
+        let a = Arena::default();
+        let prog = parse_program(
+            prog,
+            &a,
+            Escaper::Identity,
+            ExecutionStrategy::ShardPerRecord,
+        )
+        .unwrap();
+        explain(prog)
+    }
+
+    #[test]
+    fn accumulators() {
+        let out = report(
+            r#"BEGIN { PARALLEL_MERGE["hi"] = "max"; t = 3 }
+            $1 > t { n++; sum += $1 }
+            $1 > hi { hi = $1 }
+            { lo = lo == "" || $1 < lo ? $1 : lo; seen[$1]++ }
+            END { print n, sum, hi, lo, length(seen) }"#,
+        );
+        assert!(
+            out.contains("Copied from BEGIN into every worker: t\n"),
+            "{}",
+            out
+        );
+        assert!(out.contains("  hi: max\n"), "{}", out);
+        assert!(out.contains("  seen: sum, per key "), "{}", out);
+        assert!(out.contains("1 construct(s)"), "{}", out);
+        assert!(out.contains("rule 3: assigns `lo`"), "{}", out);
+        assert!(!out.contains("before setting it"), "{}", out);
+        assert!(!out.contains("different order"), "{}", out);
+    }
+
+    #[test]
+    fn merge_suggestions() {
+        let out = report(
+            r#"{ names = names "," $1; hi = $1 > hi ? $1 : hi; split($0, last) }
+            END { print names, hi, length(last) }"#,
+        );
+        assert!(
+            out.contains("assigns `names`, which END reads after adding up the last value from every worker; if that is not intended, set PARALLEL_MERGE[\"names\"] to \"concat\" in BEGIN"),
+            "{}",
+            out
+        );
+        assert!(out.contains("PARALLEL_MERGE[\"hi\"] to \"min\" or \"max\""), "{}", out);
+        assert!(out.contains("PARALLEL_MERGE[\"last\"] to \"union\""), "{}", out);
+    }
+
+    #[test]
+    fn cross_record_state() {
+        let out = report(
+            r#"NR == 1 { next }
+            /start/,/stop/
+            { if ($1 != prev) groups++; prev = $1 }
+            { getline; exit }
+            END { print groups }"#,
+        );
+        assert!(out.contains("rule 1: reads NR"), "{}", out);
+        assert!(out.contains("rule 2: uses a range pattern"), "{}", out);
+        assert!(
+            out.contains("rule 3: reads `prev` before setting it"),
+            "{}",
+            out
+        );
+        assert!(out.contains("rule 4: calls exit"), "{}", out);
+        assert!(
+            out.contains("rule 4: uses getline to read the next record"),
+            "{}",
+            out
+        );
+        assert!(out.contains("different order"), "{}", out);
+    }
+
+    #[test]
+    fn streaming_digest() {
+        let out = report(
+            r#"BEGIN { h = digest_init("sha256") }
+            { digest_update(h, $0) }
+            END { print digest_final(h) }"#,
+        );
+        assert!(out.contains("rule 1: calls digest_update"), "{}", out);
+    }
+
+    #[test]
+    fn per_record_temporaries() {
+        let out = report(
+            r#"function f(x, y) { y = x * 2; return y }
+            { split($0, parts, ","); t = parts[1]; total += f(t) }
+            END { print total }"#,
+        );
+        assert!(out.contains("No problems found"), "{}", out);
+        assert!(out.contains("  total: sum "), "{}", out);
+        assert!(!out.contains("  t:"), "{}", out);
+    }
+
+    #[test]
+    fn no_main_loop() {
+        assert!(report("BEGIN { print 1 }").contains("no main rules"));
+    }
+}
@@ -0,0 +1,173 @@
+//! Precompiled programs, written by `zawk compile` and run with `zawk -f prog.zbc`.
+//!
+//! A precompiled program is the bytecode for the interpreter together with what the interpreter
+//! needs to set itself up: register counts, the used-field analysis, and the field and record
+//! separators the program assigns. Loading one skips parsing, type inference and JIT compilation,
+//! which dominate the run time of short invocations of large scripts.
+//!
+//! The file starts with a `#zawk-bytecode` line naming the version of zawk that wrote it, followed
+//! by the program in the compact binary encoding of the `codec` module. Bytecode changes between
+//! versions, so other versions refuse to load it. Nothing in the file is validated beyond being
+//! decodable: like the source it came from, a precompiled program should only be run if it is
+//! trusted.
+use crate::builtins::VERSION;
+use crate::bytecode::{Instr, Interp};
+use crate::cfg::SepAssign;
+use crate::common::{Result, Stage};
+use crate::compile::{Ty, NUM_TYPES};
+use crate::interp::MergeSpec;
+use crate::pushdown::FieldSet;
+use crate::runtime::{self, LineReader, Str};
+
+use serde::{Deserialize, Serialize};
+
+mod codec;
+
+const MAGIC: &[u8] = b"#zawk-bytecode ";
+
+/// Everything but the instructions, which are moved into the interpreter.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Setup {
+    pub(crate) stage: Stage<usize>,
+    pub(crate) regs: [u32; NUM_TYPES],
+    pub(crate) used_fields: FieldSet,
+    pub(crate) named_columns: Option<Vec<Vec<u8>>>,
+    pub(crate) merge_spec: MergeSpec,
+    // The result of `ProgramContext::analyze_sep_assignments`.
+    pub(crate) field_sep: Option<Vec<u8>>,
+    pub(crate) record_sep: Option<Vec<u8>>,
+    pub(crate) sep_unsure: bool,
+    pub(crate) record_limit: Option<u64>,
+    // See `ProgramContext::flushes`.
+    pub(crate) flushes: bool,
+    // Whether the first line of input is a header (`-H`).
+    pub(crate) parse_header: bool,
+}
+
+impl Setup {
+    pub(crate) fn sep_assign(&self) -> SepAssign<'_> {
+        if self.sep_unsure {
+            return SepAssign::Unsure;
+        }
+        SepAssign::Potential {
+            field_sep: self.field_sep.as_deref(),
+            record_sep: self.record_sep.as_deref(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct Program<'a> {
+    pub(crate) instrs: Vec<Vec<Instr<'a>>>,
+    pub(crate) setup: Setup,
+}
+
+impl<'a> Program<'a> {
+    pub(crate) fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut res = MAGIC.to_vec();
+        res.extend(VERSION.as_bytes());
+        res.push(b'\n');
+        match codec::write(self, &mut res) {
+            Ok(()) => Ok(res),
+            Err(e) => err!("failed to serialize program: {}", e),
+        }
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Program<'a>> {
+        let (header, body) = match bytes.iter().position(|b| *b == b'\n') {
+            Some(nl) if bytes.starts_with(MAGIC) => (&bytes[MAGIC.len()..nl], &bytes[nl + 1..]),
+            _ => return err!("not a precompiled zawk program"),
+        };
+        if header != VERSION.as_bytes() {
+            return err!(
+                "program was compiled by zawk {}, but this is zawk {}; recompile it with `zawk compile`",
+                String::from_utf8_lossy(header),
+                VERSION
+            );
+        }
+        match codec::read(body) {
+            Ok(prog) => Ok(prog),
+            Err(e) => err!("corrupt precompiled program: {}", e),
+        }
+    }
+}
+
+/// Whether the file at `path` holds a precompiled program rather than AWK source.
+pub(crate) fn is_precompiled(path: &str) -> bool {
+    use std::io::Read;
+    let mut head = [0u8; MAGIC.len()];
+    std::fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut head))
+        .is_ok_and(|()| head == MAGIC)
+}
+
+/// An interpreter for the program, reading `stdin` and with `argv` in ARGV.
+pub(crate) fn interp<'a, LR: LineReader>(
+    instrs: Vec<Vec<Instr<'a>>>,
+    setup: &Setup,
+    argv: &[String],
+    stdin: LR,
+    ff: impl runtime::writers::FileFactory,
+) -> Interp<'a, LR> {
+    let cols = setup
+        .named_columns
+        .as_ref()
+        .map(|cols| cols.iter().map(|c| &c[..]).collect());
+    let mut interp = Interp::new(
+        instrs,
+        setup.stage.clone(),
+        /*num_workers=*/ 1,
+        |ty: Ty| setup.regs[ty as usize] as usize,
+        stdin,
+        ff,
+        &setup.used_fields,
+        cols,
+    );
+    interp.set_merge_spec(setup.merge_spec.clone());
+    interp.set_argv(argv.iter().map(|s| Str::from(s.clone())));
+    interp
+}
+
+// Serialization for the values in `Instr` that have no natural representation of their own.
+
+pub(crate) mod unique_str {
+    use crate::runtime::{Str, UniqueStr};
+    use serde::{de, Deserializer, Serializer};
+    use std::fmt;
+
+    pub(crate) fn serialize<S: Serializer>(s: &UniqueStr, ser: S) -> Result<S::Ok, S::Error> {
+        s.clone_str().with_bytes(|bs| ser.serialize_bytes(bs))
+    }
+
+    pub(crate) fn deserialize<'a, 'de, D: Deserializer<'de>>(
+        de: D,
+    ) -> Result<UniqueStr<'a>, D::Error> {
+        struct Bytes;
+        impl<'de> de::Visitor<'de> for Bytes {
+            type Value = Vec<u8>;
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a byte string")
+            }
+            fn visit_bytes<E: de::Error>(self, bs: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(bs.to_vec())
+            }
+        }
+        let bytes = de.deserialize_byte_buf(Bytes)?;
+        Ok(Str::from(&bytes[..]).unmoor().upcast().into())
+    }
+}
+
+pub(crate) mod regex {
+    use crate::runtime::regex_engine::Regex;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+    use std::sync::Arc;
+
+    pub(crate) fn serialize<S: Serializer>(re: &Arc<Regex>, ser: S) -> Result<S::Ok, S::Error> {
+        re.as_str().serialize(ser)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Arc<Regex>, D::Error> {
+        let text = String::deserialize(de)?;
+        Regex::new(&text).map(Arc::new).map_err(D::Error::custom)
+    }
+}
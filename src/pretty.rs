@@ -0,0 +1,839 @@
+//! `zawk fmt`: print a program with consistent indentation and spacing.
+//!
+//! The program is parsed and printed back from its syntax tree, so the output does not depend on
+//! how the input was laid out: every rule, function and loop or `if` body gets braces and a line
+//! per statement, indented by four spaces, with single spaces around binary operators. The parser
+//! drops parentheses, so they are printed where the grammar needs them rather than where they were
+//! written.
+//!
+//! Comments are not part of the syntax tree. They are found in the text of the program instead,
+//! and printed before the statement that follows them, or at the end of the line of the statement
+//! they follow. Blank lines between statements are kept, as are `@include` directives.
+use crate::arena::Arena;
+use crate::ast::{self, Binop, Expr, FunDec, Pattern, Stmt, Unop};
+use crate::builtins::Function;
+use crate::common::{Either, FileSpec, Result, Stage};
+use crate::include;
+use crate::lexer::{self, Tok};
+use crate::parsing::syntax::ProgParser;
+
+use hashbrown::HashMap;
+use std::collections::VecDeque;
+
+const INDENT: &str = "    ";
+
+// How tightly each kind of expression binds, following the levels of the grammar in
+// `parsing/syntax.lalrpop`. An expression printed where the grammar expects one that binds more
+// tightly is wrapped in parentheses.
+const EXPR: u8 = 0;
+const ASSIGN: u8 = 1;
+const TERN: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const IN: u8 = 5;
+const MATCH: u8 = 6;
+const CMP: u8 = 7;
+const ADD: u8 = 8;
+const MUL: u8 = 9;
+const POW: u8 = 10;
+const UNARY: u8 = 11;
+const INC: u8 = 12;
+const CAT: u8 = 13;
+const FIELD: u8 = 14;
+const TERM: u8 = 15;
+
+type E<'a> = Expr<'a, 'a, &'a str>;
+type S<'a> = Stmt<'a, 'a, &'a str>;
+
+/// Format the program `text`.
+pub(crate) fn format(text: &str) -> Result<String> {
+    // `@include` directives are not AWK; keep them from the parser, and print them like comments.
+    let mut directives = Vec::new();
+    let text = text
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            if !include::is_directive(line) {
+                return line;
+            }
+            directives.push(Comment {
+                line: i,
+                text: line.trim().to_string(),
+                trailing: false,
+            });
+            ""
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let a = Arena::default();
+    let text = a.alloc_str(&text);
+    let mut prog = ast::Prog::from_stage(&a, Stage::Main(()));
+    let lexer = lexer::Tokenizer::new(text);
+    if let Err(e) = ProgParser::new().parse(&a, &mut Vec::new(), &mut prog, lexer) {
+        return err!("{}", e);
+    }
+    let layout = Layout::new(text);
+    let mut comments = layout.comments;
+    comments.extend(directives);
+    comments.sort_by_key(|c| c.line);
+
+    // The parser keeps each kind of toplevel item apart; put them back in the order they were
+    // written in.
+    let at = |starts: &[(usize, usize)], i: usize| starts.get(i).copied().unwrap_or_default();
+    let mut items = Vec::new();
+    let blocks = [
+        ("BEGIN", &layout.begins, &prog.begin),
+        ("PREPARE", &layout.prepares, &prog.prepare),
+        ("END", &layout.ends, &prog.end),
+    ];
+    for (name, starts, stmts) in blocks {
+        for (i, s) in stmts.iter().enumerate() {
+            items.push((at(starts, i), Item::Block(name, s)));
+        }
+    }
+    for (i, dec) in prog.decs.iter().enumerate() {
+        let locals = layout.locals.get(i).copied().flatten();
+        items.push((at(&layout.funcs, i), Item::Func(dec, locals)));
+    }
+    for (i, (pat, body)) in prog.pats.iter().enumerate() {
+        items.push((at(&layout.rules, i), Item::Rule(pat, *body)));
+    }
+    items.sort_by_key(|(start, _)| *start);
+
+    let mut p = Printer {
+        out: String::new(),
+        depth: 0,
+        text: text.split('\n').collect(),
+        lines: &prog.lines,
+        closes: layout.closes,
+        comments: comments.into(),
+        trailing: None,
+        last: None,
+        fresh: true,
+    };
+    for (i, ((line, _), item)) in items.into_iter().enumerate() {
+        if i > 0 {
+            p.out.push('\n');
+            p.fresh = true;
+        }
+        p.start(Some(line));
+        match item {
+            Item::Block(name, body) => {
+                p.line(&format!("{} {{", name));
+                p.body(body, Some(line));
+            }
+            Item::Func(dec, locals) => {
+                let args = match locals {
+                    Some(l) if l > 0 && l < dec.args.len() => {
+                        format!("{},    {}", dec.args[..l].join(", "), dec.args[l..].join(", "))
+                    }
+                    _ => dec.args.join(", "),
+                };
+                p.line(&format!("function {}({}) {{", dec.name, args));
+                p.body(dec.body, Some(line));
+            }
+            Item::Rule(pat, body) => {
+                let pat = match pat {
+                    Pattern::Null => None,
+                    Pattern::Bool(e) => Some(expr(e, EXPR)),
+                    Pattern::Comma(l, r) => Some(format!("{}, {}", expr(l, TERM), expr(r, TERM))),
+                };
+                match (pat, body) {
+                    (Some(pat), None) => {
+                        p.line(&pat);
+                        continue;
+                    }
+                    (pat, body) => {
+                        p.line(&pat.map_or("{".into(), |pat| format!("{} {{", pat)));
+                        if let Some(body) = body {
+                            p.body(body, Some(line));
+                        }
+                    }
+                }
+            }
+        }
+        p.line("}");
+    }
+    p.flush(usize::MAX);
+    Ok(p.out)
+}
+
+enum Item<'p, 'a> {
+    // BEGIN, PREPARE or END.
+    Block(&'static str, &'a S<'a>),
+    // The function, and the first of its local variables.
+    Func(&'p FunDec<'a, 'a, &'a str>, Option<usize>),
+    Rule(&'p Pattern<'a, 'a, &'a str>, Option<&'a S<'a>>),
+}
+
+struct Comment {
+    line: usize,
+    text: String,
+    // Whether the comment follows code on the same line.
+    trailing: bool,
+}
+
+// What the syntax tree does not record about where things are in the text of a program.
+#[derive(Default)]
+struct Layout {
+    comments: Vec<Comment>,
+    // Where the BEGIN, PREPARE and END keywords and function declarations are, in order.
+    begins: Vec<(usize, usize)>,
+    prepares: Vec<(usize, usize)>,
+    ends: Vec<(usize, usize)>,
+    funcs: Vec<(usize, usize)>,
+    // The line of the closing brace of the block opened on each line.
+    closes: HashMap<usize, usize>,
+    // For each function, the first of its parameters set apart by extra spaces, by convention
+    // the start of its local variables.
+    locals: Vec<Option<usize>>,
+    // Where each rule starts, in order.
+    rules: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    fn new(text: &str) -> Layout {
+        let lines: Vec<&str> = text.split('\n').collect();
+        // Where the last token on each line ends; a comment can only come after it.
+        let mut code_end = vec![None; lines.len()];
+        let mut layout = Layout::default();
+        // `header` is set between a BEGIN, PREPARE, END or function declaration and its opening
+        // brace, `pattern` between the start of a pattern and its action or the end of its line.
+        let (mut depth, mut header, mut pattern) = (0usize, false, false);
+        let (mut prev, mut prev_end) = (Tok::Newline, (0, 0));
+        let mut params = 0;
+        // The lines of the open braces.
+        let mut open = Vec::new();
+        for (start, tok, end) in lexer::Tokenizer::new(text).map_while(|t| t.ok()) {
+            if tok != Tok::Newline && start.line == end.line {
+                code_end[start.line] = Some(end.col);
+            }
+            if depth == 0 {
+                let at = (start.line, start.col);
+                match &tok {
+                    Tok::Begin | Tok::Prepare | Tok::End | Tok::FunDec(_) => {
+                        let starts = match &tok {
+                            Tok::Begin => &mut layout.begins,
+                            Tok::Prepare => &mut layout.prepares,
+                            Tok::End => &mut layout.ends,
+                            _ => &mut layout.funcs,
+                        };
+                        starts.push(at);
+                        header = true;
+                        if let Tok::FunDec(_) = tok {
+                            layout.locals.push(None);
+                            params = 0;
+                        }
+                    }
+                    Tok::Ident(_) if header => {
+                        let spaced = start.line != prev_end.0 || start.col > prev_end.1 + 1;
+                        if prev == Tok::Comma && spaced {
+                            if let Some(locals @ None) = layout.locals.last_mut() {
+                                *locals = Some(params);
+                            }
+                        }
+                        params += 1;
+                    }
+                    Tok::LBrace if header => header = false,
+                    Tok::LBrace => {
+                        if !pattern {
+                            layout.rules.push(at);
+                        }
+                        pattern = false;
+                    }
+                    // Patterns can only continue onto the next line after `&&`, `||` or `,`.
+                    Tok::Newline | Tok::Semi => {
+                        if !matches!(prev, Tok::AND | Tok::OR | Tok::Comma) {
+                            pattern = false;
+                        }
+                    }
+                    Tok::RBrace => {}
+                    _ if header => {}
+                    _ => {
+                        if !pattern {
+                            layout.rules.push(at);
+                        }
+                        pattern = true;
+                    }
+                }
+            }
+            match tok {
+                Tok::LBrace => {
+                    depth += 1;
+                    open.push(start.line);
+                }
+                Tok::RBrace => {
+                    depth = depth.saturating_sub(1);
+                    if let Some(line) = open.pop() {
+                        // The outermost brace on a line closes last.
+                        layout.closes.insert(line, start.line);
+                    }
+                }
+                _ => {}
+            }
+            (prev, prev_end) = (tok, (end.line, end.col));
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let rest = match code_end[i] {
+                Some(col) => line.get(col..).unwrap_or(""),
+                None => line,
+            };
+            let rest = rest.trim();
+            if rest.starts_with('#') {
+                layout.comments.push(Comment {
+                    line: i,
+                    text: rest.to_string(),
+                    trailing: code_end[i].is_some(),
+                });
+            }
+        }
+        layout
+    }
+}
+
+struct Printer<'p> {
+    out: String,
+    depth: usize,
+    // The lines of the program, to keep the blank lines between statements.
+    text: Vec<&'p str>,
+    // The line each statement starts on, from the parser.
+    lines: &'p HashMap<usize, usize>,
+    closes: HashMap<usize, usize>,
+    comments: VecDeque<Comment>,
+    // A comment to print at the end of the next line.
+    trailing: Option<String>,
+    // The line of the last statement or comment printed.
+    last: Option<usize>,
+    // Whether nothing has been printed in the current block yet.
+    fresh: bool,
+}
+
+impl<'p> Printer<'p> {
+    fn line(&mut self, s: &str) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(s);
+        if let Some(c) = self.trailing.take() {
+            self.out.push_str("  ");
+            self.out.push_str(&c);
+        }
+        self.out.push('\n');
+        self.fresh = false;
+    }
+
+    // Keep a blank line before `line`, if there is one in the program.
+    fn blank(&mut self, line: usize) {
+        let blank = line > 0
+            && self.text.get(line - 1).is_some_and(|l| l.trim().is_empty())
+            && self.last.is_some_and(|last| last < line - 1);
+        if blank && !self.fresh {
+            self.out.push('\n');
+        }
+        self.last = Some(line);
+    }
+
+    // Print the comments before `line`.
+    fn flush(&mut self, line: usize) {
+        while self.comments.front().is_some_and(|c| c.line < line) {
+            let c = self.comments.pop_front().unwrap();
+            if c.trailing && self.out.ends_with('\n') {
+                // It follows the last thing printed.
+                self.out.pop();
+                self.out.push_str("  ");
+                self.out.push_str(&c.text);
+                self.out.push('\n');
+            } else {
+                self.blank(c.line);
+                self.line(&c.text);
+            }
+        }
+    }
+
+    // Called before printing a statement or toplevel item that starts on `line`.
+    fn start(&mut self, line: Option<usize>) {
+        let line = match line {
+            Some(line) => line,
+            None => return,
+        };
+        self.flush(line);
+        self.blank(line);
+        if self.comments.front().is_some_and(|c| c.line == line && c.trailing) {
+            self.trailing = self.comments.pop_front().map(|c| c.text);
+        }
+    }
+
+    // The statements of a block, or a single statement, indented between braces. `owner` is the
+    // line of the statement or toplevel item the block belongs to.
+    fn body(&mut self, s: &S, owner: Option<usize>) {
+        self.depth += 1;
+        self.fresh = true;
+        match s {
+            Stmt::Block(stmts) => stmts.iter().for_each(|s| self.stmt(s)),
+            s => self.stmt(s),
+        }
+        // Print the comments at the end of the block inside it.
+        if let Some(close) = owner.and_then(|line| self.closes.get(&line)).copied() {
+            while self.comments.front().is_some_and(|c| !c.trailing && c.line < close) {
+                let c = self.comments.pop_front().unwrap();
+                self.blank(c.line);
+                self.line(&c.text);
+            }
+        }
+        self.depth -= 1;
+    }
+
+    fn stmt(&mut self, s: &S) {
+        let line = self.lines.get(&ast::addr(s)).copied();
+        self.start(line);
+        match s {
+            Stmt::Block(_) => {
+                self.line("{");
+                self.body(s, line);
+            }
+            Stmt::If(cond, then, els) => {
+                let mut head = format!("if ({}) {{", expr(cond, EXPR));
+                let (mut then, mut els) = (*then, *els);
+                loop {
+                    self.line(&head);
+                    self.body(then, line);
+                    match els {
+                        Some(Stmt::If(cond, t, e)) => {
+                            head = format!("}} else if ({}) {{", expr(cond, EXPR));
+                            (then, els) = (*t, *e);
+                        }
+                        Some(e) => {
+                            self.line("} else {");
+                            self.body(e, line);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+            }
+            Stmt::For(init, cond, update, body) => {
+                let mut head = String::from("for (");
+                if let Some(init) = init {
+                    head.push_str(&simple(init));
+                }
+                head.push(';');
+                if let Some(cond) = cond {
+                    head.push(' ');
+                    head.push_str(&expr(cond, EXPR));
+                }
+                head.push(';');
+                if let Some(update) = update {
+                    head.push(' ');
+                    head.push_str(&simple(update));
+                }
+                head.push_str(") {");
+                self.line(&head);
+                self.body(body, line);
+            }
+            Stmt::While(_, cond, body) => {
+                self.line(&format!("while ({}) {{", expr(cond, EXPR)));
+                self.body(body, line);
+            }
+            Stmt::DoWhile(cond, body) => {
+                self.line("do {");
+                self.body(body, line);
+                self.line(&format!("}} while ({})", expr(cond, EXPR)));
+                return;
+            }
+            Stmt::ForEach(v, arr, body) => {
+                self.line(&format!("for ({} in {}) {{", v, expr(arr, EXPR)));
+                self.body(body, line);
+            }
+            s => {
+                let s = simple(s);
+                self.line(&s);
+                return;
+            }
+        }
+        self.line("}");
+    }
+}
+
+// A statement that fits on one line.
+fn simple(s: &S) -> String {
+    match s {
+        Stmt::Expr(e) => match e {
+            Expr::Call(Either::Right(Function::Delete), [arr, key]) => {
+                format!("delete {}[{}]", expr(arr, TERM), subscript(key))
+            }
+            Expr::Call(Either::Right(Function::Clear), [arr]) => {
+                format!("delete {}", expr(arr, TERM))
+            }
+            Expr::Call(Either::Right(Function::Exit), [Expr::ILit(0)]) => "exit".into(),
+            Expr::Call(Either::Right(Function::Exit), [code]) => format!("exit {}", expr(code, ADD)),
+            e => expr(e, EXPR),
+        },
+        Stmt::Print(args, out) => redirect(format!("print {}", list(args, ADD)), out),
+        Stmt::Printf(spec, args, out) => {
+            let spec = expr(spec, ADD);
+            match args.len() {
+                0 => redirect(format!("printf {}", spec), out),
+                _ => redirect(format!("printf {}, {}", spec, list(args, ADD)), out),
+            }
+        }
+        Stmt::Break => "break".into(),
+        Stmt::Continue => "continue".into(),
+        Stmt::Next => "next".into(),
+        Stmt::NextFile => "nextfile".into(),
+        Stmt::Return(None) => "return".into(),
+        Stmt::Return(Some(e)) => format!("return {}", expr(e, EXPR)),
+        Stmt::Block(_)
+        | Stmt::If(..)
+        | Stmt::For(..)
+        | Stmt::While(..)
+        | Stmt::DoWhile(..)
+        | Stmt::ForEach(..) => unreachable!("compound statements span several lines"),
+        Stmt::StartCond(_) | Stmt::EndCond(_) | Stmt::LastCond(_) => {
+            unreachable!("comma patterns are desugared after parsing")
+        }
+    }
+}
+
+fn redirect(s: String, out: &Option<(&E, FileSpec)>) -> String {
+    let s = s.trim_end().to_string();
+    match out {
+        None => s,
+        Some((e, spec)) => {
+            let op = match spec {
+                FileSpec::Trunc => ">",
+                FileSpec::Append => ">>",
+                FileSpec::Cmd => "|",
+            };
+            format!("{} {} {}", s, op, expr(e, FIELD))
+        }
+    }
+}
+
+fn list(es: &[&E], min: u8) -> String {
+    es.iter().map(|e| expr(e, min)).collect::<Vec<_>>().join(", ")
+}
+
+// The parser joins the subscripts of `a[i, j]` and `(i, j) in a` with SUBSEP.
+fn keys<'a>(e: &'a E<'a>) -> Vec<&'a E<'a>> {
+    match e {
+        Expr::Binop(Binop::Concat, Expr::Binop(Binop::Concat, l, Expr::Var("SUBSEP")), r) => {
+            let mut keys = keys(l);
+            keys.push(r);
+            keys
+        }
+        e => vec![e],
+    }
+}
+
+fn subscript(e: &E) -> String {
+    match keys(e).as_slice() {
+        [e] => expr(e, EXPR),
+        keys => list(keys, MATCH),
+    }
+}
+
+/// `e`, in parentheses if it binds more loosely than `min`.
+fn expr(e: &E, min: u8) -> String {
+    let (level, s) = expr_level(e);
+    if level < min {
+        format!("({})", s)
+    } else {
+        s
+    }
+}
+
+fn expr_level(e: &E) -> (u8, String) {
+    match e {
+        Expr::ILit(n) => (TERM, n.to_string()),
+        Expr::FLit(f) => (TERM, format!("{:?}", f)),
+        Expr::StrLit(s) => (TERM, quote(s)),
+        Expr::PatLit(s) => (TERM, regex(s)),
+        Expr::Var(v) => (TERM, v.to_string()),
+        Expr::Index(arr, key) => (TERM, format!("{}[{}]", expr(arr, TERM), subscript(key))),
+        Expr::Call(Either::Left(f), args) => (TERM, format!("{}({})", f, list(args, EXPR))),
+        Expr::Call(Either::Right(Function::Contains), [arr, key]) => {
+            let key = match keys(key).as_slice() {
+                [key] => expr(key, MATCH),
+                keys => format!("({})", list(keys, MATCH)),
+            };
+            (IN, format!("{} in {}", key, expr(arr, MATCH)))
+        }
+        Expr::Call(Either::Right(f), args) => (TERM, format!("{}({})", f, list(args, EXPR))),
+        Expr::IndirectCall(f, args) => (TERM, format!("@{}({})", expr(f, TERM), list(args, EXPR))),
+        // `a != b` and `a !~ b` are parsed as `!(a == b)` and `!(a ~ b)`.
+        Expr::Unop(Unop::Not, Expr::Binop(Binop::EQ, l, r)) => {
+            (CMP, format!("{} != {}", expr(l, ADD), expr(r, CMP)))
+        }
+        Expr::Unop(Unop::Not, Expr::Binop(Binop::IsMatch, l, r)) => {
+            (MATCH, format!("{} !~ {}", expr(l, MATCH), expr(r, CMP)))
+        }
+        Expr::Unop(Unop::Column, e) => (FIELD, format!("${}", expr(e, TERM))),
+        Expr::Unop(op, e) => {
+            let e = expr(e, INC);
+            // `- -x`, not `--x`.
+            let sep = if e.starts_with(['-', '+']) { " " } else { "" };
+            (UNARY, format!("{}{}{}", op, sep, e))
+        }
+        Expr::Binop(Binop::Concat, l, r) => {
+            // A regex after a value would be read as a division.
+            let r = match r {
+                Expr::PatLit(_) => format!("({})", expr(r, TERM)),
+                r => expr(r, FIELD),
+            };
+            (CAT, format!("{} {}", expr(l, CAT), r))
+        }
+        Expr::Binop(op, l, r) => {
+            use Binop::*;
+            let (level, left, right) = match op {
+                Plus | Minus => (ADD, ADD, MUL),
+                Mult | Div | Mod => (MUL, MUL, POW),
+                Pow => (POW, UNARY, POW),
+                LT | GT | LTE | GTE | EQ => (CMP, ADD, CMP),
+                IsMatch => (MATCH, MATCH, CMP),
+                Concat => unreachable!(),
+            };
+            (level, format!("{} {} {}", expr(l, left), op, expr(r, right)))
+        }
+        Expr::Assign(l, r) => (ASSIGN, format!("{} = {}", expr(l, IN), expr(r, ASSIGN))),
+        Expr::AssignOp(l, op, r) => {
+            (ASSIGN, format!("{} {}= {}", expr(l, IN), op, expr(r, ASSIGN)))
+        }
+        Expr::And(l, r) => (AND, format!("{} && {}", expr(l, IN), expr(r, AND))),
+        Expr::Or(l, r) => (OR, format!("{} || {}", expr(l, AND), expr(r, OR))),
+        Expr::ITE(c, t, f) => (
+            TERN,
+            format!("{} ? {} : {}", expr(c, OR), expr(t, TERN), expr(f, TERN)),
+        ),
+        Expr::Inc { is_inc, is_post, x } => {
+            let op = if *is_inc { "++" } else { "--" };
+            let x = expr(x, FIELD);
+            match is_post {
+                true => (INC, format!("{}{}", x, op)),
+                false => (INC, format!("{}{}", op, x)),
+            }
+        }
+        Expr::Getline { into, from, is_file } => {
+            let into = into.map_or(String::new(), |e| format!(" {}", expr(e, TERM)));
+            let s = match from {
+                Some(from) if *is_file => format!("getline{} < {}", into, expr(from, FIELD)),
+                Some(from) => format!("{} | getline{}", expr(from, FIELD), into),
+                None => format!("getline{}", into),
+            };
+            (EXPR, s)
+        }
+        Expr::ReadStdin => (EXPR, "getline".into()),
+        Expr::Cond(_) => unreachable!("comma patterns are desugared after parsing"),
+    }
+}
+
+fn quote(s: &[u8]) -> String {
+    fn push(res: &mut String, c: char) {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            c if c.is_ascii_control() => res.push_str(&format!("\\{:03o}", c as u32)),
+            c => res.push(c),
+        }
+    }
+    let mut res = String::from("\"");
+    match std::str::from_utf8(s) {
+        Ok(s) => s.chars().for_each(|c| push(&mut res, c)),
+        Err(_) => {
+            for b in s {
+                match b.is_ascii() {
+                    true => push(&mut res, *b as char),
+                    false => res.push_str(&format!("\\{:03o}", b)),
+                }
+            }
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn regex(s: &[u8]) -> String {
+    let mut res = String::from("/");
+    for c in String::from_utf8_lossy(s).chars() {
+        if c == '/' {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res.push('/');
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(text: &str) -> String {
+        let res = format(text).unwrap();
+        assert_eq!(format(&res).unwrap(), res, "formatting is not stable");
+        res
+    }
+
+    #[test]
+    fn one_liners() {
+        assert_eq!(
+            fmt("BEGIN{FS=\",\"}NR>1&&$3!=\"\"{s[$1]+=$3;n++}END{for(k in s)print k,s[k]/n;}"),
+            "BEGIN {
+    FS = \",\"
+}
+
+NR > 1 && $3 != \"\" {
+    s[$1] += $3
+    n++
+}
+
+END {
+    for (k in s) {
+        print k, s[k] / n
+    }
+}
+"
+        );
+        assert_eq!(fmt("/foo/"), "/foo/\n");
+        assert_eq!(fmt("/a\\/b/,/c/ { print }"), "/a\\/b/, /c/ {\n    print\n}\n");
+    }
+
+    #[test]
+    fn parentheses() {
+        let cases = [
+            ("{ x = (a + b) * c }", "x = (a + b) * c"),
+            ("{ x = a - (b - c) }", "x = a - (b - c)"),
+            ("{ x = (a - b) - c }", "x = a - b - c"),
+            ("{ x = 2 ^ 3 ^ 2 }", "x = 2 ^ 3 ^ 2"),
+            ("{ x = (2 ^ 3) ^ 2 }", "x = (2 ^ 3) ^ 2"),
+            ("{ print (a > b) }", "print (a > b)"),
+            ("{ print a, b > \"out\" }", "print a, b > \"out\""),
+            ("{ print $1 > ($2 \".txt\") }", "print $1 > ($2 \".txt\")"),
+            ("{ x = a (-1) }", "x = a (-1)"),
+            ("{ x = -(-y) }", "x = -(-y)"),
+            ("{ x = - --y }", "x = - --y"),
+            ("{ x = $(NF-1) }", "x = $(NF - 1)"),
+            ("{ x = !(a in b) }", "x = !(a in b)"),
+            ("{ if ((i,j) in a) delete a[i,j]; }", "if ((i, j) in a) {"),
+            ("{ while ((getline line < f) > 0) n++; }", "while ((getline line < f) > 0) {"),
+            ("{ \"date\" | getline d }", "\"date\" | getline d"),
+            ("{ x = y = z ? 1 : 2 }", "x = y = z ? 1 : 2"),
+            ("{ x = a ~ \"b\" || c !~ /d/ }", "x = a ~ \"b\" || c !~ /d/"),
+            ("{ x = \"a\\tb\\\"c\\\\\" }", "x = \"a\\tb\\\"c\\\\\""),
+            ("{ x = 1.5 + 1e300 }", "x = 1.5 + 1e300"),
+            ("{ exit 1 }", "exit 1"),
+        ];
+        for (prog, line) in cases {
+            let res = fmt(prog);
+            assert!(res.lines().any(|l| l.trim() == line), "{}\n=>\n{}", prog, res);
+        }
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(
+            fmt("function f(a,   b) { if (a) return b; else if (b) return a; else { do a++; while (a < 10); } }"),
+            "function f(a,    b) {
+    if (a) {
+        return b
+    } else if (b) {
+        return a
+    } else {
+        do {
+            a++
+        } while (a < 10)
+    }
+}
+"
+        );
+        assert_eq!(
+            fmt("{ for (;;) break; for (i = 0; i < 3; i++) continue; delete a; printf \"%s\\n\", $1 | \"sort\" }"),
+            "{
+    for (;;) {
+        break
+    }
+    for (i = 0; i < 3; i++) {
+        continue
+    }
+    delete a
+    printf \"%s\\n\", $1 | \"sort\"
+}
+"
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines() {
+        let prog = "@include \"lib\"
+
+# Sum the second column.
+BEGIN { total = 0 }   # start
+
+{
+  total += $2  # add
+
+
+  n++
+  # Count it.
+}
+# Print it.
+END { print total, n }
+";
+        assert_eq!(
+            fmt(prog),
+            "@include \"lib\"
+
+# Sum the second column.
+BEGIN {  # start
+    total = 0
+}
+
+{
+    total += $2  # add
+
+    n++
+    # Count it.
+}
+
+# Print it.
+END {
+    print total, n
+}
+"
+        );
+    }
+
+    #[test]
+    fn items_keep_their_order() {
+        assert_eq!(
+            fmt("END { print 1 }\nfunction f() { return 2 }\nBEGIN { print 3 }\n$1 { print 4 }\n{ print 5 }"),
+            "END {
+    print 1
+}
+
+function f() {
+    return 2
+}
+
+BEGIN {
+    print 3
+}
+
+$1 {
+    print 4
+}
+
+{
+    print 5
+}
+"
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(format("{ print ( }").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+//! Statement counts for `zawk --profile`.
+//!
+//! The profiler follows the interpreter like the debugger does, counting each time a statement
+//! starts (see `trace::FuncLines`) and each time a user-defined function is called. When it is
+//! dropped, along with the interpreter, it writes the text of the program with the counts in the
+//! margin:
+//!
+//! ```text
+//!         function add(a, b) {
+//!       3     return a + b
+//!         }
+//!
+//!         {
+//!       3     total = add(total, $1)
+//!       3     count[$2]++
+//!         }
+//!
+//!         END {
+//!       1     print total, length(count)
+//!         }
+//! ```
+//!
+//! Profiled programs run as `zawk fmt` prints them (see the `pretty` module), which puts every
+//! statement on a line of its own; so each count is that of a single statement, even for a loop
+//! that was written on one line.
+use crate::debug::{Inspect, Monitor};
+use crate::trace::DebugInfo;
+
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+
+pub(crate) struct Profiler {
+    info: Arc<DebugInfo>,
+    text: String,
+    out: String,
+    // For each function and instruction, the index in `counts` of the statement starting there.
+    slots: Vec<Vec<Option<usize>>>,
+    // The line of each statement, and the number of times it has started.
+    counts: Vec<(usize, u64)>,
+    calls: Vec<u64>,
+    depth: usize,
+}
+
+impl Profiler {
+    pub(crate) fn new(info: Arc<DebugInfo>, text: &str, out: &str) -> Profiler {
+        let mut counts = Vec::new();
+        let slots = info
+            .funcs
+            .iter()
+            .map(|f| {
+                let len = f.lines.last().map_or(0, |(instr, _)| instr + 1);
+                let mut slots = vec![None; len];
+                for (instr, line) in f.lines.iter() {
+                    // A statement that generates no code starts where the next one does; count
+                    // the next one.
+                    match slots[*instr] {
+                        Some(slot) => counts[slot] = (*line, 0),
+                        None => {
+                            slots[*instr] = Some(counts.len());
+                            counts.push((*line, 0));
+                        }
+                    }
+                }
+                slots
+            })
+            .collect();
+        Profiler {
+            calls: vec![0; info.funcs.len()],
+            info,
+            text: text.to_string(),
+            out: out.to_string(),
+            slots,
+            counts,
+            depth: 0,
+        }
+    }
+
+    /// The annotated program.
+    pub(crate) fn report(&self) -> String {
+        let mut lines = BTreeMap::<usize, u64>::new();
+        for (line, count) in self.counts.iter() {
+            let max = lines.entry(*line).or_default();
+            *max = (*max).max(*count);
+        }
+        let mut res = String::from("# zawk profile: the number of times each statement ran\n");
+        for (i, text) in self.text.trim_end_matches('\n').lines().enumerate() {
+            let line = match lines.get(&i) {
+                Some(count) => format!("{:>8} {}", count, text),
+                None => format!("{:>8} {}", "", text),
+            };
+            res.push_str(line.trim_end());
+            res.push('\n');
+        }
+        // A function is compiled once for each combination of argument types it is called with.
+        let mut by_name = BTreeMap::<&str, u64>::new();
+        for (f, calls) in self.info.funcs.iter().zip(self.calls.iter()) {
+            if let Some(name) = f.name.as_deref() {
+                *by_name.entry(name).or_default() += calls;
+            }
+        }
+        let mut calls: Vec<_> = by_name.into_iter().map(|(name, calls)| (calls, name)).collect();
+        if !calls.is_empty() {
+            calls.sort_by(|(c1, f1), (c2, f2)| c2.cmp(c1).then(f1.cmp(f2)));
+            res.push_str("\n# function calls\n");
+            for (count, name) in calls {
+                res.push_str(&format!("{:>8} {}()\n", count, name));
+            }
+        }
+        res
+    }
+}
+
+impl Monitor for Profiler {
+    fn at(&mut self, _ctx: &mut dyn Inspect, func: usize, instr: usize, depth: usize) -> bool {
+        // Functions start at their first instruction, one call deeper than the caller.
+        if instr == 0 && depth > self.depth {
+            self.calls[func] += 1;
+        }
+        self.depth = depth;
+        if let Some(Some(slot)) = self.slots[func].get(instr) {
+            self.counts[*slot].1 += 1;
+        }
+        false
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let res = std::fs::File::create(&self.out)
+            .and_then(|mut f| f.write_all(self.report().as_bytes()));
+        if let Err(e) = res {
+            eprintln!("failed to write profile to {}: {}", self.out, e);
+        }
+    }
+}
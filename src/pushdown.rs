@@ -49,6 +49,10 @@
 //! Which corresponds roughly to the AWK snippet `$$2`, or "the field corresponding to the value of
 //! the second column." We cannot predict this value ahead of time, for cases like this, we
 //! contribute "full" sets to registers written by primitives that our analysis cannot introspect.
+//!
+//! Field sets also record whether the program reads `NF`. If it does not, readers only need to
+//! split a record up to the last field the program uses; a program that only uses `$0`, like
+//! `/re/ { print }`, does not need its records split at all.
 
 use std::fmt;
 
@@ -58,12 +62,14 @@ use crate::common::NumTy;
 use crate::compile::HighLevel;
 use crate::dataflow::{self, JoinSemiLattice, Key};
 
-/// Most AWK scripts do not use more than 63 fields, so we represent our sets of used fields
-/// "lossy bitsets" that can precisely represent subsets of [0, 63] but otherwise just say "yes" to
+use serde::{Deserialize, Serialize};
+
+/// Most AWK scripts do not use more than 62 fields, so we represent our sets of used fields
+/// "lossy bitsets" that can precisely represent subsets of [0, 61] but otherwise just say "yes" to
 /// all queries. This is a lowsy choice for a general bitset type, but it's a sound and efficient
 /// choice for this analysis, where we're free to overapproximate the fields that are used by a
 /// particular program.
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct FieldSet(u64);
 
 impl Default for FieldSet {
@@ -76,9 +82,11 @@ impl Default for FieldSet {
 // this library will be field-splitting routines, which will often be passing in counters or vector
 // lengths. We may as well handle the (however unlikely to be exercised) overflow logic here rather
 // than up the stack, in more complicated code, in multiple locations.
-const MAX_INDEX: usize = 62;
+const MAX_INDEX: usize = 61;
+const NF_INDEX: usize = 62;
 const FI_INDEX: usize = 63;
-const FI_MASK: u64 = !(1 << FI_INDEX);
+// The bits that stand for fields.
+const FIELD_MASK: u64 = !((1 << FI_INDEX) | (1 << NF_INDEX));
 
 impl FieldSet {
     pub fn singleton(index: usize) -> FieldSet {
@@ -94,6 +102,19 @@ impl FieldSet {
     pub fn has_fi(&self) -> bool {
         (self.0 != FieldSet::all().0) && ((1 << FI_INDEX) & self.0) != 0
     }
+    /// The set that only says the program reads `NF`.
+    pub fn nf() -> FieldSet {
+        FieldSet(1 << NF_INDEX)
+    }
+    /// Whether the program reads `NF`, so that every field of a record has to be counted even if
+    /// its contents are not used.
+    pub fn counts_fields(&self) -> bool {
+        ((1 << NF_INDEX) & self.0) != 0
+    }
+    /// Whether records need to be split at all: false if the program only uses `$0`.
+    pub fn splits_records(&self) -> bool {
+        self.0 & !1 != 0
+    }
     pub fn is_empty(&self) -> bool {
         self.0 == 0
     }
@@ -109,7 +130,7 @@ impl FieldSet {
 
     /// Return a safe upper bound on the maximum integer value column represented by this set.
     ///
-    /// The `fi` value is ignored. for full sets we return u32::max_value()
+    /// The `fi` and `nf` values are ignored. for full sets we return u32::max_value()
     pub fn max_value(&self) -> u32 {
         if self == &FieldSet::all() {
             u32::max_value()
@@ -118,10 +139,10 @@ impl FieldSet {
         }
     }
     fn min_bit(&self) -> u32 {
-        (FI_MASK & self.0).trailing_zeros()
+        (FIELD_MASK & self.0).trailing_zeros()
     }
     fn max_bit(&self) -> u32 {
-        64 - (FI_MASK & self.0).leading_zeros()
+        64 - (FIELD_MASK & self.0).leading_zeros()
     }
     // Fill is used for `join` constructions, it fills all bits (inclusive) from the minimum bit in
     // self to the maximum bit in rhs.
@@ -188,6 +209,9 @@ impl fmt::Debug for FieldSet {
         if self.has_fi() {
             v.push("FI[..]".into());
         }
+        if self.counts_fields() {
+            v.push("NF".into());
+        }
         write!(f, "{:?}", v)
     }
 }
@@ -218,9 +242,9 @@ mod tests {
         assert_eq!(fs3, fieldset_of_range(3usize..=23));
 
         let mut fs5 = FieldSet::singleton(1);
-        let fs6 = FieldSet::singleton(62);
+        let fs6 = FieldSet::singleton(61);
         fs5.fill(&fs6);
-        assert_eq!(fs5, fieldset_of_range(1usize..=62));
+        assert_eq!(fs5, fieldset_of_range(1usize..=61));
 
         let mut fs7 = FieldSet::all();
         fs7.fill(&fs2);
@@ -235,6 +259,20 @@ mod tests {
         assert_eq!(FieldSet::singleton(23).max_value(), 24);
         assert_eq!(FieldSet::singleton(1024).max_value(), u32::max_value());
         assert_eq!(FieldSet::singleton(0).max_value(), 1);
+        let mut fs = FieldSet::singleton(3);
+        fs.union(&FieldSet::nf());
+        assert_eq!(fs.max_value(), 4);
+    }
+
+    #[test]
+    fn splits_records() {
+        assert!(!FieldSet::empty().splits_records());
+        assert!(!FieldSet::singleton(0).splits_records());
+        assert!(FieldSet::singleton(1).splits_records());
+        assert!(FieldSet::nf().splits_records());
+        assert!(FieldSet::all().splits_records());
+        assert!(FieldSet::all().counts_fields());
+        assert!(!fieldset_of_range(0usize..=61).counts_fields());
     }
 }
 
@@ -246,6 +284,11 @@ pub struct UsedFieldAnalysis {
     // the variables in question.  We can always add it in the future, but since join nodes are
     // always "leaves" we will just add the missing columns as a postprocessing step.
     joins: Vec<(Key /*lhs*/, Key /*rhs*/)>,
+    // Whether the program assigns to a column. CSV and TSV input rebuild $0 from the fields after
+    // that, so reading $0 needs every field.
+    sets_columns: bool,
+    // Whether the program reads NF, directly or through an instruction that counts the fields.
+    reads_nf: bool,
 }
 
 impl Default for UsedFieldAnalysis {
@@ -253,6 +296,8 @@ impl Default for UsedFieldAnalysis {
         let mut res = UsedFieldAnalysis {
             dfa: Default::default(),
             joins: Default::default(),
+            sets_columns: false,
+            reads_nf: false,
         };
         res.dfa.add_src(Key::Rng, FieldSet::all());
         res.dfa.add_src(Key::VarVal(Variable::FI), FieldSet::fi());
@@ -291,14 +336,26 @@ impl UsedFieldAnalysis {
                 self.dfa.add_query(col_reg);
                 self.dfa.add_src(dst, FieldSet::all());
             }
+            SetColumn(..) => self.sets_columns = true,
+            LoadVarInt(dst, Variable::NF) => {
+                self.reads_nf = true;
+                self.dfa.add_src(dst, FieldSet::all());
+            }
             JoinCSV(dst, start, end)
             | JoinTSV(dst, start, end)
             | JoinColumns(dst, start, end, _) => {
+                self.reads_nf = true;
                 self.dfa.add_query(start);
                 self.dfa.add_query(end);
                 self.dfa.add_src(dst, FieldSet::all());
                 self.joins.push((start.into(), end.into()));
             }
+            // The columns are only known once the spec (and perhaps the header) is read.
+            SelectColumns(dst, ..) => {
+                self.reads_nf = true;
+                self.dfa.add_src(dst, FieldSet::all());
+                self.dfa.add_query(dst);
+            }
             _ => dataflow::boilerplate::visit_ll(inst, |dst, _| {
                 self.dfa.add_src(dst, FieldSet::all())
             }),
@@ -314,6 +371,12 @@ impl UsedFieldAnalysis {
             l_flds.fill(r_flds);
             res.union(&l_flds);
         }
+        if self.sets_columns && res.get(0) {
+            return FieldSet::all();
+        }
+        if self.reads_nf {
+            res.union(&FieldSet::nf());
+        }
         res
     }
 }
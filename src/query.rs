@@ -0,0 +1,508 @@
+//! A small SQL-ish query language for the `zawk query` subcommand.
+//!
+//! Queries are translated into an AWK program, which then goes through the usual compilation
+//! pipeline. The supported subset is
+//!
+//! ```text
+//! select <expr>, ... [from <file>] [where <cond>] [group by <expr>, ...] [limit <n>]
+//! ```
+//!
+//! Expressions are AWK expressions with a few SQL spellings added: `=` and `<>` for comparisons,
+//! `and`/`or`/`not`, single-quoted strings, and bare identifiers (or `` `quoted names` ``) for
+//! columns named in the input's header row. `select *` prints records as they were read. The
+//! aggregates `count(*)`, `count(e)`, `sum(e)`, `avg(e)`, `min(e)` and `max(e)` may appear in the
+//! select list; groups are printed in the order they are first seen.
+use crate::common::Result;
+
+use std::fmt::Write;
+
+pub struct Query {
+    /// The generated AWK program.
+    pub program: String,
+    /// Input file named in a `from` clause, if any.
+    pub from: Option<String>,
+    /// Whether the query refers to columns by name, which requires parsing a header row.
+    pub uses_header: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    // A column referenced by name, either as `name` or as a bare identifier.
+    Column(String),
+    // `$` followed by a number or an AWK variable.
+    Field(String),
+    Num(String),
+    Str(String),
+    Punct(&'static str),
+}
+
+const PUNCT: &[&str] = &[
+    "<=", ">=", "<>", "!=", "==", "&&", "||", "(", ")", ",", "*", "+", "-", "/", "%", "<", ">",
+    "=", "!",
+];
+
+// Variables that keep their AWK meaning rather than being treated as column names.
+const AWK_VARS: &[&str] = &["NR", "NF", "FNR", "FILENAME"];
+
+const AGGREGATES: &[&str] = &["count", "sum", "avg", "min", "max"];
+
+fn tokenize(q: &str) -> Result<Vec<Tok>> {
+    let bs = q.as_bytes();
+    let mut res = Vec::new();
+    let mut i = 0;
+    let ident_end = |mut j: usize| {
+        while j < bs.len() && (bs[j].is_ascii_alphanumeric() || bs[j] == b'_') {
+            j += 1;
+        }
+        j
+    };
+    while i < bs.len() {
+        let c = bs[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if c == b'$' {
+            let end = ident_end(i + 1);
+            if end == i + 1 {
+                return err!("expected a column number or variable after `$` in query");
+            }
+            res.push(Tok::Field(q[i..end].into()));
+            i = end;
+        } else if c.is_ascii_digit() || (c == b'.' && bs.get(i + 1).is_some_and(u8::is_ascii_digit))
+        {
+            let mut end = i + 1;
+            while end < bs.len() && (bs[end].is_ascii_digit() || bs[end] == b'.') {
+                end += 1;
+            }
+            res.push(Tok::Num(q[i..end].into()));
+            i = end;
+        } else if c.is_ascii_alphabetic() || c == b'_' {
+            let end = ident_end(i);
+            res.push(Tok::Ident(q[i..end].into()));
+            i = end;
+        } else if c == b'\'' || c == b'"' || c == b'`' {
+            let end = match q[i + 1..].find(c as char) {
+                Some(off) => i + 1 + off,
+                None => return err!("unterminated {} in query", c as char),
+            };
+            let contents = q[i + 1..end].to_string();
+            res.push(if c == b'`' {
+                Tok::Column(contents)
+            } else {
+                Tok::Str(contents)
+            });
+            i = end + 1;
+        } else if let Some(p) = PUNCT.iter().find(|p| q[i..].starts_with(**p)) {
+            res.push(Tok::Punct(p));
+            i += p.len();
+        } else {
+            return err!("unexpected character {:?} in query", q[i..].chars().next().unwrap());
+        }
+    }
+    Ok(res)
+}
+
+fn is_keyword(t: &Tok, kw: &str) -> bool {
+    matches!(t, Tok::Ident(s) if s.eq_ignore_ascii_case(kw))
+}
+
+// Split `toks` at top-level commas.
+fn split_commas(toks: &[Tok]) -> Vec<&[Tok]> {
+    let mut res = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, t) in toks.iter().enumerate() {
+        match t {
+            Tok::Punct("(") => depth += 1,
+            Tok::Punct(")") => depth -= 1,
+            Tok::Punct(",") if depth == 0 => {
+                res.push(&toks[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push(&toks[start..]);
+    res
+}
+
+pub(crate) fn quote(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            res.push('\\');
+        }
+        res.push(c);
+    }
+    res.push('"');
+    res
+}
+
+struct Aggregate {
+    func: &'static str,
+    // None for count(*)
+    arg: Option<String>,
+}
+
+#[derive(Default)]
+struct Translator {
+    uses_header: bool,
+    aggs: Vec<Aggregate>,
+}
+
+impl Translator {
+    // Translate an expression to AWK. When `aggs_ok` is false, aggregate functions are an error;
+    // otherwise each one is replaced by a reference to its per-group result.
+    // Returns the translated expression and whether it refers to the current record outside of
+    // an aggregate.
+    fn expr(&mut self, toks: &[Tok], aggs_ok: bool) -> Result<(String, bool)> {
+        if toks.is_empty() {
+            return err!("missing expression in query");
+        }
+        let mut out = String::new();
+        let mut uses_record = false;
+        let mut i = 0;
+        while i < toks.len() {
+            if !out.is_empty() {
+                out.push(' ');
+            }
+            match &toks[i] {
+                Tok::Ident(id) if id.eq_ignore_ascii_case("and") => out.push_str("&&"),
+                Tok::Ident(id) if id.eq_ignore_ascii_case("or") => out.push_str("||"),
+                Tok::Ident(id) if id.eq_ignore_ascii_case("not") => out.push('!'),
+                Tok::Ident(id) if matches!(toks.get(i + 1), Some(Tok::Punct("("))) => {
+                    let lower = id.to_ascii_lowercase();
+                    let close = match matching_paren(toks, i + 1) {
+                        Some(close) => close,
+                        None => return err!("unbalanced parentheses in query"),
+                    };
+                    if let Some(func) = AGGREGATES.iter().find(|a| **a == lower) {
+                        if !aggs_ok {
+                            return err!("aggregate {}() is only allowed in the select list", id);
+                        }
+                        let inner = &toks[i + 2..close];
+                        let arg = if *func == "count" && inner == [Tok::Punct("*")] {
+                            None
+                        } else {
+                            let (arg, _) = self.expr(inner, false)?;
+                            Some(arg)
+                        };
+                        out.push_str(&self.aggregate_result(func, arg));
+                    } else {
+                        // Any other function call is passed through to AWK.
+                        let mut args = Vec::new();
+                        if close > i + 2 {
+                            for arg in split_commas(&toks[i + 2..close]) {
+                                let (arg, rec) = self.expr(arg, aggs_ok)?;
+                                uses_record |= rec;
+                                args.push(arg);
+                            }
+                        }
+                        write!(&mut out, "{}({})", id, args.join(", ")).unwrap();
+                    }
+                    i = close + 1;
+                    continue;
+                }
+                Tok::Ident(id) if AWK_VARS.contains(&id.as_str()) => {
+                    uses_record = true;
+                    out.push_str(id)
+                }
+                Tok::Ident(id) | Tok::Column(id) => {
+                    self.uses_header = true;
+                    uses_record = true;
+                    write!(&mut out, "$FI[{}]", quote(id)).unwrap();
+                }
+                Tok::Field(f) => {
+                    uses_record = true;
+                    out.push_str(f)
+                }
+                Tok::Num(n) => out.push_str(n),
+                Tok::Str(s) => out.push_str(&quote(s)),
+                Tok::Punct("=") => out.push_str("=="),
+                Tok::Punct("<>") => out.push_str("!="),
+                Tok::Punct(p) => out.push_str(p),
+            }
+            i += 1;
+        }
+        Ok((out, uses_record))
+    }
+
+    fn aggregate_result(&mut self, func: &'static str, arg: Option<String>) -> String {
+        let i = self.aggs.len();
+        self.aggs.push(Aggregate { func, arg });
+        match func {
+            "count" | "sum" => format!("(__agg{}[__k] + 0)", i),
+            "avg" => format!(
+                "(__cnt{0}[__k] ? __agg{0}[__k] / __cnt{0}[__k] : 0)",
+                i
+            ),
+            _ => format!("__agg{}[__k]", i),
+        }
+    }
+
+    // The statements updating each aggregate for the current record in group `__k`.
+    fn aggregate_updates(&self) -> String {
+        let mut out = String::new();
+        for (i, agg) in self.aggs.iter().enumerate() {
+            let arg = agg.arg.as_deref().unwrap_or("");
+            match (agg.func, &agg.arg) {
+                ("count", None) => write!(&mut out, " __agg{}[__k]++;", i),
+                ("count", Some(_)) => write!(&mut out, " if (({}) \"\" != \"\") __agg{}[__k]++;", arg, i),
+                ("sum", _) => write!(&mut out, " __agg{}[__k] += {};", i, arg),
+                ("avg", _) => write!(&mut out, " __agg{0}[__k] += {1}; __cnt{0}[__k]++;", i, arg),
+                (cmp, _) => write!(
+                    &mut out,
+                    " __t = ({1}) + 0; if (!(__k in __agg{0}) || __t {2} __agg{0}[__k]) __agg{0}[__k] = __t;",
+                    i,
+                    arg,
+                    if cmp == "min" { "<" } else { ">" }
+                ),
+            }
+            .unwrap();
+        }
+        out
+    }
+}
+
+fn matching_paren(toks: &[Tok], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, t) in toks.iter().enumerate().skip(open) {
+        match t {
+            Tok::Punct("(") => depth += 1,
+            Tok::Punct(")") => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Translate a query into an AWK program.
+pub fn compile(q: &str) -> Result<Query> {
+    let toks = tokenize(q)?;
+    if toks.first().is_none_or(|t| !is_keyword(t, "select")) {
+        return err!("query must start with `select`");
+    }
+    // Find the clauses, which must appear in this order. clauses[0] is the select list.
+    const KEYWORDS: &[&[&str]] = &[&["from"], &["where"], &["group", "by"], &["limit"]];
+    let mut bounds: Vec<Option<(usize, usize)>> = vec![None; KEYWORDS.len() + 1];
+    bounds[0] = Some((1, toks.len()));
+    let mut last = 0;
+    let mut depth = 0;
+    let mut i = 1;
+    while i < toks.len() {
+        match &toks[i] {
+            Tok::Punct("(") => depth += 1,
+            Tok::Punct(")") => depth -= 1,
+            t if depth == 0 => {
+                let found = KEYWORDS.iter().position(|kw| {
+                    is_keyword(t, kw[0])
+                        && kw[1..].iter().enumerate().all(|(j, k)| {
+                            toks.get(i + 1 + j).is_some_and(|t| is_keyword(t, k))
+                        })
+                });
+                if let Some(c) = found {
+                    let kw = KEYWORDS[c].join(" ");
+                    if bounds[c + 1].is_some() {
+                        return err!("duplicate `{}` clause in query", kw);
+                    }
+                    if c + 1 < last {
+                        return err!("`{}` clause is out of order in query", kw);
+                    }
+                    bounds[last].as_mut().unwrap().1 = i;
+                    i += KEYWORDS[c].len();
+                    bounds[c + 1] = Some((i, toks.len()));
+                    last = c + 1;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    let clause = |c: usize| bounds[c].map(|(start, end)| &toks[start..end]);
+    if let Some(Tok::Ident(id)) = toks.iter().find(|t| is_keyword(t, "order") || is_keyword(t, "having")) {
+        return err!("`{}` is not supported in queries", id);
+    }
+
+    let mut tr = Translator::default();
+    let from = match clause(1) {
+        None => None,
+        Some([Tok::Str(s)]) | Some([Tok::Column(s)]) | Some([Tok::Ident(s)]) => Some(s.clone()),
+        Some(_) => return err!("`from` expects a single (quoted) file name"),
+    };
+    let mut items = Vec::new();
+    let select_all = clause(0) == Some(&[Tok::Punct("*")][..]);
+    for item in split_commas(clause(0).unwrap()) {
+        if select_all {
+            break;
+        }
+        if item == [Tok::Punct("*")] {
+            return err!("`*` must be the only item in the select list");
+        }
+        let n_aggs = tr.aggs.len();
+        let (e, uses_record) = tr.expr(item, true)?;
+        items.push((e, uses_record, tr.aggs.len() > n_aggs));
+    }
+    let filter = match clause(2) {
+        Some(cond) => Some(tr.expr(cond, false)?.0),
+        None => None,
+    };
+    let group_by = match clause(3) {
+        Some(keys) => {
+            let mut res = Vec::new();
+            for k in split_commas(keys) {
+                res.push(tr.expr(k, false)?.0);
+            }
+            Some(res)
+        }
+        None => None,
+    };
+    let limit = match clause(4) {
+        Some([Tok::Num(n)]) if n.parse::<u64>().is_ok() => Some(n.clone()),
+        Some(_) => return err!("`limit` expects a non-negative integer"),
+        None => None,
+    };
+
+    let mut prog = String::new();
+    let skip = filter
+        .map(|f| format!("if (!({})) next; ", f))
+        .unwrap_or_default();
+    if tr.aggs.is_empty() && group_by.is_none() {
+        let outs: Vec<_> = items.into_iter().map(|(e, _, _)| e).collect();
+        if select_all {
+            // A bare print writes the record as it was read.
+            write!(&mut prog, "{{ {}print;", skip).unwrap();
+        } else {
+            write!(&mut prog, "{{ {}print {};", skip, outs.join(", ")).unwrap();
+        }
+        if let Some(n) = limit {
+            write!(&mut prog, " if (++__n >= {}) exit;", n).unwrap();
+        }
+        prog.push_str(" }\n");
+        return Ok(Query {
+            program: prog,
+            from,
+            uses_header: tr.uses_header,
+        });
+    }
+
+    if select_all {
+        return err!("`select *` can not be used with aggregates or `group by`");
+    }
+    // Aggregating: select items without aggregates take their value from the first record of
+    // each group, and items with aggregates may not refer to the record directly.
+    let mut firsts = String::new();
+    let mut outs = Vec::new();
+    for (i, (e, uses_record, has_agg)) in items.into_iter().enumerate() {
+        if has_agg && uses_record {
+            return err!(
+                "select item {} mixes aggregates with columns; columns must be grouped or aggregated",
+                i + 1
+            );
+        }
+        if has_agg || !uses_record {
+            outs.push(e);
+        } else {
+            write!(&mut firsts, " __v{}[__k] = {};", i, e).unwrap();
+            outs.push(format!("__v{}[__k]", i));
+        }
+    }
+    let key = match &group_by {
+        Some(keys) => keys
+            .iter()
+            .map(|k| format!("({})", k))
+            .collect::<Vec<_>>()
+            .join(" SUBSEP "),
+        None => "\"\"".into(),
+    };
+    writeln!(
+        &mut prog,
+        "{{ {}__k = {}; if (!(__k in __seen)) {{ __seen[__k] = 1; __order[++__n] = __k;{} }}{} }}",
+        skip,
+        key,
+        firsts,
+        tr.aggregate_updates()
+    )
+    .unwrap();
+    prog.push_str("END {");
+    if group_by.is_none() {
+        // Aggregates over no rows still produce a (single) result.
+        prog.push_str(" if (__n == 0) __order[++__n] = \"\";");
+    }
+    let bound = match limit {
+        Some(n) => format!("__i <= __n && __i <= {}", n),
+        None => "__i <= __n".into(),
+    };
+    writeln!(
+        &mut prog,
+        " for (__i = 1; {}; __i++) {{ __k = __order[__i]; print {}; }} }}",
+        bound,
+        outs.join(", ")
+    )
+    .unwrap();
+    Ok(Query {
+        program: prog,
+        from,
+        uses_header: tr.uses_header,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_select() {
+        let q = compile("select $1, $3 * 2 where $2 = 'x' and not ($3 < 1) limit 10").unwrap();
+        assert_eq!(
+            q.program,
+            "{ if (!($2 == \"x\" && ! ( $3 < 1 ))) next; print $1, $3 * 2; if (++__n >= 10) exit; }\n"
+        );
+        assert!(!q.uses_header);
+        assert!(q.from.is_none());
+    }
+
+    #[test]
+    fn named_columns() {
+        let q = compile("SELECT region, `unit price` FROM 'sales.csv'").unwrap();
+        assert_eq!(q.program, "{ print $FI[\"region\"], $FI[\"unit price\"]; }\n");
+        assert!(q.uses_header);
+        assert_eq!(q.from.as_deref(), Some("sales.csv"));
+    }
+
+    #[test]
+    fn group_by() {
+        let q = compile("select $1, count(*), avg($3) group by $1 limit 5").unwrap();
+        assert_eq!(
+            q.program,
+            "{ __k = ($1); if (!(__k in __seen)) { __seen[__k] = 1; __order[++__n] = __k; __v0[__k] = $1; } \
+             __agg0[__k]++; __agg1[__k] += $3; __cnt1[__k]++; }\n\
+             END { for (__i = 1; __i <= __n && __i <= 5; __i++) { __k = __order[__i]; \
+             print __v0[__k], (__agg0[__k] + 0), (__cnt1[__k] ? __agg1[__k] / __cnt1[__k] : 0); } }\n"
+        );
+    }
+
+    #[test]
+    fn select_star() {
+        let q = compile("select * where $1 = \"a\"").unwrap();
+        assert_eq!(q.program, "{ if (!($1 == \"a\")) next; print; }\n");
+        assert!(compile("select $1, *").is_err());
+        assert!(compile("select * group by $1").is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(compile("$1, $2").is_err());
+        assert!(compile("select $1 where sum($2) > 1").is_err());
+        assert!(compile("select $1 + sum($2)").is_err());
+        assert!(compile("select $1 group by $1 where $2 > 1").is_err());
+        assert!(compile("select $1 order by $1").is_err());
+        assert!(compile("select $1 limit x").is_err());
+    }
+}
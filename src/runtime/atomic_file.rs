@@ -0,0 +1,135 @@
+//! Output files that only appear once they are complete.
+//!
+//! Output redirected to `atomic://out.csv` (or to any output file, with `--atomic-writes`) is
+//! written to a hidden temporary file next to `out.csv`. The temporary file is renamed to
+//! `out.csv` when the file is closed with `close`, or when the program ends with exit status 0.
+//! If the program fails, with a runtime error or a non-zero exit status, the temporary file is
+//! removed instead, so `out.csv` is never left half-written.
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use crate::runtime::{command, encrypted, rotate};
+
+pub(crate) const PREFIX: &str = "atomic://";
+
+// Set by --atomic-writes: every plain output file is written atomically.
+static ALL_FILES: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn enable_all() {
+    ALL_FILES.store(true, Ordering::Relaxed);
+}
+
+/// The file to write atomically for the output name `name`, if any.
+pub(crate) fn target(name: &str) -> Option<&str> {
+    if let Some(path) = name.strip_prefix(PREFIX) {
+        return Some(path);
+    }
+    let special = [
+        rotate::PREFIX,
+        encrypted::PREFIX,
+        command::SPAWN_PREFIX,
+        "/dev/",
+    ];
+    if ALL_FILES.load(Ordering::Relaxed) && !special.iter().any(|p| name.starts_with(p)) {
+        return Some(name);
+    }
+    None
+}
+
+/// An output file, along with the temporary file that holds its new contents.
+pub(crate) struct AtomicFile {
+    path: PathBuf,
+    tmp: PathBuf,
+}
+
+impl AtomicFile {
+    /// Pick a temporary file in the same directory as `path` (so that it can be renamed over
+    /// `path`). The file itself is only created once something is written.
+    pub(crate) fn new(path: &str) -> AtomicFile {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let path = PathBuf::from(path);
+        let name = path
+            .file_name()
+            .map_or_else(Default::default, |name| name.to_string_lossy());
+        let tmp_name = format!(
+            ".{}.{}-{}.tmp",
+            name,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let tmp = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.join(tmp_name),
+            _ => PathBuf::from(tmp_name),
+        };
+        AtomicFile { path, tmp }
+    }
+
+    /// Open the temporary file for writing. Appending to a file that has not been written yet
+    /// starts from a copy of its current contents.
+    pub(crate) fn opener(&self) -> impl Fn(bool) -> io::Result<File> + Send + 'static {
+        let path = self.path.clone();
+        let tmp = self.tmp.clone();
+        move |append| {
+            if append && fs::metadata(&tmp).map_or(true, |m| m.len() == 0) {
+                match fs::copy(&path, &tmp) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .append(append)
+                .truncate(!append)
+                .open(&tmp)
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Move what has been written so far into place. The file must have been closed first.
+    pub(crate) fn commit(&self) -> io::Result<()> {
+        if let Ok(meta) = fs::metadata(&self.path) {
+            let _ = fs::set_permissions(&self.tmp, meta.permissions());
+        }
+        match fs::rename(&self.tmp, &self.path) {
+            // Nothing was written since the last commit.
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+
+    /// Throw away anything written since the last commit.
+    pub(crate) fn discard(&self) {
+        let _ = fs::remove_file(&self.tmp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commit_and_discard() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("out");
+        fs::write(&path, "old\n").unwrap();
+        let file = AtomicFile::new(path.to_str().unwrap());
+        let open = file.opener();
+        io::Write::write_all(&mut open(true).unwrap(), b"new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\n");
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\nnew\n");
+
+        io::Write::write_all(&mut open(false).unwrap(), b"partial\n").unwrap();
+        file.discard();
+        file.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old\nnew\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,317 @@
+//! Arbitrary-precision arithmetic for `-M` and the `big_add`, `big_sub`, `big_mul`, `big_div`,
+//! `big_mod`, `big_pow` and `big_cmp` functions.
+//!
+//! Numbers are passed around as strings and computed as decimals of any size, so ids and checksums
+//! past 2^53 keep all their digits: `"9007199254740993" + 0` is `9007199254740992` with floats, but
+//! `big_add("9007199254740993", 0)` is `9007199254740993`. Addition, subtraction, multiplication,
+//! `%` and integer powers are exact; division, and powers with a negative exponent, round to
+//! [`PRECISION`] significant digits. Arguments are read like AWK reads numbers, by their numeric
+//! prefix. Infinities, NaNs, division by zero and fractional powers fall back to floats.
+//!
+//! With `-M`, `cfg` lowers the arithmetic and comparison operators to these functions, so the
+//! numbers a program computes are strings holding exact values. Comparisons of two strings that
+//! look like numbers are then numeric, and such strings are false when they are zero, as in gawk.
+use crate::runtime::{convert, Float, Int, Str};
+
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+/// The significant digits kept by division.
+pub(crate) const PRECISION: u64 = 34;
+
+// Powers with more digits than this are computed with floats rather than building a huge integer.
+const MAX_POW_DIGITS: u64 = 1_000_000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// `-M`: compute with arbitrary-precision numbers.
+pub(crate) fn enable() {
+    ENABLED.store(true, AtomicOrdering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(AtomicOrdering::Relaxed)
+}
+
+enum Num {
+    Exact(BigDecimal),
+    Float(Float),
+}
+
+impl Num {
+    fn to_float(&self) -> Float {
+        match self {
+            Num::Exact(d) => d.to_f64().unwrap_or(Float::NAN),
+            Num::Float(f) => *f,
+        }
+    }
+}
+
+// The end of the number at the start of `bs`: a sign, digits with an optional fraction, and an
+// optional exponent. Returns 0 if there is no number.
+fn number_len(bs: &[u8]) -> usize {
+    let digits = |from: usize| from + bs[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let start = usize::from(matches!(bs.first(), Some(b'+' | b'-')));
+    let int_end = digits(start);
+    let mut end = int_end;
+    if bs.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    if end == start || (int_end == start && end == start + 1) {
+        return 0;
+    }
+    if let Some(b'e' | b'E') = bs.get(end) {
+        let exp = end + 1 + usize::from(matches!(bs.get(end + 1), Some(b'+' | b'-')));
+        let exp_end = digits(exp);
+        if exp_end > exp {
+            end = exp_end;
+        }
+    }
+    end
+}
+
+// The float spelled by `s` if it is an infinity or a NaN, as in `-inf` or `nan`.
+fn non_finite(s: &str) -> Option<Float> {
+    s.parse::<Float>().ok().filter(|f| !f.is_finite())
+}
+
+fn parse(s: &str) -> Num {
+    let s = s.trim_start();
+    if let Some(f) = non_finite(s.trim_end()) {
+        return Num::Float(f);
+    }
+    let len = number_len(s.as_bytes());
+    match BigDecimal::from_str(s[..len].trim_end_matches('.')) {
+        Ok(d) => Num::Exact(d),
+        Err(_) => Num::Exact(BigDecimal::zero()),
+    }
+}
+
+/// Whether all of `s` (but for surrounding blanks) is a number, so that it compares numerically.
+pub(crate) fn looks_numeric(s: &str) -> bool {
+    let s = s.trim();
+    !s.is_empty() && (number_len(s.as_bytes()) == s.len() || non_finite(s).is_some())
+}
+
+// Write `d` out in full, without an exponent or trailing zeros.
+fn show_exact(d: &BigDecimal) -> String {
+    let (int, scale) = d.normalized().into_bigint_and_exponent();
+    if int.is_zero() {
+        return "0".into();
+    }
+    let mut digits = int.abs().to_string();
+    if scale <= 0 {
+        digits.extend(std::iter::repeat_n('0', -scale as usize));
+    } else {
+        let scale = scale as usize;
+        if digits.len() <= scale {
+            digits.insert_str(0, &"0".repeat(scale - digits.len() + 1));
+        }
+        digits.insert(digits.len() - scale, '.');
+    }
+    if int.is_negative() {
+        digits.insert(0, '-');
+    }
+    digits
+}
+
+fn show(n: Num) -> String {
+    match n {
+        Num::Exact(d) => show_exact(&d),
+        Num::Float(f) => convert::<Float, Str>(f).to_string(),
+    }
+}
+
+// Apply `exact` to `x` and `y` if both are exact numbers and it gives a result, and `float`
+// otherwise.
+fn binop(
+    x: &str,
+    y: &str,
+    exact: impl FnOnce(&BigDecimal, &BigDecimal) -> Option<BigDecimal>,
+    float: impl FnOnce(Float, Float) -> Float,
+) -> String {
+    let (x, y) = (parse(x), parse(y));
+    if let (Num::Exact(a), Num::Exact(b)) = (&x, &y) {
+        if let Some(res) = exact(a, b) {
+            return show_exact(&res);
+        }
+    }
+    show(Num::Float(float(x.to_float(), y.to_float())))
+}
+
+pub(crate) fn add(x: &str, y: &str) -> String {
+    binop(x, y, |a, b| Some(a + b), |a, b| a + b)
+}
+
+pub(crate) fn sub(x: &str, y: &str) -> String {
+    binop(x, y, |a, b| Some(a - b), |a, b| a - b)
+}
+
+pub(crate) fn mul(x: &str, y: &str) -> String {
+    binop(x, y, |a, b| Some(a * b), |a, b| a * b)
+}
+
+fn divide(a: &BigDecimal, b: &BigDecimal) -> Option<BigDecimal> {
+    if b.is_zero() {
+        return None;
+    }
+    Some((a / b).with_prec(PRECISION))
+}
+
+/// `x / y`, to [`PRECISION`] significant digits.
+pub(crate) fn div(x: &str, y: &str) -> String {
+    binop(x, y, divide, |a, b| a / b)
+}
+
+/// `x % y`, which has the sign of `x`, as with floats.
+pub(crate) fn rem(x: &str, y: &str) -> String {
+    binop(
+        x,
+        y,
+        |a, b| {
+            if b.is_zero() {
+                return None;
+            }
+            // Bring both to the same scale, where the remainder of the integers is exact.
+            let scale = std::cmp::max(a.fractional_digit_count(), b.fractional_digit_count());
+            let (a, _) = a.with_scale(scale).into_bigint_and_exponent();
+            let (b, _) = b.with_scale(scale).into_bigint_and_exponent();
+            Some(BigDecimal::new(a % b, scale))
+        },
+        |a, b| a % b,
+    )
+}
+
+fn powi(a: &BigDecimal, mut exp: u64) -> BigDecimal {
+    let mut base = a.clone();
+    let mut res = BigDecimal::from(1);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            res = &res * &base;
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = &base * &base;
+        }
+    }
+    res
+}
+
+/// `x ^ y`: exact for integer `y`, rounded to [`PRECISION`] digits for negative `y`.
+pub(crate) fn pow(x: &str, y: &str) -> String {
+    binop(
+        x,
+        y,
+        |a, b| {
+            if !b.is_integer() {
+                return None;
+            }
+            let exp = b.to_i64()?;
+            let digits = a.digits().max(1).checked_mul(exp.unsigned_abs())?;
+            if digits > MAX_POW_DIGITS {
+                return None;
+            }
+            let res = powi(a, exp.unsigned_abs());
+            if exp < 0 {
+                divide(&BigDecimal::from(1), &res)
+            } else {
+                Some(res)
+            }
+        },
+        Float::powf,
+    )
+}
+
+fn ordering(o: Ordering) -> Int {
+    match o {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// Compare `x` and `y`: -1, 0 or 1. Strings that both look like numbers are compared as numbers,
+/// and other strings by their bytes. NaN is greater than everything.
+pub(crate) fn cmp(x: &str, y: &str) -> Int {
+    if !(looks_numeric(x) && looks_numeric(y)) {
+        return ordering(x.cmp(y));
+    }
+    match (parse(x), parse(y)) {
+        (Num::Exact(a), Num::Exact(b)) => ordering(a.cmp(&b)),
+        (a, b) => a
+            .to_float()
+            .partial_cmp(&b.to_float())
+            .map_or(1, ordering),
+    }
+}
+
+/// Whether `s` is false as a condition: empty, or a number equal to zero.
+pub(crate) fn is_false(s: &str) -> bool {
+    s.is_empty()
+        || (looks_numeric(s)
+            && match parse(s) {
+                Num::Exact(d) => d.is_zero(),
+                Num::Float(f) => f == 0.0,
+            })
+}
+
+/// The integer part of `s`, in full, if it is a finite number; for printf's `%d`.
+pub(crate) fn int_text(s: &str) -> Option<String> {
+    match parse(s) {
+        Num::Exact(d) => {
+            let (int, scale) = d.into_bigint_and_exponent();
+            let int = if scale > 0 {
+                int / BigInt::from(10).pow(scale as u32)
+            } else {
+                int * BigInt::from(10).pow((-scale) as u32)
+            };
+            Some(int.to_string())
+        }
+        Num::Float(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(add("9007199254740993", "0"), "9007199254740993");
+        assert_eq!(
+            mul("123456789012345678901234567890", "10"),
+            "1234567890123456789012345678900"
+        );
+        assert_eq!(sub("0.3", "0.1"), "0.2");
+        assert_eq!(add(" 12.50 EUR", "abc"), "12.5");
+        assert_eq!(div("1", "3"), "0.3333333333333333333333333333333333");
+        assert_eq!(div("10", "4"), "2.5");
+        assert_eq!(div("1", "0"), "inf");
+        assert_eq!(rem("-7", "3"), "-1");
+        assert_eq!(rem("7.5", "2"), "1.5");
+        assert_eq!(pow("2", "100"), "1267650600228229401496703205376");
+        assert_eq!(pow("2", "-2"), "0.25");
+        assert_eq!(pow("4", "0.5"), "2");
+        assert_eq!(add("1e3", "1"), "1001");
+        assert_eq!(add("inf", "1"), "inf");
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(cmp("18446744073709551617", "18446744073709551616"), 1);
+        assert_eq!(cmp("10", "9"), 1);
+        assert_eq!(cmp("1.0", "1"), 0);
+        assert_eq!(cmp("abc", "9"), 1);
+        assert_eq!(cmp("10", "9x"), -1);
+        assert!(is_false("0.00"));
+        assert!(is_false(""));
+        assert!(!is_false("0x"));
+        assert!(!is_false("1e-40"));
+        assert_eq!(int_text("-12345678901234567890.9").unwrap(), "-12345678901234567890");
+        assert_eq!(int_text("1.5e20").unwrap(), "150000000000000000000");
+    }
+}
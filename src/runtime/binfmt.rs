@@ -0,0 +1,230 @@
+//! Binary record formats for `from_msgpack`, `from_cbor` and `from_protobuf`.
+//!
+//! Each decoder yields an array shaped like `from_json`: a map gives its keys, an array is keyed
+//! from 1, nested values are JSON text and booleans are `1`/`0`. Binary strings, which JSON cannot
+//! hold, are base64 encoded.
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use lazy_static::lazy_static;
+use prost_reflect::{DescriptorPool, DynamicMessage, SerializeOptions};
+use serde_json::{Map, Number, Value};
+
+use crate::runtime::{SharedMap, Str, StrMap};
+
+pub(crate) fn float(f: f64) -> Value {
+    Number::from_f64(f).map(Value::Number).unwrap_or_else(|| Value::String(f.to_string()))
+}
+
+fn key_text(key: Value) -> String {
+    match key {
+        Value::String(s) => s,
+        key => key.to_string(),
+    }
+}
+
+fn msgpack_json(value: rmpv::Value) -> Value {
+    use rmpv::Value as M;
+    match value {
+        M::Nil => Value::Null,
+        M::Boolean(b) => Value::Bool(b),
+        M::Integer(i) => match (i.as_i64(), i.as_u64()) {
+            (Some(n), _) => Value::from(n),
+            (None, Some(n)) => Value::from(n),
+            _ => Value::Null,
+        },
+        M::F32(f) => float(f as f64),
+        M::F64(f) => float(f),
+        M::String(s) => match s.into_str() {
+            Some(s) => Value::String(s),
+            None => Value::Null,
+        },
+        M::Binary(bytes) | M::Ext(_, bytes) => Value::String(STANDARD.encode(bytes)),
+        M::Array(items) => Value::Array(items.into_iter().map(msgpack_json).collect()),
+        M::Map(entries) => Value::Object(
+            entries.into_iter().map(|(k, v)| (key_text(msgpack_json(k)), msgpack_json(v))).collect::<Map<_, _>>(),
+        ),
+    }
+}
+
+fn cbor_json(value: ciborium::Value) -> Value {
+    use ciborium::Value as C;
+    match value {
+        C::Null => Value::Null,
+        C::Bool(b) => Value::Bool(b),
+        C::Integer(i) => {
+            let n = i128::from(i);
+            match (i64::try_from(n), u64::try_from(n)) {
+                (Ok(n), _) => Value::from(n),
+                (_, Ok(n)) => Value::from(n),
+                _ => Value::String(n.to_string()),
+            }
+        }
+        C::Float(f) => float(f),
+        C::Text(s) => Value::String(s),
+        C::Bytes(bytes) => Value::String(STANDARD.encode(bytes)),
+        C::Tag(_, inner) => cbor_json(*inner),
+        C::Array(items) => Value::Array(items.into_iter().map(cbor_json).collect()),
+        C::Map(entries) => {
+            Value::Object(entries.into_iter().map(|(k, v)| (key_text(cbor_json(k)), cbor_json(v))).collect::<Map<_, _>>())
+        }
+        _ => Value::Null,
+    }
+}
+
+pub(crate) fn field_text(value: Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Bool(b) => if b { "1" } else { "0" }.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s,
+        value => value.to_string(),
+    }
+}
+
+fn to_map<'a>(value: Value) -> Result<StrMap<'a, Str<'a>>, String> {
+    let entries: Vec<(String, Value)> = match value {
+        Value::Object(obj) => obj.into_iter().collect(),
+        Value::Array(items) => items.into_iter().enumerate().map(|(i, v)| ((i + 1).to_string(), v)).collect(),
+        value => return Err(format!("expected a map or an array, got {}", value)),
+    };
+    let map: hashbrown::HashMap<Str, Str> =
+        entries.into_iter().map(|(k, v)| (Str::from(k), Str::from(field_text(v)))).collect();
+    Ok(SharedMap::from(map))
+}
+
+pub(crate) fn from_msgpack<'a>(bytes: &[u8]) -> Result<StrMap<'a, Str<'a>>, String> {
+    let value = rmpv::decode::read_value(&mut &bytes[..]).map_err(|e| e.to_string())?;
+    to_map(msgpack_json(value))
+}
+
+fn cbor_error(e: ciborium::de::Error<std::io::Error>) -> String {
+    use ciborium::de::Error;
+    match e {
+        Error::Io(_) => "unexpected end of CBOR data".to_string(),
+        Error::Syntax(offset) => format!("invalid CBOR at byte {}", offset),
+        Error::Semantic(Some(offset), msg) => format!("{} at byte {}", msg, offset),
+        Error::Semantic(None, msg) => msg,
+        Error::RecursionLimitExceeded => "CBOR nested too deeply".to_string(),
+    }
+}
+
+pub(crate) fn from_cbor<'a>(bytes: &[u8]) -> Result<StrMap<'a, Str<'a>>, String> {
+    let value: ciborium::Value = ciborium::from_reader(bytes).map_err(cbor_error)?;
+    to_map(cbor_json(value))
+}
+
+lazy_static! {
+    /// Descriptor sets by path, so that each file is read once per run.
+    static ref POOLS: RwLock<HashMap<String, DescriptorPool>> = RwLock::new(HashMap::new());
+}
+
+fn descriptor_pool(path: &str) -> Result<DescriptorPool, String> {
+    if let Some(pool) = POOLS.read().unwrap().get(path) {
+        return Ok(pool.clone());
+    }
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let pool = DescriptorPool::decode(bytes.as_slice()).map_err(|e| format!("{}: {}", path, e))?;
+    POOLS.write().unwrap().insert(path.to_string(), pool.clone());
+    Ok(pool)
+}
+
+/// Decodes a `message` (fully qualified, like `shop.Order`) using the descriptor set at `descriptor_path`,
+/// as written by `protoc --include_imports --descriptor_set_out`. Fields keep their `.proto` names.
+pub(crate) fn from_protobuf<'a>(bytes: &[u8], descriptor_path: &str, message: &str) -> Result<StrMap<'a, Str<'a>>, String> {
+    let pool = descriptor_pool(descriptor_path)?;
+    let desc = pool
+        .get_message_by_name(message)
+        .ok_or_else(|| format!("message {} not found in {}", message, descriptor_path))?;
+    let msg = DynamicMessage::decode(desc, bytes).map_err(|e| e.to_string())?;
+    let options = SerializeOptions::new().use_proto_field_name(true).stringify_64_bit_integers(false);
+    let value = msg.serialize_with_options(serde_json::value::Serializer, &options).map_err(|e| e.to_string())?;
+    to_map(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost_reflect::prost::Message;
+    use prost_reflect::prost_types::{
+        field_descriptor_proto::{Label, Type},
+        DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+    };
+
+    fn get(map: &StrMap<Str>, key: &str) -> String {
+        map.get(&Str::from(key.to_string())).to_string()
+    }
+
+    #[test]
+    fn test_from_msgpack() {
+        // {"id": 7, "ok": true, "tags": ["a"], "raw": b"\x01\x02"}
+        let bytes = b"\x84\xa2id\x07\xa2ok\xc3\xa4tags\x91\xa1a\xa3raw\xc4\x02\x01\x02";
+        let map = from_msgpack(bytes).unwrap();
+        assert_eq!(get(&map, "id"), "7");
+        assert_eq!(get(&map, "ok"), "1");
+        assert_eq!(get(&map, "tags"), r#"["a"]"#);
+        assert_eq!(get(&map, "raw"), "AQI=");
+        assert_eq!(get(&from_msgpack(b"\x92\x01\xa1b").unwrap(), "2"), "b");
+        assert_eq!(from_msgpack(b"\x07").unwrap_err(), "expected a map or an array, got 7");
+        assert!(from_msgpack(b"\x84").is_err());
+    }
+
+    #[test]
+    fn test_from_cbor() {
+        // {"name": "x", "n": -3, "f": 1.5, 1: null}
+        let bytes = b"\xa4\x64name\x61x\x61n\x22\x61f\xf9\x3e\x00\x01\xf6";
+        let map = from_cbor(bytes).unwrap();
+        assert_eq!(get(&map, "name"), "x");
+        assert_eq!(get(&map, "n"), "-3");
+        assert_eq!(get(&map, "f"), "1.5");
+        assert_eq!(get(&map, "1"), "");
+        assert_eq!(from_cbor(b"\xa1").unwrap_err(), "unexpected end of CBOR data");
+        assert_eq!(from_cbor(b"\x1f").unwrap_err(), "invalid CBOR at byte 0");
+    }
+
+    #[test]
+    fn test_from_protobuf() {
+        let field = |name: &str, number: i32, ty: Type, label: Label| FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            r#type: Some(ty as i32),
+            label: Some(label as i32),
+            ..Default::default()
+        };
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("shop.proto".to_string()),
+                package: Some("shop".to_string()),
+                syntax: Some("proto3".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Order".to_string()),
+                    field: vec![
+                        field("order_id", 1, Type::Int64, Label::Optional),
+                        field("customer", 2, Type::String, Label::Optional),
+                        field("items", 3, Type::String, Label::Repeated),
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("shop.desc");
+        std::fs::write(&path, set.encode_to_vec()).unwrap();
+        let path = path.to_str().unwrap();
+
+        // order_id = 42, customer = "ann", items = ["pen", "ink"]
+        let bytes = b"\x08\x2a\x12\x03ann\x1a\x03pen\x1a\x03ink";
+        let map = from_protobuf(bytes, path, "shop.Order").unwrap();
+        assert_eq!(get(&map, "order_id"), "42");
+        assert_eq!(get(&map, "customer"), "ann");
+        assert_eq!(get(&map, "items"), r#"["pen","ink"]"#);
+        assert_eq!(from_protobuf(b"", path, "shop.Order").unwrap().len(), 0);
+        assert_eq!(
+            from_protobuf(bytes, path, "shop.Missing").unwrap_err(),
+            format!("message shop.Missing not found in {}", path)
+        );
+        assert!(from_protobuf(b"\x0a", path, "shop.Order").is_err());
+    }
+}
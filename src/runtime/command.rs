@@ -1,9 +1,16 @@
 use std::io;
-use std::process::{ChildStdin, Command, Stdio};
+use std::process::{ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 
 use grep_cli::{CommandError, CommandReader};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
 
-use crate::runtime::Int;
+use crate::runtime::{Int, IntMap, Str};
+
+/// Handles returned by `spawn` look like `spawn://1`.
+pub(crate) const SPAWN_PREFIX: &str = "spawn://";
 
 fn prepare_command(bs: &[u8]) -> io::Result<Command> {
     let prog = match std::str::from_utf8(bs) {
@@ -45,3 +52,181 @@ pub fn command_for_read(bs: &[u8]) -> Result<CommandReader, CommandError> {
     let mut cmd = prepare_command(bs)?;
     CommandReader::new(&mut cmd)
 }
+
+// Build a command from an argument vector, without going through the shell.
+fn argv_command(argv: &[String]) -> io::Result<Command> {
+    match argv.split_first() {
+        Some((prog, args)) => {
+            let mut cmd = Command::new(prog);
+            cmd.args(args);
+            Ok(cmd)
+        }
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty argument list",
+        )),
+    }
+}
+
+fn capture_output(cmd: io::Result<Command>) -> String {
+    // Like `cmd | getline`, the exit status is ignored and stderr passes through.
+    match cmd.and_then(|mut cmd| cmd.stdin(Stdio::inherit()).stderr(Stdio::inherit()).output()) {
+        Ok(out) => String::from_utf8_lossy(&out.stdout).into_owned(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Run `bs` with the shell, returning everything it writes to standard output.
+pub fn exec_output(bs: &[u8]) -> String {
+    capture_output(prepare_command(bs))
+}
+
+/// Run the program `argv[1]` with arguments `argv[2]`, `argv[3]`, ... and without a shell,
+/// returning everything it writes to standard output.
+pub(crate) fn exec_output_argv(argv: &IntMap<Str>) -> String {
+    capture_output(argv_command(&argv_of(argv)))
+}
+
+// The values of an array indexed from 1, as produced by split(), in order.
+fn argv_of(argv: &IntMap<Str>) -> Vec<String> {
+    let mut keys = argv.to_vec();
+    keys.sort_unstable();
+    keys.into_iter().map(|k| argv.get(&k).to_string()).collect()
+}
+
+// The pipes of processes started with `spawn` that have not been claimed by a `print` or
+// `getline` redirection yet.
+struct Spawned {
+    stdin: Option<ChildStdin>,
+    stdout: Option<ChildStdout>,
+}
+
+lazy_static! {
+    static ref SPAWNED: Mutex<HashMap<String, Spawned>> = Mutex::new(HashMap::new());
+}
+static NEXT_SPAWN: AtomicU64 = AtomicU64::new(1);
+
+/// Start the command `cmd` without a shell, returning a handle that can be written to with
+/// `print > handle` (the process's standard input) and read from with `getline < handle` (its
+/// standard output). `cmd` is split into words following the shell's quoting rules, but is not
+/// otherwise interpreted. Returns the empty string if the process could not be started.
+pub fn spawn(cmd: &[u8]) -> String {
+    match std::str::from_utf8(cmd).ok().and_then(shlex::split) {
+        Some(argv) => spawn_process(&argv),
+        None => String::new(),
+    }
+}
+
+/// Like `spawn`, but with the program and its arguments given as an array.
+pub(crate) fn spawn_argv(argv: &IntMap<Str>) -> String {
+    spawn_process(&argv_of(argv))
+}
+
+fn spawn_process(argv: &[String]) -> String {
+    let mut child = match argv_command(argv).and_then(|mut cmd| {
+        cmd.stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+    }) {
+        Ok(child) => child,
+        Err(_) => return String::new(),
+    };
+    let handle = format!(
+        "{}{}",
+        SPAWN_PREFIX,
+        NEXT_SPAWN.fetch_add(1, Ordering::Relaxed)
+    );
+    let spawned = Spawned {
+        stdin: child.stdin.take(),
+        stdout: child.stdout.take(),
+    };
+    SPAWNED.lock().unwrap().insert(handle.clone(), spawned);
+    // Reap the process once it exits; its pipes live on in SPAWNED.
+    std::thread::spawn(move || child.wait());
+    handle
+}
+
+fn not_spawned(handle: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} is not a running process started with spawn()", handle),
+    )
+}
+
+/// Claim the standard input of a spawned process, for `print > handle`.
+pub(crate) fn spawned_stdin(handle: &str) -> io::Result<ChildStdin> {
+    SPAWNED
+        .lock()
+        .unwrap()
+        .get_mut(handle)
+        .and_then(|s| s.stdin.take())
+        .ok_or_else(|| not_spawned(handle))
+}
+
+/// Claim the standard output of a spawned process, for `getline < handle`.
+pub(crate) fn spawned_stdout(handle: &str) -> io::Result<ChildStdout> {
+    SPAWNED
+        .lock()
+        .unwrap()
+        .get_mut(handle)
+        .and_then(|s| s.stdout.take())
+        .ok_or_else(|| not_spawned(handle))
+}
+
+/// `close(handle)` on a spawned process that was never printed to still needs to close its
+/// standard input, or a program like `sort` would wait for more input forever.
+pub(crate) fn close_spawned_stdin(handle: &[u8]) {
+    if !handle.starts_with(SPAWN_PREFIX.as_bytes()) {
+        return;
+    }
+    let handle = String::from_utf8_lossy(handle);
+    if let Some(s) = SPAWNED.lock().unwrap().get_mut(handle.as_ref()) {
+        s.stdin = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_exec_output() {
+        assert_eq!(exec_output(b"echo hello; echo world"), "hello\nworld\n");
+        let args: IntMap<Str> = IntMap::default();
+        assert_eq!(exec_output_argv(&args), "");
+        for (i, arg) in ["printf", "%s|", "a b", "$HOME"].iter().enumerate() {
+            args.insert(i as Int + 1, Str::from(*arg));
+        }
+        assert_eq!(exec_output_argv(&args), "a b|$HOME|");
+    }
+
+    #[test]
+    fn test_spawn() {
+        let handle = spawn(b"sort");
+        assert!(handle.starts_with(SPAWN_PREFIX));
+        let mut stdin = spawned_stdin(&handle).unwrap();
+        stdin.write_all(b"b\nc\na\n").unwrap();
+        drop(stdin);
+        let mut out = String::new();
+        spawned_stdout(&handle)
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "a\nb\nc\n");
+        assert!(spawned_stdout(&handle).is_err());
+
+        // close() without printing anything still lets the process finish.
+        let handle = spawn(b"cat");
+        close_spawned_stdin(handle.as_bytes());
+        let mut out = String::new();
+        spawned_stdout(&handle)
+            .unwrap()
+            .read_to_string(&mut out)
+            .unwrap();
+        assert_eq!(out, "");
+        assert_eq!(spawn(b"/nonexistent/program"), "");
+        assert_eq!(spawn(b"unterminated 'quote"), "");
+    }
+}
@@ -0,0 +1,191 @@
+//! INI and Java properties support for `from_ini`, `to_ini` and `prop_get`.
+use crate::runtime::{SharedMap, Str, StrMap};
+
+/// Strips one pair of matching double or single quotes.
+fn unquote(value: &str) -> &str {
+    for q in ['"', '\''] {
+        if value.len() >= 2 && value.starts_with(q) && value.ends_with(q) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+/// parse INI text: `key = value` or `key: value` lines, with keys under `[section]` named `section.key`.
+/// Lines starting with `;` or `#` are comments, and lines that are neither sections nor pairs are skipped.
+pub(crate) fn from_ini<'a>(text: &str) -> StrMap<'a, Str<'a>> {
+    let mut map = hashbrown::HashMap::new();
+    let mut section = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        if let Some(i) = line.find(['=', ':']) {
+            let key = line[..i].trim();
+            if key.is_empty() {
+                continue;
+            }
+            let key = if section.is_empty() { key.to_string() } else { format!("{}.{}", section, key) };
+            let value = unquote(line[i + 1..].trim());
+            map.insert(Str::from(key), Str::from(value.to_string()));
+        }
+    }
+    SharedMap::from(map)
+}
+
+/// format array as INI text. Keys are split at their last `.` into section and key; keys without a
+/// `.` come first, outside any section. Values with surrounding spaces or a leading quote are quoted.
+pub(crate) fn to_ini<V: ToString>(obj: &StrMap<V>) -> String {
+    let mut entries: Vec<(String, String, String)> = Vec::new();
+    obj.iter(|map| {
+        for (key, value) in map {
+            let key = key.to_string();
+            let (section, name) = match key.rfind('.') {
+                Some(i) => (key[..i].to_string(), key[i + 1..].to_string()),
+                None => (String::new(), key),
+            };
+            entries.push((section, name, value.to_string()));
+        }
+    });
+    entries.sort();
+    let mut out = String::new();
+    let mut current: Option<&str> = None;
+    for (section, name, value) in &entries {
+        if current != Some(section.as_str()) {
+            if !section.is_empty() {
+                if !out.is_empty() {
+                    out.push('\n');
+                }
+                out.push_str(&format!("[{}]\n", section));
+            }
+            current = Some(section.as_str());
+        }
+        if value.trim() != value || value.starts_with(['"', '\'']) {
+            out.push_str(&format!("{} = \"{}\"\n", name, value));
+        } else {
+            out.push_str(&format!("{} = {}\n", name, value));
+        }
+    }
+    out
+}
+
+/// Splits a Java properties line into its key and raw value: the key ends at the first unescaped
+/// `=`, `:` or whitespace, and the separator may be surrounded by whitespace.
+fn split_property(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '=' || c == ':' || c.is_whitespace() {
+            let rest = line[i..].trim_start();
+            let rest = if c.is_whitespace() {
+                rest.strip_prefix(['=', ':']).unwrap_or(rest)
+            } else {
+                &rest[1..]
+            };
+            return (&line[..i], rest.trim_start());
+        }
+    }
+    (line, "")
+}
+
+fn unescape_property(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push('\x0c'),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => out.push(c),
+                    None => {
+                        out.push_str("\\u");
+                        out.push_str(&hex);
+                    }
+                }
+            }
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+/// value of `key` in Java properties text, or empty if it is not defined. Supports `=`, `:` and
+/// whitespace separators, `#`/`!` comments, backslash line continuations and `\uXXXX` escapes.
+/// The last definition of a key wins.
+pub(crate) fn prop_get(text: &str, key: &str) -> String {
+    let mut result = String::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        let mut logical = line.trim_start().to_string();
+        if logical.starts_with('#') || logical.starts_with('!') {
+            continue;
+        }
+        // An odd number of trailing backslashes continues the line.
+        while logical.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1 {
+            logical.pop();
+            match lines.next() {
+                Some(next) => logical.push_str(next.trim_start()),
+                None => break,
+            }
+        }
+        if logical.is_empty() {
+            continue;
+        }
+        let (k, v) = split_property(&logical);
+        if unescape_property(k) == key {
+            result = unescape_property(v);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ini() {
+        let text = "; global\nname = demo\n\n[server]\nhost = localhost\nport: 8080\n\n[server.tls]\n# off for now\nenabled = \"false\"\nbad line\n";
+        let map = from_ini(text);
+        assert_eq!(map.get(&Str::from("name")).as_str(), "demo");
+        assert_eq!(map.get(&Str::from("server.port")).as_str(), "8080");
+        assert_eq!(map.get(&Str::from("server.tls.enabled")).as_str(), "false");
+        assert_eq!(map.len(), 4);
+        map.insert(Str::from("server.motd"), Str::from(" hi "));
+        assert_eq!(
+            to_ini(&map),
+            "name = demo\n\n[server]\nhost = localhost\nmotd = \" hi \"\nport = 8080\n\n[server.tls]\nenabled = false\n"
+        );
+        assert_eq!(from_ini(&to_ini(&map)).get(&Str::from("server.motd")).as_str(), " hi ");
+    }
+
+    #[test]
+    fn test_prop_get() {
+        let text = "# comment\n! also a comment\ndb.url = jdbc:mysql://localhost/test\ndb.user:admin\ngreeting Hello \\\n    World\nkey\\ with\\ spaces = v\npath=C:\\\\temp\nname=caf\\u00e9\ndb.user=root\nempty\n";
+        assert_eq!(prop_get(text, "db.url"), "jdbc:mysql://localhost/test");
+        assert_eq!(prop_get(text, "db.user"), "root");
+        assert_eq!(prop_get(text, "greeting"), "Hello World");
+        assert_eq!(prop_get(text, "key with spaces"), "v");
+        assert_eq!(prop_get(text, "path"), "C:\\temp");
+        assert_eq!(prop_get(text, "name"), "café");
+        assert_eq!(prop_get(text, "empty"), "");
+        assert_eq!(prop_get(text, "missing"), "");
+    }
+}
@@ -59,14 +59,23 @@ pub fn vec_to_csv(csv: &[&str]) -> String {
     str::from_utf8(&bytes[0..bytes.len() - 1]).unwrap().to_string()
 }
 
+#[cfg(feature = "net")]
+fn fetch_text(url: &str) -> String {
+    reqwest::blocking::get(url).unwrap().text().unwrap()
+}
+
+#[cfg(not(feature = "net"))]
+fn fetch_text(_url: &str) -> String {
+    crate::runtime::features::unavailable("reading metrics from a URL", "net")
+}
+
 pub fn parse_prometheus(url_or_file: &str) -> String {
-    if url_or_file.starts_with("http://") || url_or_file.starts_with("https://") {
-        let body = reqwest::blocking::get(url_or_file).unwrap().text().unwrap();
-        return parse_prometheus_text(&body);
+    let text = if url_or_file.starts_with("http://") || url_or_file.starts_with("https://") {
+        fetch_text(url_or_file)
     } else {
-        let text = std::fs::read_to_string(url_or_file).unwrap();
-        return parse_prometheus_text(&text);
-    }
+        std::fs::read_to_string(url_or_file).unwrap()
+    };
+    parse_prometheus_text(&text)
 }
 
 pub fn parse_prometheus_text(text: &str) -> String {
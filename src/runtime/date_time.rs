@@ -1,52 +1,698 @@
-use std::time::SystemTime;
-use chrono::{Datelike, DateTime, Local, Timelike, TimeZone, Utc};
+//! Dates and times: `strftime`, `mktime`, `datetime` and `duration`.
+//!
+//! `strftime` and `mktime` take the name of a timezone: an IANA name like `America/New_York`, an
+//! offset from UTC in hours like `8`, `-5` or `+05:30`, or a POSIX `TZ` string like
+//! `EST5EDT,M3.2.0,M11.1.0`. Without one they use `TZ`, or the timezone of the system when `TZ` is
+//! not set. Names that are none of these are UTC, as they are for the C library.
+use std::cell::RefCell;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime};
+use chrono::{
+    Datelike, DateTime, Duration, FixedOffset, Local, Locale, MappedLocalTime, Months, NaiveDate,
+    NaiveDateTime, Offset, Timelike, TimeZone, Utc,
+};
+use chrono_tz::Tz;
 use crate::runtime;
-use crate::runtime::{Int, Str};
+use crate::runtime::{Float, Int, Str};
 
 const WEEKS: [&'static str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
 
-pub fn strftime(format: &str, timestamp: i64) -> String {
-    let utc_now = DateTime::from_timestamp(timestamp, 0).unwrap().naive_utc();
-    let local_now: DateTime<Local> = Local.from_utc_datetime(&utc_now);
-    local_now.format(&format.to_string()).to_string()
+/// The format of `strftime()`, when `PROCINFO["strftime"]` is empty.
+pub(crate) const DEFAULT_FORMAT: &str = "%a %m %e %H:%M:%S %Z %Y";
+
+/// Format `timestamp` (the current time when negative) in the timezone `timezone`. Names of days
+/// and months follow the locale in `LC_ALL`, `LC_TIME` or `LANG`.
+pub fn strftime(format: &str, timestamp: i64, timezone: &str) -> String {
+    let timestamp = if timestamp < 0 { now() } else { timestamp };
+    let utc = match DateTime::from_timestamp(timestamp, 0) {
+        Some(dt) => dt.naive_utc(),
+        None => return String::new(),
+    };
+    let locale = time_locale();
+    match Zone::new(timezone) {
+        Zone::Named(tz) => format_in(&tz, &utc, format, locale),
+        Zone::Fixed(tz) => format_in(&tz, &utc, format, locale),
+        Zone::Posix(tz) => format_in(&tz, &utc, format, locale),
+        Zone::Local => format_in(&Local, &utc, format, locale),
+    }
 }
 
-pub fn mktime(date_time_text: &str, timezone: i64) -> u64 {
-    let dt_text_timezone = if timezone > 0 {
-        format!("{} {}", date_time_text, timezone_offset_text(timezone))
-    } else {
-        date_time_text.to_string()
+fn format_in<T: TimeZone>(tz: &T, utc: &NaiveDateTime, format: &str, locale: Option<Locale>) -> String
+where
+    T::Offset: fmt::Display,
+{
+    let dt = tz.from_utc_datetime(utc);
+    let mut res = String::new();
+    // Formatting fails on an invalid format, like one ending in `%`.
+    let ok = match locale {
+        Some(locale) => write!(res, "{}", dt.format_localized(format, locale)),
+        None => write!(res, "{}", dt.format(format)),
     };
-    if let Some(timestamp) = parse_systemd_time_timestamp(date_time_text, timezone) {
-        return timestamp as u64;
-    } else if let Ok(date_time) = dateparser::parse(&dt_text_timezone) {
-        return date_time.timestamp() as u64;
+    if ok.is_err() {
+        res.clear();
+    }
+    res
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64
+}
+
+// The locale for the names of days and months, as `strftime(3)` reads it.
+fn time_locale() -> Option<Locale> {
+    let name = ["LC_ALL", "LC_TIME", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|name| !name.is_empty())?;
+    // Drop an encoding or modifier, as in de_DE.UTF-8 or sr_RS@latin.
+    let name = name.split(['.', '@']).next().unwrap_or("");
+    match name {
+        "C" | "POSIX" | "" => None,
+        name => Locale::try_from(name).ok(),
+    }
+}
+
+/// Read a date and time as a Unix timestamp. A date and time without an offset from UTC of its own
+/// is a time in the timezone `timezone`; on the day clocks change, a time that happens twice is the
+/// first of them, and one that is skipped is read with the offset from before the change.
+pub fn mktime(date_time_text: &str, timezone: &str) -> i64 {
+    match parse(date_time_text) {
+        Some(Parsed::Instant(timestamp)) => timestamp,
+        Some(Parsed::Local(local)) => Zone::new(timezone).resolve(local),
+        None => 0,
+    }
+}
+
+/// Read `text` as a Unix timestamp, in `format` (with the directives of `strftime`), or in any of
+/// the formats `mktime` reads when `format` is empty. Times without an offset are in the default
+/// timezone.
+pub(crate) fn dateparse(text: &str, format: &str) -> Result<Int, String> {
+    let parsed = if format.is_empty() {
+        parse(text)
     } else {
-        // fend date format: Thursday, 20 May 2021
-        if is_fend_date(&dt_text_timezone) {
-            let adjusted_dt_text = &dt_text_timezone[dt_text_timezone.find(' ').unwrap() + 1..];
-            if let Ok(date_time) = dateparser::parse(&adjusted_dt_text) {
-                return date_time.timestamp() as u64;
-            }
+        parse_format(text, format)
+    };
+    match parsed {
+        Some(Parsed::Instant(timestamp)) => Ok(timestamp),
+        Some(Parsed::Local(local)) => Ok(Zone::new("").resolve(local)),
+        None if format.is_empty() => Err(format!("dateparse: cannot read {:?} as a date", text)),
+        None => Err(format!("dateparse: {:?} does not match the format {:?}", text, format)),
+    }
+}
+
+fn parse_format(text: &str, format: &str) -> Option<Parsed> {
+    if let Ok(date_time) = DateTime::parse_from_str(text, format) {
+        return Some(Parsed::Instant(date_time.timestamp()));
+    }
+    if let Ok(local) = NaiveDateTime::parse_from_str(text, format) {
+        return Some(Parsed::Local(local));
+    }
+    let date = NaiveDate::parse_from_str(text, format).ok()?;
+    Some(Parsed::Local(date.and_hms_opt(0, 0, 0)?))
+}
+
+// The units of `dateadd` and `datediff`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Unit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+impl Unit {
+    fn new(name: &str) -> Result<Unit, String> {
+        Ok(match name.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" | "secs" | "second" | "seconds" => Unit::Second,
+            "m" | "min" | "mins" | "minute" | "minutes" => Unit::Minute,
+            "h" | "hr" | "hrs" | "hour" | "hours" => Unit::Hour,
+            "d" | "day" | "days" => Unit::Day,
+            "w" | "wk" | "wks" | "week" | "weeks" => Unit::Week,
+            "mo" | "mon" | "month" | "months" => Unit::Month,
+            "y" | "yr" | "yrs" | "year" | "years" => Unit::Year,
+            _ => return Err(format!("unknown unit of time {:?}", name)),
+        })
+    }
+
+    // The length of the unit in seconds, for the units that always have the same length.
+    fn seconds(self) -> Option<i64> {
+        match self {
+            Unit::Second => Some(1),
+            Unit::Minute => Some(60),
+            Unit::Hour => Some(3600),
+            _ => None,
+        }
+    }
+}
+
+/// Add `n` units of time to the Unix timestamp `epoch`. Days, weeks, months and years are on the
+/// calendar of the default timezone: a day later is the same time on the next day, even when clocks
+/// change in between, and a month after January 31 is the last day of February.
+pub(crate) fn dateadd(epoch: Int, n: Int, unit: &str) -> Result<Int, String> {
+    let unit = Unit::new(unit).map_err(|e| format!("dateadd: {}", e))?;
+    let out_of_range = || format!("dateadd: {} {} from {} is out of range", n, unit_name(unit), epoch);
+    if let Some(secs) = unit.seconds() {
+        return n.checked_mul(secs).and_then(|d| epoch.checked_add(d)).ok_or_else(out_of_range);
+    }
+    let zone = Zone::new("");
+    let local = zone.local(epoch).ok_or_else(out_of_range)?;
+    let res = match unit {
+        Unit::Day | Unit::Week => {
+            let days = if unit == Unit::Week { n.checked_mul(7) } else { Some(n) };
+            days.and_then(Duration::try_days).and_then(|d| local.checked_add_signed(d))
+        }
+        _ => {
+            let months = if unit == Unit::Year { n.checked_mul(12) } else { Some(n) };
+            months.and_then(|m| {
+                let abs = Months::new(u32::try_from(m.unsigned_abs()).ok()?);
+                if m < 0 {
+                    local.checked_sub_months(abs)
+                } else {
+                    local.checked_add_months(abs)
+                }
+            })
         }
-        let dt_text = format!("{} {}", date_time_text, timezone_offset_text(timezone));
-        //gawk compatible parser
-        if let Ok(date_time) = DateTime::parse_from_str(&dt_text, "%Y %m %d %H %M %S %z") {
-            return date_time.timestamp() as u64;
+    };
+    Ok(zone.resolve(res.ok_or_else(out_of_range)?))
+}
+
+/// The number of whole units of time from the Unix timestamp `b` to `a`: negative when `a` is
+/// earlier than `b`. Days, weeks, months and years count on the calendar of the default timezone.
+pub(crate) fn datediff(a: Int, b: Int, unit: &str) -> Result<Int, String> {
+    let unit = Unit::new(unit).map_err(|e| format!("datediff: {}", e))?;
+    if let Some(secs) = unit.seconds() {
+        return Ok(a.saturating_sub(b) / secs);
+    }
+    let zone = Zone::new("");
+    let (la, lb) = match (zone.local(a), zone.local(b)) {
+        (Some(la), Some(lb)) => (la, lb),
+        _ => return Err(format!("datediff: {} or {} is out of range", a, b)),
+    };
+    Ok(match unit {
+        Unit::Day => (la - lb).num_days(),
+        Unit::Week => (la - lb).num_days() / 7,
+        _ => {
+            let month = |d: &NaiveDateTime| d.year() as i64 * 12 + d.month0() as i64;
+            let mut months = month(&la) - month(&lb);
+            // Count a month only once its day and time have come around again.
+            let rest = |d: &NaiveDateTime| (d.day(), d.time());
+            if months > 0 && rest(&la) < rest(&lb) {
+                months -= 1;
+            } else if months < 0 && rest(&la) > rest(&lb) {
+                months += 1;
+            }
+            if unit == Unit::Year {
+                months / 12
+            } else {
+                months
+            }
         }
+    })
+}
+
+fn unit_name(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Second => "seconds",
+        Unit::Minute => "minutes",
+        Unit::Hour => "hours",
+        Unit::Day => "days",
+        Unit::Week => "weeks",
+        Unit::Month => "months",
+        Unit::Year => "years",
     }
-    0
 }
 
-fn parse_systemd_time_timestamp(timestamp: &str, timezone: i64) -> Option<i64> {
-    if let Ok(timestamp) = chrono_systemd_time::parse_timestamp_tz(timestamp, Utc)
-        .map(|x| x.single().unwrap())
-        .map(|x| x.timestamp()) {
-        return Some(timestamp + timezone * 3600);
+enum Parsed {
+    // The text named a moment in time, with an offset or relative to now.
+    Instant(i64),
+    // The text named a time on a clock.
+    Local(NaiveDateTime),
+}
+
+fn parse(date_time_text: &str) -> Option<Parsed> {
+    // The parsers read a text without an offset as a time in the timezone they are given. Reading
+    // it in two timezones an hour apart tells whether it has one.
+    let in_utc = parse_in(date_time_text, &Utc)?;
+    let east = FixedOffset::east_opt(3600).unwrap();
+    if parse_in(date_time_text, &east) == Some(in_utc) {
+        return Some(Parsed::Instant(in_utc));
+    }
+    Some(Parsed::Local(DateTime::from_timestamp(in_utc, 0)?.naive_utc()))
+}
+
+fn parse_in<T: TimeZone>(date_time_text: &str, tz: &T) -> Option<i64> {
+    if let Ok(date_time) = chrono_systemd_time::parse_timestamp_tz(date_time_text, tz.clone()) {
+        if let Some(date_time) = date_time.single() {
+            return Some(date_time.timestamp());
+        }
+    }
+    if let Ok(date_time) = dateparser::parse_with_timezone(date_time_text, tz) {
+        return Some(date_time.timestamp());
+    }
+    // fend date format: Thursday, 20 May 2021
+    if is_fend_date(date_time_text) {
+        let adjusted_dt_text = &date_time_text[date_time_text.find(' ').unwrap() + 1..];
+        if let Ok(date_time) = dateparser::parse_with_timezone(adjusted_dt_text, tz) {
+            return Some(date_time.timestamp());
+        }
+    }
+    //gawk compatible parser: YYYY MM DD HH MM SS, with an optional DST flag that is not needed
+    let fields: Vec<&str> = date_time_text.split_whitespace().collect();
+    if fields.len() == 6 || fields.len() == 7 {
+        let text = fields[..6].join(" ");
+        if let Ok(local) = NaiveDateTime::parse_from_str(&text, "%Y %m %d %H %M %S") {
+            return tz.from_local_datetime(&local).single().map(|dt| dt.timestamp());
+        }
     }
     None
 }
 
+fn resolve<T: TimeZone>(tz: &T, local: NaiveDateTime) -> i64 {
+    match tz.from_local_datetime(&local) {
+        MappedLocalTime::Single(dt) => dt.timestamp(),
+        MappedLocalTime::Ambiguous(a, b) => a.timestamp().min(b.timestamp()),
+        MappedLocalTime::None => {
+            let before = tz.offset_from_utc_datetime(&(local - Duration::days(1))).fix();
+            local.and_utc().timestamp() - before.local_minus_utc() as i64
+        }
+    }
+}
+
+/// A timezone, named as described in the module documentation.
+#[derive(Clone, Debug)]
+pub(crate) enum Zone {
+    Named(Tz),
+    Fixed(FixedOffset),
+    Posix(Posix),
+    // The timezone of the system, when it has no IANA name.
+    Local,
+}
+
+thread_local! {
+    // Programs usually ask for the same timezone over and over.
+    static LAST_ZONE: RefCell<Option<(String, Zone)>> = const { RefCell::new(None) };
+}
+
+impl Zone {
+    pub(crate) fn new(name: &str) -> Zone {
+        let name = if name.is_empty() {
+            match std::env::var("TZ") {
+                // As for the C library, an empty TZ is UTC.
+                Ok(tz) if tz.is_empty() => return Zone::Named(Tz::UTC),
+                Ok(tz) => tz,
+                Err(_) => return system_zone(),
+            }
+        } else {
+            name.to_string()
+        };
+        LAST_ZONE.with(|last| {
+            let mut last = last.borrow_mut();
+            match &*last {
+                Some((last_name, zone)) if *last_name == name => zone.clone(),
+                _ => {
+                    let zone = Zone::parse(&name);
+                    *last = Some((name, zone.clone()));
+                    zone
+                }
+            }
+        })
+    }
+
+    // The time on a clock in this timezone at `timestamp`.
+    fn local(&self, timestamp: i64) -> Option<NaiveDateTime> {
+        let utc = DateTime::from_timestamp(timestamp, 0)?.naive_utc();
+        Some(match self {
+            Zone::Named(tz) => tz.from_utc_datetime(&utc).naive_local(),
+            Zone::Fixed(tz) => tz.from_utc_datetime(&utc).naive_local(),
+            Zone::Posix(tz) => tz.from_utc_datetime(&utc).naive_local(),
+            Zone::Local => Local.from_utc_datetime(&utc).naive_local(),
+        })
+    }
+
+    // The timestamp of the time `local` on a clock in this timezone.
+    fn resolve(&self, local: NaiveDateTime) -> i64 {
+        match self {
+            Zone::Named(tz) => resolve(tz, local),
+            Zone::Fixed(tz) => resolve(tz, local),
+            Zone::Posix(tz) => resolve(tz, local),
+            Zone::Local => resolve(&Local, local),
+        }
+    }
+
+    fn parse(name: &str) -> Zone {
+        let name = name.trim();
+        // TZ may be `:America/New_York`, or a path into the timezone database.
+        let name = name.strip_prefix(':').unwrap_or(name);
+        let name = match name.find("zoneinfo/") {
+            Some(i) => &name[i + "zoneinfo/".len()..],
+            None => name,
+        };
+        if let Some(offset) = parse_hours(name) {
+            return Zone::Fixed(offset);
+        }
+        if let Ok(tz) = Tz::from_str(name) {
+            return Zone::Named(tz);
+        }
+        if name.eq_ignore_ascii_case("utc") || name.eq_ignore_ascii_case("z") {
+            return Zone::Named(Tz::UTC);
+        }
+        match Posix::parse(name) {
+            Some(posix) => Zone::Posix(posix),
+            None => Zone::Named(Tz::UTC),
+        }
+    }
+}
+
+fn system_zone() -> Zone {
+    static SYSTEM: OnceLock<Option<Tz>> = OnceLock::new();
+    let tz = SYSTEM.get_or_init(|| {
+        iana_time_zone::get_timezone()
+            .ok()
+            .and_then(|name| Tz::from_str(&name).ok())
+    });
+    match tz {
+        Some(tz) => Zone::Named(*tz),
+        None => Zone::Local,
+    }
+}
+
+// An offset east of UTC in hours, as in `8`, `-5`, `+0530` or `+05:30`.
+fn parse_hours(name: &str) -> Option<FixedOffset> {
+    let (sign, digits) = match name.as_bytes().first()? {
+        b'+' => (1, &name[1..]),
+        b'-' => (-1, &name[1..]),
+        _ => (1, name),
+    };
+    let (hours, minutes) = match digits.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if digits.len() > 2 => digits.split_at(digits.len() - 2),
+        None => (digits, "0"),
+    };
+    if hours.is_empty()
+        || hours.len() > 2
+        || !hours.bytes().chain(minutes.bytes()).all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    let (hours, minutes) = (hours.parse::<i32>().ok()?, minutes.parse::<i32>().ok()?);
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// A timezone given by a POSIX `TZ` string like `EST5EDT,M3.2.0,M11.1.0`: the abbreviation and
+/// offset of standard time, and of daylight saving time with the rules for when it starts and ends.
+#[derive(Clone, Debug)]
+pub(crate) struct Posix {
+    std: PosixOffset,
+    dst: Option<(PosixOffset, Rule, Rule)>,
+}
+
+/// The offset of a [`Posix`] timezone at some time; it displays as its abbreviation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PosixOffset {
+    abbr: [u8; 15],
+    len: u8,
+    offset: FixedOffset,
+}
+
+// The day of the year daylight saving time starts or ends on, and the local time it happens at, in
+// seconds after midnight.
+#[derive(Clone, Copy, Debug)]
+struct Rule {
+    day: Day,
+    time: i64,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Day {
+    // `Jn`: day 1 to 365, not counting February 29.
+    Julian(i64),
+    // `n`: day 0 to 365, counting February 29.
+    Ordinal(i64),
+    // `Mm.w.d`: day `d` of the week (0 is Sunday) of week `w` (5 is the last) of month `m`.
+    Month(u32, u32, u32),
+}
+
+impl Rule {
+    // The local time of the change in `year`, as seconds since the epoch.
+    fn local(&self, year: i32) -> i64 {
+        let jan1 = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+        let date = match self.day {
+            Day::Julian(n) => {
+                let leap = jan1.leap_year() && n >= 60;
+                jan1 + Duration::days(n - 1 + leap as i64)
+            }
+            Day::Ordinal(n) => jan1 + Duration::days(n),
+            Day::Month(m, w, d) => {
+                let first = NaiveDate::from_ymd_opt(year, m, 1).unwrap();
+                let first_wday = first.weekday().num_days_from_sunday();
+                let mut day = 1 + (d + 7 - first_wday) % 7 + (w - 1) * 7;
+                while NaiveDate::from_ymd_opt(year, m, day).is_none() {
+                    day -= 7;
+                }
+                NaiveDate::from_ymd_opt(year, m, day).unwrap()
+            }
+        };
+        date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() + self.time
+    }
+}
+
+impl Posix {
+    fn parse(s: &str) -> Option<Posix> {
+        let mut p = TzParser { s: s.as_bytes(), i: 0 };
+        let std_abbr = p.abbr()?;
+        let std_offset = -p.time(24)?;
+        let std = PosixOffset::new(std_abbr, std_offset)?;
+        if p.done() {
+            return Some(Posix { std, dst: None });
+        }
+        let dst_abbr = p.abbr()?;
+        let dst_offset = if p.done() || p.peek() == Some(b',') {
+            std_offset + 3600
+        } else {
+            -p.time(24)?
+        };
+        let dst = PosixOffset::new(dst_abbr, dst_offset)?;
+        // Without rules, daylight saving time follows the rules of the United States, as it does
+        // for glibc.
+        let (start, end) = if p.done() {
+            let rule = |m, w| Rule { day: Day::Month(m, w, 0), time: 7200 };
+            (rule(3, 2), rule(11, 1))
+        } else {
+            p.expect(b',')?;
+            let start = p.rule()?;
+            p.expect(b',')?;
+            (start, p.rule()?)
+        };
+        if !p.done() {
+            return None;
+        }
+        Some(Posix { std, dst: Some((dst, start, end)) })
+    }
+
+    fn at(&self, utc: i64) -> PosixOffset {
+        let (dst, start, end) = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std,
+        };
+        let std_secs = self.std.offset.local_minus_utc() as i64;
+        let dst_secs = dst.offset.local_minus_utc() as i64;
+        let year = match DateTime::from_timestamp(utc + std_secs, 0) {
+            Some(dt) => dt.year(),
+            None => return self.std,
+        };
+        // Daylight saving time starts at a time in standard time, and ends at one in daylight time.
+        let start = start.local(year) - std_secs;
+        let end = end.local(year) - dst_secs;
+        let in_dst = if start < end {
+            start <= utc && utc < end
+        } else {
+            // As in the southern hemisphere, where it spans the new year.
+            !(end <= utc && utc < start)
+        };
+        if in_dst {
+            *dst
+        } else {
+            self.std
+        }
+    }
+}
+
+struct TzParser<'a> {
+    s: &'a [u8],
+    i: usize,
+}
+
+impl<'a> TzParser<'a> {
+    fn done(&self) -> bool {
+        self.i == self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.i).copied()
+    }
+
+    fn expect(&mut self, b: u8) -> Option<()> {
+        if self.peek() == Some(b) {
+            self.i += 1;
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a [u8] {
+        let start = self.i;
+        while self.peek().is_some_and(&f) {
+            self.i += 1;
+        }
+        &self.s[start..self.i]
+    }
+
+    // `EST`, or `<+0530>` for abbreviations with digits or signs.
+    fn abbr(&mut self) -> Option<&'a [u8]> {
+        let abbr = if self.expect(b'<').is_some() {
+            let abbr = self.take_while(|b| b.is_ascii_alphanumeric() || b == b'+' || b == b'-');
+            self.expect(b'>')?;
+            abbr
+        } else {
+            self.take_while(|b| b.is_ascii_alphabetic())
+        };
+        if abbr.len() < 3 {
+            return None;
+        }
+        Some(abbr)
+    }
+
+    fn number(&mut self, max: i64) -> Option<i64> {
+        let digits = self.take_while(|b| b.is_ascii_digit());
+        if digits.is_empty() || digits.len() > 3 {
+            return None;
+        }
+        let n = std::str::from_utf8(digits).ok()?.parse::<i64>().ok()?;
+        (n <= max).then_some(n)
+    }
+
+    // `[+-]hh[:mm[:ss]]`, in seconds.
+    fn time(&mut self, max_hours: i64) -> Option<i64> {
+        let sign = match self.peek() {
+            Some(b'-') => {
+                self.i += 1;
+                -1
+            }
+            Some(b'+') => {
+                self.i += 1;
+                1
+            }
+            _ => 1,
+        };
+        let mut secs = self.number(max_hours)? * 3600;
+        if self.expect(b':').is_some() {
+            secs += self.number(59)? * 60;
+            if self.expect(b':').is_some() {
+                secs += self.number(59)?;
+            }
+        }
+        Some(sign * secs)
+    }
+
+    fn rule(&mut self) -> Option<Rule> {
+        let day = match self.peek()? {
+            b'J' => {
+                self.i += 1;
+                let n = self.number(365)?;
+                (n >= 1).then_some(Day::Julian(n))?
+            }
+            b'M' => {
+                self.i += 1;
+                let m = self.number(12)? as u32;
+                self.expect(b'.')?;
+                let w = self.number(5)? as u32;
+                self.expect(b'.')?;
+                let d = self.number(6)? as u32;
+                if m == 0 || w == 0 {
+                    return None;
+                }
+                Day::Month(m, w, d)
+            }
+            _ => Day::Ordinal(self.number(365)?),
+        };
+        let time = if self.expect(b'/').is_some() { self.time(167)? } else { 7200 };
+        Some(Rule { day, time })
+    }
+}
+
+impl PosixOffset {
+    fn new(abbr: &[u8], secs: i64) -> Option<PosixOffset> {
+        let mut res = PosixOffset {
+            abbr: [0; 15],
+            len: abbr.len() as u8,
+            offset: FixedOffset::east_opt(i32::try_from(secs).ok()?)?,
+        };
+        res.abbr.get_mut(..abbr.len())?.copy_from_slice(abbr);
+        Some(res)
+    }
+}
+
+impl Offset for PosixOffset {
+    fn fix(&self) -> FixedOffset {
+        self.offset
+    }
+}
+
+impl fmt::Display for PosixOffset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(std::str::from_utf8(&self.abbr[..self.len as usize]).unwrap_or(""))
+    }
+}
+
+impl TimeZone for Posix {
+    type Offset = PosixOffset;
+
+    fn from_offset(offset: &PosixOffset) -> Posix {
+        Posix { std: *offset, dst: None }
+    }
+
+    fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<PosixOffset> {
+        self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+    }
+
+    fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<PosixOffset> {
+        let local = local.and_utc().timestamp();
+        let mut candidates = vec![self.std];
+        if let Some((dst, _, _)) = &self.dst {
+            candidates.push(*dst);
+        }
+        // The offsets that give back `local`, earliest first.
+        let mut valid: Vec<(i64, PosixOffset)> = candidates
+            .into_iter()
+            .map(|off| (local - off.offset.local_minus_utc() as i64, off))
+            .filter(|(utc, off)| self.at(*utc) == *off)
+            .collect();
+        valid.sort_by_key(|(utc, _)| *utc);
+        match valid[..] {
+            [] => MappedLocalTime::None,
+            [(_, off)] => MappedLocalTime::Single(off),
+            [(_, a), (_, b), ..] => MappedLocalTime::Ambiguous(a, b),
+        }
+    }
+
+    fn offset_from_utc_date(&self, utc: &NaiveDate) -> PosixOffset {
+        self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+    }
+
+    fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> PosixOffset {
+        self.at(utc.and_utc().timestamp())
+    }
+}
+
 fn is_fend_date(text: &str) -> bool {
     if text.contains(',') {
         let temp = &text[0..text.find(',').unwrap()];
@@ -55,25 +701,13 @@ fn is_fend_date(text: &str) -> bool {
     false
 }
 
-fn timezone_offset_text(timezone: i64) -> String {
-    if timezone >= 10 {
-        format!("+{}:00", timezone)
-    } else if timezone >= 0 && timezone < 10 {
-        format!("+0{}:00", timezone)
-    } else {
-        "+00:00".to_owned()
-    }
-}
-
 pub(crate) fn datetime<'a>(date_time_text: &str) -> runtime::StrMap<'a, Int> {
     if date_time_text.is_empty() {
-        let seconds = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs() as i64;
-        return datetime2(seconds);
+        datetime2(now())
     } else if let Ok(timestamp) = date_time_text.parse::<i64>() {
         datetime2(timestamp)
     } else {
-        let timestamp = mktime(date_time_text, 0);
-        datetime2(timestamp as i64)
+        datetime2(mktime(date_time_text, "UTC"))
     }
 }
 
@@ -92,6 +726,35 @@ pub(crate) fn datetime2<'a>(timestamp: i64) -> runtime::StrMap<'a, Int> {
     return result;
 }
 
+/// The current time in milliseconds since the epoch.
+pub(crate) fn systime_ms() -> Int {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as Int
+}
+
+/// The current time in microseconds since the epoch.
+pub(crate) fn systime_us() -> Int {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_micros() as Int
+}
+
+/// Seconds on a monotonic clock, which never goes back when the system time is changed. It starts
+/// when the program first reads it, so only the difference between two readings means anything.
+pub(crate) fn clock() -> Float {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_secs_f64()
+}
+
+/// Sleep for `secs` seconds, which may be fractional. Returns 0, or -1 if `secs` is negative or not
+/// a number.
+pub(crate) fn sleep(secs: Float) -> Int {
+    match std::time::Duration::try_from_secs_f64(secs) {
+        Ok(d) => {
+            std::thread::sleep(d);
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
 pub fn duration(text: &str) -> Int {
     let expr = format!("({}) to second", text);
     let mut context = fend_core::Context::new();
@@ -116,14 +779,81 @@ mod tests {
     fn test_strftime() {
         let format = "%c";
         let timestamp = 1621530000;
-        println!("{}", strftime(format, timestamp));
+        println!("{}", strftime(format, timestamp, ""));
+    }
+
+    #[test]
+    fn test_strftime_timezones() {
+        let format = "%Y-%m-%d %H:%M %Z %z";
+        let summer = 1690000000; // 2023-07-22 04:26:40 UTC
+        assert_eq!(strftime(format, summer, "UTC"), "2023-07-22 04:26 UTC +0000");
+        assert_eq!(strftime(format, summer, "America/New_York"), "2023-07-22 00:26 EDT -0400");
+        assert_eq!(strftime(format, summer, "Europe/London"), "2023-07-22 05:26 BST +0100");
+        assert_eq!(strftime(format, summer, "8"), "2023-07-22 12:26 +08:00 +0800");
+        assert_eq!(strftime(format, summer, "-05:30"), "2023-07-21 22:56 -05:30 -0530");
+        assert_eq!(strftime(format, summer, "CET-1CEST,M3.5.0,M10.5.0/3"), "2023-07-22 06:26 CEST +0200");
+        assert_eq!(strftime(format, summer, "<+10>-10"), "2023-07-22 14:26 +10 +1000");
+        assert_eq!(strftime(format, summer, "no/such_zone"), "2023-07-22 04:26 UTC +0000");
+    }
+
+    #[test]
+    fn test_mktime_timezones() {
+        assert_eq!(mktime("2012-12-21 00:00", "UTC"), 1356048000);
+        assert_eq!(mktime("2012-12-21 00:00", "8"), 1356048000 - 8 * 3600);
+        assert_eq!(mktime("2012 12 21 0 0 0", "Asia/Tokyo"), 1356048000 - 9 * 3600);
+        // The text's own offset wins.
+        assert_eq!(mktime("2019-11-29 08:08-08", "Asia/Tokyo"), 1575043680);
+        // A skipped time reads with the offset from before clocks changed: 02:30 EST is 03:30 EDT.
+        assert_eq!(mktime("2024-03-10 02:30:00", "America/New_York"), 1710055800);
+        assert_eq!(mktime("2024-03-10 02:30:00", "EST5EDT,M3.2.0,M11.1.0"), 1710055800);
+        // A time that happens twice is the first of them, in daylight saving time.
+        assert_eq!(mktime("2024-11-03 01:30:00", "America/New_York"), 1730611800);
+        assert_eq!(mktime("2024-11-03 01:30:00", "EST5EDT"), 1730611800);
+        // Southern hemisphere: daylight saving time spans the new year.
+        assert_eq!(mktime("2024-01-15 12:00:00", "AEST-10AEDT,M10.1.0,M4.1.0/3"), 1705280400);
+        assert_eq!(mktime("2024-01-15 12:00:00", "Australia/Sydney"), 1705280400);
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        // Noon in the default timezone, whatever it is, so the tests hold in any of them.
+        let jan31 = dateparse("2024-01-31 12:00:00", "").unwrap();
+        let day = |epoch| strftime("%F %T", epoch, "");
+        assert_eq!(dateparse("31/01/2024 12h00", "%d/%m/%Y %Hh%M"), Ok(jan31));
+        assert_eq!(dateparse("2024-01-31T12:00:00+00:00", ""), Ok(1706702400));
+        assert!(dateparse("2024-01-31", "%d/%m/%Y").is_err());
+        assert_eq!(day(dateadd(jan31, 1, "month").unwrap()), "2024-02-29 12:00:00");
+        assert_eq!(day(dateadd(jan31, -1, "year").unwrap()), "2023-01-31 12:00:00");
+        assert_eq!(day(dateadd(jan31, 2, "weeks").unwrap()), "2024-02-14 12:00:00");
+        assert_eq!(dateadd(jan31, 90, "min"), Ok(jan31 + 5400));
+        assert!(dateadd(jan31, 1, "fortnight").is_err());
+        assert!(dateadd(jan31, i64::MAX, "s").is_err());
+
+        let mar1 = dateparse("2024-03-01 12:00:00", "").unwrap();
+        assert_eq!(datediff(mar1, jan31, "months"), Ok(1));
+        assert_eq!(datediff(jan31, mar1, "months"), Ok(-1));
+        assert_eq!(datediff(mar1, jan31, "d"), Ok(30));
+        assert_eq!(datediff(mar1, jan31, "week"), Ok(4));
+        assert_eq!(datediff(mar1 - 1, jan31, "year"), Ok(0));
+        assert_eq!(datediff(jan31 + 7199, jan31, "hours"), Ok(1));
+    }
+
+    #[test]
+    fn test_date_arithmetic_across_dst() {
+        // Days are on the calendar: the day after noon before clocks skip forward is noon, 23
+        // hours later.
+        let zone = Zone::parse("America/New_York");
+        let noon = NaiveDate::from_ymd_opt(2024, 3, 9).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let before = zone.resolve(noon);
+        let local = zone.local(before).unwrap() + Duration::days(1);
+        assert_eq!(zone.resolve(local) - before, 23 * 3600);
     }
 
     #[test]
     fn test_date_parse() {
         let date_text_items = vec!["Thursday, 20 May 2021", "2024-04-27 17:07:25.684184848 +08:00", "09:11:12 -1day"];
         for item in date_text_items {
-            println!("{}", mktime(item, 0));
+            println!("{}", mktime(item, "UTC"));
         }
     }
 
@@ -0,0 +1,127 @@
+//! Fixed-point decimal arithmetic for `dec_add`, `dec_sub`, `dec_mul`, `dec_div`, `dec_cmp` and
+//! `dec_fmt`.
+//!
+//! Decimals are passed around as strings and computed as 96-bit integers scaled by a power of ten
+//! (at most 28 decimal places), so sums of money columns stay exact: `0.1 + 0.2` is
+//! `0.30000000000000004`, but `dec_add("0.1", "0.2")` is `0.3`. Arguments are read like AWK reads
+//! numbers, by their numeric prefix, so `""` and `"abc"` are 0 and `"12.50 EUR"` is 12.50. Results
+//! that do not fit (about 28 significant digits), and division by zero, give the empty string.
+use crate::runtime::rounding::{self, RoundMode};
+use crate::runtime::Int;
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::cmp::Ordering;
+
+// The most decimal places a `Decimal` can have.
+const MAX_SCALE: Int = 28;
+
+pub(crate) fn parse(s: &str) -> Decimal {
+    let s = s.trim();
+    if let Ok(d) = s.parse::<Decimal>() {
+        return d;
+    }
+    if let Ok(d) = Decimal::from_scientific(s) {
+        return d;
+    }
+    let bs = s.as_bytes();
+    let digits = |from: usize| from + bs[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut end = digits(usize::from(matches!(bs.first(), Some(b'+' | b'-'))));
+    if bs.get(end) == Some(&b'.') {
+        end = digits(end + 1);
+    }
+    s[..end].trim_end_matches('.').parse().unwrap_or_default()
+}
+
+fn show(d: Option<Decimal>) -> String {
+    d.map(|d| d.to_string()).unwrap_or_default()
+}
+
+pub(crate) fn add(x: &str, y: &str) -> String {
+    show(parse(x).checked_add(parse(y)))
+}
+
+pub(crate) fn sub(x: &str, y: &str) -> String {
+    show(parse(x).checked_sub(parse(y)))
+}
+
+pub(crate) fn mul(x: &str, y: &str) -> String {
+    show(parse(x).checked_mul(parse(y)))
+}
+
+/// `x / y`, to as many decimal places as fit.
+pub(crate) fn div(x: &str, y: &str) -> String {
+    show(parse(x).checked_div(parse(y)).map(|d| d.normalize()))
+}
+
+pub(crate) fn cmp(x: &str, y: &str) -> Int {
+    match parse(x).cmp(&parse(y)) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    }
+}
+
+/// `x` with exactly `scale` decimal places, or with no trailing zeros if `scale` is negative. Ties
+/// are rounded away from zero, or to even with `--round-mode=half-even`.
+pub(crate) fn fmt(x: &str, scale: Int) -> String {
+    let d = parse(x);
+    let res = if scale < 0 {
+        d.normalize()
+    } else {
+        let scale = scale.min(MAX_SCALE) as u32;
+        let strategy = match rounding::mode() {
+            RoundMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundMode::Exact | RoundMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        };
+        let mut res = d.round_dp_with_strategy(scale, strategy);
+        // Pad with zeros: 12.5 with a scale of 2 is 12.50.
+        res.rescale(scale);
+        res
+    };
+    // Rounding -0.004 keeps the sign, which would print as -0.00.
+    if res.is_zero() {
+        return Decimal::new(0, res.scale()).to_string();
+    }
+    res.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(add("0.1", "0.2"), "0.3");
+        assert_eq!(add("19.99", "0.01"), "20.00");
+        assert_eq!(add("", "5"), "5");
+        assert_eq!(sub("1.00", "2.5"), "-1.50");
+        assert_eq!(mul("19.99", "3"), "59.97");
+        assert_eq!(mul("1.5e2", "2"), "300");
+        assert_eq!(div("10", "4"), "2.5");
+        assert_eq!(div("1", "3"), "0.3333333333333333333333333333");
+        assert_eq!(div("1", "0"), "");
+        assert_eq!(mul("79228162514264337593543950335", "2"), "");
+        assert_eq!(cmp("1.0", "1"), 0);
+        assert_eq!(cmp("-2", "1"), -1);
+        assert_eq!(cmp("0.30", "0.3000000000000000000000000001"), -1);
+    }
+
+    #[test]
+    fn numeric_prefixes() {
+        assert_eq!(parse("12.50 EUR").to_string(), "12.50");
+        assert_eq!(parse(" -3.x").to_string(), "-3");
+        assert_eq!(parse("7.").to_string(), "7");
+        assert_eq!(parse("abc"), Decimal::ZERO);
+        assert_eq!(parse("-"), Decimal::ZERO);
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(fmt("12.5", 2), "12.50");
+        assert_eq!(fmt("2.675", 2), "2.68");
+        assert_eq!(fmt("-2.665", 2), "-2.67");
+        assert_eq!(fmt("1.2300", -1), "1.23");
+        assert_eq!(fmt("-0.004", 2), "0.00");
+        assert_eq!(fmt("1234.5", 0), "1235");
+    }
+}
@@ -1,120 +1,121 @@
-use std::io::{Read, Write};
-use std::sync::Mutex;
+use std::io::{self, Read, Write};
+use std::sync::OnceLock;
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
 use base64::{engine::general_purpose::STANDARD, engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use hashbrown::HashMap;
-use urlencoding::{encode as url_encode, decode as url_decode};
+use urlencoding::{encode_binary as url_encode_binary, decode_binary as url_decode_binary};
 use base58;
 use base58::{FromBase58, ToBase58};
 use flate2::Compression;
-use flate2::write::ZlibEncoder;
-use flate2::read::{ZlibDecoder};
-use growable_bloom_filter::{GrowableBloom, GrowableBloomBuilder};
-use lazy_static::lazy_static;
+use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
 use crate::runtime;
 use crate::runtime::{SharedMap, Str};
 
 
-pub fn encode(format: &str, text: &str) -> String {
-    match format {
-        "base32" => data_encoding::BASE32_NOPAD.encode(text.as_bytes()),
-        "base32hex" => data_encoding::BASE32HEX_NOPAD.encode(text.as_bytes()),
-        "base58" => text.as_bytes().to_base58(),
-        "base62" => base_62::encode(text.as_bytes()),
+/// The formats understood by `encode`, for error messages.
+const ENCODE_FORMATS: &str = "base32, base32hex, base58, base62, base64, base64url, base85, zlib2base64url, url, \
+    hex, hex-upper, hex-base64, hex-base64url, base64-hex, base64url-hex";
+/// The formats understood by `decode`.
+const DECODE_FORMATS: &str =
+    "base32, base32hex, base58, base62, base64, base64url, base85, zlib2base64url, url, hex, hex-upper";
+
+fn hex_bytes(text: &[u8]) -> Result<Vec<u8>, String> {
+    hex::decode(text).map_err(|_| "invalid hex text".to_string())
+}
+
+/// encode bytes by format, such as `base64` or `hex-upper`
+pub fn encode(format: &str, text: &[u8]) -> Result<String, String> {
+    Ok(match format {
+        "base32" => data_encoding::BASE32_NOPAD.encode(text),
+        "base32hex" => data_encoding::BASE32HEX_NOPAD.encode(text),
+        "base58" => text.to_base58(),
+        "base62" => base_62::encode(text),
         "base64" => STANDARD.encode(text),
-        "base85" => base85::encode(text.as_bytes()),
+        "base85" => base85::encode(text),
         "base64url" => URL_SAFE_NO_PAD.encode(text),
         "zlib2base64url" => {
             let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
-            e.write_all(text.as_bytes()).unwrap();
+            e.write_all(text).unwrap();
             let compressed_bytes = e.finish().unwrap();
             URL_SAFE_NO_PAD.encode(compressed_bytes)
         }
-        "url" => url_encode(text).to_string(),
+        "url" => url_encode_binary(text).to_string(),
         "hex" => hex::encode(text),
-        "hex-base64" => {
-            let bytes = hex::decode(text).unwrap();
-            STANDARD.encode(&bytes)
-        }
-        "hex-base64url" => {
-            let bytes = hex::decode(text).unwrap();
-            URL_SAFE_NO_PAD.encode(&bytes)
-        }
-        "base64-hex" => {
-            let bytes = STANDARD.decode(text).unwrap();
-            hex::encode(&bytes)
-        }
-        "base64url-hex" => {
-            let bytes = URL_SAFE_NO_PAD.decode(text).unwrap();
-            hex::encode(&bytes)
-        }
-        &_ => {
-            format!("{}:{}", format, text)
-        }
-    }
+        "hex-upper" => hex::encode_upper(text),
+        "hex-base64" => STANDARD.encode(hex_bytes(text)?),
+        "hex-base64url" => URL_SAFE_NO_PAD.encode(hex_bytes(text)?),
+        "base64-hex" => hex::encode(STANDARD.decode(text).map_err(|_| "invalid base64 text")?),
+        "base64url-hex" => hex::encode(URL_SAFE_NO_PAD.decode(text).map_err(|_| "invalid base64url text")?),
+        _ => return Err(format!("unknown format '{}', expected one of {}", format, ENCODE_FORMATS)),
+    })
 }
 
-pub fn decode(format: &str, text: &str) -> String {
-    if format == "base32" {
-        if let Ok(bytes) = data_encoding::BASE32_NOPAD.decode(text.as_bytes()) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base32hex" {
-        if let Ok(bytes) = data_encoding::BASE32HEX_NOPAD.decode(text.as_bytes()) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base58" {
-        if let Ok(bytes) = text.from_base58() {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base62" {
-        if let Ok(bytes) = base_62::decode(text) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base64" {
-        if let Ok(bytes) = STANDARD.decode(text) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base85" {
-        if let Ok(bytes) = base85::decode(text) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "base64url" {
-        if let Ok(bytes) = URL_SAFE_NO_PAD.decode(text) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
-        }
-    } else if format == "zlib2base64url" {
-        if let Ok(bytes) = URL_SAFE_NO_PAD.decode(text) {
-            let mut d = ZlibDecoder::new(bytes.as_slice());
-            let mut s = String::new();
-            d.read_to_string(&mut s).unwrap();
-            return s;
+/// decode text by format into bytes, which need not be UTF-8 text. Hex digits may be in either
+/// case for `hex` and `hex-upper`.
+pub fn decode(format: &str, text: &str) -> Result<Vec<u8>, String> {
+    let bytes = match format {
+        "base32" => data_encoding::BASE32_NOPAD.decode(text.as_bytes()).ok(),
+        "base32hex" => data_encoding::BASE32HEX_NOPAD.decode(text.as_bytes()).ok(),
+        "base58" => text.from_base58().ok(),
+        "base62" => base_62::decode(text).ok(),
+        "base64" => STANDARD.decode(text).ok(),
+        "base85" => base85::decode(text).ok(),
+        "base64url" => URL_SAFE_NO_PAD.decode(text).ok(),
+        "zlib2base64url" => URL_SAFE_NO_PAD.decode(text).ok().and_then(|bytes| {
+            let mut plain = Vec::new();
+            ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut plain).ok().map(|_| plain)
+        }),
+        "url" => Some(url_decode_binary(text.as_bytes()).into_owned()),
+        "hex" | "hex-upper" => hex::decode(text).ok(),
+        _ => return Err(format!("unknown format '{}', expected one of {}", format, DECODE_FORMATS)),
+    };
+    bytes.ok_or_else(|| format!("invalid {} text", format))
+}
+
+/// The formats understood by `compress` and `decompress`.
+const COMPRESS_FORMATS: &str = "gzip, zlib, deflate, zstd, lz4";
+
+/// `compress(format, data)`: `data` compressed with `gzip`, `zlib`, raw `deflate`, `zstd` or `lz4`
+/// (in the frame format of the `lz4` command), at the default level.
+pub(crate) fn compress(format: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let res = match format {
+        "gzip" => {
+            let mut e = GzEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).and_then(|_| e.finish())
         }
-    } else if format == "url" {
-        if let Ok(url_text) = url_decode(text) {
-            return url_text.to_string();
+        "zlib" => {
+            let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).and_then(|_| e.finish())
         }
-    } else if format == "hex" {
-        if let Ok(bytes) = hex::decode(text) {
-            if let Ok(text) = String::from_utf8(bytes) {
-                return text;
-            }
+        "deflate" => {
+            let mut e = DeflateEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).and_then(|_| e.finish())
         }
-    }
-    return format!("{}:{}", format, text);
+        "zstd" => zstd::encode_all(data, 0),
+        "lz4" => lz4::EncoderBuilder::new().build(Vec::new()).and_then(|mut e| {
+            e.write_all(data)?;
+            let (out, res) = e.finish();
+            res.map(|_| out)
+        }),
+        _ => return Err(format!("unknown format '{}', expected one of {}", format, COMPRESS_FORMATS)),
+    };
+    res.map_err(|e| e.to_string())
+}
+
+/// `decompress(format, data)`: the inverse of `compress`. Concatenated gzip members are all read.
+pub(crate) fn decompress(format: &str, data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    let res = match format {
+        "gzip" => MultiGzDecoder::new(data).read_to_end(&mut out),
+        "zlib" => ZlibDecoder::new(data).read_to_end(&mut out),
+        "deflate" => DeflateDecoder::new(data).read_to_end(&mut out),
+        "zstd" => zstd::Decoder::new(data).and_then(|mut d| d.read_to_end(&mut out)),
+        "lz4" => lz4::Decoder::new(data).and_then(|mut d| d.read_to_end(&mut out)),
+        _ => return Err(format!("unknown format '{}', expected one of {}", format, COMPRESS_FORMATS)),
+    };
+    res.map_err(|e| format!("invalid {} data: {}", format, e))?;
+    Ok(out)
 }
 
 pub(crate) fn data_url<'b>(text: &str) -> runtime::StrMap<'b, Str<'b>> {
@@ -139,33 +140,106 @@ pub(crate) fn data_url<'b>(text: &str) -> runtime::StrMap<'b, Str<'b>> {
     return SharedMap::from(map);
 }
 
-lazy_static! {
-    static ref BLOOM_FILTERS: Mutex<HashMap<String, GrowableBloom>> = Mutex::new(HashMap::new());
+/// The encoding called `label`, such as `gbk`, `shift_jis`, `euc-kr`, `latin1` or `utf-16le`.
+/// Labels are those of the WHATWG Encoding Standard, so `latin1` and `iso-8859-1` are read as
+/// windows-1252, which they are a subset of.
+pub(crate) fn lookup(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes()).ok_or_else(|| format!("unknown encoding '{}'", label))
 }
 
-pub fn bf_insert(item: &str, group: &str) {
-    let mut filters = BLOOM_FILTERS.lock().unwrap();
-    let filter = filters.entry(group.to_string()).or_insert_with(|| GrowableBloomBuilder::new().build());
-    filter.insert(item);
+/// `iconv(text, from, to)`: `text` converted from the encoding `from` to the encoding `to`. Bytes
+/// that are invalid in `from` become U+FFFD, and characters that `to` has no code for become
+/// HTML numeric character references such as `&#8364;`.
+pub(crate) fn iconv(text: &[u8], from: &str, to: &str) -> Result<Vec<u8>, String> {
+    let (from, to) = (lookup(from)?, lookup(to)?);
+    let (utf8, _) = from.decode_without_bom_handling(text);
+    // encoding_rs only decodes UTF-16, so its encoders for UTF-16 write UTF-8.
+    let bytes = if to == UTF_16LE {
+        utf8.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    } else if to == UTF_16BE {
+        utf8.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    } else {
+        to.encode(&utf8).0.into_owned()
+    };
+    Ok(bytes)
 }
 
-pub fn bf_contains(item: &str, group: &str) -> i64 {
-    let filters = BLOOM_FILTERS.lock().unwrap();
-    if let Some(filter) = filters.get(group) {
-        return filter.contains(item) as i64;
+static INPUT_ENCODING: OnceLock<&'static Encoding> = OnceLock::new();
+
+/// `--input-encoding`: the encoding of the main input and of files read with `getline`.
+pub(crate) fn set_input_encoding(label: &str) -> Result<(), String> {
+    let encoding = lookup(label)?;
+    if encoding != UTF_8 {
+        let _ = INPUT_ENCODING.set(encoding);
     }
-    return 0;
+    Ok(())
 }
 
-pub fn bf_icontains(item: &str, group: &str) -> i64 {
-    let mut filters = BLOOM_FILTERS.lock().unwrap();
-    let filter = filters.entry(group.to_string()).or_insert_with(|| GrowableBloomBuilder::new().build());
-    return if filter.contains(item) {
-        1
-    } else {
-        filter.insert(item);
-        0
-    };
+/// Whether the main input is converted from another encoding (see `decode_input`).
+pub(crate) fn decodes_input() -> bool {
+    INPUT_ENCODING.get().is_some()
+}
+
+/// `r`, converted to UTF-8 from the encoding set with `--input-encoding`, if there is one.
+pub(crate) fn decode_input(r: Box<dyn Read + Send>) -> Box<dyn Read + Send> {
+    match INPUT_ENCODING.get() {
+        Some(encoding) => Box::new(DecodeReader::new(r, encoding)),
+        None => r,
+    }
+}
+
+/// Converts the bytes read from `R` to UTF-8 as they are read, so that records are split on the
+/// converted text. A byte order mark overrides the encoding.
+pub(crate) struct DecodeReader<R> {
+    inner: R,
+    decoder: Decoder,
+    raw: Vec<u8>,
+    decoded: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> DecodeReader<R> {
+    pub(crate) fn new(inner: R, encoding: &'static Encoding) -> DecodeReader<R> {
+        DecodeReader {
+            inner,
+            decoder: encoding.new_decoder(),
+            raw: vec![0; 64 << 10],
+            decoded: Vec::new(),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let n = self.inner.read(&mut self.raw)?;
+        let last = n == 0;
+        let max_len = self
+            .decoder
+            .max_utf8_buffer_length(n)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "input too large to decode"))?;
+        self.decoded.resize(max_len, 0);
+        let (_, _, written, _) = self.decoder.decode_to_utf8(&self.raw[..n], &mut self.decoded, last);
+        self.decoded.truncate(written);
+        self.pos = 0;
+        self.done = last;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecodeReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let n = std::cmp::min(buf.len(), self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
 }
 
 #[cfg(test)]
@@ -175,23 +249,43 @@ mod tests {
     #[test]
     fn test_base32() {
         let text = "Hello";
-        let encode_text = encode("base32", text);
-        let plain = decode("base32", &encode_text);
-        assert_eq!(&plain, text);
+        let encode_text = encode("base32", text.as_bytes()).unwrap();
+        let plain = decode("base32", &encode_text).unwrap();
+        assert_eq!(plain, text.as_bytes());
+    }
+
+    #[test]
+    fn test_iconv() {
+        let gbk = iconv("中文".as_bytes(), "utf-8", "gbk").unwrap();
+        assert_eq!(gbk, b"\xd6\xd0\xce\xc4");
+        assert_eq!(iconv(&gbk, "GBK", "utf-8").unwrap(), "中文".as_bytes());
+        assert_eq!(iconv(b"caf\xe9", "latin1", "utf-8").unwrap(), "café".as_bytes());
+        assert_eq!(iconv("€".as_bytes(), "utf-8", "shift_jis").unwrap(), b"&#8364;");
+        assert_eq!(iconv("hé".as_bytes(), "utf-8", "utf-16le").unwrap(), b"h\0\xe9\0");
+        assert!(iconv(b"x", "klingon", "utf-8").is_err());
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let sjis = iconv("日本語\nテキスト\n".as_bytes(), "utf-8", "sjis").unwrap();
+        let mut reader = DecodeReader::new(&sjis[..], lookup("shift_jis").unwrap());
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "日本語\nテキスト\n");
     }
 
     #[test]
     fn test_base62() {
         let text = "Hello";
-        let encoded_text = encode("base62", text);
+        let encoded_text = encode("base62", text.as_bytes()).unwrap();
         assert_eq!(encoded_text, "Rs8MZpO");
-        let text2 = decode("base62", &encoded_text);
-        assert_eq!(text2, text);
+        let text2 = decode("base62", &encoded_text).unwrap();
+        assert_eq!(text2, text.as_bytes());
     }
 
     #[test]
     fn test_base64() {
-        let encode_text = encode("base64", "Hello");
+        let encode_text = encode("base64", b"Hello").unwrap();
         println!("{}", encode_text);
         assert_eq!(encode_text, "SGVsbG8=")
     }
@@ -199,14 +293,14 @@ mod tests {
     #[test]
     fn test_un_base64() {
         let encoded_text = "SGVsbG8=";
-        let plain_text = decode("base64", encoded_text);
-        println!("{}", plain_text);
-        assert_eq!(plain_text, "Hello")
+        let plain_text = decode("base64", encoded_text).unwrap();
+        println!("{}", String::from_utf8_lossy(&plain_text));
+        assert_eq!(plain_text, b"Hello")
     }
 
     #[test]
     fn test_url_encode() {
-        let encode_text = encode("url", "Hello World");
+        let encode_text = encode("url", b"Hello World").unwrap();
         println!("{}", encode_text);
         assert_eq!(encode_text, "Hello%20World")
     }
@@ -214,14 +308,14 @@ mod tests {
     #[test]
     fn test_url_decode() {
         let encoded_text = "Hello%20World";
-        let plain_text = decode("url", encoded_text);
-        println!("{}", plain_text);
-        assert_eq!(plain_text, "Hello World")
+        let plain_text = decode("url", encoded_text).unwrap();
+        println!("{}", String::from_utf8_lossy(&plain_text));
+        assert_eq!(plain_text, b"Hello World")
     }
 
     #[test]
     fn test_hex2base64() {
-        let base64_text = encode("hex-base64", "91e1fa4f7c75cfb9a684a2f54f7afdb10740c7177307ab227a618caffe993b05");
+        let base64_text = encode("hex-base64", b"91e1fa4f7c75cfb9a684a2f54f7afdb10740c7177307ab227a618caffe993b05").unwrap();
         println!("{}", base64_text);
     }
 
@@ -241,41 +335,19 @@ mod tests {
 Bob -> Alice : hello
 @enduml
         "#.trim();
-        let encoded_text = encode("zlib2base64url", text);
+        let encoded_text = encode("zlib2base64url", text.as_bytes()).unwrap();
         println!("encode: {}", encoded_text);
-        let plain_text = decode("zlib2base64url", &encoded_text);
-        println!("plain: {}", plain_text);
-        assert_eq!(text, plain_text);
-    }
-
-    #[test]
-    fn test_bf_insert() {
-        bf_insert("first", "_");
-        assert_eq!(bf_contains("first", "_"), 1);
-    }
-
-    #[test]
-    fn test_icontains() {
-        println!("first: {}", bf_icontains("first", "_"));
-        println!("second: {}", bf_icontains("second", "_"));
-        println!("first: {}", bf_icontains("first", "_"));
-    }
-
-    #[test]
-    fn test_bloom_filter() {
-        use growable_bloom_filter::GrowableBloomBuilder;
-        let mut bloom = GrowableBloomBuilder::new().build();
-        bloom.insert("first");
-        assert!(bloom.contains("first"));
-        assert_eq!(bloom.contains("second"), false);
+        let plain_text = decode("zlib2base64url", &encoded_text).unwrap();
+        println!("plain: {}", String::from_utf8_lossy(&plain_text));
+        assert_eq!(text.as_bytes(), plain_text);
     }
 
     #[test]
     fn test_base32hex() {
         let text = "Hello";
-        let encoded_text = encode("base32hex", text);
-        let plain_text = decode("base32hex", &encoded_text);
-        assert_eq!(&plain_text, text);
+        let encoded_text = encode("base32hex", text.as_bytes()).unwrap();
+        let plain_text = decode("base32hex", &encoded_text).unwrap();
+        assert_eq!(plain_text, text.as_bytes());
     }
 
     #[test]
@@ -287,4 +359,40 @@ Bob -> Alice : hello
         let plain_text = String::from_utf8(bytes).unwrap();
         assert_eq!(plain_text, text);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_compress() {
+        let text = "zawk ".repeat(100);
+        for format in ["gzip", "zlib", "deflate", "zstd", "lz4"] {
+            let packed = compress(format, text.as_bytes()).unwrap();
+            assert!(packed.len() < text.len(), "{}", format);
+            assert_eq!(decompress(format, &packed).unwrap(), text.as_bytes(), "{}", format);
+        }
+        let mut two = compress("gzip", b"a").unwrap();
+        two.extend(compress("gzip", b"b").unwrap());
+        assert_eq!(decompress("gzip", &two).unwrap(), b"ab");
+        assert_eq!(compress("brotli", b"x").unwrap_err(), "unknown format 'brotli', expected one of gzip, zlib, deflate, zstd, lz4");
+        assert!(decompress("zstd", b"not zstd").unwrap_err().starts_with("invalid zstd data: "));
+        assert_eq!(decompress("gzip", b"").unwrap_err(), "invalid gzip data: unexpected end of file");
+    }
+
+    #[test]
+    fn test_hex_upper() {
+        assert_eq!(encode("hex", b"Hi!").unwrap(), "486921");
+        assert_eq!(encode("hex-upper", b"\xc3\xbf").unwrap(), "C3BF");
+        assert_eq!(decode("hex-upper", "C3BF").unwrap(), b"\xc3\xbf");
+        assert_eq!(decode("hex", "c3Bf").unwrap(), b"\xc3\xbf");
+    }
+
+    #[test]
+    fn test_encode_errors() {
+        let err = encode("base36", b"x").unwrap_err();
+        assert!(err.starts_with("unknown format 'base36', expected one of base32, base32hex, base58,"), "{}", err);
+        assert!(err.ends_with("base64url-hex"), "{}", err);
+        assert!(decode("hex-base64", "x").unwrap_err().ends_with("url, hex, hex-upper"));
+        assert_eq!(encode("hex-base64", b"zz").unwrap_err(), "invalid hex text");
+        assert_eq!(decode("base64", "!!").unwrap_err(), "invalid base64 text");
+        assert_eq!(decode("hex", "ff").unwrap(), b"\xff");
+        assert_eq!(decode("base58", "0OIl").unwrap_err(), "invalid base58 text");
+    }
+}
@@ -0,0 +1,430 @@
+//! Encrypted output files.
+//!
+//! Output redirected to `enc://report.csv` is encrypted with AES-256-GCM before it reaches the
+//! disk. The key is read from the `ZAWK_ENC_KEY` environment variable (or the variable named by
+//! the `key_env` option, as in `enc://report.csv?key_env=REPORT_KEY`): either 64 hex digits for a
+//! raw key, or a passphrase that is stretched with Argon2id.
+//!
+//! The file starts with `MAGIC` and a random salt, followed by a sequence of sealed chunks, each
+//! stored as a 4-byte big-endian length, a random 12-byte nonce and the ciphertext (including the
+//! tag). The top bit of the length marks the last chunk. Every chunk is authenticated together
+//! with the salt, its position in the file and that flag, so chunks cannot be reordered, dropped
+//! or moved between files, and a file that stops short of its last chunk is rejected. zawk reads
+//! `enc://` input files transparently; `open_read` is the inverse of `EncryptedFile`.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+
+pub(crate) const PREFIX: &str = "enc://";
+
+const MAGIC: &[u8; 8] = b"ZAWKENC2";
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN;
+const DEFAULT_KEY_ENV: &str = "ZAWK_ENC_KEY";
+const NONCE_LEN: usize = 12;
+// Set in the length of the last chunk of a file.
+const LAST: u32 = 1 << 31;
+// Plaintext is sealed in chunks of (at most) this size, as well as on every flush.
+const CHUNK_SIZE: usize = 64 << 10;
+// Refuse to allocate absurd buffers when reading a corrupt file.
+const MAX_CHUNK: usize = 64 << 20;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn corrupt(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} is not a valid encrypted file, or the key is wrong",
+            path
+        ),
+    )
+}
+
+fn truncated(path: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is truncated: its last encrypted chunk is missing", path),
+    )
+}
+
+/// The path of the file on disk for an `enc://` url.
+pub(crate) fn file_path(url: &str) -> &str {
+    let rest = url.strip_prefix(PREFIX).unwrap_or(url);
+    rest.split_once('?').map_or(rest, |(path, _)| path)
+}
+
+/// Split an `enc://path?key_env=VAR` url into the file path and the secret to derive its key from.
+fn parse(url: &str) -> io::Result<(String, String)> {
+    let path = file_path(url);
+    let query = url.split_once('?').map_or("", |(_, q)| q);
+    if path.is_empty() {
+        return Err(invalid(format!("missing file name in {}", url)));
+    }
+    let mut key_env = DEFAULT_KEY_ENV;
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some(("key_env", var)) if !var.is_empty() => key_env = var,
+            _ => return Err(invalid(format!("unknown encryption option: {}", pair))),
+        }
+    }
+    let secret = std::env::var(key_env).map_err(|_| {
+        invalid(format!(
+            "no encryption key for {}: set the {} environment variable",
+            url, key_env
+        ))
+    })?;
+    Ok((path.into(), secret))
+}
+
+fn cipher_for(secret: &str, salt: &[u8; SALT_LEN]) -> io::Result<Aes256Gcm> {
+    let key = match hex::decode(secret) {
+        Ok(bytes) if bytes.len() == 32 => bytes,
+        _ => {
+            let mut key = vec![0u8; 32];
+            argon2::Argon2::default()
+                .hash_password_into(secret.as_bytes(), salt, &mut key)
+                .map_err(|e| io::Error::other(format!("failed to derive key: {}", e)))?;
+            key
+        }
+    };
+    Ok(Aes256Gcm::new_from_slice(&key).unwrap())
+}
+
+/// Read the header of an encrypted file, returning its salt.
+fn read_header(r: &mut impl Read, path: &str) -> io::Result<[u8; SALT_LEN]> {
+    let mut header = [0u8; HEADER_LEN];
+    if r.read_exact(&mut header).is_err() || !header.starts_with(MAGIC) {
+        return Err(corrupt(path));
+    }
+    Ok(header[MAGIC.len()..].try_into().unwrap())
+}
+
+// The associated data for the chunk at `index`, given its length field.
+fn chunk_aad(salt: &[u8; SALT_LEN], index: u64, len_field: u32) -> [u8; SALT_LEN + 12] {
+    let mut aad = [0u8; SALT_LEN + 12];
+    aad[..SALT_LEN].copy_from_slice(salt);
+    aad[SALT_LEN..SALT_LEN + 8].copy_from_slice(&index.to_be_bytes());
+    aad[SALT_LEN + 8..].copy_from_slice(&len_field.to_be_bytes());
+    aad
+}
+
+fn open_chunk(
+    cipher: &Aes256Gcm,
+    salt: &[u8; SALT_LEN],
+    index: u64,
+    len_field: u32,
+    frame: &[u8],
+) -> Option<Vec<u8>> {
+    let (nonce, sealed) = frame.split_at(NONCE_LEN);
+    let aad = chunk_aad(salt, index, len_field);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: sealed,
+                aad: &aad,
+            },
+        )
+        .ok()
+}
+
+pub(crate) struct EncryptedFile {
+    file: File,
+    cipher: Aes256Gcm,
+    salt: [u8; SALT_LEN],
+    // The index of the next chunk.
+    index: u64,
+    buf: Vec<u8>,
+}
+
+impl EncryptedFile {
+    pub(crate) fn open(url: &str, append: bool) -> io::Result<EncryptedFile> {
+        let (path, secret) = parse(url)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(!append)
+            .open(&path)?;
+        if file.metadata()?.len() == 0 {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            file.write_all(MAGIC)?;
+            file.write_all(&salt)?;
+            return Ok(EncryptedFile {
+                file,
+                cipher: cipher_for(&secret, &salt)?,
+                salt,
+                index: 0,
+                buf: Vec::with_capacity(CHUNK_SIZE),
+            });
+        }
+        // Appending to an existing file: make sure it is one of ours, then take its last chunk
+        // back so that it can be sealed again, no longer marked as last, once more output arrives.
+        let salt = read_header(&mut file, &path)?;
+        let cipher = cipher_for(&secret, &salt)?;
+        let mut index = 0;
+        let mut offset = HEADER_LEN as u64;
+        let buf = loop {
+            let mut len = [0u8; 4];
+            file.read_exact(&mut len).map_err(|_| truncated(&path))?;
+            let len_field = u32::from_be_bytes(len);
+            let len = (len_field & !LAST) as usize;
+            if len > MAX_CHUNK {
+                return Err(corrupt(&path));
+            }
+            if len_field & LAST == 0 {
+                file.seek(SeekFrom::Current((NONCE_LEN + len) as i64))?;
+                offset += (4 + NONCE_LEN + len) as u64;
+                index += 1;
+                continue;
+            }
+            let mut frame = vec![0u8; NONCE_LEN + len];
+            file.read_exact(&mut frame).map_err(|_| truncated(&path))?;
+            let mut rest = [0u8; 1];
+            if file.read(&mut rest)? != 0 {
+                return Err(corrupt(&path));
+            }
+            break open_chunk(&cipher, &salt, index, len_field, &frame)
+                .ok_or_else(|| corrupt(&path))?;
+        };
+        file.set_len(offset)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(EncryptedFile {
+            file,
+            cipher,
+            salt,
+            index,
+            buf,
+        })
+    }
+
+    fn seal(&mut self, last: bool) -> io::Result<()> {
+        if self.buf.is_empty() && !last {
+            return Ok(());
+        }
+        let len = self.buf.len() + 16;
+        let len_field = len as u32 | if last { LAST } else { 0 };
+        let aad = chunk_aad(&self.salt, self.index, len_field);
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &self.buf[..],
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        debug_assert_eq!(sealed.len(), len);
+        let mut frame = Vec::with_capacity(4 + NONCE_LEN + sealed.len());
+        frame.extend_from_slice(&len_field.to_be_bytes());
+        frame.extend_from_slice(&nonce);
+        frame.extend_from_slice(&sealed);
+        self.file.write_all(&frame)?;
+        self.index += 1;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl Write for EncryptedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        if self.buf.len() >= CHUNK_SIZE {
+            self.seal(false)?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.seal(false)?;
+        self.file.flush()
+    }
+}
+
+impl Drop for EncryptedFile {
+    fn drop(&mut self) {
+        // Always write a last chunk, even an empty one: it is what tells a complete file apart
+        // from a truncated one.
+        let _ = self.seal(true);
+    }
+}
+
+/// Reads the plaintext of a file written by `EncryptedFile`.
+pub(crate) struct DecryptingReader<R> {
+    inner: R,
+    path: String,
+    cipher: Aes256Gcm,
+    salt: [u8; SALT_LEN],
+    index: u64,
+    // Whether the last chunk has been read.
+    done: bool,
+    plain: Vec<u8>,
+    pos: usize,
+}
+
+pub(crate) fn open_read(url: &str) -> io::Result<DecryptingReader<io::BufReader<File>>> {
+    let (path, secret) = parse(url)?;
+    let mut inner = io::BufReader::new(File::open(&path)?);
+    let salt = read_header(&mut inner, &path)?;
+    Ok(DecryptingReader {
+        inner,
+        cipher: cipher_for(&secret, &salt)?,
+        path,
+        salt,
+        index: 0,
+        done: false,
+        plain: Vec::new(),
+        pos: 0,
+    })
+}
+
+impl<R: Read> DecryptingReader<R> {
+    // Decrypt the next chunk into `plain`, returning false at the end of the file.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if self.done {
+            // Nothing may follow the last chunk.
+            let mut rest = [0u8; 1];
+            return match self.inner.read(&mut rest)? {
+                0 => Ok(false),
+                _ => Err(corrupt(&self.path)),
+            };
+        }
+        // Running out of input anywhere before the last chunk, including partway through a
+        // length, means the file was cut short.
+        let mut len = [0u8; 4];
+        self.inner
+            .read_exact(&mut len)
+            .map_err(|_| truncated(&self.path))?;
+        let len_field = u32::from_be_bytes(len);
+        let len = (len_field & !LAST) as usize;
+        if len > MAX_CHUNK {
+            return Err(corrupt(&self.path));
+        }
+        let mut frame = vec![0u8; NONCE_LEN + len];
+        self.inner
+            .read_exact(&mut frame)
+            .map_err(|_| truncated(&self.path))?;
+        self.plain = open_chunk(&self.cipher, &self.salt, self.index, len_field, &frame)
+            .ok_or_else(|| corrupt(&self.path))?;
+        self.index += 1;
+        self.done = len_field & LAST != 0;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plain.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.plain.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plain[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.csv");
+        let url = format!(
+            "{}{}?key_env=ZAWK_TEST_ENC_KEY",
+            PREFIX,
+            path.to_str().unwrap()
+        );
+        std::env::set_var("ZAWK_TEST_ENC_KEY", "correct horse battery staple");
+        {
+            let mut f = EncryptedFile::open(&url, false).unwrap();
+            f.write_all(b"name,ssn\n").unwrap();
+            f.flush().unwrap();
+            f.write_all(&vec![b'x'; CHUNK_SIZE + 10]).unwrap();
+        }
+        {
+            let mut f = EncryptedFile::open(&url, true).unwrap();
+            f.write_all(b"\nlast\n").unwrap();
+        }
+        let raw = std::fs::read(&path).unwrap();
+        assert!(raw.starts_with(MAGIC));
+        assert!(!raw.windows(8).any(|w| w == b"name,ssn"));
+
+        let mut plain = Vec::new();
+        open_read(&url).unwrap().read_to_end(&mut plain).unwrap();
+        let mut expected = b"name,ssn\n".to_vec();
+        expected.extend(vec![b'x'; CHUNK_SIZE + 10]);
+        expected.extend(b"\nlast\n");
+        assert_eq!(plain, expected);
+
+        std::env::set_var("ZAWK_TEST_ENC_KEY", "wrong");
+        let mut plain = Vec::new();
+        assert!(open_read(&url).unwrap().read_to_end(&mut plain).is_err());
+        assert!(parse("enc://x?color=red").is_err());
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.csv");
+        let url = format!(
+            "{}{}?key_env=ZAWK_TEST_TRUNC_KEY",
+            PREFIX,
+            path.to_str().unwrap()
+        );
+        std::env::set_var("ZAWK_TEST_TRUNC_KEY", "a".repeat(64));
+        {
+            let mut f = EncryptedFile::open(&url, false).unwrap();
+            f.write_all(b"first\n").unwrap();
+            f.flush().unwrap();
+            f.write_all(b"second\n").unwrap();
+        }
+        let read = || {
+            let mut plain = Vec::new();
+            open_read(&url)?.read_to_end(&mut plain)?;
+            Ok::<_, io::Error>(plain)
+        };
+        assert_eq!(read().unwrap(), b"first\nsecond\n");
+        let raw = std::fs::read(&path).unwrap();
+        let first_len = HEADER_LEN + 4 + NONCE_LEN + b"first\n".len() + 16;
+        // Dropping the last chunk, or cutting the file anywhere short of its end (including
+        // partway through a length), is an error rather than a shorter file.
+        for cut in [first_len, first_len + 2, raw.len() - 1] {
+            std::fs::write(&path, &raw[..cut]).unwrap();
+            assert!(read().is_err(), "cut at {}", cut);
+        }
+        // So is swapping chunks around, or trailing data after the last chunk.
+        let mut swapped = raw[..HEADER_LEN].to_vec();
+        swapped.extend_from_slice(&raw[first_len..]);
+        swapped.extend_from_slice(&raw[HEADER_LEN..first_len]);
+        std::fs::write(&path, &swapped).unwrap();
+        assert!(read().is_err());
+        let mut trailing = raw.clone();
+        trailing.extend_from_slice(&raw[HEADER_LEN..first_len]);
+        std::fs::write(&path, &trailing).unwrap();
+        assert!(read().is_err());
+
+        // Appending picks up after the last chunk, which is no longer the last one.
+        std::fs::write(&path, &raw).unwrap();
+        {
+            let mut f = EncryptedFile::open(&url, true).unwrap();
+            f.write_all(b"third\n").unwrap();
+        }
+        assert_eq!(read().unwrap(), b"first\nsecond\nthird\n");
+        std::fs::write(&path, &raw[..first_len]).unwrap();
+        assert!(EncryptedFile::open(&url, true).is_err());
+    }
+}
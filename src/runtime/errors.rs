@@ -0,0 +1,109 @@
+//! Errors in builtins that should not stop the program.
+//!
+//! A builtin that cannot produce a result, like `char_at(s, 0)`, an `s3_get` whose request fails,
+//! or a builtin handed a string that is not valid UTF-8, reports the error here and returns an
+//! empty value. By default the message ends up in `ERRNO` and the program carries on, as gawk does
+//! for failed I/O. With `--strict` the error stops the program instead.
+//!
+//! Most builtins reach the error through code that does not have the runtime at hand (`Str`
+//! conversions in particular), so a reported message waits in a thread-local slot until the next
+//! read of `ERRNO` picks it up. Workers of a parallel program each have their own slot, as they
+//! have their own `ERRNO`, and the strict setting is kept next to it: `Core::shuttle` hands it on
+//! to each worker.
+use crate::common::{CompileError, Result};
+
+use std::cell::{Cell, RefCell};
+
+thread_local! {
+    static STRICT: Cell<bool> = const { Cell::new(false) };
+    static PENDING: RefCell<Option<String>> = const { RefCell::new(None) };
+    // A strict-mode error from code that could not return it, until `check` returns it instead.
+    static FATAL: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Make errors reported by builtins on this thread fatal.
+pub(crate) fn set_strict(strict: bool) {
+    STRICT.with(|s| s.set(strict));
+}
+
+pub(crate) fn is_strict() -> bool {
+    STRICT.with(|s| s.get())
+}
+
+/// Report an error from a builtin. In strict mode this returns the error, which the caller
+/// propagates like any other runtime error; otherwise the message is stored for `ERRNO`.
+pub(crate) fn report(msg: String) -> Result<()> {
+    if is_strict() {
+        return Err(CompileError(msg));
+    }
+    PENDING.with(|p| *p.borrow_mut() = Some(msg));
+    Ok(())
+}
+
+/// Like [`report`], for callers that have no way to return an error. In strict mode the error is
+/// held until the runtime next calls [`check`], which returns it.
+pub(crate) fn report_deferred(msg: String) {
+    if let Err(CompileError(msg)) = report(msg) {
+        FATAL.with(|f| *f.borrow_mut() = Some(msg));
+    }
+}
+
+/// Stop the program with `msg` at the next [`check`], whether or not errors are strict. For
+/// builtins that have no sensible result to carry on with.
+pub(crate) fn report_fatal(msg: String) {
+    FATAL.with(|f| *f.borrow_mut() = Some(msg));
+}
+
+/// Return the error held by [`report_deferred`], if there is one. The runtime calls this before
+/// it reads a record or writes output, and when the program finishes, so the error stops the
+/// program as if the builtin had returned it.
+pub(crate) fn check() -> Result<()> {
+    match FATAL.with(|f| f.borrow_mut().take()) {
+        Some(msg) => Err(CompileError(msg)),
+        None => Ok(()),
+    }
+}
+
+/// The error reported since `ERRNO` was last read or assigned, if any.
+pub(crate) fn take() -> Option<String> {
+    PENDING.with(|p| p.borrow_mut().take())
+}
+
+/// Drop any error reported so far, because `ERRNO` was assigned after it.
+pub(crate) fn clear() {
+    PENDING.with(|p| p.borrow_mut().take());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pending() {
+        clear();
+        assert_eq!(take(), None);
+        report("first".into()).unwrap();
+        report("second".into()).unwrap();
+        assert_eq!(take().as_deref(), Some("second"));
+        assert_eq!(take(), None);
+        report("third".into()).unwrap();
+        clear();
+        assert_eq!(take(), None);
+    }
+
+    #[test]
+    fn strict() {
+        set_strict(true);
+        assert!(report("now".into()).is_err());
+        report_deferred("later".into());
+        assert_eq!(take(), None);
+        assert_eq!(check().unwrap_err().0, "later");
+        assert!(check().is_ok());
+        // The setting belongs to this thread alone.
+        assert!(!std::thread::spawn(is_strict).join().unwrap());
+        set_strict(false);
+        report_deferred("errno".into());
+        assert!(check().is_ok());
+        assert_eq!(take().as_deref(), Some("errno"));
+    }
+}
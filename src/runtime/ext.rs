@@ -0,0 +1,329 @@
+//! Builtin functions loaded from shared libraries with `--load`, like gawk's `@load`.
+//!
+//! A library exports a C function `zawk_init`, which zawk calls once, before the program is parsed,
+//! with a table of callbacks it can use to register functions:
+//!
+//! ```c
+//! typedef struct { const unsigned char *ptr; size_t len; } zawk_str;
+//! typedef struct {
+//!     void *buf;
+//!     void (*write)(void *buf, const unsigned char *ptr, size_t len);
+//! } zawk_out;
+//! /* Returns 0 on success; on failure, what was written to `out` is the error message. */
+//! typedef int (*zawk_fn)(const zawk_str *args, size_t nargs, zawk_out *out);
+//! typedef struct {
+//!     unsigned int abi_version; /* 1 */
+//!     void *ctx;
+//!     /* Returns 0 on success. */
+//!     int (*add_function)(void *ctx, const char *name, size_t nargs, zawk_fn f);
+//! } zawk_api;
+//!
+//! int zawk_init(const zawk_api *api); /* Returns 0 on success. */
+//! ```
+//!
+//! Extension functions take a fixed number of strings and return a string; numbers are passed in
+//! their string form. Arrays do not cross the boundary directly: pass `to_json(arr)` and read the
+//! result back with `from_json`. Arguments are only valid for the duration of the call.
+//!
+//! The same types are available to extensions written in Rust as [`ExtStr`], [`Out`] and [`Api`].
+//! A program compiled with `zawk compile` refers to extension functions by the order in which they
+//! were registered, so it must be run with the same `--load` flags.
+use crate::common::Result;
+use crate::runtime::Str;
+
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::sync::OnceLock;
+
+pub const ABI_VERSION: c_uint = 1;
+
+/// A string argument.
+#[repr(C)]
+pub struct ExtStr {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl ExtStr {
+    pub fn as_bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+/// Where an extension function writes its result.
+#[repr(C)]
+pub struct Out {
+    pub buf: *mut c_void,
+    pub write: unsafe extern "C" fn(buf: *mut c_void, ptr: *const u8, len: usize),
+}
+
+impl Out {
+    pub fn write(&mut self, bs: &[u8]) {
+        unsafe { (self.write)(self.buf, bs.as_ptr(), bs.len()) }
+    }
+}
+
+pub type ExtFn = unsafe extern "C" fn(args: *const ExtStr, nargs: usize, out: *mut Out) -> c_int;
+
+/// The table passed to `zawk_init`.
+#[repr(C)]
+pub struct Api {
+    pub abi_version: c_uint,
+    pub ctx: *mut c_void,
+    pub add_function:
+        unsafe extern "C" fn(ctx: *mut c_void, name: *const c_char, nargs: usize, f: ExtFn) -> c_int,
+}
+
+impl Api {
+    /// Register `f` as the function `name`, returning false if the name is taken or invalid.
+    pub fn add_function(&self, name: &CStr, nargs: usize, f: ExtFn) -> bool {
+        unsafe { (self.add_function)(self.ctx, name.as_ptr(), nargs, f) == 0 }
+    }
+}
+
+pub type InitFn = unsafe extern "C" fn(api: *const Api) -> c_int;
+
+pub(crate) struct Extension {
+    pub(crate) name: String,
+    pub(crate) arity: usize,
+    f: ExtFn,
+}
+
+static EXTENSIONS: OnceLock<Vec<Extension>> = OnceLock::new();
+
+fn extensions() -> &'static [Extension] {
+    EXTENSIONS.get().map_or(&[], |exts| &exts[..])
+}
+
+/// The extension function called `name`.
+pub(crate) fn lookup(name: &str) -> Option<u32> {
+    let ix = extensions().iter().position(|e| e.name == name)?;
+    Some(ix as u32)
+}
+
+pub(crate) fn get(ix: u32) -> &'static Extension {
+    &extensions()[ix as usize]
+}
+
+struct Loader {
+    exts: Vec<Extension>,
+    // The first function that could not be registered, and why.
+    error: Option<String>,
+}
+
+unsafe extern "C" fn add_function(
+    ctx: *mut c_void,
+    name: *const c_char,
+    nargs: usize,
+    f: ExtFn,
+) -> c_int {
+    let loader = &mut *(ctx as *mut Loader);
+    let name = CStr::from_ptr(name).to_string_lossy().into_owned();
+    let taken = crate::builtins::Function::try_from(name.as_str()).is_ok()
+        || loader.exts.iter().any(|e| e.name == name);
+    let error = if !crate::lexer::is_ident(&name) {
+        format!("invalid function name {:?}", name)
+    } else if taken {
+        format!("function {} is already defined", name)
+    } else {
+        loader.exts.push(Extension {
+            name,
+            arity: nargs,
+            f,
+        });
+        return 0;
+    };
+    loader.error.get_or_insert(error);
+    1
+}
+
+// Run the `zawk_init` of one library.
+fn init(loader: &mut Loader, what: &str, init: InitFn) -> Result<()> {
+    let api = Api {
+        abi_version: ABI_VERSION,
+        ctx: loader as *mut Loader as *mut c_void,
+        add_function,
+    };
+    let rc = unsafe { init(&api) };
+    if let Some(e) = loader.error.take() {
+        return err!("{}: {}", what, e);
+    }
+    if rc != 0 {
+        return err!("{}: zawk_init failed with status {}", what, rc);
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn open(path: &str) -> Result<InitFn> {
+    err!("failed to load {}: extensions are not supported on this platform", path)
+}
+
+#[cfg(unix)]
+fn open(path: &str) -> Result<InitFn> {
+    let dl_error = || unsafe {
+        let e = libc::dlerror();
+        if e.is_null() {
+            String::from("unknown error")
+        } else {
+            CStr::from_ptr(e).to_string_lossy().into_owned()
+        }
+    };
+    let cpath = match CString::new(path) {
+        Ok(p) => p,
+        Err(_) => return err!("invalid library path {:?}", path),
+    };
+    // The library is never closed: its functions stay registered until the process exits.
+    let handle = unsafe { libc::dlopen(cpath.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+    if handle.is_null() {
+        return err!("failed to load {}: {}", path, dl_error());
+    }
+    let sym = unsafe { libc::dlsym(handle, c"zawk_init".as_ptr()) };
+    if sym.is_null() {
+        return err!("{} is not a zawk extension: {}", path, dl_error());
+    }
+    Ok(unsafe { std::mem::transmute::<*mut c_void, InitFn>(sym) })
+}
+
+/// Load the libraries at `paths`, in order. This can only be done once.
+pub fn load(paths: &[String]) -> Result<()> {
+    let inits = paths
+        .iter()
+        .map(|p| Ok((p.as_str(), open(p)?)))
+        .collect::<Result<Vec<_>>>()?;
+    register(&inits)
+}
+
+fn register(inits: &[(&str, InitFn)]) -> Result<()> {
+    let mut loader = Loader {
+        exts: Vec::new(),
+        error: None,
+    };
+    for (what, f) in inits {
+        init(&mut loader, what, *f)?;
+    }
+    if EXTENSIONS.set(loader.exts).is_err() {
+        return err!("extensions have already been loaded");
+    }
+    Ok(())
+}
+
+unsafe extern "C" fn write_vec(buf: *mut c_void, ptr: *const u8, len: usize) {
+    if len > 0 {
+        (*(buf as *mut Vec<u8>)).extend_from_slice(std::slice::from_raw_parts(ptr, len));
+    }
+}
+
+/// Call extension function `ix`.
+pub(crate) fn call<'a>(ix: u32, args: &[&[u8]]) -> Result<Str<'a>> {
+    let ext = get(ix);
+    let args: Vec<ExtStr> = args
+        .iter()
+        .map(|a| ExtStr {
+            ptr: a.as_ptr(),
+            len: a.len(),
+        })
+        .collect();
+    let mut res = Vec::new();
+    let mut out = Out {
+        buf: &mut res as *mut Vec<u8> as *mut c_void,
+        write: write_vec,
+    };
+    let rc = unsafe { (ext.f)(args.as_ptr(), args.len(), &mut out) };
+    if rc != 0 {
+        return err!("{}: {}", ext.name, String::from_utf8_lossy(&res));
+    }
+    Ok(Str::from(&res[..]).unmoor().upcast())
+}
+
+/// Call extension function `ix` on the contents of `args`.
+pub(crate) fn call_strs<'a>(ix: u32, args: &[&Str]) -> Result<Str<'a>> {
+    let bytes: Vec<&[u8]> = args.iter().map(|s| unsafe { &*s.get_bytes() }).collect();
+    call(ix, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" fn join(args: *const ExtStr, nargs: usize, out: *mut Out) -> c_int {
+        let out = &mut *out;
+        for (i, a) in std::slice::from_raw_parts(args, nargs).iter().enumerate() {
+            if i > 0 {
+                out.write(b"+");
+            }
+            out.write(a.as_bytes());
+        }
+        0
+    }
+
+    unsafe extern "C" fn fails(_: *const ExtStr, _: usize, out: *mut Out) -> c_int {
+        (*out).write(b"no luck");
+        1
+    }
+
+    unsafe extern "C" fn init_test(api: *const Api) -> c_int {
+        let api = &*api;
+        assert_eq!(api.abi_version, ABI_VERSION);
+        assert!(api.add_function(c"reg_join", 3, join));
+        assert!(api.add_function(c"reg_fails", 0, fails));
+        assert!(!api.add_function(c"length", 1, join));
+        0
+    }
+
+    unsafe extern "C" fn init_duplicate(api: *const Api) -> c_int {
+        (*api).add_function(c"reg_join", 1, join);
+        0
+    }
+
+    #[test]
+    fn registration() {
+        let mut loader = Loader {
+            exts: Vec::new(),
+            error: None,
+        };
+        let err = init(&mut loader, "test", init_test).unwrap_err().to_string();
+        assert!(err.contains("length is already defined"), "{}", err);
+        let err = init(&mut loader, "dup", init_duplicate).unwrap_err().to_string();
+        assert!(err.contains("reg_join is already defined"), "{}", err);
+        assert_eq!(loader.exts.len(), 2);
+        assert!(open("/nonexistent/libzawk_ext.so").is_err());
+    }
+
+    // The only test to set the global registry; the other tests register different names.
+    #[test]
+    fn calls() {
+        unsafe extern "C" fn init(api: *const Api) -> c_int {
+            (*api).add_function(c"ext_join", 3, join);
+            (*api).add_function(c"ext_fails", 0, fails);
+            0
+        }
+        register(&[("test", init)]).unwrap();
+        let ix = lookup("ext_join").unwrap();
+        assert_eq!(get(ix).arity, 3);
+        let res = call(ix, &[b"a", b"", b"c"]).unwrap();
+        assert_eq!(res, Str::from("a++c"));
+        let err = call(lookup("ext_fails").unwrap(), &[]).unwrap_err().to_string();
+        assert!(err.contains("ext_fails: no luck"), "{}", err);
+        assert!(lookup("ext_missing").is_none());
+        #[cfg(feature = "jit")]
+        assert_eq!(
+            crate::harness::run_cranelift(
+                r#"BEGIN { print ext_join(1, "b", 2.5) }"#,
+                "",
+                crate::cfg::Escaper::Identity,
+                None,
+                crate::common::ExecutionStrategy::Serial,
+            )
+            .unwrap(),
+            "1+b+2.5\n"
+        );
+        assert_eq!(
+            crate::harness::run_precompiled(r#"BEGIN { x = ext_join("x", "y", "z"); print x }"#, "")
+                .unwrap(),
+            "x+y+z\n"
+        );
+    }
+}
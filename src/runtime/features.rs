@@ -0,0 +1,231 @@
+//! Builtins that need an optional cargo feature.
+//!
+//! `sqlite` and `net` pull in C libraries and sockets that are not available everywhere (notably
+//! wasm32-wasip1), and `bignum` a big-number library, so a build can leave them out. Programs
+//! calling a builtin that was left out fail to compile; for the rest of the runtime, the modules
+//! below stand in for the real ones. `str_arena` is off by default; without it, every string
+//! buffer comes from the heap.
+use crate::builtins::Function;
+
+/// The feature `f` needs, if this build does not have it.
+pub(crate) fn missing(f: &Function) -> Option<&'static str> {
+    use Function::*;
+    match f {
+        SqliteQuery | SqliteExecute if !cfg!(feature = "sqlite") => Some("sqlite"),
+        // The local kv store is a sqlite database; the others are Redis and NATS servers.
+        KvGet | KvPut | KvDelete | KvClear
+            if !cfg!(feature = "sqlite") && !cfg!(feature = "net") =>
+        {
+            Some("sqlite")
+        }
+        BigAdd | BigSub | BigMul | BigDiv | BigMod | BigPow | BigCmp if !cfg!(feature = "bignum") => {
+            Some("bignum")
+        }
+        HttpGet | HttpPost | S3Get | S3Put | MysqlQuery | MysqlExecute | Publish | LocalIp
+            if !cfg!(feature = "net") =>
+        {
+            Some("net")
+        }
+        _ => None,
+    }
+}
+
+/// Report a call to `what`, which needs `feature`. Programs are checked with [`missing`] when they
+/// are compiled, so this is only reached for features that depend on a builtin's arguments, like
+/// the kv store behind a `redis://` namespace. The program stops with the error the next time the
+/// runtime checks for one (see `errors::check`); until then the call returns an empty value.
+#[cfg(not(all(
+    feature = "sqlite",
+    feature = "net",
+    feature = "bignum",
+    feature = "str_arena"
+)))]
+pub(crate) fn unavailable<T: Default>(what: &str, feature: &str) -> T {
+    crate::runtime::errors::report_fatal(format!(
+        "{} is not available: zawk was built without the `{}` feature",
+        what, feature
+    ));
+    T::default()
+}
+
+#[cfg(not(feature = "bignum"))]
+pub(crate) mod bignum {
+    use super::unavailable;
+    use crate::runtime::Int;
+
+    pub(crate) fn enable() {
+        unavailable("-M", "bignum")
+    }
+
+    pub(crate) fn enabled() -> bool {
+        false
+    }
+
+    pub(crate) fn add(_x: &str, _y: &str) -> String {
+        unavailable("big_add", "bignum")
+    }
+
+    pub(crate) fn sub(_x: &str, _y: &str) -> String {
+        unavailable("big_sub", "bignum")
+    }
+
+    pub(crate) fn mul(_x: &str, _y: &str) -> String {
+        unavailable("big_mul", "bignum")
+    }
+
+    pub(crate) fn div(_x: &str, _y: &str) -> String {
+        unavailable("big_div", "bignum")
+    }
+
+    pub(crate) fn rem(_x: &str, _y: &str) -> String {
+        unavailable("big_mod", "bignum")
+    }
+
+    pub(crate) fn pow(_x: &str, _y: &str) -> String {
+        unavailable("big_pow", "bignum")
+    }
+
+    pub(crate) fn cmp(_x: &str, _y: &str) -> Int {
+        unavailable("big_cmp", "bignum")
+    }
+
+    pub(crate) fn is_false(s: &str) -> bool {
+        s.is_empty()
+    }
+
+    pub(crate) fn int_text(_s: &str) -> Option<String> {
+        None
+    }
+}
+
+#[cfg(not(feature = "str_arena"))]
+pub mod str_arena {
+    use super::unavailable;
+
+    pub fn set_enabled(on: bool) {
+        if on {
+            unavailable("--str-arena", "str_arena")
+        }
+    }
+
+    pub(crate) fn enabled() -> bool {
+        false
+    }
+
+    pub(crate) fn alloc_zeroed(_size: usize) -> *mut u8 {
+        std::ptr::null_mut()
+    }
+
+    pub(crate) unsafe fn free(_p: *mut u8) {
+        unreachable!("no string buffers come from the arena without the str_arena feature")
+    }
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub(crate) mod sqlite {
+    use super::unavailable;
+    use crate::runtime::{Int, IntMap, Str};
+
+    pub(crate) fn sqlite_query<'a>(_db_path: &str, _sql: &str) -> IntMap<Str<'a>> {
+        unavailable("sqlite_query", "sqlite")
+    }
+
+    pub(crate) fn sqlite_execute(_db_path: &str, _sql: &str) -> Int {
+        unavailable("sqlite_execute", "sqlite")
+    }
+}
+
+#[cfg(not(feature = "net"))]
+pub(crate) mod mysql {
+    use super::unavailable;
+    use crate::runtime::{Int, IntMap, Str};
+
+    pub(crate) fn mysql_query<'a>(_db_url: &str, _sql: &str) -> IntMap<Str<'a>> {
+        unavailable("mysql_query", "net")
+    }
+
+    pub(crate) fn mysql_execute(_db_url: &str, _sql: &str) -> Int {
+        unavailable("mysql_execute", "net")
+    }
+}
+
+#[cfg(not(feature = "net"))]
+pub(crate) mod network {
+    use super::unavailable;
+    use crate::runtime::{Str, StrMap};
+
+    pub fn local_ip() -> String {
+        unavailable("local_ip", "net")
+    }
+
+    pub(crate) fn http_get<'a>(_url: &str, _headers: &StrMap<'a, Str<'a>>) -> StrMap<'a, Str<'a>> {
+        unavailable("http_get", "net")
+    }
+
+    pub(crate) fn http_post<'a>(
+        _url: &str,
+        _headers: &StrMap<'a, Str<'a>>,
+        _body: &Str,
+    ) -> StrMap<'a, Str<'a>> {
+        unavailable("http_post", "net")
+    }
+
+    pub(crate) fn publish(_namespace: &str, _body: &str) {
+        unavailable("publish", "net")
+    }
+}
+
+#[cfg(not(feature = "net"))]
+pub(crate) mod s3 {
+    use super::unavailable;
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+    #[derive(Default)]
+    pub struct UploadObjectResponse {
+        pub etag: String,
+    }
+
+    pub fn get_object(_bucket_name: &str, _object_name: &str) -> Result<String> {
+        Ok(unavailable("s3_get", "net"))
+    }
+
+    pub fn put_object(
+        _bucket_name: &str,
+        _object_name: &str,
+        _body: &str,
+    ) -> Result<UploadObjectResponse> {
+        Ok(unavailable("s3_put", "net"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::harness::program_compiles;
+
+    #[test]
+    fn missing_features() {
+        let http = program_compiles(
+            r#"BEGIN { print http_get("http://localhost")["body"] }"#,
+            false,
+        );
+        if cfg!(feature = "net") {
+            http.unwrap();
+        } else {
+            let err = http.unwrap_err().to_string();
+            assert!(err.contains("http_get is not available"), "{}", err);
+        }
+        let sqlite = program_compiles(r#"BEGIN { print sqlite_execute("x.db", "") }"#, false);
+        assert_eq!(sqlite.is_ok(), cfg!(feature = "sqlite"));
+        program_compiles("BEGIN { print strtonum(1) }", false).unwrap();
+    }
+
+    #[cfg(not(feature = "str_arena"))]
+    #[test]
+    fn unavailable_stops_the_program() {
+        super::str_arena::set_enabled(true);
+        let err = crate::runtime::errors::check().unwrap_err().to_string();
+        assert!(err.contains("--str-arena is not available"), "{}", err);
+        assert!(crate::runtime::errors::check().is_ok());
+    }
+}
@@ -32,35 +32,45 @@ pub fn strtoi(bs: &[u8]) -> i64 {
 }
 
 /// Simple hexadecimal integer parser, similar in spirit to the strtoi implementation here.
-pub fn hextoi(mut bs: &[u8]) -> i64 {
+pub fn hextoi(bs: &[u8]) -> i64 {
+    radixtoi(bs, 16)
+}
+
+/// Parse an integer in base 2, 8 or 16 with an optional `0b`, `0o` or `0x` prefix. Values take up
+/// to 64 bits, so `0xffffffffffffffff` is -1. Longer values overflow to 0.
+pub fn radixtoi(mut bs: &[u8], radix: u32) -> i64 {
     let mut neg = false;
     if bs.is_empty() {
         return 0;
     }
-    if bs[0] == b'-' {
-        neg = true;
+    if bs[0] == b'-' || bs[0] == b'+' {
+        neg = bs[0] == b'-';
         bs = &bs[1..]
     }
-    if bs.len() >= 2 && bs[0..2] == [b'0', b'x'] || bs[0..2] == [b'0', b'X'] {
+    let prefix = match radix {
+        2 => b'b',
+        8 => b'o',
+        _ => b'x',
+    };
+    if bs.len() >= 2 && bs[0] == b'0' && bs[1].to_ascii_lowercase() == prefix {
         bs = &bs[2..]
     }
-    let mut i = 0i64;
+    let mut i = 0u64;
     for b in bs.iter().cloned() {
-        let digit = match b {
-            b'A'..=b'F' => (b - b'A') as i64 + 10,
-            b'a'..=b'f' => (b - b'a') as i64 + 10,
-            b'0'..=b'9' => (b - b'0') as i64,
-            _ => break,
+        let digit = match (b as char).to_digit(radix) {
+            Some(d) => d as u64,
+            None => break,
         };
-        i = if let Some(i) = i.checked_mul(16).and_then(|i| i.checked_add(digit)) {
+        i = if let Some(i) = i.checked_mul(radix as u64).and_then(|i| i.checked_add(digit)) {
             i
         } else {
             // overflow
             return 0;
         }
     }
+    let i = i as i64;
     if neg {
-        -i
+        i.wrapping_neg()
     } else {
         i
     }
@@ -91,4 +101,18 @@ mod tests {
         assert_eq!(strtod(imax.as_bytes()), i64::max_value() as f64);
         assert_eq!(strtod(imin.as_bytes()), i64::min_value() as f64);
     }
+
+    #[test]
+    fn radix_ints() {
+        assert_eq!(hextoi(b"0x1F"), 31);
+        assert_eq!(hextoi(b"ff"), 255);
+        assert_eq!(hextoi(b"0"), 0);
+        assert_eq!(hextoi(b"-0X10g"), -16);
+        assert_eq!(hextoi(b"0xffffffffffffffff"), -1);
+        assert_eq!(hextoi(b"0x8000000000000000"), i64::MIN);
+        assert_eq!(hextoi(b"0x10000000000000000"), 0);
+        assert_eq!(radixtoi(b"0b1010", 2), 10);
+        assert_eq!(radixtoi(b"0o755", 8), 0o755);
+        assert_eq!(radixtoi(b"0o78", 8), 7);
+    }
 }
@@ -0,0 +1,274 @@
+//! GeoIP lookups for `geoip` and `geoip_db`, backed by MaxMind DB (`.mmdb`) files.
+//!
+//! Databases are opened once and shared by every worker of a parallel script. A script either
+//! opens them with `geoip_db(path)`, typically in BEGIN, or lists them in the `GEOIP_DB`
+//! environment variable (separated like `PATH`), which is read on the first lookup.
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use maxminddb::{MaxMindDBError, Reader};
+use serde::Deserialize;
+
+use crate::runtime::{SharedMap, Str, StrMap};
+
+#[derive(Default)]
+struct Databases {
+    env_loaded: bool,
+    readers: Vec<(String, Reader<Vec<u8>>)>,
+}
+
+impl Databases {
+    fn open(&mut self, path: &str) -> Result<(), String> {
+        if self.readers.iter().any(|(p, _)| p == path) {
+            return Ok(());
+        }
+        let reader = Reader::open_readfile(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.readers.push((path.to_string(), reader));
+        Ok(())
+    }
+
+    fn load_env(&mut self) -> Result<(), String> {
+        self.env_loaded = true;
+        if let Some(paths) = std::env::var_os("GEOIP_DB") {
+            for path in std::env::split_paths(&paths) {
+                self.open(&path.to_string_lossy())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref DATABASES: RwLock<Databases> = RwLock::new(Databases::default());
+}
+
+/// The parts of the GeoIP2/GeoLite2 City, Country and ASN schemas that `geoip` reports.
+#[derive(Deserialize)]
+struct GeoRecord<'a> {
+    #[serde(borrow)]
+    country: Option<Place<'a>>,
+    #[serde(borrow)]
+    subdivisions: Option<Vec<Place<'a>>>,
+    #[serde(borrow)]
+    city: Option<Place<'a>>,
+    #[serde(borrow)]
+    location: Option<Location<'a>>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Place<'a> {
+    iso_code: Option<&'a str>,
+    #[serde(borrow)]
+    names: Option<BTreeMap<&'a str, &'a str>>,
+}
+
+impl<'a> Place<'a> {
+    fn name(&self) -> Option<&'a str> {
+        self.names.as_ref().and_then(|names| names.get("en").copied())
+    }
+}
+
+#[derive(Deserialize)]
+struct Location<'a> {
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    time_zone: Option<&'a str>,
+}
+
+pub(crate) fn geoip_db(path: &str) -> Result<(), String> {
+    DATABASES.write().unwrap().open(path)
+}
+
+/// Looks `ip` up in every open database and merges the results. An address that no database
+/// knows about yields an empty map rather than an error.
+pub(crate) fn geoip<'a>(ip: &str) -> Result<StrMap<'a, Str<'a>>, String> {
+    if !DATABASES.read().unwrap().env_loaded {
+        let mut dbs = DATABASES.write().unwrap();
+        if !dbs.env_loaded {
+            dbs.load_env()?;
+        }
+    }
+    let dbs = DATABASES.read().unwrap();
+    if dbs.readers.is_empty() {
+        return Err("no database, call geoip_db(path) or set GEOIP_DB".to_string());
+    }
+    let addr: IpAddr = ip.parse().map_err(|_| format!("invalid IP address: {}", ip))?;
+    let mut map = hashbrown::HashMap::new();
+    let mut put = |key: &'static str, value: String| {
+        map.insert(Str::from(key), Str::from(value));
+    };
+    for (path, reader) in &dbs.readers {
+        let addr = match addr {
+            // The reader would walk an IPv4-only tree with all 128 bits of an IPv6 address.
+            IpAddr::V6(v6) if reader.metadata.ip_version == 4 => match v6.to_ipv4_mapped() {
+                Some(v4) => IpAddr::V4(v4),
+                None => continue,
+            },
+            addr => addr,
+        };
+        let record: GeoRecord = match reader.lookup(addr) {
+            Ok(record) => record,
+            Err(MaxMindDBError::AddressNotFoundError(_)) => continue,
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        };
+        if let Some(country) = &record.country {
+            if let Some(code) = country.iso_code {
+                put("country", code.to_string());
+            }
+            if let Some(name) = country.name() {
+                put("country_name", name.to_string());
+            }
+        }
+        if let Some(region) = record.subdivisions.as_ref().and_then(|s| s.first()).and_then(Place::name) {
+            put("region", region.to_string());
+        }
+        if let Some(city) = record.city.as_ref().and_then(Place::name) {
+            put("city", city.to_string());
+        }
+        if let Some(location) = &record.location {
+            if let (Some(lat), Some(lon)) = (location.latitude, location.longitude) {
+                put("lat", lat.to_string());
+                put("lon", lon.to_string());
+            }
+            if let Some(tz) = location.time_zone {
+                put("timezone", tz.to_string());
+            }
+        }
+        if let Some(asn) = record.autonomous_system_number {
+            put("asn", asn.to_string());
+        }
+        if let Some(org) = record.autonomous_system_organization {
+            put("as_org", org.to_string());
+        }
+    }
+    Ok(SharedMap::from(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// MaxMind DB data-section encoding of the handful of types the test database needs.
+    enum Val {
+        Str(&'static str),
+        U16(u16),
+        U32(u32),
+        U64(u64),
+        F64(f64),
+        Arr(Vec<Val>),
+        Map(Vec<(&'static str, Val)>),
+    }
+
+    fn encode(v: &Val, out: &mut Vec<u8>) {
+        fn uint(ty: u8, n: u64, out: &mut Vec<u8>) {
+            let bytes = n.to_be_bytes();
+            let skip = bytes.iter().take_while(|b| **b == 0).count();
+            let bytes = &bytes[skip..];
+            if ty < 8 {
+                out.push(ty << 5 | bytes.len() as u8);
+            } else {
+                out.push(bytes.len() as u8);
+                out.push(ty - 7);
+            }
+            out.extend_from_slice(bytes);
+        }
+        match v {
+            Val::Str(s) if s.len() >= 29 => {
+                out.push(2 << 5 | 29);
+                out.push(s.len() as u8 - 29);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Val::Str(s) => {
+                out.push(2 << 5 | s.len() as u8);
+                out.extend_from_slice(s.as_bytes());
+            }
+            Val::U16(n) => uint(5, *n as u64, out),
+            Val::U32(n) => uint(6, *n as u64, out),
+            Val::U64(n) => uint(9, *n, out),
+            Val::F64(f) => {
+                out.push(3 << 5 | 8);
+                out.extend_from_slice(&f.to_be_bytes());
+            }
+            Val::Arr(items) => {
+                out.push(items.len() as u8);
+                out.push(11 - 7);
+                items.iter().for_each(|item| encode(item, out));
+            }
+            Val::Map(entries) => {
+                out.push(7 << 5 | entries.len() as u8);
+                for (k, v) in entries {
+                    encode(&Val::Str(k), out);
+                    encode(v, out);
+                }
+            }
+        }
+    }
+
+    /// An IPv4 database with a single node: 0.0.0.0/1 maps to `record`, 128.0.0.0/1 is empty.
+    fn test_db(record: Val) -> Vec<u8> {
+        let node_count = 1u32;
+        let mut db = Vec::new();
+        let left = node_count + 16;
+        db.extend_from_slice(&left.to_be_bytes()[1..]);
+        db.extend_from_slice(&node_count.to_be_bytes()[1..]);
+        db.extend_from_slice(&[0; 16]);
+        encode(&record, &mut db);
+        db.extend_from_slice(b"\xAB\xCD\xEFMaxMind.com");
+        let metadata = Val::Map(vec![
+            ("binary_format_major_version", Val::U16(2)),
+            ("binary_format_minor_version", Val::U16(0)),
+            ("build_epoch", Val::U64(0)),
+            ("database_type", Val::Str("zawk-test")),
+            ("description", Val::Map(vec![])),
+            ("ip_version", Val::U16(4)),
+            ("languages", Val::Arr(vec![Val::Str("en")])),
+            ("node_count", Val::U32(node_count)),
+            ("record_size", Val::U16(24)),
+        ]);
+        encode(&metadata, &mut db);
+        db
+    }
+
+    fn get(map: &StrMap<Str>, key: &'static str) -> String {
+        map.get(&Str::from(key)).to_string()
+    }
+
+    #[test]
+    fn test_geoip() {
+        let dir = tempfile::tempdir().unwrap();
+        let city = dir.path().join("city.mmdb");
+        let asn = dir.path().join("asn.mmdb");
+        std::fs::write(&city, test_db(Val::Map(vec![
+            ("city", Val::Map(vec![("names", Val::Map(vec![("en", Val::Str("Stockholm"))]))])),
+            ("country", Val::Map(vec![
+                ("iso_code", Val::Str("SE")),
+                ("names", Val::Map(vec![("en", Val::Str("Sweden"))])),
+            ])),
+            ("location", Val::Map(vec![("latitude", Val::F64(59.33)), ("longitude", Val::F64(18.06))])),
+        ]))).unwrap();
+        std::fs::write(&asn, test_db(Val::Map(vec![
+            ("autonomous_system_number", Val::U32(29518)),
+            ("autonomous_system_organization", Val::Str("Bredband2 AB")),
+        ]))).unwrap();
+
+        assert!(geoip_db(dir.path().join("missing.mmdb").to_str().unwrap()).is_err());
+        geoip_db(city.to_str().unwrap()).unwrap();
+        geoip_db(asn.to_str().unwrap()).unwrap();
+        let info = geoip("89.160.20.128").unwrap();
+        assert_eq!(get(&info, "country"), "SE");
+        assert_eq!(get(&info, "country_name"), "Sweden");
+        assert_eq!(get(&info, "city"), "Stockholm");
+        assert_eq!(get(&info, "lat"), "59.33");
+        assert_eq!(get(&info, "lon"), "18.06");
+        assert_eq!(get(&info, "asn"), "29518");
+        assert_eq!(get(&info, "as_org"), "Bredband2 AB");
+        assert_eq!(geoip("200.1.1.1").unwrap().len(), 0);
+        assert_eq!(geoip("::1").unwrap().len(), 0);
+        assert_eq!(get(&geoip("::ffff:89.160.20.128").unwrap(), "asn"), "29518");
+        assert!(geoip("not-an-ip").is_err());
+    }
+}
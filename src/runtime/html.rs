@@ -0,0 +1,74 @@
+//! HTML support for `html_table`.
+use scraper::{ElementRef, Html, Selector};
+
+use crate::runtime::str_escape::escape_csv;
+use crate::runtime::{Int, IntMap, Str};
+
+fn cell_text(cell: &ElementRef) -> String {
+    cell.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ")
+}
+
+/// Rows of the `index`th `<table>` (from 1, in document order) as CSV lines keyed from 1.
+/// Cell text has its whitespace collapsed, a `colspan` cell is followed by empty cells so columns
+/// stay aligned, and rows of nested tables are left out. A missing table gives an empty array.
+pub(crate) fn html_table<'a>(html: &str, index: Int) -> IntMap<Str<'a>> {
+    let result: IntMap<Str> = IntMap::default();
+    if index < 1 {
+        return result;
+    }
+    let doc = Html::parse_document(html);
+    let tables = Selector::parse("table").unwrap();
+    let rows = Selector::parse("tr").unwrap();
+    let table = match doc.select(&tables).nth(index as usize - 1) {
+        Some(table) => table,
+        None => return result,
+    };
+    let mut line = 1;
+    for row in table.select(&rows) {
+        let owner = row.ancestors().filter_map(ElementRef::wrap).find(|e| e.value().name() == "table");
+        if owner.map(|e| e.id()) != Some(table.id()) {
+            continue;
+        }
+        let mut cells = Vec::new();
+        for cell in row.children().filter_map(ElementRef::wrap) {
+            if !matches!(cell.value().name(), "td" | "th") {
+                continue;
+            }
+            cells.push(escape_csv(&cell_text(&cell)));
+            let span = cell.value().attr("colspan").and_then(|s| s.trim().parse::<usize>().ok()).unwrap_or(1);
+            cells.extend(std::iter::repeat_n(String::new(), span.clamp(1, 1000) - 1));
+        }
+        result.insert(line, Str::from(cells.join(",")));
+        line += 1;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_table() {
+        let html = r#"<html><body>
+            <table><tr><td>skip</td></tr></table>
+            <table>
+              <thead><tr><th>Name</th><th>City,  State</th></tr></thead>
+              <tbody>
+                <tr><td> Alice
+                  Smith </td><td>Portland, OR</td></tr>
+                <tr><td colspan="2">Total <b>2</b></td></tr>
+                <tr><td>Nested</td><td><table><tr><td>inner</td></tr></table></td></tr>
+              </tbody>
+            </table></body></html>"#;
+        let rows = html_table(html, 2);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows.get(&1).as_str(), r#"Name,"City, State""#);
+        assert_eq!(rows.get(&2).as_str(), r#"Alice Smith,"Portland, OR""#);
+        assert_eq!(rows.get(&3).as_str(), "Total 2,");
+        assert_eq!(rows.get(&4).as_str(), "Nested,inner");
+        assert_eq!(html_table(html, 3).get(&1).as_str(), "inner");
+        assert_eq!(html_table(html, 4).len(), 0);
+        assert_eq!(html_table(html, 0).len(), 0);
+    }
+}
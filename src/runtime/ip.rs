@@ -0,0 +1,130 @@
+//! Address arithmetic for ip_in_cidr, cidr_contains, ip2int, int2ip and ip_version. Unlike the
+//! rest of the network functions these never touch the network, so they are available without
+//! the "net" feature.
+use std::net::{IpAddr, Ipv4Addr};
+
+use crate::runtime::Int;
+
+/// An address or network as (address width in bits, address, prefix length). IPv4-mapped IPv6
+/// addresses are folded into IPv4 so that `::ffff:10.0.0.1` is inside `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct IpNet {
+    bits: u32,
+    addr: u128,
+    prefix: u32,
+}
+
+impl IpNet {
+    fn from_addr(ip: IpAddr) -> IpNet {
+        match ip {
+            IpAddr::V4(v4) => IpNet { bits: 32, addr: u32::from(v4) as u128, prefix: 32 },
+            IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+                Some(v4) => IpNet::from_addr(IpAddr::V4(v4)),
+                None => IpNet { bits: 128, addr: u128::from(v6), prefix: 128 },
+            },
+        }
+    }
+
+    /// Parses "addr/len", or a bare address as a single-host network. Host bits may be set.
+    fn parse(text: &str) -> Option<IpNet> {
+        match text.split_once('/') {
+            Some((addr, len)) => {
+                let ip = addr.parse::<IpAddr>().ok()?;
+                let prefix = len.parse::<u32>().ok()?;
+                let bits = if ip.is_ipv4() { 32 } else { 128 };
+                if len.starts_with('+') || prefix > bits {
+                    return None;
+                }
+                let addr = match ip {
+                    IpAddr::V4(v4) => u32::from(v4) as u128,
+                    IpAddr::V6(v6) => u128::from(v6),
+                };
+                Some(IpNet { bits, addr, prefix })
+            }
+            None => Some(IpNet::from_addr(text.parse().ok()?)),
+        }
+    }
+
+    fn mask(&self) -> u128 {
+        if self.prefix == 0 {
+            0
+        } else {
+            (u128::MAX >> (128 - self.bits)) & !((1u128 << (self.bits - self.prefix)) - 1)
+        }
+    }
+
+    fn contains(&self, other: &IpNet) -> bool {
+        let mask = self.mask();
+        self.bits == other.bits && other.prefix >= self.prefix && (self.addr & mask) == (other.addr & mask)
+    }
+}
+
+pub(crate) fn is_cidr(text: &str) -> bool {
+    text.contains('/') && IpNet::parse(text).is_some()
+}
+
+/// 1 if `inner` (an address or a network) lies within the network `outer`, otherwise 0.
+pub(crate) fn cidr_contains(outer: &str, inner: &str) -> Int {
+    match (IpNet::parse(outer), IpNet::parse(inner)) {
+        (Some(outer), Some(inner)) if outer.contains(&inner) => 1,
+        _ => 0,
+    }
+}
+
+pub(crate) fn ip_version(text: &str) -> Int {
+    match text.parse::<IpAddr>() {
+        Ok(IpAddr::V4(_)) => 4,
+        Ok(IpAddr::V6(_)) => 6,
+        Err(_) => 0,
+    }
+}
+
+/// The numeric value of an IPv4 (or IPv4-mapped IPv6) address, -1 for anything else.
+pub(crate) fn ip_to_int(text: &str) -> Int {
+    match text.parse::<IpAddr>().map(IpNet::from_addr) {
+        Ok(IpNet { bits: 32, addr, .. }) => addr as Int,
+        _ => -1,
+    }
+}
+
+/// Dotted-quad form of an IPv4 address value, empty if `n` is outside 0..=2^32-1.
+pub(crate) fn int_to_ip(n: Int) -> String {
+    match u32::try_from(n) {
+        Ok(n) => Ipv4Addr::from(n).to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ip_cidr() {
+        assert_eq!(cidr_contains("10.0.0.0/8", "10.1.2.3"), 1);
+        assert_eq!(cidr_contains("10.0.0.0/8", "11.1.2.3"), 0);
+        assert_eq!(cidr_contains("10.0.0.0/8", "10.20.0.0/16"), 1);
+        assert_eq!(cidr_contains("10.20.0.0/16", "10.0.0.0/8"), 0);
+        assert_eq!(cidr_contains("0.0.0.0/0", "192.168.1.1"), 1);
+        assert_eq!(cidr_contains("10.0.0.0/8", "::ffff:10.0.0.1"), 1);
+        assert_eq!(cidr_contains("2001:db8::/32", "2001:db8:1::5"), 1);
+        assert_eq!(cidr_contains("2001:db8::/32", "2001:db9::5"), 0);
+        assert_eq!(cidr_contains("::/0", "10.0.0.1"), 0);
+        assert_eq!(cidr_contains("10.0.0.0/33", "10.0.0.1"), 0);
+        assert_eq!(cidr_contains("10.0.0.0/8", "bogus"), 0);
+        assert!(is_cidr("192.168.0.0/24"));
+        assert!(is_cidr("fe80::/10"));
+        assert!(!is_cidr("192.168.0.1"));
+        assert!(!is_cidr("192.168.0.0/+8"));
+        assert!(!is_cidr("fe80::/129"));
+        assert_eq!(ip_version("1.2.3.4"), 4);
+        assert_eq!(ip_version("::1"), 6);
+        assert_eq!(ip_version("1.2.3"), 0);
+        assert_eq!(ip_to_int("192.168.1.1"), 3232235777);
+        assert_eq!(ip_to_int("::ffff:0.0.1.0"), 256);
+        assert_eq!(ip_to_int("::1"), -1);
+        assert_eq!(int_to_ip(3232235777), "192.168.1.1");
+        assert_eq!(int_to_ip(-1), "");
+        assert_eq!(int_to_ip(1 << 32), "");
+    }
+}
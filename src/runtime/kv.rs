@@ -1,5 +1,8 @@
+#[cfg(any(feature = "sqlite", feature = "net"))]
 use std::collections::HashMap;
+#[cfg(any(feature = "sqlite", feature = "net"))]
 use std::sync::Mutex;
+#[cfg(any(feature = "sqlite", feature = "net"))]
 use lazy_static::lazy_static;
 
 pub(crate) fn kv_get(namespace: &str, key: &str) -> String {
@@ -42,12 +45,50 @@ pub(crate) fn kv_clear(namespace: &str) {
     };
 }
 
+#[cfg(feature = "sqlite")]
 lazy_static! {
     static ref SQLITE_CONNECTIONS: Mutex<HashMap<String, rusqlite::Connection>> = Mutex::new(HashMap::new());
+}
+
+#[cfg(feature = "net")]
+lazy_static! {
     static ref REDIS_CONNECTIONS: Mutex<HashMap<String, redis::Connection>> = Mutex::new(HashMap::new());
     static ref NATS_JETSTREAM: Mutex<HashMap<String, nats::jetstream::JetStream>> = Mutex::new(HashMap::new());
 }
 
+// Stands in for a backend that this build leaves out.
+#[cfg(not(all(feature = "sqlite", feature = "net")))]
+macro_rules! unavailable_kv {
+    ($backend:ident, $what:expr, $feature:expr) => {
+        mod $backend {
+            use crate::runtime::features::unavailable;
+
+            pub(crate) fn kv_get(_namespace: &str, _key: &str) -> String {
+                unavailable($what, $feature)
+            }
+
+            pub(crate) fn kv_put(_namespace: &str, _key: &str, _value: &str) {
+                unavailable($what, $feature)
+            }
+
+            pub(crate) fn kv_delete(_namespace: &str, _key: &str) {
+                unavailable($what, $feature)
+            }
+
+            pub(crate) fn kv_clear(_namespace: &str) {
+                unavailable($what, $feature)
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "net"))]
+unavailable_kv!(redis_kv, "the Redis kv store", "net");
+#[cfg(not(feature = "net"))]
+unavailable_kv!(nats_kv, "the NATS kv store", "net");
+#[cfg(not(feature = "sqlite"))]
+unavailable_kv!(sqlite_kv, "the local kv store", "sqlite");
+
 fn is_redis_url(namespace: &str) -> bool {
     namespace.starts_with("redis://") || namespace.starts_with("redis+tls://")
 }
@@ -56,6 +97,7 @@ fn is_nats_url(namespace: &str) -> bool {
     namespace.starts_with("nats://")
 }
 
+#[cfg(feature = "net")]
 mod redis_kv {
     use crate::runtime::kv::REDIS_CONNECTIONS;
     use redis::Commands;
@@ -119,6 +161,7 @@ mod redis_kv {
     }
 }
 
+#[cfg(feature = "net")]
 mod nats_kv {
     use nats::jetstream::JetStream;
     use crate::runtime::kv::NATS_JETSTREAM;
@@ -200,6 +243,7 @@ mod nats_kv {
     }
 }
 
+#[cfg(feature = "sqlite")]
 mod sqlite_kv {
     use rusqlite::{Connection, OptionalExtension};
     use crate::runtime::kv::{SQLITE_CONNECTIONS};
@@ -265,7 +309,7 @@ mod sqlite_kv {
 }
 
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sqlite"))]
 mod tests {
     use super::*;
 
@@ -0,0 +1,431 @@
+//! Locale-aware reading and writing of numbers: `parse_number`, `format_num`, the `'` printf flag
+//! and `--use-lc-numeric`.
+//!
+//! Spreadsheet and accounting exports format numbers for people: `1.234,56 €` in Germany,
+//! `(1,234.56)` for a negative amount in a US ledger. `strtonum` reads the numeric prefix of both
+//! (`1.234` and `0`); `parse_number` reads them as `1234.56` and `-1234.56`.
+//!
+//! A locale only decides the decimal mark; `.`, `,`, spaces and apostrophes that are not the decimal
+//! mark are thousands separators. Without a locale the decimal mark is guessed: the last of `.` and
+//! `,` when both appear, and otherwise `.`, or a single `,` that is not followed by exactly three
+//! digits.
+//!
+//! Writing numbers uses the `LC_NUMERIC` data of the glibc locales (the decimal point, thousands
+//! separator and digit grouping), so `1234567` is `1,234,567` in `en_US`, `1.234.567` in `de_DE`
+//! and `12,34,567` in `en_IN`. The C locale does not group digits.
+use crate::runtime::{rounding, Float};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Languages that write `1.234,5`, or `1 234,5`.
+const COMMA_DECIMAL: &[&str] = &[
+    "af", "az", "be", "bg", "bs", "ca", "cs", "da", "de", "el", "es", "et", "eu", "fi", "fo", "fr",
+    "gl", "hr", "hu", "hy", "id", "is", "it", "ka", "kk", "ky", "lt", "lv", "mk", "mn", "nb", "nl",
+    "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sq", "sr", "sv", "tr", "uk", "uz", "vi",
+];
+
+/// Regions that use `.` although their language usually does not, as in `de_CH` or `es_MX`.
+const POINT_DECIMAL_REGIONS: &[&str] =
+    &["ch", "li", "mx", "us", "pr", "gt", "do", "ni", "pa", "sv"];
+
+/// The decimal mark of `locale`, a name like `de_DE`, `pt-BR` or `fr`, or the mark itself (`,` or
+/// `.`). `None` means it should be guessed.
+pub(crate) fn decimal_mark(locale: &str) -> Option<char> {
+    let locale = locale.trim();
+    match locale {
+        "" | "C" | "POSIX" => return None,
+        "." => return Some('.'),
+        "," => return Some(','),
+        _ => {}
+    }
+    // Drop an encoding or modifier, as in de_DE.UTF-8 or sr_RS@latin.
+    let name = locale
+        .split(['.', '@'])
+        .next()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let mut parts = name.split(['_', '-']);
+    let lang = parts.next().unwrap_or("");
+    let region = parts.next().unwrap_or("");
+    if COMMA_DECIMAL.contains(&lang) && !POINT_DECIMAL_REGIONS.contains(&region) {
+        Some(',')
+    } else {
+        Some('.')
+    }
+}
+
+fn is_separator(c: char) -> bool {
+    matches!(
+        c,
+        '.' | ',' | '\'' | '\u{2019}' | ' ' | '\u{a0}' | '\u{202f}' | '\u{2009}' | '_'
+    )
+}
+
+fn is_minus(c: char) -> bool {
+    matches!(c, '-' | '\u{2212}')
+}
+
+// Guess the decimal mark of `mantissa`, which has digits at both ends.
+fn guess_mark(mantissa: &str) -> char {
+    match (mantissa.rfind('.'), mantissa.rfind(',')) {
+        (Some(dot), Some(comma)) => {
+            if dot > comma {
+                '.'
+            } else {
+                ','
+            }
+        }
+        (None, Some(comma)) => {
+            let after = &mantissa[comma + 1..];
+            let thousands = after.len() == 3 && after.bytes().all(|b| b.is_ascii_digit());
+            if mantissa.matches(',').count() == 1 && !thousands {
+                ','
+            } else {
+                '.'
+            }
+        }
+        _ => '.',
+    }
+}
+
+/// Read `s` as a number written for `locale` (see [`decimal_mark`]). Currency symbols and codes
+/// around the number are ignored, and a number in parentheses, or with a trailing minus, is
+/// negative. Text that does not contain a number gives NaN.
+pub(crate) fn parse_number(s: &str, locale: &str) -> Float {
+    let s = s.trim();
+    let (first, last) = match (
+        s.find(|c: char| c.is_ascii_digit()),
+        s.rfind(|c: char| c.is_ascii_digit()),
+    ) {
+        (Some(first), Some(last)) => (first, last + 1),
+        _ => return Float::NAN,
+    };
+    let (mut prefix, mut body, suffix) = (&s[..first], &s[first..last], &s[last..]);
+    // A leading decimal mark, as in `$.50`.
+    if let Some(c @ ('.' | ',')) = prefix.chars().next_back() {
+        prefix = &prefix[..prefix.len() - 1];
+        body = &s[first - c.len_utf8()..last];
+    }
+    let negative = prefix.chars().any(|c| is_minus(c) || c == '(')
+        || suffix.chars().any(|c| is_minus(c) || c == ')');
+    let (mantissa, exponent) = match body.find(['e', 'E']) {
+        Some(ix) => (&body[..ix], Some(&body[ix + 1..])),
+        None => (body, None),
+    };
+    let mark = decimal_mark(locale).unwrap_or_else(|| guess_mark(mantissa));
+    let mut num = String::with_capacity(body.len() + 1);
+    if negative {
+        num.push('-');
+    }
+    let mut seen_mark = false;
+    for c in mantissa.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+        } else if c == mark && !seen_mark {
+            seen_mark = true;
+            num.push('.');
+        } else if !is_separator(c) || seen_mark {
+            // Thousands separators only appear before the decimal mark.
+            return Float::NAN;
+        }
+    }
+    if let Some(exp) = exponent {
+        let digits = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Float::NAN;
+        }
+        num.push('e');
+        num.push_str(exp);
+    }
+    num.parse().unwrap_or(Float::NAN)
+}
+
+/// How a locale writes numbers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Numeric {
+    pub(crate) decimal_point: &'static str,
+    pub(crate) thousands_sep: &'static str,
+    // Group sizes from the right, as in `localeconv`: the last one repeats, and a size that is not
+    // positive ends the grouping.
+    grouping: &'static [i64],
+}
+
+const C_NUMERIC: Numeric = Numeric {
+    decimal_point: ".",
+    thousands_sep: "",
+    grouping: &[],
+};
+
+static USE_LC_NUMERIC: AtomicBool = AtomicBool::new(false);
+
+/// `--use-lc-numeric`: print floats with the decimal point of the environment's locale.
+pub(crate) fn set_use_lc_numeric(on: bool) {
+    USE_LC_NUMERIC.store(on, Ordering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn use_lc_numeric() -> bool {
+    USE_LC_NUMERIC.load(Ordering::Relaxed)
+}
+
+impl Numeric {
+    /// The conventions of `locale`, a name like `de_DE.UTF-8`. Locales without glibc data fall back
+    /// on their decimal mark (see [`decimal_mark`]), grouping by thousands.
+    pub(crate) fn of(locale: &str) -> Numeric {
+        let name = locale.trim().split(['.', '@']).next().unwrap_or("");
+        if matches!(name, "" | "C" | "POSIX") {
+            return C_NUMERIC;
+        }
+        if let Ok(locale) = pure_rust_locales::Locale::try_from(name.replace('-', "_").as_str()) {
+            return Numeric {
+                decimal_point: pure_rust_locales::locale_match!(locale => LC_NUMERIC::DECIMAL_POINT),
+                thousands_sep: pure_rust_locales::locale_match!(locale => LC_NUMERIC::THOUSANDS_SEP),
+                grouping: pure_rust_locales::locale_match!(locale => LC_NUMERIC::GROUPING),
+            };
+        }
+        match decimal_mark(locale) {
+            Some(',') => Numeric {
+                decimal_point: ",",
+                thousands_sep: ".",
+                grouping: &[3],
+            },
+            _ => Numeric {
+                decimal_point: ".",
+                thousands_sep: ",",
+                grouping: &[3],
+            },
+        }
+    }
+
+    /// The conventions of the environment's `LC_NUMERIC` locale, from `LC_ALL`, `LC_NUMERIC` or
+    /// `LANG`.
+    pub(crate) fn current() -> &'static Numeric {
+        static CURRENT: OnceLock<Numeric> = OnceLock::new();
+        CURRENT.get_or_init(|| {
+            let name = ["LC_ALL", "LC_NUMERIC", "LANG"]
+                .iter()
+                .filter_map(|var| std::env::var(var).ok())
+                .find(|name| !name.is_empty())
+                .unwrap_or_default();
+            Numeric::of(&name)
+        })
+    }
+
+    // Append `digits`, an integer, to `out` with the thousands separator between groups of
+    // `sizes`.
+    fn group(&self, digits: &str, sizes: &[i64], out: &mut String) {
+        let mut breaks = Vec::new();
+        let mut end = digits.len();
+        let mut ix = 0;
+        if !self.thousands_sep.is_empty() {
+            while let Some(&size) = sizes.get(ix).or(sizes.last()) {
+                if size <= 0 || size as usize >= end {
+                    break;
+                }
+                end -= size as usize;
+                breaks.push(end);
+                ix += 1;
+            }
+        }
+        let mut start = 0;
+        for &b in breaks.iter().rev() {
+            out.push_str(&digits[start..b]);
+            out.push_str(self.thousands_sep);
+            start = b;
+        }
+        out.push_str(&digits[start..]);
+    }
+
+    /// Rewrite `num`, a number as printf writes it (`-1234.5`, `1.5e3`), with this locale's decimal
+    /// point and, if `group` is set, its thousands separator.
+    pub(crate) fn localize(&self, num: &str, group: bool) -> String {
+        let mut out = String::with_capacity(num.len() + 8);
+        let start = num.find(|c: char| c.is_ascii_digit()).unwrap_or(num.len());
+        let digits = num[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(num.len(), |n| start + n);
+        out.push_str(&num[..start]);
+        if group {
+            self.group(&num[start..digits], self.grouping, &mut out);
+        } else {
+            out.push_str(&num[start..digits]);
+        }
+        let rest = &num[digits..];
+        match rest.strip_prefix('.') {
+            Some(frac) => {
+                out.push_str(self.decimal_point);
+                out.push_str(frac);
+            }
+            None => out.push_str(rest),
+        }
+        out
+    }
+}
+
+/// `format_num(x, pattern[, locale])`: `x` written with a pattern like `#,##0.00`, in the style of
+/// spreadsheets and Java's `DecimalFormat`.
+///
+/// * `0` is a digit that is always printed and `#` one that is printed if it is significant;
+/// * `,` groups the integer digits by the number of digits after the last `,`;
+/// * `.` starts the fraction: `x` is rounded to as many places as there are digits after it, and
+///   trailing zeros are dropped down to the number of `0`s;
+/// * other text before and after the digits is copied, and a `%` in it multiplies `x` by 100.
+///
+/// With a `locale`, the grouping separator and decimal point are those of the locale.
+pub(crate) fn format_num(x: Float, pattern: &str, locale: &str) -> String {
+    let is_digit = |c: char| matches!(c, '#' | '0' | ',' | '.');
+    let (prefix, body, suffix) = match (pattern.find(is_digit), pattern.rfind(is_digit)) {
+        (Some(start), Some(end)) => (&pattern[..start], &pattern[start..=end], &pattern[end + 1..]),
+        // A pattern without digits is text to put before the number.
+        _ => (pattern, "0.###", ""),
+    };
+    let (int_pat, frac_pat) = body.split_once('.').unwrap_or((body, ""));
+    let min_int = int_pat.matches('0').count();
+    let group_size = int_pat
+        .rfind(',')
+        .map_or(0, |ix| int_pat[ix..].matches(['#', '0']).count());
+    let min_frac = frac_pat.matches('0').count();
+    let max_frac = frac_pat.matches(['#', '0']).count();
+    let numeric = if locale.is_empty() {
+        Numeric {
+            decimal_point: ".",
+            thousands_sep: ",",
+            grouping: &[],
+        }
+    } else {
+        Numeric::of(locale)
+    };
+
+    let mut x = x;
+    if prefix.contains('%') || suffix.contains('%') {
+        x *= 100.0;
+    }
+    let mut out = String::new();
+    if !x.is_finite() {
+        if x < 0.0 {
+            out.push('-');
+        }
+        out.push_str(prefix);
+        out.push_str(if x.is_nan() { "nan" } else { "inf" });
+        out.push_str(suffix);
+        return out;
+    }
+    let rounded = rounding::for_printf(x.abs(), max_frac, false).unwrap_or(x.abs());
+    let text = format!("{:.*}", max_frac, rounded);
+    let (int, frac) = text.split_once('.').unwrap_or((&text, ""));
+    let mut frac = frac;
+    while frac.len() > min_frac && frac.ends_with('0') {
+        frac = &frac[..frac.len() - 1];
+    }
+    let int = int.trim_start_matches('0');
+    let int = if int.len() < min_int {
+        format!("{}{}", "0".repeat(min_int - int.len()), int)
+    } else {
+        int.to_string()
+    };
+    if x < 0.0 && (int.bytes().chain(frac.bytes())).any(|b| b != b'0') {
+        out.push('-');
+    }
+    out.push_str(prefix);
+    if int.is_empty() && frac.is_empty() {
+        out.push('0');
+    }
+    numeric.group(&int, &[group_size as i64], &mut out);
+    if !frac.is_empty() {
+        out.push_str(numeric.decimal_point);
+        out.push_str(frac);
+    }
+    out.push_str(suffix);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locales() {
+        assert_eq!(decimal_mark("de_DE.UTF-8"), Some(','));
+        assert_eq!(decimal_mark("pt-BR"), Some(','));
+        assert_eq!(decimal_mark("de_CH"), Some('.'));
+        assert_eq!(decimal_mark("en_US"), Some('.'));
+        assert_eq!(decimal_mark("ja"), Some('.'));
+        assert_eq!(decimal_mark(","), Some(','));
+        assert_eq!(decimal_mark(""), None);
+    }
+
+    #[test]
+    fn numbers() {
+        let cases: &[(&str, &str, Float)] = &[
+            ("1.234,56 €", "de_DE", 1234.56),
+            ("1.234.567", "de", 1234567.0),
+            ("(1,234.56)", "en_US", -1234.56),
+            ("$-12", "en", -12.0),
+            ("12-", "en", -12.0),
+            ("1\u{202f}234,5", "fr_FR", 1234.5),
+            ("CHF 1'234.50", "de_CH", 1234.5),
+            ("1,234", "de", 1.234),
+            ("$.50", "", 0.5),
+            ("1.234,56", "", 1234.56),
+            ("1,234.56", "", 1234.56),
+            ("1,234", "", 1234.0),
+            ("3,5", "", 3.5),
+            ("1.5e3 USD", "", 1500.0),
+            ("\u{2212}7", "", -7.0),
+        ];
+        for (s, locale, want) in cases {
+            assert_eq!(parse_number(s, locale), *want, "{:?} in {:?}", s, locale);
+        }
+        for s in [
+            "",
+            "n/a",
+            "1.2.3,4.5",
+            "12abc34",
+            "1,234.5",
+            "2024-01-02",
+            "2e+x3",
+        ] {
+            assert!(parse_number(s, "de").is_nan(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn grouping() {
+        let cases: &[(&str, &str, &str)] = &[
+            ("en_US.UTF-8", "-1234567.891", "-1,234,567.891"),
+            ("de_DE", "1234567.5", "1.234.567,5"),
+            ("en_IN", "123456789", "12,34,56,789"),
+            ("fr_FR", "12345", "12\u{202f}345"),
+            ("en_US", "999", "999"),
+            ("en_US", "1.5e12", "1.5e12"),
+            ("C", "1234567.5", "1234567.5"),
+            ("de", "1234.5", "1.234,5"),
+        ];
+        for (locale, num, want) in cases {
+            assert_eq!(Numeric::of(locale).localize(num, true), *want, "{} in {}", num, locale);
+        }
+        assert_eq!(Numeric::of("de_DE").localize("1234.5", false), "1234,5");
+    }
+
+    #[test]
+    fn patterns() {
+        let cases: &[(Float, &str, &str, &str)] = &[
+            (1234567.891, "#,##0.00", "", "1,234,567.89"),
+            (1234567.891, "#,##0.00", "de_DE", "1.234.567,89"),
+            (-1234.5, "$#,##0.00", "", "-$1,234.50"),
+            (0.5, "#.##", "", ".5"),
+            (0.0, "#.##", "", "0"),
+            (7.0, "000", "", "007"),
+            (0.256, "0.0%", "", "25.6%"),
+            (-0.001, "0.00", "", "0.00"),
+            (1234.5678, "#,##0.0#", "", "1,234.57"),
+            (1234.0, "#,##0.0#", "", "1,234.0"),
+            (123456789.0, "#,####", "", "1,2345,6789"),
+            (42.0, "n = 0 units", "", "n = 42 units"),
+        ];
+        for (x, pattern, locale, want) in cases {
+            assert_eq!(format_num(*x, pattern, locale), *want, "{} {:?}", x, pattern);
+        }
+    }
+}
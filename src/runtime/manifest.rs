@@ -0,0 +1,132 @@
+//! Output manifests.
+//!
+//! With `--manifest out.manifest`, zawk remembers every file it writes through the writers
+//! registry. Once all output has been flushed it writes a JSON manifest listing the row (line)
+//! count and SHA-256 digest of each of those files, so that downstream jobs can check that a
+//! multi-file output arrived complete.
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use sha2::{Digest, Sha256};
+
+use super::command;
+use super::encrypted;
+use super::rotate::{self, RotateSpec};
+
+struct Manifest {
+    path: String,
+    files: Mutex<BTreeSet<String>>,
+    written: AtomicBool,
+}
+
+static MANIFEST: OnceLock<Manifest> = OnceLock::new();
+
+/// Write a manifest to `path` when the program exits.
+pub fn enable(path: &str) {
+    let _ = MANIFEST.set(Manifest {
+        path: path.into(),
+        files: Default::default(),
+        written: AtomicBool::new(false),
+    });
+}
+
+/// Add an output file to the manifest, if one is being kept.
+pub fn record(name: &str) {
+    let manifest = match MANIFEST.get() {
+        Some(m) => m,
+        None => return,
+    };
+    let path = if name.starts_with(rotate::PREFIX) {
+        // Only the live file is listed; rotated-out copies are not tracked.
+        match RotateSpec::parse(name) {
+            Ok(spec) => spec.path,
+            Err(_) => return,
+        }
+    } else {
+        name.into()
+    };
+    if path.starts_with("/dev/") || path.starts_with(command::SPAWN_PREFIX) || path == manifest.path
+    {
+        return;
+    }
+    // Encrypted files keep their url (with any key_env option) so that finish can count the rows of
+    // the plaintext.
+    manifest.files.lock().unwrap().insert(path);
+}
+
+/// Write out the manifest. This should be called once all output files have been flushed; only
+/// the first call has any effect.
+pub(crate) fn finish() -> io::Result<()> {
+    let manifest = match MANIFEST.get() {
+        Some(m) => m,
+        None => return Ok(()),
+    };
+    if manifest.written.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+    let files = std::mem::take(&mut *manifest.files.lock().unwrap());
+    let mut entries = Vec::with_capacity(files.len());
+    for name in files {
+        let (path, rows, sha256) = if name.starts_with(encrypted::PREFIX) {
+            // The digest covers the encrypted contents, as written to disk.
+            let path = encrypted::file_path(&name);
+            let (rows, _) = summarize(encrypted::open_read(&name)?)?;
+            let (_, sha256) = summarize(File::open(path)?)?;
+            (path.to_string(), rows, sha256)
+        } else {
+            let (rows, sha256) = summarize(File::open(&name)?)?;
+            (name, rows, sha256)
+        };
+        entries.push(serde_json::json!({
+            "path": path,
+            "rows": rows,
+            "sha256": sha256,
+        }));
+    }
+    let out = File::create(&manifest.path)?;
+    serde_json::to_writer_pretty(out, &serde_json::json!({ "files": entries }))?;
+    Ok(())
+}
+
+/// Count the lines in `r` (including an unterminated final line) and compute its SHA-256 digest.
+fn summarize(mut r: impl Read) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 << 10];
+    let mut rows = 0u64;
+    let mut last = b'\n';
+    loop {
+        let n = match r.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buf[..n]);
+        rows += memchr::memchr_iter(b'\n', &buf[..n]).count() as u64;
+        last = buf[n - 1];
+    }
+    if last != b'\n' {
+        rows += 1;
+    }
+    Ok((rows, hex::encode(hasher.finalize())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize() {
+        let (rows, sha) = summarize(&b"a\nb\n"[..]).unwrap();
+        assert_eq!(rows, 2);
+        assert_eq!(
+            sha,
+            "911169ddaaf146aff539f58c26c489af3b892dff0fe283c1c264c65ae5aa59a2"
+        );
+        assert_eq!(summarize(&b"a\nb"[..]).unwrap().0, 2);
+        assert_eq!(summarize(&b""[..]).unwrap().0, 0);
+    }
+}
@@ -0,0 +1,182 @@
+//! Looking up and storing the keys of AWK arrays.
+//!
+//! Group-by programs look the same few keys up over and over, often keys built on the spot like
+//! `a[$1, $2]` or `a[$1 "-" $2]`. Such keys are concatenations, and hashing one the usual way
+//! flattens it into a freshly allocated buffer that is thrown away at the end of the record. Here
+//! they are instead copied into a reusable scratch buffer and looked up by their bytes; a buffer
+//! of their own is only allocated when they are stored as a new key.
+//!
+//! With `--intern-keys`, keys stored in string-keyed arrays are also interned: each distinct key
+//! is copied out of the input once and shared by every array that holds it, rather than keeping
+//! the whole input chunk it was read from alive. The table of interned keys is bounded; once it
+//! is full, new keys are still copied out of the input but no longer shared.
+//!
+//! Strings in the string arena (see `str_arena`) are copied out of it when they are stored in an
+//! array, as a key or as a value.
+use std::cell::RefCell;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use hashbrown::hash_map::DefaultHashBuilder;
+use indexmap::{Equivalent, IndexSet};
+
+use crate::runtime::{str_arena, Float, Int, Map, Str};
+
+static INTERN_KEYS: AtomicBool = AtomicBool::new(false);
+
+// The most distinct keys each thread interns.
+const MAX_INTERNED: usize = 1 << 16;
+
+/// `--intern-keys`: intern the keys stored in string-keyed arrays.
+pub fn set_intern_keys(on: bool) {
+    INTERN_KEYS.store(on, Ordering::Relaxed);
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static INTERNED: RefCell<IndexSet<Str<'static>, DefaultHashBuilder>> = Default::default();
+}
+
+/// The key types of AWK arrays.
+pub(crate) trait MapKey: Hash + Eq + Clone {
+    /// The index of the entry for `self` in `map`, if there is one.
+    fn find<V>(&self, map: &Map<Self, V>) -> Option<usize>;
+    /// The key to store in a map for a new entry.
+    fn to_stored(&self) -> Self;
+}
+
+impl MapKey for Int {
+    fn find<V>(&self, map: &Map<Int, V>) -> Option<usize> {
+        map.get_index_of(self)
+    }
+    fn to_stored(&self) -> Int {
+        *self
+    }
+}
+
+impl<'a> MapKey for Str<'a> {
+    fn find<V>(&self, map: &Map<Str<'a>, V>) -> Option<usize> {
+        if !self.is_concat() {
+            return map.get_index_of(self);
+        }
+        with_flat(self, |bs| map.get_index_of(KeyBytes::new(bs)))
+    }
+    fn to_stored(&self) -> Str<'a> {
+        let intern = INTERN_KEYS.load(Ordering::Relaxed);
+        if !intern && !self.is_concat() && !self.in_arena() {
+            return self.clone();
+        }
+        with_flat(self, |bs| {
+            if !intern {
+                return copy(bs);
+            }
+            INTERNED.with(|interned| {
+                let mut interned = interned.borrow_mut();
+                if let Some(i) = interned.get_index_of(KeyBytes::new(bs)) {
+                    return interned[i].clone().upcast();
+                }
+                let s = Str::from(bs).unmoor();
+                if interned.len() < MAX_INTERNED {
+                    interned.insert(s.clone());
+                }
+                s.upcast()
+            })
+        })
+    }
+}
+
+/// The value types of AWK arrays.
+pub(crate) trait MapValue: Clone {
+    /// The value to store in a map.
+    fn to_stored_value(&self) -> Self;
+}
+
+impl MapValue for Int {
+    fn to_stored_value(&self) -> Int {
+        *self
+    }
+}
+
+impl MapValue for Float {
+    fn to_stored_value(&self) -> Float {
+        *self
+    }
+}
+
+impl<'a> MapValue for Str<'a> {
+    fn to_stored_value(&self) -> Str<'a> {
+        // Strings stored in an array usually outlive the record they were built for, and would
+        // keep their block of the string arena from being reused.
+        if !str_arena::enabled() || !(self.is_concat() || self.in_arena()) {
+            return self.clone();
+        }
+        with_flat(self, copy)
+    }
+}
+
+// A copy of `bs` on the heap.
+fn copy<'a>(bs: &[u8]) -> Str<'a> {
+    Str::from(bs).unmoor().upcast()
+}
+
+fn with_flat<R>(s: &Str, f: impl FnOnce(&[u8]) -> R) -> R {
+    SCRATCH.with(|scratch| {
+        let mut scratch = scratch.borrow_mut();
+        scratch.clear();
+        s.write_flat(&mut scratch);
+        f(&scratch)
+    })
+}
+
+/// The bytes of a string key. Hashes the same way as a `Str` with those bytes.
+#[repr(transparent)]
+struct KeyBytes([u8]);
+
+impl KeyBytes {
+    fn new(bs: &[u8]) -> &KeyBytes {
+        unsafe { &*(bs as *const [u8] as *const KeyBytes) }
+    }
+}
+
+impl Hash for KeyBytes {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl<'a> Equivalent<Str<'a>> for KeyBytes {
+    fn equivalent(&self, key: &Str<'a>) -> bool {
+        key.with_bytes(|bs| bs == &self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concatenated_keys() {
+        let long = |s: &str| Str::from(s.to_string());
+        let mut map: Map<Str, Int> = Default::default();
+        map.insert(long("alpha-region-north|x"), 1);
+        map.insert(Str::from("b|y"), 2);
+
+        let key = Str::concat(
+            long("alpha-region-north"),
+            Str::concat("|".into(), "x".into()),
+        );
+        assert!(key.is_concat());
+        assert_eq!(key.find(&map), Some(0));
+        // Looking the key up leaves it as it was.
+        assert!(key.is_concat());
+        assert_eq!(Str::concat("b|".into(), "y".into()).find(&map), Some(1));
+        assert_eq!(
+            Str::concat(long("alpha-region-south"), "|x".into()).find(&map),
+            None
+        );
+
+        let stored = key.to_stored();
+        assert!(!stored.is_concat());
+        assert_eq!(stored, long("alpha-region-north|x"));
+    }
+}
@@ -2,9 +2,14 @@ use std::collections::HashMap;
 use std::sync::Mutex;
 use lazy_static::lazy_static;
 use logos::Logos;
+use rand::{seq::SliceRandom, Rng};
 use semver::{Version};
 use snowflake::SnowflakeIdGenerator;
-use crate::runtime::{Float, Int, IntMap, Str, StrMap};
+use std::cmp::Ordering;
+use std::hash::Hash;
+use std::rc::Rc;
+use crate::common::Result;
+use crate::runtime::{convert, Float, Int, IntMap, MapKey, MapValue, SharedMap, Str, StrMap};
 
 pub fn min(first: &str, second: &str, third: &str) -> String {
     let num1_result = first.parse::<f64>();
@@ -98,78 +103,21 @@ pub fn max(first: &str, second: &str, third: &str) -> String {
     }
 }
 
-pub(crate) fn map_int_int_asort(obj: &IntMap<Int>, target_obj: &IntMap<Int>) {
-    let mut items: Vec<Int> = vec![];
-    for index in obj.to_vec() {
-        items.push(obj.get(&index));
-    }
-    items.sort();
-    if target_obj.len() > 0 {
-        target_obj.clear();
-        let mut index = 1;
-        for item in items {
-            target_obj.insert(index, item);
-            index += 1;
-        }
-    } else {
-        obj.clear();
-        let mut index = 1;
-        for item in items {
-            obj.insert(index, item);
-            index += 1;
-        }
-    }
-}
-
-pub(crate) fn map_int_float_asort(obj: &IntMap<Float>, target_obj: &IntMap<Float>) {
-    let mut items: Vec<Float> = vec![];
-    for index in obj.to_vec() {
-        items.push(obj.get(&index));
-    }
-    if target_obj.len() > 0 {
-        target_obj.clear();
-        let mut index = 1;
-        for item in items {
-            target_obj.insert(index, item);
-            index += 1;
-        }
-    } else {
-        obj.clear();
-        let mut index = 1;
-        for item in items {
-            obj.insert(index, item);
-            index += 1;
-        }
-    }
-}
-
-pub(crate) fn map_int_str_asort(obj: &IntMap<Str>, target_obj: &IntMap<Str>) {
-    let mut items: Vec<String> = vec![];
-    for index in obj.to_vec() {
-        items.push(obj.get(&index).to_string());
-    }
-    if target_obj.len() > 0 {
-        target_obj.clear();
-        let mut index = 1;
-        for item in items {
-            target_obj.insert(index, Str::from(item));
-            index += 1;
-        }
-    } else {
-        obj.clear();
-        let mut index = 1;
-        for item in items {
-            obj.insert(index, Str::from(item));
-            index += 1;
-        }
+/// `asort(arr, dst, order)`: the values of arr, sorted by `order`, replace the contents of dst
+/// (which may be arr itself) indexed from 1. Returns the number of elements.
+pub(crate) fn asort<V: MapValue + Sortable>(obj: &IntMap<V>, target_obj: &IntMap<V>, order: SortOrder) -> Int {
+    let mut items: Vec<V> = sorted_entries(obj).into_iter().map(|(_, v)| v).collect();
+    items.sort_by(|x, y| order.cmp(x, y));
+    target_obj.clear();
+    for (i, item) in items.into_iter().enumerate() {
+        target_obj.insert(i as Int + 1, item);
     }
+    target_obj.len() as Int
 }
 
 pub(crate) fn map_int_int_join(obj: &IntMap<Int>, sep: &str) -> String {
     let mut items: Vec<String> = vec![];
-    let mut keys = obj.to_vec().clone();
-    keys.reverse();
-    for index in keys {
+    for index in obj.to_vec() {
         items.push(obj.get(&index).to_string());
     }
     items.join(sep)
@@ -177,9 +125,7 @@ pub(crate) fn map_int_int_join(obj: &IntMap<Int>, sep: &str) -> String {
 
 pub(crate) fn map_int_float_join(obj: &IntMap<Float>, sep: &str) -> String {
     let mut items: Vec<String> = vec![];
-    let mut keys = obj.to_vec().clone();
-    keys.reverse();
-    for index in keys {
+    for index in obj.to_vec() {
         items.push(obj.get(&index).to_string());
     }
     items.join(sep)
@@ -187,9 +133,7 @@ pub(crate) fn map_int_float_join(obj: &IntMap<Float>, sep: &str) -> String {
 
 pub(crate) fn map_int_str_join(obj: &IntMap<Str>, sep: &str) -> String {
     let mut items: Vec<String> = vec![];
-    let mut keys = obj.to_vec().clone();
-    keys.reverse();
-    for index in keys {
+    for index in obj.to_vec() {
         items.push(obj.get(&index).to_string());
     }
     items.join(sep)
@@ -340,6 +284,305 @@ pub(crate) fn map_int_float_mean(obj: &IntMap<Float>) -> Float {
 const NO: &'static [&'static str] = &["false", "no", "𐄂", "0", "0.0", "0.00", "00.0",
     "0x0", "0x00", "0X0", "0X00", "0o0", "0o00", "0O0", "0O00", "0b0", "0b00", "0B0", "0B00"];
 
+/// The keys of an array in the order `_keys` and `_values` list them: numeric order for integer
+/// keys and byte order for string keys.
+pub(crate) trait SortKey: MapKey + MapValue {
+    fn sort_keys(keys: &mut [Self]);
+}
+
+impl SortKey for Int {
+    fn sort_keys(keys: &mut [Int]) {
+        keys.sort_unstable();
+    }
+}
+
+impl<'a> SortKey for Str<'a> {
+    fn sort_keys(keys: &mut [Str<'a>]) {
+        keys.sort_by(|x, y| x.with_bytes(|x| y.with_bytes(|y| x.cmp(y))));
+    }
+}
+
+/// How `_invert` turns a value into a key: floats become strings, as they do as subscripts.
+pub(crate) trait IntoKey<K> {
+    fn into_key(self) -> K;
+}
+
+impl IntoKey<Int> for Int {
+    fn into_key(self) -> Int {
+        self
+    }
+}
+
+impl<'a> IntoKey<Str<'a>> for Str<'a> {
+    fn into_key(self) -> Str<'a> {
+        self
+    }
+}
+
+impl<'a> IntoKey<Str<'a>> for Float {
+    fn into_key(self) -> Str<'a> {
+        convert::<Float, Str>(self)
+    }
+}
+
+fn sorted_entries<K: SortKey, V: Clone>(obj: &SharedMap<K, V>) -> Vec<(K, V)> {
+    let mut keys = obj.to_vec();
+    K::sort_keys(&mut keys);
+    obj.iter(|it| {
+        let map: HashMap<&K, &V> = it.collect();
+        keys.iter().map(|k| (k.clone(), map[k].clone())).collect()
+    })
+}
+
+/// `_keys(arr)`: the keys of arr, in order, as an array indexed from 1.
+pub(crate) fn map_keys<K: SortKey, V: Clone>(obj: &SharedMap<K, V>) -> IntMap<K> {
+    let res = IntMap::default();
+    for (i, (k, _)) in sorted_entries(obj).into_iter().enumerate() {
+        res.insert(i as Int + 1, k);
+    }
+    res
+}
+
+/// `_values(arr)`: the values of arr, in the order of their keys, as an array indexed from 1.
+pub(crate) fn map_values<K: SortKey, V: MapValue>(obj: &SharedMap<K, V>) -> IntMap<V> {
+    let res = IntMap::default();
+    for (i, (_, v)) in sorted_entries(obj).into_iter().enumerate() {
+        res.insert(i as Int + 1, v);
+    }
+    res
+}
+
+/// `_invert(arr)`: an array mapping each value of arr to its key. Where several keys have the same
+/// value, the last of them in order wins.
+pub(crate) fn map_invert<K: SortKey, V: Clone + IntoKey<I>, I: MapKey>(
+    obj: &SharedMap<K, V>,
+) -> SharedMap<I, K> {
+    let res = SharedMap::default();
+    for (k, v) in sorted_entries(obj) {
+        res.insert(v.into_key(), k);
+    }
+    res
+}
+
+/// `_merge(a, b)`: a new array with the elements of a and b, taking those of b for keys in both.
+pub(crate) fn map_merge<K: MapKey, V: MapValue>(
+    left: &SharedMap<K, V>,
+    right: &SharedMap<K, V>,
+) -> SharedMap<K, V> {
+    let res = SharedMap::default();
+    for m in [left, right] {
+        m.iter(|it| it.for_each(|(k, v)| res.insert(k.clone(), v.clone())));
+    }
+    res
+}
+
+/// How `asort`, `_sorted_keys` and `_sort_by_value` compare elements, from their `order` argument:
+/// `asc` (the default) or `desc`, optionally prefixed by `num_` to compare as numbers, `str_` to
+/// compare as strings, `natural_` to compare digit runs as numbers (see [`natural_cmp`]) or
+/// `version_` to compare as versions (see [`version_cmp`]). Without a prefix, numbers compare as
+/// numbers and strings as strings; `natural` and `version` alone are ascending.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) struct SortOrder {
+    by: SortBy,
+    desc: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum SortBy {
+    Typed,
+    Num,
+    Str,
+    Natural,
+    Version,
+}
+
+impl SortOrder {
+    pub(crate) fn parse(order: &str) -> Result<SortOrder> {
+        let (by, dir) = match order.split_once('_').unwrap_or((order, "")) {
+            ("num", dir) => (SortBy::Num, dir),
+            ("str", dir) => (SortBy::Str, dir),
+            ("natural", dir) => (SortBy::Natural, dir),
+            ("version", dir) => (SortBy::Version, dir),
+            _ => (SortBy::Typed, order),
+        };
+        let desc = match dir {
+            "" | "asc" => false,
+            "desc" => true,
+            _ => {
+                return err!(
+                    "invalid sort order {:?}: expected asc or desc, optionally prefixed by num_, str_, natural_ or version_",
+                    order
+                )
+            }
+        };
+        Ok(SortOrder { by, desc })
+    }
+
+    fn cmp<T: Sortable>(&self, x: &T, y: &T) -> Ordering {
+        let ord = match self.by {
+            SortBy::Typed => x.natural_cmp(y),
+            SortBy::Num => x.as_num().total_cmp(&y.as_num()),
+            SortBy::Str => x.with_str(|x| y.with_str(|y| x.cmp(y))),
+            SortBy::Natural => x.with_str(|x| y.with_str(|y| natural_cmp(x, y))),
+            SortBy::Version => x.with_str(|x| y.with_str(|y| version_cmp(x, y))),
+        };
+        if self.desc {
+            ord.reverse()
+        } else {
+            ord
+        }
+    }
+}
+
+/// Keys and values that `_sorted_keys` and `_sort_by_value` can order.
+pub(crate) trait Sortable {
+    fn natural_cmp(&self, other: &Self) -> Ordering;
+    fn as_num(&self) -> Float;
+    fn with_str<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R;
+}
+
+impl Sortable for Int {
+    fn natural_cmp(&self, other: &Int) -> Ordering {
+        self.cmp(other)
+    }
+    fn as_num(&self) -> Float {
+        *self as Float
+    }
+    fn with_str<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        f(self.to_string().as_bytes())
+    }
+}
+
+impl Sortable for Float {
+    fn natural_cmp(&self, other: &Float) -> Ordering {
+        self.total_cmp(other)
+    }
+    fn as_num(&self) -> Float {
+        *self
+    }
+    fn with_str<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        convert::<Float, Str>(*self).with_bytes(f)
+    }
+}
+
+impl<'a> Sortable for Str<'a> {
+    fn natural_cmp(&self, other: &Str<'a>) -> Ordering {
+        self.with_bytes(|x| other.with_bytes(|y| x.cmp(y)))
+    }
+    fn as_num(&self) -> Float {
+        convert::<&Str, Float>(self)
+    }
+    fn with_str<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
+        self.with_bytes(f)
+    }
+}
+
+/// `_sorted_keys(arr, order)`: the keys of arr, sorted by `order`, as an array indexed from 1.
+pub(crate) fn sorted_keys<K: SortKey + Sortable, V: Clone>(
+    obj: &SharedMap<K, V>,
+    order: SortOrder,
+) -> IntMap<K> {
+    let mut keys = obj.to_vec();
+    keys.sort_by(|x, y| order.cmp(x, y));
+    let res = IntMap::default();
+    for (i, k) in keys.into_iter().enumerate() {
+        res.insert(i as Int + 1, k);
+    }
+    res
+}
+
+/// `_sort_by_value(arr, order)`: the keys of arr, sorted by their values and then by key, as an
+/// array indexed from 1.
+pub(crate) fn sort_by_value<K: SortKey, V: Sortable + Clone>(
+    obj: &SharedMap<K, V>,
+    order: SortOrder,
+) -> IntMap<K> {
+    let mut entries = sorted_entries(obj);
+    entries.sort_by(|(_, x), (_, y)| order.cmp(x, y));
+    let res = IntMap::default();
+    for (i, (k, _)) in entries.into_iter().enumerate() {
+        res.insert(i as Int + 1, k);
+    }
+    res
+}
+
+/// `_copy(dst, src)`: replaces the elements of dst with copies of those of src, so that later
+/// changes to either leave the other alone. Returns the number of elements.
+pub(crate) fn map_copy<K: MapKey, V: MapValue>(
+    dst: &SharedMap<K, V>,
+    src: &SharedMap<K, V>,
+) -> Int {
+    // Copying an array onto itself leaves it as it is.
+    if !Rc::ptr_eq(&dst.0, &src.0) {
+        let elts = src.iter(|it| it.map(|(k, v)| (k.clone(), v.clone())).collect::<Vec<_>>());
+        dst.clear();
+        for (k, v) in elts {
+            dst.insert(k, v);
+        }
+    }
+    dst.len() as Int
+}
+
+/// `_equals(a, b)`: whether a and b have the same keys, with equal values.
+pub(crate) fn map_equals<K: Hash + Eq, V: PartialEq>(
+    left: &SharedMap<K, V>,
+    right: &SharedMap<K, V>,
+) -> bool {
+    Rc::ptr_eq(&left.0, &right.0) || *left.0.borrow() == *right.0.borrow()
+}
+
+/// `_slice(arr, from, to)`: the elements of arr with indexes from `from` to `to`, in order,
+/// renumbered from 1.
+pub(crate) fn map_slice<V: MapValue>(obj: &IntMap<V>, from: Int, to: Int) -> IntMap<V> {
+    let res = IntMap::default();
+    let entries = sorted_entries(obj);
+    let in_range = entries.into_iter().filter(|(k, _)| (from..=to).contains(k));
+    for (i, (_, v)) in in_range.enumerate() {
+        res.insert(i as Int + 1, v);
+    }
+    res
+}
+
+/// `randint(lo, hi)`: a random integer from lo to hi, both included, drawn from the generator
+/// behind `rand()`. The bounds may come in either order.
+pub(crate) fn rand_int(rng: &mut impl Rng, lo: Int, hi: Int) -> Int {
+    rng.gen_range(lo.min(hi)..=lo.max(hi))
+}
+
+/// `choice(arr)`: one of the values of arr, picked at random, or an empty value if arr is empty.
+pub(crate) fn map_choice<K: MapKey, V: MapValue + Default>(rng: &mut impl Rng, obj: &SharedMap<K, V>) -> V {
+    let map = obj.0.borrow();
+    if map.is_empty() {
+        return V::default();
+    }
+    map[rng.gen_range(0..map.len())].clone()
+}
+
+/// `sample(arr, k)`: k values of arr (or all of them, if there are fewer), each picked at most once,
+/// in random order as an array indexed from 1.
+pub(crate) fn map_sample<K: MapKey, V: MapValue>(rng: &mut impl Rng, obj: &SharedMap<K, V>, k: Int) -> IntMap<V> {
+    let mut vals: Vec<V> = obj.iter(|it| it.map(|(_, v)| v.clone()).collect());
+    let k = (k.max(0) as usize).min(vals.len());
+    let (picked, _) = vals.partial_shuffle(rng, k);
+    let res = IntMap::default();
+    for (i, v) in picked.iter().enumerate() {
+        res.insert(i as Int + 1, v.clone());
+    }
+    res
+}
+
+/// `shuffle(arr)`: puts the values of arr in random order, renumbered from 1 like `asort`. Returns
+/// the number of elements.
+pub(crate) fn map_shuffle<V: MapValue>(rng: &mut impl Rng, obj: &IntMap<V>) -> Int {
+    let mut vals: Vec<V> = sorted_entries(obj).into_iter().map(|(_, v)| v).collect();
+    vals.shuffle(rng);
+    obj.clear();
+    for (i, v) in vals.into_iter().enumerate() {
+        obj.insert(i as Int + 1, v);
+    }
+    obj.len() as Int
+}
+
 pub(crate) fn mkbool(text: &str) -> i64 {
     let text = text.trim().to_lowercase();
     return if text.is_empty() || NO.contains(&text.as_str()) {
@@ -383,6 +626,51 @@ pub(crate) fn snowflake(machine_id: u16) -> Int {
     generator.real_time_generate() as Int
 }
 
+/// Read `text` as an unsigned 64-bit integer, wrapping around past 2^64 - 1: decimal digits, or
+/// digits after a `0x`, `0o` or `0b` prefix. A leading `-` wraps negative values around, so
+/// `-1` is `18446744073709551615`. Other text is read like AWK reads numbers.
+pub(crate) fn parse_u64(text: &str) -> u64 {
+    let text = text.trim();
+    let (neg, digits) = match text.as_bytes().first() {
+        Some(b'-') => (true, &text[1..]),
+        Some(b'+') => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x" | "0X") => (16, &digits[2..]),
+        Some("0o" | "0O") => (8, &digits[2..]),
+        Some("0b" | "0B") => (2, &digits[2..]),
+        _ => (10, digits),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return crate::runtime::convert::<_, Int>(&Str::from(text)) as u64;
+    }
+    let res = digits.chars().fold(0u64, |acc, c| {
+        acc.wrapping_mul(radix as u64)
+            .wrapping_add(c.to_digit(radix).unwrap() as u64)
+    });
+    if neg {
+        res.wrapping_neg()
+    } else {
+        res
+    }
+}
+
+/// `x + y` modulo 2^64.
+pub(crate) fn u64_add(x: &str, y: &str) -> String {
+    parse_u64(x).wrapping_add(parse_u64(y)).to_string()
+}
+
+/// `x * y` modulo 2^64.
+pub(crate) fn u64_mul(x: &str, y: &str) -> String {
+    parse_u64(x).wrapping_mul(parse_u64(y)).to_string()
+}
+
+/// The bits of `x` read as an unsigned integer.
+pub(crate) fn u64_str(x: Int) -> String {
+    (x as u64).to_string()
+}
+
 pub(crate) fn ulid() -> String {
     ulid::Ulid::new().to_string()
 }
@@ -442,9 +730,7 @@ pub(crate) fn is_str_num(text: &str) -> bool {
 pub(crate) fn uniq<'a>(obj: &IntMap<Str<'a>>, _param: &str) -> IntMap<Str<'a>> {
     //todo uniq implement logic with param
     let mut items: Vec<String> = vec![];
-    let mut keys = obj.to_vec().clone();
-    keys.reverse();
-    for index in keys {
+    for index in obj.to_vec() {
         items.push(obj.get(&index).to_string());
     }
     items.dedup();
@@ -566,6 +852,63 @@ pub(crate) fn parse_array<'a>(text: &str) -> IntMap<Str<'a>> {
     result
 }
 
+/// Orders texts so that runs of digits compare by their value, as in `file2` < `file10`. Texts that
+/// differ only in leading zeros fall back to byte order, so that the order stays total.
+pub(crate) fn natural_cmp(x: &[u8], y: &[u8]) -> Ordering {
+    fn digits(s: &[u8], i: usize) -> usize {
+        s[i..].iter().take_while(|b| b.is_ascii_digit()).count()
+    }
+    let (mut i, mut j) = (0, 0);
+    while i < x.len() && j < y.len() {
+        if x[i].is_ascii_digit() && y[j].is_ascii_digit() {
+            let (m, n) = (digits(x, i), digits(y, j));
+            let a = &x[i..i + m];
+            let b = &y[j..j + n];
+            let a = &a[a.iter().take_while(|&&d| d == b'0').count()..];
+            let b = &b[b.iter().take_while(|&&d| d == b'0').count()..];
+            let ord = a.len().cmp(&b.len()).then_with(|| a.cmp(b));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            i += m;
+            j += n;
+        } else {
+            if x[i] != y[j] {
+                return x[i].cmp(&y[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+    }
+    (x.len() - i).cmp(&(y.len() - j)).then_with(|| x.cmp(y))
+}
+
+/// Reads `text` as a semantic version, also accepting a leading `v` and a missing minor or patch
+/// number, like `v1.2`.
+fn parse_version(text: &str) -> Option<Version> {
+    let text = text.trim();
+    let text = text.strip_prefix(['v', 'V']).unwrap_or(text);
+    let core = text.find(['-', '+']).map_or(text, |i| &text[..i]);
+    let padding = match core.matches('.').count() {
+        0 => ".0.0",
+        1 => ".0",
+        _ => "",
+    };
+    Version::parse(&format!("{}{}{}", core, padding, &text[core.len()..])).ok()
+}
+
+/// Orders texts by semantic version precedence, so `1.10.0` > `1.9.0` and `1.0.0-rc.1` < `1.0.0`.
+/// Texts that are not versions sort after those that are, in natural order.
+pub(crate) fn version_cmp(x: &[u8], y: &[u8]) -> Ordering {
+    let parse = |s: &[u8]| std::str::from_utf8(s).ok().and_then(parse_version);
+    match (parse(x), parse(y)) {
+        (Some(a), Some(b)) => a.cmp(&b).then_with(|| x.cmp(y)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => natural_cmp(x, y),
+    }
+}
+
 pub(crate) fn semver<'a>(text: &str) -> StrMap<'a, Str<'a>> {
     let version_obj: StrMap<Str> = StrMap::default();
     if let Ok(version) = Version::parse(text) {
@@ -699,6 +1042,100 @@ mod tests {
         println!("{}", uuid("v7"));
     }
 
+    #[test]
+    fn test_array_fns() {
+        let arr: StrMap<Float> = StrMap::default();
+        arr.insert(Str::from("b"), 2.5);
+        arr.insert(Str::from("a"), 1.0);
+        arr.insert(Str::from("c"), 1.0);
+        let keys = map_keys(&arr);
+        assert_eq!((keys.get(&1), keys.get(&3)), (Str::from("a"), Str::from("c")));
+        assert_eq!(map_values(&arr).get(&2), 2.5);
+        let inv: StrMap<Str> = map_invert(&arr);
+        assert_eq!(inv.len(), 2);
+        assert_eq!(inv.get(&Str::from("1")), Str::from("c"));
+        let other: StrMap<Float> = StrMap::default();
+        other.insert(Str::from("a"), 7.0);
+        other.insert(Str::from("d"), 8.0);
+        let merged = map_merge(&arr, &other);
+        assert_eq!((merged.len(), merged.get(&Str::from("a"))), (4, 7.0));
+        let copy = StrMap::default();
+        assert_eq!(map_copy(&copy, &arr), 3);
+        assert!(map_equals(&copy, &arr));
+        copy.insert(Str::from("a"), 2.0);
+        assert!(!map_equals(&copy, &arr));
+        assert_eq!(arr.get(&Str::from("a")), 1.0);
+        assert_eq!(map_copy(&arr, &arr), 3);
+        let by_key = sorted_keys(&arr, SortOrder::parse("desc").unwrap());
+        assert_eq!(by_key.get(&1), Str::from("c"));
+        let by_val = sort_by_value(&arr, SortOrder::parse("asc").unwrap());
+        let order: Vec<_> = (1..=3).map(|i| by_val.get(&i)).collect();
+        assert_eq!(order, vec![Str::from("a"), Str::from("c"), Str::from("b")]);
+        let nums: IntMap<Str> = IntMap::default();
+        for (i, s) in ["10", "9", "x"].iter().enumerate() {
+            nums.insert(i as Int, Str::from(*s));
+        }
+        let lexical = sort_by_value(&nums, SortOrder::parse("asc").unwrap());
+        assert_eq!((lexical.get(&1), lexical.get(&2)), (0, 1));
+        let numeric = sort_by_value(&nums, SortOrder::parse("num_desc").unwrap());
+        assert_eq!((numeric.get(&1), numeric.get(&3)), (0, 2));
+        assert!(SortOrder::parse("up").is_err());
+        let list: IntMap<Int> = IntMap::default();
+        for i in 1..=5 {
+            list.insert(i, i * 10);
+        }
+        let slice = map_slice(&list, 2, 4);
+        assert_eq!((slice.len(), slice.get(&1), slice.get(&3)), (3, 20, 40));
+        assert_eq!(map_slice(&list, 4, Int::MAX).len(), 2);
+    }
+
+    #[test]
+    fn test_rand_helpers() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!((0..100).map(|_| rand_int(&mut rng, 3, -2)).all(|x| (-2..=3).contains(&x)));
+        let list: IntMap<Int> = IntMap::default();
+        for i in 1..=10 {
+            list.insert(i, i * 10);
+        }
+        assert_eq!(map_shuffle(&mut rng, &list), 10);
+        let mut vals: Vec<Int> = (1..=10).map(|i| list.get(&i)).collect();
+        vals.sort();
+        assert_eq!(vals, (1..=10).map(|i| i * 10).collect::<Vec<_>>());
+        let picked = map_sample(&mut rng, &list, 4);
+        let mut vals: Vec<Int> = (1..=4).map(|i| picked.get(&i)).collect();
+        vals.sort();
+        vals.dedup();
+        assert_eq!(vals.len(), 4);
+        assert_eq!(map_sample(&mut rng, &list, -1).len(), 0);
+        let c = map_choice(&mut rng, &list);
+        assert!(c % 10 == 0 && (10..=100).contains(&c));
+        let draws = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..5).map(|_| map_choice(&mut rng, &list)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(1), draws(1));
+        assert_eq!(map_choice(&mut rng, &IntMap::<Str>::default()), Str::default());
+    }
+
+    #[test]
+    fn test_natural_version_order() {
+        let vals: IntMap<Str> = IntMap::default();
+        for (i, s) in ["v1.10", "1.2.0", "1.2.0-rc.1", "beta", "1.9.3", "alpha2", "alpha10"].iter().enumerate() {
+            vals.insert(i as Int + 1, Str::from(*s));
+        }
+        let sorted = |order: &str| {
+            let dst = IntMap::default();
+            assert_eq!(asort(&vals, &dst, SortOrder::parse(order).unwrap()), 7);
+            (1..=7).map(|i| dst.get(&i).to_string()).collect::<Vec<_>>().join(" ")
+        };
+        assert_eq!(sorted("version"), "1.2.0-rc.1 1.2.0 1.9.3 v1.10 alpha2 alpha10 beta");
+        assert_eq!(sorted("natural_desc"), "v1.10 beta alpha10 alpha2 1.9.3 1.2.0-rc.1 1.2.0");
+        assert_eq!(sorted("asc"), "1.2.0 1.2.0-rc.1 1.9.3 alpha10 alpha2 beta v1.10");
+        assert_eq!(natural_cmp(b"img007", b"img7"), Ordering::Less);
+        assert!(SortOrder::parse("natural_up").is_err());
+    }
+
     #[test]
     fn test_seq() {
         let result = seq(1.0, 1.0, 10.0);
@@ -740,6 +1177,17 @@ mod tests {
         println!("{}", snowflake(machine_id as u16));
     }
 
+    #[test]
+    fn test_u64() {
+        assert_eq!(parse_u64("18446744073709551615"), u64::MAX);
+        assert_eq!(parse_u64("0xffffffffffffffff"), u64::MAX);
+        assert_eq!(parse_u64("-1"), u64::MAX);
+        assert_eq!(parse_u64(" 12 apples"), 12);
+        assert_eq!(u64_add("18446744073709551615", "2"), "1");
+        assert_eq!(u64_mul("14695981039346656037", "1099511628211"), "12638153115695167455");
+        assert_eq!(u64_str(-2), "18446744073709551614");
+    }
+
     #[test]
     fn test_semver() {
         let map = semver("1.2.3-beta1");
@@ -0,0 +1,244 @@
+//! Memory statistics for `--stats-mem` and the `mem_*` entries of `PROCINFO`.
+//!
+//! Peak and current RSS come from the operating system and are always available. The remaining
+//! counters (heap usage from the global allocator, string buffers, and maps) are only kept once
+//! `enable` has been called, so that normal runs pay no more than a relaxed load per allocation.
+//! They are process-wide: in parallel mode they add up the usage of every worker.
+use std::alloc::{GlobalAlloc, Layout};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+struct Gauge {
+    cur: AtomicIsize,
+    peak: AtomicIsize,
+}
+
+impl Gauge {
+    const fn new() -> Gauge {
+        Gauge {
+            cur: AtomicIsize::new(0),
+            peak: AtomicIsize::new(0),
+        }
+    }
+    fn add(&self, n: isize) {
+        let cur = self.cur.fetch_add(n, Ordering::Relaxed) + n;
+        if n > 0 {
+            self.peak.fetch_max(cur, Ordering::Relaxed);
+        }
+    }
+    // Values allocated before counting was enabled can make `cur` dip below zero.
+    fn cur(&self) -> usize {
+        self.cur.load(Ordering::Relaxed).max(0) as usize
+    }
+    fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed).max(0) as usize
+    }
+}
+
+static HEAP: Gauge = Gauge::new();
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static STRINGS: Gauge = Gauge::new();
+static MAPS: Gauge = Gauge::new();
+static MAP_MAX_LEN: AtomicUsize = AtomicUsize::new(0);
+
+#[inline(always)]
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Start counting allocations, and print a summary to standard error when the process exits.
+pub(crate) fn enable() {
+    extern "C" fn at_exit() {
+        let _ = std::io::stderr().write_all(report().as_bytes());
+    }
+    if !ENABLED.swap(true, Ordering::Relaxed) {
+        unsafe { libc::atexit(at_exit) };
+    }
+}
+
+/// A global allocator that keeps track of the bytes allocated through `A` once counting is
+/// enabled.
+pub struct Counting<A>(pub A);
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Counting<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if enabled() {
+            HEAP.add(layout.size() as isize);
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.0.alloc(layout)
+    }
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if enabled() {
+            HEAP.add(layout.size() as isize);
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+        }
+        self.0.alloc_zeroed(layout)
+    }
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if enabled() {
+            HEAP.add(-(layout.size() as isize));
+        }
+        self.0.dealloc(ptr, layout)
+    }
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if enabled() {
+            HEAP.add(new_size as isize - layout.size() as isize);
+        }
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Record a string buffer growing by `delta` bytes (or shrinking, if it is negative).
+#[inline]
+pub(crate) fn string_bytes(delta: isize) {
+    if enabled() {
+        STRINGS.add(delta);
+    }
+}
+
+#[inline]
+pub(crate) fn map_created() {
+    if enabled() {
+        MAPS.add(1);
+    }
+}
+
+#[inline]
+pub(crate) fn map_dropped() {
+    if enabled() {
+        MAPS.add(-1);
+    }
+}
+
+#[inline]
+pub(crate) fn map_len(len: usize) {
+    if enabled() {
+        MAP_MAX_LEN.fetch_max(len, Ordering::Relaxed);
+    }
+}
+
+/// The largest resident set size of the process so far, in bytes.
+#[cfg(unix)]
+fn peak_rss() -> Option<usize> {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    // ru_maxrss is in bytes on macOS, and in kilobytes elsewhere.
+    let scale = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    Some(usage.ru_maxrss as usize * scale)
+}
+
+#[cfg(not(unix))]
+fn peak_rss() -> Option<usize> {
+    None
+}
+
+/// The current resident set size of the process, in bytes.
+#[cfg(target_os = "linux")]
+fn rss() -> Option<usize> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * page_size.max(0) as usize)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss() -> Option<usize> {
+    None
+}
+
+/// The current statistics, keyed by their names in `PROCINFO`.
+pub(crate) fn entries() -> Vec<(&'static str, usize)> {
+    let mut res = Vec::new();
+    res.extend(peak_rss().map(|n| ("mem_maxrss", n)));
+    res.extend(rss().map(|n| ("mem_rss", n)));
+    if enabled() {
+        res.extend([
+            ("mem_heap", HEAP.cur()),
+            ("mem_heap_peak", HEAP.peak()),
+            ("mem_allocs", ALLOCS.load(Ordering::Relaxed)),
+            ("mem_strings", STRINGS.cur()),
+            ("mem_strings_peak", STRINGS.peak()),
+            ("mem_maps", MAPS.cur()),
+            ("mem_maps_peak", MAPS.peak()),
+            ("mem_map_max_len", MAP_MAX_LEN.load(Ordering::Relaxed)),
+        ]);
+    }
+    res
+}
+
+fn human(n: usize) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut v = n as f64 / 1024.0;
+    let mut unit = 0;
+    while v >= 1024.0 && unit + 1 < UNITS.len() {
+        v /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", v, UNITS[unit])
+}
+
+/// The summary printed by `--stats-mem`.
+pub(crate) fn report() -> String {
+    let mut res = String::from("memory statistics:\n");
+    let mut line = |name: &str, value: String| {
+        res.push_str(&format!("  {:<22}{}\n", name, value));
+    };
+    if let Some(n) = peak_rss() {
+        line("peak RSS", human(n));
+    }
+    line(
+        "heap",
+        format!(
+            "{} peak, {} at exit, {} allocations",
+            human(HEAP.peak()),
+            human(HEAP.cur()),
+            ALLOCS.load(Ordering::Relaxed)
+        ),
+    );
+    line(
+        "string buffers",
+        format!("{} peak, {} at exit", human(STRINGS.peak()), human(STRINGS.cur())),
+    );
+    line(
+        "maps",
+        format!(
+            "{} peak live, {} at exit, largest had {} entries",
+            MAPS.peak(),
+            MAPS.cur(),
+            MAP_MAX_LEN.load(Ordering::Relaxed)
+        ),
+    );
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(human(10), "10 B");
+        assert_eq!(human(1536), "1.5 KiB");
+        assert_eq!(human(3 << 30), "3.0 GiB");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rss_is_always_reported() {
+        let entries = entries();
+        let maxrss = entries.iter().find(|(k, _)| *k == "mem_maxrss");
+        assert!(maxrss.is_some_and(|(_, v)| *v > 0), "{:?}", entries);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::common::{FileSpec, Result};
 use grep_cli::CommandReader;
 use hashbrown::HashMap;
-use regex::bytes::Regex;
+use regex_engine::Regex;
 use std::cell::{Cell, RefCell};
 use std::fs::File;
 use std::hash::Hash;
@@ -10,6 +10,9 @@ use std::iter::FromIterator;
 use std::mem;
 use std::rc::Rc;
 use std::str;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 mod command;
 pub mod float_parse;
@@ -19,37 +22,78 @@ pub mod str_impl;
 pub mod string_search;
 pub mod utf8;
 pub mod writers;
+pub mod manifest;
+pub(crate) mod rotate;
+pub(crate) mod encrypted;
+pub(crate) mod atomic_file;
+pub mod map_key;
+#[cfg(feature = "str_arena")]
+pub mod str_arena;
 pub mod date_time;
 pub mod encoding;
 pub mod crypto;
 pub mod str_escape;
 pub mod math_util;
+pub mod mem_stats;
+pub(crate) mod rounding;
+pub(crate) mod decimal;
+pub(crate) mod locale;
+pub(crate) mod safe_math;
+pub(crate) mod errors;
+pub mod regex_engine;
+pub mod ext;
 
 pub mod json;
+pub(crate) mod toml;
+pub(crate) mod config_util;
+pub(crate) mod xml;
+pub(crate) mod html;
+pub(crate) mod binfmt;
+pub(crate) mod ip;
+pub(crate) mod geoip;
+#[cfg(feature = "net")]
 pub mod network;
 pub mod kv;
+#[cfg(feature = "net")]
 pub mod s3;
 pub mod os_util;
 pub mod csv;
+#[cfg(feature = "sqlite")]
 pub mod sqlite;
+#[cfg(feature = "net")]
 pub mod mysql;
+#[cfg(feature = "bignum")]
+pub(crate) mod bignum;
+pub(crate) mod features;
+#[cfg(not(feature = "bignum"))]
+pub(crate) use features::bignum;
+#[cfg(not(feature = "sqlite"))]
+pub(crate) use features::sqlite;
+#[cfg(not(feature = "net"))]
+pub(crate) use features::{mysql, network, s3};
+#[cfg(not(feature = "str_arena"))]
+pub use features::str_arena;
 pub mod logging;
 pub mod string_util;
 pub mod faker;
+pub mod sketch;
 
 use crate::pushdown::FieldSet;
 use splitter::regex::RegexSplitter;
+use splitter::timeout::TimedReader;
 
 // TODO: remove the pub use for Variables here.
 pub(crate) use crate::builtins::Variables;
-pub use command::run_command;
-pub(crate) use float_parse::{hextoi, strtod, strtoi};
+pub(crate) use command::{exec_output_argv, spawn_argv};
+pub use command::{exec_output, run_command, spawn};
+pub(crate) use float_parse::{hextoi, radixtoi, strtod, strtoi};
 pub(crate) use printf::FormatArg;
 pub use splitter::{
     batch::{escape_csv, escape_tsv},
     ChainedReader, Line, LineReader,
 };
 pub use str_impl::{Str, UniqueStr};
+pub(crate) use map_key::{MapKey, MapValue};
 
 #[derive(Default)]
 pub struct RegexCache(Registry<Regex>);
@@ -88,13 +132,16 @@ impl RegexCache {
         pat: &Str<'a>,
         reg: &mut FileRead<LR>,
         is_file: bool,
+        timeout: Option<Duration>,
     ) -> Result<Str<'a>> {
         Ok(if is_file {
             reg.with_file(file, |reader| {
+                reader.set_timeout(timeout);
                 self.with_regex(pat, |re| reader.read_line_regex(re))
             })?
         } else {
             reg.with_cmd(file, |reader| {
+                reader.set_timeout(timeout);
                 self.with_regex(pat, |re| reader.read_line_regex(re))
             })?
         }
@@ -105,21 +152,43 @@ impl RegexCache {
     // This only gets used if getline is invoked explicitly without an input file argument.
     pub(crate) fn get_line_stdin<'a, LR: LineReader>(
         &mut self,
-        pat: &Str<'a>,
+        vars: &Variables<'a>,
         reg: &mut FileRead<LR>,
     ) -> Result<(/* file changed */ bool, Str<'a>)> {
-        let (changed, mut line) = reg.stdin.read_line(pat, self)?;
+        errors::check()?;
+        let pat = &vars.rs;
+        if !reg.take_record() {
+            return Ok((false, Str::default()));
+        }
+        let (mut changed, mut line) = reg.stdin.read_line(pat, self)?;
+        while reg.stdin.read_state() == 0 /* EOF */ && reg.next_input(vars) {
+            (_, line) = reg.stdin.read_line(pat, self)?;
+            changed = true;
+        }
+        reg.settle_record();
         // NB both of these `pat`s are "wrong" but we are fine because they are only used
         // when the column is nonzero, or someone has overwritten a nonzero column.
         Ok((changed, line.get_col(0, pat, pat, self)?.clone().upcast()))
     }
     pub(crate) fn get_line_stdin_reuse<LR: LineReader>(
         &mut self,
-        pat: &Str,
+        vars: &Variables,
         reg: &mut FileRead<LR>,
         old_line: &mut LR::Line,
     ) -> Result</*file changed */ bool> {
-        reg.stdin.read_line_reuse(pat, self, old_line)
+        errors::check()?;
+        let pat = &vars.rs;
+        if !reg.take_record() {
+            *old_line = Default::default();
+            return Ok(false);
+        }
+        let mut changed = reg.stdin.read_line_reuse(pat, self, old_line)?;
+        while reg.stdin.read_state() == 0 /* EOF */ && reg.next_input(vars) {
+            reg.stdin.read_line_reuse(pat, self, old_line)?;
+            changed = true;
+        }
+        reg.settle_record();
+        Ok(changed)
     }
     fn split_internal<'a>(
         &mut self,
@@ -129,7 +198,9 @@ impl RegexCache {
         mut push: impl FnMut(Str<'a>),
     ) -> Result<()> {
         if pat == &Str::from(" ") {
-            self.with_regex(&Str::from(r#"[ \t]+"#), |re| {
+            // As in POSIX awk, the default FS splits on newlines too. They only show up inside a
+            // record when RS is something else, as with -0.
+            self.with_regex(&Str::from(r#"[ \t\n]+"#), |re| {
                 s.split(
                     re,
                     |s, is_empty| {
@@ -196,13 +267,81 @@ impl RegexCache {
         })
     }
 
+    /// `split(s, arr, pat, seps)`: `split`, which also passes each separator to `sep`, numbered
+    /// like the field before it. With the default `" "`, the blanks before the first field are
+    /// separator 0 and those after the last are separator `n`, as in gawk. Returns `n`.
+    pub(crate) fn split_seps<'a>(
+        &mut self,
+        pat: &Str,
+        s: &Str<'a>,
+        mut field: impl FnMut(Int, Str<'a>),
+        mut sep: impl FnMut(Int, Str<'a>),
+    ) -> Result<Int> {
+        let blanks = pat == &Str::from(" ");
+        let pat = if blanks { Str::from(r#"[ \t\n]+"#) } else { pat.clone() };
+        self.with_regex(&pat, |re| {
+            s.with_bytes(|bs| {
+                if bs.is_empty() {
+                    return 0;
+                }
+                let mut n = 0;
+                let mut prev = 0;
+                for m in re.find_iter(bs) {
+                    if blanks && m.start() == 0 {
+                        sep(0, s.slice(0, m.end()));
+                    } else {
+                        n += 1;
+                        field(n, s.slice(prev, m.start()));
+                        sep(n, s.slice(m.start(), m.end()));
+                    }
+                    prev = m.end();
+                }
+                if !(blanks && prev == bs.len()) {
+                    n += 1;
+                    field(n, s.slice(prev, bs.len()));
+                }
+                n
+            })
+        })
+    }
+
+    /// `patsplit(s, arr, pat, seps)`: the fields are the non-empty matches of `pat`, and separator
+    /// `i` is the text between fields `i` and `i + 1`. Text before the first field is separator 0,
+    /// and text after the last is separator `n`. Returns `n`.
+    pub(crate) fn pat_split<'a>(
+        &mut self,
+        pat: &Str,
+        s: &Str<'a>,
+        mut field: impl FnMut(Int, Str<'a>),
+        mut sep: impl FnMut(Int, Str<'a>),
+    ) -> Result<Int> {
+        self.with_regex(pat, |re| {
+            s.with_bytes(|bs| {
+                let mut n = 0;
+                let mut prev = 0;
+                for m in re.find_iter(bs).filter(|m| m.start() < m.end()) {
+                    if n > 0 || m.start() > 0 {
+                        sep(n, s.slice(prev, m.start()));
+                    }
+                    n += 1;
+                    field(n, s.slice(m.start(), m.end()));
+                    prev = m.end();
+                }
+                if prev < bs.len() {
+                    sep(n, s.slice(prev, bs.len()));
+                }
+                n
+            })
+        })
+    }
+
     pub(crate) fn regex_const_match_loc(vars: &mut Variables, re: &Regex, s: &Str) -> Result<Int> {
         use crate::builtins::Variable;
         let (start, len) = s.with_bytes(|bs| match re.find(bs) {
             Some(m) => {
-                let start = m.start() as Int;
-                let end = m.end() as Int;
-                (start + 1, end - start)
+                let start = str_impl::position_units(&bs[..m.start()]) as Int;
+                let len = str_impl::position_units(m.as_bytes()) as Int;
+                (start + 1, len)
             }
             None => (0, -1),
         });
@@ -219,6 +358,49 @@ impl RegexCache {
         self.with_regex_fallible(pat, |re| Self::regex_const_match_loc(vars, re, s))
     }
 
+    /// `match(s, pat, arr)`: `match`, which also stores the matched text in `arr[0]` and that of
+    /// each group `n` that took part in the match in `arr[n]`, with its position in
+    /// `arr[n, "start"]` and `arr[n, "length"]`, as in gawk. `arr` is emptied first.
+    pub(crate) fn regex_match_arr<'a>(
+        &mut self,
+        vars: &mut Variables,
+        pat: &Str<'a>,
+        s: &Str<'a>,
+        arr: &StrMap<'a, Str<'a>>,
+    ) -> Result<Int> {
+        use crate::builtins::Variable;
+        arr.clear();
+        let (start, len) = self.with_regex(pat, |re| {
+            s.with_bytes(|bs| {
+                let caps = match re.captures(bs) {
+                    Some(caps) => caps,
+                    None => return (0, -1),
+                };
+                for i in 0..caps.len() {
+                    let m = match caps.get(i) {
+                        Some(m) => m,
+                        None => continue,
+                    };
+                    let start = str_impl::position_units(&bs[..m.start()]) as Int + 1;
+                    let len = str_impl::position_units(m.as_bytes()) as Int;
+                    let key = convert::<Int, Str>(i as Int);
+                    // NB: this assumes the default SUBSEP.
+                    let start_key = Str::concat(key.clone(), Str::from("\x1cstart"));
+                    let len_key = Str::concat(key.clone(), Str::from("\x1clength"));
+                    arr.insert(key, s.slice(m.start(), m.end()));
+                    arr.insert(start_key, convert::<Int, Str>(start));
+                    arr.insert(len_key, convert::<Int, Str>(len));
+                }
+                let m = caps.get(0).unwrap();
+                let start = str_impl::position_units(&bs[..m.start()]) as Int;
+                (start + 1, str_impl::position_units(m.as_bytes()) as Int)
+            })
+        })?;
+        vars.store_int(Variable::RSTART, start)?;
+        vars.store_int(Variable::RLENGTH, len)?;
+        Ok(start)
+    }
+
     pub(crate) fn regex_const_match(pat: &Regex, s: &Str) -> bool {
         s.with_bytes(|bs| pat.is_match(bs))
     }
@@ -244,6 +426,13 @@ impl FileWrite {
     pub(crate) fn close(&mut self, path: &Str) -> Result<()> {
         self.0.close(path)
     }
+    /// fflush(name): 0 on success, -1 if nothing named `name` is open or the flush failed.
+    pub(crate) fn fflush(&mut self, name: &Str) -> Int {
+        match self.0.flush(name) {
+            Ok(true) => 0,
+            Ok(false) | Err(_) => -1,
+        }
+    }
     pub(crate) fn new(ff: impl writers::FileFactory) -> FileWrite {
         FileWrite(writers::Registry::from_factory(ff))
     }
@@ -252,6 +441,18 @@ impl FileWrite {
         self.0.destroy_and_flush_all_files()
     }
 
+    /// Called when the program fails: output to atomic files is thrown away.
+    pub(crate) fn discard_atomic_writes(&mut self) {
+        self.0.discard_atomic_writes()
+    }
+
+    pub(crate) fn start_input_file(&mut self, filename: &Str) -> Result<()> {
+        filename.with_bytes(|bs| match str::from_utf8(bs) {
+            Ok(s) => self.0.start_input_file(s),
+            Err(e) => err!("invalid UTF8 in filename: {}", e),
+        })
+    }
+
     pub(crate) fn printf(
         &mut self,
         path: Option<(&Str, FileSpec)>,
@@ -290,8 +491,26 @@ pub const CHUNK_SIZE: usize = 8 << 10;
 
 #[derive(Default)]
 pub(crate) struct Inputs {
-    files: Registry<RegexSplitter<File>>,
-    commands: Registry<RegexSplitter<CommandReader>>,
+    files: Registry<RegexSplitter<TimedReader<Box<dyn io::Read + Send>>>>,
+    commands: Registry<RegexSplitter<TimedReader<CommandReader>>>,
+}
+
+// The number of records that may still be read from the main input, when it is limited. The
+// count is shared by all worker threads.
+static RECORD_LIMIT: OnceLock<AtomicU64> = OnceLock::new();
+
+/// Stop reading the main input after `n` records (see `--head`).
+pub(crate) fn limit_records(n: u64) {
+    let _ = RECORD_LIMIT.set(AtomicU64::new(n));
+}
+
+// The ARGV indexes holding arguments for the script itself (those after `--`), which are never
+// read as input files.
+static SCRIPT_ARGS: OnceLock<(Int, Int)> = OnceLock::new();
+
+/// Mark ARGV[start] through ARGV[end-1] as arguments for the script rather than input files.
+pub(crate) fn script_args(start: Int, end: Int) {
+    let _ = SCRIPT_ARGS.set((start, end));
 }
 
 pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
@@ -300,6 +519,12 @@ pub(crate) struct FileRead<LR = RegexSplitter<Box<dyn io::Read + Send>>> {
     named_columns: Option<Vec<Str<'static>>>,
     used_fields: FieldSet,
     backup_used_fields: FieldSet,
+    // Set once RECORD_LIMIT has run out; the main input then reads as if it were at EOF.
+    limit_reached: bool,
+    // For a main input that takes its files from ARGV, the index of the next ARGV entry to look
+    // at, and whether any file has been opened yet.
+    argv_ix: Option<Int>,
+    opened_input: bool,
 }
 
 impl<LR: LineReader> FileRead<LR> {
@@ -318,6 +543,9 @@ impl<LR: LineReader> FileRead<LR> {
                             used_fields: fields.clone(),
                             backup_used_fields: fields,
                             stdin,
+                            limit_reached: false,
+                            argv_ix: None,
+                            opened_input: false,
                         })
                     } else {
                         None
@@ -346,11 +574,15 @@ impl<LR: LineReader> FileRead<LR> {
         } else {
             backup_used_fields.clone()
         };
+        let argv_ix = if stdin.reads_argv() { Some(1) } else { None };
         let mut res = FileRead {
             inputs: Default::default(),
             stdin,
             used_fields,
             backup_used_fields,
+            limit_reached: false,
+            argv_ix,
+            opened_input: false,
             named_columns: named_columns
                 .map(|cs| cs.into_iter().map(|s| Str::from(s).unmoor()).collect()),
         };
@@ -394,10 +626,46 @@ impl<LR: LineReader> FileRead<LR> {
         self.stdin.filename()
     }
 
+    /// Let parallel workers that have not started yet know that they never will.
+    pub(crate) fn stop_waiting(&self) {
+        self.stdin.stop_waiting()
+    }
+
     pub(crate) fn read_err_stdin(&mut self) -> Int {
+        if self.limit_reached {
+            return 0;
+        }
         self.stdin.read_state()
     }
 
+    // Claim a record from RECORD_LIMIT before reading from the main input. Returns false once
+    // the limit is used up, at which point we stop reading without draining the input.
+    fn take_record(&mut self) -> bool {
+        let limit = match RECORD_LIMIT.get() {
+            Some(limit) => limit,
+            None => return true,
+        };
+        if self.limit_reached
+            || limit
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+                .is_err()
+        {
+            self.limit_reached = true;
+            return false;
+        }
+        true
+    }
+
+    // Return the record claimed by `take_record` if the read did not produce one, so that other
+    // workers can still use it.
+    fn settle_record(&self) {
+        if let Some(limit) = RECORD_LIMIT.get() {
+            if self.stdin.read_state() != 1 {
+                limit.fetch_add(1, Ordering::AcqRel);
+            }
+        }
+    }
+
     pub(crate) fn read_err(&mut self, path: &Str) -> Result<Int> {
         self.with_file(path, |reader| Ok(reader.read_state()))
     }
@@ -410,17 +678,54 @@ impl<LR: LineReader> FileRead<LR> {
         Ok(())
     }
 
+    // Queue the next file named by ARGV[1] through ARGV[ARGC-1] on the main input, returning false
+    // if there are none left. As in POSIX awk, empty entries and `var=value` assignments are
+    // skipped, ARGV is consulted afresh each time a file runs out, and standard input is read if
+    // no file is ever named. Arguments for the script (see `script_args`) are passed over. The
+    // assignments themselves are made by the program (see `ast::assign_operands`).
+    fn next_input(&mut self, vars: &Variables) -> bool {
+        let ix = match &mut self.argv_ix {
+            Some(ix) => ix,
+            None => return false,
+        };
+        let (script_start, script_end) = SCRIPT_ARGS.get().copied().unwrap_or_default();
+        while *ix < vars.argc {
+            if (script_start..script_end).contains(ix) {
+                *ix = script_end;
+                continue;
+            }
+            let arg = if vars.argv.contains(ix) {
+                vars.argv.get(ix).to_string()
+            } else {
+                String::new()
+            };
+            *ix += 1;
+            if arg.is_empty() || split_assignment(&arg).is_some() {
+                continue;
+            }
+            self.stdin.push_input(&arg);
+            self.opened_input = true;
+            return true;
+        }
+        if self.opened_input {
+            return false;
+        }
+        self.stdin.push_input("-");
+        self.opened_input = true;
+        true
+    }
+
     fn with_cmd<R>(
         &mut self,
         cmd: &Str,
-        f: impl FnMut(&mut RegexSplitter<CommandReader>) -> Result<R>,
+        f: impl FnMut(&mut RegexSplitter<TimedReader<CommandReader>>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
         self.inputs.commands.get_fallible(
             cmd,
             |s| match command::command_for_read(s.as_bytes()) {
                 Ok(r) => Ok(RegexSplitter::new(
-                    r,
+                    TimedReader::new(r),
                     CHUNK_SIZE,
                     cmd.clone().unmoor(),
                     check_utf8,
@@ -434,25 +739,70 @@ impl<LR: LineReader> FileRead<LR> {
     fn with_file<R>(
         &mut self,
         path: &Str,
-        f: impl FnMut(&mut RegexSplitter<File>) -> Result<R>,
+        f: impl FnMut(&mut RegexSplitter<TimedReader<Box<dyn io::Read + Send>>>) -> Result<R>,
     ) -> Result<R> {
         let check_utf8 = self.stdin.check_utf8();
         self.inputs.files.get_fallible(
             path,
-            |s| match File::open(s) {
-                Ok(f) => Ok(RegexSplitter::new(
-                    f,
+            |s| {
+                // Opening a FIFO waits for a writer, and READ_TIMEOUT has to cover that too.
+                let reader = if is_fifo(s) {
+                    let s = s.to_string();
+                    TimedReader::deferred(move || open_input(&s))
+                } else {
+                    match open_input(s) {
+                        Ok(f) => TimedReader::new(f),
+                        Err(e) => return err!("failed to open file '{}': {}", s, e),
+                    }
+                };
+                Ok(RegexSplitter::new(
+                    reader,
                     CHUNK_SIZE,
                     path.clone().unmoor(),
                     check_utf8,
-                )),
-                Err(e) => err!("failed to open file '{}': {}", s, e),
+                ))
             },
             f,
         )
     }
 }
 
+/// Split a command-line operand of the form `var=value`, which awk treats as an assignment rather
+/// than as an input file.
+pub(crate) fn split_assignment(arg: &str) -> Option<(&str, &str)> {
+    let (var, value) = arg.split_once('=')?;
+    if var.is_empty() || !crate::lexer::is_ident(var) {
+        return None;
+    }
+    Some((var, value))
+}
+
+// `getline < (STR_PREFIX s)` reads the lines of the string `s` rather than a file.
+const STR_PREFIX: &str = "@str:";
+
+// Open a file read with `getline < path`, which may also name a process started with spawn(), or
+// a string.
+fn open_input(path: &str) -> io::Result<Box<dyn io::Read + Send>> {
+    if path.starts_with(command::SPAWN_PREFIX) {
+        return Ok(Box::new(command::spawned_stdout(path)?));
+    }
+    if let Some(s) = path.strip_prefix(STR_PREFIX) {
+        return Ok(Box::new(io::Cursor::new(s.as_bytes().to_vec())));
+    }
+    Ok(encoding::decode_input(Box::new(File::open(path)?)))
+}
+
+#[cfg(unix)]
+fn is_fifo(path: &str) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    std::fs::metadata(path).is_ok_and(|m| m.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_path: &str) -> bool {
+    false
+}
+
 pub(crate) struct Registry<T> {
     // TODO(ezr): use the raw bucket interface so we can avoid calls to `unmoor` here.
     // TODO(ezr): we could potentially increase speed here if we did pointer equality (and
@@ -597,15 +947,42 @@ where
     _Carrier::convert(s)
 }
 
+/// The map behind AWK arrays. It iterates in insertion order, so `for (k in a)` and `_join` visit
+/// the elements of `split(s, a)` in the order they appear in `s`. Deletes only keep that order
+/// with `--ordered-arrays`; see [`SharedMap::delete`].
+pub(crate) type Map<K, V> = indexmap::IndexMap<K, V, hashbrown::hash_map::DefaultHashBuilder>;
+
+thread_local! {
+    static ORDERED_ARRAYS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Keep arrays in insertion order across deletes (`--ordered-arrays`). Like `--strict`, this is
+/// set per thread, and `Core::shuttle` hands it on to parallel workers.
+pub(crate) fn set_ordered_arrays(on: bool) {
+    ORDERED_ARRAYS.with(|o| o.set(on))
+}
+
+pub(crate) fn ordered_arrays() -> bool {
+    ORDERED_ARRAYS.with(|o| o.get())
+}
+
 // AWK arrays are inherently shared and mutable, so we have to do this, even if it is a code smell.
 // NB These are repr(transparent) because we pass them around as void* when compiling with LLVM.
 #[repr(transparent)]
 #[derive(Debug)]
-pub(crate) struct SharedMap<K, V>(pub(crate) Rc<RefCell<HashMap<K, V>>>);
+pub(crate) struct SharedMap<K, V>(pub(crate) Rc<RefCell<Map<K, V>>>);
 
 impl<K, V> Default for SharedMap<K, V> {
     fn default() -> SharedMap<K, V> {
-        SharedMap(Rc::new(RefCell::new(Default::default())))
+        SharedMap::new(Default::default())
+    }
+}
+
+impl<K, V> Drop for SharedMap<K, V> {
+    fn drop(&mut self) {
+        if mem_stats::enabled() && Rc::strong_count(&self.0) == 1 {
+            mem_stats::map_dropped();
+        }
     }
 }
 
@@ -619,15 +996,9 @@ impl<K: Hash + Eq, V> SharedMap<K, V> {
     pub(crate) fn len(&self) -> usize {
         self.0.borrow().len()
     }
-    pub(crate) fn insert(&self, k: K, v: V) {
-        self.borrow_mut().insert(k, v);
-    }
-    pub(crate) fn delete(&self, k: &K) {
-        self.borrow_mut().remove(k);
-    }
     pub(crate) fn iter<F, R>(&self, f: F) -> R
     where
-        F: FnOnce(hashbrown::hash_map::Iter<K, V>) -> R,
+        F: FnOnce(indexmap::map::Iter<K, V>) -> R,
     {
         f(self.0.borrow().iter())
     }
@@ -636,7 +1007,37 @@ impl<K: Hash + Eq, V> SharedMap<K, V> {
     }
 }
 
-impl<K: Hash + Eq + Clone, V: Inc + Default + Clone> SharedMap<K, V> {
+impl<K: MapKey, V: MapValue> SharedMap<K, V> {
+    pub(crate) fn insert(&self, k: K, v: V) {
+        let mut slf = self.borrow_mut();
+        let v = v.to_stored_value();
+        match k.find(&slf) {
+            Some(i) => slf[i] = v,
+            None => {
+                slf.insert(k.to_stored(), v);
+            }
+        }
+        mem_stats::map_len(slf.len());
+    }
+}
+
+impl<K: MapKey, V> SharedMap<K, V> {
+    pub(crate) fn delete(&self, k: &K) {
+        let mut slf = self.borrow_mut();
+        if let Some(i) = k.find(&slf) {
+            if ordered_arrays() {
+                slf.shift_remove_index(i);
+            } else {
+                // Removing from the middle of an IndexMap in place is linear, which would make
+                // deleting every element quadratic. Unless asked to keep the order, the last
+                // element takes the place of the deleted one instead.
+                slf.swap_remove_index(i);
+            }
+        }
+    }
+}
+
+impl<K: MapKey, V: Inc + Default + Clone> SharedMap<K, V> {
     pub(crate) fn inc_int(&self, k: &K, by: Int) -> V {
         self.with_inserted(k, |kref| {
             kref.inc_int(by);
@@ -653,43 +1054,45 @@ impl<K: Hash + Eq + Clone, V: Inc + Default + Clone> SharedMap<K, V> {
 
     fn with_inserted<R>(&self, k: &K, f: impl FnOnce(&mut V) -> R) -> R {
         let mut slf = self.0.borrow_mut();
-        if let Some(k) = slf.get_mut(k) {
-            f(k)
-        } else {
-            f(slf.entry(k.clone()).or_insert(Default::default()))
-        }
+        let i = match k.find(&slf) {
+            Some(i) => i,
+            None => {
+                mem_stats::map_len(slf.len() + 1);
+                slf.insert_full(k.to_stored(), Default::default()).0
+            }
+        };
+        f(&mut slf[i])
     }
 }
 
 // When sending SharedMaps across threads we have to clone them and clone their contents, as Rc is
 // not thread-safe (and we don't want to pay the cost of Arc clones during normal execution).
 pub(crate) struct Shuttle<T>(T);
-impl<'a> From<Shuttle<HashMap<Int, UniqueStr<'a>>>> for IntMap<Str<'a>> {
-    fn from(sh: Shuttle<HashMap<Int, UniqueStr<'a>>>) -> Self {
-        SharedMap(Rc::new(RefCell::new(
-            sh.0.into_iter().map(|(x, y)| (x, y.into_str())).collect(),
-        )))
+impl<'a> From<Shuttle<Map<Int, UniqueStr<'a>>>> for IntMap<Str<'a>> {
+    fn from(sh: Shuttle<Map<Int, UniqueStr<'a>>>) -> Self {
+        SharedMap::new(sh.0.into_iter().map(|(x, y)| (x, y.into_str())).collect())
     }
 }
 
-impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, Int>>> for StrMap<'a, Int> {
-    fn from(sh: Shuttle<HashMap<UniqueStr<'a>, Int>>) -> Self {
-        SharedMap(Rc::new(RefCell::new(
-            sh.0.into_iter().map(|(x, y)| (x.into_str(), y)).collect(),
-        )))
+impl<'a> From<Shuttle<Map<UniqueStr<'a>, Int>>> for StrMap<'a, Int> {
+    fn from(sh: Shuttle<Map<UniqueStr<'a>, Int>>) -> Self {
+        SharedMap::new(sh.0.into_iter().map(|(x, y)| (x.into_str(), y)).collect())
     }
 }
 
-impl<'a> From<Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>> for StrMap<'a, Str<'a>> {
-    fn from(sh: Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>>) -> Self {
-        SharedMap(Rc::new(RefCell::new(
-            sh.0.into_iter().map(|(x, y)| (x.into_str(), y.into_str())).collect(),
-        )))
+impl<'a> From<Shuttle<Map<UniqueStr<'a>, UniqueStr<'a>>>> for StrMap<'a, Str<'a>> {
+    fn from(sh: Shuttle<Map<UniqueStr<'a>, UniqueStr<'a>>>) -> Self {
+        SharedMap::new(sh.0.into_iter().map(|(x, y)| (x.into_str(), y.into_str())).collect())
     }
 }
 
 impl<K, V> SharedMap<K, V> {
-    fn borrow_mut(&self) -> impl std::ops::DerefMut<Target = HashMap<K, V>> + '_ {
+    fn new(m: Map<K, V>) -> SharedMap<K, V> {
+        mem_stats::map_created();
+        SharedMap(Rc::new(RefCell::new(m)))
+    }
+
+    fn borrow_mut(&self) -> impl std::ops::DerefMut<Target = Map<K, V>> + '_ {
         // Unlike the full std::collections APIs, we are careful not to hand out any references
         // internal to a SharedMap from a public function. That means that functions which mutate
         // the map are "Cell"-like, in that they swap out values or drop them in, but never hold
@@ -708,32 +1111,32 @@ impl<K, V> SharedMap<K, V> {
     }
 }
 
-impl<K: Hash + Eq, V: Clone> SharedMap<K, V> {
+impl<K: MapKey, V: Clone> SharedMap<K, V> {
     pub(crate) fn contains(&self, k: &K) -> bool {
         #[cfg(debug_assertions)]
         {
-            self.0.borrow().get(k).is_some()
+            k.find(&self.0.borrow()).is_some()
         }
         #[cfg(not(debug_assertions))]
         {
-            unsafe { &mut *self.0.as_ptr() }.get(k).is_some()
+            k.find(unsafe { &*self.0.as_ptr() }).is_some()
         }
     }
 }
 
-impl<K: Hash + Eq + Clone, V: Clone + Default> SharedMap<K, V> {
+impl<K: MapKey, V: Clone + Default> SharedMap<K, V> {
     pub(crate) fn get(&self, k: &K) -> V {
-        self.borrow_mut()
-            .raw_entry_mut()
-            .from_key(k)
-            .or_insert_with(|| (k.clone(), V::default()))
-            .1
-            .clone()
+        let mut slf = self.borrow_mut();
+        if let Some(i) = k.find(&slf) {
+            return slf[i].clone();
+        }
+        slf.insert(k.to_stored(), Default::default());
+        Default::default()
     }
 }
 
 impl<'a> IntMap<Str<'a>> {
-    pub(crate) fn shuttle(&self) -> Shuttle<HashMap<Int, UniqueStr<'a>>> {
+    pub(crate) fn shuttle(&self) -> Shuttle<Map<Int, UniqueStr<'a>>> {
         Shuttle(
             self.0
                 .borrow()
@@ -745,7 +1148,7 @@ impl<'a> IntMap<Str<'a>> {
 }
 
 impl<'a> StrMap<'a, Int> {
-    pub(crate) fn shuttle(&self) -> Shuttle<HashMap<UniqueStr<'a>, Int>> {
+    pub(crate) fn shuttle(&self) -> Shuttle<Map<UniqueStr<'a>, Int>> {
         Shuttle(
             self.0
                 .borrow()
@@ -757,7 +1160,7 @@ impl<'a> StrMap<'a, Int> {
 }
 
 impl<'a> StrMap<'a, Str<'a>> {
-    pub(crate) fn shuttle(&self) -> Shuttle<HashMap<UniqueStr<'a>, UniqueStr<'a>>> {
+    pub(crate) fn shuttle(&self) -> Shuttle<Map<UniqueStr<'a>, UniqueStr<'a>>> {
         Shuttle(
             self.0
                 .borrow()
@@ -779,7 +1182,13 @@ impl<K: Hash + Eq + Clone, V> SharedMap<K, V> {
 
 impl<K: Hash + Eq, V> From<HashMap<K, V>> for SharedMap<K, V> {
     fn from(m: HashMap<K, V>) -> SharedMap<K, V> {
-        SharedMap(Rc::new(RefCell::new(m)))
+        SharedMap::new(m.into_iter().collect())
+    }
+}
+
+impl<K: Hash + Eq, V> From<Map<K, V>> for SharedMap<K, V> {
+    fn from(m: Map<K, V>) -> SharedMap<K, V> {
+        SharedMap::new(m)
     }
 }
 
@@ -788,9 +1197,7 @@ impl<K: Hash + Eq, V> FromIterator<(K, V)> for SharedMap<K, V> {
     where
         T: IntoIterator<Item = (K, V)>,
     {
-        SharedMap(Rc::new(RefCell::new(
-            iter.into_iter().collect::<HashMap<K, V>>(),
-        )))
+        SharedMap::new(iter.into_iter().collect::<Map<K, V>>())
     }
 }
 
@@ -1,6 +1,8 @@
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
 use crate::runtime;
-use crate::runtime::{SharedMap, Str};
+use crate::runtime::{Int, SharedMap, Str};
 
 pub fn os() -> String {
     std::env::consts::OS.to_string()
@@ -63,6 +65,64 @@ pub(crate) fn path<'b>(text: &str) -> runtime::StrMap<'b, Str<'b>> {
     return SharedMap::from(map);
 }
 
+/// The paths matching a shell wildcard pattern, in sorted order, in an array indexed from 1.
+/// Paths that cannot be read, such as the contents of unreadable directories, are left out.
+pub(crate) fn glob<'b>(pattern: &str) -> Result<runtime::IntMap<Str<'b>>, String> {
+    let paths = ::glob::glob(pattern).map_err(|e| e.to_string())?;
+    let res = runtime::IntMap::default();
+    for (i, path) in paths.flatten().enumerate() {
+        res.insert(i as Int + 1, Str::from(path.to_string_lossy().into_owned()));
+    }
+    Ok(res)
+}
+
+/// The size in bytes, modification time (in seconds since the epoch), permission bits (in octal)
+/// and type (`file`, `dir`, `symlink` or `other`) of a path. Symbolic links are not followed.
+pub(crate) fn stat<'b>(path: &str) -> io::Result<runtime::StrMap<'b, Str<'b>>> {
+    let meta = std::fs::symlink_metadata(path)?;
+    let file_type = meta.file_type();
+    let kind = if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "dir"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    };
+    let mtime = match meta.modified()?.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        meta.permissions().mode() & 0o7777
+    };
+    #[cfg(not(unix))]
+    let mode = if meta.permissions().readonly() { 0o444 } else { 0o644 };
+    let mut map = hashbrown::HashMap::new();
+    map.insert(Str::from("size"), Str::from(meta.len().to_string()));
+    map.insert(Str::from("mtime"), Str::from(mtime.to_string()));
+    map.insert(Str::from("mode"), Str::from(format!("{:o}", mode)));
+    map.insert(Str::from("type"), Str::from(kind));
+    Ok(SharedMap::from(map))
+}
+
+pub(crate) fn exists(path: &str) -> bool {
+    Path::new(path).exists()
+}
+
+/// Create a directory along with any missing parents, like `mkdir -p`.
+pub(crate) fn mkdirp(path: &str) -> io::Result<()> {
+    std::fs::create_dir_all(path)
+}
+
+/// Remove a file. Directories are left alone.
+pub(crate) fn rmfile(path: &str) -> io::Result<()> {
+    std::fs::remove_file(path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,4 +149,31 @@ mod tests {
         let map = path(".");
         println!("{}", map.get(&Str::from("full_path")).to_string());
     }
+
+    #[test]
+    fn test_file_management() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let nested = format!("{}/a/b", root);
+        mkdirp(&nested).unwrap();
+        mkdirp(&nested).unwrap();
+        std::fs::write(format!("{}/x.txt", nested), "hello").unwrap();
+        std::fs::write(format!("{}/y.txt", nested), "").unwrap();
+
+        let found = glob(&format!("{}/*/*/*.txt", root)).unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found.get(&1), Str::from(format!("{}/x.txt", nested)));
+        assert!(glob("[").is_err());
+
+        let info = stat(&format!("{}/x.txt", nested)).unwrap();
+        assert_eq!(info.get(&Str::from("size")), Str::from("5"));
+        assert_eq!(info.get(&Str::from("type")), Str::from("file"));
+        assert_eq!(stat(&nested).unwrap().get(&Str::from("type")), Str::from("dir"));
+
+        assert!(exists(&format!("{}/y.txt", nested)));
+        rmfile(&format!("{}/y.txt", nested)).unwrap();
+        assert!(!exists(&format!("{}/y.txt", nested)));
+        assert!(rmfile(&nested).is_err());
+        assert!(stat(&format!("{}/y.txt", nested)).is_err());
+    }
 }
\ No newline at end of file
@@ -10,8 +10,12 @@
 //! appends a newline) may find some bytes replaced inadvertently. We could solve this by adding a
 //! new print function that does not append a newline.
 use crate::common::Result;
-use crate::runtime::{convert, strtoi, Float, Int, Str};
+use crate::runtime::bignum;
+use crate::runtime::locale::{self, Numeric};
+use crate::runtime::math_util;
+use crate::runtime::{convert, rounding, Float, Int, Str};
 
+use std::cell::RefCell;
 use std::convert::TryFrom;
 use std::fmt;
 use std::io::Write;
@@ -102,6 +106,14 @@ impl<'a> FormatArg<'a> {
             Null => 0,
         }
     }
+    // The argument of `%u`, `%o`, `%x` and `%b`. Negative numbers wrap around, and strings may hold
+    // values up to 2^64 - 1.
+    fn to_unsigned(&self) -> u64 {
+        match self {
+            FormatArg::S(s) => math_util::parse_u64(s.as_str()),
+            _ => self.to_int() as u64,
+        }
+    }
     fn with_bytes<R>(&self, f: impl FnOnce(&[u8]) -> R) -> R {
         use FormatArg::*;
         let s: Str<'a> = match self {
@@ -118,6 +130,8 @@ impl<'a> FormatArg<'a> {
 struct FormatSpec {
     // leading '-' ? -- left justification.
     minus: bool,
+    // '\'' -- group the digits of the integer part with the locale's thousands separator.
+    group: bool,
     // number to the left of '.', if any
     leading_zeros: bool,
     // padding
@@ -132,19 +146,86 @@ impl Default for FormatSpec {
     fn default() -> FormatSpec {
         FormatSpec {
             minus: false,
+            group: false,
             leading_zeros: false,
             lnum: 0,
-            rnum: usize::max_value(),
+            rnum: usize::MAX,
             spec: b'z', /* invalid */
         }
     }
 }
 
 fn is_spec(c: u8) -> bool {
-    matches!(c, b'f' | b'c' | b'd' | b'e' | b'g' | b'o' | b's' | b'x')
+    matches!(
+        c,
+        b'f' | b'c' | b'd' | b'i' | b'u' | b'e' | b'g' | b'o' | b's' | b'x' | b'X' | b'b'
+    )
+}
+
+// Whether `%c` should treat `text` as a character code rather than print its first character.
+fn looks_numeric(text: &str) -> bool {
+    let text = text.trim();
+    let digits = text.trim_start_matches(['+', '-']).trim_start_matches('.');
+    digits.starts_with(|c: char| c.is_ascii_digit()) && text.parse::<f64>().is_ok()
+}
+
+// Apply the --round-mode setting to a float about to be printed with an explicit precision.
+fn with_precision(fspec: &FormatSpec, f: Float, scientific: bool) -> Float {
+    if fspec.rnum == usize::MAX {
+        return f;
+    }
+    rounding::for_printf(f, fspec.rnum, scientific).unwrap_or(f)
 }
 
 fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> Result<()> {
+    let float = matches!(fspec.spec, b'f' | b'e' | b'g');
+    let group = fspec.group && matches!(fspec.spec, b'd' | b'i' | b'u' | b'f' | b'g');
+    // With -M, numbers are strings that may not fit in an Int.
+    let big = match arg {
+        FormatArg::S(s) if matches!(fspec.spec, b'd' | b'i') && bignum::enabled() => {
+            bignum::int_text(s.as_str())
+        }
+        _ => None,
+    };
+    if big.is_none() && !group && !(float && locale::use_lc_numeric()) {
+        return format_spec(w, fspec, arg);
+    }
+    // Format the number without padding, then add the locale's separators and pad the result.
+    let num = match big {
+        Some(digits) => digits,
+        None => {
+            let mut inner = FormatSpec {
+                minus: false,
+                group: false,
+                leading_zeros: false,
+                lnum: 0,
+                ..*fspec
+            };
+            let mut buf = StackWriter::default();
+            format_spec(&mut buf, &mut inner, arg)?;
+            String::from_utf8_lossy(&buf.0).into_owned()
+        }
+    };
+    let num = if group || (float && locale::use_lc_numeric()) {
+        Numeric::current().localize(&num, group)
+    } else {
+        num
+    };
+    let pad = fspec.lnum.saturating_sub(num.chars().count());
+    let res = if fspec.minus {
+        write!(w, "{}{:pad$}", num, "", pad = pad)
+    } else if fspec.leading_zeros {
+        // Zeros go after the sign.
+        let digits = num.trim_start_matches(['-', '+']);
+        let sign = &num[..num.len() - digits.len()];
+        write!(w, "{}{}{}", sign, "0".repeat(pad), digits)
+    } else {
+        write!(w, "{:pad$}{}", "", num, pad = pad)
+    };
+    wrap_result(res)
+}
+
+fn format_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> Result<()> {
     macro_rules! match_for_spec {
         ($s:expr, $arg:expr) => {
             match (
@@ -197,19 +278,19 @@ fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> R
                 let mut buf = ryu::Buffer::new();
                 write!(w, "{}", buf.format(arg.to_float()))
             } else {
-                match_for_spec!("", arg.to_float())
+                match_for_spec!("", with_precision(fspec, arg.to_float(), false))
             }
         }
-        b'e' => match_for_spec!("e", arg.to_float()),
+        b'e' => match_for_spec!("e", with_precision(fspec, arg.to_float(), true)),
         b'g' => {
             let mut buf = StackWriter::default();
             // %g means "pick the shorter of standard and scientific notation". We do the obvious
             // thing of computing both and writing out the smaller one.
             fspec.spec = b'f';
-            process_spec(&mut buf, fspec, arg)?;
+            format_spec(&mut buf, fspec, arg)?;
             let l1 = buf.len();
             fspec.spec = b'e';
-            process_spec(&mut buf, fspec, arg)?;
+            format_spec(&mut buf, fspec, arg)?;
             let l2 = buf.len() - l1;
             let bytes = if l1 < l2 {
                 &buf.0[0..l1]
@@ -218,17 +299,35 @@ fn process_spec(mut w: impl Write, fspec: &mut FormatSpec, arg: &FormatArg) -> R
             };
             return write_bytes(&mut w, bytes);
         }
-        b'd' => match_for_spec!("", arg.to_int()),
-        b'o' => match_for_spec!("o", arg.to_int()),
-        b'x' => match_for_spec!("x", arg.to_int()),
+        b'd' | b'i' => match_for_spec!("", arg.to_int()),
+        b'u' => match_for_spec!("", arg.to_unsigned()),
+        b'o' => match_for_spec!("o", arg.to_unsigned()),
+        b'x' => match_for_spec!("x", arg.to_unsigned()),
+        b'X' => match_for_spec!("X", arg.to_unsigned()),
+        b'b' => match_for_spec!("b", arg.to_unsigned()),
         b'c' => {
-            // First, see if we have something ascii/UTF8 here
-            match char::try_from(arg.to_int() as u32) {
-                Ok(ch) => match_for_spec!("", ch),
-                // TODO: Unclear what we should do here, write out the raw bytes? write out the
-                // character code? Awk may just write the raw bytes out, but it's hard to say
-                // (different behavior across implementations)
-                _ => match_for_spec!("", "?"),
+            // Precision does not apply to a single character.
+            fspec.rnum = usize::MAX;
+            // Numbers, and strings that look like numbers (such as fields read from the input), are
+            // printed as the character with that code point. Other strings print their first
+            // character.
+            let first = match arg {
+                FormatArg::S(s) => s.with_bytes(|bs| {
+                    let text = String::from_utf8_lossy(bs);
+                    (!looks_numeric(&text)).then(|| text.chars().next())
+                }),
+                _ => None,
+            };
+            match first {
+                Some(Some(ch)) => match_for_spec!("", ch),
+                Some(None) => match_for_spec!("", ""),
+                None => match char::try_from(arg.to_int() as u32) {
+                    Ok(ch) => match_for_spec!("", ch),
+                    // TODO: Unclear what we should do here, write out the raw bytes? write out the
+                    // character code? Awk may just write the raw bytes out, but it's hard to say
+                    // (different behavior across implementations)
+                    _ => match_for_spec!("", "?"),
+                },
             }
         }
         b's' => arg.with_bytes(|bs| match_for_spec!("", DisplayBytes(bs))),
@@ -248,154 +347,218 @@ fn write_bytes(mut w: impl Write, bs: &[u8]) -> Result<()> {
     wrap_result(w.write(bs))
 }
 
-pub(crate) fn printf(mut w: impl Write, spec: &[u8], mut args: &[FormatArg]) -> Result<()> {
-    #[derive(Copy, Clone)]
-    enum State {
-        // Byte index of start of string
-        Raw(usize),
-        // Byte index of percent sign
-        Format(usize),
+// A conversion in a format string: `%[n$][-0'][width][.precision]spec`.
+#[derive(Copy, Clone, Debug)]
+struct Directive {
+    // The argument named with `n$`, counting from 1, if any.
+    arg: Option<usize>,
+    fs: FormatSpec,
+}
+
+// Parse the decimal number at the start of `bs`, returning it and the number of digits read.
+fn parse_num(bs: &[u8]) -> Option<(usize, usize)> {
+    let digits = bs.iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut num = 0usize;
+    for b in &bs[..digits] {
+        num = num.checked_mul(10)?.checked_add((b - b'0') as usize)?;
     }
+    Some((num, digits))
+}
 
-    use State::*;
-    let mut iter = spec.iter().cloned().enumerate();
-    macro_rules! next_state {
-        ($e:expr) => {
-            match $e {
-                Some((_, b'%')) => Format(0),
-                Some(_) => Raw(0),
-                None => return Ok(()),
+// Parse the directive following the `%` at `spec[start - 1]`, returning it along with the index
+// just past its format specifier. Returns `None` if the directive is malformed; such directives
+// are printed as they are, as in other AWKs.
+fn parse_directive(spec: &[u8], start: usize) -> Option<(Directive, usize)> {
+    let mut ix = start;
+    let mut arg = None;
+    if let Some((n, len @ 1..)) = parse_num(&spec[ix..]) {
+        if spec.get(ix + len) == Some(&b'$') {
+            if n == 0 {
+                return None;
             }
-        };
+            arg = Some(n);
+            ix += len + 1;
+        }
+    }
+    let mut fs = FormatSpec::default();
+    loop {
+        match spec.get(ix)? {
+            b'-' => fs.minus = true,
+            b'0' => fs.leading_zeros = true,
+            b'\'' => fs.group = true,
+            _ => break,
+        }
+        ix += 1;
+    }
+    let (lnum, len) = parse_num(&spec[ix..])?;
+    fs.lnum = lnum;
+    ix += len;
+    if spec.get(ix) == Some(&b'.') {
+        let (rnum, len) = parse_num(&spec[ix + 1..])?;
+        fs.rnum = rnum;
+        ix += len + 1;
     }
-    let mut state = next_state!(iter.next());
+    match spec.get(ix) {
+        Some(&ch) if is_spec(ch) => {
+            fs.spec = ch;
+            Some((Directive { arg, fs }, ix + 1))
+        }
+        _ => None,
+    }
+}
+
+/// The arguments that a format string uses, for checking calls to printf with a constant format.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub(crate) struct FormatArgs {
+    /// The number of directives that take the next argument in turn, like `%s`.
+    pub(crate) sequential: usize,
+    /// The highest argument number named with `%n$`, or 0 if there are none.
+    pub(crate) positional: usize,
+}
+
+impl FormatArgs {
+    /// The number of arguments that the format expects.
+    pub(crate) fn expected(&self) -> usize {
+        std::cmp::max(self.sequential, self.positional)
+    }
+}
+
+pub(crate) fn format_args(spec: &[u8]) -> FormatArgs {
+    let mut res = FormatArgs::default();
+    let mut ix = 0;
+    while let Some(pct) = memchr::memchr(b'%', &spec[ix..]) {
+        ix += pct + 1;
+        if spec.get(ix) == Some(&b'%') {
+            ix += 1;
+            continue;
+        }
+        if let Some((d, end)) = parse_directive(spec, ix) {
+            match d.arg {
+                Some(n) => res.positional = std::cmp::max(res.positional, n),
+                None => res.sequential += 1,
+            }
+            ix = end;
+        }
+    }
+    res
+}
+
+pub(crate) fn printf(mut w: impl Write, spec: &[u8], args: &[FormatArg]) -> Result<()> {
     let default = FormatArg::S(Default::default());
-    let mut next_arg = || {
-        if args.is_empty() {
-            &default
-        } else {
-            let res = &args[0];
-            args = &args[1..];
-            res
+    // Directives with an explicit argument number (`%2$s`) do not move on to the next argument.
+    let mut next = 0;
+    let mut ix = 0;
+    while let Some(pct) = memchr::memchr(b'%', &spec[ix..]) {
+        write_bytes(&mut w, &spec[ix..ix + pct])?;
+        ix += pct + 1;
+        if spec.get(ix) == Some(&b'%') {
+            write_bytes(&mut w, b"%")?;
+            ix += 1;
+            continue;
         }
-    };
-    let mut buf = SmallVec::new();
-    'outer: loop {
-        match state {
-            Raw(start) => {
-                for (ix, ch) in iter.by_ref() {
-                    if ch == b'%' {
-                        write_bytes(&mut w, &spec[start..ix])?;
-                        state = Format(ix);
-                        continue 'outer;
-                    }
-                }
-                write_bytes(&mut w, &spec[start..])?;
-                break 'outer;
+        let (mut d, end) = match parse_directive(spec, ix) {
+            Some(x) => x,
+            None => {
+                write_bytes(&mut w, b"%")?;
+                continue;
             }
-            Format(start) => {
-                let mut fs = FormatSpec::default();
-                #[derive(Copy, Clone)]
-                enum Stage {
-                    Begin,
-                    Lnum,
-                    Rnum,
-                }
-                use Stage::*;
-                let mut stage = Begin;
-                let mut next = iter.next();
-                // AWK is, as usual, rather permissive when it comes to invalid format specifiers:
-                // If something is formatted incorrectly, it is simply treated like a normal
-                // string. We implement by checking for error conditions and `break`ing out of the
-                // inner loop, which will change state to Raw(start).
-                while let Some((ix, ch)) = next {
-                    if !ch.is_ascii() {
-                        // We cast characters to bytes in what follows.
-                        break;
-                    }
-                    match (ch, stage) {
-                        (b'%', Begin) => {
-                            fs.spec = b'%';
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (ch, _) if is_spec(ch) => {
-                            fs.spec = ch;
-                            process_spec(&mut w, &mut fs, next_arg())?;
-                            state = Raw(ix + 1);
-                            continue 'outer;
-                        }
-                        (b'-', Begin) => {
-                            stage = Lnum;
-                            fs.minus = true;
-                        }
-                        (b'-', _) | (b'%', _) => break,
-                        (ch, Lnum) | (ch, Begin) => {
-                            if fs.lnum != 0 {
-                                break;
-                            }
-                            buf.clear();
-                            if ch == b'0' {
-                                fs.leading_zeros = true;
-                            } else if ch == b'.' {
-                                stage = Rnum;
-                                continue;
-                            } else {
-                                buf.push(ch);
-                            };
-                            next = None;
-                            for (ix, ch) in iter.by_ref() {
-                                if !ch.is_ascii_digit() {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.lnum = num as usize;
-                            stage = Rnum;
-                            continue;
-                        }
-                        (ch, Rnum) => {
-                            if fs.rnum != usize::max_value() {
-                                break;
-                            }
-                            if ch != b'.' {
-                                break;
-                            }
-                            buf.clear();
-                            next = None;
-                            for (ix, ch) in iter.by_ref() {
-                                if !ch.is_ascii_digit() {
-                                    next = Some((ix, ch));
-                                    break;
-                                }
-                                buf.push(ch);
-                            }
-                            let num = strtoi(&buf[..]);
-                            if num < 0 {
-                                break;
-                            }
-                            fs.rnum = num as usize;
-                            continue;
-                        }
-                    };
-                    next = iter.next();
-                }
-                // We do not have a complete format specifier, and we have exhausted the string.
-                // Just print it out.
-                state = Raw(start);
-                continue 'outer;
+        };
+        let arg = match d.arg {
+            Some(n) => args.get(n - 1),
+            None => {
+                next += 1;
+                args.get(next - 1)
             }
-        }
+        };
+        process_spec(&mut w, &mut d.fs, arg.unwrap_or(&default))?;
+        ix = end;
+    }
+    write_bytes(&mut w, &spec[ix..])
+}
+
+/// The formats for converting numbers with a fractional part to strings.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum NumberFormat {
+    /// `CONVFMT`, for conversions in expressions.
+    Convert,
+    /// `OFMT`, for numbers passed to `print`.
+    Output,
+}
+
+thread_local! {
+    // Number-to-string conversions happen all over the runtime, far from the variables. Empty
+    // until a script assigns the variable.
+    static CONVFMT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static OFMT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Set by assignments to `CONVFMT` and `OFMT`. An empty format goes back to printing the shortest
+/// string that reads back as the same number.
+pub(crate) fn set_number_format(which: NumberFormat, spec: &[u8]) -> Result<()> {
+    if !spec.is_empty() && !formats_one_number(spec) {
+        let name = match which {
+            NumberFormat::Convert => "CONVFMT",
+            NumberFormat::Output => "OFMT",
+        };
+        return err!(
+            "invalid {} setting {:?}: expected a format for a single number, like \"%.6g\"",
+            name,
+            String::from_utf8_lossy(spec)
+        );
     }
+    let cell = match which {
+        NumberFormat::Convert => &CONVFMT,
+        NumberFormat::Output => &OFMT,
+    };
+    cell.with(|fmt| {
+        let mut fmt = fmt.borrow_mut();
+        fmt.clear();
+        fmt.extend_from_slice(spec);
+    });
     Ok(())
 }
 
+/// Format `f` with CONVFMT or OFMT, or return `None` if the script has not set it.
+pub(crate) fn format_number<'a>(which: NumberFormat, f: Float) -> Option<Str<'a>> {
+    let cell = match which {
+        NumberFormat::Convert => &CONVFMT,
+        NumberFormat::Output => &OFMT,
+    };
+    cell.with(|fmt| {
+        let fmt = fmt.borrow();
+        if fmt.is_empty() {
+            return None;
+        }
+        let mut buf = StackWriter::default();
+        printf(&mut buf, &fmt, &[FormatArg::F(f)]).ok()?;
+        Some(Str::number(&buf.0))
+    })
+}
+
+// Whether `spec` has exactly one directive and it formats a number. Formatting a number as a
+// string (`%s` or `%c`) would convert it with CONVFMT all over again.
+fn formats_one_number(spec: &[u8]) -> bool {
+    let mut directives = 0;
+    let mut ix = 0;
+    while let Some(pct) = memchr::memchr(b'%', &spec[ix..]) {
+        ix += pct + 1;
+        if spec.get(ix) == Some(&b'%') {
+            ix += 1;
+            continue;
+        }
+        let (d, end) = match parse_directive(spec, ix) {
+            Some(x) => x,
+            None => return false,
+        };
+        if matches!(d.fs.spec, b's' | b'c') || d.arg.is_some_and(|n| n != 1) {
+            return false;
+        }
+        directives += 1;
+        ix = end;
+    }
+    directives == 1
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -441,6 +604,15 @@ mod tests {
         assert_eq!(s2.as_str(), "|%-10.");
     }
 
+    #[test]
+    fn integer_radixes() {
+        let s1 = sprintf!(b"%x %X %o %08b", 255, 255, 493, 10);
+        assert_eq!(s1.as_str(), "ff FF 755 00001010");
+        // Negative values print as their 64-bit two's complement.
+        let s2 = sprintf!(b"%x %o", -1, i64::MIN);
+        assert_eq!(s2.as_str(), "ffffffffffffffff 1000000000000000000000");
+    }
+
     #[test]
     fn float_rounding() {
         let s1 = sprintf!(b"%02.2f", 2.375);
@@ -448,4 +620,73 @@ mod tests {
         let s2 = sprintf!(b"%.2f", 2.375);
         assert_eq!(s2.as_str(), "2.38");
     }
+
+    #[test]
+    fn percent_and_chars() {
+        let s1 = sprintf!(b"%d%% %i%%", 50, 7.9);
+        assert_eq!(s1.as_str(), "50% 7%");
+        let s2 = sprintf!(b"[%c|%c|%c|%3c|%.1c]", 65, "65", "hello", "x", "é");
+        assert_eq!(s2.as_str(), "[A|A|h|  x|é]");
+        let s3 = sprintf!(b"[%c]", "");
+        assert_eq!(s3.as_str(), "[]");
+    }
+
+    #[test]
+    fn unsigned() {
+        let s1 = sprintf!(b"%u %x %u", -1, "18446744073709551615", "0x20");
+        assert_eq!(s1.as_str(), "18446744073709551615 ffffffffffffffff 32");
+        let s2 = sprintf!(b"%08X|%-4u|", "3054", 7);
+        assert_eq!(s2.as_str(), "00000BEE|7   |");
+    }
+
+    #[test]
+    fn positional_args() {
+        let s1 = sprintf!(b"%2$s %1$s, %2$s!", "world", "hello");
+        assert_eq!(s1.as_str(), "hello world, hello!");
+        let s2 = sprintf!(b"%2$-5d|%1$05.1f|%3$s|", 2.5, 3);
+        assert_eq!(s2.as_str(), "3    |002.5||");
+        // Numbered directives do not use up the arguments of unnumbered ones.
+        let s3 = sprintf!(b"%1$s %s %s", "a", "b");
+        assert_eq!(s3.as_str(), "a a b");
+        let s4 = sprintf!(b"%0$s %5", "a");
+        assert_eq!(s4.as_str(), "%0$s %5");
+    }
+
+    #[test]
+    fn analyze_format() {
+        assert_eq!(
+            format_args(b"%-5s %% %.2f %c"),
+            FormatArgs {
+                sequential: 3,
+                positional: 0
+            }
+        );
+        let args = format_args(b"%3$s %1$d %q %");
+        assert_eq!(
+            args,
+            FormatArgs {
+                sequential: 0,
+                positional: 3
+            }
+        );
+        assert_eq!(args.expected(), 3);
+    }
+
+    #[test]
+    fn number_formats() {
+        assert!(formats_one_number(b"%.6g"));
+        assert!(formats_one_number(b"%1$.2f%%"));
+        assert!(formats_one_number(b"[%d]"));
+        assert!(!formats_one_number(b"%s"));
+        assert!(!formats_one_number(b"%.2f %.2f"));
+        assert!(!formats_one_number(b"no directive"));
+        assert!(set_number_format(NumberFormat::Convert, b"%c").is_err());
+
+        set_number_format(NumberFormat::Convert, b"%.3f").unwrap();
+        assert_eq!(convert::<_, Str>(2.0f64 / 3.0).as_str(), "0.667");
+        assert_eq!(convert::<_, Str>(12.0f64).as_str(), "12");
+        assert!(format_number(NumberFormat::Output, 0.5).is_none());
+        set_number_format(NumberFormat::Convert, b"").unwrap();
+        assert_eq!(convert::<_, Str>(0.5f64).as_str(), "0.5");
+    }
 }
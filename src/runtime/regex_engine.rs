@@ -0,0 +1,266 @@
+//! The regexes of patterns in programs: `/re/`, `~`, `match`, `sub`, `gsub`, `gensub`, `split`,
+//! `FS` and `RS`.
+//!
+//! Patterns are compiled with the `regex` crate, which matches in linear time but has no
+//! lookaround or backreferences. With the `fancy-regex` feature, patterns that `regex` rejects,
+//! such as `(?<=\$)[0-9]+` or `(\w)\1`, are compiled with the backtracking `fancy-regex` engine
+//! instead, so existing patterns keep their meaning and speed. `fancy-regex` matches text rather
+//! than bytes: on input that is not valid UTF-8, these patterns only see the text before the first
+//! invalid byte.
+use super::str_impl::ignore_case;
+use regex::bytes;
+use std::sync::OnceLock;
+
+pub struct Regex {
+    engine: Engine,
+    // The pattern with `(?i)`, compiled the first time it is matched while `IGNORECASE` is set.
+    folded: OnceLock<Engine>,
+}
+
+enum Engine {
+    Bytes(bytes::Regex),
+    #[cfg(feature = "fancy-regex")]
+    Fancy(fancy_regex::Regex),
+}
+
+impl Engine {
+    fn new(pat: &str) -> Result<Engine, String> {
+        match bytes::Regex::new(pat) {
+            Ok(re) => Ok(Engine::Bytes(re)),
+            #[cfg(feature = "fancy-regex")]
+            Err(e) => fancy_regex::Regex::new(pat)
+                .map(Engine::Fancy)
+                .map_err(|_| e.to_string()),
+            #[cfg(not(feature = "fancy-regex"))]
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+impl Regex {
+    pub fn new(pat: &str) -> Result<Regex, String> {
+        Ok(Regex {
+            engine: Engine::new(pat)?,
+            folded: OnceLock::new(),
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.engine {
+            Engine::Bytes(re) => re.as_str(),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => re.as_str(),
+        }
+    }
+
+    // The engine to match with: the pattern as written, or ignoring case under `IGNORECASE`.
+    fn engine(&self) -> &Engine {
+        if !ignore_case() {
+            return &self.engine;
+        }
+        self.folded.get_or_init(|| {
+            Engine::new(&format!("(?i){}", self.as_str()))
+                .expect("a valid pattern stays valid with (?i)")
+        })
+    }
+
+    pub fn is_match(&self, bs: &[u8]) -> bool {
+        match self.engine() {
+            Engine::Bytes(re) => re.is_match(bs),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => re.is_match(text(bs)).unwrap_or(false),
+        }
+    }
+
+    pub fn find<'t>(&self, bs: &'t [u8]) -> Option<Match<'t>> {
+        match self.engine() {
+            Engine::Bytes(re) => re.find(bs).map(Match::of_bytes),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => re.find(text(bs)).ok()?.map(Match::of_fancy),
+        }
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, bs: &'t [u8]) -> Matches<'r, 't> {
+        match self.engine() {
+            Engine::Bytes(re) => Matches::Bytes(re.find_iter(bs)),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => Matches::Fancy(re.find_iter(text(bs))),
+        }
+    }
+
+    pub fn captures<'t>(&self, bs: &'t [u8]) -> Option<Captures<'t>> {
+        match self.engine() {
+            Engine::Bytes(re) => re.captures(bs).map(Captures::Bytes),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => re.captures(text(bs)).ok()?.map(Captures::Fancy),
+        }
+    }
+
+    pub fn captures_iter<'r, 't>(&'r self, bs: &'t [u8]) -> CaptureMatches<'r, 't> {
+        match self.engine() {
+            Engine::Bytes(re) => CaptureMatches::Bytes(re.captures_iter(bs)),
+            #[cfg(feature = "fancy-regex")]
+            Engine::Fancy(re) => CaptureMatches::Fancy(re.captures_iter(text(bs))),
+        }
+    }
+}
+
+impl std::fmt::Debug for Regex {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// The text that fancy-regex matches `bs` as: all of it if it is valid UTF-8, and the part before
+// the first invalid byte otherwise. Offsets into it are offsets into `bs`.
+#[cfg(feature = "fancy-regex")]
+fn text(bs: &[u8]) -> &str {
+    match std::str::from_utf8(bs) {
+        Ok(s) => s,
+        // SAFETY: `valid_up_to` is the length of the longest valid prefix.
+        Err(e) => unsafe { std::str::from_utf8_unchecked(&bs[..e.valid_up_to()]) },
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Match<'t> {
+    bytes: &'t [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    fn of_bytes(m: bytes::Match<'t>) -> Match<'t> {
+        Match {
+            bytes: m.as_bytes(),
+            start: m.start(),
+            end: m.end(),
+        }
+    }
+    #[cfg(feature = "fancy-regex")]
+    fn of_fancy(m: fancy_regex::Match<'t>) -> Match<'t> {
+        Match {
+            bytes: m.as_str().as_bytes(),
+            start: m.start(),
+            end: m.end(),
+        }
+    }
+    pub fn start(&self) -> usize {
+        self.start
+    }
+    pub fn end(&self) -> usize {
+        self.end
+    }
+    pub fn as_bytes(&self) -> &'t [u8] {
+        self.bytes
+    }
+}
+
+pub enum Matches<'r, 't> {
+    Bytes(bytes::Matches<'r, 't>),
+    #[cfg(feature = "fancy-regex")]
+    Fancy(fancy_regex::Matches<'r, 't>),
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+    fn next(&mut self) -> Option<Match<'t>> {
+        match self {
+            Matches::Bytes(ms) => ms.next().map(Match::of_bytes),
+            // Stop at the first error, such as exceeding the backtracking limit.
+            #[cfg(feature = "fancy-regex")]
+            Matches::Fancy(ms) => ms.next()?.ok().map(Match::of_fancy),
+        }
+    }
+}
+
+pub enum Captures<'t> {
+    Bytes(bytes::Captures<'t>),
+    #[cfg(feature = "fancy-regex")]
+    Fancy(fancy_regex::Captures<'t>),
+}
+
+impl<'t> Captures<'t> {
+    /// Group `i`, where group 0 is the whole match.
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        match self {
+            Captures::Bytes(c) => c.get(i).map(Match::of_bytes),
+            #[cfg(feature = "fancy-regex")]
+            Captures::Fancy(c) => c.get(i).map(Match::of_fancy),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Captures::Bytes(c) => c.len(),
+            #[cfg(feature = "fancy-regex")]
+            Captures::Fancy(c) => c.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub enum CaptureMatches<'r, 't> {
+    Bytes(bytes::CaptureMatches<'r, 't>),
+    #[cfg(feature = "fancy-regex")]
+    Fancy(fancy_regex::CaptureMatches<'r, 't>),
+}
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Captures<'t>;
+    fn next(&mut self) -> Option<Captures<'t>> {
+        match self {
+            CaptureMatches::Bytes(cs) => cs.next().map(Captures::Bytes),
+            #[cfg(feature = "fancy-regex")]
+            CaptureMatches::Fancy(cs) => cs.next()?.ok().map(Captures::Fancy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::str_impl::set_ignore_case;
+
+    #[test]
+    fn engines() {
+        let re = Regex::new("a+").unwrap();
+        assert!(matches!(re.engine, Engine::Bytes(_)));
+        let ms: Vec<_> = re.find_iter(b"baab a").map(|m| (m.start(), m.end())).collect();
+        assert_eq!(ms, vec![(1, 3), (5, 6)]);
+        assert!(Regex::new("(").is_err());
+    }
+
+    #[cfg(feature = "fancy-regex")]
+    #[test]
+    fn lookaround_and_backreferences() {
+        let re = Regex::new(r"(?<=\$)[0-9]+").unwrap();
+        assert!(matches!(re.engine, Engine::Fancy(_)));
+        let m = re.find(b"cost: $42").unwrap();
+        assert_eq!((m.start(), m.end(), m.as_bytes()), (7, 9, &b"42"[..]));
+        let re = Regex::new(r"(\w)\1").unwrap();
+        assert!(re.is_match(b"hello"));
+        assert!(!re.is_match(b"helo"));
+        let caps = re.captures(b"abba").unwrap();
+        let (all, b) = (caps.get(0).unwrap(), caps.get(1).unwrap());
+        assert_eq!((all.start(), all.end(), b.as_bytes()), (1, 3, &b"b"[..]));
+        // Text after an invalid byte is not searched.
+        assert!(!re.is_match(b"ab\xffcc"));
+        let err = bytes::Regex::new(r"(?<=x").unwrap_err().to_string();
+        assert_eq!(Regex::new(r"(?<=x").unwrap_err(), err);
+    }
+
+    #[test]
+    fn ignore_case() {
+        let re = Regex::new("ab+").unwrap();
+        assert!(!re.is_match(b"xABB"));
+        set_ignore_case(true);
+        assert_eq!(re.find(b"xABB").map(|m| m.as_bytes()), Some(&b"ABB"[..]));
+        assert_eq!(re.as_str(), "ab+");
+        set_ignore_case(false);
+        assert!(!re.is_match(b"xABB"));
+    }
+}
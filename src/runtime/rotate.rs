@@ -0,0 +1,225 @@
+//! Output files that rotate themselves.
+//!
+//! Output paths of the form `rotate://app.log?size=100M&interval=1d&keep=5` write to `app.log`
+//! until it grows past `size` bytes or has been open for longer than `interval`. At that point
+//! `app.log` is renamed to `app.log.1` (shifting older files up to `app.log.<keep>`, dropping
+//! anything beyond it) and a fresh `app.log` is started.
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub(crate) const PREFIX: &str = "rotate://";
+
+const DEFAULT_KEEP: usize = 5;
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Parse sizes like `4096`, `10K`, `100M` or `1GB`, using powers of 1024.
+pub(crate) fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let upper = s.to_ascii_uppercase();
+    let digits = upper.trim_end_matches('B');
+    let (num, mult) = match digits.chars().last()? {
+        'K' => (&digits[..digits.len() - 1], 1 << 10),
+        'M' => (&digits[..digits.len() - 1], 1 << 20),
+        'G' => (&digits[..digits.len() - 1], 1 << 30),
+        _ => (digits, 1),
+    };
+    num.trim().parse::<u64>().ok()?.checked_mul(mult)
+}
+
+/// Parse durations like `30`, `30s`, `10m`, `1h` or `7d`; a bare number is in seconds.
+pub(crate) fn parse_interval(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (num, mult) = match s.chars().last()? {
+        's' => (&s[..s.len() - 1], 1),
+        'm' => (&s[..s.len() - 1], 60),
+        'h' => (&s[..s.len() - 1], 60 * 60),
+        'd' => (&s[..s.len() - 1], 24 * 60 * 60),
+        _ => (s, 1),
+    };
+    let secs = num.trim().parse::<u64>().ok()?.checked_mul(mult)?;
+    Some(Duration::from_secs(secs))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct RotateSpec {
+    pub path: String,
+    pub max_bytes: Option<u64>,
+    pub interval: Option<Duration>,
+    pub keep: usize,
+}
+
+impl RotateSpec {
+    /// Parse a `rotate://path?options` url. The caller is expected to have checked for `PREFIX`.
+    pub(crate) fn parse(url: &str) -> io::Result<RotateSpec> {
+        let rest = url.strip_prefix(PREFIX).unwrap_or(url);
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (rest, ""),
+        };
+        if path.is_empty() {
+            return Err(invalid(format!("missing file name in {}", url)));
+        }
+        let mut spec = RotateSpec {
+            path: path.to_string(),
+            max_bytes: None,
+            interval: None,
+            keep: DEFAULT_KEEP,
+        };
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "size" => {
+                    spec.max_bytes = Some(
+                        parse_size(value)
+                            .ok_or_else(|| invalid(format!("invalid rotation size: {}", value)))?,
+                    )
+                }
+                "interval" | "time" => {
+                    spec.interval = Some(parse_interval(value).ok_or_else(|| {
+                        invalid(format!("invalid rotation interval: {}", value))
+                    })?)
+                }
+                "keep" => {
+                    spec.keep = value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid rotation keep count: {}", value)))?
+                }
+                _ => return Err(invalid(format!("unknown rotation option: {}", key))),
+            }
+        }
+        Ok(spec)
+    }
+
+    fn backup(&self, i: usize) -> String {
+        format!("{}.{}", self.path, i)
+    }
+}
+
+pub(crate) struct RotatingFile {
+    spec: RotateSpec,
+    file: File,
+    written: u64,
+    opened: Instant,
+}
+
+impl RotatingFile {
+    pub(crate) fn open(spec: RotateSpec, append: bool) -> io::Result<RotatingFile> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(&spec.path)?;
+        let written = if append { file.metadata()?.len() } else { 0 };
+        Ok(RotatingFile {
+            spec,
+            file,
+            written,
+            opened: Instant::now(),
+        })
+    }
+
+    fn should_rotate(&self, incoming: usize) -> bool {
+        if self.written == 0 {
+            // Never rotate out an empty file: a single write larger than `size` still has to go
+            // somewhere.
+            return false;
+        }
+        let too_big = matches!(self.spec.max_bytes, Some(max) if self.written + incoming as u64 > max);
+        let too_old = matches!(self.spec.interval, Some(dur) if self.opened.elapsed() >= dur);
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let keep = self.spec.keep;
+        if keep > 0 {
+            let _ = fs::remove_file(self.spec.backup(keep));
+            for i in (1..keep).rev() {
+                let from = self.spec.backup(i);
+                if fs::metadata(&from).is_ok() {
+                    fs::rename(&from, self.spec.backup(i + 1))?;
+                }
+            }
+            fs::rename(&self.spec.path, self.spec.backup(1))?;
+        }
+        self.file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.spec.path)?;
+        self.written = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    // Writes arrive in large batches of records, so we only write the prefix of `buf` that fits
+    // in the current file, cut at a newline so that records are not split across files. Callers
+    // use write_all, which hands us the rest of the batch afterwards.
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        if let Some(max) = self.spec.max_bytes {
+            if self.written + buf.len() as u64 > max {
+                let room = max.saturating_sub(self.written) as usize;
+                if let Some(i) = memchr::memrchr(b'\n', &buf[..room.min(buf.len())]) {
+                    buf = &buf[..=i];
+                } else if self.written == 0 {
+                    if let Some(i) = memchr::memchr(b'\n', buf) {
+                        buf = &buf[..=i];
+                    }
+                }
+            }
+        }
+        if self.should_rotate(buf.len()) {
+            self.rotate()?;
+            return self.write(buf);
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_spec() {
+        let spec = RotateSpec::parse("rotate://app.log?size=100M&keep=3&interval=1h").unwrap();
+        assert_eq!(spec.path, "app.log");
+        assert_eq!(spec.max_bytes, Some(100 << 20));
+        assert_eq!(spec.interval, Some(Duration::from_secs(3600)));
+        assert_eq!(spec.keep, 3);
+        let spec = RotateSpec::parse("rotate:///var/log/x.log").unwrap();
+        assert_eq!(spec.path, "/var/log/x.log");
+        assert_eq!(spec.keep, DEFAULT_KEEP);
+        assert!(RotateSpec::parse("rotate://x.log?size=lots").is_err());
+        assert!(RotateSpec::parse("rotate://x.log?color=red").is_err());
+        assert_eq!(parse_size("10kb"), Some(10 << 10));
+    }
+
+    #[test]
+    fn test_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let path = path.to_str().unwrap();
+        let spec = RotateSpec::parse(&format!("rotate://{}?size=10&keep=2", path)).unwrap();
+        let mut file = RotatingFile::open(spec, false).unwrap();
+        file.write_all(b"aaaaaa\nbbbbbb\ncccccc\n").unwrap();
+        file.write_all(b"dddddd\n").unwrap();
+        file.flush().unwrap();
+        assert_eq!(fs::read_to_string(path).unwrap(), "dddddd\n");
+        assert_eq!(fs::read_to_string(format!("{}.1", path)).unwrap(), "cccccc\n");
+        assert_eq!(fs::read_to_string(format!("{}.2", path)).unwrap(), "bbbbbb\n");
+        assert!(fs::metadata(format!("{}.3", path)).is_err());
+    }
+}
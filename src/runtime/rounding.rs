@@ -0,0 +1,224 @@
+//! Decimal rounding for `round`, `floor_to` and `ceil_to`, and the `--round-mode` switch.
+//!
+//! Floats are rounded on their shortest decimal representation (the digits `print` shows) rather
+//! than on their exact binary value, so `round(2.675, 2)` is `2.68` even though the closest double
+//! to 2.675 is a little below it. The rounding mode decides what happens to ties:
+//!
+//! * `exact` (the default): `round` rounds ties away from zero, and printf rounds the exact binary
+//!   value, as C does.
+//! * `half-up`: ties are rounded away from zero, by `round` and by printf precisions.
+//! * `half-even`: ties are rounded to the nearest even digit ("banker's rounding"), by `round` and
+//!   by printf precisions.
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum RoundMode {
+    Exact = 0,
+    HalfUp = 1,
+    HalfEven = 2,
+}
+
+static MODE: AtomicU8 = AtomicU8::new(RoundMode::Exact as u8);
+
+impl RoundMode {
+    pub(crate) fn from_name(name: &str) -> Option<RoundMode> {
+        match name {
+            "exact" => Some(RoundMode::Exact),
+            "half-up" => Some(RoundMode::HalfUp),
+            "half-even" => Some(RoundMode::HalfEven),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn set_mode(mode: RoundMode) {
+    MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn mode() -> RoundMode {
+    match MODE.load(Ordering::Relaxed) {
+        1 => RoundMode::HalfUp,
+        2 => RoundMode::HalfEven,
+        _ => RoundMode::Exact,
+    }
+}
+
+/// `round(x)`: the nearest integer to `x`.
+pub(crate) fn round(x: f64) -> f64 {
+    match mode() {
+        RoundMode::HalfEven => x.round_ties_even(),
+        RoundMode::Exact | RoundMode::HalfUp => x.round(),
+    }
+}
+
+/// `round(x, digits)`: `x` rounded to `digits` decimal places. Negative `digits` round to tens,
+/// hundreds, and so on.
+pub(crate) fn round_to(x: f64, digits: f64) -> f64 {
+    decimal_round(x, digits as i64, mode() == RoundMode::HalfEven)
+}
+
+/// The multiple of `step` at or below `x`.
+pub(crate) fn floor_to(x: f64, step: f64) -> f64 {
+    to_step(x, step, f64::floor)
+}
+
+/// The multiple of `step` at or above `x`.
+pub(crate) fn ceil_to(x: f64, step: f64) -> f64 {
+    to_step(x, step, f64::ceil)
+}
+
+/// The value printf should format with `precision` digits after the point (or, for `%e`,
+/// significant digits after the first), or `None` to format `x` as it is.
+pub(crate) fn for_printf(x: f64, precision: usize, scientific: bool) -> Option<f64> {
+    printf_value(x, precision, scientific, mode())
+}
+
+fn printf_value(x: f64, precision: usize, scientific: bool, mode: RoundMode) -> Option<f64> {
+    let even = match mode {
+        RoundMode::Exact => return None,
+        RoundMode::HalfUp => false,
+        RoundMode::HalfEven => true,
+    };
+    if !x.is_finite() || x == 0.0 {
+        return None;
+    }
+    let digits = precision.min(i64::MAX as usize / 2) as i64;
+    let digits = if scientific {
+        digits - shortest(x).2
+    } else {
+        digits
+    };
+    Some(decimal_round(x, digits, even))
+}
+
+// The sign, digits and exponent of the shortest decimal representation of finite `x`, which is
+// `0.d1d2d3... * 10^(exp + 1)`.
+fn shortest(x: f64) -> (bool, Vec<u8>, i64) {
+    let s = format!("{:e}", x.abs());
+    let (mantissa, exp) = s.split_once('e').expect("{:e} should print an exponent");
+    let digits = mantissa.bytes().filter(u8::is_ascii_digit).collect();
+    (x < 0.0, digits, exp.parse().expect("{:e} should print an integer exponent"))
+}
+
+fn decimal_round(x: f64, digits: i64, even: bool) -> f64 {
+    if !x.is_finite() || x == 0.0 {
+        return x;
+    }
+    let (neg, mut ds, exp) = shortest(x);
+    // The number of leading digits to keep.
+    let keep = exp.saturating_add(1).saturating_add(digits);
+    if keep >= ds.len() as i64 {
+        return x;
+    }
+    if keep < 0 {
+        return 0.0;
+    }
+    let keep = keep as usize;
+    let (first, rest) = (ds[keep], &ds[keep + 1..]);
+    let round_up = match first.cmp(&b'5') {
+        std::cmp::Ordering::Less => false,
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Equal => {
+            rest.iter().any(|d| *d != b'0')
+                || !even
+                || keep.checked_sub(1).is_some_and(|last| (ds[last] - b'0') % 2 == 1)
+        }
+    };
+    ds.truncate(keep);
+    if round_up {
+        match ds.iter().rposition(|d| *d != b'9') {
+            Some(i) => {
+                ds[i] += 1;
+                ds.truncate(i + 1);
+            }
+            // All nines (or nothing kept at all): carry into a new leading digit.
+            None => return signed(neg, format!("1e{}", exp + 1)),
+        }
+    }
+    if ds.is_empty() {
+        return 0.0;
+    }
+    let scale = exp + 1 - ds.len() as i64;
+    signed(neg, format!("{}e{}", std::str::from_utf8(&ds).unwrap(), scale))
+}
+
+fn signed(neg: bool, digits: String) -> f64 {
+    let v: f64 = digits.parse().expect("rounded digits should parse as a float");
+    if neg {
+        -v
+    } else {
+        v
+    }
+}
+
+fn to_step(x: f64, step: f64, snap: fn(f64) -> f64) -> f64 {
+    let step = step.abs();
+    if step == 0.0 || !step.is_finite() || !x.is_finite() {
+        return x;
+    }
+    // Division can leave an exact multiple a hair off an integer (7.3 / 0.1 is 73.00000000000001),
+    // which would push ceil_to(7.3, 0.1) up to 7.4.
+    let q = x / step;
+    let nearest = q.round();
+    let q = if (q - nearest).abs() <= 1e-9 * nearest.abs().max(1.0) {
+        nearest
+    } else {
+        snap(q)
+    };
+    // Multiplying back can leave noise in the last place, too; the result has no more decimal
+    // places than the step does.
+    let (_, ds, exp) = shortest(step);
+    let places = ds.len() as i64 - 1 - exp;
+    let res = q * step;
+    if places < 16 {
+        decimal_round(res, places.max(0), false)
+    } else {
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_places() {
+        assert_eq!(decimal_round(2.675, 2, false), 2.68);
+        assert_eq!(decimal_round(2.665, 2, false), 2.67);
+        assert_eq!(decimal_round(2.665, 2, true), 2.66);
+        assert_eq!(decimal_round(2.675, 2, true), 2.68);
+        assert_eq!(decimal_round(-0.125, 2, true), -0.12);
+        assert_eq!(decimal_round(-0.125, 2, false), -0.13);
+        assert_eq!(decimal_round(0.5, 0, true), 0.0);
+        assert_eq!(decimal_round(1.5, 0, true), 2.0);
+        assert_eq!(decimal_round(9.996, 2, false), 10.0);
+        assert_eq!(decimal_round(0.6, 0, false), 1.0);
+        assert_eq!(decimal_round(0.004, 2, false), 0.0);
+        assert_eq!(decimal_round(1234.5, -2, false), 1200.0);
+        assert_eq!(decimal_round(1250.0, -2, true), 1200.0);
+        assert_eq!(decimal_round(1.25, 5, true), 1.25);
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(floor_to(7.3, 0.5), 7.0);
+        assert_eq!(ceil_to(7.3, 0.5), 7.5);
+        assert_eq!(ceil_to(7.3, 0.1), 7.3);
+        assert_eq!(floor_to(0.3, 0.1), 0.3);
+        assert_eq!(ceil_to(0.71, 0.05), 0.75);
+        assert_eq!(floor_to(-7.3, 0.5), -7.5);
+        assert_eq!(floor_to(1234.0, 100.0), 1200.0);
+        assert_eq!(floor_to(5.0, 0.0), 5.0);
+    }
+
+    #[test]
+    fn printf_precision() {
+        use RoundMode::*;
+        assert_eq!(printf_value(2.665, 2, false, HalfEven), Some(2.66));
+        assert_eq!(printf_value(2.665, 2, false, HalfUp), Some(2.67));
+        assert_eq!(printf_value(2.665e10, 2, true, HalfEven), Some(2.66e10));
+        assert_eq!(printf_value(0.0, 2, false, HalfEven), None);
+        assert_eq!(printf_value(2.665, 2, false, Exact), None);
+    }
+}
@@ -0,0 +1,83 @@
+//! Arithmetic for `--safe-math`, which reports errors in `ERRNO` instead of stopping the program.
+//!
+//! Division (`/` and `%`) by zero gives NaN rather than an infinity or a crash; integers have no
+//! NaN, so `%` of two integers gives 0 instead. Integer `+`, `-` and `*` wrap around on overflow.
+//! Either way `ERRNO` is set to a message describing the error. Operations that succeed leave
+//! `ERRNO` alone, so a script checking for errors clears it first.
+use crate::runtime::{Float, Int};
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) const DIVISION_BY_ZERO: &str = "division by zero";
+pub(crate) const OVERFLOW: &str = "integer overflow";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum Op {
+    Add = 0,
+    Sub = 1,
+    Mul = 2,
+    Div = 3,
+    Mod = 4,
+}
+
+impl Op {
+    /// The inverse of `op as Int`, which is how generated code passes an `Op`.
+    #[cfg_attr(not(any(feature = "jit", feature = "llvm_backend")), allow(dead_code))]
+    pub(crate) fn from_int(i: Int) -> Op {
+        match i {
+            0 => Op::Add,
+            1 => Op::Sub,
+            2 => Op::Mul,
+            3 => Op::Div,
+            _ => Op::Mod,
+        }
+    }
+}
+
+/// `x op y` on integers, and the error, if any.
+pub(crate) fn int(op: Op, x: Int, y: Int) -> (Int, Option<&'static str>) {
+    if y == 0 && matches!(op, Op::Div | Op::Mod) {
+        return (0, Some(DIVISION_BY_ZERO));
+    }
+    let (res, overflow) = match op {
+        Op::Add => x.overflowing_add(y),
+        Op::Sub => x.overflowing_sub(y),
+        Op::Mul => x.overflowing_mul(y),
+        Op::Div => x.overflowing_div(y),
+        Op::Mod => x.overflowing_rem(y),
+    };
+    (res, overflow.then_some(OVERFLOW))
+}
+
+/// `x op y` on floats, and the error, if any.
+pub(crate) fn float(op: Op, x: Float, y: Float) -> (Float, Option<&'static str>) {
+    match op {
+        Op::Div | Op::Mod if y == 0.0 => (Float::NAN, Some(DIVISION_BY_ZERO)),
+        Op::Add => (x + y, None),
+        Op::Sub => (x - y, None),
+        Op::Mul => (x * y, None),
+        Op::Div => (x / y, None),
+        Op::Mod => (x % y, None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors() {
+        assert_eq!(int(Op::Add, 2, 3), (5, None));
+        assert_eq!(int(Op::Mod, 7, 0), (0, Some(DIVISION_BY_ZERO)));
+        assert_eq!(int(Op::Add, Int::MAX, 1), (Int::MIN, Some(OVERFLOW)));
+        assert_eq!(int(Op::Mul, Int::MIN, -1), (Int::MIN, Some(OVERFLOW)));
+        assert_eq!(int(Op::Mod, Int::MIN, -1), (0, Some(OVERFLOW)));
+        assert_eq!(float(Op::Div, 1.0, 4.0), (0.25, None));
+        let (res, err) = float(Op::Div, -1.0, 0.0);
+        assert!(res.is_nan() && err == Some(DIVISION_BY_ZERO));
+        assert!(float(Op::Mod, 1.0, -0.0).0.is_nan());
+        for op in [Op::Add, Op::Sub, Op::Mul, Op::Div, Op::Mod] {
+            assert_eq!(Op::from_int(op as Int), op);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Probabilistic "sketch" state used by builtins such as `bf_insert` and `bf_contains`.
+//!
+//! Sketches live in the runtime `Core`, so each worker of a parallel script owns its own copy.
+//! A worker's copy starts out as a clone of the main thread's state (so items inserted in BEGIN
+//! are visible everywhere) and records every item it inserts afterwards. When the stage finishes,
+//! those records are replayed into the main thread's sketches, which means the END block sees
+//! the union of everything inserted by every worker.
+use growable_bloom_filter::{GrowableBloom, GrowableBloomBuilder};
+use hashbrown::HashMap;
+use xxhash_rust::xxh64::xxh64;
+
+use crate::runtime::Int;
+
+#[derive(Default, Clone)]
+pub(crate) struct Sketches {
+    blooms: HashMap<String, GrowableBloom>,
+    // Hashes inserted into `blooms`, by group, since this copy was handed to a worker. `None` for
+    // the main thread, which never has to send its state anywhere.
+    inserted: Option<HashMap<String, Vec<u64>>>,
+}
+
+// Items are hashed up front so that workers only have to keep a u64 per insertion around for the
+// merge, and so that non-UTF8 items behave like any other string.
+fn item_hash(item: &[u8]) -> u64 {
+    xxh64(item, 0)
+}
+
+impl Sketches {
+    /// A copy of the current sketch state for use by a worker thread.
+    pub(crate) fn for_worker(&self) -> Sketches {
+        Sketches {
+            blooms: self.blooms.clone(),
+            inserted: Some(Default::default()),
+        }
+    }
+
+    /// Fold the insertions performed by a worker's copy back into `self`.
+    pub(crate) fn merge(&mut self, other: Sketches) {
+        for (group, hashes) in other.inserted.unwrap_or_default() {
+            for h in hashes {
+                self.insert_hash(group.as_str(), h);
+            }
+        }
+    }
+
+    fn insert_hash(&mut self, group: &str, h: u64) -> bool /* inserted */ {
+        let filter = match self.blooms.get_mut(group) {
+            Some(filter) => filter,
+            None => self
+                .blooms
+                .entry(group.to_string())
+                .or_insert_with(|| GrowableBloomBuilder::new().build()),
+        };
+        if filter.contains(h) {
+            return false;
+        }
+        filter.insert(h);
+        if let Some(inserted) = &mut self.inserted {
+            match inserted.get_mut(group) {
+                Some(hs) => hs.push(h),
+                None => {
+                    inserted.insert(group.to_string(), vec![h]);
+                }
+            }
+        }
+        true
+    }
+
+    pub(crate) fn bf_insert(&mut self, item: &[u8], group: &str) {
+        self.insert_hash(group, item_hash(item));
+    }
+
+    pub(crate) fn bf_contains(&self, item: &[u8], group: &str) -> Int {
+        match self.blooms.get(group) {
+            Some(filter) => filter.contains(item_hash(item)) as Int,
+            None => 0,
+        }
+    }
+
+    /// Insert `item` if it is not already present, returning 1 if it was found.
+    pub(crate) fn bf_icontains(&mut self, item: &[u8], group: &str) -> Int {
+        !self.insert_hash(group, item_hash(item)) as Int
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bf_insert() {
+        let mut sketches = Sketches::default();
+        sketches.bf_insert(b"first", "_");
+        assert_eq!(sketches.bf_contains(b"first", "_"), 1);
+        assert_eq!(sketches.bf_contains(b"first", "other"), 0);
+    }
+
+    #[test]
+    fn test_icontains() {
+        let mut sketches = Sketches::default();
+        assert_eq!(sketches.bf_icontains(b"first", "_"), 0);
+        assert_eq!(sketches.bf_icontains(b"second", "_"), 0);
+        assert_eq!(sketches.bf_icontains(b"first", "_"), 1);
+    }
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut bloom = GrowableBloomBuilder::new().build();
+        bloom.insert("first");
+        assert!(bloom.contains("first"));
+        assert_eq!(bloom.contains("second"), false);
+    }
+
+    #[test]
+    fn test_merge_workers() {
+        let mut main = Sketches::default();
+        main.bf_insert(b"begin", "_");
+        let mut w1 = main.for_worker();
+        let mut w2 = main.for_worker();
+        assert_eq!(w1.bf_contains(b"begin", "_"), 1);
+        w1.bf_insert(b"one", "_");
+        assert_eq!(w2.bf_icontains(b"two", "ids"), 0);
+        main.merge(w1);
+        main.merge(w2);
+        assert_eq!(main.bf_contains(b"begin", "_"), 1);
+        assert_eq!(main.bf_contains(b"one", "_"), 1);
+        assert_eq!(main.bf_contains(b"two", "ids"), 1);
+        assert_eq!(main.bf_contains(b"two", "_"), 0);
+    }
+}
@@ -0,0 +1,795 @@
+//! Reading Avro object container files (`-i avro`).
+//!
+//! Each record of the file becomes a line whose fields are the fields of the record, in schema
+//! order. Fields are rendered the same way as `from_json` renders values: `null` is empty,
+//! booleans are `1`/`0`, bytes and fixed values are base64, decimals are written out in full and
+//! nested records, arrays and maps are JSON text. `$0` is the record as a CSV line.
+//!
+//! Only the fields a program uses are decoded: the others are skipped over in the block buffer,
+//! which for strings and blocked arrays and maps does not look at their contents at all.
+use std::collections::HashMap;
+use std::io::{BufReader, ErrorKind, Read};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde_json::{Map, Value};
+
+use crate::common::Result;
+use crate::pushdown::FieldSet;
+use crate::runtime::binfmt::{field_text, float};
+use crate::runtime::{str_impl::Str, RegexCache};
+
+use super::{
+    batch::{escape_csv, Line},
+    LineReader, ReaderState,
+};
+
+const MAGIC: &[u8] = b"Obj\x01";
+
+#[derive(Clone, Debug)]
+enum Schema {
+    Null,
+    Boolean,
+    Int,
+    Long,
+    Float,
+    Double,
+    Bytes,
+    String,
+    Fixed(usize),
+    // Scale, and the size for decimals stored as fixed values.
+    Decimal(u32, Option<usize>),
+    Enum(Vec<String>),
+    Array(Box<Schema>),
+    Map(Box<Schema>),
+    Union(Vec<Schema>),
+    Record(Vec<(String, Schema)>),
+    // A named type, by its index in `Names::types`. Named types can refer to themselves.
+    Named(usize),
+}
+
+#[derive(Default)]
+struct Names {
+    types: Vec<Schema>,
+    index: HashMap<String, usize>,
+}
+
+impl Names {
+    fn define(&mut self, obj: &Map<String, Value>, namespace: &str) -> (usize, String) {
+        let name = obj.get("name").and_then(Value::as_str).unwrap_or_default();
+        let namespace = match (name.rfind('.'), obj.get("namespace").and_then(Value::as_str)) {
+            (Some(i), _) => &name[..i],
+            (None, Some(ns)) => ns,
+            (None, None) => namespace,
+        };
+        let short = name.rsplit('.').next().unwrap_or_default();
+        let full = if namespace.is_empty() {
+            short.to_string()
+        } else {
+            format!("{}.{}", namespace, short)
+        };
+        let ix = self.types.len();
+        self.types.push(Schema::Null);
+        self.index.entry(short.to_string()).or_insert(ix);
+        self.index.insert(full, ix);
+        (ix, namespace.to_string())
+    }
+
+    fn parse(&mut self, v: &Value, namespace: &str) -> std::result::Result<Schema, String> {
+        let obj = match v {
+            Value::String(name) => {
+                return Ok(match name.as_str() {
+                    "null" => Schema::Null,
+                    "boolean" => Schema::Boolean,
+                    "int" => Schema::Int,
+                    "long" => Schema::Long,
+                    "float" => Schema::Float,
+                    "double" => Schema::Double,
+                    "bytes" => Schema::Bytes,
+                    "string" => Schema::String,
+                    name => {
+                        let full = format!("{}.{}", namespace, name);
+                        match self.index.get(name).or_else(|| self.index.get(&full)) {
+                            Some(ix) => Schema::Named(*ix),
+                            None => return Err(format!("unknown type {}", name)),
+                        }
+                    }
+                })
+            }
+            Value::Array(branches) => {
+                return Ok(Schema::Union(
+                    branches
+                        .iter()
+                        .map(|b| self.parse(b, namespace))
+                        .collect::<std::result::Result<_, _>>()?,
+                ))
+            }
+            Value::Object(obj) => obj,
+            v => return Err(format!("invalid schema {}", v)),
+        };
+        let decimal = obj.get("logicalType").and_then(Value::as_str) == Some("decimal");
+        let scale = obj.get("scale").and_then(Value::as_u64).unwrap_or(0) as u32;
+        let ty = match obj.get("type") {
+            Some(Value::String(ty)) => ty.as_str(),
+            Some(ty) => return self.parse(ty, namespace),
+            None => return Err("schema without a type".to_string()),
+        };
+        match ty {
+            "record" | "error" => {
+                let (ix, namespace) = self.define(obj, namespace);
+                let mut fields = Vec::new();
+                for field in obj.get("fields").and_then(Value::as_array).into_iter().flatten() {
+                    let name = field.get("name").and_then(Value::as_str).unwrap_or_default();
+                    let ty = field.get("type").unwrap_or(&Value::Null);
+                    fields.push((name.to_string(), self.parse(ty, &namespace)?));
+                }
+                self.types[ix] = Schema::Record(fields);
+                Ok(Schema::Named(ix))
+            }
+            "enum" => {
+                let (ix, _) = self.define(obj, namespace);
+                let symbols = obj.get("symbols").and_then(Value::as_array).into_iter().flatten();
+                self.types[ix] = Schema::Enum(
+                    symbols
+                        .map(|s| s.as_str().unwrap_or_default().to_string())
+                        .collect(),
+                );
+                Ok(Schema::Named(ix))
+            }
+            "fixed" => {
+                let (ix, _) = self.define(obj, namespace);
+                let size = obj.get("size").and_then(Value::as_u64).unwrap_or(0) as usize;
+                self.types[ix] = if decimal {
+                    Schema::Decimal(scale, Some(size))
+                } else {
+                    Schema::Fixed(size)
+                };
+                Ok(Schema::Named(ix))
+            }
+            "array" => Ok(Schema::Array(Box::new(
+                self.parse(obj.get("items").unwrap_or(&Value::Null), namespace)?,
+            ))),
+            "map" => Ok(Schema::Map(Box::new(
+                self.parse(obj.get("values").unwrap_or(&Value::Null), namespace)?,
+            ))),
+            "bytes" if decimal => Ok(Schema::Decimal(scale, None)),
+            // Other logical types are read as their underlying type.
+            ty => self.parse(&Value::String(ty.to_string()), namespace),
+        }
+    }
+
+    fn resolve<'a>(&'a self, mut schema: &'a Schema) -> &'a Schema {
+        while let Schema::Named(ix) = schema {
+            schema = &self.types[*ix];
+        }
+        schema
+    }
+
+    fn decode(&self, schema: &Schema, cur: &mut Cursor) -> std::result::Result<Value, String> {
+        Ok(match self.resolve(schema) {
+            Schema::Null => Value::Null,
+            Schema::Boolean => Value::Bool(cur.take(1)?[0] != 0),
+            Schema::Int | Schema::Long => Value::from(cur.long()?),
+            Schema::Float => {
+                let f = f32::from_le_bytes(cur.take(4)?.try_into().unwrap());
+                // Go through the shortest representation of the f32, not its exact f64 value.
+                float(f.to_string().parse().unwrap_or(f as f64))
+            }
+            Schema::Double => float(f64::from_le_bytes(cur.take(8)?.try_into().unwrap())),
+            Schema::Bytes => {
+                let len = cur.len()?;
+                Value::String(STANDARD.encode(cur.take(len)?))
+            }
+            Schema::String => {
+                let len = cur.len()?;
+                Value::String(String::from_utf8_lossy(cur.take(len)?).into_owned())
+            }
+            Schema::Fixed(size) => Value::String(STANDARD.encode(cur.take(*size)?)),
+            Schema::Decimal(scale, size) => {
+                let len = match size {
+                    Some(size) => *size,
+                    None => cur.len()?,
+                };
+                let bytes = cur.take(len)?;
+                match decimal_text(bytes, *scale) {
+                    Some(text) => Value::String(text),
+                    None => Value::String(STANDARD.encode(bytes)),
+                }
+            }
+            Schema::Enum(symbols) => {
+                let ix = cur.long()?;
+                match usize::try_from(ix).ok().and_then(|ix| symbols.get(ix)) {
+                    Some(symbol) => Value::String(symbol.clone()),
+                    None => return Err(format!("enum index {} out of range", ix)),
+                }
+            }
+            Schema::Array(items) => {
+                let mut res = Vec::new();
+                cur.blocks(false, |cur| {
+                    res.push(self.decode(items, cur)?);
+                    Ok(())
+                })?;
+                Value::Array(res)
+            }
+            Schema::Map(values) => {
+                let mut res = Map::new();
+                cur.blocks(false, |cur| {
+                    let len = cur.len()?;
+                    let key = String::from_utf8_lossy(cur.take(len)?).into_owned();
+                    res.insert(key, self.decode(values, cur)?);
+                    Ok(())
+                })?;
+                Value::Object(res)
+            }
+            Schema::Union(branches) => self.decode(cur.branch(branches)?, cur)?,
+            Schema::Record(fields) => {
+                let mut res = Map::new();
+                for (name, schema) in fields {
+                    res.insert(name.clone(), self.decode(schema, cur)?);
+                }
+                Value::Object(res)
+            }
+            Schema::Named(_) => unreachable!(),
+        })
+    }
+
+    fn skip(&self, schema: &Schema, cur: &mut Cursor) -> std::result::Result<(), String> {
+        match self.resolve(schema) {
+            Schema::Null => {}
+            Schema::Boolean => {
+                cur.take(1)?;
+            }
+            Schema::Int | Schema::Long | Schema::Enum(_) => {
+                cur.long()?;
+            }
+            Schema::Float => {
+                cur.take(4)?;
+            }
+            Schema::Double => {
+                cur.take(8)?;
+            }
+            Schema::Bytes | Schema::String | Schema::Decimal(_, None) => {
+                let len = cur.len()?;
+                cur.take(len)?;
+            }
+            Schema::Fixed(size) | Schema::Decimal(_, Some(size)) => {
+                cur.take(*size)?;
+            }
+            Schema::Array(items) => cur.blocks(true, |cur| self.skip(items, cur))?,
+            Schema::Map(values) => cur.blocks(true, |cur| {
+                let len = cur.len()?;
+                cur.take(len)?;
+                self.skip(values, cur)
+            })?,
+            Schema::Union(branches) => self.skip(cur.branch(branches)?, cur)?,
+            Schema::Record(fields) => {
+                for (_, schema) in fields {
+                    self.skip(schema, cur)?;
+                }
+            }
+            Schema::Named(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// The decimal value of a big-endian two's complement integer, with `scale` digits after the
+/// point. None if the value is too large to convert.
+fn decimal_text(bytes: &[u8], scale: u32) -> Option<String> {
+    if bytes.is_empty() || bytes.len() > 16 {
+        return None;
+    }
+    let fill = if bytes[0] & 0x80 != 0 { 0xff } else { 0 };
+    let mut buf = [fill; 16];
+    buf[16 - bytes.len()..].copy_from_slice(bytes);
+    let n = i128::from_be_bytes(buf);
+    let scale = scale as usize;
+    let mut digits = n.unsigned_abs().to_string();
+    if scale > 0 {
+        if digits.len() <= scale {
+            digits.insert_str(0, &"0".repeat(scale + 1 - digits.len()));
+        }
+        digits.insert(digits.len() - scale, '.');
+    }
+    if n < 0 {
+        digits.insert(0, '-');
+    }
+    Some(digits)
+}
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> std::result::Result<&'a [u8], String> {
+        match self.buf.get(self.pos..self.pos.saturating_add(n)) {
+            Some(bytes) => {
+                self.pos += n;
+                Ok(bytes)
+            }
+            None => Err("truncated record".to_string()),
+        }
+    }
+    fn long(&mut self) -> std::result::Result<i64, String> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.take(1)?[0];
+            n |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return Ok((n >> 1) as i64 ^ -((n & 1) as i64));
+            }
+        }
+        Err("invalid variable-length integer".to_string())
+    }
+    fn len(&mut self) -> std::result::Result<usize, String> {
+        let n = self.long()?;
+        usize::try_from(n).map_err(|_| format!("negative length {}", n))
+    }
+    fn branch<'s>(&mut self, branches: &'s [Schema]) -> std::result::Result<&'s Schema, String> {
+        let ix = self.long()?;
+        usize::try_from(ix)
+            .ok()
+            .and_then(|ix| branches.get(ix))
+            .ok_or_else(|| format!("union branch {} out of range", ix))
+    }
+    /// Calls `item` for each item of an array or map. Blocks written with their size in bytes are
+    /// jumped over whole if `skip` is set.
+    fn blocks(
+        &mut self,
+        skip: bool,
+        mut item: impl FnMut(&mut Self) -> std::result::Result<(), String>,
+    ) -> std::result::Result<(), String> {
+        loop {
+            let mut count = self.long()?;
+            if count == 0 {
+                return Ok(());
+            }
+            if count < 0 {
+                count = -count;
+                let size = self.len()?;
+                if skip {
+                    self.take(size)?;
+                    continue;
+                }
+            }
+            for _ in 0..count {
+                item(self)?;
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Codec {
+    Null,
+    Deflate,
+}
+
+struct Container {
+    names: Names,
+    fields: Vec<(String, Schema)>,
+    codec: Codec,
+    sync: [u8; 16],
+}
+
+/// Reads a zig-zag encoded long from the file itself, or None at the end of the file.
+fn read_long(r: &mut impl Read) -> std::result::Result<Option<i64>, String> {
+    let mut n = 0u64;
+    for (i, shift) in (0..64).step_by(7).enumerate() {
+        let mut b = [0u8];
+        match r.read_exact(&mut b) {
+            Ok(()) => {}
+            Err(e) if i == 0 && e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(read_error(e)),
+        }
+        n |= ((b[0] & 0x7f) as u64) << shift;
+        if b[0] & 0x80 == 0 {
+            return Ok(Some((n >> 1) as i64 ^ -((n & 1) as i64)));
+        }
+    }
+    Err("invalid variable-length integer".to_string())
+}
+
+fn read_bytes(r: &mut impl Read, len: usize) -> std::result::Result<Vec<u8>, String> {
+    let mut buf = Vec::new();
+    r.take(len as u64).read_to_end(&mut buf).map_err(read_error)?;
+    if buf.len() < len {
+        return Err("unexpected end of file".to_string());
+    }
+    Ok(buf)
+}
+
+fn read_error(e: std::io::Error) -> String {
+    if e.kind() == ErrorKind::UnexpectedEof {
+        "unexpected end of file".to_string()
+    } else {
+        e.to_string()
+    }
+}
+
+fn read_len(r: &mut impl Read) -> std::result::Result<usize, String> {
+    match read_long(r)? {
+        Some(n) => usize::try_from(n).map_err(|_| format!("negative length {}", n)),
+        None => Err("unexpected end of file".to_string()),
+    }
+}
+
+fn read_container(r: &mut impl Read) -> std::result::Result<Container, String> {
+    let mut magic = [0u8; 4];
+    if r.read_exact(&mut magic).is_err() || magic != MAGIC {
+        return Err("not an Avro object container file".to_string());
+    }
+    let mut meta = HashMap::new();
+    loop {
+        let count = match read_long(r)? {
+            Some(0) => break,
+            Some(n) if n < 0 => {
+                read_len(r)?;
+                -n
+            }
+            Some(n) => n,
+            None => return Err("unexpected end of file".to_string()),
+        };
+        for _ in 0..count {
+            let len = read_len(r)?;
+            let key = String::from_utf8_lossy(&read_bytes(r, len)?).into_owned();
+            let len = read_len(r)?;
+            meta.insert(key, read_bytes(r, len)?);
+        }
+    }
+    let mut sync = [0u8; 16];
+    r.read_exact(&mut sync).map_err(read_error)?;
+
+    let codec = match meta.get("avro.codec").map(|c| c.as_slice()) {
+        None | Some(b"null") => Codec::Null,
+        Some(b"deflate") => Codec::Deflate,
+        Some(codec) => {
+            return Err(format!("unsupported codec {}", String::from_utf8_lossy(codec)));
+        }
+    };
+    let schema = meta.get("avro.schema").ok_or("missing schema")?;
+    let schema: Value = serde_json::from_slice(schema).map_err(|e| format!("invalid schema: {}", e))?;
+    let mut names = Names::default();
+    let root = names.parse(&schema, "")?;
+    let fields = match names.resolve(&root) {
+        Schema::Record(fields) => fields.clone(),
+        _ => vec![("value".to_string(), root)],
+    };
+    Ok(Container {
+        names,
+        fields,
+        codec,
+        sync,
+    })
+}
+
+pub struct AvroReader {
+    input: BufReader<Box<dyn Read + Send>>,
+    name: Str<'static>,
+    // Whether to produce a line of field names before the first record, for `-H`.
+    header: bool,
+    container: Option<Container>,
+    // The current block, once decompressed, and the records left in it.
+    block: Vec<u8>,
+    pos: usize,
+    remaining: i64,
+    field_set: FieldSet,
+    state: ReaderState,
+    started: bool,
+    check_utf8: bool,
+}
+
+impl AvroReader {
+    pub fn new(input: Box<dyn Read + Send>, name: &str, header: bool, check_utf8: bool) -> Self {
+        AvroReader {
+            input: BufReader::new(input),
+            name: Str::from(name.to_string()).unmoor(),
+            header,
+            container: None,
+            block: Vec::new(),
+            pos: 0,
+            remaining: 0,
+            field_set: FieldSet::all(),
+            state: ReaderState::OK,
+            started: false,
+            check_utf8,
+        }
+    }
+
+    // Moves on to the next block with records in it. Returns false at the end of the file.
+    fn next_block(&mut self) -> std::result::Result<bool, String> {
+        let container = self.container.as_ref().unwrap();
+        while self.remaining == 0 {
+            let count = match read_long(&mut self.input)? {
+                Some(count) => count,
+                None => return Ok(false),
+            };
+            let size = read_len(&mut self.input)?;
+            let data = read_bytes(&mut self.input, size)?;
+            let mut sync = [0u8; 16];
+            self.input.read_exact(&mut sync).map_err(read_error)?;
+            if sync != container.sync {
+                return Err("corrupt block: sync marker does not match".to_string());
+            }
+            self.block = match container.codec {
+                Codec::Null => data,
+                Codec::Deflate => {
+                    let mut out = Vec::new();
+                    flate2::read::DeflateDecoder::new(data.as_slice())
+                        .read_to_end(&mut out)
+                        .map_err(|e| format!("corrupt block: {}", e))?;
+                    out
+                }
+            };
+            self.pos = 0;
+            self.remaining = count.max(0);
+        }
+        Ok(true)
+    }
+
+    fn read_record(&mut self, line: &mut Line) -> std::result::Result<(), String> {
+        if self.container.is_none() {
+            self.container = Some(read_container(&mut self.input)?);
+        }
+        if self.header {
+            self.header = false;
+            let container = self.container.as_ref().unwrap();
+            let names: Vec<Str<'static>> = container
+                .fields
+                .iter()
+                .map(|(name, _)| Str::from(name.clone()).unmoor())
+                .collect();
+            let raw = join_csv(&names);
+            line.fill(raw, names);
+            return Ok(());
+        }
+        if !self.next_block()? {
+            line.clear();
+            self.state = ReaderState::Eof;
+            return Ok(());
+        }
+        let container = self.container.as_ref().unwrap();
+        let mut cur = Cursor {
+            buf: &self.block,
+            pos: self.pos,
+        };
+        let whole = self.field_set.get(0);
+        let mut fields = Vec::with_capacity(container.fields.len());
+        for (i, (_, schema)) in container.fields.iter().enumerate() {
+            if whole || self.field_set.get(i + 1) {
+                let value = container.names.decode(schema, &mut cur)?;
+                fields.push(Str::from(field_text(value)).unmoor());
+            } else {
+                container.names.skip(schema, &mut cur)?;
+                fields.push(Str::default());
+            }
+        }
+        self.pos = cur.pos;
+        self.remaining -= 1;
+        let raw = if whole {
+            join_csv(&fields)
+        } else {
+            Str::default()
+        };
+        line.fill(raw, fields);
+        Ok(())
+    }
+}
+
+fn join_csv(fields: &[Str<'static>]) -> Str<'static> {
+    let escaped: Vec<Str<'static>> = fields.iter().map(escape_csv).collect();
+    Str::from(",").join_slice(&escaped[..])
+}
+
+impl LineReader for AvroReader {
+    type Line = Line;
+    fn filename(&self) -> Str<'static> {
+        self.name.clone()
+    }
+    fn check_utf8(&self) -> bool {
+        self.check_utf8
+    }
+    fn read_line(&mut self, pat: &Str, rc: &mut RegexCache) -> Result<(bool, Line)> {
+        let mut line = Line::default();
+        let changed = self.read_line_reuse(pat, rc, &mut line)?;
+        Ok((changed, line))
+    }
+    fn read_line_reuse<'a, 'b: 'a>(
+        &'b mut self,
+        _pat: &Str,
+        _rc: &mut RegexCache,
+        old: &'a mut Line,
+    ) -> Result<bool> {
+        let changed = !self.started;
+        self.started = true;
+        if self.state != ReaderState::OK {
+            old.clear();
+            return Ok(changed);
+        }
+        if let Err(e) = self.read_record(old) {
+            self.state = ReaderState::Error;
+            return err!("{}: invalid Avro file: {}", self.name, e);
+        }
+        Ok(changed)
+    }
+    fn read_state(&self) -> i64 {
+        self.state as i64
+    }
+    fn next_file(&mut self) -> Result<bool> {
+        self.state = ReaderState::Eof;
+        Ok(false)
+    }
+    fn set_used_fields(&mut self, field_set: &FieldSet) {
+        self.field_set = field_set.clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::splitter::Line as _;
+
+    fn long(n: i64, out: &mut Vec<u8>) {
+        let mut n = ((n << 1) ^ (n >> 63)) as u64;
+        while n >= 0x80 {
+            out.push((n as u8 & 0x7f) | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+
+    fn bytes(b: &[u8], out: &mut Vec<u8>) {
+        long(b.len() as i64, out);
+        out.extend_from_slice(b);
+    }
+
+    fn container(schema: &str, codec: &str, records: &[Vec<u8>]) -> Vec<u8> {
+        let sync = [7u8; 16];
+        let mut out = MAGIC.to_vec();
+        long(2, &mut out);
+        bytes(b"avro.schema", &mut out);
+        bytes(schema.as_bytes(), &mut out);
+        bytes(b"avro.codec", &mut out);
+        bytes(codec.as_bytes(), &mut out);
+        long(0, &mut out);
+        out.extend_from_slice(&sync);
+        // One block per record, to cover reading across blocks.
+        for rec in records {
+            let data = if codec == "deflate" {
+                use std::io::Write;
+                let mut enc =
+                    flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+                enc.write_all(rec).unwrap();
+                enc.finish().unwrap()
+            } else {
+                rec.clone()
+            };
+            long(1, &mut out);
+            bytes(&data, &mut out);
+            out.extend_from_slice(&sync);
+        }
+        out
+    }
+
+    const SCHEMA: &str = r#"{"type": "record", "name": "Order", "namespace": "shop", "fields": [
+        {"name": "id", "type": "long"},
+        {"name": "customer", "type": ["null", "string"]},
+        {"name": "items", "type": {"type": "array", "items": "string"}},
+        {"name": "total", "type": {"type": "bytes", "logicalType": "decimal", "precision": 6, "scale": 2}},
+        {"name": "status", "type": {"type": "enum", "name": "Status", "symbols": ["NEW", "PAID"]}},
+        {"name": "paid", "type": "boolean"}
+    ]}"#;
+
+    fn order(id: i64, customer: Option<&str>, items: &[&str], cents: i16, paid: bool) -> Vec<u8> {
+        let mut rec = Vec::new();
+        long(id, &mut rec);
+        match customer {
+            Some(c) => {
+                long(1, &mut rec);
+                bytes(c.as_bytes(), &mut rec);
+            }
+            None => long(0, &mut rec),
+        }
+        if !items.is_empty() {
+            // A block with its size in bytes, which skipping jumps over.
+            let mut block = Vec::new();
+            for item in items {
+                bytes(item.as_bytes(), &mut block);
+            }
+            long(-(items.len() as i64), &mut rec);
+            long(block.len() as i64, &mut rec);
+            rec.extend_from_slice(&block);
+        }
+        long(0, &mut rec);
+        bytes(&cents.to_be_bytes(), &mut rec);
+        long(paid as i64, &mut rec);
+        rec.push(paid as u8);
+        rec
+    }
+
+    fn read_all(data: Vec<u8>, header: bool, used: Option<FieldSet>) -> Vec<(String, Vec<String>)> {
+        let mut reader = AvroReader::new(Box::new(std::io::Cursor::new(data)), "orders.avro", header, false);
+        if let Some(used) = used {
+            reader.set_used_fields(&used);
+        }
+        let mut rc = RegexCache::default();
+        let pat = Str::default();
+        let mut res = Vec::new();
+        loop {
+            let (_, mut line) = reader.read_line(&pat, &mut rc).unwrap();
+            if reader.read_state() != ReaderState::OK as i64 {
+                break;
+            }
+            let nf = line.nf(&pat, &mut rc).unwrap();
+            let fields = (1..=nf as i64)
+                .map(|i| line.get_col(i, &pat, &pat, &mut rc).unwrap().to_string())
+                .collect();
+            res.push((line.get_col(0, &pat, &pat, &mut rc).unwrap().to_string(), fields));
+        }
+        res
+    }
+
+    #[test]
+    fn test_avro_reader() {
+        let records = vec![
+            order(1, Some("ann, jr"), &["pen", "ink"], 1250, true),
+            order(2, None, &[], -5, false),
+        ];
+        for codec in ["null", "deflate"] {
+            let rows = read_all(container(SCHEMA, codec, &records), true, None);
+            assert_eq!(rows.len(), 3);
+            assert_eq!(rows[0].0, "id,customer,items,total,status,paid");
+            assert_eq!(rows[1].0, r#"1,"ann, jr","[""pen"",""ink""]",12.50,PAID,1"#);
+            assert_eq!(rows[2].1, vec!["2", "", "[]", "-0.05", "NEW", "0"]);
+        }
+
+        // Only the fields in the field set are decoded, but NF stays the same.
+        let mut used = FieldSet::singleton(1);
+        used.set(5);
+        let rows = read_all(container(SCHEMA, "null", &records), false, Some(used));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], (String::new(), vec!["1", "", "", "", "PAID", ""].into_iter().map(String::from).collect()));
+    }
+
+    #[test]
+    fn test_avro_schemas() {
+        // A recursive record, and a top-level schema that is not a record.
+        let list = r#"{"type": "record", "name": "Node", "fields": [
+            {"name": "v", "type": "int"},
+            {"name": "next", "type": ["null", "Node"]}
+        ]}"#;
+        let mut rec = Vec::new();
+        long(1, &mut rec);
+        long(1, &mut rec);
+        long(2, &mut rec);
+        long(0, &mut rec);
+        let rows = read_all(container(list, "null", &[rec]), false, None);
+        assert_eq!(rows[0].1, vec!["1", r#"{"next":null,"v":2}"#]);
+
+        let mut rec = Vec::new();
+        rec.extend_from_slice(&0.1f32.to_le_bytes());
+        let rows = read_all(container(r#""float""#, "null", &[rec]), true, None);
+        assert_eq!(rows[0].0, "value");
+        assert_eq!(rows[1].0, "0.1");
+    }
+
+    #[test]
+    fn test_avro_errors() {
+        let mut reader = AvroReader::new(Box::new(&b"a,b\n"[..]), "x.csv", false, false);
+        let mut rc = RegexCache::default();
+        let err = reader.read_line(&Str::default(), &mut rc).unwrap_err();
+        assert!(err.0.ends_with("x.csv: invalid Avro file: not an Avro object container file"), "{}", err.0);
+
+        let mut data = container(SCHEMA, "snappy", &[]);
+        let mut reader = AvroReader::new(Box::new(std::io::Cursor::new(data.clone())), "x", false, false);
+        let err = reader.read_line(&Str::default(), &mut rc).unwrap_err();
+        assert!(err.0.ends_with("unsupported codec snappy"), "{}", err.0);
+
+        data = container(SCHEMA, "null", &[order(1, None, &[], 0, false)]);
+        data.truncate(data.len() - 20);
+        let mut reader = AvroReader::new(Box::new(std::io::Cursor::new(data)), "x", false, false);
+        let err = reader.read_line(&Str::default(), &mut rc).unwrap_err();
+        assert!(err.0.ends_with("unexpected end of file"), "{}", err.0);
+    }
+}
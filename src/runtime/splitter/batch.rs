@@ -20,15 +20,18 @@
 /// auxiliary vector at the cost of 2x steady-state memory usage, or more complex offset management
 /// in the `Offsets` type.
 /// NB the changes to fix this issue will now be in the chunk module.
+use std::cell::Cell;
 use std::io::Read;
 use std::mem;
 use std::str;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 use lazy_static::lazy_static;
-use regex::{bytes, bytes::Regex};
+use regex::bytes;
 
 use crate::common::{CancelSignal, ExecutionStrategy, Result};
 use crate::pushdown::FieldSet;
+use crate::runtime::regex_engine::Regex;
 use crate::runtime::{
     str_impl::{Buf, Str, UniqueBuf},
     Int, RegexCache,
@@ -65,6 +68,9 @@ impl LineReader for CSVReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
     fn wait(&self) -> bool {
         self.prod.wait()
     }
+    fn stop_waiting(&self) {
+        self.prod.stop_waiting()
+    }
     fn check_utf8(&self) -> bool {
         self.check_utf8
     }
@@ -231,6 +237,7 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
         line: &'a mut Line,
     ) -> Result</*file changed*/ bool> {
         line.clear();
+        line.ifmt = Some(self.ifmt);
         let mut changed = false;
         if self.cur_chunk.off.rel.start == self.cur_chunk.off.rel.fields.len() {
             // NB: see comment on corresponding condition in ByteReader.
@@ -243,6 +250,12 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> CSVReader<P> {
                 debug_assert!(!changed);
                 return Ok(false);
             }
+            // An empty chunk (from an empty input, or one that ended right after a read) holds no
+            // records, as in ByteReader::consume_line.
+            if self.buf_len == 0 {
+                self.last_len = 0;
+                return Ok(changed);
+            }
         }
 
         let (prev_ix, st) = {
@@ -302,6 +315,11 @@ pub struct Line {
     len: usize,
     fields: Vec<Str<'static>>,
     partial: Str<'static>,
+    // Whether a field has been assigned since `raw` was last rebuilt from `fields`.
+    diverged: bool,
+    // The format the line was read in, used to split it again when $0 is assigned. None for
+    // records filled in by other readers.
+    ifmt: Option<InputFormat>,
 }
 
 impl Line {
@@ -340,10 +358,19 @@ impl<'a> super::Line<'a> for Line {
         &mut self,
         col: super::Int,
         _pat: &Str,
-        _ofs: &Str,
+        ofs: &Str,
         _rc: &mut super::RegexCache,
     ) -> Result<Str<'a>> {
         if col == 0 {
+            if self.diverged {
+                let ofs = ofs.clone().unmoor();
+                self.raw = match REBUILD_ESCAPE.load(Ordering::Relaxed) {
+                    1 => ofs.join(self.fields.iter().map(escape_csv)),
+                    2 => ofs.join(self.fields.iter().map(escape_tsv)),
+                    _ => ofs.join_slice(&self.fields[..]),
+                };
+                self.diverged = false;
+            }
             return Ok(self.raw.clone().upcast());
         }
         if col < 0 {
@@ -357,14 +384,35 @@ impl<'a> super::Line<'a> for Line {
             .upcast())
     }
 
-    // Setting columns for CSV doesn't work. We refuse it outright.
+    // Assigning a field rebuilds $0 from the fields, joined with OFS (and escaped for CSV or TSV
+    // output), the next time it is read. Assigning $0 splits it into fields again.
     fn set_col(
         &mut self,
-        _col: super::Int,
-        _s: &Str<'a>,
+        col: super::Int,
+        s: &Str<'a>,
         _pat: &Str,
         _rc: &mut super::RegexCache,
     ) -> Result<()> {
+        if col == 0 {
+            let s = s.clone().unmoor();
+            match self.ifmt {
+                Some(ifmt) => self.split_record(ifmt, s),
+                None => {
+                    self.raw = s;
+                    self.diverged = false;
+                }
+            }
+            return Ok(());
+        }
+        if col < 0 {
+            return err!("attempt to access field {}; field must be nonnegative", col);
+        }
+        let col = col as usize - 1;
+        if col >= self.fields.len() {
+            self.fields.resize_with(col + 1, Str::default);
+        }
+        self.fields[col] = s.clone().unmoor();
+        self.diverged = true;
         Ok(())
     }
 }
@@ -383,6 +431,46 @@ impl Line {
         self.partial = Str::default();
         self.raw = Str::default();
         self.len = 0;
+        self.diverged = false;
+    }
+    // Split `s`, a single record in `ifmt`, into the fields of the line, as if it had been read.
+    fn split_record(&mut self, ifmt: InputFormat, s: Str<'static>) {
+        let len = s.len();
+        // The index kernels work on whole blocks, so leave zeroed room past the end of the
+        // record, as the chunk readers do.
+        let mut data = UniqueBuf::new(len + 128);
+        s.with_bytes(|bs| data.as_mut_bytes()[..len].copy_from_slice(bs));
+        let buf = data.into_buf();
+        let mut off = Offsets::default();
+        unsafe { get_find_indexes(ifmt)(buf.as_bytes(), &mut off, 0, 0) };
+        off.rel.fields.retain(|ix| (*ix as usize) < len);
+        self.clear();
+        let mut stepper = Stepper {
+            ifmt,
+            buf: &buf,
+            buf_len: len,
+            off: &mut off,
+            prev_ix: 0,
+            st: State::Init,
+            line: self,
+            field_set: FieldSet::all(),
+        };
+        unsafe { stepper.step() };
+        if stepper.st != State::Done {
+            self.promote();
+        }
+        self.raw = s;
+        self.len = len;
+    }
+
+    /// Replaces the line with a record that was split elsewhere, as by a reader for a binary
+    /// format, with `raw` as `$0`.
+    pub fn fill(&mut self, raw: Str<'static>, fields: impl IntoIterator<Item = Str<'static>>) {
+        self.clear();
+        self.ifmt = None;
+        self.len = raw.len();
+        self.raw = raw;
+        self.fields.extend(fields);
     }
 }
 
@@ -470,6 +558,10 @@ impl<'a> Stepper<'a> {
                     // First, skip over any unused fields.
                     let cur_field = self.line.fields.len() + 1;
                     if !self.field_set.get(cur_field) {
+                        // Skipped fields still take up a slot if a later field or NF needs it;
+                        // otherwise the rest of the record is scanned without recording anything.
+                        let keep = self.field_set.counts_fields()
+                            || (cur_field as u32) < self.field_set.max_value();
                         loop {
                             if cur == self.off.rel.fields.len() {
                                 self.prev_ix = bs.len() + 1;
@@ -481,14 +573,18 @@ impl<'a> Stepper<'a> {
                                 b'\r' | b'"' | b'\\' => {}
                                 b'\n' => {
                                     self.prev_ix = ix + 1;
-                                    self.promote_null();
+                                    if keep {
+                                        self.promote_null();
+                                    }
                                     self.st = State::Done;
                                     return self.get(line_start, ix, cur);
                                 }
                                 _x => {
                                     debug_assert_eq!(_x, sep);
                                     self.prev_ix = ix + 1;
-                                    self.promote_null();
+                                    if keep {
+                                        self.promote_null();
+                                    }
                                     continue 'init;
                                 }
                             }
@@ -601,7 +697,7 @@ impl<'a> Stepper<'a> {
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 pub enum InputFormat {
     CSV,
     TSV,
@@ -736,7 +832,7 @@ pub fn get_find_indexes_bytes() -> BytesIndexKernel {
     }
 }
 
-pub type WhitespaceIndexKernel = unsafe fn(&[u8], &mut WhitespaceOffsets, u64) -> u64;
+pub type WhitespaceIndexKernel = unsafe fn(&[u8], &mut WhitespaceOffsets, u64, u8) -> u64;
 
 #[cfg(target_arch = "x86_64")]
 #[target_feature(enable = "avx2")]
@@ -744,8 +840,9 @@ unsafe fn find_indexes_ascii_whitespace_avx2(
     buf: &[u8],
     offsets: &mut WhitespaceOffsets,
     start_ws: u64,
+    record_sep: u8,
 ) -> u64 {
-    generic::find_indexes_ascii_whitespace::<avx2::Impl>(buf, offsets, start_ws)
+    generic::find_indexes_ascii_whitespace::<avx2::Impl>(buf, offsets, start_ws, record_sep)
 }
 
 #[cfg(target_arch = "x86_64")]
@@ -754,8 +851,9 @@ unsafe fn find_indexes_ascii_whitespace_sse2(
     buf: &[u8],
     offsets: &mut WhitespaceOffsets,
     start_ws: u64,
+    record_sep: u8,
 ) -> u64 {
-    generic::find_indexes_ascii_whitespace::<sse2::Impl>(buf, offsets, start_ws)
+    generic::find_indexes_ascii_whitespace::<sse2::Impl>(buf, offsets, start_ws, record_sep)
 }
 
 pub fn get_find_indexes_ascii_whitespace() -> WhitespaceIndexKernel {
@@ -789,7 +887,76 @@ lazy_static! {
         bytes::RegexSet::new([r#"""#, r#"\t"#, r#"\n"#, ","]).unwrap();
 }
 
+/// How `escape_csv` quotes fields, as set by `OCSVQUOTE`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CsvQuoting {
+    // Quote every field, not just the ones that need it.
+    all: bool,
+    quote: u8,
+    // Escape quotes (and backslashes) inside a field with a backslash, rather than doubling them.
+    backslash: bool,
+}
+
+impl Default for CsvQuoting {
+    fn default() -> CsvQuoting {
+        CsvQuoting {
+            all: false,
+            quote: b'"',
+            backslash: false,
+        }
+    }
+}
+
+impl CsvQuoting {
+    /// Parse a list of space- or comma-separated settings: `minimal` or `all`, `quote=C` and
+    /// `escape=double` or `escape=backslash`. Settings that are not mentioned keep their defaults.
+    pub(crate) fn parse(spec: &str) -> Result<CsvQuoting> {
+        let mut res = CsvQuoting::default();
+        for setting in spec.split([' ', ',']).filter(|s| !s.is_empty()) {
+            match setting.split_once('=') {
+                None if setting == "minimal" => res.all = false,
+                None if setting == "all" => res.all = true,
+                Some(("quote", q)) if q.len() == 1 && !matches!(q, "," | "\t" | "\n") => {
+                    res.quote = q.as_bytes()[0]
+                }
+                Some(("escape", "double")) => res.backslash = false,
+                Some(("escape", "backslash")) => res.backslash = true,
+                _ => return err!("invalid OCSVQUOTE setting {:?}", setting),
+            }
+        }
+        Ok(res)
+    }
+}
+
+thread_local! {
+    // Parsed once per assignment to OCSVQUOTE rather than once per field escaped.
+    static CSV_QUOTING: Cell<CsvQuoting> = Cell::new(CsvQuoting::default());
+}
+
+// How fields are escaped when $0 is rebuilt after a field assignment: 1 for CSV and 2 for TSV
+// (as in `select`), 0 for not at all. Set once, from the output format.
+static REBUILD_ESCAPE: AtomicU8 = AtomicU8::new(0);
+
+/// Escape fields for `-o csv` or `-o tsv` when `$0` is rebuilt from them.
+pub(crate) fn set_output_format(fmt: Option<InputFormat>) {
+    let code = match fmt {
+        Some(InputFormat::CSV) => 1,
+        Some(InputFormat::TSV) => 2,
+        None => 0,
+    };
+    REBUILD_ESCAPE.store(code, Ordering::Relaxed);
+}
+
+/// Set by assignments to `OCSVQUOTE`.
+pub(crate) fn set_csv_quoting(q: CsvQuoting) {
+    CSV_QUOTING.with(|c| c.set(q))
+}
+
 pub fn escape_csv<'a>(s: &Str<'a>) -> Str<'a> {
+    let quoting = CSV_QUOTING.with(|c| c.get());
+    if quoting != CsvQuoting::default() {
+        return s.with_bytes(|bs| escape_csv_with(bs, quoting));
+    }
     let bs = unsafe { &*s.get_bytes() };
     let matches = NEEDS_ESCAPE_CSV.matches(bs);
     if !matches.matched_any() {
@@ -811,6 +978,32 @@ pub fn escape_csv<'a>(s: &Str<'a>) -> Str<'a> {
     Str::concat(Str::concat(quote.clone(), cur), quote)
 }
 
+// escape_csv under a policy other than the default one.
+fn escape_csv_with<'a>(bs: &[u8], q: CsvQuoting) -> Str<'a> {
+    let needs_quotes =
+        q.all || bs.iter().any(|b| matches!(*b, b',' | b'\t' | b'\n') || *b == q.quote);
+    let mut res = Vec::with_capacity(bs.len() + 2);
+    if needs_quotes {
+        res.push(q.quote);
+    }
+    for b in bs.iter().cloned() {
+        match b {
+            b'\t' => res.extend_from_slice(b"\\t"),
+            b'\n' => res.extend_from_slice(b"\\n"),
+            b'\\' if q.backslash => res.extend_from_slice(b"\\\\"),
+            b if b == q.quote => {
+                res.push(if q.backslash { b'\\' } else { q.quote });
+                res.push(b);
+            }
+            b => res.push(b),
+        }
+    }
+    if needs_quotes {
+        res.push(q.quote);
+    }
+    Str::from(&res[..]).unmoor().upcast()
+}
+
 pub fn escape_tsv<'a>(s: &Str<'a>) -> Str<'a> {
     let bs = unsafe { &*s.get_bytes() };
     let matches = NEEDS_ESCAPE_TSV.matches(bs);
@@ -844,6 +1037,17 @@ mod escape_tests {
         );
     }
 
+    #[test]
+    fn csv_quoting() {
+        let q = CsvQuoting::parse("all, quote=' escape=backslash").unwrap();
+        let quoted = |s: &str| escape_csv_with(s.as_bytes(), q);
+        assert_eq!(quoted("plain"), Str::from("'plain'"));
+        assert_eq!(quoted(r#"it's "a\b""#), Str::from(r#"'it\'s "a\\b"'"#));
+        assert_eq!(CsvQuoting::parse("minimal").unwrap(), CsvQuoting::default());
+        assert!(CsvQuoting::parse("quote=,").is_err());
+        assert!(CsvQuoting::parse("escape=none").is_err());
+    }
+
     #[test]
     fn tsv_escaping() {
         let s1 = Str::from("no, escaping");
@@ -885,10 +1089,11 @@ mod generic {
 
         // SIMD splitting by whitespace.
         //
-        // whitespace_masks outputs (a) the position of newlines in the input and (b) the location
-        // of field delimiters. Unlike, say, byte-based splitting, the end of one field is not
-        // necessarily the start of the next field. To allow for this, the returned whitespace mask
-        // contains both the start and end offsets for individual fields. For example,
+        // whitespace_masks outputs (a) the position of record separators (usually newlines) in the
+        // input and (b) the location of field delimiters. Unlike, say, byte-based splitting, the
+        // end of one field is not necessarily the start of the next field. To allow for this, the
+        // returned whitespace mask contains both the start and end offsets for individual fields.
+        // For example,
         //
         // [the raven  caws]
         //  ^  ^^    ^ ^
@@ -916,9 +1121,10 @@ mod generic {
         unsafe fn whitespace_masks(
             self,
             start_ws: u64,
+            record_sep: u8,
         ) -> (
             /* whitespace runs */ u64,
-            /* newlines */ u64,
+            /* record separators */ u64,
             /* next start_ws */ u64,
         ) {
             // TODO: we could probably use a shuffle to do this faster.
@@ -926,14 +1132,17 @@ mod generic {
             let tab = self.cmp_against_input(b'\t');
             let nl = self.cmp_against_input(b'\n');
             let cr = self.cmp_against_input(b'\r');
-            let ws1 = space.or(tab).or(nl).or(cr).mask();
+            // Record separators also separate fields; newlines separate fields even when they
+            // do not separate records (e.g. with RS="\0").
+            let rs = self.cmp_against_input(record_sep);
+            let ws1 = space.or(tab).or(nl).or(cr).or(rs).mask();
             let mut ws2 = ws1.wrapping_shl(1) | start_ws;
             if Self::INPUT_SIZE != 64 {
                 ws2 &= !(1 << Self::INPUT_SIZE as u32)
             }
             let ws_res = ws1 ^ ws2;
             let next_start_ws = ws1.wrapping_shr(Self::INPUT_SIZE as u32 - 1);
-            (ws_res, nl.mask(), next_start_ws)
+            (ws_res, rs.mask(), next_start_ws)
         }
     }
 
@@ -1255,10 +1464,11 @@ mod generic {
         buf: &[u8],
         offsets: &mut WhitespaceOffsets,
         start_ws: u64, /*start at 1*/
+        record_sep: u8,
     ) -> u64 /*next start ws*/ {
         find_indexes::<V, _, _>(buf, &mut offsets.0, start_ws, |start_ws, buf| {
             let inp = V::fill_input(buf);
-            let (ws, nl, next_start) = inp.whitespace_masks(start_ws);
+            let (ws, nl, next_start) = inp.whitespace_masks(start_ws, record_sep);
             (next_start, ws, nl)
         })
     }
@@ -1518,6 +1728,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk>>> {
 impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>> {
     pub fn new_whitespace<I, S>(
         rs: I,
+        record_sep: u8,
         chunk_size: usize,
         check_utf8: bool,
         exec_strategy: ExecutionStrategy,
@@ -1529,6 +1740,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
     {
         Self::new_whitespace_internal(
             rs,
+            record_sep,
             chunk_size,
             check_utf8,
             exec_strategy,
@@ -1538,10 +1750,11 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
     }
     pub fn new_whitespace_internal<I, S>(
         rs: I,
+        record_sep: u8,
         chunk_size: usize,
         check_utf8: bool,
         exec_strategy: ExecutionStrategy,
-        find_indexes: unsafe fn(&[u8], &mut WhitespaceOffsets, u64) -> u64,
+        find_indexes: WhitespaceIndexKernel,
         cancel_signal: CancelSignal,
     ) -> Self
     where
@@ -1553,6 +1766,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                 ExecutionStrategy::Serial => {
                     Box::new(chunk::new_chained_offset_chunk_producer_ascii_whitespace(
                         rs,
+                        record_sep,
                         chunk_size,
                         check_utf8,
                         find_indexes,
@@ -1565,6 +1779,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                             move || {
                                 chunk::new_chained_offset_chunk_producer_ascii_whitespace(
                                     rs,
+                                    record_sep,
                                     chunk_size,
                                     check_utf8,
                                     find_indexes,
@@ -1581,6 +1796,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
                                 r,
                                 chunk_size,
                                 name.as_str(),
+                                record_sep,
                                 i as u32 + 1,
                                 check_utf8,
                                 find_indexes,
@@ -1599,7 +1815,7 @@ impl ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<WhitespaceOffsets>>>>
             cur_buf: UniqueBuf::new(0).into_buf(),
             buf_len: 0,
             progress: 0,
-            record_sep: 0, // unused
+            record_sep,
             used_fields: FieldSet::all(),
             last_len: usize::max_value(),
             check_utf8,
@@ -1621,6 +1837,9 @@ where
     fn wait(&self) -> bool {
         ByteReaderBase::wait(self)
     }
+    fn stop_waiting(&self) {
+        self.prod.stop_waiting()
+    }
     fn request_handles(&self, size: usize) -> Vec<Box<dyn FnOnce() -> Self + Send>> {
         let producers = self.prod.try_dyn_resize(size);
         let mut res = Vec::with_capacity(producers.len());
@@ -1804,6 +2023,15 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> ByteReaderBase for ByteReader<P> {
             .get(offs.nl.start)
             .map(|x| *x as usize)
             .unwrap_or(self.buf_len);
+        if !self.used_fields.splits_records() {
+            // Only $0 is used: skip over the separators in this record without recording any
+            // fields.
+            offs.rel.start += gallop(&offs.rel.fields[offs.rel.start..], |ix| ix as usize <= end);
+            offs.nl.start += 1;
+            self.progress = (end + 1).min(self.buf_len);
+            let line = get_field!(0, line_start, end);
+            return (line, self.progress - line_start);
+        }
         for index in &offs.rel.fields[offs.rel.start..] {
             let mut index = *index as usize;
             debug_assert!(
@@ -1824,13 +2052,17 @@ impl<P: ChunkProducer<Chunk = OffsetChunk>> ByteReaderBase for ByteReader<P> {
             }
             if fields.len() == max {
                 let start_inc = gallop(&offs.rel.fields[offs.rel.start..], |ix| ix as usize <= end);
-                let len_inc = fields.len() + start_inc;
-                fields.resize_with(len_inc, Str::default);
+                if self.used_fields.counts_fields() {
+                    // Keep NF accurate without copying out the remaining fields. A record at the
+                    // end of the input has one more field than it has separators left.
+                    let len_inc = fields.len() + start_inc + (end == self.buf_len) as usize;
+                    fields.resize_with(len_inc, Str::default);
+                }
                 offs.rel.start += start_inc;
                 index = end;
                 is_record_sep = true;
             }
-            self.progress = index + 1;
+            self.progress = (index + 1).min(self.buf_len);
             if is_record_sep {
                 offs.nl.start += 1;
                 let line = get_field!(0, line_start, index);
@@ -1903,6 +2135,15 @@ impl ByteReaderBase for ByteReader<Box<dyn ChunkProducer<Chunk = OffsetChunk<Whi
             return (Str::default(), 0);
         }
 
+        if !self.used_fields.splits_records() {
+            // Only $0 is used: skip the field offsets in this record.
+            let rel = &mut self.cur_chunk.off.0.rel;
+            rel.start += gallop(&rel.fields[rel.start..], |ix| ix as usize <= record_end);
+            self.progress = record_end + 1;
+            let consumed = self.progress - line_start;
+            return (get_field!(0, line_start, record_end), consumed);
+        }
+
         // See the comments for Vector::whitespace_masks for more info on the format of the offsets
         // here.
         //
@@ -2081,6 +2322,24 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         tsv_split(crate::test_string_constants::PRIDE_PREJUDICE_CH2);
     }
 
+    #[test]
+    fn csv_empty_input() {
+        let mut _cache = RegexCache::default();
+        let _pat = Str::default();
+        let mut reader = CSVReader::new(
+            iter::once((std::io::Cursor::new(""), String::from("fake-stdin"))),
+            InputFormat::CSV,
+            /*chunk_size=*/ 512,
+            /*check_utf8=*/ true,
+            ExecutionStrategy::Serial,
+            Default::default(),
+        );
+        reader
+            .read_line(&_pat, &mut _cache)
+            .expect("failed to read line");
+        assert_eq!(reader.read_state(), 0);
+    }
+
     fn bytes_split(kernel: BytesIndexKernel, fs: u8, rs: u8, corpus: &'static str) {
         let mut _cache = RegexCache::default();
         let _pat = Str::default();
@@ -2268,6 +2527,7 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         fn make_br_ws(reader: impl io::Read + Send + 'static) -> impl LineReader {
             ByteReader::new_whitespace(
                 iter::once((reader, String::from("fake-stdin"))),
+                /*record_sep=*/ b'\n',
                 /*chunk_size=*/ 1024,
                 /*check_utf8=*/ false,
                 ExecutionStrategy::ShardPerRecord,
@@ -2305,14 +2565,14 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
         multithreaded_count("   leading whitespace   \n and some    more\n", 2, make_br);
     }
 
-    fn whitespace_split(kernel: WhitespaceIndexKernel, corpus: &'static str) {
+    fn whitespace_split(kernel: WhitespaceIndexKernel, record_sep: u8, corpus: &str) {
         let mut _cache = RegexCache::default();
         let _pat = Str::default();
         let mut expected_lines: Vec<Str<'static>> = Vec::new();
         let mut expected: Vec<Vec<Str<'static>>> = corpus
-            .split('\n')
+            .split(record_sep as char)
             .map(|line| {
-                expected_lines.push(line.into());
+                expected_lines.push(Str::from(line).unmoor());
                 line.split(|c: char| c.is_ascii_whitespace())
                     // trim of leading and trailing whitespace.
                     .filter(|x| !x.is_empty())
@@ -2321,14 +2581,15 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
             })
             .collect();
 
-        // For buffers that end in "\n" we don't want a trailing empty field.
-        if corpus.as_bytes().last() == Some(&b'\n') {
+        // For buffers that end in a record separator we don't want a trailing empty field.
+        if corpus.as_bytes().last() == Some(&record_sep) {
             let _ = expected_lines.pop();
             let _ = expected.pop();
         }
-        let reader = std::io::Cursor::new(corpus);
+        let reader = std::io::Cursor::new(corpus.to_string());
         let mut reader = ByteReader::new_whitespace_internal(
             std::iter::once((reader, String::from("fake-stdin"))),
+            record_sep,
             1024,
             /*check_utf8=*/ false,
             ExecutionStrategy::Serial,
@@ -2376,17 +2637,23 @@ unquoted,commas,"as well, including some long ones", and there we have it.""#;
 
     fn whitespace_splitter_generic<V: generic::Vector>() {
         let k = generic::find_indexes_ascii_whitespace::<V>;
-        whitespace_split(k, crate::test_string_constants::PRIDE_PREJUDICE_CH2);
-        whitespace_split(k, crate::test_string_constants::VIRGIL);
-        whitespace_split(k, "   leading whitespace   \n and some    more\n");
+        whitespace_split(k, b'\n', crate::test_string_constants::PRIDE_PREJUDICE_CH2);
+        whitespace_split(k, b'\n', crate::test_string_constants::VIRGIL);
+        whitespace_split(k, b'\n', "   leading whitespace   \n and some    more\n");
         whitespace_split(
             k,
+            b'\n',
             r#"xxxxxxxxxxxxxxxxxxxxxxxxxxxxx  yyyyyyyyyyyyyyyyyyyyyyyyyyyy 111111
 xxxxxxxxxxxxxxxxxxxxxxxxxxx    yyyyyyyyyyyyyyyyyyyyyyyy     222222
 xxxxxxxxxxxxxxxxxxxxxxxxxxxx  yyyyyyyyyyyyyyyyyyyyyyyyyyyy 3333333
 xxxxxxxxxxxxxxxxxxxxxxxxxx    yyyyyyyyyyyyyyyyyyyyyyyy     4444444
 "#,
         );
+        // Trailing whitespace on records that straddle chunk boundaries.
+        whitespace_split(k, b'\n', &"a bb ccc \n".repeat(300));
+        // NUL-separated records (as with -0), where newlines are ordinary whitespace.
+        whitespace_split(k, b'\0', "first record\nspans lines\0 second  \0\0third\0");
+        whitespace_split(k, b'\0', &"./some dir/file name.txt \0".repeat(200));
     }
 
     #[test]
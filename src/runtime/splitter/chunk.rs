@@ -1,9 +1,9 @@
 use std::borrow::Borrow;
 use std::io::Read;
 use std::mem;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
+use crossbeam_channel::{bounded, select, Receiver, Sender, TrySendError};
 
 use crate::common::{CancelSignal, Result};
 use crate::runtime::{
@@ -42,6 +42,9 @@ pub trait ChunkProducer {
     fn wait(&self) -> bool {
         true
     }
+    // Called once the main thread has stopped reading: handles still blocked in `wait` return
+    // false instead of waiting to be started.
+    fn stop_waiting(&self) {}
     fn get_chunk(&mut self, chunk: &mut Self::Chunk) -> Result<bool /*done*/>;
     fn next_file(&mut self) -> Result<bool /*new file available*/>;
 }
@@ -113,6 +116,7 @@ pub fn new_offset_chunk_producer_ascii_whitespace<R: Read>(
     r: R,
     chunk_size: usize,
     name: &str,
+    record_sep: u8,
     start_version: u32,
     check_utf8: bool,
     find_indexes: WhitespaceIndexKernel,
@@ -122,10 +126,10 @@ pub fn new_offset_chunk_producer_ascii_whitespace<R: Read>(
             name: name.into(),
             inner: Reader::new(r, chunk_size, /*padding=*/ 128, check_utf8),
             find_indexes: move |bs: &[u8], offs: &mut WhitespaceOffsets, start: u64| unsafe {
-                find_indexes(bs, offs, start)
+                find_indexes(bs, offs, start, record_sep)
             },
             cur_file_version: start_version,
-            record_sep: 0u8, // unused
+            record_sep,
             state: ChunkState::Init,
         },
         1,
@@ -194,6 +198,7 @@ pub fn new_chained_offset_chunk_producer_ascii_whitespace<
     I: Iterator<Item = (R, N)>,
 >(
     r: I,
+    record_sep: u8,
     chunk_size: usize,
     check_utf8: bool,
     find_indexes: WhitespaceIndexKernel,
@@ -207,6 +212,7 @@ pub fn new_chained_offset_chunk_producer_ascii_whitespace<
                     r,
                     chunk_size,
                     name.borrow(),
+                    record_sep,
                     /*start_version=*/ (i as u32).wrapping_add(1),
                     check_utf8,
                     find_indexes,
@@ -224,6 +230,9 @@ impl<C: Chunk> ChunkProducer for Box<dyn ChunkProducer<Chunk = C>> {
     fn wait(&self) -> bool {
         (**self).wait()
     }
+    fn stop_waiting(&self) {
+        (**self).stop_waiting()
+    }
     fn next_file(&mut self) -> Result<bool> {
         (**self).next_file()
     }
@@ -322,6 +331,15 @@ impl<R: Read, F: FnMut(&[u8], &mut Offsets)> ChunkProducer for OffsetChunkProduc
                     // Lastly, if it is not the last buffer and we do not have a record separator,
                     // we simply repeat this entire loop.
                     chunk.len = self.inner.end;
+                    if !is_partial && self.inner.yield_early() {
+                        // Hand out the complete records now; the next call reads the rest.
+                        self.inner.detach();
+                        chunk.buf = Some(buf.try_unique().unwrap());
+                        chunk.off.rel.fields.truncate(new_len);
+                        chunk.len = target.unwrap();
+                        self.state = ChunkState::Init;
+                        return Ok(false);
+                    }
                     let is_eof = self.inner.reset()?;
                     return match (is_partial, is_eof) {
                         (false, false) => {
@@ -377,30 +395,38 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
                     chunk.name = self.0.name.clone();
                     let buf = self.0.inner.buf.clone();
                     let bs = buf.as_bytes();
-                    self.1 = (self.0.find_indexes)(bs, &mut chunk.off, self.1);
+                    let start_ws = self.1;
+                    self.1 = (self.0.find_indexes)(bs, &mut chunk.off, start_ws);
+                    // The zeroed padding past the end of the input is full of record separators
+                    // when RS="\0"; drop those.
+                    let end = self.0.inner.end as u64;
+                    while chunk.off.0.nl.fields.last().is_some_and(|off| *off >= end) {
+                        chunk.off.0.nl.fields.pop();
+                    }
                     // Find the last newline in the buffer, if there is one.
                     let (is_partial, truncate_to, len_if_not_last) =
                         if let Some(nl_off) = chunk.off.0.nl.fields.last().cloned() {
                             let buf_end = nl_off as usize + 1;
                             self.0.inner.start = buf_end;
-                            let mut start = chunk.off.0.rel.fields.len() as isize - 1;
-                            while start > 0 {
-                                if chunk.off.0.rel.fields[start as usize] > nl_off {
-                                    // We are removing trailing fields from the input, but we know
-                                    // that newlines are whitespace, so we reset the start_ws
-                                    // variable to 1.
-                                    self.1 = 1;
-                                    start -= 1;
-                                } else {
-                                    break;
-                                }
-                            }
-                            (false, start as usize, buf_end)
+                            // Drop the fields that start after the last record; the next chunk
+                            // picks them up. It begins just past a record separator, which is
+                            // whitespace, so we reset the start_ws variable to 1.
+                            let keep = chunk.off.0.rel.fields.partition_point(|off| *off <= nl_off);
+                            self.1 = 1;
+                            (false, keep, buf_end)
                         } else {
                             (true, 0, 0)
                         };
                     // See comments in get_chunk for OffsetChunkProducer<R, F>
                     chunk.len = self.0.inner.end;
+                    if !is_partial && self.0.inner.yield_early() {
+                        self.0.inner.detach();
+                        chunk.buf = Some(buf.try_unique().unwrap());
+                        chunk.off.0.rel.fields.truncate(truncate_to);
+                        chunk.len = len_if_not_last;
+                        self.0.state = ChunkState::Init;
+                        return Ok(false);
+                    }
                     let is_eof = self.0.inner.reset()?;
                     return match (is_partial, is_eof) {
                         (false, false) => {
@@ -419,8 +445,12 @@ impl<R: Read, F: FnMut(&[u8], &mut WhitespaceOffsets, u64) -> u64> ChunkProducer
                         }
                         // We read an entire chunk, but we didn't find a full record. Try again
                         // (note that the call to reset read in a larger chunk and would have kept
-                        // a prefix)
-                        (true, false) => continue,
+                        // a prefix). The buffer is scanned from the start again, so it starts
+                        // in the same whitespace state.
+                        (true, false) => {
+                            self.1 = start_ws;
+                            continue;
+                        }
                     };
                 }
                 ChunkState::Done => return Ok(true),
@@ -450,6 +480,12 @@ impl<P: ChunkProducer> ChunkProducer for ChainedChunkProducer<P> {
         res
     }
 
+    fn stop_waiting(&self) {
+        if let Some(cur) = self.0.last() {
+            cur.stop_waiting()
+        }
+    }
+
     fn next_file(&mut self) -> Result<bool> {
         if let Some(cur) = self.0.last_mut() {
             if !cur.next_file()? {
@@ -480,6 +516,10 @@ pub struct ParallelChunkProducer<P: ChunkProducer> {
     start: Receiver<()>,
     incoming: Receiver<P::Chunk>,
     spent: Sender<P::Chunk>,
+    // Nothing is ever sent on `stopped`: `stop_waiting` drops its only sender, which wakes every
+    // handle blocked in `wait`.
+    stop: Arc<Mutex<Option<Sender<()>>>>,
+    stopped: Receiver<()>,
 }
 
 impl<P: ChunkProducer> Clone for ParallelChunkProducer<P> {
@@ -488,6 +528,8 @@ impl<P: ChunkProducer> Clone for ParallelChunkProducer<P> {
             start: self.start.clone(),
             incoming: self.incoming.clone(),
             spent: self.spent.clone(),
+            stop: self.stop.clone(),
+            stopped: self.stopped.clone(),
         }
     }
 }
@@ -500,6 +542,7 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
         let (start_sender, start_receiver) = bounded(chan_size);
         let (in_sender, in_receiver) = bounded(chan_size);
         let (spent_sender, spent_receiver) = bounded(chan_size);
+        let (stop_sender, stopped) = bounded(0);
         std::thread::spawn(move || {
             let mut n_workers = 0;
             let mut p = p_factory();
@@ -555,6 +598,8 @@ impl<P: ChunkProducer + 'static> ParallelChunkProducer<P> {
             start: start_receiver,
             incoming: in_receiver,
             spent: spent_sender,
+            stop: Arc::new(Mutex::new(Some(stop_sender))),
+            stopped,
         }
     }
 }
@@ -573,9 +618,14 @@ impl<P: ChunkProducer + 'static> ChunkProducer for ParallelChunkProducer<P> {
         err!("nextfile is not supported in record-oriented parallel mode")
     }
     fn wait(&self) -> bool {
-        self.start
-            .recv_timeout(std::time::Duration::from_secs(2))
-            .is_ok()
+        select! {
+            recv(self.start) -> res => res.is_ok(),
+            recv(self.stopped) -> _ => false,
+            default(std::time::Duration::from_secs(2)) => false,
+        }
+    }
+    fn stop_waiting(&self) {
+        self.stop.lock().unwrap().take();
     }
     fn get_chunk(&mut self, chunk: &mut P::Chunk) -> Result<bool> {
         if let Ok(mut new_chunk) = self.incoming.recv() {
@@ -709,6 +759,10 @@ impl<P: ChunkProducer + 'static> ChunkProducer for CancellableChunkProducer<P> {
         self.prod.wait()
     }
 
+    fn stop_waiting(&self) {
+        self.prod.stop_waiting()
+    }
+
     fn next_file(&mut self) -> Result<bool> {
         if self.signal.cancelled() {
             return Ok(false);
@@ -5,17 +5,36 @@
 //! characters that cross chunk boundaries, or multi-chunk "lines".
 
 // TODO: add padding to the linereader trait
+pub mod avro;
 pub mod batch;
 pub mod chunk;
+pub mod ranges;
 pub mod regex;
+pub mod timeout;
 
 use super::str_impl::{Buf, Str, UniqueBuf};
 use super::utf8::{is_utf8, validate_utf8_clipped};
-use super::{Int, RegexCache};
+use super::{Int, RegexCache, StrMap};
 use crate::common::Result;
 use crate::pushdown::FieldSet;
 
+use std::collections::VecDeque;
 use std::io::{ErrorKind, Read};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+// Set by --unbuffered: hand each read to the splitter as soon as it returns, rather than waiting
+// for a full chunk.
+static UNBUFFERED: AtomicBool = AtomicBool::new(false);
+
+/// Process input as soon as it arrives (see `--unbuffered`). By default, input is only split into
+/// records once a full chunk has been read, which can hold up a slow stream like `tail -f` for a
+/// long time. Must be called before any input is read.
+pub fn set_unbuffered() {
+    UNBUFFERED.store(true, Ordering::Relaxed);
+}
 
 // We have several implementations of "read and split a line"; they are governed by the LineReader
 // and Line traits.
@@ -45,6 +64,9 @@ pub trait LineReader: Sized {
     fn wait(&self) -> bool {
         true
     }
+    // Wake handles from `request_handles` that are still in `wait`, once the main thread has
+    // stopped reading.
+    fn stop_waiting(&self) {}
     // TODO we should probably have the default impl the other way around.
     fn read_line(
         &mut self,
@@ -67,6 +89,14 @@ pub trait LineReader: Sized {
     // Whether or not this LineReader is configured to check for valid UTF-8. This is used to
     // propagate consistent options across multiple LineReader instances.
     fn check_utf8(&self) -> bool;
+    // Whether this reader takes its input files from ARGV as reading proceeds, rather than being
+    // handed a fixed list up front. See `FileRead::next_input`.
+    fn reads_argv(&self) -> bool {
+        false
+    }
+    // Queue `file` to be read once the current input is exhausted. Only called on readers for
+    // which `reads_argv` returns true.
+    fn push_input(&mut self, _file: &str) {}
 }
 
 fn normalize_join_indexes(start: Int, end: Int, nf: usize) -> Result<(usize, usize)> {
@@ -84,6 +114,71 @@ fn normalize_join_indexes(start: Int, end: Int, nf: usize) -> Result<(usize, usi
     Ok((start, end))
 }
 
+/// The columns listed in a `select` spec, in order. A spec is a comma-separated list of items,
+/// each of which is a header name from `fi`, a column number, or a range `a-b` of either; `a-`
+/// runs to the last column, and a range whose start is after its end runs backwards. Like
+/// `join_fields`, ranges stop at the last column.
+fn select_indexes<'a>(spec: &Str<'a>, fi: &StrMap<'a, Int>, nf: usize) -> Result<Vec<Int>> {
+    let column = |item: &str| -> Option<Int> {
+        let key = Str::from(item.to_string()).upcast();
+        if fi.contains(&key) {
+            return Some(fi.get(&key));
+        }
+        item.trim().parse::<Int>().ok().filter(|c| *c > 0)
+    };
+    let spec = spec.with_bytes(|bs| String::from_utf8_lossy(bs).into_owned());
+    let last = nf as Int;
+    let mut res = Vec::new();
+    for item in spec.split(',').filter(|item| !item.trim().is_empty()) {
+        if let Some(col) = column(item) {
+            res.push(col);
+            continue;
+        }
+        // Header names may contain '-' themselves, so try every split point.
+        let range = item.match_indices('-').find_map(|(i, _)| {
+            let (from, to) = (&item[..i], &item[i + 1..]);
+            let to = if to.trim().is_empty() {
+                Some(last)
+            } else {
+                column(to)
+            };
+            Some((column(from)?, to?))
+        });
+        match range {
+            Some((from, to)) if from <= to => res.extend(from..=to.min(last)),
+            Some((from, to)) => res.extend((to..=from.min(last)).rev()),
+            None => return err!("select: unknown column {:?}", item),
+        }
+    }
+    Ok(res)
+}
+
+/// Join the columns of `line` listed in `spec` (see `select_indexes`) with `sep`. Columns are
+/// escaped as CSV if `esc` is 1 and as TSV if it is 2. This is the implementation of `select`.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn select_cols<'a, L: Line<'a>>(
+    line: &mut L,
+    spec: &Str<'a>,
+    sep: &Str<'a>,
+    fi: &StrMap<'a, Int>,
+    pat: &Str,
+    ofs: &Str,
+    rc: &mut RegexCache,
+    esc: Int,
+) -> Result<Str<'a>> {
+    let nf = line.nf(pat, rc)?;
+    let mut fields = Vec::new();
+    for col in select_indexes(spec, fi, nf)? {
+        let field = line.get_col(col, pat, ofs, rc)?;
+        fields.push(match esc {
+            1 => super::escape_csv(&field),
+            2 => super::escape_tsv(&field),
+            _ => field,
+        });
+    }
+    Ok(sep.join_slice(&fields[..]))
+}
+
 // Default implementation of Line; it supports assignment into fields as well as lazy splitting.
 pub struct DefaultLine {
     line: Str<'static>,
@@ -208,7 +303,19 @@ impl<'a> Line<'a> for DefaultLine {
     }
 }
 
-pub struct ChainedReader<R>(Vec<R>, /*check_utf8=*/ bool);
+type ReaderFactory<R> = Box<dyn FnOnce() -> R + Send>;
+type ReaderOpener<R> = Box<dyn Fn(&str) -> R + Send>;
+
+pub struct ChainedReader<R> {
+    readers: Vec<R>,
+    // When sharding input files across workers, files that no worker has started reading yet.
+    // Each worker takes the next one from the front of the queue once it runs out of input.
+    queue: Option<Arc<Mutex<VecDeque<ReaderFactory<R>>>>>,
+    // For readers created with `from_argv`, opens the files named by `push_input`.
+    opener: Option<ReaderOpener<R>>,
+    used_fields: FieldSet,
+    check_utf8: bool,
+}
 
 impl<R: LineReader> ChainedReader<R> {
     pub fn new(rs: impl Iterator<Item = R>) -> ChainedReader<R> {
@@ -219,7 +326,61 @@ impl<R: LineReader> ChainedReader<R> {
         } else {
             false
         };
-        ChainedReader(v, check_utf8)
+        ChainedReader {
+            readers: v,
+            queue: None,
+            opener: None,
+            used_fields: FieldSet::all(),
+            check_utf8,
+        }
+    }
+
+    /// Create a reader with no inputs of its own: the files it reads are named by ARGV once the
+    /// program starts reading, and are opened with `open`.
+    pub fn from_argv<F>(open: F, check_utf8: bool) -> ChainedReader<R>
+    where
+        F: Fn(&str) -> R + Send + 'static,
+    {
+        ChainedReader {
+            readers: Vec::new(),
+            queue: None,
+            opener: Some(Box::new(open)),
+            used_fields: FieldSet::all(),
+            check_utf8,
+        }
+    }
+
+    /// Create a reader whose inputs form a work queue: handles returned by `request_handles`
+    /// share the queue, so each file is read in its entirety by whichever worker picks it up.
+    pub fn sharded<F>(rs: impl Iterator<Item = F>, check_utf8: bool) -> ChainedReader<R>
+    where
+        F: FnOnce() -> R + Send + 'static,
+    {
+        let queue = rs.map(|f| Box::new(f) as ReaderFactory<R>).collect();
+        ChainedReader {
+            readers: Vec::new(),
+            queue: Some(Arc::new(Mutex::new(queue))),
+            opener: None,
+            used_fields: FieldSet::all(),
+            check_utf8,
+        }
+    }
+
+    // Start reading the next file from the queue, if there is one.
+    fn refill(&mut self) -> bool {
+        let next = match &self.queue {
+            Some(queue) => queue.lock().unwrap().pop_front(),
+            None => None,
+        };
+        match next {
+            Some(factory) => {
+                let mut reader = factory();
+                reader.set_used_fields(&self.used_fields);
+                self.readers.push(reader);
+                true
+            }
+            None => false,
+        }
     }
 }
 
@@ -229,10 +390,30 @@ where
 {
     type Line = R::Line;
     fn check_utf8(&self) -> bool {
-        self.1
+        self.check_utf8
+    }
+    fn request_handles(&self, size: usize) -> Vec<Box<dyn FnOnce() -> Self + Send>> {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return vec![],
+        };
+        (0..size)
+            .map(|_| {
+                let queue = queue.clone();
+                let used_fields = self.used_fields.clone();
+                let check_utf8 = self.check_utf8;
+                Box::new(move || ChainedReader {
+                    readers: Vec::new(),
+                    queue: Some(queue),
+                    opener: None,
+                    used_fields,
+                    check_utf8,
+                }) as _
+            })
+            .collect()
     }
     fn filename(&self) -> Str<'static> {
-        self.0
+        self.readers
             .last()
             .map(LineReader::filename)
             .unwrap_or_else(Str::default)
@@ -248,7 +429,10 @@ where
         rc: &mut RegexCache,
         old: &'a mut Self::Line,
     ) -> Result<bool> {
-        let cur = match self.0.last_mut() {
+        if self.readers.is_empty() {
+            self.refill();
+        }
+        let cur = match self.readers.last_mut() {
             Some(cur) => cur,
             None => {
                 *old = Default::default();
@@ -264,27 +448,40 @@ where
         }
     }
     fn read_state(&self) -> i64 {
-        match self.0.last() {
+        match self.readers.last() {
             Some(cur) => cur.read_state(),
             None => 0, /* EOF */
         }
     }
     fn next_file(&mut self) -> Result<bool> {
-        Ok(match self.0.last_mut() {
+        Ok(match self.readers.last_mut() {
             Some(e) => {
                 if !e.next_file()? {
-                    self.0.pop();
+                    self.readers.pop();
+                    self.refill();
                 }
                 true
             }
-            None => false,
+            None => self.refill(),
         })
     }
     fn set_used_fields(&mut self, used_fields: &FieldSet) {
-        for i in self.0.iter_mut() {
+        self.used_fields = used_fields.clone();
+        for i in self.readers.iter_mut() {
             i.set_used_fields(used_fields);
         }
     }
+    fn reads_argv(&self) -> bool {
+        self.opener.is_some()
+    }
+    fn push_input(&mut self, file: &str) {
+        if let Some(open) = &self.opener {
+            let mut reader = open(file);
+            reader.set_used_fields(&self.used_fields);
+            // `readers` is a stack with the current file on top.
+            self.readers.insert(0, reader);
+        }
+    }
 }
 
 // Buffer management and io
@@ -292,6 +489,8 @@ where
 #[repr(i64)]
 #[derive(PartialEq, Eq, Copy, Clone)]
 pub(crate) enum ReaderState {
+    // A read timed out (see the `timeout` module). Reading again will pick up where it left off.
+    Timeout = -2,
     Error = -1,
     Eof = 0,
     OK = 1,
@@ -325,7 +524,12 @@ struct Reader<R> {
     check_utf8: bool,
 }
 
-fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
+// Read until `buf` is full, or (with `once` set) until a read returns some data.
+fn read_to_slice(
+    r: &mut impl Read,
+    mut buf: &mut [u8],
+    once: bool,
+) -> Result<(usize, /*timed out*/ bool)> {
     let mut read = 0;
     while !buf.is_empty() {
         match r.read(buf) {
@@ -335,17 +539,21 @@ fn read_to_slice(r: &mut impl Read, mut buf: &mut [u8]) -> Result<usize> {
                 }
                 buf = &mut buf[n..];
                 read += n;
+                if once {
+                    break;
+                }
             }
             Err(e) => match e.kind() {
                 ErrorKind::Interrupted => continue,
                 ErrorKind::UnexpectedEof => {
                     break;
                 }
+                ErrorKind::TimedOut => return Ok((read, true)),
                 _ => return err!("read error {}", e),
             },
         }
     }
-    Ok(read)
+    Ok((read, false))
 }
 
 impl<R: Read> Reader<R> {
@@ -380,7 +588,7 @@ impl<R: Read> Reader<R> {
     fn read_state(&self) -> i64 {
         match self.state {
             ReaderState::OK => self.state as i64,
-            ReaderState::Error | ReaderState::Eof => {
+            ReaderState::Error | ReaderState::Eof | ReaderState::Timeout => {
                 // NB: last_len should really be "bytes consumed"; i.e. it should be the length
                 // of the line including any trimmed characters, and the record separator. I.e.
                 // "empty lines" that are actually in the input should result in a nonzero value
@@ -401,6 +609,30 @@ impl<R: Read> Reader<R> {
         self.buf = UniqueBuf::new(0).into_buf();
     }
 
+    /// Move the unconsumed bytes into a fresh buffer without reading any more input, so the
+    /// current buffer can be handed out while the next one is still waiting on the reader.
+    fn detach(&mut self) {
+        let plen = self.input_end.saturating_sub(self.start);
+        let mut data = UniqueBuf::new(plen + self.padding);
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.buf.as_ptr().add(self.start),
+                data.as_mut_ptr(),
+                plen,
+            );
+        }
+        self.buf = data.into_buf();
+        self.end = self.end.saturating_sub(self.start);
+        self.input_end = plen;
+        self.start = 0;
+    }
+
+    /// Whether a chunk holding complete records should be yielded before reading further. That
+    /// read can block on a pipe indefinitely, which would hold back the records already read.
+    fn yield_early(&self) -> bool {
+        UNBUFFERED.load(Ordering::Relaxed) && self.state != ReaderState::Eof
+    }
+
     fn reset(&mut self) -> Result</*done*/ bool> {
         if self.state == ReaderState::Eof {
             return Ok(true);
@@ -418,6 +650,9 @@ impl<R: Read> Reader<R> {
         consume: usize,
     ) -> Result<(UniqueBuf, /*end*/ usize, /*input_end*/ usize)> {
         let mut done = false;
+        if self.state == ReaderState::Timeout {
+            self.state = ReaderState::OK;
+        }
         let plen = self.input_end.saturating_sub(consume);
         // Double the chunk size if it is too small to read a sufficient batch given the prefix
         // size.
@@ -432,8 +667,13 @@ impl<R: Read> Reader<R> {
             std::ptr::copy_nonoverlapping(self.buf.as_ptr().add(consume), data.as_mut_ptr(), plen);
         }
         let mut bytes = &mut data.as_mut_bytes()[..self.chunk_size];
-        let bytes_read = plen + read_to_slice(&mut self.inner, &mut bytes[plen..])?;
-        if bytes_read != self.chunk_size {
+        let unbuffered = UNBUFFERED.load(Ordering::Relaxed);
+        let (n, timed_out) = read_to_slice(&mut self.inner, &mut bytes[plen..], unbuffered)?;
+        let bytes_read = plen + n;
+        // A short read only means EOF if we were waiting for a full chunk.
+        if timed_out || (unbuffered && n > 0) {
+            bytes = &mut bytes[..bytes_read];
+        } else if bytes_read != self.chunk_size {
             done = true;
             bytes = &mut bytes[..bytes_read];
         }
@@ -463,7 +703,64 @@ impl<R: Read> Reader<R> {
 
         if done {
             self.state = ReaderState::Eof;
+        } else if timed_out {
+            self.state = ReaderState::Timeout;
         }
         Ok((data, ulen, bytes_read))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::regex::RegexSplitter;
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn sharded_files_are_read_once() {
+        let files: [&'static str; 3] = ["a1\na2\na3\n", "b1\n", "c1\nc2\n"];
+        let mut main = ChainedReader::sharded(
+            files.into_iter().enumerate().map(|(i, contents)| {
+                move || {
+                    RegexSplitter::new(
+                        Cursor::new(contents.as_bytes()),
+                        /*chunk_size=*/ 16,
+                        format!("file{}", i),
+                        /*check_utf8=*/ false,
+                    )
+                }
+            }),
+            /*check_utf8=*/ false,
+        );
+        let mut worker = main.request_handles(1).pop().unwrap()();
+        assert!(ChainedReader::new(std::iter::empty::<RegexSplitter<Cursor<&[u8]>>>())
+            .request_handles(1)
+            .is_empty());
+
+        let pat = Str::from("\n");
+        let mut rc = RegexCache::default();
+        let mut lines = Vec::new();
+        // Alternate between readers so that they both pull files off of the queue.
+        let mut done = [false, false];
+        while !done.iter().all(|x| *x) {
+            for (i, rdr) in [&mut main, &mut worker].into_iter().enumerate() {
+                let (_, mut line) = rdr.read_line(&pat, &mut rc).unwrap();
+                if rdr.read_state() == ReaderState::Eof as i64 {
+                    done[i] = true;
+                    continue;
+                }
+                let text = line.get_col(0, &pat, &Str::default(), &mut rc).unwrap();
+                lines.push((i, rdr.filename().to_string(), text.to_string()));
+            }
+        }
+        let mut all: Vec<_> = lines.iter().map(|(_, _, l)| l.as_str()).collect();
+        all.sort();
+        assert_eq!(all, vec!["a1", "a2", "a3", "b1", "c1", "c2"]);
+        // Every file is read by exactly one of the readers.
+        for (_, name, line) in lines.iter() {
+            let owner: Vec<_> = lines.iter().filter(|(_, n, _)| n == name).map(|x| x.0).collect();
+            assert!(owner.iter().all(|o| *o == owner[0]), "{} split across readers", line);
+        }
+        assert!(lines.iter().any(|x| x.0 == 0) && lines.iter().any(|x| x.0 == 1));
+    }
+}
@@ -0,0 +1,189 @@
+//! Record-aligned byte ranges of a single input file.
+//!
+//! With `-p range`, each input file is cut into byte ranges that start and end on record
+//! boundaries. Worker threads then take ranges from a shared queue, just as they take whole files
+//! with `-p file`, so several workers can read different parts of one large file at once instead
+//! of waiting on a single thread that reads the file and hands out chunks.
+//!
+//! Finding a boundary only means scanning forward from the split point to the next record
+//! separator. CSV is the exception: a newline inside a quoted field does not end a record, and
+//! there is no telling whether a given byte is quoted without reading everything before it. CSV
+//! files are therefore scanned once from the start, tracking quotes, before any work begins.
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use memchr::{memchr, memchr2_iter};
+
+/// What ends a record.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Boundary {
+    /// A single record separator byte, e.g. `\n` for TSV or the default RS.
+    Byte(u8),
+    /// A newline that is not inside a quoted field.
+    Csv,
+}
+
+// Ranges are never smaller than this many chunks (see --chunk-size); small inputs are not worth
+// splitting up.
+const MIN_CHUNKS: u64 = 16;
+
+// Ranges per worker. Having a few lets workers that finish early pick up more of the file.
+const RANGES_PER_WORKER: usize = 4;
+
+const SCAN_SIZE: usize = 64 << 10;
+
+/// Split `file` into ranges for `workers` worker threads to read in parallel. Every range but the
+/// last ends right after a record boundary, and together they cover the whole file.
+pub fn for_workers(
+    file: &mut File,
+    workers: usize,
+    chunk_size: usize,
+    boundary: Boundary,
+) -> io::Result<Vec<Range<u64>>> {
+    let len = file.metadata()?.len();
+    let min_len = (chunk_size as u64).max(1) * MIN_CHUNKS;
+    let pieces = (workers * RANGES_PER_WORKER).min((len / min_len) as usize);
+    split(file, len, pieces, boundary)
+}
+
+/// Split the first `len` bytes of `r` into at most `pieces` ranges of roughly equal size that
+/// start on record boundaries.
+pub fn split(
+    r: &mut (impl Read + Seek),
+    len: u64,
+    pieces: usize,
+    boundary: Boundary,
+) -> io::Result<Vec<Range<u64>>> {
+    let targets: Vec<u64> = (1..pieces as u64)
+        .map(|i| i * (len / pieces as u64))
+        .collect();
+    let mut starts = vec![0];
+    match boundary {
+        Boundary::Byte(sep) => {
+            for target in targets {
+                if target <= *starts.last().unwrap() {
+                    continue;
+                }
+                match next_record(r, target, sep)? {
+                    Some(start) if start < len => starts.push(start),
+                    _ => break,
+                }
+            }
+        }
+        Boundary::Csv => starts.extend(csv_records(r, &targets)?.into_iter().filter(|s| *s < len)),
+    }
+    starts.push(len);
+    Ok(starts.windows(2).map(|w| w[0]..w[1]).collect())
+}
+
+/// The offset of the first record that starts at or after `pos`.
+fn next_record(r: &mut (impl Read + Seek), pos: u64, sep: u8) -> io::Result<Option<u64>> {
+    // A record starts at `pos` if the byte before it is a separator.
+    let mut offset = pos - 1;
+    r.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0; SCAN_SIZE];
+    loop {
+        let n = r.read(&mut buf)?;
+        if n == 0 {
+            return Ok(None);
+        }
+        if let Some(i) = memchr(sep, &buf[..n]) {
+            return Ok(Some(offset + i as u64 + 1));
+        }
+        offset += n as u64;
+    }
+}
+
+/// For each of the (sorted) `targets`, the offset of the first CSV record starting at or after
+/// it. Targets that fall within the same record share a start, which is only returned once.
+fn csv_records(r: &mut (impl Read + Seek), targets: &[u64]) -> io::Result<Vec<u64>> {
+    let mut starts = Vec::new();
+    let mut targets = targets.iter().copied().peekable();
+    let mut quoted = false;
+    let mut offset = 0;
+    r.seek(SeekFrom::Start(0))?;
+    let mut buf = vec![0; SCAN_SIZE];
+    while targets.peek().is_some() {
+        let n = r.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for i in memchr2_iter(b'"', b'\n', &buf[..n]) {
+            if buf[i] == b'"' {
+                // An escaped quote ("") flips this twice.
+                quoted = !quoted;
+                continue;
+            }
+            let start = offset + i as u64 + 1;
+            if quoted || targets.peek().is_none_or(|t| start < *t) {
+                continue;
+            }
+            starts.push(start);
+            while targets.peek().is_some_and(|t| *t <= start) {
+                targets.next();
+            }
+            if targets.peek().is_none() {
+                break;
+            }
+        }
+        offset += n as u64;
+    }
+    Ok(starts)
+}
+
+/// A reader for the bytes of `path` in `range`.
+pub fn open(path: &str, range: Range<u64>) -> io::Result<io::Take<File>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start))?;
+    Ok(file.take(range.end - range.start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn pieces(text: &str, n: usize, boundary: Boundary) -> Vec<&str> {
+        let ranges = split(&mut Cursor::new(text), text.len() as u64, n, boundary).unwrap();
+        ranges
+            .into_iter()
+            .map(|r| &text[r.start as usize..r.end as usize])
+            .collect()
+    }
+
+    #[test]
+    fn split_on_separator() {
+        let text = "aaaa\nbb\ncccccc\nd\neeeeeeee\n";
+        for n in 0..=text.len() + 1 {
+            let got = pieces(text, n, Boundary::Byte(b'\n'));
+            assert_eq!(got.concat(), text);
+            assert!(got.iter().all(|p| !p.is_empty() && p.ends_with('\n')));
+        }
+        assert_eq!(
+            pieces(text, 3, Boundary::Byte(b'\n')),
+            ["aaaa\nbb\n", "cccccc\nd\n", "eeeeeeee\n"]
+        );
+        // A file without a trailing separator keeps its last record intact.
+        assert_eq!(
+            pieces("a;b;cde", 3, Boundary::Byte(b';')),
+            ["a;", "b;", "cde"]
+        );
+    }
+
+    #[test]
+    fn split_csv_outside_quotes() {
+        let text = "1,\"a\nb\nc\nd\"\n2,\"\"\"x\ny\"\n3,z\n4,w\n";
+        for n in 0..=text.len() + 1 {
+            let got = pieces(text, n, Boundary::Csv);
+            assert_eq!(got.concat(), text);
+            assert!(got
+                .iter()
+                .all(|p| p.chars().filter(|c| *c == '"').count() % 2 == 0));
+        }
+        assert_eq!(
+            pieces(text, 3, Boundary::Csv),
+            ["1,\"a\nb\nc\nd\"\n", "2,\"\"\"x\ny\"\n", "3,z\n4,w\n"]
+        );
+    }
+}
@@ -1,11 +1,13 @@
 //! Regex-based splitting routines
 use std::io::Read;
+use std::time::Duration;
 
 use crate::common::Result;
 use crate::pushdown::FieldSet;
 use crate::runtime::Str;
-use regex::bytes::Regex;
+use crate::runtime::regex_engine::Regex;
 
+use super::timeout::TimedReader;
 use super::{DefaultLine, LineReader, Reader, ReaderState};
 
 // TODO: this can probably just be "Splitter"
@@ -72,6 +74,12 @@ impl<R: Read> LineReader for RegexSplitter<R> {
     }
 }
 
+impl<R: Read + Send + 'static> RegexSplitter<TimedReader<R>> {
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.reader.inner.set_timeout(timeout);
+    }
+}
+
 impl<R: Read> RegexSplitter<R> {
     pub fn new(r: R, chunk_size: usize, name: impl Into<Str<'static>>, check_utf8: bool) -> Self {
         RegexSplitter {
@@ -89,6 +97,12 @@ impl<R: Read> RegexSplitter<R> {
         res
     }
 
+    // Whether the last call to `reset` timed out without adding anything to the `pending` bytes
+    // that were already buffered.
+    fn timed_out(&self, pending: usize) -> bool {
+        self.reader.state == ReaderState::Timeout && self.reader.end == pending
+    }
+
     fn read_line_inner(&mut self, pat: &Regex) -> (Str<'static>, usize) {
         if self.reader.is_eof() {
             return (Str::default(), 0);
@@ -99,8 +113,14 @@ impl<R: Read> RegexSplitter<R> {
             // makes it harder for us to call mutable methods like advance in the body, so just get
             // the start and end pointers.
             match pat.find(s).map(|m| (m.start(), m.end())) {
-                // We need this check in case the regex matches across a chunk boundary.
-                Some((start, end)) if end + self.reader.start < self.reader.end => {
+                // We need this check in case the regex matches across a chunk boundary. A literal
+                // separator cannot grow with more input, so with unbuffered input we take a match
+                // at the end of the buffer rather than blocking on the next read.
+                Some((start, end))
+                    if end + self.reader.start < self.reader.end
+                        || (self.reader.yield_early()
+                            && regex::escape(pat.as_str()) == pat.as_str()) =>
+                {
                     // Valid offsets guaranteed by correctness of regex `find`.
                     let res = self
                         .reader
@@ -121,6 +141,10 @@ impl<R: Read> RegexSplitter<R> {
                             self.reader.start = self.reader.end;
                             (line, consumed)
                         }
+                        Ok(false) if self.timed_out(consumed) => {
+                            // The partial line stays in the buffer for the next read.
+                            (Str::default(), 0)
+                        }
                         Ok(false) => {
                             // search the new (potentially larger) buffer.
                             // NB: isn't this wasteful? The new buffer could be as much as half
@@ -148,6 +172,7 @@ impl<R: Read> RegexSplitter<R> {
                     };
                 }
                 Some((start, end)) => {
+                    let pending = self.reader.end - self.reader.start;
                     return match self.reader.reset() {
                         Ok(true) => {
                             // Valid offsets guaranteed by correctness of regex `find`.
@@ -158,6 +183,7 @@ impl<R: Read> RegexSplitter<R> {
                             self.reader.start += end;
                             (res, end)
                         }
+                        Ok(false) if self.timed_out(pending) => (Str::default(), 0),
                         Ok(false) => {
                             // See comment in the previous branch.
                             continue;
@@ -178,7 +204,6 @@ mod tests {
     // need to benchmark batched splitting vs. regular splitting to get a feel for things.
     use super::*;
     use lazy_static::lazy_static;
-    use regex::bytes::Regex;
     use std::io::Cursor;
 
     lazy_static! {
@@ -193,6 +218,12 @@ mod tests {
         std::str::from_utf8(s).unwrap().into()
     }
 
+    // The pieces of `bs` between matches of `re`, as the regex crate splits them.
+    fn split<'a>(re: &Regex, bs: &'a [u8]) -> Vec<Str<'a>> {
+        let re = regex::bytes::Regex::new(re.as_str()).unwrap();
+        re.split(bs).map(ref_str).collect()
+    }
+
     #[test]
     fn test_line_split_big_sep() {
         let mut buf = Vec::new();
@@ -212,7 +243,7 @@ mod tests {
             assert!(rdr.read_state() != -1);
             lines.push(line);
         }
-        let mut expected: Vec<_> = BS.split(bs.as_bytes()).map(ref_str).collect();
+        let mut expected: Vec<_> = split(&BS, bs.as_bytes());
         // remove trailing empty line
         assert_eq!(expected.pop(), Some(Str::default()));
         if lines != expected {
@@ -226,6 +257,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_line_split_timeout() {
+        use std::io::{Error, ErrorKind};
+        // Yields its chunks in order; `None` is a read that times out.
+        struct Stalls(Vec<Option<&'static str>>);
+        impl Read for Stalls {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0.is_empty() {
+                    return Ok(0);
+                }
+                match self.0.remove(0) {
+                    Some(chunk) => {
+                        buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+                        Ok(chunk.len())
+                    }
+                    None => Err(Error::new(ErrorKind::TimedOut, "timed out")),
+                }
+            }
+        }
+        let chunks = vec![None, Some("one\npar"), None, None, Some("tial\n")];
+        let mut rdr =
+            RegexSplitter::new(Stalls(chunks), /*chunk_size=*/ 512, "", /*check_utf8=*/ false);
+        let mut got = Vec::new();
+        while !rdr.reader.is_eof() {
+            let line = rdr.read_line_regex(&LINE);
+            got.push((rdr.read_state(), line.to_string()));
+        }
+        let timeout = ReaderState::Timeout as i64;
+        assert_eq!(
+            got,
+            vec![
+                (timeout, "".into()),
+                (1, "one".into()),
+                (timeout, "".into()),
+                (1, "partial".into()),
+            ]
+        );
+    }
+
     #[test]
     fn test_line_split() {
         let chunk_size = 1 << 9;
@@ -239,7 +309,7 @@ mod tests {
             lines.push(line);
         }
 
-        let expected: Vec<_> = LINE.split(bs.as_bytes()).map(ref_str).collect();
+        let expected: Vec<_> = split(&LINE, bs.as_bytes());
         if lines != expected {
             eprintln!("lines.len={}, expected.len={}", lines.len(), expected.len());
             for (i, (l, e)) in lines.iter().zip(expected.iter()).enumerate() {
@@ -275,7 +345,7 @@ mod tests {
             assert!(rdr.read_state() != -1);
             lines.push(line);
         }
-        let expected: Vec<_> = LINE.split(s.as_bytes()).map(ref_str).collect();
+        let expected: Vec<_> = split(&LINE, s.as_bytes());
         if lines != expected {
             eprintln!("lines.len={}, expected.len={}", lines.len(), expected.len());
             for (i, (l, e)) in lines.iter().zip(expected.iter()).enumerate() {
@@ -311,7 +381,7 @@ mod tests {
                 assert!(rdr.read_state() != -1);
                 lines.push(line);
             }
-            let expected: Vec<_> = LINE.split(s.as_bytes()).map(ref_str).collect();
+            let expected: Vec<_> = split(&LINE, s.as_bytes());
             if lines != expected {
                 eprintln!(
                     "Failed after {} runs. lines.len={}, expected.len={}",
@@ -0,0 +1,204 @@
+//! Reads that give up after a timeout.
+//!
+//! Files and commands read via `getline` can be given a timeout through `PROCINFO`, in which case
+//! a read that does not produce any data in time fails with `ErrorKind::TimedOut` instead of
+//! blocking. There is no portable way to put a deadline on a blocking read, so once a timeout is
+//! set the underlying reader is moved to a background thread that forwards its data over a
+//! channel. Nothing is lost when a read times out: the data arrives on a later read.
+//!
+//! Opening a FIFO blocks until something opens it for writing, so those are opened lazily (see
+//! `TimedReader::deferred`), on the background thread if there is a timeout.
+use std::io::{self, ErrorKind, Read};
+use std::time::Duration;
+
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError};
+
+// Size of the chunks read by the background thread, and the number of chunks it may read ahead.
+const CHUNK_SIZE: usize = 64 << 10;
+const READ_AHEAD: usize = 4;
+
+type Opener<R> = Box<dyn FnOnce() -> io::Result<R> + Send>;
+
+enum Source<R> {
+    Direct(R),
+    Unopened(Opener<R>),
+    Threaded(Receiver<io::Result<Vec<u8>>>),
+    Done,
+}
+
+pub struct TimedReader<R> {
+    source: Source<R>,
+    timeout: Option<Duration>,
+    pending: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read + Send + 'static> TimedReader<R> {
+    pub fn new(r: R) -> Self {
+        TimedReader {
+            source: Source::Direct(r),
+            timeout: None,
+            pending: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// A reader that calls `open` on the first read. A read that times out while `open` is still
+    /// blocked fails with `ErrorKind::TimedOut`, like any other.
+    pub fn deferred(open: impl FnOnce() -> io::Result<R> + Send + 'static) -> Self {
+        TimedReader {
+            source: Source::Unopened(Box::new(open)),
+            timeout: None,
+            pending: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    fn spawn(open: Opener<R>) -> Receiver<io::Result<Vec<u8>>> {
+        let (sender, receiver) = bounded(READ_AHEAD);
+        std::thread::spawn(move || {
+            let mut r = match open() {
+                Ok(r) => r,
+                Err(e) => {
+                    let _ = sender.send(Err(e));
+                    return;
+                }
+            };
+            loop {
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                match r.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        buf.truncate(n);
+                        if sender.send(Ok(buf)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                }
+            }
+        });
+        receiver
+    }
+}
+
+impl<R: Read + Send + 'static> Read for TimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.pending.len() {
+            let source = std::mem::replace(&mut self.source, Source::Done);
+            self.source = match (source, self.timeout) {
+                (Source::Unopened(open), None) => Source::Direct(open()?),
+                (Source::Unopened(open), Some(_)) => Source::Threaded(Self::spawn(open)),
+                (Source::Direct(r), Some(_)) => Source::Threaded(Self::spawn(Box::new(|| Ok(r)))),
+                (source, _) => source,
+            };
+            let receiver = match &mut self.source {
+                Source::Direct(r) => return r.read(buf),
+                Source::Threaded(receiver) => receiver,
+                Source::Unopened(_) | Source::Done => return Ok(0),
+            };
+            let next = match self.timeout {
+                Some(timeout) => match receiver.recv_timeout(timeout) {
+                    Ok(next) => Some(next),
+                    Err(RecvTimeoutError::Timeout) => {
+                        return Err(io::Error::new(ErrorKind::TimedOut, "read timed out"))
+                    }
+                    Err(RecvTimeoutError::Disconnected) => None,
+                },
+                None => receiver.recv().ok(),
+            };
+            match next {
+                Some(Ok(data)) => {
+                    self.pending = data;
+                    self.pos = 0;
+                }
+                Some(Err(e)) => {
+                    self.source = Source::Done;
+                    return Err(e);
+                }
+                None => {
+                    self.source = Source::Done;
+                    return Ok(0);
+                }
+            }
+        }
+        let n = buf.len().min(self.pending.len() - self.pos);
+        buf[..n].copy_from_slice(&self.pending[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    // Produces "a\n", then stalls for `delay` before producing "b\n".
+    struct Slow {
+        delay: Duration,
+        step: usize,
+    }
+
+    impl Read for Slow {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.step += 1;
+            match self.step {
+                1 => {
+                    buf[..2].copy_from_slice(b"a\n");
+                    Ok(2)
+                }
+                2 => {
+                    std::thread::sleep(self.delay);
+                    buf[..2].copy_from_slice(b"b\n");
+                    Ok(2)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn times_out_without_losing_data() {
+        let mut r = TimedReader::new(Slow {
+            delay: Duration::from_millis(300),
+            step: 0,
+        });
+        r.set_timeout(Some(Duration::from_millis(50)));
+        let mut buf = [0u8; 16];
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        let start = Instant::now();
+        assert_eq!(r.read(&mut buf).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_millis(250));
+        r.set_timeout(None);
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"b\n");
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn times_out_while_opening() {
+        // Stands in for a FIFO that nothing has opened for writing yet.
+        let mut r = TimedReader::deferred(|| {
+            std::thread::sleep(Duration::from_millis(300));
+            Ok(io::Cursor::new(b"a\n".to_vec()))
+        });
+        r.set_timeout(Some(Duration::from_millis(50)));
+        let mut buf = [0u8; 16];
+        let start = Instant::now();
+        assert_eq!(r.read(&mut buf).unwrap_err().kind(), ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_millis(250));
+        r.set_timeout(None);
+        assert_eq!(r.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"a\n");
+        assert_eq!(r.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -0,0 +1,197 @@
+//! A bump allocator for short-lived string buffers.
+//!
+//! Most strings built while processing a record (concatenations, numbers, substrings and the
+//! results of functions like `toupper`) are dropped before the next record is read. With
+//! `--str-arena`, the buffers for small strings of this kind are carved out of 64KiB blocks
+//! instead of being allocated one by one. Each block counts the buffers still alive in it; once
+//! they have all been dropped, which for per-record temporaries happens by the end of each
+//! iteration of the main loop, the block is reset and reused from the start.
+//!
+//! A buffer that outlives its record keeps the whole block alive, so strings stored in arrays are
+//! copied out of the arena (see `map_key`). Buffers never leave the thread that allocated them:
+//! strings handed to another thread (as `UniqueStr`) are copied to the heap as well.
+use std::alloc::{alloc, dealloc, Layout};
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+const BLOCK_SIZE: usize = 64 << 10;
+
+/// Buffers larger than this (including their header) always come from the heap.
+pub(crate) const MAX_ALLOC: usize = 1 << 10;
+
+// Empty blocks kept around for reuse, per thread.
+const MAX_SPARE: usize = 4;
+
+/// `--str-arena`: allocate short-lived string buffers from per-thread blocks.
+pub fn set_enabled(on: bool) {
+    ENABLED.store(on, Ordering::Relaxed);
+}
+
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+#[repr(C)]
+struct Block {
+    // The number of buffers allocated from this block that have not been freed.
+    live: usize,
+    // The offset of the next allocation.
+    used: usize,
+}
+
+const START: usize = mem::size_of::<Block>();
+
+fn block_layout() -> Layout {
+    Layout::from_size_align(BLOCK_SIZE, BLOCK_SIZE).unwrap()
+}
+
+struct Arena {
+    // The block that allocations come from, or null before the first one.
+    current: Cell<*mut Block>,
+    spare: RefCell<Vec<*mut Block>>,
+}
+
+impl Arena {
+    unsafe fn new_block(&self) -> *mut Block {
+        let block = match self.spare.borrow_mut().pop() {
+            Some(block) => block,
+            None => {
+                let block = alloc(block_layout()) as *mut Block;
+                assert!(!block.is_null());
+                block
+            }
+        };
+        ptr::write(
+            block,
+            Block {
+                live: 0,
+                used: START,
+            },
+        );
+        block
+    }
+
+    // Called once a block other than the current one has no live buffers left.
+    unsafe fn recycle(&self, block: *mut Block) {
+        let mut spare = self.spare.borrow_mut();
+        if spare.len() < MAX_SPARE {
+            spare.push(block);
+        } else {
+            dealloc(block as *mut u8, block_layout());
+        }
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe {
+            // Blocks that still hold live buffers are leaked: whatever holds those buffers will
+            // try to free them later.
+            let current = self.current.get();
+            if !current.is_null() && (*current).live == 0 {
+                dealloc(current as *mut u8, block_layout());
+            }
+            for block in self.spare.get_mut().drain(..) {
+                dealloc(block as *mut u8, block_layout());
+            }
+        }
+    }
+}
+
+thread_local! {
+    static ARENA: Arena = const {
+        Arena {
+            current: Cell::new(ptr::null_mut()),
+            spare: RefCell::new(Vec::new()),
+        }
+    };
+}
+
+/// Allocate `size` zeroed bytes aligned to 8 bytes, or return null if `size` is larger than
+/// `MAX_ALLOC` or the thread is shutting down.
+pub(crate) fn alloc_zeroed(size: usize) -> *mut u8 {
+    if size > MAX_ALLOC {
+        return ptr::null_mut();
+    }
+    let size = (size + 7) & !7;
+    ARENA
+        .try_with(|arena| unsafe {
+            let mut block = arena.current.get();
+            if block.is_null() || (*block).used + size > BLOCK_SIZE {
+                if !block.is_null() && (*block).live == 0 {
+                    (*block).used = START;
+                } else {
+                    // Any buffers left in the old block return it to the arena when they are
+                    // freed.
+                    block = arena.new_block();
+                    arena.current.set(block);
+                }
+            }
+            let res = (block as *mut u8).add((*block).used);
+            (*block).used += size;
+            (*block).live += 1;
+            ptr::write_bytes(res, 0, size);
+            res
+        })
+        .unwrap_or(ptr::null_mut())
+}
+
+/// Free a buffer returned by `alloc_zeroed`. It must be freed on the thread that allocated it.
+pub(crate) unsafe fn free(p: *mut u8) {
+    let block = (p as usize & !(BLOCK_SIZE - 1)) as *mut Block;
+    (*block).live -= 1;
+    if (*block).live != 0 {
+        return;
+    }
+    // If the thread is shutting down, the block is simply leaked.
+    let _ = ARENA.try_with(|arena| {
+        if arena.current.get() == block {
+            (*block).used = START;
+        } else {
+            arena.recycle(block);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_are_reused() {
+        unsafe {
+            let a = alloc_zeroed(24);
+            let b = alloc_zeroed(10);
+            assert!(!a.is_null() && !b.is_null());
+            assert_eq!(b as usize - a as usize, 24);
+            assert!(alloc_zeroed(MAX_ALLOC + 1).is_null());
+            free(a);
+            free(b);
+            // Everything in the block was freed, so it starts over.
+            let c = alloc_zeroed(8);
+            assert_eq!(c, a);
+
+            // Fill up the block while `c` is alive: allocation moves on to a new block, and the
+            // old one is recycled once `c` is freed.
+            let mut bufs = Vec::new();
+            for _ in 0..2 * BLOCK_SIZE / MAX_ALLOC {
+                bufs.push(alloc_zeroed(MAX_ALLOC));
+            }
+            let first = c as usize & !(BLOCK_SIZE - 1);
+            assert!(bufs
+                .iter()
+                .any(|p| *p as usize & !(BLOCK_SIZE - 1) != first));
+            free(c);
+            for p in bufs {
+                assert!(std::slice::from_raw_parts(p, MAX_ALLOC)
+                    .iter()
+                    .all(|b| *b == 0));
+                free(p);
+            }
+        }
+    }
+}
@@ -7,9 +7,10 @@
 ///
 /// TODO explain more about what is going on here.
 use crate::pushdown::FieldSet;
-use crate::runtime::{strtoi, Float, Int, SharedMap, IntMap};
+use crate::runtime::{mem_stats, str_arena, strtoi, Float, Int, SharedMap, IntMap};
+use crate::runtime::printf::{self, NumberFormat};
 
-use regex::bytes::{Captures, Regex};
+use super::regex_engine::{Captures, Regex};
 use smallvec::SmallVec;
 
 use std::alloc::{alloc_zeroed, dealloc, realloc, Layout};
@@ -17,6 +18,9 @@ use std::cell::{Cell, UnsafeCell};
 use std::hash::{Hash, Hasher};
 use std::io::{self, Write};
 use std::marker::PhantomData;
+use std::borrow::Cow;
+use std::cmp;
+use std::sync::atomic::{AtomicU8, Ordering};
 
 #[cfg(feature = "unstable")]
 use std::intrinsics::likely;
@@ -60,8 +64,10 @@ impl StrTag {
 
 // Why the repr(C)? We may rely on the lengths coming first.
 
+// packed(8): u128 is 16-byte aligned, but we view a StrRep, which is only 8-byte aligned, as an
+// Inline in place.
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
-#[repr(transparent)]
+#[repr(C, packed(8))]
 struct Inline(u128);
 
 const MAX_INLINE_SIZE: usize = 15;
@@ -356,7 +362,8 @@ impl<'a> From<Str<'a>> for UniqueStr<'a> {
             debug_assert_eq!(StrTag::Boxed, rep.get_tag());
             // We have a box in place, check its refcount
             if let Some(boxed) = rep.view_as(|b: &Boxed| {
-                if b.buf.refcount() == 1 {
+                // Buffers in the string arena cannot leave the thread.
+                if b.buf.refcount() == 1 && !b.buf.in_arena() {
                     None
                 } else {
                     // Copy a new buffer.
@@ -662,30 +669,28 @@ impl<'a> Str<'a> {
 
     /// index start from 0
     pub fn sub_str<'b>(&self, l: usize, r: usize) -> Str<'b> {
-        let text = self.as_str();
-        let len = text.chars().count();
-        if l >= len {
-            Str::default()
-        } else {
-            let end = l + r;
-            if end > len {
-                let sub: String = text.chars().skip(l).take(len - l).collect();
-                Str::from(sub)
-            } else {
-                let sub: String = text.chars().skip(l).take(r).collect();
-                Str::from(sub)
-            }
+        if text_units() == TextUnits::Graphemes {
+            let sub: String = self.as_str().graphemes(true).skip(l).take(r).collect();
+            return Str::from(sub);
         }
+        self.with_bytes(|bs| {
+            let start = char_offset(bs, l);
+            let end = start + char_offset(&bs[start..], r);
+            copy_bytes(&bs[start..end])
+        })
     }
 
-    /// index start from 0
+    /// index start from 0; see [`char_at_error`] for indexes passed to `char_at` below 1.
     pub fn char_at<'b>(&self, index: usize) -> Str<'b> {
-        let text = self.as_str();
-        if let Some(c) = text.chars().nth(index) {
-            Str::from(c.to_string())
-        } else {
-            Str::default()
+        if text_units() == TextUnits::Graphemes {
+            let text = self.as_str();
+            return text.graphemes(true).nth(index).map(|g| Str::from(g.to_string())).unwrap_or_default();
         }
+        self.with_bytes(|bs| {
+            let start = char_offset(bs, index);
+            let end = start + char_offset(&bs[start..], 1);
+            copy_bytes(&bs[start..end])
+        })
     }
 
     pub(crate) fn words<'b>(&self) -> IntMap<Str<'b>> {
@@ -750,6 +755,22 @@ impl<'a> Str<'a> {
         };
     }
 
+    /// `toupper`: ASCII letters only, unless `--unicode` is on.
+    pub fn to_upper<'b>(&self) -> Str<'b> {
+        match text_units() {
+            TextUnits::Default => self.to_upper_ascii(),
+            TextUnits::Chars | TextUnits::Graphemes => Str::from(self.as_str().to_uppercase()),
+        }
+    }
+
+    /// `tolower`: ASCII letters only, unless `--unicode` is on.
+    pub fn to_lower<'b>(&self) -> Str<'b> {
+        match text_units() {
+            TextUnits::Default => self.to_lower_ascii(),
+            TextUnits::Chars | TextUnits::Graphemes => Str::from(self.as_str().to_lowercase()),
+        }
+    }
+
     pub fn to_upper_ascii<'b>(&self) -> Str<'b> {
         self.map_bytes(|b| match b {
             b'a'..=b'z' => b - b'a' + b'A',
@@ -896,8 +917,10 @@ impl<'a> Str<'a> {
     }
 
     pub fn len(&self) -> usize {
-        // todo performance
-        self.as_str().chars().count()
+        match text_units() {
+            TextUnits::Graphemes => self.as_str().graphemes(true).count(),
+            TextUnits::Default | TextUnits::Chars => self.with_bytes(char_count),
+        }
     }
 
     pub fn concat(left: Str<'a>, right: Str<'a>) -> Str<'a> {
@@ -1017,7 +1040,7 @@ impl<'a> Str<'a> {
             // just called `force`
             debug_assert_eq!(tag, StrTag::Boxed);
             return Str::from_rep(rep.view_as(|b: &Boxed| {
-                let buf = Buf::read_from_raw(b.buf.as_ptr().add(from), new_len);
+                let buf = Buf::read_temp(b.buf.as_ptr().add(from), new_len);
                 Boxed {
                     len: new_len as u64,
                     buf,
@@ -1073,7 +1096,7 @@ impl<'a> Str<'a> {
             return;
         }
         let mut whead = 0;
-        let mut res = UniqueBuf::new(len);
+        let mut res = UniqueBuf::new_temp(len);
         macro_rules! push_bytes {
             ($slice:expr, [$from:expr, $to:expr]) => {{
                 let from = $from;
@@ -1154,6 +1177,43 @@ impl<'a> Str<'a> {
         unsafe { f(&*raw) }
     }
 
+    /// Whether this string is a concatenation that has not been flattened yet, so that reading
+    /// its bytes with `with_bytes` would allocate a buffer for them.
+    pub(crate) fn is_concat(&self) -> bool {
+        unsafe { self.rep().get_tag() == StrTag::Concat }
+    }
+
+    /// Whether the bytes of this string live in the string arena (see str_arena). The pieces of
+    /// a concatenation are not looked at.
+    pub(crate) fn in_arena(&self) -> bool {
+        unsafe {
+            let rep = self.rep_mut();
+            match rep.get_tag() {
+                StrTag::Boxed => rep.view_as(|b: &Boxed| b.buf.in_arena()),
+                StrTag::Shared => rep.view_as(|s: &Shared| s.buf.in_arena()),
+                StrTag::Inline | StrTag::Literal | StrTag::Concat => false,
+            }
+        }
+    }
+
+    /// Append the contents of the string to `out`, leaving concatenations as they are.
+    pub(crate) fn write_flat(&self, out: &mut Vec<u8>) {
+        let mut todos = SmallVec::<[Str<'a>; 16]>::new();
+        todos.push(self.clone());
+        while let Some(cur) = todos.pop() {
+            if cur.is_concat() {
+                unsafe {
+                    cur.rep_mut().view_as(|c: &Concat<'a>| {
+                        todos.push(c.right());
+                        todos.push(c.left());
+                    })
+                }
+            } else {
+                cur.with_bytes(|bs| out.extend_from_slice(bs));
+            }
+        }
+    }
+
     pub fn unmoor(self) -> Str<'static> {
         let rep = unsafe { self.rep_mut() };
         let tag = rep.get_tag();
@@ -1169,10 +1229,162 @@ impl<'a> Str<'a> {
         unsafe { mem::transmute::<Str<'a>, Str<'static>>(self) }
     }
 
+    /// The string as UTF-8 text. Invalid UTF-8 is reported as an error (see
+    /// [`errors`](crate::runtime::errors)), and the text up to the first invalid byte is returned.
     pub fn as_str(&self) -> &'a str {
         let bs = unsafe { &*self.get_bytes() };
-        str::from_utf8(bs).unwrap()
+        match str::from_utf8(bs) {
+            Ok(s) => s,
+            Err(e) => {
+                runtime::errors::report_deferred(format!("string is not valid UTF-8: {}", e));
+                unsafe { str::from_utf8_unchecked(&bs[..e.valid_up_to()]) }
+            }
+        }
+    }
+
+    /// Compare strings as `<`, `==` and the other comparison operators do: by their bytes, ignoring
+    /// case under `IGNORECASE`.
+    pub fn compare(&self, other: &Str) -> cmp::Ordering {
+        self.with_bytes(|l| other.with_bytes(|r| case_folded(l).cmp(&case_folded(r))))
+    }
+}
+
+/// The number of characters in `bs`. Bytes that are not part of valid UTF-8 count as one
+/// character each, so `length`, `substr` and `char_at` work on binary data as well.
+fn char_count(bs: &[u8]) -> usize {
+    bs.utf8_chunks()
+        .map(|chunk| chunk.valid().chars().count() + chunk.invalid().len())
+        .sum()
+}
+
+/// The byte offset of the `n`th character of `bs` (counted as in [`char_count`]), or the length of
+/// `bs` if it has no more than `n` characters.
+fn char_offset(bs: &[u8], n: usize) -> usize {
+    let (mut seen, mut off) = (0, 0);
+    for chunk in bs.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if seen == n {
+                return off;
+            }
+            seen += 1;
+            off += c.len_utf8();
+        }
+        let invalid = chunk.invalid().len();
+        if n - seen < invalid {
+            return off + (n - seen);
+        }
+        seen += invalid;
+        off += invalid;
+    }
+    off
+}
+
+fn copy_bytes<'b>(bs: &[u8]) -> Str<'b> {
+    let mut buf = DynamicBuf::new(bs.len());
+    buf.write_all(bs).unwrap();
+    buf.into_str()
+}
+
+/// What the string builtins count, set with `--unicode`.
+///
+/// By default `length`, `substr` and `char_at` count characters, while `index`, `last_index` and
+/// the `RSTART` and `RLENGTH` of `match` count bytes, and `toupper` and `tolower` only change ASCII
+/// letters. In the other modes all of them count the same units and case mapping covers Unicode.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum TextUnits {
+    Default = 0,
+    Chars = 1,
+    Graphemes = 2,
+}
+
+static TEXT_UNITS: AtomicU8 = AtomicU8::new(TextUnits::Default as u8);
+
+impl TextUnits {
+    /// The mode for `--unicode=name`, or for `UNICODE=name` in the environment.
+    pub(crate) fn from_name(name: &str) -> Option<TextUnits> {
+        match name {
+            "1" | "chars" => Some(TextUnits::Chars),
+            "graphemes" => Some(TextUnits::Graphemes),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn set_text_units(units: TextUnits) {
+    TEXT_UNITS.store(units as u8, Ordering::Relaxed);
+}
+
+#[inline]
+pub(crate) fn text_units() -> TextUnits {
+    match TEXT_UNITS.load(Ordering::Relaxed) {
+        1 => TextUnits::Chars,
+        2 => TextUnits::Graphemes,
+        _ => TextUnits::Default,
+    }
+}
+
+/// The number of units in `bs` that positions in `index` and `match` count: bytes by default, or
+/// characters or grapheme clusters with `--unicode`.
+pub(crate) fn position_units(bs: &[u8]) -> usize {
+    count_units(bs, text_units())
+}
+
+fn count_units(bs: &[u8], units: TextUnits) -> usize {
+    match units {
+        TextUnits::Default => bs.len(),
+        TextUnits::Chars => bs.iter().filter(|&&b| (b as i8) >= -0x40).count(),
+        TextUnits::Graphemes => String::from_utf8_lossy(bs).graphemes(true).count(),
+    }
+}
+
+thread_local! {
+    // A copy of IGNORECASE for comparisons and regex matching, which never see the variables.
+    static IGNORE_CASE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Set by assignments to `IGNORECASE`: whether regexes, string comparisons and `index` ignore
+/// the case of letters.
+pub(crate) fn set_ignore_case(on: bool) {
+    IGNORE_CASE.with(|c| c.set(on))
+}
+
+#[inline]
+pub(crate) fn ignore_case() -> bool {
+    IGNORE_CASE.with(|c| c.get())
+}
+
+/// `bs` with its letters in lower case under `IGNORECASE`, and as it is otherwise.
+///
+/// Letters whose lower case has a different length in UTF-8 are left alone, so offsets into the
+/// result are offsets into `bs`. Bytes that are not valid UTF-8 are kept as they are.
+pub(crate) fn case_folded(bs: &[u8]) -> Cow<'_, [u8]> {
+    if !ignore_case() || !bs.iter().any(|b| b.is_ascii_uppercase() || !b.is_ascii()) {
+        return Cow::Borrowed(bs);
+    }
+    let mut res = Vec::with_capacity(bs.len());
+    for chunk in bs.utf8_chunks() {
+        let mut buf = [0u8; 4];
+        for c in chunk.valid().chars() {
+            let mut lower = c.to_lowercase();
+            let c = match (lower.next(), lower.next()) {
+                (Some(l), None) if l.len_utf8() == c.len_utf8() => l,
+                _ => c,
+            };
+            res.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        res.extend_from_slice(chunk.invalid());
     }
+    Cow::Owned(res)
+}
+
+/// Whether `s` starts with `prefix`, ignoring case under `IGNORECASE`.
+pub(crate) fn starts_with(s: &[u8], prefix: &[u8]) -> bool {
+    prefix.len() <= s.len() && case_folded(&s[..prefix.len()]) == case_folded(prefix)
+}
+
+/// The error for `char_at(s, index)` with an index below 1.
+pub(crate) fn char_at_error(index: Int) -> String {
+    format!("char_at: index {} is out of range, indexes start at 1", index)
 }
 
 impl<'a> Clone for Str<'a> {
@@ -1257,30 +1469,49 @@ impl<'a> From<String> for Str<'a> {
     }
 }
 
-// For numbers, we are careful to check if a number only requires 15 digits or fewer to be
-// represented. This allows us to trigger the "Inline" variant and avoid a heap allocation,
-// sometimes at the expenseof a small copy.
+// Numbers rarely need more than 15 bytes, which fit in the "Inline" variant without a heap
+// allocation.
 
 impl<'a> From<Int> for Str<'a> {
     fn from(i: Int) -> Str<'a> {
         let mut itoabuf = itoa::Buffer::new();
-        let s = itoabuf.format(i);
-        Buf::read_from_bytes(s.as_bytes()).into_str()
+        Str::number(itoabuf.format(i).as_bytes())
     }
 }
 
 impl<'a> From<Float> for Str<'a> {
     fn from(f: Float) -> Str<'a> {
+        Str::from_float(f, NumberFormat::Convert)
+    }
+}
+
+// Below this, every integer is exactly representable as a Float.
+const EXACT_INT_FLOAT: Float = (1u64 << 53) as Float;
+
+impl<'a> Str<'a> {
+    /// Copy the text of a number into a new string.
+    pub(crate) fn number(bs: &[u8]) -> Str<'a> {
+        if bs.len() <= MAX_INLINE_SIZE {
+            Str::from_rep(unsafe { Inline::from_unchecked(bs).into() })
+        } else {
+            unsafe { Buf::read_temp(bs.as_ptr(), bs.len()) }.into_str()
+        }
+    }
+
+    /// Convert `f` to a string, using CONVFMT or OFMT if the script set them.
+    pub(crate) fn from_float(f: Float, fmt: NumberFormat) -> Str<'a> {
+        // Integral values print as integers, whatever the format says. Skip Ryu for them as well;
+        // it would print the same digits followed by ".0". -0 is left to Ryu to keep its sign.
+        if f.fract() == 0.0 && f.abs() < EXACT_INT_FLOAT && (f != 0.0 || f.is_sign_positive()) {
+            return Str::from(f as Int);
+        }
+        if let Some(s) = printf::format_number(fmt, f) {
+            return s;
+        }
         let mut ryubuf = ryu::Buffer::new();
-        let s = ryubuf.format(f);
-        let slen = s.len();
+        let s = ryubuf.format(f).as_bytes();
         // Print Float as Int if it ends in ".0".
-        let slen = if &s.as_bytes()[slen - 2..] == b".0" {
-            slen - 2
-        } else {
-            slen
-        };
-        Buf::read_from_bytes(&s.as_bytes()[..slen]).into_str()
+        Str::number(s.strip_suffix(b".0").unwrap_or(s))
     }
 }
 
@@ -1297,11 +1528,23 @@ impl Str<'static> {
 
 #[repr(C)]
 struct BufHeader {
+    // The high bit is set for buffers allocated from the string arena (see str_arena).
     size: usize,
     // We only have "strong counts"
     count: Cell<usize>,
 }
 
+const ARENA_BIT: usize = 1 << (usize::BITS - 1);
+
+impl BufHeader {
+    fn size(&self) -> usize {
+        self.size & !ARENA_BIT
+    }
+    fn in_arena(&self) -> bool {
+        self.size & ARENA_BIT != 0
+    }
+}
+
 #[repr(transparent)]
 pub struct UniqueBuf(*mut BufHeader);
 
@@ -1315,12 +1558,12 @@ pub struct DynamicBufHeap {
 impl DynamicBufHeap {
     pub fn new(size: usize) -> DynamicBufHeap {
         DynamicBufHeap {
-            data: UniqueBuf::new(size),
+            data: UniqueBuf::new_temp(size),
             write_head: 0,
         }
     }
     fn size(&self) -> usize {
-        unsafe { (*self.data.0).size }
+        unsafe { (*self.data.0).size() }
     }
     pub fn as_mut_bytes(&mut self) -> &mut [u8] {
         self.data.as_mut_bytes()
@@ -1342,6 +1585,22 @@ impl DynamicBufHeap {
         if cap == new_cap {
             return;
         }
+        if (*self.data.0).in_arena() {
+            if new_cap < cap {
+                // Leave the tail of the buffer unused.
+                (*self.data.0).size = new_cap | ARENA_BIT;
+                mem_stats::string_bytes(new_cap as isize - cap as isize);
+            } else {
+                let mut new_data = UniqueBuf::new_temp(new_cap);
+                ptr::copy_nonoverlapping(
+                    self.data.as_mut_ptr(),
+                    new_data.as_mut_ptr(),
+                    self.write_head,
+                );
+                self.data = new_data;
+            }
+            return;
+        }
         let new_buf = realloc(
             self.data.0 as *mut u8,
             UniqueBuf::layout(cap),
@@ -1349,6 +1608,7 @@ impl DynamicBufHeap {
         ) as *mut BufHeader;
         (*new_buf).size = new_cap;
         self.data.0 = new_buf;
+        mem_stats::string_bytes(new_cap as isize - cap as isize);
     }
 
     fn push_byte(&mut self, b: u8) {
@@ -1481,7 +1741,12 @@ impl Drop for UniqueBuf {
     fn drop(&mut self) {
         let header: &mut BufHeader = unsafe { &mut (*self.0) };
         debug_assert_eq!(header.count.get(), 1);
-        unsafe { dealloc(self.0 as *mut u8, UniqueBuf::layout(header.size)) }
+        mem_stats::string_bytes(-(header.size() as isize));
+        if header.in_arena() {
+            unsafe { str_arena::free(self.0 as *mut u8) }
+        } else {
+            unsafe { dealloc(self.0 as *mut u8, UniqueBuf::layout(header.size)) }
+        }
     }
 }
 
@@ -1508,6 +1773,7 @@ impl UniqueBuf {
     }
     pub fn new(size: usize) -> UniqueBuf {
         let layout = UniqueBuf::layout(size);
+        mem_stats::string_bytes(size as isize);
         unsafe {
             let alloced = alloc_zeroed(layout) as *mut BufHeader;
             assert!(!alloced.is_null());
@@ -1521,10 +1787,32 @@ impl UniqueBuf {
             UniqueBuf(alloced)
         }
     }
+    /// A buffer for a string that is likely to be dropped soon. With `--str-arena`, small ones
+    /// come from the string arena. The buffer must not be sent to another thread.
+    pub(crate) fn new_temp(size: usize) -> UniqueBuf {
+        if !str_arena::enabled() {
+            return UniqueBuf::new(size);
+        }
+        let alloced = str_arena::alloc_zeroed(UniqueBuf::layout(size).size()) as *mut BufHeader;
+        if alloced.is_null() {
+            return UniqueBuf::new(size);
+        }
+        mem_stats::string_bytes(size as isize);
+        unsafe {
+            ptr::write(
+                alloced,
+                BufHeader {
+                    size: size | ARENA_BIT,
+                    count: Cell::new(1),
+                },
+            );
+        }
+        UniqueBuf(alloced)
+    }
     pub fn as_mut_bytes(&mut self) -> &mut [u8] {
         let header: &BufHeader = unsafe { &(*self.0) };
         debug_assert_eq!(header.count.get(), 1);
-        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), header.size) }
+        unsafe { slice::from_raw_parts_mut(self.as_mut_ptr(), header.size()) }
     }
     pub fn as_mut_ptr(&mut self) -> *mut u8 {
         let header: &BufHeader = unsafe { &(*self.0) };
@@ -1550,7 +1838,11 @@ impl Buf {
     }
 
     pub(crate) fn len(&self) -> usize {
-        unsafe { &(*self.0) }.size
+        unsafe { &(*self.0) }.size()
+    }
+
+    fn in_arena(&self) -> bool {
+        unsafe { &(*self.0) }.in_arena()
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -1616,6 +1908,14 @@ impl Buf {
     pub fn read_from_bytes(s: &[u8]) -> Buf {
         unsafe { Buf::read_from_raw(s.as_ptr(), s.len()) }
     }
+
+    /// Like `read_from_raw`, for a string that is likely to be dropped soon (see
+    /// `UniqueBuf::new_temp`).
+    pub(crate) unsafe fn read_temp(ptr: *const u8, len: usize) -> Buf {
+        let mut ubuf = UniqueBuf::new_temp(len);
+        ptr::copy_nonoverlapping(ptr, ubuf.as_mut_ptr(), len);
+        ubuf.into_buf()
+    }
     pub fn try_unique(self) -> Result<UniqueBuf, Buf> {
         if self.refcount() == 1 {
             let res = UniqueBuf(self.0 as *mut _);
@@ -1733,6 +2033,43 @@ mod tests {
         s1.with_bytes(|bs1| assert_eq!(bs1, b"h"));
     }
 
+    #[test]
+    fn inline_alignment() {
+        // Str and UniqueStr are transmuted to &Inline, so Inline may not demand more alignment
+        // than StrRep has.
+        assert!(mem::align_of::<Inline>() <= mem::align_of::<StrRep>());
+        // Every other element here starts at an address that is 8- but not 16-byte aligned.
+        let strs: Vec<(u64, Str)> = (0..8).map(|i| (i, Str::from(i as Int))).collect();
+        for (i, s) in &strs {
+            assert!(!s.is_empty());
+            s.with_bytes(|bs| assert_eq!(bs, i.to_string().as_bytes()));
+        }
+    }
+
+    #[test]
+    fn text_units() {
+        let text = "héllo 世界 e\u{301}".as_bytes();
+        assert_eq!(count_units(text, TextUnits::Default), 17);
+        assert_eq!(count_units(text, TextUnits::Chars), 11);
+        assert_eq!(count_units(text, TextUnits::Graphemes), 10);
+        assert_eq!(TextUnits::from_name("1"), Some(TextUnits::Chars));
+        assert_eq!(TextUnits::from_name("0"), None);
+    }
+
+    #[test]
+    fn ignore_case() {
+        let (a, b) = (Str::from("Straße"), Str::from("STRASSE"));
+        assert_eq!(a.compare(&Str::from("straße")), cmp::Ordering::Less);
+        set_ignore_case(true);
+        assert!(a.compare(&Str::from("straße")).is_eq());
+        assert!(a.compare(&b).is_gt());
+        // The Kelvin sign lowercases to a shorter `k`, so it is kept to preserve offsets.
+        assert_eq!(&*case_folded(b"\xe2\x84\xaaAb\xff"), b"\xe2\x84\xaaab\xff");
+        assert!(starts_with(b"HELLO world", b"hello"));
+        set_ignore_case(false);
+        assert!(!starts_with(b"HELLO world", b"hello"));
+    }
+
     #[test]
     fn basic_behavior() {
         let base_1 = b"hi there fellow";
@@ -1764,7 +2101,8 @@ mod tests {
 
     fn test_str_split(pat: &Regex, base: &[u8]) {
         let s = Str::from(base);
-        let want = pat
+        let want = regex::bytes::Regex::new(pat.as_str())
+            .unwrap()
             .split(base)
             .skip_while(|x| x.is_empty())
             .collect::<Vec<_>>();
@@ -1938,6 +2276,30 @@ mod bench {
         bench_max_min(b, i64::min_value(), i64::max_value())
     }
 
+    fn bench_floats(b: &mut Bencher, f: impl Fn(i64) -> Float) {
+        use rand::{thread_rng, Rng};
+        let mut rng = thread_rng();
+        let size = 1 << 12;
+        let v: Vec<Float> = (0..size).map(|_| f(rng.gen_range(-99999..=99999))).collect();
+        let mut i = 0;
+        b.iter(|| {
+            let n = unsafe { *v.get_unchecked(i) };
+            i += 1;
+            i &= size - 1;
+            black_box(Str::from(n))
+        })
+    }
+
+    #[bench]
+    fn bench_ftoa_integral(b: &mut Bencher) {
+        bench_floats(b, |i| i as Float)
+    }
+
+    #[bench]
+    fn bench_ftoa_fraction(b: &mut Bencher) {
+        bench_floats(b, |i| i as Float / 64.0)
+    }
+
     #[bench]
     fn bench_get_bytes_drop_empty(b: &mut Bencher) {
         b.iter(|| {
@@ -2034,9 +2396,8 @@ mod formatting {
         fn fmt(&self, f: &mut Formatter) -> fmt::Result {
             write!(
                 f,
-                "Literal {{ len: {}, ptr: {:x}=>{:?} }}",
+                "Literal {{ len: {}, {:?} }}",
                 self.len,
-                self.ptr as usize,
                 str::from_utf8(unsafe { slice::from_raw_parts(self.ptr, self.len as usize) })
                     .unwrap(),
             )
@@ -2049,7 +2410,7 @@ mod formatting {
             write!(
                 f,
                 "Buf {{ size: {}, count: {}, contents: {:?} }}",
-                header.size,
+                header.size(),
                 header.count.get(),
                 self.as_bytes(),
             )
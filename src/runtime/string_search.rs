@@ -1,20 +1,30 @@
 //! Implementation of substring searches.
 //!
 //! This is a tiny wrapper on top of `memmem::find` from the `memchr` crate.
+use super::str_impl::{case_folded, position_units};
 use super::{Int, Str};
 use memchr::memmem;
 
-// 1-indexed, 0 on failure
+// 1-indexed, 0 on failure. Positions count bytes, or characters with `--unicode`. Under
+// `IGNORECASE` both strings are case folded first, which keeps the offsets of the haystack.
 pub fn index_substr<'a>(needle: &Str<'a>, haystack: &Str<'a>) -> Int {
     needle
-        .with_bytes(|n| haystack.with_bytes(|h| memmem::find(h, n)))
+        .with_bytes(|n| {
+            haystack.with_bytes(|h| {
+                memmem::find(&case_folded(h), &case_folded(n)).map(|x| position_units(&h[..x]))
+            })
+        })
         .map(|x| x as Int + 1)
         .unwrap_or(0)
 }
 
 pub fn last_index_substr<'a>(needle: &Str<'a>, haystack: &Str<'a>) -> Int {
     needle
-        .with_bytes(|n| haystack.with_bytes(|h| memmem::rfind(h, n)))
+        .with_bytes(|n| {
+            haystack.with_bytes(|h| {
+                memmem::rfind(&case_folded(h), &case_folded(n)).map(|x| position_units(&h[..x]))
+            })
+        })
         .map(|x| x as Int + 1)
         .unwrap_or(0)
 }
@@ -36,15 +36,232 @@ pub fn strcmp(text1: &str, text2: &str) -> i64 {
     };
 }
 
-pub fn read_all(path: &str) -> String {
-    let mut reader = oneio::get_reader(path).unwrap();
-    let mut text = "".to_string();
-    reader.read_to_string(&mut text).unwrap();
-    text
+/// Natural order comparison, like `strcmp` except that runs of digits compare by their value,
+/// so `natcmp("file10", "file2")` is 1.
+pub fn natcmp(text1: &str, text2: &str) -> i64 {
+    crate::runtime::math_util::natural_cmp(text1.as_bytes(), text2.as_bytes()) as i64
 }
 
-pub fn write_all(path: &str, content: &str) {
-    std::fs::write(path, content).unwrap()
+/// Edit distance between two texts, counted in characters.
+pub fn levenshtein(text1: &str, text2: &str) -> Int {
+    strsim::levenshtein(text1, text2) as Int
+}
+
+/// Levenshtein similarity from 0 (nothing in common) to 1 (equal), relative to the longer text.
+pub fn similarity(text1: &str, text2: &str) -> f64 {
+    strsim::normalized_levenshtein(text1, text2)
+}
+
+/// Line-based unified diff from `text1` to `text2`, with three lines of context; empty when the texts are equal.
+pub fn diff(text1: &str, text2: &str) -> String {
+    if text1 == text2 {
+        return String::new();
+    }
+    similar::TextDiff::from_lines(text1, text2).unified_diff().context_radius(3).header("a", "b").to_string()
+}
+
+fn ascii_letters(text: &str) -> Vec<u8> {
+    text.bytes().filter(u8::is_ascii_alphabetic).map(|b| b.to_ascii_uppercase()).collect()
+}
+
+/// American Soundex code, like `R163` for `Robert`. Characters other than ASCII letters are ignored;
+/// a text without letters gives an empty string.
+pub fn soundex(text: &str) -> String {
+    fn code(b: u8) -> u8 {
+        match b {
+            b'B' | b'F' | b'P' | b'V' => b'1',
+            b'C' | b'G' | b'J' | b'K' | b'Q' | b'S' | b'X' | b'Z' => b'2',
+            b'D' | b'T' => b'3',
+            b'L' => b'4',
+            b'M' | b'N' => b'5',
+            b'R' => b'6',
+            _ => b'0',
+        }
+    }
+    let letters = ascii_letters(text);
+    let Some(&first) = letters.first() else {
+        return String::new();
+    };
+    let mut res = vec![first];
+    let mut last = code(first);
+    for &b in &letters[1..] {
+        if res.len() == 4 {
+            break;
+        }
+        let c = code(b);
+        if c != b'0' && c != last {
+            res.push(c);
+        }
+        // H and W do not separate letters with the same code, vowels do
+        if b != b'H' && b != b'W' {
+            last = c;
+        }
+    }
+    res.resize(4, b'0');
+    String::from_utf8(res).unwrap()
+}
+
+/// Original Metaphone key, like `SM0` for `Smith` (`0` stands for "th"). Characters other than ASCII
+/// letters are ignored and the key is not truncated. As in common implementations, an initial "TH"
+/// before "OM" or "AM" is a plain T (`Thomas`, `Thames`) and a P between M and S is silent
+/// (`TMSN` for `Thompson`).
+pub fn metaphone(text: &str) -> String {
+    let is_vowel = |b: u8| matches!(b, b'A' | b'E' | b'I' | b'O' | b'U');
+    let mut w = ascii_letters(text);
+    match w.as_slice() {
+        [b'A', b'E', ..] | [b'G' | b'K' | b'P', b'N', ..] | [b'W', b'R', ..] => {
+            w.remove(0);
+        }
+        [b'W', b'H', ..] | [b'T', b'H', b'O' | b'A', b'M', ..] => {
+            w.remove(1);
+        }
+        [b'X', ..] => w[0] = b'S',
+        _ => {}
+    }
+    let at = |i: usize| w.get(i).copied().unwrap_or(0);
+    let mut res = String::new();
+    for i in 0..w.len() {
+        let (b, prev, next, next2) = (w[i], if i > 0 { at(i - 1) } else { 0 }, at(i + 1), at(i + 2));
+        if b == prev && b != b'C' {
+            continue;
+        }
+        let front_vowel = |c: u8| matches!(c, b'E' | b'I' | b'Y');
+        match b {
+            b'A' | b'E' | b'I' | b'O' | b'U' => {
+                if i == 0 {
+                    res.push(b as char)
+                }
+            }
+            b'B' => {
+                if !(prev == b'M' && i + 1 == w.len()) {
+                    res.push('B')
+                }
+            }
+            b'C' => {
+                if next == b'I' && next2 == b'A' {
+                    res.push('X')
+                } else if next == b'H' {
+                    res.push(if prev == b'S' { 'K' } else { 'X' })
+                } else if front_vowel(next) {
+                    if prev != b'S' {
+                        res.push('S')
+                    }
+                } else {
+                    res.push('K')
+                }
+            }
+            b'D' => res.push(if next == b'G' && front_vowel(next2) { 'J' } else { 'T' }),
+            b'G' => {
+                let silent = (next == b'H' && next2 != 0 && !is_vowel(next2))
+                    || (next == b'N' && (i + 2 == w.len() || w[i + 1..] == *b"NED"))
+                    || (prev == b'D' && front_vowel(next));
+                if !silent {
+                    res.push(if front_vowel(next) { 'J' } else { 'K' })
+                }
+            }
+            b'H' => {
+                let after = matches!(prev, b'C' | b'S' | b'P' | b'T' | b'G');
+                if !after && (!is_vowel(prev) || is_vowel(next)) {
+                    res.push('H')
+                }
+            }
+            b'K' => {
+                if prev != b'C' {
+                    res.push('K')
+                }
+            }
+            b'P' => {
+                if next == b'H' {
+                    res.push('F')
+                } else if !(prev == b'M' && next == b'S') {
+                    res.push('P')
+                }
+            }
+            b'Q' => res.push('K'),
+            b'S' => {
+                if next == b'H' || (next == b'I' && matches!(next2, b'O' | b'A')) {
+                    res.push('X')
+                } else {
+                    res.push('S')
+                }
+            }
+            b'T' => {
+                if next == b'I' && matches!(next2, b'O' | b'A') {
+                    res.push('X')
+                } else if next == b'H' {
+                    res.push('0')
+                } else if !(next == b'C' && next2 == b'H') {
+                    res.push('T')
+                }
+            }
+            b'V' => res.push('F'),
+            b'W' | b'Y' => {
+                if is_vowel(next) {
+                    res.push(b as char)
+                }
+            }
+            b'X' => res.push_str("KS"),
+            b'Z' => res.push('S'),
+            _ => res.push(b as char),
+        }
+    }
+    res
+}
+
+/// Whether the Jaro-Winkler similarity of the trimmed, lowercased texts reaches `threshold`, as 1 or 0.
+/// Jaro-Winkler favours a shared prefix, which suits names with typos or abbreviated endings.
+pub fn fuzzy_match(text1: &str, text2: &str, threshold: f64) -> Int {
+    let text1 = text1.trim().to_lowercase();
+    let text2 = text2.trim().to_lowercase();
+    (strsim::jaro_winkler(&text1, &text2) >= threshold) as Int
+}
+
+/// Read a file or url into memory. The contents are returned as-is, so binary files survive.
+pub fn read_all(path: &str) -> Result<Vec<u8>, String> {
+    let mut reader = oneio::get_reader(path).map_err(|e| e.to_string())?;
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Read a file or url into an array of its lines, indexed from 1. Line endings (`\n` or `\r\n`)
+/// are not kept.
+pub(crate) fn read_lines<'a>(path: &str) -> Result<IntMap<Str<'a>>, String> {
+    let bytes = read_all(path)?;
+    let res = IntMap::default();
+    let body = bytes.strip_suffix(b"\n").unwrap_or(&bytes);
+    if !bytes.is_empty() {
+        for (i, line) in body.split(|b| *b == b'\n').enumerate() {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            res.insert(i as Int + 1, Str::from(line).unmoor().upcast());
+        }
+    }
+    Ok(res)
+}
+
+/// Write `content` to the file at `path`, replacing it, or adding to the end of it if `append` is
+/// set.
+pub fn write_all(path: &str, content: &[u8], append: bool) -> std::io::Result<()> {
+    if !append {
+        return std::fs::write(path, content);
+    }
+    use std::io::Write;
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(content)
+}
+
+/// `write_all` and `append_all` for the runtime: failures are reported in ERRNO.
+pub(crate) fn write_str(path: &Str, content: &Str, append: bool) -> crate::common::Result<()> {
+    match content.with_bytes(|bs| write_all(path.as_str(), bs, append)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let name = if append { "append_all" } else { "write_all" };
+            crate::runtime::errors::report(format!("{}: {}", name, e))
+        }
+    }
 }
 
 pub(crate) fn pairs<'a>(text: &str, pair_sep: &str, kv_sep: &str) -> StrMap<'a, Str<'a>> {
@@ -125,6 +342,91 @@ impl PairState {
     }
 }
 
+/// parse logfmt: `level=info msg="hello \"world\"" took=12ms verbose`. Quoted values may contain spaces,
+/// `=` and the escapes `\"`, `\\`, `\n`, `\r` and `\t`; a key without `=` gets an empty value.
+pub(crate) fn logfmt<'a>(line: &str) -> StrMap<'a, Str<'a>> {
+    let mut map = hashbrown::HashMap::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('r') => value.push('\r'),
+                            Some('t') => value.push('\t'),
+                            Some(c @ ('"' | '\\')) => value.push(c),
+                            Some(c) => {
+                                value.push('\\');
+                                value.push(c);
+                            }
+                            None => value.push('\\'),
+                        },
+                        c => value.push(c),
+                    }
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    value.push(c);
+                }
+            }
+        }
+        if !key.is_empty() {
+            map.insert(Str::from(key), Str::from(value));
+        }
+    }
+    SharedMap::from(map)
+}
+
+/// format map as logfmt. Keys listed in `order` (comma separated) come first, the rest follow sorted.
+/// Values with spaces, `=`, `"` or control characters are quoted and escaped.
+pub(crate) fn to_logfmt<V: ToString>(obj: &StrMap<V>, order: &str) -> String {
+    let mut pairs: Vec<(String, String)> = Vec::new();
+    obj.iter(|map| {
+        for (key, value) in map {
+            pairs.push((key.to_string(), value.to_string()));
+        }
+    });
+    let rank = |key: &str| order.split(',').position(|k| !k.is_empty() && k.trim() == key).unwrap_or(usize::MAX);
+    pairs.sort_by(|(a, _), (b, _)| rank(a).cmp(&rank(b)).then_with(|| a.cmp(b)));
+    let mut out = String::new();
+    for (key, value) in pairs {
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.extend(key.chars().map(|c| if c <= ' ' || c == '=' || c == '"' { '_' } else { c }));
+        out.push('=');
+        if value.chars().any(|c| c <= ' ' || c == '=' || c == '"' || c.is_control()) {
+            out.push('"');
+            for c in value.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\r' => out.push_str("\\r"),
+                    '\t' => out.push_str("\\t"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        } else {
+            out.push_str(&value);
+        }
+    }
+    out
+}
+
 /// parse message - `msg_name{key1=value1,key2=value2}(body)`
 pub(crate) fn message(text: &str) -> StrMap<Str> {
     record(text)
@@ -389,6 +691,9 @@ pub fn is_format(format: &str, text: &str) -> Int {
                 text.parse::<Ipv4Addr>().is_ok()
             }
         }
+        "cidr" => {
+            crate::runtime::ip::is_cidr(text)
+        }
         &_ => {
             panic!("format not supported");
         }
@@ -400,6 +705,28 @@ pub fn is_format(format: &str, text: &str) -> Int {
     }
 }
 
+/// parse user agent: browser, browser_version, os, os_version, device(pc, smartphone, crawler...) and vendor.
+/// Fields woothee can't identify are left out, and an unrecognized agent gives an empty map.
+pub(crate) fn ua_parse<'a>(user_agent: &str) -> StrMap<'a, Str<'a>> {
+    let mut map = hashbrown::HashMap::new();
+    if let Some(ua) = woothee::parser::Parser::new().parse(user_agent) {
+        let fields = [
+            ("browser", ua.name),
+            ("browser_version", ua.version),
+            ("os", ua.os),
+            ("os_version", ua.os_version.as_ref()),
+            ("device", ua.category),
+            ("vendor", ua.vendor),
+        ];
+        for (key, value) in fields {
+            if !value.is_empty() && value != woothee::woothee::VALUE_UNKNOWN {
+                map.insert(Str::from(key), Str::from(value.to_string()));
+            }
+        }
+    }
+    SharedMap::from(map)
+}
+
 #[cfg(test)]
 mod tests {
     use unicode_segmentation::UnicodeSegmentation;
@@ -434,6 +761,77 @@ mod tests {
         println!("{}", strcmp(text1, text2));
     }
 
+    #[test]
+    fn test_natcmp() {
+        assert_eq!(natcmp("file10", "file2"), 1);
+        assert_eq!(natcmp("file2", "file10"), -1);
+        assert_eq!(natcmp("a1b2", "a1b2"), 0);
+        assert_eq!(natcmp("a01", "a1"), -1);
+        assert_eq!(natcmp("x", "x1"), -1);
+        assert_eq!(natcmp("B", "a"), -1);
+    }
+
+    #[test]
+    fn test_levenshtein_similarity() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("你好", "你们好"), 1);
+        assert_eq!(similarity("abc", "abc"), 1.0);
+        assert_eq!(similarity("", ""), 1.0);
+        assert_eq!(similarity("abcd", "abxy"), 0.5);
+    }
+
+    #[test]
+    fn test_soundex() {
+        for (name, code) in [
+            ("Robert", "R163"),
+            ("Rupert", "R163"),
+            ("Tymczak", "T522"),
+            ("Pfister", "P236"),
+            ("Ashcraft", "A261"),
+            ("Honeyman", "H555"),
+            ("o'Lee", "O400"),
+            ("42", ""),
+        ] {
+            assert_eq!(soundex(name), code, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_metaphone() {
+        for (name, key) in [
+            ("Smith", "SM0"),
+            ("Knight", "NT"),
+            ("Wright", "RT"),
+            ("Philip", "FLP"),
+            ("Xavier", "SFR"),
+            ("Thumb", "0M"),
+            ("Science", "SNS"),
+            ("Judge", "JJ"),
+            ("Nation", "NXN"),
+            ("Thompson", "TMSN"),
+            ("Thomas", "TMS"),
+            ("Thames", "TMS"),
+            ("Simpson", "SMSN"),
+            ("Campbell", "KMPBL"),
+            ("", ""),
+        ] {
+            assert_eq!(metaphone(name), key, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert_eq!(fuzzy_match("Jonathan ", "jonathon", 0.85), 1);
+        assert_eq!(fuzzy_match("Jonathan", "Smith", 0.85), 0);
+        assert_eq!(fuzzy_match("a", "a", 1.0), 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(diff("a\nb\nc\n", "a\nB\nc\n"), "--- a\n+++ b\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+    }
+
     #[test]
     fn test_words() {
         let text = "Hello , world! could you give a 名称?";
@@ -452,21 +850,29 @@ mod tests {
 
     #[test]
     fn test_read_all() {
-        let content = read_all("demo.awk");
-        println!("{}", content);
+        let content = read_all("demo.awk").unwrap();
+        println!("{}", String::from_utf8_lossy(&content));
     }
 
     #[test]
     fn test_read_all_from_remote() {
-        let content = read_all("https://httpbin.org/ip");
-        println!("{}", content);
+        let content = read_all("https://httpbin.org/ip").unwrap();
+        println!("{}", String::from_utf8_lossy(&content));
     }
 
     #[test]
     fn test_write_all() {
-        let content = "hello";
-        write_all("demo2.txt", content);
-        write_all("demo2.txt", "hello2");
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("demo2.txt");
+        let path = path.to_str().unwrap();
+        write_all(path, b"hello", false).unwrap();
+        write_all(path, b"\xff\x00\n", true).unwrap();
+        assert_eq!(read_all(path).unwrap(), b"hello\xff\x00\n");
+        write_all(path, b"a\n\nb", false).unwrap();
+        let lines = read_lines(path).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines.get(&3), Str::from("b"));
+        assert!(read_all(dir.path().join("missing").to_str().unwrap()).is_err());
     }
 
     #[test]
@@ -476,6 +882,36 @@ mod tests {
         println!("{:?}", map);
     }
 
+    #[test]
+    fn test_logfmt() {
+        let map = logfmt(r#"level=info msg="hello \"big\" world" path=/a=b empty= debug  quote="a\\b\nc""#);
+        assert_eq!(map.get(&Str::from("level")).as_str(), "info");
+        assert_eq!(map.get(&Str::from("msg")).as_str(), r#"hello "big" world"#);
+        assert_eq!(map.get(&Str::from("path")).as_str(), "/a=b");
+        assert_eq!(map.get(&Str::from("quote")).as_str(), "a\\b\nc");
+        assert_eq!(map.len(), 6);
+        assert_eq!(to_logfmt(&map, "level,msg"), r#"level=info msg="hello \"big\" world" debug= empty= path="/a=b" quote="a\\b\nc""#);
+        assert_eq!(logfmt(&to_logfmt(&map, "")).len(), 6);
+        assert_eq!(logfmt(r#"msg="unterminated"#).get(&Str::from("msg")).as_str(), "unterminated");
+    }
+
+    #[test]
+    fn test_ua_parse() {
+        let ua = ua_parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36");
+        assert_eq!(ua.get(&Str::from("browser")).as_str(), "Chrome");
+        assert_eq!(ua.get(&Str::from("browser_version")).as_str(), "120.0.0.0");
+        assert_eq!(ua.get(&Str::from("os")).as_str(), "Windows 10");
+        assert_eq!(ua.get(&Str::from("device")).as_str(), "pc");
+        let ua = ua_parse("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1");
+        assert_eq!(ua.get(&Str::from("os")).as_str(), "iPhone");
+        assert_eq!(ua.get(&Str::from("os_version")).as_str(), "17.1");
+        assert_eq!(ua.get(&Str::from("device")).as_str(), "smartphone");
+        let ua = ua_parse("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)");
+        assert_eq!(ua.get(&Str::from("browser")).as_str(), "Googlebot");
+        assert_eq!(ua.get(&Str::from("device")).as_str(), "crawler");
+        assert_eq!(ua_parse("-").len(), 0);
+    }
+
     #[test]
     fn test_record() {
         let text = r#"mysql{host=localhost user=root password=123456 database=test}(1)"#;
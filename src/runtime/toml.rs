@@ -0,0 +1,273 @@
+//! TOML support for `from_toml`, `to_toml` and `toml_value`.
+//!
+//! Nested tables are flattened into dotted keys (`package.version`), so a document read with
+//! `from_toml` can be edited as a plain array and written back with `to_toml`. Arrays, including
+//! arrays of tables, are kept whole as JSON text.
+use serde_json::Value as JsonValue;
+use toml::{Table, Value};
+
+use crate::runtime::{Float, Int, SharedMap, Str, StrMap};
+
+fn to_json(value: &Value) -> JsonValue {
+    match value {
+        Value::String(s) => JsonValue::from(s.as_str()),
+        Value::Integer(i) => JsonValue::from(*i),
+        Value::Float(f) => JsonValue::from(*f),
+        Value::Boolean(b) => JsonValue::from(*b),
+        Value::Datetime(dt) => JsonValue::from(dt.to_string()),
+        Value::Array(items) => JsonValue::Array(items.iter().map(to_json).collect()),
+        Value::Table(table) => JsonValue::Object(table.iter().map(|(k, v)| (k.clone(), to_json(v))).collect()),
+    }
+}
+
+fn from_json(value: JsonValue) -> Value {
+    match value {
+        JsonValue::Null => Value::String(String::new()),
+        JsonValue::Bool(b) => Value::Boolean(b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => Value::Integer(i),
+            None => Value::Float(n.as_f64().unwrap_or(0.0)),
+        },
+        JsonValue::String(s) => Value::String(s),
+        JsonValue::Array(items) => Value::Array(items.into_iter().map(from_json).collect()),
+        JsonValue::Object(obj) => Value::Table(obj.into_iter().map(|(k, v)| (k, from_json(v))).collect()),
+    }
+}
+
+/// Scalars as awk sees them: strings unquoted, booleans as `true`/`false`; arrays and tables as JSON.
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Integer(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Datetime(dt) => dt.to_string(),
+        Value::Array(_) | Value::Table(_) => to_json(value).to_string(),
+    }
+}
+
+fn flatten(prefix: &str, table: &Table, map: &mut hashbrown::HashMap<Str<'_>, Str<'_>>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        match value {
+            Value::Table(inner) => flatten(&key, inner, map),
+            value => {
+                map.insert(Str::from(key), Str::from(value_text(value)));
+            }
+        }
+    }
+}
+
+/// Parses a document, with errors on a single line: "duplicate key `a` in document root at line 2, column 1".
+fn parse(text: &str) -> Result<Table, String> {
+    text.parse::<Table>().map_err(|e| {
+        let full = e.to_string();
+        let at = full.lines().next().unwrap_or("").trim_start_matches("TOML parse error at ");
+        match e.message().replace('\n', "; ") {
+            msg if msg.is_empty() => format!("invalid TOML at {}", at),
+            msg => format!("{} at {}", msg, at),
+        }
+    })
+}
+
+/// Walks a dotted path through tables, and through arrays with 1-based indexes.
+fn lookup<'t>(table: &'t Table, path: &str) -> Option<&'t Value> {
+    let mut segments = path.split('.');
+    let mut value = table.get(segments.next()?);
+    for segment in segments {
+        value = match value? {
+            Value::Table(table) => table.get(segment),
+            Value::Array(items) => match segment.parse::<usize>() {
+                Ok(i) if i >= 1 => items.get(i - 1),
+                _ => None,
+            },
+            _ => None,
+        };
+    }
+    value
+}
+
+pub(crate) fn from_toml<'a>(text: &str) -> Result<StrMap<'a, Str<'a>>, String> {
+    let table = parse(text)?;
+    let mut map = hashbrown::HashMap::new();
+    flatten("", &table, &mut map);
+    Ok(SharedMap::from(map))
+}
+
+/// The value at a dotted `path`, such as `package.version` or `bin.1.name`; array elements are
+/// numbered from 1. A missing path gives an empty string.
+pub(crate) fn toml_value(text: &str, path: &str) -> Result<String, String> {
+    let table = parse(text)?;
+    Ok(lookup(&table, path).map(value_text).unwrap_or_default())
+}
+
+/// Conversion of array values for `to_toml`.
+pub(crate) trait TomlScalar {
+    fn text(&self) -> String;
+    /// The value's type when the template has no say.
+    fn guess(&self) -> Value;
+}
+
+impl TomlScalar for Int {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+
+    fn guess(&self) -> Value {
+        Value::Integer(*self)
+    }
+}
+
+impl TomlScalar for Float {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+
+    fn guess(&self) -> Value {
+        Value::Float(*self)
+    }
+}
+
+/// Strings become integers, `true`/`false`, or arrays and tables when they hold JSON; anything
+/// else, including `1.0`, stays a string.
+impl<'a> TomlScalar for Str<'a> {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+
+    fn guess(&self) -> Value {
+        let text = self.to_string();
+        let digits = text.strip_prefix(['+', '-']).unwrap_or(&text);
+        let is_int = !digits.is_empty()
+            && digits.bytes().all(|b| b.is_ascii_digit())
+            && (digits == "0" || !digits.starts_with('0'));
+        if is_int {
+            if let Ok(i) = text.parse::<i64>() {
+                return Value::Integer(i);
+            }
+        }
+        match text.as_str() {
+            "true" => return Value::Boolean(true),
+            "false" => return Value::Boolean(false),
+            _ => {}
+        }
+        if text.starts_with('[') || text.starts_with('{') {
+            if let Ok(json) = serde_json::from_str::<JsonValue>(&text) {
+                return from_json(json);
+            }
+        }
+        Value::String(text)
+    }
+}
+
+/// Gives `value` the type of the same key in the template, so `version = "1"` stays a string.
+fn typed<V: TomlScalar>(value: &V, like: Option<&Value>) -> Value {
+    let text = value.text();
+    let parsed = match like {
+        Some(Value::String(_)) => Some(Value::String(text)),
+        Some(Value::Integer(_)) => text.parse().ok().map(Value::Integer),
+        Some(Value::Float(_)) => text.parse().ok().map(Value::Float),
+        Some(Value::Boolean(_)) => match text.as_str() {
+            "true" | "1" => Some(Value::Boolean(true)),
+            "false" | "0" | "" => Some(Value::Boolean(false)),
+            _ => None,
+        },
+        Some(Value::Datetime(_)) => text.parse().ok().map(Value::Datetime),
+        Some(Value::Array(_)) | Some(Value::Table(_)) | None => None,
+    };
+    parsed.unwrap_or_else(|| value.guess())
+}
+
+/// Builds a document from dotted keys, creating a table for each prefix. Value types follow the
+/// TOML text `like` where it has the key, typically the document the array was read from.
+pub(crate) fn to_toml<V: TomlScalar>(obj: &StrMap<V>, like: &str) -> Result<String, String> {
+    let like = if like.is_empty() { Table::new() } else { parse(like)? };
+    let mut entries: Vec<(String, Value)> = Vec::new();
+    obj.iter(|map| {
+        for (key, value) in map {
+            let key = key.to_string();
+            let value = typed(value, lookup(&like, &key));
+            entries.push((key, value));
+        }
+    });
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut root = Table::new();
+    for (key, value) in entries {
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().unwrap();
+        let mut table = &mut root;
+        for (i, part) in parts.iter().enumerate() {
+            let entry = table.entry(part.to_string()).or_insert_with(|| Value::Table(Table::new()));
+            table = match entry {
+                Value::Table(inner) => inner,
+                _ => return Err(format!("{} is not a table", parts[..=i].join("."))),
+            };
+        }
+        if let Some(Value::Table(_)) = table.get(last) {
+            return Err(format!("{} is a table", key));
+        }
+        table.insert(last.to_string(), value);
+    }
+    toml::to_string(&root).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO: &str = r#"
+[package]
+name = "demo"
+version = "1.0"
+edition = 2021
+publish = false
+
+[dependencies]
+serde = { version = "1", features = ["derive"] }
+
+[[bin]]
+name = "demo-cli"
+"#;
+
+    #[test]
+    fn test_from_toml() {
+        let map = from_toml(CARGO).unwrap();
+        assert_eq!(map.get(&Str::from("package.name")).as_str(), "demo");
+        assert_eq!(map.get(&Str::from("package.version")).as_str(), "1.0");
+        assert_eq!(map.get(&Str::from("package.publish")).as_str(), "false");
+        assert_eq!(map.get(&Str::from("dependencies.serde.features")).as_str(), r#"["derive"]"#);
+        assert_eq!(map.get(&Str::from("bin")).as_str(), r#"[{"name":"demo-cli"}]"#);
+        assert_eq!(from_toml("a = 1\na = 2").unwrap_err(), "duplicate key `a` in document root at line 2, column 1");
+        assert_eq!(from_toml("a = ").unwrap_err(), "invalid TOML at line 1, column 5");
+    }
+
+    #[test]
+    fn test_toml_value() {
+        assert_eq!(toml_value(CARGO, "package.edition").unwrap(), "2021");
+        assert_eq!(toml_value(CARGO, "bin.1.name").unwrap(), "demo-cli");
+        assert_eq!(toml_value(CARGO, "bin.0.name").unwrap(), "");
+        assert_eq!(toml_value(CARGO, "package.missing").unwrap(), "");
+        assert_eq!(toml_value(CARGO, "dependencies.serde").unwrap(), r#"{"features":["derive"],"version":"1"}"#);
+    }
+
+    #[test]
+    fn test_to_toml() {
+        let map = from_toml(CARGO).unwrap();
+        map.insert(Str::from("package.version"), Str::from("1.1"));
+        let text = to_toml(&map, CARGO).unwrap();
+        let round_trip = from_toml(&text).unwrap();
+        assert_eq!(toml_value(&text, "package.version").unwrap(), "1.1");
+        assert_eq!(toml_value(&text, "package.edition").unwrap(), "2021");
+        assert_eq!(toml_value(&text, "package.publish").unwrap(), "false");
+        assert_eq!(round_trip.get(&Str::from("bin")).as_str(), r#"[{"name":"demo-cli"}]"#);
+        assert_eq!(toml_value(&text, "dependencies.serde.version").unwrap(), "1");
+        assert!(text.contains("[package]"), "{}", text);
+        assert!(text.contains(r#"version = "1""#), "{}", text);
+        assert!(to_toml(&map, "").unwrap().contains("version = 1\n"));
+
+        let bad: StrMap<Int> = StrMap::default();
+        bad.insert(Str::from("a"), 1);
+        bad.insert(Str::from("a.b"), 2);
+        assert_eq!(to_toml(&bad, ""), Err("a is not a table".to_string()));
+    }
+}
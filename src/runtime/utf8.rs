@@ -2,6 +2,7 @@
 /// and over 3x faster when validating non-ASCII UTF-8. Only x86 is supported for now, with
 /// fallback to the standard library string conversion routines if SSE2 is unavailable.
 // TODO: support AVX2 or neon?
+use crate::builtins::NormalForm;
 use std::str;
 
 // These functions could also be used in a standalone library.
@@ -63,6 +64,28 @@ pub(crate) fn is_utf8(bs: &[u8]) -> bool {
     }
 }
 
+/// `utf8fix(s)`: `bs` with each invalid sequence replaced by U+FFFD, or `None` if it is valid
+/// UTF-8 already.
+pub(crate) fn repair(bs: &[u8]) -> Option<String> {
+    if is_utf8(bs) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(bs).into_owned())
+}
+
+/// `nfc(s)`, `nfd(s)`, `nfkc(s)` and `nfkd(s)`: `bs` in the given Unicode normalization form.
+/// Invalid UTF-8 is repaired first, as with `utf8fix`.
+pub(crate) fn normalize(bs: &[u8], form: NormalForm) -> String {
+    use unicode_normalization::UnicodeNormalization;
+    let text = String::from_utf8_lossy(bs);
+    match form {
+        NormalForm::Nfc => text.nfc().collect(),
+        NormalForm::Nfd => text.nfd().collect(),
+        NormalForm::Nfkc => text.nfkc().collect(),
+        NormalForm::Nfkd => text.nfkd().collect(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use lazy_static::lazy_static;
@@ -78,6 +101,19 @@ mod tests {
         static ref UTF8: String = String::from_utf8(bytes(LEN, 1.0)).unwrap();
     }
 
+    #[test]
+    fn repair_and_normalize() {
+        use super::{normalize, repair, NormalForm};
+        assert_eq!(repair("café".as_bytes()), None);
+        assert_eq!(repair(b"caf\xe9 ok").unwrap(), "caf\u{fffd} ok");
+        let decomposed = "cafe\u{301}";
+        assert_eq!(normalize(decomposed.as_bytes(), NormalForm::Nfc), "café");
+        assert_eq!(normalize("café".as_bytes(), NormalForm::Nfd), decomposed);
+        assert_eq!(normalize("ﬁ①".as_bytes(), NormalForm::Nfkc), "fi1");
+        assert_eq!(normalize("ﬁé".as_bytes(), NormalForm::Nfkd), "fie\u{301}");
+        assert_eq!(normalize(b"\xff", NormalForm::Nfc), "\u{fffd}");
+    }
+
     #[test]
     fn test_partial() {
         let bs: Vec<_> = UTF8.as_bytes().to_vec();
@@ -58,7 +58,14 @@ use crossbeam_channel::{bounded, Receiver, Sender};
 use hashbrown::HashMap;
 
 use crate::common::{CompileError, FileSpec, Notification, Result};
-use crate::runtime::{command::command_for_write, Str};
+use crate::runtime::{
+    atomic_file::{self, AtomicFile},
+    command::{self, command_for_write},
+    encrypted::{self, EncryptedFile},
+    manifest,
+    rotate::{self, RotateSpec, RotatingFile},
+    Str,
+};
 
 /// The maximum number of pending requests in the per-file channels.
 const IO_CHAN_SIZE: usize = 8;
@@ -66,6 +73,201 @@ const IO_CHAN_SIZE: usize = 8;
 /// The size of client-side batches.
 const BUFFER_SIZE: usize = 64 << 10;
 
+/// Limits on how much output can be queued up for a command (`print | "cmd"`) that is slow to
+/// consume its input.
+///
+/// At most `queue` batches of (roughly) `buffer` bytes each are in flight for a command at once;
+/// once that limit is reached the writing thread blocks. With a `timeout`, blocking for longer
+/// than that is reported as an error rather than waiting on the command forever.
+#[derive(Clone, Debug)]
+pub struct PipeConfig {
+    pub buffer: usize,
+    pub queue: usize,
+    pub timeout: Option<std::time::Duration>,
+    /// Print per-command statistics to standard error on exit.
+    pub stats: bool,
+}
+
+impl Default for PipeConfig {
+    fn default() -> PipeConfig {
+        PipeConfig {
+            buffer: BUFFER_SIZE,
+            queue: IO_CHAN_SIZE,
+            timeout: None,
+            stats: false,
+        }
+    }
+}
+
+static PIPE_CONFIG: std::sync::OnceLock<PipeConfig> = std::sync::OnceLock::new();
+
+/// Set the limits used for command pipes. Must be called before any output is written.
+pub fn set_pipe_config(config: PipeConfig) {
+    let _ = PIPE_CONFIG.set(config);
+}
+
+fn pipe_config() -> &'static PipeConfig {
+    PIPE_CONFIG.get_or_init(Default::default)
+}
+
+/// How plain output files (`print > file`, `print >> file`, `--out-file`) are opened.
+#[derive(Clone, Debug, Default)]
+pub struct OutputConfig {
+    /// `print > file` fails rather than overwriting a file that already exists. Files created by
+    /// this run of zawk can still be closed and written again.
+    pub no_clobber: bool,
+    /// Take an advisory lock on the file for each batch of writes, and always write at the end of
+    /// the file, so that concurrent zawk processes appending to one file don't interleave lines.
+    pub lock: bool,
+}
+
+static OUTPUT_CONFIG: std::sync::OnceLock<OutputConfig> = std::sync::OnceLock::new();
+
+/// Set how output files are opened. Must be called before any output is written.
+pub fn set_output_config(config: OutputConfig) {
+    let _ = OUTPUT_CONFIG.set(config);
+}
+
+fn output_config() -> &'static OutputConfig {
+    OUTPUT_CONFIG.get_or_init(Default::default)
+}
+
+// The backup suffix for in-place editing (`Some(None)` for no backup), if it is enabled.
+static INPLACE: std::sync::OnceLock<Option<String>> = std::sync::OnceLock::new();
+
+/// Edit input files in place (see `--inplace`): what is written to standard output while a file
+/// is read replaces that file's contents once it has been read to the end. With a `backup_suffix`
+/// the old contents are kept in a file with that suffix appended. Must be called before any
+/// output is written.
+pub fn set_inplace(backup_suffix: Option<String>) {
+    let _ = INPLACE.set(backup_suffix);
+}
+
+/// A file being edited in place. Output goes to a temporary file in the same directory, which is
+/// renamed over the original when the edit is finished, and deleted if it never is.
+struct InPlaceFile {
+    path: String,
+    tmp: tempfile::TempPath,
+    out: FileHandle,
+}
+
+impl InPlaceFile {
+    fn start(path: &str) -> Result<InPlaceFile> {
+        let dir = match std::path::Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+        let tmp = match tempfile::Builder::new()
+            .prefix(".zawk-inplace")
+            .tempfile_in(dir)
+        {
+            Ok(f) => f.into_temp_path(),
+            Err(e) => return err!("failed to edit {} in place: {}", path, e),
+        };
+        let tmp_path = tmp.to_path_buf();
+        let out = build_handle(
+            move |_| std::fs::OpenOptions::new().append(true).open(&tmp_path),
+            /*is_stdout=*/ false,
+            /*pipe=*/ None,
+        )
+        .into_handle();
+        Ok(InPlaceFile {
+            path: path.into(),
+            tmp,
+            out,
+        })
+    }
+
+    fn finish(mut self, backup_suffix: Option<&str>) -> Result<()> {
+        self.out.close()?;
+        let res = (|| -> io::Result<()> {
+            std::fs::set_permissions(&self.tmp, std::fs::metadata(&self.path)?.permissions())?;
+            if let Some(suffix) = backup_suffix {
+                let backup = format!("{}{}", self.path, suffix);
+                let _ = std::fs::remove_file(&backup);
+                if std::fs::hard_link(&self.path, &backup).is_err() {
+                    std::fs::copy(&self.path, &backup)?;
+                }
+            }
+            self.tmp.persist(&self.path).map_err(|e| e.error)
+        })();
+        match res {
+            Ok(()) => Ok(()),
+            Err(e) => err!("failed to edit {} in place: {}", self.path, e),
+        }
+    }
+}
+
+struct InPlace {
+    backup_suffix: Option<String>,
+    current: Option<InPlaceFile>,
+}
+
+// Set by --line-buffered: standard output is flushed after every line even if it is not a terminal.
+static LINE_BUFFERED: AtomicBool = AtomicBool::new(false);
+
+/// Flush standard output after every line, as is done when it is a terminal. Must be called before
+/// any output is written.
+pub fn set_line_buffered() {
+    LINE_BUFFERED.store(true, Ordering::Relaxed);
+}
+
+// Set by --unbuffered: every write is handed to the writer thread and flushed right away.
+static UNBUFFERED: AtomicBool = AtomicBool::new(false);
+
+/// Flush every output after each write (see `--unbuffered`). Must be called before any output is
+/// written.
+pub fn set_unbuffered() {
+    UNBUFFERED.store(true, Ordering::Relaxed);
+}
+
+/// Write every output file atomically (see `--atomic-writes` and the `atomic_file` module), as
+/// if its name started with `atomic://`. Must be called before any output is written.
+pub fn set_atomic_writes() {
+    atomic_file::enable_all();
+}
+
+/// Counters for a single command pipe, used to debug stuck pipelines.
+struct PipeState {
+    cmd: String,
+    config: &'static PipeConfig,
+    bytes: AtomicUsize,
+    batches: AtomicUsize,
+    // Number of times a writer found the queue full, and how long it spent waiting in total.
+    blocked: AtomicUsize,
+    blocked_micros: AtomicUsize,
+    // Set once the command has timed out; later requests fail immediately instead of waiting.
+    stuck: AtomicBool,
+}
+
+impl PipeState {
+    fn new(cmd: &[u8]) -> PipeState {
+        PipeState {
+            cmd: String::from_utf8_lossy(cmd).into_owned(),
+            config: pipe_config(),
+            bytes: Default::default(),
+            batches: Default::default(),
+            blocked: Default::default(),
+            blocked_micros: Default::default(),
+            stuck: Default::default(),
+        }
+    }
+}
+
+impl std::fmt::Display for PipeState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "pipe to {:?}: {} bytes in {} batches, blocked {} times for {:.3}s",
+            self.cmd,
+            self.bytes.load(Ordering::Relaxed),
+            self.batches.load(Ordering::Relaxed),
+            self.blocked.load(Ordering::Relaxed),
+            self.blocked_micros.load(Ordering::Relaxed) as f64 / 1e6,
+        )
+    }
+}
+
 /// FileFactory abstracts over the portions of the file system used for the output of a frawk
 /// program. It includes "file objects" as well as "stdout", which both implement the io::Write
 /// trait.
@@ -80,8 +282,9 @@ pub trait FileFactory: Clone + 'static + Send + Sync {
         command_for_write(cmd)
     }
     fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output>;
-    // TODO maybe we should support this returning an error.
-    fn stdout(&self) -> Self::Stdout;
+    /// Standard output. Errors are reported like those of any other output file, the first time
+    /// the program writes to it.
+    fn stdout(&self) -> io::Result<Self::Stdout>;
 }
 
 impl<W: io::Write, T: Fn(&str, FileSpec) -> io::Result<W> + Clone + 'static + Send + Sync>
@@ -92,20 +295,106 @@ impl<W: io::Write, T: Fn(&str, FileSpec) -> io::Result<W> + Clone + 'static + Se
     fn build(&self, path: &str, spec: FileSpec) -> io::Result<W> {
         (self)(path, spec)
     }
-    fn stdout(&self) -> Self::Stdout {
-        grep_cli::stdout(termcolor::ColorChoice::Auto)
+    fn stdout(&self) -> io::Result<Self::Stdout> {
+        Ok(grep_cli::stdout(termcolor::ColorChoice::Auto))
     }
 }
 
-type FileWriter = std::fs::File;
+/// An output file: either a plain file, or one that rotates itself (see the `rotate` module).
+enum FileWriter {
+    Plain(std::fs::File),
+    // A plain file opened for appending, locked for each write (see `OutputConfig::lock`).
+    Locked(std::fs::File),
+    Rotating(RotatingFile),
+    Encrypted(Box<EncryptedFile>),
+    // The standard input of a process started with spawn().
+    Spawned(std::process::ChildStdin),
+}
+
+impl io::Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            FileWriter::Plain(f) => f.write(buf),
+            FileWriter::Locked(f) => {
+                // Writes are whole batches of records: write all of it while holding the lock.
+                f.lock()?;
+                let res = f.write_all(buf);
+                f.unlock()?;
+                res.map(|()| buf.len())
+            }
+            FileWriter::Rotating(f) => f.write(buf),
+            FileWriter::Encrypted(f) => f.write(buf),
+            FileWriter::Spawned(f) => f.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            FileWriter::Plain(f) | FileWriter::Locked(f) => f.flush(),
+            FileWriter::Rotating(f) => f.flush(),
+            FileWriter::Encrypted(f) => f.flush(),
+            FileWriter::Spawned(f) => f.flush(),
+        }
+    }
+}
 
 fn open_file(path: &str, spec: FileSpec) -> io::Result<FileWriter> {
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(matches!(spec, FileSpec::Append))
-        .open(path)?;
-    Ok(file)
+    let append = matches!(spec, FileSpec::Append);
+    if path.starts_with(rotate::PREFIX) {
+        let rspec = RotateSpec::parse(path)?;
+        return Ok(FileWriter::Rotating(RotatingFile::open(rspec, append)?));
+    }
+    if path.starts_with(command::SPAWN_PREFIX) {
+        return Ok(FileWriter::Spawned(command::spawned_stdin(path)?));
+    }
+    if path.starts_with(encrypted::PREFIX) {
+        return Ok(FileWriter::Encrypted(Box::new(EncryptedFile::open(
+            path, append,
+        )?)));
+    }
+    open_plain(path, append)
+}
+
+fn open_plain(path: &str, append: bool) -> io::Result<FileWriter> {
+    // Files created by `print > file` with no_clobber set: these may be truncated again.
+    static CREATED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    let config = output_config();
+    let exclusive =
+        config.no_clobber && !append && !CREATED.lock().unwrap().iter().any(|p| p == path);
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true);
+    if exclusive {
+        opts.create_new(true);
+    } else {
+        opts.create(true);
+    }
+    if config.lock {
+        // Truncated below, while holding the lock.
+        opts.append(true);
+    } else {
+        opts.append(append).truncate(!append);
+    }
+    let file = match opts.open(path) {
+        Err(e) if exclusive && e.kind() == io::ErrorKind::AlreadyExists => {
+            return Err(io::Error::new(
+                e.kind(),
+                format!("not overwriting existing file {} (--no-clobber)", path),
+            ))
+        }
+        res => res?,
+    };
+    if exclusive {
+        CREATED.lock().unwrap().push(path.into());
+    }
+    if !config.lock {
+        return Ok(FileWriter::Plain(file));
+    }
+    if !append {
+        file.lock()?;
+        let res = file.set_len(0);
+        file.unlock()?;
+        res?;
+    }
+    Ok(FileWriter::Locked(file))
 }
 
 pub fn default_factory() -> impl FileFactory {
@@ -124,25 +413,108 @@ pub fn factory_from_file(fname: &str) -> io::Result<impl FileFactory> {
         fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output> {
             open_file(path, spec)
         }
-        fn stdout(&self) -> Self::Stdout {
-            open_file(self.0.as_str(), FileSpec::Append).expect("failed to open stdout")
+        fn stdout(&self) -> io::Result<Self::Stdout> {
+            reopen_stdout_file(&self.0)
         }
     }
     Ok(FileStdout(fname.into()))
 }
 
+// The file was opened once already when the factory was built; this open happens on the first
+// write, so the error names the file as the command line does.
+fn reopen_stdout_file(fname: &str) -> io::Result<FileWriter> {
+    open_file(fname, FileSpec::Append)
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to open {}: {}", fname, e)))
+}
+
+/// Writes everything to both `.0` and `.1`.
+pub struct Tee<A, B>(A, B);
+
+impl<A: io::Write, B: io::Write> io::Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// A factory writing files as usual, but copying everything written to standard output into
+/// `fname` as well.
+pub fn factory_with_tee(fname: &str) -> io::Result<impl FileFactory> {
+    // Do a test open+truncate of the file.
+    let _file = open_file(fname, FileSpec::Trunc)?;
+
+    #[derive(Clone)]
+    struct TeeStdout(String);
+    impl FileFactory for TeeStdout {
+        type Output = FileWriter;
+        type Stdout = Tee<grep_cli::StandardStream, FileWriter>;
+        fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output> {
+            open_file(path, spec)
+        }
+        fn stdout(&self) -> io::Result<Self::Stdout> {
+            Ok(Tee(
+                grep_cli::stdout(termcolor::ColorChoice::Auto),
+                reopen_stdout_file(&self.0)?,
+            ))
+        }
+    }
+    Ok(TeeStdout(fname.into()))
+}
+
+/// A factory writing files as usual, but sending standard output to `out` rather than to the
+/// standard output of the process.
+pub fn factory_with_stdout<W: io::Write + Send + 'static>(out: W) -> impl FileFactory {
+    struct SharedStdout<W>(Arc<Mutex<W>>);
+    // Not derived, which would require `W: Clone`.
+    impl<W> Clone for SharedStdout<W> {
+        fn clone(&self) -> Self {
+            SharedStdout(self.0.clone())
+        }
+    }
+    impl<W: io::Write> io::Write for SharedStdout<W> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.lock().unwrap().flush()
+        }
+    }
+    impl<W: io::Write + Send + 'static> FileFactory for SharedStdout<W> {
+        type Output = FileWriter;
+        type Stdout = Self;
+        fn build(&self, path: &str, spec: FileSpec) -> io::Result<Self::Output> {
+            open_file(path, spec)
+        }
+        fn stdout(&self) -> io::Result<Self::Stdout> {
+            Ok(self.clone())
+        }
+    }
+    SharedStdout(Arc::new(Mutex::new(out)))
+}
+
 fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>(
     f: F,
     is_stdout: bool,
+    pipe: Option<Arc<PipeState>>,
 ) -> RawHandle {
-    let (sender, receiver) = bounded(IO_CHAN_SIZE);
+    let chan_size = pipe
+        .as_ref()
+        .map_or(IO_CHAN_SIZE, |p| p.config.queue.max(1));
+    let (sender, receiver) = bounded(chan_size);
     let error = Arc::new(Mutex::new(None));
     let receiver_error = error.clone();
     std::thread::spawn(move || receive_thread(receiver, receiver_error, f));
     RawHandle {
         error,
         sender,
-        line_buffer: is_stdout && io::stdout().is_terminal(),
+        line_buffer: is_stdout
+            && (LINE_BUFFERED.load(Ordering::Relaxed) || io::stdout().is_terminal()),
+        pipe,
     }
 }
 
@@ -151,10 +523,17 @@ fn build_handle<W: io::Write, F: Fn(FileSpec) -> io::Result<W> + Send + 'static>
 /// Note that handles are never removed, even after a file is closed. The single thread continues
 /// to run and listen for new requests that might trigger a reopen.
 pub struct Registry {
-    global: Arc<dyn Root>,
     files: HashMap<Str<'static>, FileHandle>,
     cmds: HashMap<Str<'static>, FileHandle>,
     stdout: FileHandle,
+    // Set when editing files in place; standard output then goes to the file being edited.
+    inplace: Option<InPlace>,
+    // Files written through a temporary file, keyed by the name used in the program.
+    atomic: HashMap<Str<'static>, AtomicFile>,
+    // Set when the program failed; atomic files are then discarded rather than moved into place.
+    discard_atomic: bool,
+    // Declared last so that the handles above are flushed (on drop) before the root can go away.
+    global: Arc<dyn Root>,
 }
 
 impl Registry {
@@ -166,9 +545,32 @@ impl Registry {
             files: Default::default(),
             cmds: Default::default(),
             stdout,
+            inplace: INPLACE.get().map(|suffix| InPlace {
+                backup_suffix: suffix.clone(),
+                current: None,
+            }),
+            atomic: Default::default(),
+            discard_atomic: false,
         }
     }
 
+    /// Called when the main input moves on to `path`. When editing files in place, this finishes
+    /// the edit of the previous file and sends standard output to a new copy of `path`. An empty
+    /// `path` (the end of the input) or `-` (standard input) sends output to stdout again.
+    pub fn start_input_file(&mut self, path: &str) -> Result<()> {
+        let inplace = match &mut self.inplace {
+            Some(inplace) => inplace,
+            None => return Ok(()),
+        };
+        if let Some(prev) = inplace.current.take() {
+            prev.finish(inplace.backup_suffix.as_deref())?;
+        }
+        if !path.is_empty() && path != "-" {
+            inplace.current = Some(InPlaceFile::start(path)?);
+        }
+        Ok(())
+    }
+
     pub fn get_handle(&mut self, name: Option<&Str>, fspec: FileSpec) -> Result<&mut FileHandle> {
         let name = if let Some(s) = name {
             s
@@ -187,15 +589,49 @@ impl Registry {
         // Borrow<Str<'static>> (conflicts with the blanket impl for Borrow).
         if let Some(fh) = self.files.get_mut(&path_or_cmd.clone().unmoor()) {
             fh.close()?;
+            if let Some(file) = self.atomic.get(&path_or_cmd.clone().unmoor()) {
+                commit_atomic(file)?;
+            }
             return Ok(());
         }
         if let Some(ch) = self.cmds.get_mut(&path_or_cmd.clone().unmoor()) {
             ch.close()?;
             return Ok(());
         }
+        // Nothing was written to a spawned process: closing it should still end its input.
+        path_or_cmd.with_bytes(command::close_spawned_stdin);
         path_or_cmd.with_bytes(|bs| self.global.close(bs))
     }
 
+    /// Flush the file or command `name`, or every output (including standard output) if `name` is
+    /// empty. Returns false if the program has not written to anything called `name`.
+    pub fn flush(&mut self, name: &Str) -> Result<bool> {
+        if name.is_empty() {
+            self.stdout.flush()?;
+            if let Some(InPlace {
+                current: Some(file),
+                ..
+            }) = &mut self.inplace
+            {
+                file.out.flush()?;
+            }
+            for fh in self.files.values_mut().chain(self.cmds.values_mut()) {
+                fh.flush()?;
+            }
+            return Ok(true);
+        }
+        let key = name.clone().unmoor();
+        if let Some(fh) = self.files.get_mut(&key) {
+            fh.flush()?;
+            return Ok(true);
+        }
+        if let Some(ch) = self.cmds.get_mut(&key) {
+            ch.flush()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
     pub fn get_cmd(&mut self, cmd: &Str) -> Result<&mut FileHandle> {
         use hashbrown::hash_map::Entry;
         // borrowed by with_bytes closure.
@@ -214,33 +650,81 @@ impl Registry {
                 use hashbrown::hash_map::Entry;
                 // borrowed by with_bytes closure.
                 let global = &self.global;
+                let atomic = &mut self.atomic;
                 match self.files.entry(path.clone().unmoor()) {
                     Entry::Occupied(o) => Ok(o.into_mut()),
                     Entry::Vacant(v) => {
                         let raw = path.with_bytes(|bs| match std::str::from_utf8(bs) {
-                            Ok(s) => Ok(global.get_handle(s)),
+                            Ok(s) => match atomic_file::target(s) {
+                                Some(target) => {
+                                    let file = AtomicFile::new(target);
+                                    manifest::record(target);
+                                    let open = file.opener();
+                                    atomic.insert(path.clone().unmoor(), file);
+                                    Ok(build_handle(
+                                        move |spec| open(matches!(spec, FileSpec::Append)),
+                                        /*is_stdout=*/ false,
+                                        /*pipe=*/ None,
+                                    ))
+                                }
+                                None => Ok(global.get_handle(s)),
+                            },
                             Err(e) => err!("invalid UTF8 in filename: {}", e),
                         })?;
                         Ok(v.insert(raw.into_handle()))
                     }
                 }
             }
-            None => Ok(&mut self.stdout),
+            None => match &mut self.inplace {
+                Some(InPlace {
+                    current: Some(file),
+                    ..
+                }) => Ok(&mut file.out),
+                _ => Ok(&mut self.stdout),
+            },
         }
     }
 
+    /// Throw away the output to atomic files rather than moving it into place on shutdown.
+    pub fn discard_atomic_writes(&mut self) {
+        self.discard_atomic = true;
+    }
+
     pub fn destroy_and_flush_all_files(&mut self) -> Result<()> {
         let mut last_error = Ok(());
-        for (_, mut fh) in self.files.drain().chain(self.cmds.drain()) {
-            let res = fh.flush();
+        for (name, mut fh) in self.files.drain().chain(self.cmds.drain()) {
+            // Atomic files are closed, so that they can be renamed. So are encrypted files, which
+            // are only complete once their last chunk is written.
+            let res = if self.atomic.contains_key(&name)
+                || name.with_bytes(|bs| bs.starts_with(encrypted::PREFIX.as_bytes()))
+            {
+                fh.close()
+            } else {
+                fh.flush()
+            };
             if res.is_err() {
                 last_error = res;
             }
         }
+        for (_, file) in self.atomic.drain() {
+            if self.discard_atomic {
+                file.discard();
+            } else if let Err(e) = commit_atomic(&file) {
+                file.discard();
+                last_error = Err(e);
+            }
+        }
         last_error
     }
 }
 
+fn commit_atomic(file: &AtomicFile) -> Result<()> {
+    match file.commit() {
+        Ok(()) => Ok(()),
+        Err(e) => err!("failed to move {} into place: {}", file.path().display(), e),
+    }
+}
+
 impl Clone for Registry {
     fn clone(&self) -> Registry {
         Registry {
@@ -248,6 +732,9 @@ impl Clone for Registry {
             files: Default::default(),
             cmds: Default::default(),
             stdout: self.stdout.raw().into_handle(),
+            inplace: None,
+            atomic: Default::default(),
+            discard_atomic: false,
         }
     }
 }
@@ -273,8 +760,16 @@ impl<F: FileFactory> RootImpl<F> {
     fn from_factory(file_factory: F) -> RootImpl<F> {
         let local_factory = file_factory.clone();
         let stdout_raw = build_handle(
-            move |_append| Ok(local_factory.stdout()),
+            // Write errors on stdout (say, a closed pipe) end the program quietly, but failing to
+            // open it is reported here, as nothing else prints it.
+            move |_append| {
+                local_factory.stdout().map_err(|e| {
+                    eprintln_ignore!("{}", e);
+                    e
+                })
+            },
             /*is_stdout*/ true,
+            /*pipe=*/ None,
         );
         RootImpl {
             handles: Default::default(),
@@ -285,6 +780,25 @@ impl<F: FileFactory> RootImpl<F> {
     }
 }
 
+impl<F> Drop for RootImpl<F> {
+    fn drop(&mut self) {
+        // Every registry has flushed its files by the time the root goes away.
+        if let Err(e) = manifest::finish() {
+            eprintln_ignore!("failed to write manifest: {}", e);
+        }
+        if !pipe_config().stats {
+            return;
+        }
+        if let Ok(cmds) = self.commands.lock() {
+            for h in cmds.values() {
+                if let Some(p) = &h.pipe {
+                    eprintln_ignore!("{}", p);
+                }
+            }
+        }
+    }
+}
+
 impl<F: FileFactory> Root for RootImpl<F> {
     fn close(&self, fname: &[u8]) -> Result<()> {
         let mut handle = None;
@@ -329,6 +843,7 @@ impl<F: FileFactory> Root for RootImpl<F> {
         let handle = build_handle(
             move |_| local_factory.cmd(&local_name),
             /*is_stdout=*/ false,
+            Some(Arc::new(PipeState::new(cmd))),
         );
         let _old = cmds.insert(global_name, handle.clone());
         debug_assert!(
@@ -343,12 +858,14 @@ impl<F: FileFactory> Root for RootImpl<F> {
         if let Some(h) = handles.get(fname) {
             return h.clone();
         }
+        manifest::record(fname);
         let local_factory = self.file_factory.clone();
         let local_name = String::from(fname);
         let global_name = local_name.clone();
         let handle = build_handle(
             move |append| local_factory.build(local_name.as_str(), append),
             /*is_stdout=*/ false,
+            /*pipe=*/ None,
         );
         handles.insert(global_name, handle.clone());
         handle
@@ -423,6 +940,14 @@ impl FileHandle {
         }
     }
 
+    // Called when we give up waiting on a stuck command. The writer thread may still hold
+    // references to pending batches, so we leak them rather than free memory that is in use.
+    fn abandon_guards(&mut self) {
+        for g in self.guards.drain(..) {
+            std::mem::forget(g);
+        }
+    }
+
     fn clear_batch(&mut self, upto: Option<usize>) -> Result<()> {
         if self.cur_batch.data.is_empty() {
             return Ok(());
@@ -436,7 +961,12 @@ impl FileHandle {
         let mut next_batch = self.guard();
         self.cur_batch.peel(upto, &mut next_batch);
         let req = self.cur_batch.request(flush);
-        self.raw.sender.send(req).unwrap();
+        if let Err(e) = self.raw.send(req) {
+            // The batch was never handed to the writer thread, so nothing refers to it.
+            next_batch.status.set_ok();
+            self.abandon_guards();
+            return Err(e);
+        }
         std::mem::swap(&mut next_batch, &mut self.cur_batch);
         self.guards.push_back(next_batch);
         Ok(())
@@ -457,7 +987,13 @@ impl FileHandle {
             }
             added_bytes += bs.len();
         }
-        if (self.raw.line_buffer && last_line.is_some()) || (added_bytes + cur_len > BUFFER_SIZE) {
+        if UNBUFFERED.load(Ordering::Relaxed) {
+            let len = self.cur_batch.data.len();
+            return self.clear_batch(Some(len));
+        }
+        if (self.raw.line_buffer && last_line.is_some())
+            || (added_bytes + cur_len > self.raw.buffer_size())
+        {
             self.clear_batch(last_line)?;
         }
         Ok(())
@@ -467,10 +1003,22 @@ impl FileHandle {
     }
 
     pub fn flush(&mut self) -> Result<()> {
+        if self.raw.pipe.as_ref().is_some_and(|p| p.stuck.load(Ordering::Relaxed)) {
+            // The timeout has already been reported; don't wait on the command again.
+            self.abandon_guards();
+            return Ok(());
+        }
         self.clear_batch(None)?;
         let (n, req) = Request::flush();
-        self.raw.sender.send(req).unwrap();
-        n.1.wait();
+        self.raw.send(req)?;
+        if let Some(timeout) = self.raw.pipe.as_ref().and_then(|p| p.config.timeout) {
+            if !n.1.wait_timeout(timeout) {
+                self.abandon_guards();
+                return Err(self.raw.timed_out(timeout));
+            }
+        } else {
+            n.1.wait();
+        }
         self.guards.clear();
         if let RequestStatus::Error = n.0.read() {
             Err(self.read_error())
@@ -481,7 +1029,7 @@ impl FileHandle {
 
     pub fn close(&mut self) -> Result<()> {
         self.clear_batch(None)?;
-        self.raw.sender.send(Request::Close).unwrap();
+        self.raw.send(Request::Close)?;
         self.flush()
     }
 }
@@ -647,9 +1195,82 @@ struct RawHandle {
     error: Arc<Mutex<Option<CompileError>>>,
     sender: Sender<Request>,
     line_buffer: bool,
+    // Set for command pipes.
+    pipe: Option<Arc<PipeState>>,
 }
 
 impl RawHandle {
+    fn buffer_size(&self) -> usize {
+        self.pipe.as_ref().map_or(BUFFER_SIZE, |p| p.config.buffer)
+    }
+
+    // Mark the pipe as stuck and stop the program: a command that has stopped reading will not
+    // start again, so there is no point in waiting for it any further.
+    fn timed_out(&self, timeout: std::time::Duration) -> CompileError {
+        let pipe = self.pipe.as_ref().unwrap();
+        pipe.stuck.store(true, Ordering::Relaxed);
+        let msg = format!(
+            "command {:?} did not consume its input for {:?} ({})",
+            pipe.cmd, timeout, pipe
+        );
+        crate::runtime::errors::report_fatal(msg.clone());
+        CompileError(msg)
+    }
+
+    // Send a request to the writer thread. For command pipes, this is where we block (or time out)
+    // when the command is not keeping up with its input.
+    fn send(&self, req: Request) -> Result<()> {
+        use crossbeam_channel::TrySendError;
+        let pipe = match &self.pipe {
+            Some(pipe) => pipe,
+            None => {
+                self.sender.send(req).unwrap();
+                return Ok(());
+            }
+        };
+        if pipe.stuck.load(Ordering::Relaxed) {
+            req.set_code(ErrorCode::set_error);
+            return Err(CompileError(format!(
+                "command {:?} is no longer consuming its input",
+                pipe.cmd
+            )));
+        }
+        let size = req.size();
+        let res = match self.sender.try_send(req) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(req)) => self.send_blocking(pipe, req),
+            Err(TrySendError::Disconnected(_)) => unreachable!(),
+        };
+        if res.is_ok() && size > 0 {
+            pipe.bytes.fetch_add(size, Ordering::Relaxed);
+            pipe.batches.fetch_add(1, Ordering::Relaxed);
+        }
+        res
+    }
+
+    fn send_blocking(&self, pipe: &PipeState, req: Request) -> Result<()> {
+        use crossbeam_channel::SendTimeoutError;
+        pipe.blocked.fetch_add(1, Ordering::Relaxed);
+        let start = std::time::Instant::now();
+        let res = match pipe.config.timeout {
+            Some(timeout) => self.sender.send_timeout(req, timeout),
+            None => {
+                self.sender.send(req).unwrap();
+                Ok(())
+            }
+        };
+        pipe.blocked_micros
+            .fetch_add(start.elapsed().as_micros() as usize, Ordering::Relaxed);
+        match res {
+            Ok(()) => Ok(()),
+            Err(SendTimeoutError::Timeout(req)) => {
+                req.set_code(ErrorCode::set_error);
+                Err(self.timed_out(pipe.config.timeout.unwrap()))
+            }
+            Err(SendTimeoutError::Disconnected(_)) => unreachable!(),
+        }
+    }
+
     fn into_handle(self) -> FileHandle {
         FileHandle {
             cur_batch: Default::default(),
@@ -850,8 +1471,8 @@ pub mod testing {
             named.insert(path.into(), new_file.clone());
             Ok(new_file)
         }
-        fn stdout(&self) -> Self::Stdout {
-            self.stdout.clone()
+        fn stdout(&self) -> io::Result<Self::Stdout> {
+            Ok(self.stdout.clone())
         }
     }
 
@@ -1029,4 +1650,52 @@ mod tests {
         assert_eq!(fs.get_handle("/fake/A").unwrap().read_data(), expected_a);
         assert_eq!(fs.get_handle("/fake/B").unwrap().read_data(), expected_b);
     }
+
+    #[test]
+    fn stuck_pipe_times_out() {
+        // A "command" that does not read anything until we tell it to.
+        struct Stuck(std::sync::mpsc::Receiver<()>);
+        impl io::Write for Stuck {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                let _ = self.0.recv();
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let config: &'static PipeConfig = Box::leak(Box::new(PipeConfig {
+            buffer: 16,
+            queue: 1,
+            timeout: Some(std::time::Duration::from_millis(50)),
+            stats: false,
+        }));
+        let mut state = PipeState::new(b"stuck");
+        state.config = config;
+        let (unblock, blocked) = std::sync::mpsc::channel();
+        let blocked = Mutex::new(Some(blocked));
+        let mut handle = build_handle(
+            move |_| Ok(Stuck(blocked.lock().unwrap().take().unwrap())),
+            /*is_stdout=*/ false,
+            Some(Arc::new(state)),
+        )
+        .into_handle();
+        let line = Str::from("0123456789abcdef\n");
+        let mut res = Ok(());
+        for _ in 0..100 {
+            res = handle.write(&line, FileSpec::Append);
+            if res.is_err() {
+                break;
+            }
+        }
+        let msg = res.expect_err("writes to a stuck pipe should time out").0;
+        assert!(
+            msg.contains("\"stuck\" did not consume its input"),
+            "{}",
+            msg
+        );
+        let stats = handle.raw.pipe.as_ref().unwrap();
+        assert!(stats.blocked.load(Ordering::Relaxed) > 0);
+        drop(unblock);
+    }
 }
@@ -0,0 +1,245 @@
+//! XML support for `to_xml` and `xml_update`.
+//!
+//! `to_xml` follows the dotted-key layout of `from_toml`: `server.host` becomes a `<host>` element
+//! inside `<server>`, and a last segment starting with `@` (`server.@port`) becomes an attribute.
+use std::collections::BTreeMap;
+
+use sxd_document::dom::{ChildOfElement, ChildOfRoot, Document, Element as DomElement};
+use sxd_document::{parser, writer::Writer};
+use sxd_xpath::{nodeset::Node, Context, Factory, Value};
+
+use crate::runtime::str_escape::escape_xml;
+use crate::runtime::StrMap;
+
+#[derive(Default)]
+struct Element {
+    text: Option<String>,
+    attrs: BTreeMap<String, String>,
+    children: BTreeMap<String, Element>,
+}
+
+/// Replaces characters that are not allowed in an XML name with `_`.
+fn xml_name(name: &str) -> String {
+    let mut out: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':') { c } else { '_' })
+        .collect();
+    if !out.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        out.insert(0, '_');
+    }
+    out
+}
+
+fn write_element(name: &str, elem: &Element, depth: usize, out: &mut String) {
+    let indent = "  ".repeat(depth);
+    out.push_str(&indent);
+    out.push('<');
+    out.push_str(name);
+    for (attr, value) in &elem.attrs {
+        out.push_str(&format!(" {}=\"{}\"", attr, escape_xml(value)));
+    }
+    let text = elem.text.as_deref().map(escape_xml).unwrap_or_default();
+    if elem.children.is_empty() {
+        if text.is_empty() {
+            out.push_str("/>\n");
+        } else {
+            out.push_str(&format!(">{}</{}>\n", text, name));
+        }
+        return;
+    }
+    out.push('>');
+    out.push_str(&text);
+    out.push('\n');
+    for (child_name, child) in &elem.children {
+        write_element(child_name, child, depth + 1, out);
+    }
+    out.push_str(&format!("{}</{}>\n", indent, name));
+}
+
+/// Builds an indented document with a `root` element, children and attributes in sorted order.
+/// Characters that cannot appear in element or attribute names are replaced with `_`.
+pub(crate) fn to_xml<V: ToString>(obj: &StrMap<V>, root: &str) -> String {
+    let mut tree = Element::default();
+    obj.iter(|map| {
+        for (key, value) in map {
+            let key = key.to_string();
+            let mut parts: Vec<&str> = key.split('.').collect();
+            let attr = match parts.last() {
+                Some(last) if last.len() > 1 && last.starts_with('@') => parts.pop().map(|a| xml_name(&a[1..])),
+                _ => None,
+            };
+            let mut elem = &mut tree;
+            for part in parts {
+                elem = elem.children.entry(xml_name(part)).or_default();
+            }
+            match attr {
+                Some(attr) => {
+                    elem.attrs.insert(attr, value.to_string());
+                }
+                None => elem.text = Some(value.to_string()),
+            }
+        }
+    });
+    let mut out = String::new();
+    write_element(&xml_name(root), &tree, 0, &mut out);
+    out
+}
+
+fn parse_error(text: &str, offset: usize) -> String {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+    format!("invalid XML at line {}, column {}", line, column)
+}
+
+// Binds the namespace prefixes declared on `elem` and its descendants, and lists them in `declared`.
+fn bind_namespaces<'d>(elem: DomElement<'d>, context: &mut Context, declared: &mut Vec<&'d str>) {
+    for ns in elem.namespaces_in_scope() {
+        context.set_namespace(ns.prefix(), ns.uri());
+        declared.push(ns.prefix());
+    }
+    for child in elem.children() {
+        if let ChildOfElement::Element(child) = child {
+            bind_namespaces(child, context, declared);
+        }
+    }
+}
+
+// The namespace prefixes used in `xpath`: names followed by a single `:`, outside string literals.
+fn xpath_prefixes(xpath: &str) -> Vec<&str> {
+    let bs = xpath.as_bytes();
+    let is_name = |b: u8| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.') || b >= 0x80;
+    let mut prefixes = Vec::new();
+    let mut i = 0;
+    while i < bs.len() {
+        match bs[i] {
+            q @ (b'"' | b'\'') => {
+                i += bs[i + 1..].iter().position(|&b| b == q).map_or(bs.len(), |n| n + 2);
+            }
+            b if is_name(b) => {
+                let start = i;
+                while i < bs.len() && is_name(bs[i]) {
+                    i += 1;
+                }
+                if bs.get(i) == Some(&b':') && bs.get(i + 1) != Some(&b':') {
+                    prefixes.push(&xpath[start..i]);
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    prefixes
+}
+
+// Evaluates `xpath` with the namespace prefixes declared anywhere in `doc`. sxd-xpath panics on a
+// prefix it has no binding for, so those are rejected first.
+fn select<'d>(doc: &Document<'d>, xpath: &str) -> Result<Value<'d>, String> {
+    let mut context = Context::new();
+    let mut declared = Vec::new();
+    for child in doc.root().children() {
+        if let ChildOfRoot::Element(elem) = child {
+            bind_namespaces(elem, &mut context, &mut declared);
+        }
+    }
+    if let Some(p) = xpath_prefixes(xpath).into_iter().find(|p| !declared.contains(p)) {
+        return Err(format!("no namespace for prefix {} in {}", p, xpath));
+    }
+    let expr = match Factory::new().build(xpath).map_err(|e| e.to_string())? {
+        Some(expr) => expr,
+        None => return Err(format!("{} is not an XPath expression", xpath)),
+    };
+    expr.evaluate(&context, doc.root()).map_err(|e| e.to_string())
+}
+
+/// Sets the text of every element, attribute or text node that `xpath` selects to `value`, and
+/// returns the new document. Text that has no match is returned unchanged. Namespace prefixes in
+/// `xpath` refer to the ones declared in the document.
+pub(crate) fn xml_update(text: &str, xpath: &str, value: &str) -> Result<String, String> {
+    let package = parser::parse(text).map_err(|e| parse_error(text, e.location()))?;
+    let doc = package.as_document();
+    let nodes = match select(&doc, xpath)? {
+        Value::Nodeset(nodes) => nodes.document_order(),
+        _ => return Err(format!("{} does not select nodes", xpath)),
+    };
+    if nodes.is_empty() {
+        return Ok(text.to_string());
+    }
+    for node in nodes {
+        match node {
+            Node::Element(elem) => {
+                elem.set_text(value);
+            }
+            Node::Attribute(attr) => {
+                if let Some(elem) = attr.parent() {
+                    elem.set_attribute_value(attr.name(), value);
+                }
+            }
+            Node::Text(t) => t.set_text(value),
+            Node::Comment(c) => c.set_text(value),
+            _ => return Err(format!("{} selects a node that cannot hold text", xpath)),
+        }
+    }
+    let prolog = text.trim_start().strip_prefix("<?xml").and_then(|rest| rest.split("?>").next());
+    let writer = Writer::new()
+        .set_single_quotes(false)
+        .set_write_encoding(prolog.is_some_and(|p| p.contains("encoding")));
+    let mut out = Vec::new();
+    writer.format_document(&doc, &mut out).map_err(|e| e.to_string())?;
+    let mut out = String::from_utf8(out).map_err(|e| e.to_string())?;
+    if prolog.is_none() {
+        if let Some(end) = out.find("?>") {
+            out.drain(..end + 2);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{Int, Str};
+
+    #[test]
+    fn test_to_xml() {
+        let map: StrMap<Str> = StrMap::default();
+        map.insert(Str::from("name"), Str::from("demo & co"));
+        map.insert(Str::from("server.host"), Str::from("localhost"));
+        map.insert(Str::from("server.@port"), Str::from("8080"));
+        map.insert(Str::from("server.tls"), Str::from(""));
+        map.insert(Str::from("1st key"), Str::from("x"));
+        assert_eq!(
+            to_xml(&map, "config"),
+            "<config>\n  <_1st_key>x</_1st_key>\n  <name>demo &amp; co</name>\n  <server port=\"8080\">\n    <host>localhost</host>\n    <tls/>\n  </server>\n</config>\n"
+        );
+        let empty: StrMap<Int> = StrMap::default();
+        assert_eq!(to_xml(&empty, "a b"), "<a_b/>\n");
+    }
+
+    #[test]
+    fn test_xml_update() {
+        let doc = "<config>\n  <server port=\"80\"><host>a</host></server>\n  <server port=\"81\"><host>b</host></server>\n</config>";
+        assert_eq!(
+            xml_update(doc, "//server[@port='81']/host", "c & d").unwrap(),
+            "<config>\n  <server port=\"80\"><host>a</host></server>\n  <server port=\"81\"><host>c &amp; d</host></server>\n</config>"
+        );
+        let updated = xml_update(doc, "//server/@port", "8080").unwrap();
+        assert_eq!(updated.matches("port=\"8080\"").count(), 2);
+        assert_eq!(xml_update(doc, "//missing", "x").unwrap(), doc);
+        let with_prolog = "<?xml version=\"1.0\" encoding=\"UTF-8\"?><a>1</a>";
+        assert_eq!(xml_update(with_prolog, "/a", "2").unwrap(), "<?xml version=\"1.0\" encoding=\"UTF-8\"?><a>2</a>");
+        assert_eq!(xml_update("<a>\n<b></a>", "/a", "x").unwrap_err(), "invalid XML at line 2, column 6");
+        assert_eq!(xml_update(doc, "count(//server)", "x").unwrap_err(), "count(//server) does not select nodes");
+        assert!(xml_update(doc, "//[", "x").is_err());
+    }
+
+    #[test]
+    fn test_xml_update_namespaces() {
+        // The writer moves namespace declarations to the elements that use them.
+        let doc = "<r xmlns:p=\"u\"><p:a>1</p:a><a>3</a></r>";
+        let updated = "<r><p:a xmlns:p='u'>2</p:a><a>3</a></r>";
+        assert_eq!(xml_update(doc, "/r/p:a", "2").unwrap(), updated);
+        assert_eq!(xml_update(doc, "/r/child::p:a", "2").unwrap(), updated);
+        assert_eq!(xml_update(doc, "/r/a[. = 'q:x']", "4").unwrap(), doc);
+        assert_eq!(xml_update(doc, "/r/q:a", "2").unwrap_err(), "no namespace for prefix q in /r/q:a");
+    }
+}
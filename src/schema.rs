@@ -0,0 +1,445 @@
+//! Column type sniffing for the `zawk schema` subcommand.
+//!
+//! `sniff` reads the first rows of a CSV or TSV file and summarizes each column: the narrowest
+//! type that all of its values fit, how many values are missing, how many are distinct, the
+//! smallest and largest value, and a few examples. The point is to find out what a file holds
+//! before writing a script against it, so everything is computed from a sample and nothing is
+//! inferred from the column names.
+//!
+//! Types are tried from narrowest to widest: `int`, `float`, `bool`, `date`, `datetime`, and
+//! finally `string`. Numbers with leading zeros (`007`, as in zip codes or ids) are strings, since
+//! turning them into numbers loses the zeros. Empty values and the usual spellings of a missing
+//! value (`NULL`, `NA`, `N/A`, `\N`) are counted as nulls and do not affect the type.
+use crate::common::Result;
+use crate::runtime::str_escape::{escape_csv, escape_tsv};
+use crate::runtime::{Int, Line, LineReader, RegexCache, Str};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use hashbrown::HashSet;
+
+use std::io::Write;
+
+/// Distinct values are counted exactly up to this many per column.
+const DISTINCT_LIMIT: usize = 100_000;
+const EXAMPLES: usize = 3;
+const EXAMPLE_WIDTH: usize = 24;
+const NULLS: &[&str] = &["null", "na", "n/a", "\\n", "none", "nil"];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Type {
+    /// No values other than nulls.
+    Empty,
+    Int,
+    Float,
+    Bool,
+    Date,
+    DateTime,
+    String,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Empty => "empty",
+            Type::Int => "int",
+            Type::Float => "float",
+            Type::Bool => "bool",
+            Type::Date => "date",
+            Type::DateTime => "datetime",
+            Type::String => "string",
+        }
+    }
+}
+
+// Candidate types, as bits. A value clears the bits of the types it does not fit.
+const INT: u8 = 1;
+const FLOAT: u8 = 1 << 1;
+const BOOL: u8 = 1 << 2;
+const DATE: u8 = 1 << 3;
+const DATETIME: u8 = 1 << 4;
+
+fn is_null(s: &str) -> bool {
+    s.is_empty() || NULLS.iter().any(|n| s.eq_ignore_ascii_case(n))
+}
+
+// A leading zero followed by another digit, ignoring the sign.
+fn leading_zero(s: &str) -> bool {
+    let digits = s.strip_prefix(['-', '+']).unwrap_or(s).as_bytes();
+    digits.len() > 1 && digits[0] == b'0' && digits[1].is_ascii_digit()
+}
+
+fn parse_datetime(s: &str) -> bool {
+    const FORMATS: &[&str] = &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ];
+    DateTime::parse_from_rfc3339(s).is_ok()
+        || FORMATS
+            .iter()
+            .any(|f| NaiveDateTime::parse_from_str(s, f).is_ok())
+}
+
+// The types `s`, a non-null value, fits, along with its numeric value.
+fn classify(s: &str) -> (u8, Option<f64>) {
+    if leading_zero(s) {
+        return (0, None);
+    }
+    if let Ok(i) = s.parse::<Int>() {
+        return (INT | FLOAT, Some(i as f64));
+    }
+    match s.parse::<f64>() {
+        // Rust also reads "inf" and "NaN", which are more likely to be words than numbers.
+        Ok(f) if f.is_finite() => return (FLOAT, Some(f)),
+        _ => {}
+    }
+    if ["true", "false"].iter().any(|b| s.eq_ignore_ascii_case(b)) {
+        return (BOOL, None);
+    }
+    if NaiveDate::parse_from_str(s, "%Y-%m-%d").is_ok() {
+        return (DATE | DATETIME, None);
+    }
+    if parse_datetime(s) {
+        return (DATETIME, None);
+    }
+    (0, None)
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Column {
+    pub name: String,
+    /// Values seen, including nulls.
+    pub count: usize,
+    pub nulls: usize,
+    candidates: u8,
+    distinct: HashSet<String>,
+    distinct_overflow: bool,
+    num_min: f64,
+    num_max: f64,
+    str_min: Option<String>,
+    str_max: Option<String>,
+    pub examples: Vec<String>,
+}
+
+impl Column {
+    fn new(name: String) -> Column {
+        Column {
+            name,
+            count: 0,
+            nulls: 0,
+            candidates: INT | FLOAT | BOOL | DATE | DATETIME,
+            distinct: HashSet::new(),
+            distinct_overflow: false,
+            num_min: f64::INFINITY,
+            num_max: f64::NEG_INFINITY,
+            str_min: None,
+            str_max: None,
+            examples: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, value: &str) {
+        self.count += 1;
+        let value = value.trim();
+        if is_null(value) {
+            self.nulls += 1;
+            return;
+        }
+        let (types, num) = classify(value);
+        self.candidates &= types;
+        if let Some(x) = num {
+            self.num_min = self.num_min.min(x);
+            self.num_max = self.num_max.max(x);
+        }
+        if self.str_min.as_deref().is_none_or(|m| value < m) {
+            self.str_min = Some(value.to_string());
+        }
+        if self.str_max.as_deref().is_none_or(|m| value > m) {
+            self.str_max = Some(value.to_string());
+        }
+        if !self.distinct.contains(value) {
+            if self.examples.len() < EXAMPLES {
+                self.examples.push(value.to_string());
+            }
+            if self.distinct.len() < DISTINCT_LIMIT {
+                self.distinct.insert(value.to_string());
+            } else {
+                self.distinct_overflow = true;
+            }
+        }
+    }
+
+    pub(crate) fn ty(&self) -> Type {
+        if self.count == self.nulls {
+            return Type::Empty;
+        }
+        [
+            (INT, Type::Int),
+            (FLOAT, Type::Float),
+            (BOOL, Type::Bool),
+            (DATE, Type::Date),
+            (DATETIME, Type::DateTime),
+        ]
+        .iter()
+        .find(|(bit, _)| self.candidates & bit != 0)
+        .map_or(Type::String, |(_, ty)| *ty)
+    }
+
+    /// The number of distinct non-null values, and whether there were more than could be counted.
+    pub(crate) fn distinct(&self) -> (usize, bool) {
+        (self.distinct.len(), self.distinct_overflow)
+    }
+
+    /// The smallest and largest values: numeric for numbers, in byte order otherwise (which is
+    /// chronological for ISO dates).
+    pub(crate) fn range(&self) -> Option<(String, String)> {
+        match self.ty() {
+            Type::Empty => None,
+            Type::Int | Type::Float => Some((fmt_num(self.num_min), fmt_num(self.num_max))),
+            _ => Some((self.str_min.clone()?, self.str_max.clone()?)),
+        }
+    }
+
+    fn null_rate(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.nulls as f64 * 100.0 / self.count as f64
+        }
+    }
+}
+
+fn fmt_num(x: f64) -> String {
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        format!("{}", x as Int)
+    } else {
+        format!("{}", x)
+    }
+}
+
+pub(crate) struct Schema {
+    /// Rows read, not counting the header.
+    pub rows: usize,
+    pub columns: Vec<Column>,
+}
+
+/// Read the header (if `header` is set) and up to `sample` rows of `inp`, which should be a CSV or
+/// TSV reader. A `sample` of zero reads every row.
+pub(crate) fn sniff(mut inp: impl LineReader, header: bool, sample: usize) -> Result<Schema> {
+    // CSV and TSV readers split records themselves; the separator is only used by other readers.
+    let fs = Str::from(",");
+    let ofs = Str::from(" ");
+    let mut rc = RegexCache::default();
+    let mut line = Default::default();
+    let mut columns: Vec<Column> = Vec::new();
+    let mut rows = 0;
+    let mut first = header;
+    while sample == 0 || rows < sample {
+        inp.read_line_reuse(&fs, &mut rc, &mut line)?;
+        if inp.read_state() <= 0 {
+            break;
+        }
+        let nf = line.nf(&fs, &mut rc)?;
+        if first {
+            first = false;
+            for i in 1..=nf {
+                let name = line.get_col(i as Int, &fs, &ofs, &mut rc)?;
+                let name = name.with_bytes(|b| String::from_utf8_lossy(b).trim().to_string());
+                columns.push(Column::new(if name.is_empty() {
+                    format!("${}", i)
+                } else {
+                    name
+                }));
+            }
+            continue;
+        }
+        // Columns missing from a short row are nulls; a long row adds columns.
+        while columns.len() < nf {
+            let mut col = Column::new(format!("${}", columns.len() + 1));
+            col.count = rows;
+            col.nulls = rows;
+            columns.push(col);
+        }
+        for (i, col) in columns.iter_mut().enumerate() {
+            if i < nf {
+                let value = line.get_col(i as Int + 1, &fs, &ofs, &mut rc)?;
+                value.with_bytes(|b| col.add(&String::from_utf8_lossy(b)));
+            } else {
+                col.add("");
+            }
+        }
+        rows += 1;
+    }
+    Ok(Schema { rows, columns })
+}
+
+fn example(s: &str) -> String {
+    if s.chars().count() <= EXAMPLE_WIDTH {
+        s.to_string()
+    } else {
+        let mut short: String = s.chars().take(EXAMPLE_WIDTH - 3).collect();
+        short.push_str("...");
+        short
+    }
+}
+
+impl Schema {
+    fn cells(&self) -> Vec<[String; 7]> {
+        self.columns
+            .iter()
+            .map(|col| {
+                let (distinct, overflow) = col.distinct();
+                let (min, max) = col.range().unwrap_or_default();
+                [
+                    col.name.clone(),
+                    col.ty().name().to_string(),
+                    format!("{} ({:.1}%)", col.nulls, col.null_rate()),
+                    format!("{}{}", distinct, if overflow { "+" } else { "" }),
+                    example(&min),
+                    example(&max),
+                    col.examples
+                        .iter()
+                        .map(|e| match example(e) {
+                            e if e.contains(',') => format!("\"{}\"", e),
+                            e => e,
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                ]
+            })
+            .collect()
+    }
+
+    /// Write the report as an aligned table.
+    pub(crate) fn write_table(&self, mut w: impl Write) -> std::io::Result<()> {
+        const HEADER: [&str; 7] = [
+            "column", "type", "nulls", "distinct", "min", "max", "examples",
+        ];
+        let cells = self.cells();
+        let mut widths = HEADER.map(|h| h.chars().count());
+        for row in cells.iter() {
+            for (w, cell) in widths.iter_mut().zip(row.iter()) {
+                *w = (*w).max(cell.chars().count());
+            }
+        }
+        let header = HEADER.map(String::from);
+        for row in std::iter::once(&header).chain(cells.iter()) {
+            let mut line = String::new();
+            for (i, (cell, width)) in row.iter().zip(widths.iter()).enumerate() {
+                if i + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(cell);
+                    line.extend(std::iter::repeat_n(' ', width - cell.chars().count() + 2));
+                }
+            }
+            writeln!(w, "{}", line.trim_end())?;
+        }
+        writeln!(w, "{} rows", self.rows)
+    }
+
+    /// Write the report as CSV or TSV, one row per column of the input.
+    pub(crate) fn write_delimited(&self, mut w: impl Write, tsv: bool) -> std::io::Result<()> {
+        let (sep, escape): (&str, fn(&str) -> String) = if tsv {
+            ("\t", escape_tsv)
+        } else {
+            (",", escape_csv)
+        };
+        writeln!(
+            w,
+            "{}",
+            ["column", "type", "rows", "nulls", "distinct", "min", "max", "examples"].join(sep)
+        )?;
+        for col in self.columns.iter() {
+            let (distinct, _) = col.distinct();
+            let (min, max) = col.range().unwrap_or_default();
+            let fields = [
+                col.name.clone(),
+                col.ty().name().to_string(),
+                col.count.to_string(),
+                col.nulls.to_string(),
+                distinct.to_string(),
+                min,
+                max,
+                col.examples.join("; "),
+            ];
+            let fields: Vec<String> = fields.iter().map(|f| escape(f)).collect();
+            writeln!(w, "{}", fields.join(sep))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{CancelSignal, ExecutionStrategy};
+    use crate::runtime::splitter::batch::{CSVReader, InputFormat};
+    use std::io::Cursor;
+
+    fn schema(text: &str, header: bool, sample: usize) -> Schema {
+        let reader = CSVReader::new(
+            std::iter::once((
+                Cursor::new(text.to_string().into_bytes()),
+                String::from("-"),
+            )),
+            InputFormat::CSV,
+            1 << 10,
+            false,
+            ExecutionStrategy::Serial,
+            CancelSignal::default(),
+        );
+        sniff(reader, header, sample).unwrap()
+    }
+
+    #[test]
+    fn types() {
+        let s = schema(
+            "id,price,ok,day,at,zip,note,blank\n\
+             1,2.5,true,2024-01-02,2024-01-02T03:04:05Z,02134,hello,\n\
+             2,-3,FALSE,2024-03-04,2024-01-02 10:00,10001,,NULL\n\
+             3,NA,false,2023-12-31,2024-02-02,94110,\"a, b\",\n",
+            true,
+            0,
+        );
+        assert_eq!(s.rows, 3);
+        let types: Vec<_> = s.columns.iter().map(Column::ty).collect();
+        use Type::*;
+        assert_eq!(
+            types,
+            vec![Int, Float, Bool, Date, DateTime, String, String, Empty]
+        );
+        let price = &s.columns[1];
+        assert_eq!(price.nulls, 1);
+        assert_eq!(price.range(), Some(("-3".into(), "2.5".into())));
+        assert_eq!(
+            s.columns[3].range(),
+            Some(("2023-12-31".into(), "2024-03-04".into()))
+        );
+        assert_eq!(s.columns[6].examples, vec!["hello", "a, b"]);
+    }
+
+    #[test]
+    fn sample_and_ragged_rows() {
+        let s = schema("a,b\n1,x\n2\n3,y,z\n4,w\n", true, 3);
+        assert_eq!(s.rows, 3);
+        assert_eq!(s.columns.len(), 3);
+        assert_eq!(s.columns[1].nulls, 1);
+        assert_eq!(s.columns[1].distinct(), (2, false));
+        assert_eq!(s.columns[2].name, "$3");
+        assert_eq!((s.columns[2].count, s.columns[2].nulls), (3, 2));
+
+        let s = schema("1,1\n1,2\n", false, 0);
+        assert_eq!(s.columns[0].name, "$1");
+        assert_eq!(s.columns[0].distinct(), (1, false));
+        let mut out = Vec::new();
+        s.write_delimited(&mut out, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "column,type,rows,nulls,distinct,min,max,examples\n\
+             $1,int,2,0,1,1,1,1\n\
+             $2,int,2,0,2,1,2,1; 2\n"
+        );
+    }
+}
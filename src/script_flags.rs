@@ -0,0 +1,379 @@
+//! Command-line options declared by the script itself.
+//!
+//! A script can declare its parameters with comments of the form
+//!
+//! ```text
+//! #@flag threshold 10 "Only report lines longer than this"
+//! #@flag label "" "Prefix for every output line"
+//! ```
+//!
+//! and is then run as `zawk -f report.awk --threshold 20 access.log`. Each declared flag becomes
+//! a `--name` option (written `--name value` or `--name=value`) that assigns the variable `name`
+//! just like `-v name=value`; variables whose flag is not given start out with the declared
+//! default. `--help` after the script prints the declared options instead of running anything.
+//!
+//! This is handled by rewriting the command line before it is parsed: flags following the script
+//! turn into `-v` assignments, and defaults are added before everything else so that an explicit
+//! `-v` still wins. A script named `*.awk` may also be given in place of the program text, as in
+//! `zawk report.awk --help`.
+//!
+//! The same pass makes scripts usable as standalone tools behind a `#!` line:
+//!
+//! * A shebang such as `#!/usr/bin/zawk -F: -f` hands all of its options over as one argument;
+//!   it is split back into words.
+//! * Once a program file has been named, arguments after `--` are only made visible in `ARGV`;
+//!   they are not read as input files.
+//! * `-E file` (`--exec file`) works like `-f file` but ends zawk's own option processing, as in
+//!   gawk: everything after it apart from the script's declared flags is an operand.
+use crate::common::Result;
+
+use std::fmt::Write;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flag {
+    pub name: String,
+    pub default: String,
+    pub help: String,
+}
+
+pub enum Expanded {
+    /// The rewritten command line, and the arguments following `--` that only go into `ARGV`.
+    Args {
+        args: Vec<String>,
+        script_args: Vec<String>,
+    },
+    /// `--help` was requested for a script; this is the text to print.
+    Help(String),
+}
+
+/// Collect the `#@flag name default "help"` declarations in `src`.
+pub fn parse(src: &str) -> Result<Vec<Flag>> {
+    let mut flags: Vec<Flag> = Vec::new();
+    for (i, line) in src.lines().enumerate() {
+        let rest = match line.trim_start().strip_prefix("#@flag") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => rest,
+            _ => continue,
+        };
+        let mut words = match shlex::split(rest) {
+            Some(words) => words.into_iter(),
+            None => return err!("line {}: unbalanced quotes in #@flag", i + 1),
+        };
+        let name = match words.next() {
+            Some(name) => name,
+            None => return err!("line {}: #@flag needs a variable name", i + 1),
+        };
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return err!("line {}: invalid #@flag variable name {:?}", i + 1, name);
+        }
+        if flags.iter().any(|f| f.name == name) {
+            return err!("line {}: flag {} is declared twice", i + 1, name);
+        }
+        let default = words.next().unwrap_or_default();
+        let help = words.collect::<Vec<_>>().join(" ");
+        flags.push(Flag {
+            name,
+            default,
+            help,
+        });
+    }
+    Ok(flags)
+}
+
+/// The `--help` text for a script declaring `flags`.
+pub fn usage(script: &str, flags: &[Flag]) -> String {
+    let mut out = format!("Usage: zawk -f {} [OPTIONS] [FILE]...\n\nOptions:\n", script);
+    let specs: Vec<String> = flags
+        .iter()
+        .map(|f| format!("--{} <{}>", f.name, f.name.to_uppercase()))
+        .collect();
+    let width = specs.iter().map(String::len).max().unwrap_or(0).max(6);
+    for (f, spec) in flags.iter().zip(specs.iter()) {
+        let mut line = format!("  {:width$}  {}", spec, f.help, width = width);
+        if !f.default.is_empty() {
+            let _ = write!(line, " [default: {}]", f.default);
+        }
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+    let _ = writeln!(out, "  {:width$}  Print help", "--help", width = width);
+    out
+}
+
+// The program files named on the command line, with the index of the first argument after the
+// last of them.
+fn scripts(args: &mut [String]) -> (Vec<String>, usize) {
+    let mut files = Vec::new();
+    let mut end = 0;
+    let mut i = 1;
+    while i < args.len() {
+        let arg = &args[i];
+        if arg == "--" {
+            break;
+        }
+        if arg == "-f" || arg == "--program-file" {
+            if let Some(f) = args.get(i + 1) {
+                files.push(f.clone());
+                end = i + 2;
+            }
+            i += 2;
+            continue;
+        }
+        if let Some(f) = arg
+            .strip_prefix("--program-file=")
+            .or_else(|| arg.strip_prefix("-f").filter(|f| !f.is_empty()))
+        {
+            files.push(f.to_string());
+            end = i + 1;
+        }
+        i += 1;
+    }
+    // `zawk script.awk ...`: treat the script like a program file.
+    if files.is_empty() {
+        if let Some(arg) = args.get(1) {
+            if arg.ends_with(".awk") && std::path::Path::new(arg).is_file() {
+                files.push(arg.clone());
+                args[1] = format!("--program-file={}", arg);
+                end = 2;
+            }
+        }
+    }
+    (files, end)
+}
+
+// `#!/usr/bin/zawk -F: -f script` runs `zawk "-F: -f" script ...`.
+fn split_shebang(args: &mut Vec<String>) {
+    let words = match args.get(1) {
+        Some(arg) if arg.starts_with('-') && arg.contains(char::is_whitespace) => shlex::split(arg),
+        _ => None,
+    };
+    if let Some(words) = words.filter(|w| w.len() > 1) {
+        args.splice(1..2, words);
+    }
+}
+
+// Rewrite `-E file` into `--program-file=file`, returning the index just past it.
+fn exclusive(args: &mut Vec<String>) -> Option<usize> {
+    let i = args
+        .iter()
+        .skip(1)
+        .take_while(|a| *a != "--")
+        .position(|a| a.starts_with("-E") || a == "--exec" || a.starts_with("--exec="))?
+        + 1;
+    let file = match args[i].strip_prefix("--exec=").or_else(|| args[i].strip_prefix("-E")) {
+        Some(f) if !f.is_empty() => f.to_string(),
+        // Leave a missing file for clap to report.
+        _ if i + 1 == args.len() => return None,
+        _ => args.remove(i + 1),
+    };
+    args[i] = format!("--program-file={}", file);
+    Some(i + 1)
+}
+
+/// Rewrite `args` (including the program name) to bind the flags declared by the program files
+/// it names.
+pub fn expand(mut args: Vec<String>) -> Result<Expanded> {
+    split_shebang(&mut args);
+    let exclusive_end = exclusive(&mut args);
+    let limit = exclusive_end.unwrap_or(args.len());
+    let (files, end) = scripts(&mut args[..limit]);
+    let mut flags = Vec::new();
+    let path = crate::include::search_path();
+    for file in files.iter() {
+        if file.starts_with("http://") || file.starts_with("https://") {
+            continue;
+        }
+        // Unreadable files are reported when the program is loaded. Pipes such as /dev/stdin can
+        // only be read once, so they are left for the loader, too.
+        let file = crate::include::find(file, &path).filter(|f| f.is_file());
+        if let Some(src) = file.and_then(|f| std::fs::read_to_string(f).ok()) {
+            flags.extend(parse(&src)?);
+        }
+    }
+    if files.is_empty() {
+        return Ok(Expanded::Args {
+            args,
+            script_args: Vec::new(),
+        });
+    }
+    let end = exclusive_end.unwrap_or(end);
+    let mut vars = Vec::new();
+    let mut rest = Vec::with_capacity(args.len() - end);
+    let mut script_args = Vec::new();
+    let mut given = Vec::new();
+    let mut tail = args.split_off(end).into_iter();
+    while let Some(arg) = tail.next() {
+        if arg == "--" {
+            script_args.extend(tail.by_ref());
+            break;
+        }
+        if arg == "--help" || arg == "-h" {
+            return Ok(Expanded::Help(usage(files.last().unwrap(), &flags)));
+        }
+        let (name, value) = match arg.strip_prefix("--") {
+            Some(opt) => opt.split_once('=').map_or((opt, None), |(n, v)| (n, Some(v))),
+            None => {
+                rest.push(arg);
+                continue;
+            }
+        };
+        let flag = match flags.iter().find(|f| f.name == name.replace('-', "_")) {
+            Some(flag) => flag,
+            None => {
+                rest.push(arg);
+                continue;
+            }
+        };
+        let value = match value {
+            Some(v) => v.to_string(),
+            None => match tail.next() {
+                Some(v) => v,
+                None => return err!("option --{} needs a value", name),
+            },
+        };
+        given.push(flag.name.clone());
+        vars.push("-v".into());
+        vars.push(format!("{}={}", flag.name, value));
+    }
+    let defaults = flags
+        .iter()
+        .filter(|f| !given.contains(&f.name))
+        .flat_map(|f| ["-v".to_string(), format!("{}={}", f.name, f.default)]);
+    let mut res: Vec<String> = args.drain(..1).chain(defaults).collect();
+    res.extend(args);
+    res.extend(vars);
+    if exclusive_end.is_some() && !rest.is_empty() {
+        res.push("--".into());
+    }
+    res.extend(rest);
+    Ok(Expanded::Args {
+        args: res,
+        script_args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_declarations() {
+        let flags = parse(
+            "#!/usr/bin/env zawk -f\n#@flag limit 10 \"Max rows\"\n  #@flag sep ',' Field joiner\n# @flag no 1\n{ print }\n",
+        )
+        .unwrap();
+        assert_eq!(
+            flags,
+            vec![
+                Flag {
+                    name: "limit".into(),
+                    default: "10".into(),
+                    help: "Max rows".into()
+                },
+                Flag {
+                    name: "sep".into(),
+                    default: ",".into(),
+                    help: "Field joiner".into()
+                },
+            ]
+        );
+        assert!(parse("#@flag 1x 2").is_err());
+        assert!(parse("#@flag a 1\n#@flag a 2").is_err());
+        assert!(parse("#@flag a \"1").is_err());
+    }
+
+    #[test]
+    fn expand_args() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("report.awk");
+        std::fs::write(&script, "#@flag limit 10 \"Max rows\"\n#@flag min_len 0\n{ print }\n")
+            .unwrap();
+        let script = script.to_str().unwrap();
+        let args = |v: &[&str]| match expand(strs(v)).unwrap() {
+            Expanded::Args { args, .. } => args,
+            Expanded::Help(h) => panic!("unexpected help: {}", h),
+        };
+        assert_eq!(
+            args(&["zawk", "-f", script, "--limit", "3", "--min-len=2", "a.txt"]),
+            strs(&["zawk", "-f", script, "-v", "limit=3", "-v", "min_len=2", "a.txt"])
+        );
+        assert_eq!(
+            args(&["zawk", script, "--limit=3", "--", "--min_len", "x"]),
+            strs(&[
+                "zawk",
+                "-v",
+                "min_len=0",
+                &format!("--program-file={}", script),
+                "-v",
+                "limit=3",
+            ])
+        );
+        // Options before the script belong to zawk.
+        assert_eq!(
+            args(&["zawk", "--limit", "-f", script]),
+            strs(&["zawk", "-v", "limit=10", "-v", "min_len=0", "--limit", "-f", script])
+        );
+        assert_eq!(args(&["zawk", "{ print }", "x.awk"]), strs(&["zawk", "{ print }", "x.awk"]));
+        assert!(expand(strs(&["zawk", "-f", script, "--limit"])).is_err());
+        match expand(strs(&["zawk", "-f", script, "--help"])).unwrap() {
+            Expanded::Help(h) => {
+                assert!(h.contains("--limit <LIMIT>      Max rows [default: 10]"), "{}", h)
+            }
+            Expanded::Args { args, .. } => panic!("expected help, got {:?}", args),
+        }
+    }
+
+    #[test]
+    fn tool_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("tool.awk");
+        std::fs::write(&script, "#@flag n 1\n{ print }\n").unwrap();
+        let script = script.to_str().unwrap();
+        let expanded = |v: &[&str]| match expand(strs(v)).unwrap() {
+            Expanded::Args { args, script_args } => (args, script_args),
+            Expanded::Help(h) => panic!("unexpected help: {}", h),
+        };
+        // A shebang passes its options as one word.
+        assert_eq!(
+            expanded(&["zawk", "-F: -f", script, "a.txt", "--", "-x", "b"]),
+            (
+                strs(&["zawk", "-v", "n=1", "-F:", "-f", script, "a.txt"]),
+                strs(&["-x", "b"])
+            )
+        );
+        assert_eq!(expanded(&["zawk", "-F\t", "{}"]).0, strs(&["zawk", "-F\t", "{}"]));
+        // Nothing after -E is an option, except the script's own flags.
+        assert_eq!(
+            expanded(&["zawk", "-E", script, "-f", "x.awk", "--n=3", "-v"]),
+            (
+                strs(&[
+                    "zawk",
+                    &format!("--program-file={}", script),
+                    "-v",
+                    "n=3",
+                    "--",
+                    "-f",
+                    "x.awk",
+                    "-v"
+                ]),
+                vec![]
+            )
+        );
+        assert_eq!(
+            expanded(&["zawk", &format!("--exec={}", script), "--", "-v"]),
+            (
+                strs(&["zawk", "-v", "n=1", &format!("--program-file={}", script)]),
+                strs(&["-v"])
+            )
+        );
+        // Without a program file, `--` is left to the usual option parsing.
+        assert_eq!(
+            expanded(&["zawk", "--", "{ print }", "a"]),
+            (strs(&["zawk", "--", "{ print }", "a"]), vec![])
+        );
+    }
+}
@@ -89,8 +89,10 @@ impl<'a> StringConstantAnalysis<'a> {
     pub(crate) fn visit_ll(&mut self, inst: &Instr<'a>) {
         use Instr::*;
         if self.cfg.query_regex {
-            // TODO: Do the same for Sub, GSub, Split*
-            if let Match(_, _, pat) | IsMatch(_, _, pat) = inst {
+            // TODO: Do the same for Split*
+            if let Match(_, _, pat) | IsMatch(_, _, pat) | Sub(_, pat, _, _) | GSub(_, pat, _, _) =
+                inst
+            {
                 self.dfa.add_query(pat)
             }
         }
@@ -0,0 +1,198 @@
+//! Locating runtime errors in the program text.
+//!
+//! When compiling for the bytecode interpreter, each function gets a table from instruction
+//! offsets to the line of the program that the instructions were generated from (see
+//! `compile::HighLevel::Line`). If an instruction fails, the interpreter looks up its line, as
+//! well as the lines of the calls to the functions that are still running, and adds them to the
+//! error:
+//!
+//! ```text
+//! error at lib.awk:4 in function ratio(): division by zero in %
+//!     called from report.awk:12
+//! ```
+//!
+//! A program given as several files (with `-f` or `@include`) is parsed as a single text, so
+//! a `SourceMap` translates the lines of that text back to the files they were read from.
+use crate::common::CompileError;
+use crate::compile::Ty;
+
+/// Where the lines of a program's text were read from.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct SourceMap {
+    // (first line of the text, file, the line of the file it is), sorted by the first line.
+    segments: Vec<(usize, String, usize)>,
+}
+
+impl SourceMap {
+    /// The map for text read from a single file.
+    pub(crate) fn new(file: &str) -> SourceMap {
+        SourceMap {
+            segments: vec![(0, file.to_string(), 0)],
+        }
+    }
+
+    /// Lines from `line` onward are lines of `file`, starting at `file_line`.
+    pub(crate) fn add(&mut self, line: usize, file: &str, file_line: usize) {
+        self.segments.push((line, file.to_string(), file_line));
+    }
+
+    /// Add the map of text inserted at `line`.
+    pub(crate) fn append(&mut self, line: usize, other: SourceMap) {
+        self.segments.extend(
+            other
+                .segments
+                .into_iter()
+                .map(|(start, file, file_line)| (start + line, file, file_line)),
+        );
+    }
+
+    /// The file that line `line` (counting from 0) of the text was read from, if known, and its
+    /// line number in that file (counting from 1).
+    pub(crate) fn locate(&self, line: usize) -> (Option<&str>, usize) {
+        match self
+            .segments
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= line)
+        {
+            Some((start, file, file_line)) => (Some(file), file_line + line - start + 1),
+            None => (None, line + 1),
+        }
+    }
+
+    /// Describe line `line` (counting from 0) of the text, as `file:line`.
+    pub(crate) fn describe(&self, line: usize) -> String {
+        match self.locate(line) {
+            (Some(file), n) => format!("{}:{}", file, n),
+            (None, n) => format!("line {}", n),
+        }
+    }
+
+    /// The line of the text that is line `line` (counting from 1) of `file`, or of the first file
+    /// read if `file` is not given. This is the inverse of `locate`.
+    pub(crate) fn find(&self, file: Option<&str>, line: usize) -> Option<usize> {
+        let line = line.checked_sub(1)?;
+        let file = match (file, self.segments.first()) {
+            (_, None) => return Some(line),
+            (Some(file), _) => file,
+            (None, Some((_, first, _))) => first.as_str(),
+        };
+        self.segments
+            .iter()
+            .enumerate()
+            .find_map(|(i, (start, f, file_line))| {
+                if f != file || line < *file_line {
+                    return None;
+                }
+                let at = start + line - file_line;
+                match self.segments.get(i + 1) {
+                    Some((next, _, _)) if at >= *next => None,
+                    _ => Some(at),
+                }
+            })
+    }
+}
+
+/// The lines that the bytecode for a function was generated from.
+#[derive(Default, Clone, Debug)]
+pub(crate) struct FuncLines {
+    // Set for user-defined functions.
+    pub(crate) name: Option<String>,
+    // (instruction offset, line) pairs, sorted by offset.
+    pub(crate) lines: Vec<(usize, usize)>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub(crate) struct DebugInfo {
+    // Indexed like the functions of the bytecode.
+    pub(crate) funcs: Vec<FuncLines>,
+    pub(crate) sources: SourceMap,
+    // The register holding each named global variable. This is only filled in for programs
+    // compiled for the debugger (see `Prog::debug`); otherwise globals that are only used in the
+    // main loop may live in several registers.
+    pub(crate) globals: Vec<(String, u32, Ty)>,
+}
+
+impl DebugInfo {
+    /// Describe the line that instruction `instr` of function `func` was generated from, along
+    /// with the function, if it is user-defined.
+    pub(crate) fn describe(&self, func: usize, instr: usize) -> Option<String> {
+        let f = self.funcs.get(func)?;
+        let (_, line) = match f.lines.partition_point(|(off, _)| *off <= instr) {
+            0 => return None,
+            i => f.lines[i - 1],
+        };
+        let at = self.sources.describe(line);
+        Some(match &f.name {
+            Some(name) => format!("{} in function {}()", at, name),
+            None => at,
+        })
+    }
+
+    /// Add to `err` the location of instruction `instr` of function `func`, where it happened,
+    /// and those of the `Call` instructions preceding the return addresses in `callers`,
+    /// outermost first.
+    pub(crate) fn locate(
+        &self,
+        err: CompileError,
+        (func, instr): (usize, usize),
+        callers: impl DoubleEndedIterator<Item = (usize, usize)>,
+    ) -> CompileError {
+        let at = match self.describe(func, instr) {
+            Some(at) => at,
+            None => return err,
+        };
+        let mut msg = format!("error at {}: {}", at, err);
+        for (func, ret) in callers.rev() {
+            if let Some(at) = self.describe(func, ret.saturating_sub(1)) {
+                msg.push_str("\n    called from ");
+                msg.push_str(&at);
+            }
+        }
+        CompileError(msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate() {
+        let mut sources = SourceMap::new("main.awk");
+        let mut lib = SourceMap::new("lib.awk");
+        lib.add(3, "lib.awk", 2);
+        sources.append(1, lib);
+        sources.add(6, "main.awk", 1);
+        assert_eq!(sources.describe(0), "main.awk:1");
+        assert_eq!(sources.describe(5), "lib.awk:4");
+        assert_eq!(sources.describe(7), "main.awk:3");
+        assert_eq!(SourceMap::default().describe(2), "line 3");
+        assert_eq!(sources.find(None, 3), Some(7));
+        assert_eq!(sources.find(Some("lib.awk"), 4), Some(5));
+        assert_eq!(sources.find(Some("lib.awk"), 1), Some(1));
+        assert_eq!(sources.find(Some("other.awk"), 1), None);
+
+        let info = DebugInfo {
+            funcs: vec![
+                FuncLines {
+                    name: None,
+                    lines: vec![(0, 6), (5, 7)],
+                },
+                FuncLines {
+                    name: Some("f".into()),
+                    lines: vec![(2, 5)],
+                },
+            ],
+            sources,
+            globals: Vec::new(),
+        };
+        let err = info.locate(CompileError("oops".into()), (1, 3), [(0, 7)].into_iter());
+        assert_eq!(
+            err.to_string(),
+            "error at lib.awk:4 in function f(): oops\n    called from main.awk:3"
+        );
+        let err = info.locate(CompileError("oops".into()), (1, 0), [(0, 7)].into_iter());
+        assert_eq!(err.to_string(), "oops");
+    }
+}
@@ -18,7 +18,7 @@ const BACKEND_ARGS: &[&str] = &["-Binterp", "-Bcranelift"];
 fn assert_folded(p: &str) {
     let prog: String = p.into();
     let out = String::from_utf8(
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(prog)
             .arg(String::from("--dump-bytecode"))
@@ -54,7 +54,7 @@ BEGIN {
 }"#
     .into();
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(prog.clone())
@@ -88,7 +88,7 @@ fn simple_fi() {
     }
     let prog: String = r#"{n+=$FI["Count"]} END { print n, NR; }"#.into();
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(String::from("-icsv"))
@@ -100,6 +100,92 @@ fn simple_fi() {
     }
 }
 
+#[test]
+fn fields_by_name() {
+    let input = r#"Item,Count
+carrots,2
+potato chips,3"#;
+    let expected = "carrots,4,\npotato chips,6,\nItem Count 10\n";
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    // "Typo" is not a column: it reads as "" and assigning it leaves the record alone.
+    let prog: String = r#"{FIELDS["Count"] *= 2; n += FIELDS["Count"]; FIELDS["Typo"] = "X"; print $0, FIELDS["Typo"]}
+END { for (k in FIELDS) printf "%s ", k; print n; }"#
+        .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-H"))
+            .arg(String::from("-vOFS=,"))
+            .arg(prog.clone())
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn rebuilt_records_are_escaped() {
+    for backend_arg in BACKEND_ARGS {
+        // Fields are escaped for the output format when $0 is rebuilt from them.
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-icsv")
+            .arg("-ocsv")
+            .arg(r#"{ $3 = "x,\"y"; print }"#)
+            .write_stdin("a,\"b,c\",d\n")
+            .assert()
+            .stdout("a,\"b,c\",\"x,\"\"y\"\r\n");
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-itsv")
+            .arg("-otsv")
+            .arg(r#"{ $2 = "x\ty"; print; $0 = "1\t2\t3"; print NF, $3 }"#)
+            .write_stdin("a\tb\n")
+            .assert()
+            .stdout("a\tx\\ty\n3\t3\n");
+    }
+}
+
+#[test]
+fn named_columns() {
+    let input = r#"Item,Count
+carrots,2
+potato chips,3"#;
+    let expected = "carrots,5,carrots,5,\npotato chips,7,potato chips,7,\n";
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    let prog: String =
+        r#"{@"Count" = @"Count" * 2; @"Count"++; @"Typo" = "X"; print @"Item", @"Count", $0, @"Typo"}"#
+            .into();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-H"))
+            .arg(String::from("-vOFS=,"))
+            .arg(prog.clone())
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+    }
+}
+
 #[test]
 fn file_and_data_arg() {
     let input = r#"Hi"#;
@@ -116,7 +202,7 @@ fn file_and_data_arg() {
         prog_file.write_all(prog.as_bytes()).unwrap();
     }
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(backend_arg)
             .arg("-f")
@@ -149,18 +235,18 @@ file 2 3
         (&data_fname, input),
         (
             &prog1,
-            r#"function max(x, y) { return x<y?y:x; } BEGIN { FS = ","; } { print; } END { print "file 1"; } "#,
+            r#"function maxof(x, y) { return x<y?y:x; } BEGIN { FS = ","; } { print; } END { print "file 1"; } "#,
         ),
         (
             &prog2,
-            r#"{ x = max(int($2), x); } END { print "file 2", x; }"#,
+            r#"{ x = maxof(int($2), x); } END { print "file 2", x; }"#,
         ),
     ] {
         let mut file = File::create(fname).unwrap();
         file.write_all(data.as_bytes()).unwrap();
     }
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(format!("-f{}", fname_to_string(&prog1)))
@@ -180,7 +266,7 @@ mod v_args {
         let expected = "1\n";
         let prog: String = r#"BEGIN {print x;}"#.into();
         for backend_arg in BACKEND_ARGS {
-            Command::cargo_bin("frawk")
+            Command::cargo_bin("zawk")
                 .unwrap()
                 .arg(String::from(*backend_arg))
                 .arg(String::from("-vx=1"))
@@ -195,7 +281,7 @@ mod v_args {
         let expected = "var-with-dash\n";
         let prog: String = r#"BEGIN {print x;}"#.into();
         for backend_arg in BACKEND_ARGS {
-            Command::cargo_bin("frawk")
+            Command::cargo_bin("zawk")
                 .unwrap()
                 .arg(String::from(*backend_arg))
                 .arg(String::from("-vx=var-with-dash"))
@@ -210,7 +296,7 @@ mod v_args {
         let expected = "var-with\n-dash 1+1\n";
         let prog: String = r#"BEGIN {print x, y;}"#.into();
         for backend_arg in BACKEND_ARGS {
-            Command::cargo_bin("frawk")
+            Command::cargo_bin("zawk")
                 .unwrap()
                 .arg(String::from(*backend_arg))
                 .arg(String::from("-vx=var-with\\n-dash"))
@@ -234,7 +320,7 @@ for (k in m) {
 }}"#
     .into();
     for backend_arg in BACKEND_ARGS {
-        let output = Command::cargo_bin("frawk")
+        let output = Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(prog.clone())
@@ -264,7 +350,7 @@ fn iter_across_functions() {
         END {for (k in h) { print k, h[k]; }}"#
         .into();
     for backend_arg in BACKEND_ARGS {
-        let output = Command::cargo_bin("frawk")
+        let output = Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(prog.clone())
@@ -289,7 +375,7 @@ fn simple_rc() {
         (r#"BEGIN { print "hi"; exit 4; print "there"; }"#, 4),
     ] {
         for backend_arg in BACKEND_ARGS {
-            Command::cargo_bin("frawk")
+            Command::cargo_bin("zawk")
                 .unwrap()
                 .arg(String::from(*backend_arg))
                 .arg(String::from(prog))
@@ -308,7 +394,7 @@ fn trivial_parallel_rc() {
         (r#"END { print "hi"; exit 1; print "there"; }"#, 1),
     ] {
         for backend_arg in BACKEND_ARGS {
-            Command::cargo_bin("frawk")
+            Command::cargo_bin("zawk")
                 .unwrap()
                 .arg(String::from(*backend_arg))
                 .arg(String::from(prog))
@@ -334,7 +420,7 @@ fn multi_rc() {
     );
     eprintln!("data={:?}", data);
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(backend_arg)
             .arg("-pf")
@@ -357,7 +443,7 @@ fn nested_loops() {
     let prog: String =
         "BEGIN { m[0]=0; m[1]=1; m[2]=2; for (i in m) for (j in m) print i,j; }".into();
     for backend_arg in BACKEND_ARGS {
-        let output = Command::cargo_bin("frawk")
+        let output = Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(prog.clone())
@@ -391,7 +477,7 @@ fn dont_reorder_files_with_f() {
         .write_all(prog.as_bytes())
         .unwrap();
     for backend_arg in BACKEND_ARGS {
-        Command::cargo_bin("frawk")
+        Command::cargo_bin("zawk")
             .unwrap()
             .arg(String::from(*backend_arg))
             .arg(format!("-f{}", fname_to_string(&prog_file)))
@@ -418,3 +504,674 @@ fn file_from_string(
         .unwrap();
     (tmp, file)
 }
+
+#[test]
+fn select_by_name() {
+    let input = r#"Item,Count,Price
+carrots,2,1.5
+potato chips,3,2"#;
+    let expected = "2,carrots,1.5\n3,potato chips,2\n";
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("numbers");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-H"))
+            .arg(String::from("-vOFS=,"))
+            .arg(String::from("--select=Count,Item,3-"))
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn output_template() {
+    let input = "2024-01-01 boot 100%\n2024-01-02 disk 50%\n";
+    let expected = "{\"ts\":\"2024-01-01\",\"msg\":\"boot 100%\"}\n{\"ts\":\"2024-01-02\",\"msg\":\"disk 50%\"}\n";
+
+    let tmpdir = tempdir().unwrap();
+    let data_fname = tmpdir.path().join("log");
+    {
+        let mut file = File::create(data_fname.clone()).unwrap();
+        file.write_all(input.as_bytes()).unwrap();
+    }
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(r#"-otemplate={"ts":"{1}","msg":"{2}"}"#))
+            .arg(String::from(r#"{ print $1, $2 " " $3 }"#))
+            .arg(fname_to_string(&data_fname))
+            .assert()
+            .stdout(expected);
+        // Single-column aggregations have a fast path that must honor the template too.
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-otemplate=<{1}>"))
+            .arg(String::from("$1 > m { m = $1 + 0 } { n++ } END { print m, n }"))
+            .write_stdin("3\n9\n4\n")
+            .assert()
+            .stdout("<9>\n");
+    }
+}
+
+#[test]
+fn argv_controls_input_files() {
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let second = tmpdir.path().join("second");
+    File::create(first.clone()).unwrap().write_all(b"a\nb\n").unwrap();
+    File::create(second.clone()).unwrap().write_all(b"c\n").unwrap();
+
+    // Swap the two files, drop the missing one, and add an assignment to run before END.
+    let prog = r#"BEGIN { t = ARGV[2]; ARGV[2] = ARGV[4]; ARGV[4] = t; delete ARGV[5]; ARGV[ARGC++] = "x=3" }
+{ print x, $0 }
+END { print x, NR }"#;
+    let expected = "1 c\n2 a\n2 b\n3 3\n";
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(prog))
+            .arg(String::from("x=1"))
+            .arg(fname_to_string(&first))
+            .arg(String::from("x=2"))
+            .arg(fname_to_string(&second))
+            .arg(String::from("missing"))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn assignments_between_files() {
+    let tmpdir = tempdir().unwrap();
+    let colons = tmpdir.path().join("colons");
+    let tabs = tmpdir.path().join("tabs");
+    File::create(colons.clone()).unwrap().write_all(b"a:b c\n").unwrap();
+    File::create(tabs.clone()).unwrap().write_all(b"d e\tf\n").unwrap();
+    let expected = "b c|colons\nf|tabs\n";
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from(r#"{ print $2 "|" name }"#))
+            .arg(String::from("FS=:"))
+            .arg(String::from("name=colons"))
+            .arg(fname_to_string(&colons))
+            .arg(String::from(r"FS=\t"))
+            .arg(String::from("name=tabs"))
+            .arg(fname_to_string(&tabs))
+            .assert()
+            .stdout(expected);
+    }
+}
+
+#[test]
+fn inplace_edit() {
+    for backend_arg in BACKEND_ARGS {
+        let tmpdir = tempdir().unwrap();
+        let first = tmpdir.path().join("first");
+        let second = tmpdir.path().join("second");
+        File::create(first.clone()).unwrap().write_all(b"foo\nbar\n").unwrap();
+        File::create(second.clone()).unwrap().write_all(b"boo\n").unwrap();
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--inplace=.bak"))
+            .arg(String::from(r#"BEGIN { print "begin" } { sub(/o/, "0"); print }"#))
+            .arg(fname_to_string(&first))
+            .arg(fname_to_string(&second))
+            .assert()
+            .stdout("begin\n");
+        assert_eq!(read_to_string(&first).unwrap(), "f0o\nbar\n");
+        assert_eq!(read_to_string(&second).unwrap(), "b0o\n");
+        let backup = tmpdir.path().join("first.bak");
+        assert_eq!(read_to_string(backup).unwrap(), "foo\nbar\n");
+
+        // A single-column aggregation, which otherwise skips the main loop. END runs after the
+        // last file is replaced, so its output goes to stdout.
+        let nums = tmpdir.path().join("nums");
+        File::create(nums.clone()).unwrap().write_all(b"3\n9\n").unwrap();
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--inplace"))
+            .arg(String::from("$1 > m { m = $1 + 0 } END { print m }"))
+            .arg(fname_to_string(&nums))
+            .assert()
+            .stdout("9\n");
+        assert_eq!(read_to_string(&nums).unwrap(), "");
+    }
+}
+
+#[test]
+fn column_stats_reads_files() {
+    let tmpdir = tempdir().unwrap();
+    let first = tmpdir.path().join("first");
+    let second = tmpdir.path().join("second");
+    File::create(first.clone()).unwrap().write_all(b"3\n9\n").unwrap();
+    File::create(second.clone()).unwrap().write_all(b"4\n").unwrap();
+    Command::cargo_bin("zawk")
+        .unwrap()
+        .arg(String::from("$1 > m { m = $1 + 0 } { n++ } END { print m, n }"))
+        .arg(fname_to_string(&first))
+        .arg(fname_to_string(&second))
+        .assert()
+        .stdout("9 3\n");
+}
+
+#[test]
+fn profile_counts_statements_on_one_line() {
+    let tmpdir = tempdir().unwrap();
+    let prof = tmpdir.path().join("prof");
+    Command::cargo_bin("zawk")
+        .unwrap()
+        .arg(format!("--profile={}", fname_to_string(&prof)))
+        .arg(String::from("END { for (i = 0; i < 1000; i++) s += i; print s }"))
+        .assert()
+        .stdout("499500\n");
+    assert_eq!(
+        read_to_string(prof).unwrap(),
+        "# zawk profile: the number of times each statement ran
+         END {
+       1     for (i = 0; i < 1000; i++) {
+    1000         s += i
+             }
+       1     print s
+         }
+"
+    );
+}
+
+#[test]
+fn parallel_ranges() {
+    let tmpdir = tempdir().unwrap();
+    let csv = tmpdir.path().join("in.csv");
+    let mut text = String::from("id,note\n");
+    for i in 0..2000 {
+        text.push_str(&format!("{},\"multi\nline, {}\"\n", i, i % 7));
+    }
+    File::create(csv.clone()).unwrap().write_all(text.as_bytes()).unwrap();
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-prange"))
+            .arg(String::from("-j4"))
+            .arg(String::from("--chunk-size=64"))
+            .arg(String::from("-icsv"))
+            .arg(String::from("-H"))
+            .arg(String::from(r#"{ n++; s += $1; if ($2 ~ /\n/) m++; } END { print n, s, m }"#))
+            .arg(fname_to_string(&csv))
+            .assert()
+            .stdout("2000 1999000 2000\n");
+    }
+}
+
+#[test]
+fn atomic_output_files() {
+    for backend_arg in BACKEND_ARGS {
+        let tmpdir = tempdir().unwrap();
+        let done = tmpdir.path().join("done");
+        let failed = tmpdir.path().join("failed");
+        File::create(failed.clone()).unwrap().write_all(b"old\n").unwrap();
+        let prog = format!(
+            r#"BEGIN {{ print "a" > "atomic://{done}"; close("atomic://{done}"); print "b" > "atomic://{failed}"; exit 1 }}"#,
+            done = fname_to_string(&done),
+            failed = fname_to_string(&failed),
+        );
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog)
+            .assert()
+            .code(1);
+        assert_eq!(read_to_string(&done).unwrap(), "a\n");
+        assert_eq!(read_to_string(&failed).unwrap(), "old\n");
+        assert_eq!(std::fs::read_dir(tmpdir.path()).unwrap().count(), 2);
+    }
+}
+
+#[test]
+fn output_file_modes() {
+    for backend_arg in BACKEND_ARGS {
+        let tmpdir = tempdir().unwrap();
+        let out = tmpdir.path().join("out");
+        let out_s = fname_to_string(&out);
+        File::create(out.clone())
+            .unwrap()
+            .write_all(b"a much longer line\n")
+            .unwrap();
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(format!(
+                r#"BEGIN {{ print "x" > "{0}"; print "y" > "{0}"; close("{0}"); print "z" >> "{0}" }}"#,
+                out_s
+            ))
+            .assert()
+            .success();
+        assert_eq!(read_to_string(&out).unwrap(), "x\ny\nz\n");
+
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--no-clobber"))
+            .arg(format!(r#"BEGIN {{ print "w" > "{}" }}"#, out_s))
+            .assert()
+            .stdout("");
+        assert_eq!(read_to_string(&out).unwrap(), "x\ny\nz\n");
+    }
+}
+
+#[test]
+fn tee_output() {
+    for backend_arg in BACKEND_ARGS {
+        let tmpdir = tempdir().unwrap();
+        let out = tmpdir.path().join("out");
+        let out_s = fname_to_string(&out);
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--tee"))
+            .arg(out_s.clone())
+            .arg(String::from(r#"BEGIN { print "x"; print "y" }"#))
+            .assert()
+            .stdout("x\ny\n");
+        assert_eq!(read_to_string(&out).unwrap(), "x\ny\n");
+
+        // The tee file is opened again on the first print; by then its directory is gone.
+        let dir = tmpdir.path().join("gone");
+        std::fs::create_dir(&dir).unwrap();
+        let gone = fname_to_string(&dir.join("out"));
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--tee"))
+            .arg(gone.clone())
+            .arg(format!(
+                r#"BEGIN {{ system("rm -r {}"); print "x" }}"#,
+                fname_to_string(&dir)
+            ))
+            .assert()
+            .stdout("")
+            .stderr(format!(
+                "failed to open {}: No such file or directory (os error 2)\n",
+                gone
+            ));
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn read_timeout_fifo() {
+    let prog = r#"BEGIN {
+        PROCINFO["READ_TIMEOUT"] = 100
+        while ((r = (getline line < fifo)) != 0) {
+            if (r == -2) waited = 1
+            else print line
+        }
+        print waited
+    }"#;
+    for backend_arg in BACKEND_ARGS {
+        let tmpdir = tempdir().unwrap();
+        let fifo = tmpdir.path().join("fifo");
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        // Nothing opens the FIFO for writing until well after the first read has timed out.
+        let writer_path = fifo.clone();
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(500));
+            File::options()
+                .write(true)
+                .open(writer_path)
+                .unwrap()
+                .write_all(b"a\nb\n")
+                .unwrap();
+        });
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-v"))
+            .arg(format!("fifo={}", fname_to_string(&fifo)))
+            .arg(prog)
+            .timeout(std::time::Duration::from_secs(10))
+            .assert()
+            .stdout("a\nb\n1\n");
+        writer.join().unwrap();
+    }
+}
+
+#[cfg(not(feature = "str_arena"))]
+#[test]
+fn str_arena_unavailable() {
+    Command::cargo_bin("zawk")
+        .unwrap()
+        .arg(String::from("--str-arena"))
+        .arg(String::from("BEGIN { print 1 }"))
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("--str-arena is not available: zawk was built without the `str_arena` feature\n");
+}
+
+#[test]
+fn ordered_arrays() {
+    let prog =
+        r#"BEGIN { split("z y x w v u", a); delete a[2]; delete a[3]; print _join(a, ",") }"#;
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(prog)
+            .assert()
+            .stdout("z,u,v,w\n");
+
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("--ordered-arrays"))
+            .arg(prog)
+            .assert()
+            .stdout("z,w,v,u\n");
+    }
+}
+
+#[test]
+fn null_records() {
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-0"))
+            .arg(String::from("{ print NF, $NF }"))
+            .write_stdin("a b\nc\0d  e\0")
+            .assert()
+            .stdout("3 c\x002 e\0");
+
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(String::from("-0"))
+            .arg(String::from("-F,"))
+            .arg(String::from("{ print $2 }"))
+            .write_stdin("a,b\nc,d\0e,f\0")
+            .assert()
+            .stdout("b\nc\0f\0");
+    }
+}
+
+#[test]
+fn typed_vars() {
+    for backend_arg in BACKEND_ARGS {
+        // A plain -v variable is a string, so "10" > "5" is false.
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-v")
+            .arg("t=5")
+            .arg("$1 > t")
+            .write_stdin("10\n3\n7\n")
+            .assert()
+            .stdout("7\n");
+
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-v:int")
+            .arg("t=5")
+            .arg("-v:float")
+            .arg("r=0.5")
+            .arg("$1 > t { print $1 * r }")
+            .write_stdin("10\n3\n7\n")
+            .assert()
+            .stdout("5\n3.5\n");
+
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-v:int")
+            .arg("t=x")
+            .arg("BEGIN { print t }")
+            .assert()
+            .failure()
+            .stdout("")
+            .stderr("invalid value for -v:int t: \"x\"\n");
+    }
+}
+
+#[test]
+fn emit() {
+    let prog = String::from(r#"BEGIN { print "hello" }"#);
+    let out = Command::cargo_bin("zawk")
+        .unwrap()
+        .arg("--emit")
+        .arg("ast")
+        .arg("--emit")
+        .arg("bytecode")
+        .arg("--emit")
+        .arg("ir")
+        .arg(prog.clone())
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    let out = String::from_utf8(out.stdout).unwrap();
+    // The stages come out in the order given, and the program does not run.
+    assert!(out.starts_with("BEGIN\n  Print\n    StrLit \"hello\"\n"), "{}", out);
+    let bytecode = out.find("PrintAll").expect("no bytecode");
+    let ir = out.find("function <main>").expect("no IR");
+    assert!(bytecode < ir, "{}", out);
+    assert!(!out.contains("\nhello\n"), "{}", out);
+
+    // Nothing that changes from run to run, like the address of a string, is in the dump.
+    let dump_bytecode = || {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg("--emit")
+            .arg("bytecode")
+            .arg(prog.clone())
+            .output()
+            .unwrap()
+            .stdout
+    };
+    assert_eq!(dump_bytecode(), dump_bytecode());
+
+    #[cfg(feature = "jit")]
+    {
+        let clif = Command::cargo_bin("zawk")
+            .unwrap()
+            .arg("--emit")
+            .arg("clif")
+            .arg(prog.clone())
+            .output()
+            .unwrap();
+        assert!(clif.status.success());
+        assert!(String::from_utf8(clif.stdout).unwrap().contains("function u0:0("));
+    }
+
+    Command::cargo_bin("zawk")
+        .unwrap()
+        .arg("--emit")
+        .arg("asm")
+        .arg(prog)
+        .assert()
+        .failure()
+        .stdout("");
+}
+
+#[test]
+fn pipe_timeout() {
+    let prog = r#"BEGIN {
+    for (i = 0; i < 200000; i++) print "xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx" | "sleep 5"
+    print "after"
+}"#;
+    let tmp = tempdir().unwrap();
+    for backend_arg in BACKEND_ARGS {
+        // The command inherits zawk's stderr, so send it to a file rather than a pipe that stays
+        // open until the command exits.
+        let err_path = tmp.path().join("stderr");
+        let start = std::time::Instant::now();
+        let status = std::process::Command::new(assert_cmd::cargo::cargo_bin("zawk"))
+            .arg(String::from(*backend_arg))
+            .arg("--pipe-timeout")
+            .arg("1s")
+            .arg(prog)
+            .stdout(std::process::Stdio::null())
+            .stderr(File::create(&err_path).unwrap())
+            .status()
+            .unwrap();
+        // The program stops without waiting for the command to exit.
+        assert!(start.elapsed() < std::time::Duration::from_secs(4));
+        assert!(!status.success());
+        let stderr = read_to_string(&err_path).unwrap();
+        assert!(
+            stderr.contains("command \"sleep 5\" did not consume its input for 1s"),
+            "{}",
+            stderr
+        );
+        assert!(!stderr.contains("writers.rs"), "{}", stderr);
+    }
+}
+
+#[test]
+fn parallel_head() {
+    let tmp = tempdir().unwrap();
+    let path = tmp.path().join("big.txt");
+    {
+        let mut f = std::io::BufWriter::new(File::create(&path).unwrap());
+        for i in 1..=1_000_000 {
+            writeln!(f, "{}", i).unwrap();
+        }
+    }
+    for backend_arg in BACKEND_ARGS {
+        // The limit applies to the first records of the input, not whichever ones the workers
+        // got to first.
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("-j4")
+            .arg("--head")
+            .arg("5")
+            .arg("{ print }")
+            .arg(&path)
+            .assert()
+            .success()
+            .stdout("1\n2\n3\n4\n5\n");
+
+        // Workers that have not started yet do not hold up the exit.
+        let start = std::time::Instant::now();
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg("-pr")
+            .arg("-j4")
+            .arg("{ exit }")
+            .arg(&path)
+            .assert()
+            .success();
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+    }
+}
+
+// Feed `lines` to zawk one at a time over a pipe, checking that each produces `expected` output
+// before the next line is written.
+fn assert_streams(args: &[&str], lines: &[(&str, &str)]) {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("zawk"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    let (send, recv) = std::sync::mpsc::channel();
+    let reader = std::thread::spawn(move || {
+        for line in stdout.lines() {
+            if send.send(line.unwrap()).is_err() {
+                break;
+            }
+        }
+    });
+    for (input, expected) in lines {
+        stdin.write_all(input.as_bytes()).unwrap();
+        stdin.flush().unwrap();
+        let got = recv.recv_timeout(std::time::Duration::from_secs(5));
+        assert_eq!(got.as_deref(), Ok(*expected), "args={:?} input={:?}", args, input);
+    }
+    drop(stdin);
+    assert!(child.wait().unwrap().success());
+    reader.join().unwrap();
+}
+
+#[test]
+fn unbuffered_input() {
+    for backend_arg in BACKEND_ARGS {
+        for split in [&[][..], &["-F,"], &["-F[,]"], &["-icsv"], &["-itsv"]] {
+            let mut args = vec![*backend_arg, "--unbuffered"];
+            args.extend_from_slice(split);
+            args.push("{print NR, $1}");
+            assert_streams(&args, &[("a\n", "1 a"), ("b\n", "2 b")]);
+        }
+    }
+}
+
+#[test]
+fn flushed_output_reads_unbuffered_input() {
+    for backend_arg in BACKEND_ARGS {
+        for prog in [
+            &["--line-buffered", "{print NR, $1}"][..],
+            &["{print NR, $1; fflush()}"],
+            &["-F,", "function f() { fflush(\"\") } {print NR, $1; f()}"],
+        ] {
+            let mut args = vec![*backend_arg];
+            args.extend_from_slice(prog);
+            assert_streams(&args, &[("a\n", "1 a"), ("b\n", "2 b")]);
+        }
+    }
+}
+
+#[test]
+fn binary_file_contents() {
+    let tmp = tempdir().unwrap();
+    let bin = tmp.path().join("bin.dat");
+    let crlf = tmp.path().join("crlf.txt");
+    let copy = tmp.path().join("copy.dat");
+    std::fs::write(&bin, b"\xff\x00ab\n").unwrap();
+    std::fs::write(&crlf, b"a\r\nb\r\n").unwrap();
+    let prog = format!(
+        r#"BEGIN {{
+    s = read_all("{bin}")
+    print length(s), "[" ERRNO "]", length(substr(s, 2)), index(s, "ab"), (char_at(s, 3) == "a")
+    write_all("{copy}", substr(s, 1, 3))
+    lines = read_lines("{crlf}"); print length(lines), length(lines[1]), lines[2]
+}}"#,
+        bin = fname_to_string(&bin),
+        copy = fname_to_string(&copy),
+        crlf = fname_to_string(&crlf),
+    );
+    for backend_arg in BACKEND_ARGS {
+        Command::cargo_bin("zawk")
+            .unwrap()
+            .arg(String::from(*backend_arg))
+            .arg(&prog)
+            .assert()
+            .stdout("5 [] 4 3 1\n2 1 b\n");
+        assert_eq!(std::fs::read(&copy).unwrap(), b"\xff\x00a");
+    }
+}